@@ -1,11 +1,15 @@
 //! Semantic static and dynamic analysis of the source code.
 
+pub mod auto_import;
+pub use auto_import::*;
 mod bib;
 pub(crate) use bib::*;
 pub mod call;
 pub use call::*;
 pub mod color_exprs;
 pub use color_exprs::*;
+pub mod data;
+pub use data::*;
 pub mod def_use;
 pub use def_use::*;
 pub mod import;
@@ -18,6 +22,8 @@ mod ty;
 pub(crate) use ty::*;
 pub mod track_values;
 pub use track_values::*;
+pub mod workspace_index;
+pub use workspace_index::*;
 mod prelude;
 
 mod global;
@@ -0,0 +1,118 @@
+//! Imports of the names exported by packages, which are offered before the
+//! packages are imported.
+
+use std::time::Duration;
+
+use ecow::EcoString;
+use typst::syntax::{
+    ast::{self, AstNode},
+    package::{PackageManifest, PackageSpec},
+    LinkedNode, SyntaxKind,
+};
+
+use super::prelude::*;
+
+/// The time for which a package index is reused, as looking up the packages
+/// and parsing their entrypoints is too slow to be done on every keystroke.
+pub(crate) const PACKAGE_INDEX_TTL: Duration = Duration::from_secs(60);
+
+/// The top-level bindings of the packages available without downloading them,
+/// i.e. the local packages and the preview packages in the cached package
+/// index which are downloaded already.
+#[derive(Debug, Default)]
+pub struct PackageIndex {
+    /// The names exported by the packages.
+    pub exports: Vec<(PackageSpec, EcoString)>,
+}
+
+impl PackageIndex {
+    /// Look up the packages and collect their exports.
+    pub(crate) fn build(ctx: &AnalysisContext) -> Self {
+        let mut exports = vec![];
+
+        let packages = ctx.resources.local_packages().into_iter();
+        for spec in packages.chain(ctx.resources.preview_packages()) {
+            let Some(names) = package_bindings(ctx, &spec) else {
+                continue;
+            };
+            exports.extend(names.into_iter().map(|name| (spec.clone(), name)));
+        }
+
+        Self { exports }
+    }
+}
+
+/// Get the top-level bindings of the entrypoint of a package, reading it from
+/// the disk, so that the package is never downloaded.
+fn package_bindings(ctx: &AnalysisContext, spec: &PackageSpec) -> Option<Vec<EcoString>> {
+    let dir = ctx.resources.local_package_dir(spec)?;
+    let manifest = std::fs::read_to_string(dir.join("typst.toml")).ok()?;
+    let manifest: PackageManifest = toml::from_str(&manifest).ok()?;
+    let entrypoint = dir.join(manifest.package.entrypoint.as_str());
+    let source = Source::detached(std::fs::read_to_string(entrypoint).ok()?);
+    let names = (source.root().children())
+        .filter_map(|child| child.cast::<ast::LetBinding>())
+        .flat_map(|binding| binding.kind().bindings())
+        .map(|ident| ident.get().clone());
+    Some(names.collect())
+}
+
+/// Find the names accepted by the filter among the top-level bindings of the
+/// packages available without downloading them.
+///
+/// The packages are looked up through the [`PackageIndex`] cached by the
+/// context.
+pub fn package_exports(
+    ctx: &mut AnalysisContext,
+    mut filter: impl FnMut(&str) -> bool,
+) -> Vec<(PackageSpec, EcoString)> {
+    let index = ctx.package_index();
+    let exports = index.exports.iter().filter(|(_, name)| filter(name));
+    exports.cloned().collect()
+}
+
+/// Get the edit importing the name from the package into the source, as the
+/// range to replace and the new text.
+///
+/// An existing import of the package with a list of items is extended with the
+/// name, whatever version it imports. There is no edit if the package is
+/// imported as a module or with a wildcard, or the name is imported already.
+pub fn import_edit(
+    source: &Source,
+    spec: &PackageSpec,
+    name: &str,
+) -> Option<(Range<usize>, String)> {
+    for node in LinkedNode::new(source.root()).children() {
+        let Some(import) = node.cast::<ast::ModuleImport>() else {
+            continue;
+        };
+        let ast::Expr::Str(path) = import.source() else {
+            continue;
+        };
+        let Ok(imported) = path.get().parse::<PackageSpec>() else {
+            continue;
+        };
+        if imported.namespace != spec.namespace || imported.name != spec.name {
+            continue;
+        }
+
+        let Some(ast::Imports::Items(items)) = import.imports() else {
+            return None;
+        };
+        if items.iter().any(|item| item.bound_name().as_str() == name) {
+            return None;
+        }
+        let items = node
+            .children()
+            .find(|n| n.kind() == SyntaxKind::ImportItems)?;
+        let end = items.range().end;
+        let sep = if source.text()[items.range()].ends_with(',') {
+            " "
+        } else {
+            ", "
+        };
+        return Some((end..end, format!("{sep}{name}")));
+    }
+
+    Some((0..0, format!("#import \"{spec}\": {name}\n")))
+}
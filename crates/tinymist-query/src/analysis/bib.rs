@@ -111,12 +111,80 @@ impl YamlBib {
                     file_id,
                     name_span: k_span.clone(),
                     span: span.clone(),
+                    author: None,
+                    year: None,
                 };
                 Some((k.value, entry))
             })
             .collect();
 
-        Self { entries }
+        let mut this = Self { entries };
+        this.fill_details(content);
+        this
+    }
+
+    /// Fill the author and year of entries, which are used as completion
+    /// details.
+    fn fill_details(&mut self, content: &str) {
+        let Ok(serde_yaml::Value::Mapping(map)) = serde_yaml::from_str(content) else {
+            return;
+        };
+
+        for (key, entry) in self.entries.iter_mut() {
+            let Some(fields) = map.get(key.as_str()) else {
+                continue;
+            };
+            entry.author = fields.get("author").and_then(yaml_text);
+            entry.year = fields.get("date").and_then(yaml_text).and_then(year_of);
+        }
+    }
+}
+
+fn yaml_text(v: &serde_yaml::Value) -> Option<String> {
+    match v {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Sequence(seq) => {
+            let items = seq.iter().filter_map(yaml_text).collect::<Vec<_>>();
+            (!items.is_empty()).then(|| items.join(" and "))
+        }
+        _ => None,
+    }
+}
+
+fn bib_text(field: &biblatex::Field) -> String {
+    field
+        .iter()
+        .map(|chunk| match chunk.v {
+            biblatex::RawChunk::Normal(s) | biblatex::RawChunk::Abbreviation(s) => s,
+        })
+        .collect()
+}
+
+/// Extract the year from a date like `2020`, `2020-01-02`, etc.
+fn year_of(date: String) -> Option<String> {
+    let year = date
+        .trim()
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect::<String>();
+    (!year.is_empty()).then_some(year)
+}
+
+/// Shorten an author list like `Doe, John and Roe, Jane` to `Doe et al.`.
+fn short_author(author: &str) -> String {
+    let mut authors = author.split(" and ").map(str::trim);
+    let first = authors.next().unwrap_or_default();
+    let family = match first.split_once(',') {
+        Some((family, _)) => family,
+        None => first.rsplit(' ').next().unwrap_or(first),
+    };
+    let family = family.trim().trim_matches(|c| c == '{' || c == '}');
+
+    if authors.next().is_some() {
+        format!("{family} et al.")
+    } else {
+        family.to_owned()
     }
 }
 
@@ -125,6 +193,21 @@ pub struct BibEntry {
     pub file_id: TypstFileId,
     pub name_span: Range<usize>,
     pub span: Range<usize>,
+    pub author: Option<String>,
+    pub year: Option<String>,
+}
+
+impl BibEntry {
+    /// Describe the entry by its author and year, e.g. `Doe et al. (2020)`.
+    pub fn citation_detail(&self) -> Option<String> {
+        let author = self.author.as_deref().map(short_author);
+        match (author, &self.year) {
+            (Some(author), Some(year)) => Some(format!("{author} ({year})")),
+            (Some(author), None) => Some(author),
+            (None, Some(year)) => Some(format!("({year})")),
+            (None, None) => None,
+        }
+    }
 }
 
 #[derive(Default)]
@@ -175,12 +258,21 @@ impl BibWorker {
                 for e in bibliography.entries {
                     let k = e.v.key;
                     let span = e.span;
+                    let field = |name: &str| {
+                        let mut fields = e.v.fields.iter();
+                        let pair = fields.find(|p| p.key.v.eq_ignore_ascii_case(name))?;
+                        Some(bib_text(&pair.value.v))
+                    };
+                    let author = field("author");
+                    let year = field("year").or_else(|| field("date")).and_then(year_of);
                     self.info.entries.insert(
                         k.v.to_owned(),
                         BibEntry {
                             file_id: path,
                             name_span: k.span,
                             span,
+                            author,
+                            year,
                         },
                     );
                 }
@@ -218,6 +310,46 @@ Euclid2:
         assert_eq!(yaml.entries[1].0, "Euclid2");
     }
 
+    #[test]
+    fn yaml_bib_detail() {
+        let content = r#"
+Euclid:
+  type: article
+  author: [Euclid, Heath, Thomas Little]
+  date: 1956-01-01
+"#;
+        let yaml = super::YamlBib::from_content(
+            content,
+            TypstFileId::new_fake(VirtualPath::new(Path::new("test.yml"))),
+        );
+        let detail = yaml.entries[0].1.citation_detail();
+        assert_eq!(detail.as_deref(), Some("Euclid et al. (1956)"));
+    }
+
+    #[test]
+    fn bib_detail() {
+        let content = r#"
+@article{netwok,
+    title={At-scale impact of the {Net Wok}},
+    author={Astley, Rasmus and Morris, Laura},
+    year={2020},
+}
+@book{distress,
+    title={Distress},
+    author={Jane Barnes},
+    date={2019-03},
+}
+"#;
+        let info = super::analyze_bib(ecow::eco_vec![(
+            TypstFileId::new_fake(VirtualPath::new(Path::new("test.bib"))),
+            content.as_bytes().into(),
+        )])
+        .unwrap();
+        let detail = |k: &str| info.entries[k].citation_detail();
+        assert_eq!(detail("netwok").as_deref(), Some("Astley et al. (2020)"));
+        assert_eq!(detail("distress").as_deref(), Some("Barnes (2019)"));
+    }
+
     #[test]
     fn yaml_bib_imcomplete() {
         let content = r#"
@@ -1,13 +1,20 @@
 //! Analyze color expressions in a source file.
-use std::{ops::Range, str::FromStr};
+//!
+//! Besides the calls constructing colors, the names of the predefined colors,
+//! e.g. `red`, are colors as well, unless they are shadowed by a variable in
+//! scope. So are the uses of the variables bound to colors by `let`.
+use std::{collections::HashMap, ops::Range, str::FromStr};
 
+use ecow::EcoString;
 use lsp_types::ColorInformation;
 use typst::{
+    foundations::Value,
     syntax::{
         ast::{self, AstNode},
         LinkedNode, Source, SyntaxKind,
     },
     visualize::Color,
+    World,
 };
 
 use crate::AnalysisContext;
@@ -18,6 +25,7 @@ pub fn get_color_exprs(ctx: &mut AnalysisContext, src: &Source) -> Option<Vec<Co
         ctx,
         source: src.clone(),
         colors: vec![],
+        scopes: vec![HashMap::new()],
     };
     let root = LinkedNode::new(src.root());
     worker.collect_colors(root)?;
@@ -28,6 +36,9 @@ struct ColorExprWorker<'a, 'w> {
     ctx: &'a mut AnalysisContext<'w>,
     source: Source,
     colors: Vec<ColorInformation>,
+    /// The variables in scope with the colors bound to them, if known, from
+    /// the outermost scope to the innermost one.
+    scopes: Vec<HashMap<EcoString, Option<Color>>>,
 }
 
 impl<'a, 'w> ColorExprWorker<'a, 'w> {
@@ -39,6 +50,71 @@ impl<'a, 'w> ColorExprWorker<'a, 'w> {
                     return Some(());
                 }
             }
+            SyntaxKind::Ident => {
+                self.analyze_ident(&node);
+                return Some(());
+            }
+            SyntaxKind::LetBinding => {
+                for child in node.children() {
+                    self.collect_colors(child);
+                }
+                let binding = node.cast::<ast::LetBinding>()?;
+                let color = binding.init().and_then(|init| self.color_of(init));
+                match binding.kind() {
+                    ast::LetBindingKind::Normal(ast::Pattern::Normal(ast::Expr::Ident(name))) => {
+                        self.bind_color(name, color)
+                    }
+                    kind => self.bind(kind.bindings()),
+                }
+                return Some(());
+            }
+            SyntaxKind::ModuleImport => {
+                let import = node.cast::<ast::ModuleImport>()?;
+                if let Some(ast::Imports::Items(items)) = import.imports() {
+                    self.bind(items.iter().map(ast::ImportItem::bound_name));
+                }
+                self.bind(import.new_name());
+                return Some(());
+            }
+            SyntaxKind::CodeBlock | SyntaxKind::ContentBlock => {
+                self.scopes.push(HashMap::new());
+                for child in node.children() {
+                    self.collect_colors(child);
+                }
+                self.scopes.pop();
+                return Some(());
+            }
+            SyntaxKind::Closure => {
+                let closure = node.cast::<ast::Closure>()?;
+                self.scopes.push(HashMap::new());
+                self.bind(closure.name());
+                for child in node.children() {
+                    let is_params = child.kind() == SyntaxKind::Params;
+                    self.collect_colors(child);
+                    // The default values of the parameters are evaluated
+                    // before the parameters are bound.
+                    if is_params {
+                        self.bind(closure.params().children().flat_map(param_bindings));
+                    }
+                }
+                self.scopes.pop();
+                return Some(());
+            }
+            SyntaxKind::ForLoop => {
+                let for_loop = node.cast::<ast::ForLoop>()?;
+                let body = for_loop.body().span();
+                for child in node.children() {
+                    if child.span() != body {
+                        self.collect_colors(child);
+                        continue;
+                    }
+                    self.scopes.push(HashMap::new());
+                    self.bind(for_loop.pattern().bindings());
+                    self.collect_colors(child);
+                    self.scopes.pop();
+                }
+                return Some(());
+            }
             SyntaxKind::Named => {}
             k if k.is_trivia() || k.is_keyword() || k.is_error() => return Some(()),
             _ => {}
@@ -109,6 +185,55 @@ impl<'a, 'w> ColorExprWorker<'a, 'w> {
         Some(())
     }
 
+    fn analyze_ident(&mut self, node: &LinkedNode) -> Option<()> {
+        if !is_use(node) {
+            return None;
+        }
+        let ident = node.cast::<ast::Ident>()?;
+        let color = self.lookup(ident.get())?;
+        self.push_color(node.range(), color)
+    }
+
+    /// Gets the color of an expression initializing a variable, which is a
+    /// color call or another variable.
+    fn color_of(&mut self, expr: ast::Expr) -> Option<Color> {
+        match expr {
+            ast::Expr::Ident(ident) => self.lookup(ident.get()),
+            ast::Expr::FuncCall(_) => self.ctx.mini_eval(expr)?.cast().ok(),
+            _ => None,
+        }
+    }
+
+    /// Gets the color of a variable, which is either bound in scope or a
+    /// predefined color.
+    fn lookup(&self, name: &EcoString) -> Option<Color> {
+        let bound = self.scopes.iter().rev().find_map(|scope| scope.get(name));
+        if let Some(color) = bound {
+            return *color;
+        }
+        let library = self.ctx.world().library();
+        match library.global.scope().get(name)? {
+            Value::Color(color) => Some(*color),
+            _ => None,
+        }
+    }
+
+    /// Binds variables in the innermost scope.
+    fn bind<'n>(&mut self, names: impl IntoIterator<Item = ast::Ident<'n>>) {
+        for name in names {
+            self.bind_color(name, None);
+        }
+    }
+
+    /// Binds a variable to a color, if known, in the innermost scope.
+    fn bind_color(&mut self, name: ast::Ident, color: Option<Color>) {
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("the file scope is never popped");
+        scope.insert(name.get().clone(), color);
+    }
+
     fn push_color(&mut self, range: Range<usize>, color: Color) -> Option<()> {
         let rng = self.ctx.to_lsp_range(range, &self.source);
         let [r, g, b, a] = color.to_rgb().to_vec4();
@@ -126,3 +251,34 @@ impl<'a, 'w> ColorExprWorker<'a, 'w> {
         Some(())
     }
 }
+
+/// Get the variables bound by a parameter of a closure.
+fn param_bindings(param: ast::Param) -> Vec<ast::Ident> {
+    match param {
+        ast::Param::Pos(pattern) => pattern.bindings(),
+        ast::Param::Named(named) => vec![named.name()],
+        ast::Param::Spread(spread) => spread.sink_ident().into_iter().collect(),
+    }
+}
+
+/// Whether an identifier refers to a value, rather than binds or names one.
+fn is_use(node: &LinkedNode) -> bool {
+    let prev = node.prev_sibling_kind();
+    match node.parent_kind() {
+        Some(SyntaxKind::LetBinding | SyntaxKind::Closure) => {
+            matches!(prev, Some(SyntaxKind::Eq | SyntaxKind::Arrow))
+        }
+        Some(SyntaxKind::ForLoop) => prev == Some(SyntaxKind::In),
+        Some(SyntaxKind::Named) => prev == Some(SyntaxKind::Colon),
+        Some(SyntaxKind::FieldAccess) => prev != Some(SyntaxKind::Dot),
+        Some(
+            SyntaxKind::Params
+            | SyntaxKind::Spread
+            | SyntaxKind::Destructuring
+            | SyntaxKind::ModuleImport
+            | SyntaxKind::ImportItems
+            | SyntaxKind::RenamedImportItem,
+        ) => false,
+        _ => true,
+    }
+}
@@ -0,0 +1,255 @@
+//! Fields of data files loaded by `csv` and `json`.
+//!
+//! Variables are associated with the data they hold syntactically, by their
+//! `let` bindings and `for` loops, and the fields are peeked from the header or
+//! the keys of the loaded file.
+
+use crate::prelude::*;
+
+/// The fields available on a variable holding loaded data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataFields {
+    /// The path of the data file, as written in the loading call.
+    pub path: EcoString,
+    /// The names of the fields, deduplicated across rows.
+    pub fields: Vec<EcoString>,
+}
+
+/// What a variable holds of the loaded data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Holding {
+    /// The loaded data itself.
+    Data,
+    /// An element of the loaded data.
+    Row,
+}
+
+/// A call loading a data file.
+struct LoadCall {
+    path: EcoString,
+    is_csv: bool,
+    /// Whether the rows of a CSV file are dictionaries keyed by the header.
+    dict_rows: bool,
+}
+
+/// Get the fields of the data held by the variable, e.g. the columns of `row`
+/// in `for row in csv("data.csv", row-type: dictionary) { .. }`.
+///
+/// Missing or malformed data files are ignored.
+pub fn data_fields(world: &dyn World, source: &Source, var: &LinkedNode) -> Option<DataFields> {
+    let ident = var.cast::<ast::Ident>()?;
+    let (call, holding) = resolve_var(source, var, ident.get(), 0)?;
+
+    let id = source.id().join(&call.path);
+    let bytes = world.file(id).ok()?;
+    let text = std::str::from_utf8(&bytes).ok()?;
+
+    let fields = match (call.is_csv, holding) {
+        (true, Holding::Row) if call.dict_rows => csv_header(text),
+        (true, _) => return None,
+        (false, holding) => json_keys(text, holding)?,
+    };
+
+    (!fields.is_empty()).then_some(DataFields {
+        path: call.path,
+        fields,
+    })
+}
+
+/// Find the data held by a variable named `name` at the node.
+fn resolve_var(
+    source: &Source,
+    node: &LinkedNode,
+    name: &str,
+    depth: usize,
+) -> Option<(LoadCall, Holding)> {
+    // Avoid infinite recursion on self-referencing bindings.
+    if depth > 4 {
+        return None;
+    }
+
+    // The nearest loop binding the variable.
+    let mut ancestor = node.parent();
+    while let Some(parent) = ancestor {
+        if let Some(for_loop) = parent.cast::<ast::ForLoop>() {
+            let binds = for_loop.pattern().bindings();
+            if binds.iter().any(|bind| bind.get() == name) {
+                let (call, holding) = resolve_expr(source, parent, for_loop.iter(), depth)?;
+                return (holding == Holding::Data).then_some((call, Holding::Row));
+            }
+        }
+        ancestor = parent.parent();
+    }
+
+    // The last let binding of the variable before the node.
+    let root = LinkedNode::new(source.root());
+    let mut init = None;
+    find_let_bindings(&root, node.offset(), &mut |binding| {
+        let named = binding.kind().bindings().iter().any(|b| b.get() == name);
+        if named {
+            init = binding.init();
+        }
+    });
+    let init = init?;
+    let init_node = root.find(init.span())?;
+    resolve_expr(source, &init_node, init, depth)
+}
+
+/// Find the data held by an expression.
+fn resolve_expr(
+    source: &Source,
+    node: &LinkedNode,
+    expr: ast::Expr,
+    depth: usize,
+) -> Option<(LoadCall, Holding)> {
+    match expr {
+        ast::Expr::Ident(ident) => resolve_var(source, node, ident.get(), depth + 1),
+        ast::Expr::Parenthesized(paren) => resolve_expr(source, node, paren.expr(), depth),
+        ast::Expr::FuncCall(call) => Some((load_call(call)?, Holding::Data)),
+        _ => None,
+    }
+}
+
+fn load_call(call: ast::FuncCall) -> Option<LoadCall> {
+    let ast::Expr::Ident(callee) = call.callee() else {
+        return None;
+    };
+    let is_csv = match callee.get().as_str() {
+        "csv" => true,
+        "json" => false,
+        _ => return None,
+    };
+
+    let mut path = None;
+    let mut dict_rows = false;
+    for arg in call.args().items() {
+        match arg {
+            ast::Arg::Pos(ast::Expr::Str(s)) if path.is_none() => path = Some(s.get()),
+            ast::Arg::Named(named) if named.name().as_str() == "row-type" => {
+                dict_rows = matches!(named.expr(), ast::Expr::Ident(i) if i.get() == "dictionary");
+            }
+            _ => {}
+        }
+    }
+
+    Some(LoadCall {
+        path: path?,
+        is_csv,
+        dict_rows,
+    })
+}
+
+/// Visit the let bindings starting before the offset.
+fn find_let_bindings<'a>(
+    node: &LinkedNode<'a>,
+    before: usize,
+    f: &mut impl FnMut(ast::LetBinding<'a>),
+) {
+    for child in node.children() {
+        if child.offset() >= before {
+            break;
+        }
+        if let Some(binding) = child.cast::<ast::LetBinding>() {
+            f(binding);
+        }
+        find_let_bindings(&child, before, f);
+    }
+}
+
+/// Get the column names from the header of a CSV file.
+fn csv_header(text: &str) -> Vec<EcoString> {
+    let line = text.lines().next().unwrap_or_default();
+
+    let mut fields = vec![];
+    let mut field = EcoString::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields.retain(|field| !field.trim().is_empty());
+    fields
+}
+
+/// Get the keys of a JSON object, or of the objects in a JSON array.
+fn json_keys(text: &str, holding: Holding) -> Option<Vec<EcoString>> {
+    let value: JsonValue = serde_json::from_str(text).ok()?;
+    let objects = match (holding, &value) {
+        (Holding::Data, JsonValue::Object(_)) => vec![&value],
+        (Holding::Row, JsonValue::Array(items)) => items.iter().collect(),
+        _ => return None,
+    };
+
+    let mut keys: Vec<EcoString> = vec![];
+    for key in objects
+        .iter()
+        .filter_map(|v| v.as_object())
+        .flat_map(|o| o.keys())
+    {
+        if !keys.iter().any(|k| k == key) {
+            keys.push(key.into());
+        }
+    }
+    Some(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    fn fields_at(content: &str, marker: &str) -> Option<Vec<EcoString>> {
+        run_with_ctx(content, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let cursor = source.text().rfind(marker).unwrap() + 1;
+            let leaf = LinkedNode::new(source.root()).leaf_at(cursor).unwrap();
+            let fields = data_fields(ctx.world(), &source, &leaf)?;
+            Some(fields.fields)
+        })
+    }
+
+    #[test]
+    fn test_csv_rows() {
+        let content = r#"// path: /data.csv
+name,"unit, price",city
+Alice,3,Paris
+-----
+// path: /main.typ
+#let data = csv("data.csv", row-type: dictionary)
+#for row in data { row.at("name") }"#;
+        assert_eq!(
+            fields_at(content, "row.").unwrap(),
+            vec!["name", "unit, price", "city"]
+        );
+    }
+
+    #[test]
+    fn test_json_keys() {
+        let content = r#"// path: /data.json
+{ "title": "Report", "authors": [] }
+-----
+// path: /main.typ
+#let meta = json("data.json")
+#meta.title"#;
+        let mut fields = fields_at(content, "meta.").unwrap();
+        fields.sort();
+        assert_eq!(fields, vec!["authors", "title"]);
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let content = r#"#let data = csv("missing.csv", row-type: dictionary)
+#for row in data { row.at("name") }"#;
+        assert_eq!(fields_at(content, "row."), None);
+    }
+}
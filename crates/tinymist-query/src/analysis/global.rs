@@ -1,4 +1,5 @@
 use std::sync::atomic::AtomicBool;
+use std::time::Instant;
 use std::{
     collections::{HashMap, HashSet},
     hash::Hash,
@@ -13,27 +14,36 @@ use reflexo::hash::hash128;
 use reflexo::{cow_mut::CowMut, debug_loc::DataSource, ImmutPath};
 use typst::eval::Eval;
 use typst::foundations;
+use typst::foundations::{ContextElem, IntoValue, NativeElement};
+use typst::introspection::Location;
+use typst::model::{BibliographyElem, Document};
 use typst::syntax::{LinkedNode, SyntaxNode};
 use typst::{
     diag::{eco_format, FileError, FileResult, PackageError},
     foundations::Bytes,
-    syntax::{package::PackageSpec, Source, Span, VirtualPath},
+    syntax::{
+        package::{PackageSpec, PackageVersion, VersionlessPackageSpec},
+        Source, Span, VirtualPath,
+    },
     World,
 };
 use typst::{foundations::Value, syntax::ast, text::Font};
 use typst::{layout::Position, syntax::FileId as TypstFileId};
 
 use super::{
-    analyze_bib, post_type_check, BibInfo, DefUseInfo, FlowType, ImportInfo, PathPreference,
-    Signature, SignatureTarget, TypeCheckInfo,
+    analyze_bib, post_type_check, BibInfo, DefUseInfo, FlowType, ImportInfo, IndexProgress,
+    PackageIndex, PathPreference, Signature, SignatureTarget, TypeCheckInfo, WorkspaceIndex,
+    PACKAGE_INDEX_TTL,
 };
 use crate::syntax::resolve_id_by_path;
 use crate::{
     lsp_to_typst,
     syntax::{
-        construct_module_dependencies, scan_workspace_files, LexicalHierarchy, ModuleDependency,
+        construct_module_dependencies, scan_workspace_files, ColorTheme, IgnorePatterns,
+        LexicalHierarchy, ModuleDependency,
     },
-    typst_to_lsp, LspPosition, LspRange, PositionEncoding, TypstRange, VersionedDocument,
+    typst_to_lsp, LspPosition, LspRange, PositionEncoding, TypstRange, UserSnippet,
+    VersionedDocument,
 };
 
 /// A cache for module-level analysis results of a module.
@@ -125,11 +135,38 @@ pub struct Analysis {
     pub position_encoding: PositionEncoding,
     /// The position encoding for the workspace.
     pub enable_periscope: bool,
+    /// The color theme preferred by the editor, which highlights the code in
+    /// hover if set.
+    pub preferred_theme: Option<ColorTheme>,
+    /// Whether the client renders the HTML tags in markdown, with which the
+    /// code in hover is highlighted.
+    pub markdown_html: bool,
+    /// Whether the client renders the images in markdown, with which the
+    /// equations are previewed.
+    pub markdown_images: bool,
+    /// Whether to render the equations in hover to images.
+    pub hover_math_preview: bool,
+    /// Whether to list the set rules in effect when hovering text.
+    pub hover_set_rules: bool,
+    /// Whether to show the values read from counters and states in the
+    /// document as inlay hints.
+    pub inlay_hint_document_values: bool,
+    /// The paths in the workspace that are not indexed.
+    pub index_ignore: IgnorePatterns,
+    /// The snippets configured by the user, which are completed in their
+    /// modes.
+    pub user_snippets: Vec<UserSnippet>,
     /// The global caches for analysis.
     pub caches: AnalysisGlobalCaches,
 }
 
 impl Analysis {
+    /// Get the theme to highlight the code in hover with, which is only set if
+    /// the client renders the highlighted HTML.
+    pub fn highlight_theme(&self) -> Option<ColorTheme> {
+        self.preferred_theme.filter(|_| self.markdown_html)
+    }
+
     /// Get estimated memory usage of the analysis data.
     pub fn estimated_memory(&self) -> usize {
         self.caches.modules.capacity() * 32
@@ -335,8 +372,21 @@ pub struct AnalysisGlobalCaches {
     lifetime: u64,
     modules: HashMap<TypstFileId, ModuleAnalysisGlobalCache>,
     signatures: HashMap<u128, (u64, foundations::Func, Signature)>,
+    workspace_index: Option<Arc<WorkspaceIndex>>,
+    package_index: Option<(Instant, Arc<PackageIndex>)>,
+    binding_values: HashMap<TypstFileId, (u128, Option<Arc<Document>>, Arc<BindingValues>)>,
+    glyph_images: HashMap<(Font, char, Option<ColorTheme>), Option<EcoString>>,
+    contextual_locations: Option<(Arc<Document>, Arc<ContextualLocations>)>,
 }
 
+/// The locations of the `context` expressions shown in a document, in order,
+/// by the spans of the expressions.
+pub(crate) type ContextualLocations = HashMap<Span, Vec<Location>>;
+
+/// The texts shown for the values of the top-level bindings in a source, by
+/// the ranges of their names.
+pub(crate) type BindingValues = Vec<(TypstRange, String)>;
+
 impl AnalysisGlobalCaches {
     /// Get the signature of a function.
     pub fn signature(&self, source: Option<Source>, func: &SignatureTarget) -> Option<Signature> {
@@ -418,6 +468,35 @@ pub trait AnalysisResources {
     /// Get all the files in the workspace.
     fn iter_dependencies(&self, f: &mut dyn FnMut(&ImmutPath, std::time::SystemTime));
 
+    /// Get the latest version of a package available to the world, if it is
+    /// known without waiting for the network.
+    fn latest_package_version(&self, _spec: &VersionlessPackageSpec) -> Option<PackageVersion> {
+        None
+    }
+
+    /// Get the directory of a package if it is available without downloading
+    /// it.
+    fn local_package_dir(&self, _spec: &PackageSpec) -> Option<Arc<Path>> {
+        None
+    }
+
+    /// Get the latest version of each package in the local namespace.
+    fn local_packages(&self) -> EcoVec<PackageSpec> {
+        EcoVec::new()
+    }
+
+    /// Get the latest version of each package in the preview namespace, which
+    /// is listed in the cached package index and downloaded already.
+    fn preview_packages(&self) -> EcoVec<PackageSpec> {
+        EcoVec::new()
+    }
+
+    /// Get the inputs provided to the world, which are visible through
+    /// `sys.inputs`.
+    fn inputs(&self) -> foundations::Dict {
+        foundations::Dict::new()
+    }
+
     /// Resolve extra font information.
     fn font_info(&self, _font: Font) -> Option<Arc<DataSource>> {
         None
@@ -472,7 +551,7 @@ impl<'w> AnalysisContext<'w> {
 
     #[cfg(test)]
     pub fn test_completion_files(&mut self, f: impl FnOnce() -> Vec<PathBuf>) {
-        self.caches.completion_files.get_or_init(f);
+        self.caches.completion_files = OnceCell::from(f());
     }
 
     #[cfg(test)]
@@ -489,6 +568,7 @@ impl<'w> AnalysisContext<'w> {
                 scan_workspace_files(
                     &self.analysis.root,
                     PathPreference::Special.ext_matcher(),
+                    &self.analysis.index_ignore,
                     |relative_path| relative_path.to_owned(),
                 )
             })
@@ -509,6 +589,106 @@ impl<'w> AnalysisContext<'w> {
         })
     }
 
+    /// Get the index of the workspace, if it has been built.
+    pub fn workspace_index(&self) -> Option<Arc<WorkspaceIndex>> {
+        self.analysis.caches.workspace_index.clone()
+    }
+
+    /// Get the index of the names exported by packages, which is rebuilt once
+    /// it is older than [`PACKAGE_INDEX_TTL`].
+    pub(crate) fn package_index(&mut self) -> Arc<PackageIndex> {
+        if let Some((built, index)) = &self.analysis.caches.package_index {
+            if built.elapsed() < PACKAGE_INDEX_TTL {
+                return index.clone();
+            }
+        }
+        let index = Arc::new(PackageIndex::build(self));
+        self.analysis.caches.package_index = Some((Instant::now(), index.clone()));
+        index
+    }
+
+    /// Get the values of the top-level bindings in a source, which are
+    /// evaluated by `f` once per version of the source and the document.
+    pub(crate) fn binding_values(
+        &mut self,
+        source: &Source,
+        doc: Option<&VersionedDocument>,
+        f: impl FnOnce(&Self) -> BindingValues,
+    ) -> Arc<BindingValues> {
+        let hash = hash128(source);
+        let doc = doc.map(|doc| doc.document.clone());
+        let cached = self.analysis.caches.binding_values.get(&source.id());
+        if let Some((cached_hash, cached_doc, values)) = cached {
+            let same_doc = cached_doc.as_ref().map(Arc::as_ptr) == doc.as_ref().map(Arc::as_ptr);
+            if *cached_hash == hash && same_doc {
+                return values.clone();
+            }
+        }
+        let values = Arc::new(f(self));
+        let caches = &mut self.analysis.caches;
+        caches
+            .binding_values
+            .insert(source.id(), (hash, doc, values.clone()));
+        values
+    }
+
+    /// Get the image of a glyph in a font, which is rendered by `f` once per
+    /// color theme.
+    pub(crate) fn glyph_image(
+        &mut self,
+        font: Font,
+        c: char,
+        f: impl FnOnce(&Self, Font) -> Option<EcoString>,
+    ) -> Option<EcoString> {
+        let key = (font, c, self.analysis.preferred_theme);
+        if let Some(image) = self.analysis.caches.glyph_images.get(&key) {
+            return image.clone();
+        }
+        let image = f(self, key.0.clone());
+        let caches = &mut self.analysis.caches;
+        caches.glyph_images.insert(key, image.clone());
+        image
+    }
+
+    /// Get the locations of the `context` expressions shown in a document,
+    /// which are indexed once per version of the document.
+    pub(crate) fn contextual_locations(
+        &mut self,
+        doc: &VersionedDocument,
+    ) -> Arc<ContextualLocations> {
+        let caches = &mut self.analysis.caches;
+        if let Some((indexed, locations)) = &caches.contextual_locations {
+            if Arc::ptr_eq(indexed, &doc.document) {
+                return locations.clone();
+            }
+        }
+
+        let mut locations = ContextualLocations::new();
+        let introspector = &doc.document.introspector;
+        for elem in introspector.query(&ContextElem::elem().select()) {
+            if let Some(location) = elem.location() {
+                locations.entry(elem.span()).or_default().push(location);
+            }
+        }
+        let locations = Arc::new(locations);
+        caches.contextual_locations = Some((doc.document.clone(), locations.clone()));
+        locations
+    }
+
+    /// Rebuild the index of the workspace from scratch. A cancelled index is
+    /// partial and doesn't replace the last one.
+    pub fn reindex_workspace(
+        &mut self,
+        cancel: Option<&AtomicBool>,
+        progress: Option<&IndexProgress>,
+    ) -> Arc<WorkspaceIndex> {
+        let index = Arc::new(WorkspaceIndex::build(self, cancel, progress));
+        if !index.cancelled {
+            self.analysis.caches.workspace_index = Some(index.clone());
+        }
+        index
+    }
+
     /// Get the module dependencies of the workspace.
     pub fn module_dependencies(&mut self) -> &HashMap<TypstFileId, ModuleDependency> {
         if self.caches.module_deps.get().is_some() {
@@ -565,6 +745,20 @@ impl<'w> AnalysisContext<'w> {
         self.source_by_id(id)
     }
 
+    /// Get the sources of the files depended on by the last compilation.
+    pub fn dependent_sources(&mut self) -> Vec<Source> {
+        let resources = self.resources;
+        let mut sources = vec![];
+        resources.iter_dependencies(&mut |path, _| {
+            if path.extension().is_some_and(|ext| ext == "typ") {
+                if let Ok(source) = self.source_by_path(path) {
+                    sources.push(source);
+                }
+            }
+        });
+        sources
+    }
+
     /// Get the module-level analysis cache of a file.
     pub fn get(&self, file_id: TypstFileId) -> Option<&ModuleAnalysisCache> {
         self.caches.modules.get(&file_id)
@@ -773,6 +967,19 @@ impl<'w> AnalysisContext<'w> {
         res
     }
 
+    /// Analyze the bibliography files referenced by the `#bibliography` call
+    /// of a compiled document.
+    pub(crate) fn analyze_document_bib(&mut self, doc: &Document) -> Option<Arc<BibInfo>> {
+        use comemo::Track;
+
+        let bib_elem = BibliographyElem::find(doc.introspector.track()).ok()?;
+        let Value::Array(paths) = bib_elem.path().clone().into_value() else {
+            return None;
+        };
+        let paths = paths.into_iter().map(Value::cast).flat_map(|e| e.ok());
+        self.analyze_bib(bib_elem.span(), paths)
+    }
+
     fn at_module(&mut self, fid: TypstFileId) -> &mut ModuleAnalysisGlobalCache {
         self.analysis.caches.modules.entry(fid).or_default()
     }
@@ -4,8 +4,7 @@ use std::ops::Range;
 
 use log::debug;
 use once_cell::sync::Lazy;
-use typst::foundations::{IntoValue, Label, Selector, Type};
-use typst::model::BibliographyElem;
+use typst::foundations::{Label, Selector, Type};
 use typst::syntax::FileId as TypstFileId;
 use typst::{foundations::Value, syntax::Span};
 
@@ -77,17 +76,7 @@ pub fn find_definition(
             let doc = document?;
             let introspector = &doc.document.introspector;
             let label = Label::new(ref_node);
-            let bib_elem = BibliographyElem::find(introspector.track())
-                .ok()
-                .and_then(|bib_elem| {
-                    ctx.analyze_bib(bib_elem.span(), {
-                        let Value::Array(arr) = bib_elem.path().clone().into_value() else {
-                            return None;
-                        };
-
-                        arr.into_iter().map(Value::cast).flat_map(|e| e.ok())
-                    })
-                });
+            let bib_elem = ctx.analyze_document_bib(&doc.document);
 
             return bib_elem
                 .and_then(|e| find_bib_definition(e, ref_node))
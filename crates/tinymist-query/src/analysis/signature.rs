@@ -69,6 +69,11 @@ impl ParamSpec {
             settable: p.settable,
         })
     }
+
+    /// Get the default value of the parameter as code, if it has one.
+    pub fn default_repr(&self) -> Option<EcoString> {
+        self.expr.clone().or_else(|| Some(self.default?().repr()))
+    }
 }
 
 /// Describes a function signature.
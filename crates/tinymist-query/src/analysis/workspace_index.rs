@@ -0,0 +1,90 @@
+//! An index of the source files in the workspace, which complements the files
+//! depended on by the last compilation.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use typst::syntax::{LinkedNode, SyntaxKind};
+
+use super::prelude::*;
+use crate::syntax::{get_lexical_hierarchy, LexicalScopeKind};
+
+/// The source files in the workspace, along with statistics of them.
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceIndex {
+    /// The source files which are not ignored by `indexIgnore`.
+    pub files: Vec<TypstFileId>,
+    /// The number of symbols in the files.
+    pub symbols: usize,
+    /// The number of labels in the files.
+    pub labels: usize,
+    /// Whether the indexing is cancelled before all the files are indexed.
+    pub cancelled: bool,
+}
+
+/// The callback receiving the number of indexed files and the number of all
+/// the files, whenever the percentage of indexed files increases.
+#[derive(Clone)]
+pub struct IndexProgress(pub Arc<dyn Fn(usize, usize) + Send + Sync>);
+
+impl fmt::Debug for IndexProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IndexProgress")
+    }
+}
+
+impl WorkspaceIndex {
+    /// Scan the workspace for source files from scratch, and parse them to
+    /// count the symbols and labels. The indexing stops before the next file
+    /// once the `cancel` flag is set.
+    pub fn build(
+        ctx: &mut AnalysisContext,
+        cancel: Option<&AtomicBool>,
+        progress: Option<&IndexProgress>,
+    ) -> Self {
+        let files = ctx.source_files().clone();
+
+        let mut index = Self::default();
+        let mut reported = None;
+        for (idx, id) in files.iter().enumerate() {
+            if cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+                log::info!(
+                    "indexing workspace: cancelled at {idx}/{} files",
+                    files.len()
+                );
+                index.cancelled = true;
+                break;
+            }
+            if idx % 100 == 0 {
+                log::info!("indexing workspace: {idx}/{} files", files.len());
+            }
+            let percentage = idx * 100 / files.len();
+            if let Some(progress) = progress.filter(|_| reported != Some(percentage)) {
+                (progress.0)(idx, files.len());
+                reported = Some(percentage);
+            }
+            let Ok(source) = ctx.source_by_id(*id) else {
+                continue;
+            };
+            if let Some(hierarchy) = get_lexical_hierarchy(source.clone(), LexicalScopeKind::Symbol)
+            {
+                index.symbols += count_symbols(&hierarchy);
+            }
+            index.labels += count_labels(&LinkedNode::new(source.root()));
+            index.files.push(*id);
+        }
+        log::info!("indexing workspace: {} files", index.files.len());
+
+        index
+    }
+}
+
+fn count_symbols(hierarchy: &[LexicalHierarchy]) -> usize {
+    let children = |e: &LexicalHierarchy| e.children.as_deref().map_or(0, |c| count_symbols(c));
+    hierarchy.iter().map(|e| 1 + children(e)).sum()
+}
+
+fn count_labels(node: &LinkedNode) -> usize {
+    let children = node.children().map(|child| count_labels(&child));
+    usize::from(node.kind() == SyntaxKind::Label) + children.sum::<usize>()
+}
@@ -1,7 +1,14 @@
-use lsp_types::TextEdit;
+use std::ops::Range;
+
+use lsp_types::{CodeActionContext, TextEdit};
 use once_cell::sync::OnceCell;
 
-use crate::{prelude::*, SemanticRequest};
+use crate::{
+    analysis::{import_edit, package_exports},
+    prelude::*,
+    syntax::extract_to_let,
+    SemanticRequest,
+};
 
 /// The [`textDocument/codeLens`] request is sent from the client to the server
 /// to compute code lenses for a given text document.
@@ -13,6 +20,8 @@ pub struct CodeActionRequest {
     pub path: PathBuf,
     /// The range of the document to get code actions for.
     pub range: LspRange,
+    /// The diagnostics overlapping the range, sent by the client.
+    pub context: CodeActionContext,
 }
 
 impl SemanticRequest for CodeActionRequest {
@@ -27,6 +36,10 @@ impl SemanticRequest for CodeActionRequest {
         let root = LinkedNode::new(source.root());
         let mut worker = CodeActionWorker::new(ctx, source.clone());
         worker.work(root, cursor);
+        if !range.is_empty() {
+            worker.extract_actions(range);
+        }
+        worker.import_actions(&self.context.diagnostics);
 
         let res = worker.actions;
         (!res.is_empty()).then_some(res)
@@ -169,11 +182,138 @@ impl<'a, 'w> CodeActionWorker<'a, 'w> {
         Some(())
     }
 
+    /// Offer to wrap an image into a figure, or unwrap a figure of an image
+    /// into the bare image. The arguments of the image are kept, while the
+    /// ones of the figure are dropped with it.
+    fn figure_actions(&mut self, leaf: &LinkedNode) -> Option<()> {
+        let call = leaf.cast::<ast::FuncCall>()?;
+        let ast::Expr::Ident(callee) = call.callee() else {
+            return None;
+        };
+
+        let (title, new_text) = match callee.as_str() {
+            // An image in a figure is unwrapped by the figure.
+            "image" if !is_figure_body(leaf) => {
+                let image = &self.current.text()[leaf.range()];
+                let figure = format!("figure({image}, caption: [])");
+                ("Wrap image in figure", figure)
+            }
+            "figure" => {
+                let body = call.args().items().find_map(|arg| match arg {
+                    ast::Arg::Pos(ast::Expr::FuncCall(body)) => Some(body),
+                    _ => None,
+                })?;
+                let ast::Expr::Ident(body_callee) = body.callee() else {
+                    return None;
+                };
+                if body_callee.as_str() != "image" {
+                    return None;
+                }
+                let body = leaf.find(body.span())?;
+                let image = self.current.text()[body.range()].to_owned();
+                ("Unwrap figure to image", image)
+            }
+            _ => return None,
+        };
+
+        let action = CodeActionOrCommand::CodeAction(CodeAction {
+            title: title.to_owned(),
+            kind: Some(CodeActionKind::REFACTOR_REWRITE),
+            edit: Some(self.local_edit(TextEdit {
+                range: self.ctx.to_lsp_range(leaf.range(), &self.current),
+                new_text,
+            })?),
+            ..CodeAction::default()
+        });
+        self.actions.push(action);
+
+        Some(())
+    }
+
+    /// Offer to extract the selected expression or markup into a `#let`
+    /// binding, along with the identical expressions after the binding.
+    fn extract_actions(&mut self, range: Range<usize>) -> Option<()> {
+        let extraction = extract_to_let(&self.current, range)?;
+        let insert_at = self
+            .ctx
+            .to_lsp_range(extraction.insert_at..extraction.insert_at, &self.current);
+        let edits = |replacements: &[(Range<usize>, String)]| {
+            let insertion = TextEdit {
+                range: insert_at,
+                new_text: extraction.binding.clone(),
+            };
+            let replacements = replacements.iter().map(|(range, new_text)| TextEdit {
+                range: self.ctx.to_lsp_range(range.clone(), &self.current),
+                new_text: new_text.clone(),
+            });
+            self.local_edits(std::iter::once(insertion).chain(replacements).collect())
+        };
+
+        let target = if extraction.is_function {
+            "function"
+        } else {
+            "variable"
+        };
+        let action = CodeActionOrCommand::CodeAction(CodeAction {
+            title: format!("Extract to {target}"),
+            kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+            edit: Some(edits(std::slice::from_ref(&extraction.replacement))?),
+            ..CodeAction::default()
+        });
+        let count = extraction.occurrences.len();
+        let all = (count > 1).then(|| {
+            Some(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Extract all {count} occurrences to {target}"),
+                kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+                edit: Some(edits(&extraction.occurrences)?),
+                ..CodeAction::default()
+            }))
+        });
+
+        self.actions.push(action);
+        self.actions.extend(all.flatten());
+        Some(())
+    }
+
+    /// Offer imports for unknown variables that a known package provides.
+    fn import_actions(&mut self, diagnostics: &[LspDiagnostic]) {
+        let mut names = diagnostics
+            .iter()
+            .filter_map(|diag| diag.message.strip_prefix("unknown variable: "))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        if names.is_empty() {
+            return;
+        }
+
+        let exports = package_exports(self.ctx, |name| names.contains(&name));
+        for (spec, name) in exports {
+            let Some((range, new_text)) = import_edit(&self.current, &spec, &name) else {
+                continue;
+            };
+            let Some(edit) = self.local_edit(TextEdit {
+                range: self.ctx.to_lsp_range(range, &self.current),
+                new_text,
+            }) else {
+                continue;
+            };
+            let action = CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Add import from {spec}"),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(edit),
+                ..CodeAction::default()
+            });
+            self.actions.push(action);
+        }
+    }
+
     fn work(&mut self, root: LinkedNode, cursor: usize) -> Option<()> {
         let mut node = root.leaf_at(cursor)?;
 
         let mut heading_resolved = false;
         let mut equation_resolved = false;
+        let mut figure_resolved = false;
 
         loop {
             match node.kind() {
@@ -187,6 +327,10 @@ impl<'a, 'w> CodeActionWorker<'a, 'w> {
                     equation_resolved = true;
                     self.equation_actions(&node);
                 }
+                // Only the deepest image or figure is considered
+                SyntaxKind::FuncCall if !figure_resolved => {
+                    figure_resolved = self.figure_actions(&node).is_some();
+                }
                 _ => {}
             }
 
@@ -194,3 +338,223 @@ impl<'a, 'w> CodeActionWorker<'a, 'w> {
         }
     }
 }
+
+/// Whether the call is an argument of a `figure` call.
+fn is_figure_body(call: &LinkedNode) -> bool {
+    let Some(figure) = call.parent().and_then(|args| args.parent()) else {
+        return false;
+    };
+    match figure.cast::<ast::FuncCall>().map(|figure| figure.callee()) {
+        Some(ast::Expr::Ident(callee)) => callee.as_str() == "figure",
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Diagnostic;
+
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn test_import_known_package() {
+        run_with_ctx("#canvas({})", |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let range = ctx.to_lsp_range(1..7, &source);
+            let request = CodeActionRequest {
+                path: path.clone(),
+                range,
+                context: CodeActionContext {
+                    diagnostics: vec![Diagnostic {
+                        range,
+                        message: "unknown variable: canvas".to_owned(),
+                        ..Diagnostic::default()
+                    }],
+                    ..CodeActionContext::default()
+                },
+            };
+
+            let actions = request.request(ctx).unwrap();
+            let action = actions
+                .iter()
+                .find_map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action)
+                        if action.title.starts_with("Add import") =>
+                    {
+                        Some(action)
+                    }
+                    _ => None,
+                })
+                .unwrap();
+            assert_eq!(action.title, "Add import from @preview/cetz:0.2.2");
+            assert_eq!(action.kind, Some(CodeActionKind::QUICKFIX));
+
+            let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+            let edits = changes.values().next().unwrap();
+            assert_eq!(
+                edits,
+                &vec![TextEdit {
+                    range: LspRange::default(),
+                    new_text: "#import \"@preview/cetz:0.2.2\": canvas\n".to_owned(),
+                }]
+            );
+        });
+    }
+
+    /// Apply the rewrite with the title at the cursor, checking that the
+    /// result is valid Typst.
+    fn rewrite(content: &str, cursor: usize, title: &str) -> String {
+        run_with_ctx(content, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let request = CodeActionRequest {
+                path: path.clone(),
+                range: ctx.to_lsp_range(cursor..cursor, &source),
+                context: CodeActionContext::default(),
+            };
+
+            let actions = request.request(ctx).unwrap();
+            let action = actions
+                .iter()
+                .find_map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) if action.title == title => {
+                        Some(action)
+                    }
+                    _ => None,
+                })
+                .unwrap_or_else(|| panic!("no action {title:?} in {actions:?}"));
+            let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+            let [edit] = &changes.values().next().unwrap()[..] else {
+                panic!("unexpected edits {changes:?}");
+            };
+
+            let range = ctx.to_typst_range(edit.range, &source).unwrap();
+            let mut text = content.to_owned();
+            text.replace_range(range, &edit.new_text);
+            let errors = typst::syntax::parse(&text).errors();
+            assert!(errors.is_empty(), "{text:?} has errors {errors:?}");
+            text
+        })
+    }
+
+    #[test]
+    fn test_wrap_image_in_figure() {
+        let content = "Look:\n#image(\"cat.png\", width: 50%)";
+        assert_eq!(
+            rewrite(content, 8, "Wrap image in figure"),
+            "Look:\n#figure(image(\"cat.png\", width: 50%), caption: [])"
+        );
+    }
+
+    #[test]
+    fn test_unwrap_figure_to_image() {
+        let content = "#figure(image(\"cat.png\", width: 50%), caption: [A cat.]) <cat>";
+        // The cursor is on the image, which is in a figure.
+        let image = rewrite(content, 10, "Unwrap figure to image");
+        assert_eq!(image, "#image(\"cat.png\", width: 50%) <cat>");
+        assert_eq!(rewrite(content, 2, "Unwrap figure to image"), image);
+    }
+
+    /// Apply the extraction with the title of the selection, checking that
+    /// the result is valid Typst.
+    fn extract(content: &str, selection: &str, title: &str) -> String {
+        run_with_ctx(content, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let start = content.find(selection).unwrap();
+            let request = CodeActionRequest {
+                path: path.clone(),
+                range: ctx.to_lsp_range(start..start + selection.len(), &source),
+                context: CodeActionContext::default(),
+            };
+
+            let actions = request.request(ctx).unwrap_or_default();
+            let action = actions
+                .iter()
+                .find_map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) if action.title == title => {
+                        Some(action)
+                    }
+                    _ => None,
+                })
+                .unwrap_or_else(|| panic!("no action {title:?} in {actions:?}"));
+            assert_eq!(action.kind, Some(CodeActionKind::REFACTOR_EXTRACT));
+            let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+            let mut edits = changes.values().next().unwrap().clone();
+
+            // Apply the edits from the end, keeping the insertion before a
+            // replacement at the same position.
+            edits.sort_by_key(|edit| edit.range.start);
+            let mut text = content.to_owned();
+            for edit in edits.iter().rev() {
+                let range = ctx.to_typst_range(edit.range, &source).unwrap();
+                text.replace_range(range, &edit.new_text);
+            }
+            let errors = typst::syntax::parse(&text).errors();
+            assert!(errors.is_empty(), "{text:?} has errors {errors:?}");
+            text
+        })
+    }
+
+    #[test]
+    fn test_extract_to_variable() {
+        let content = "= Sums\nTotal: #calc.pow(1 + 2, 2).\n";
+        assert_eq!(
+            extract(content, "1 + 2", "Extract to variable"),
+            "= Sums\n#let extracted = 1 + 2\nTotal: #calc.pow(extracted, 2).\n"
+        );
+
+        // The name does not collide with the existing ones.
+        let content = "#let extracted = 1\n#(extracted + 2)\n";
+        assert_eq!(
+            extract(content, "extracted + 2", "Extract to variable"),
+            "#let extracted = 1\n#let extracted2 = extracted + 2\n#(extracted2)\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_markup() {
+        let content = "Hello *world*, and more.\n";
+        assert_eq!(
+            extract(content, "*world*", "Extract to variable"),
+            "#let extracted = [*world*]\nHello #extracted, and more.\n"
+        );
+        assert_eq!(
+            extract(content, "Hello *world*", "Extract to variable"),
+            "#let extracted = [Hello *world*]\n#extracted, and more.\n"
+        );
+
+        // The reference is terminated before text continuing it.
+        let content = "A _world_-like text.\n";
+        assert_eq!(
+            extract(content, "_world_", "Extract to variable"),
+            "#let extracted = [_world_]\nA #extracted;-like text.\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_to_function() {
+        let content = "#let area(w, h) = {\n  w * h / 2\n}\n";
+        assert_eq!(
+            extract(content, "w * h", "Extract to function"),
+            "#let extracted(w, h) = w * h\n#let area(w, h) = {\n  extracted(w, h) / 2\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_all_occurrences() {
+        let content = "#let a = (1, 2)\n#(1 + 2) and #(1 + 2)\n";
+        assert_eq!(
+            extract(content, "(1 + 2)", "Extract all 2 occurrences to variable"),
+            "#let a = (1, 2)\n#let extracted = (1 + 2)\n#extracted and #extracted\n"
+        );
+
+        // The expressions referring to other bindings, e.g. the parameter of a
+        // closure or a later binding shadowing the first one, are kept.
+        let content = "#let a = 1\n#(a + 1)\n#let f(a) = a + 1\n#(a + 1)\n#let a = 2\n#(a + 1)\n";
+        assert_eq!(
+            extract(content, "a + 1", "Extract all 2 occurrences to variable"),
+            "#let a = 1\n#let extracted = a + 1\n#(extracted)\n#let f(a) = a + 1\n\
+             #(extracted)\n#let a = 2\n#(a + 1)\n"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 use lsp_types::Command;
 
 use crate::{prelude::*, SemanticRequest};
@@ -40,6 +42,79 @@ impl SemanticRequest for CodeLensRequest {
         res.push(doc_lens("Export PDF", vec!["export-pdf".into()]));
         res.push(doc_lens("Export as ..", vec!["export-as".into()]));
 
+        for (name, range) in entry_functions(&source) {
+            res.push(CodeLens {
+                range: ctx.to_lsp_range(range, &source),
+                command: Some(run_code_lens_cmd(
+                    "Render this variant",
+                    vec!["render-variant".into(), name.as_str().into()],
+                )),
+                data: None,
+            });
+        }
+
         Some(res)
     }
 }
+
+/// Find the functions rendering a variant of the document, which are defined
+/// at the top level, named `main`, `main-*` or `variant-*` and callable
+/// without arguments.
+fn entry_functions(source: &Source) -> Vec<(EcoString, Range<usize>)> {
+    let root = LinkedNode::new(source.root());
+    let mut functions = vec![];
+    for child in root.children() {
+        let Some(binding) = child.cast::<ast::LetBinding>() else {
+            continue;
+        };
+        let ast::LetBindingKind::Closure(name) = binding.kind() else {
+            continue;
+        };
+        let name = name.get();
+        if !(name == "main" || name.starts_with("main-") || name.starts_with("variant-")) {
+            continue;
+        }
+
+        let Some(ast::Expr::Closure(closure)) = binding.init() else {
+            continue;
+        };
+        let required = (closure.params().children()).any(|p| matches!(p, ast::Param::Pos(_)));
+        if !required {
+            functions.push((name.clone(), child.range()));
+        }
+    }
+    functions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn test_render_variant() {
+        let content = "#let main() = [Main]\n\
+                       #let variant-print(color: false) = [Print]\n\
+                       #let main-helper(x) = x\n\
+                       #let other() = none\n\
+                       #{ let main() = [Inner] }";
+        run_with_ctx(content, |ctx, path| {
+            let lenses = CodeLensRequest { path }.request(ctx).unwrap();
+            let variants: Vec<_> = (lenses.iter())
+                .filter_map(|lens| Some((lens.range.start.line, lens.command.as_ref()?)))
+                .filter(|(_, cmd)| cmd.title == "Render this variant")
+                .map(|(line, cmd)| (line, cmd.arguments.clone().unwrap()))
+                .collect();
+            assert_eq!(
+                variants,
+                [
+                    (0, vec![JsonValue::from("render-variant"), "main".into()]),
+                    (
+                        1,
+                        vec![JsonValue::from("render-variant"), "variant-print".into()]
+                    ),
+                ]
+            );
+        });
+    }
+}
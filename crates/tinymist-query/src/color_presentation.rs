@@ -23,23 +23,60 @@ impl ColorPresentationRequest {
             self.color.alpha,
         ));
         Some(vec![
-            simple(format!("{:?}", color.to_hex())),
+            // The hex form, e.g. `rgb("#ff4136")`.
             simple(color.to_rgb().repr().to_string()),
+            simple(rgb_components(color)),
             simple(color.to_luma().repr().to_string()),
+            simple(color.to_cmyk().repr().to_string()),
             simple(color.to_oklab().repr().to_string()),
             simple(color.to_oklch().repr().to_string()),
-            simple(color.to_rgb().repr().to_string()),
             simple(color.to_linear_rgb().repr().to_string()),
-            simple(color.to_cmyk().repr().to_string()),
             simple(color.to_hsl().repr().to_string()),
             simple(color.to_hsv().repr().to_string()),
         ])
     }
 }
 
+/// Get the form of a color with its RGB components, e.g. `rgb(255, 65, 54)`.
+fn rgb_components(color: typst::visualize::Color) -> String {
+    match color.to_rgb().to_vec4_u8() {
+        [r, g, b, 255] => format!("rgb({r}, {g}, {b})"),
+        [r, g, b, a] => format!("rgb({r}, {g}, {b}, {a})"),
+    }
+}
+
 fn simple(label: String) -> ColorPresentation {
     ColorPresentation {
         label,
         ..ColorPresentation::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presentations() {
+        let request = ColorPresentationRequest {
+            path: PathBuf::from("/main.typ"),
+            color: lsp_types::Color {
+                red: 1.,
+                green: 0.,
+                blue: 0.,
+                alpha: 1.,
+            },
+            range: LspRange::default(),
+        };
+        let labels: Vec<_> = request
+            .request()
+            .unwrap()
+            .into_iter()
+            .map(|p| p.label)
+            .collect();
+        assert!(labels.contains(&r##"rgb("#ff0000")"##.to_owned()));
+        assert!(labels.contains(&"rgb(255, 0, 0)".to_owned()));
+        assert!(labels.iter().any(|l| l.starts_with("cmyk(")));
+        assert!(labels.iter().any(|l| l.starts_with("luma(")));
+    }
+}
@@ -1,15 +1,60 @@
-use lsp_types::CompletionList;
+use std::ops::Range;
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionList, CompletionTextEdit, InsertTextFormat,
+    TextEdit,
+};
+use serde::{Deserialize, Serialize};
+use typst::foundations::{Array, CastInfo, Content, Dict, Str, Type};
+use typst::visualize::{Color, Rgb};
 
 use crate::{
-    analysis::{FlowBuiltinType, FlowType},
+    analysis::{
+        data_fields, get_color_exprs, import_edit, package_exports, FlowBuiltinType, FlowType,
+    },
+    hover::math_image,
     prelude::*,
-    syntax::{get_deref_target, DerefTarget},
-    upstream::{autocomplete, complete_path, CompletionContext},
-    StatefulRequest,
+    syntax::{
+        active_arg, get_deref_target, get_field_target, is_sys_inputs, ActiveArg, DerefTarget,
+        FieldTarget,
+    },
+    upstream::{
+        autocomplete, complete_path, package_import_str, plain_docs_sentence, Completion,
+        CompletionContext,
+    },
+    SemanticRequest, StatefulRequest,
 };
 
 use self::typst_to_lsp::completion;
 
+/// A snippet configured by the user with `userSnippets`, e.g. a figure wrapper
+/// or a theorem environment of a team.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSnippet {
+    /// The label of the snippet, which is completed once it is typed.
+    pub prefix: String,
+    /// The body of the snippet, with placeholders like `${1:name}`.
+    pub body: String,
+    /// The description of the snippet.
+    #[serde(default)]
+    pub description: String,
+    /// The mode where the snippet is completed, or all modes if absent.
+    pub scope: Option<SnippetScope>,
+}
+
+/// The syntax mode of a snippet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SnippetScope {
+    /// Markup, e.g. the text of paragraphs.
+    Markup,
+    /// Code, e.g. after a hash.
+    Code,
+    /// Math, e.g. in equations.
+    Math,
+}
+
 /// The [`textDocument/completion`] request is sent from the client to the
 /// server to compute completion items at a given cursor position.
 ///
@@ -79,6 +124,39 @@ impl StatefulRequest for CompletionRequest {
 
         let root = LinkedNode::new(source.root());
         let node = root.leaf_at(cursor);
+
+        // `@` triggers completions of labels behind a reference marker and of
+        // packages in an import path, and nothing elsewhere, e.g. in a string.
+        if source.text()[..cursor].ends_with('@')
+            && !node.as_ref().is_some_and(|leaf| {
+                leaf.kind() == SyntaxKind::RefMarker || package_import_str(leaf).is_some()
+            })
+        {
+            return None;
+        }
+
+        // Complete the fields of loaded data, e.g. `row.` or `row.at("")`, and
+        // the provided inputs, e.g. `sys.inputs.`.
+        if let Some(items) = node.as_ref().and_then(|leaf| {
+            complete_sys_inputs(ctx, &source, leaf)
+                .or_else(|| complete_data_fields(ctx, &source, leaf))
+        }) {
+            return Some(CompletionResponse::List(CompletionList {
+                is_incomplete: false,
+                items,
+            }));
+        }
+
+        // Complete the methods of the value inferred for `value.`, and wrap the
+        // value, e.g. an image path in a figure.
+        let postfix = node
+            .as_ref()
+            .and_then(|leaf| complete_postfix(ctx, &source, leaf));
+        // Complete colors with swatches where a color is expected.
+        let colors = node
+            .as_ref()
+            .and_then(|leaf| complete_colors(ctx, &source, leaf, cursor));
+
         let deref_target = node.and_then(|node| get_deref_target(node, cursor));
 
         if let Some(d) = &deref_target {
@@ -153,26 +231,13 @@ impl StatefulRequest for CompletionRequest {
             let _ = ic;
 
             let replace_range;
+            let mut auto_imports = vec![];
             if match_ident.as_ref().is_some_and(|i| i.offset() == offset) {
                 let match_ident = match_ident.unwrap();
                 let mut rng = match_ident.range();
                 let ident_prefix = source.text()[rng.start..cursor].to_string();
 
-                completions.retain(|c| {
-                    // c.label
-                    let mut prefix_matcher = c.label.chars();
-                    'ident_matching: for ch in ident_prefix.chars() {
-                        for c in prefix_matcher.by_ref() {
-                            if c == ch {
-                                continue 'ident_matching;
-                            }
-                        }
-
-                        return false;
-                    }
-
-                    true
-                });
+                completions.retain(|c| matches_prefix(&ident_prefix, &c.label));
 
                 // if modifying some arguments, we need to truncate and add a comma
                 if !is_callee && cursor != rng.end && is_arg_like_context(&match_ident) {
@@ -196,22 +261,43 @@ impl StatefulRequest for CompletionRequest {
                 }
 
                 replace_range = ctx.to_lsp_range(rng, &source);
+                auto_imports = auto_import_completions(
+                    ctx,
+                    &source,
+                    &ident_prefix,
+                    &completions,
+                    replace_range,
+                );
             } else {
                 let lsp_start_position = ctx.to_lsp_pos(offset, &source);
                 replace_range = LspRange::new(lsp_start_position, self.position);
             }
 
-            Some(
-                completions
-                    .iter()
-                    .map(|typst_completion| completion(typst_completion, replace_range))
-                    .collect_vec(),
-            )
-        })?;
+            let items = completions
+                .iter()
+                .map(|typst_completion| completion(typst_completion, replace_range));
+            Some(items.chain(auto_imports).collect_vec())
+        });
+        let mut items = match (items, postfix.is_some() || colors.is_some()) {
+            (Some(items), _) => items,
+            (None, true) => vec![],
+            (None, false) => return None,
+        };
 
         if let Some(items_rest) = completion_items_rest.as_mut() {
             items.append(items_rest);
         }
+        // The methods found by evaluation take precedence.
+        for item in postfix.into_iter().flatten() {
+            if !items.iter().any(|existing| existing.label == item.label) {
+                items.push(item);
+            }
+        }
+        // The colors replace the plain completions of the same names.
+        if let Some(colors) = colors {
+            items.retain(|existing| !colors.iter().any(|item| item.label == existing.label));
+            items.extend(colors);
+        }
 
         // To response completions in fine-grained manner, we need to mark result as
         // incomplete. This follows what rust-analyzer does.
@@ -223,6 +309,535 @@ impl StatefulRequest for CompletionRequest {
     }
 }
 
+/// The data of a completion item, which is resolved lazily in
+/// [`CompletionResolveRequest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionData {
+    /// The path and the range of the equation to preview in the
+    /// documentation.
+    pub equation: Option<(PathBuf, Range<usize>)>,
+}
+
+/// The [`completionItem/resolve`] request is sent from the client to the
+/// server to resolve additional information for a given completion item.
+///
+/// The previews of equations are rendered here rather than for every
+/// completion, since only the selected items are resolved.
+///
+/// [`completionItem/resolve`]: https://microsoft.github.io/language-server-protocol/specification#completionItem_resolve
+#[derive(Debug, Clone)]
+pub struct CompletionResolveRequest {
+    /// The item to resolve.
+    pub item: CompletionItem,
+    /// The data of the item.
+    pub data: CompletionData,
+}
+
+impl CompletionResolveRequest {
+    /// Create a request to resolve the item, if it has data to resolve.
+    pub fn new(item: CompletionItem) -> Option<Self> {
+        let data = serde_json::from_value(item.data.clone()?).ok()?;
+        Some(Self { item, data })
+    }
+}
+
+impl SemanticRequest for CompletionResolveRequest {
+    type Response = CompletionItem;
+
+    fn request(self, ctx: &mut AnalysisContext) -> Option<Self::Response> {
+        let mut item = self.item;
+        // The item is kept as is if the equation has been edited away.
+        let image = self.data.equation.and_then(|(path, range)| {
+            let source = ctx.source_by_path(&path).ok()?;
+            math_image(ctx, source.text().get(range)?)
+        });
+        if let Some(image) = image {
+            item.documentation = Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: image,
+            }));
+        }
+        Some(item)
+    }
+}
+
+/// Complete the fields of a variable holding data loaded by `csv` or `json`.
+fn complete_data_fields(
+    ctx: &AnalysisContext,
+    source: &Source,
+    leaf: &LinkedNode,
+) -> Option<Vec<CompletionItem>> {
+    let FieldTarget {
+        target: var,
+        range,
+        quoted,
+    } = get_field_target(leaf)?;
+
+    let data = data_fields(ctx.world(), source, &var)?;
+    let range = ctx.to_lsp_range(range, source);
+    let items = data
+        .fields
+        .iter()
+        // Only identifiers can be accessed as fields.
+        .filter(|field| quoted || typst::syntax::is_ident(field))
+        .map(|field| {
+            let new_text = if quoted {
+                format!("{:?}", field.as_str())
+            } else {
+                field.to_string()
+            };
+            CompletionItem {
+                label: field.to_string(),
+                kind: Some(CompletionItemKind::FIELD),
+                detail: Some(format!("field of {}", data.path)),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+                ..Default::default()
+            }
+        })
+        .collect_vec();
+
+    (!items.is_empty()).then_some(items)
+}
+
+/// Complete the keys of the inputs provided to the world, e.g. by `--input`,
+/// after `sys.inputs.` or in `sys.inputs.at("")`.
+fn complete_sys_inputs(
+    ctx: &AnalysisContext,
+    source: &Source,
+    leaf: &LinkedNode,
+) -> Option<Vec<CompletionItem>> {
+    let FieldTarget {
+        target,
+        range,
+        quoted,
+    } = get_field_target(leaf)?;
+    if !is_sys_inputs(&target) {
+        return None;
+    }
+
+    let range = ctx.to_lsp_range(range, source);
+    let inputs = ctx.resources.inputs();
+    let items = inputs
+        .iter()
+        .filter(|(key, _)| quoted || typst::syntax::is_ident(key))
+        .map(|(key, value)| {
+            let new_text = if quoted {
+                format!("{:?}", key.as_str())
+            } else {
+                key.to_string()
+            };
+            CompletionItem {
+                label: key.to_string(),
+                kind: Some(CompletionItemKind::CONSTANT),
+                detail: Some(value.repr().to_string()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+                ..Default::default()
+            }
+        })
+        .collect_vec();
+
+    (!items.is_empty()).then_some(items)
+}
+
+/// Check whether the characters of the prefix appear in the label in order.
+fn matches_prefix(prefix: &str, label: &str) -> bool {
+    let mut prefix_matcher = label.chars();
+    'ident_matching: for ch in prefix.chars() {
+        for c in prefix_matcher.by_ref() {
+            if c == ch {
+                continue 'ident_matching;
+            }
+        }
+
+        return false;
+    }
+
+    true
+}
+
+/// The extensions of image files, which can be wrapped in figures.
+const IMAGE_EXTENSIONS: &[&str] = &[".png", ".jpg", ".jpeg", ".gif", ".svg"];
+
+/// Complete a field access in code by the type of its target inferred from
+/// the syntax and the type checker, so that methods are offered even if the
+/// target is not evaluated, e.g. in the body of a function. A path to an image
+/// can also be wrapped by `.fig` or `.image`.
+fn complete_postfix(
+    ctx: &mut AnalysisContext,
+    source: &Source,
+    leaf: &LinkedNode,
+) -> Option<Vec<CompletionItem>> {
+    let FieldTarget {
+        target,
+        range,
+        quoted: false,
+    } = get_field_target(leaf)?
+    else {
+        return None;
+    };
+    // Text after a value in markup is not a field access.
+    if target.parent_kind() == Some(SyntaxKind::Markup)
+        && target.prev_sibling_kind() != Some(SyntaxKind::Hash)
+    {
+        return None;
+    }
+
+    let ty = postfix_target_type(ctx, source, &target)?;
+    let edit_range = ctx.to_lsp_range(range.clone(), source);
+    let mut items = vec![];
+    for (name, value) in ty.scope().iter() {
+        let Value::Func(func) = value else {
+            continue;
+        };
+        // Only methods take the target as `self`.
+        let Some(params) = func.params() else {
+            continue;
+        };
+        if params.first().map_or(true, |param| param.name != "self") {
+            continue;
+        }
+        let new_text = if params.len() > 1 {
+            format!("{name}(${{1}})")
+        } else {
+            format!("{name}()")
+        };
+        items.push(CompletionItem {
+            label: name.to_string(),
+            kind: Some(CompletionItemKind::METHOD),
+            detail: func.docs().map(|docs| plain_docs_sentence(docs).into()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: edit_range,
+                new_text,
+            })),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            ..Default::default()
+        });
+    }
+
+    // Wrap a path to an image, replacing the path along with the dot.
+    if let Some(path) = target.cast::<ast::Str>() {
+        let path = path.get();
+        if IMAGE_EXTENSIONS.iter().any(|ext| path.ends_with(ext)) {
+            let expr = &source.text()[target.range()];
+            let snippet_expr = escape_snippet(expr);
+            let wrap_range = ctx.to_lsp_range(target.offset()..range.end, source);
+            let wrappers = [
+                (
+                    "fig",
+                    format!("figure(image({expr}), caption: [])"),
+                    format!("figure(image({snippet_expr}), caption: [${{1}}])"),
+                ),
+                (
+                    "image",
+                    format!("image({expr})"),
+                    format!("image({snippet_expr})"),
+                ),
+            ];
+            for (label, wrapped, new_text) in wrappers {
+                items.push(CompletionItem {
+                    label: label.to_owned(),
+                    kind: Some(CompletionItemKind::SNIPPET),
+                    detail: Some(format!("wrap the path in `{wrapped}`")),
+                    filter_text: Some(format!("{expr}.{label}")),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                        range: wrap_range,
+                        new_text,
+                    })),
+                    insert_text_format: Some(InsertTextFormat::SNIPPET),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    (!items.is_empty()).then_some(items)
+}
+
+/// Escape the text inserted literally into a snippet, in which `$`, `}` and
+/// `\` are special.
+fn escape_snippet(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '$' | '}' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Infer the type of the target of a field access, from its syntax or from the
+/// type inferred for the variable or the call.
+fn postfix_target_type(
+    ctx: &mut AnalysisContext,
+    source: &Source,
+    target: &LinkedNode,
+) -> Option<Type> {
+    match target.kind() {
+        SyntaxKind::Array => return Some(Type::of::<Array>()),
+        SyntaxKind::Dict => return Some(Type::of::<Dict>()),
+        SyntaxKind::Str => return Some(Type::of::<Str>()),
+        SyntaxKind::Int => return Some(Type::of::<i64>()),
+        SyntaxKind::Float => return Some(Type::of::<f64>()),
+        SyntaxKind::Bool => return Some(Type::of::<bool>()),
+        SyntaxKind::ContentBlock => return Some(Type::of::<Content>()),
+        SyntaxKind::Ident | SyntaxKind::FuncCall => {}
+        _ => return None,
+    }
+
+    // The span of the inferred type, which is the binding of a variable.
+    let (span, init) = match target.cast::<ast::Ident>() {
+        Some(ident) => {
+            let (binding, init) = find_local_binding(target, ident.get())?;
+            (binding, init)
+        }
+        None => (target.span(), None),
+    };
+    let types = ctx.type_check(source.clone());
+    let inferred = types.and_then(|types| {
+        let ty = types.mapping.get(&span)?;
+        flow_value_type(&types.simplify(ty.clone(), false))
+    });
+    inferred.or_else(|| postfix_target_type(ctx, source, &init?))
+}
+
+/// Find the binding of a variable before the node in its scopes, along with
+/// the initial value of a simple binding.
+fn find_local_binding<'a>(
+    node: &LinkedNode<'a>,
+    name: &str,
+) -> Option<(TypstSpan, Option<LinkedNode<'a>>)> {
+    let mut ancestor = Some(node.clone());
+    while let Some(current) = ancestor {
+        let mut sibling = current.prev_sibling();
+        while let Some(prev) = sibling {
+            if let Some(binding) = prev.cast::<ast::LetBinding>() {
+                let ident =
+                    (binding.kind().bindings().into_iter()).find(|ident| ident.get() == name);
+                if let Some(ident) = ident {
+                    let init = match binding.kind() {
+                        ast::LetBindingKind::Normal(ast::Pattern::Normal(_)) => {
+                            binding.init().and_then(|init| prev.find(init.span()))
+                        }
+                        _ => None,
+                    };
+                    return Some((ident.span(), init));
+                }
+            }
+            sibling = prev.prev_sibling();
+        }
+        ancestor = current.parent().cloned();
+    }
+    None
+}
+
+/// Get the type of the values of an inferred type, if they have one.
+fn flow_value_type(ty: &FlowType) -> Option<Type> {
+    match ty {
+        FlowType::Array(..) | FlowType::Tuple(..) => Some(Type::of::<Array>()),
+        FlowType::Dict(..) => Some(Type::of::<Dict>()),
+        FlowType::Content | FlowType::Element(..) => Some(Type::of::<Content>()),
+        FlowType::Boolean(..) => Some(Type::of::<bool>()),
+        FlowType::Value(value) => Some(value.0.ty()),
+        FlowType::ValueDoc(value) => Some(value.0.ty()),
+        FlowType::Union(types) => {
+            let mut types = types.iter().map(flow_value_type);
+            let first = types.next()??;
+            types.all(|ty| ty == Some(first)).then_some(first)
+        }
+        _ => None,
+    }
+}
+
+/// Complete colors where a color is expected, i.e. in `rgb(`, after `color.`
+/// and in arguments accepting colors. The colors bound by `#let` and used in
+/// the document are offered along with the named colors, all with their hex
+/// values, so that clients render swatches of them.
+fn complete_colors(
+    ctx: &mut AnalysisContext,
+    source: &Source,
+    leaf: &LinkedNode,
+    cursor: usize,
+) -> Option<Vec<CompletionItem>> {
+    let (range, filter_prefix) = color_context(ctx, leaf, cursor)?;
+    let edit_range = ctx.to_lsp_range(range, source);
+
+    let mut items: Vec<CompletionItem> = vec![];
+    let mut push = |label: String, detail: Option<String>, color: Color| {
+        if items.iter().any(|item| item.label == label) {
+            return;
+        }
+        let hex = color.to_hex().to_string();
+        items.push(CompletionItem {
+            kind: Some(CompletionItemKind::COLOR),
+            detail: Some(detail.unwrap_or_else(|| hex.clone())),
+            documentation: Some(Documentation::String(hex)),
+            filter_text: (!filter_prefix.is_empty()).then(|| format!("{filter_prefix}{label}")),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: edit_range,
+                new_text: label.clone(),
+            })),
+            label,
+            ..Default::default()
+        });
+    };
+
+    // The colors bound at the top level, which are visible everywhere after.
+    let root = LinkedNode::new(source.root());
+    for child in root.children() {
+        let Some(binding) = child.cast::<ast::LetBinding>() else {
+            continue;
+        };
+        let (ast::LetBindingKind::Normal(ast::Pattern::Normal(ast::Expr::Ident(name))), Some(init)) =
+            (binding.kind(), binding.init())
+        else {
+            continue;
+        };
+        if child.offset() >= cursor {
+            break;
+        }
+        if let Some(Value::Color(color)) = ctx.mini_eval(init) {
+            let hex = color.to_hex();
+            push(
+                name.get().to_string(),
+                Some(format!("{hex} (defined)")),
+                color,
+            );
+        }
+    }
+
+    // The colors used in the document.
+    for info in get_color_exprs(ctx, source).unwrap_or_default() {
+        let Some(range) = ctx.to_typst_range(info.range, source) else {
+            continue;
+        };
+        let lsp_types::Color {
+            red,
+            green,
+            blue,
+            alpha,
+        } = info.color;
+        let color = Color::Rgb(Rgb::new(red, green, blue, alpha));
+        let hex = color.to_hex();
+        push(
+            source.text()[range].to_owned(),
+            Some(format!("{hex} (used)")),
+            color,
+        );
+    }
+
+    for (name, value) in ctx.world().library().global.scope().iter() {
+        if let Value::Color(color) = value {
+            push(name.to_string(), None, *color);
+        }
+    }
+
+    Some(items)
+}
+
+/// Get the range replaced by a color completed at the cursor, along with the
+/// text before the color to filter it by, if a color is expected there.
+fn color_context(
+    ctx: &AnalysisContext,
+    leaf: &LinkedNode,
+    cursor: usize,
+) -> Option<(Range<usize>, String)> {
+    // `color.` is replaced by the color.
+    if let Some(FieldTarget {
+        target,
+        range,
+        quoted: false,
+    }) = get_field_target(leaf)
+    {
+        let is_color = target
+            .cast::<ast::Ident>()
+            .is_some_and(|i| i.get() == "color");
+        // Text in markup is not a field access.
+        let is_text = target.parent_kind() == Some(SyntaxKind::Markup)
+            && target.prev_sibling_kind() != Some(SyntaxKind::Hash);
+        if !is_color || is_text {
+            return None;
+        }
+        return Some((target.offset()..range.end, "color.".into()));
+    }
+
+    // A color is typed or to be typed after the punctuation.
+    let range = match leaf.kind() {
+        SyntaxKind::Ident if leaf.range().end == cursor => leaf.range(),
+        SyntaxKind::Space | SyntaxKind::LeftParen | SyntaxKind::Comma | SyntaxKind::Colon => {
+            cursor..cursor
+        }
+        _ => return None,
+    };
+    let (callee, arg) = active_arg(leaf, cursor)?;
+    if let (Some(ast::Expr::Ident(callee)), ActiveArg::Positional(0)) = (callee.cast(), &arg) {
+        if callee.get() == "rgb" {
+            return Some((range, String::new()));
+        }
+    }
+
+    let func =
+        analyze_expr(ctx.world(), &callee)
+            .into_iter()
+            .find_map(|(value, _)| match value {
+                Value::Func(func) => Some(func),
+                _ => None,
+            })?;
+    let params = func.params()?;
+    let param = match &arg {
+        ActiveArg::Named(name) => params.iter().find(|param| param.name == name.as_str()),
+        ActiveArg::Positional(index) => params.iter().filter(|param| param.positional).nth(*index),
+        ActiveArg::AfterSpread => None,
+    }?;
+    accepts_color(&param.input).then_some((range, String::new()))
+}
+
+/// Whether the cast info accepts colors.
+fn accepts_color(info: &CastInfo) -> bool {
+    match info {
+        CastInfo::Type(ty) => *ty == Type::of::<Color>(),
+        CastInfo::Union(infos) => infos.iter().any(accepts_color),
+        _ => false,
+    }
+}
+
+/// Complete the names exported by packages which are not in scope, inserting
+/// the imports of them as well on accepting.
+fn auto_import_completions(
+    ctx: &mut AnalysisContext,
+    source: &Source,
+    prefix: &str,
+    in_scope: &[Completion],
+    range: LspRange,
+) -> Vec<CompletionItem> {
+    if prefix.is_empty() {
+        return vec![];
+    }
+
+    let exports = package_exports(ctx, |name| {
+        matches_prefix(prefix, name) && !in_scope.iter().any(|c| c.label == name)
+    });
+    let items = exports.into_iter().filter_map(|(spec, name)| {
+        let (import_range, import_text) = import_edit(source, &spec, &name)?;
+        Some(CompletionItem {
+            label: name.to_string(),
+            kind: Some(CompletionItemKind::MODULE),
+            detail: Some(format!("import from {spec}")),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: name.to_string(),
+            })),
+            additional_text_edits: Some(vec![TextEdit {
+                range: ctx.to_lsp_range(import_range, source),
+                new_text: import_text,
+            }]),
+            ..Default::default()
+        })
+    });
+    items.collect()
+}
+
 fn is_arg_like_context(mut matching: &LinkedNode) -> bool {
     while let Some(parent) = matching.parent() {
         use SyntaxKind::*;
@@ -250,7 +865,8 @@ mod tests {
     use std::collections::HashSet;
 
     use insta::with_settings;
-    use lsp_types::CompletionItem;
+    use lsp_types::{CompletionItem, Documentation};
+    use typst::foundations::Dict;
 
     use super::*;
     use crate::{syntax::find_module_level_docs, tests::*};
@@ -336,4 +952,544 @@ mod tests {
             })
         });
     }
+
+    #[test]
+    fn test_postfix_methods() {
+        // The body of the function is not evaluated, so the methods are only
+        // known from the inferred type.
+        let content = "#let f() = {\n  let arr = (1, 2, 3)\n  arr.\n}";
+        run_with_ctx(content, |ctx, path| {
+            let items = complete_at(ctx, &path, content.find("arr.").unwrap() + 4, None);
+
+            let new_text = |label: &str| {
+                let item = items.iter().find(|item| item.label == label);
+                let item = item.unwrap_or_else(|| panic!("{label} is not completed"));
+                match &item.text_edit {
+                    Some(CompletionTextEdit::Edit(edit)) => edit.new_text.clone(),
+                    edit => panic!("unexpected edit {edit:?}"),
+                }
+            };
+            assert_eq!(new_text("len"), "len()");
+            assert_eq!(new_text("at"), "at(${1})");
+        });
+    }
+
+    #[test]
+    fn test_postfix_figure() {
+        let fig = |content: &str| {
+            run_with_ctx(content, |ctx, path| {
+                let items = complete_at(ctx, &path, content.len(), None);
+                let item = items.into_iter().find(|item| item.label == "fig").unwrap();
+                let Some(CompletionTextEdit::Edit(edit)) = item.text_edit else {
+                    panic!("no edit for {:?}", item.label);
+                };
+                edit
+            })
+        };
+
+        let edit = fig("#\"cat.png\".fi");
+        assert_eq!(edit.new_text, "figure(image(\"cat.png\"), caption: [${1}])");
+        // The path is replaced along with the dot.
+        assert_eq!(edit.range.start, LspPosition::new(0, 1));
+
+        // The path is inserted literally into the snippet.
+        let edit = fig(r#"#"$1}\\.png".fi"#);
+        assert_eq!(
+            edit.new_text,
+            r#"figure(image("\$1\}\\\\.png"), caption: [${1}])"#
+        );
+    }
+
+    #[test]
+    fn test_color_values() {
+        let content = "#let accent = rgb(\"#239dad\")\n#rect(fill: )\n#rgb()\n#rect(width: )";
+        run_with_ctx(content, |ctx, path| {
+            let mut complete = |after: &str| {
+                complete_at(ctx, &path, content.find(after).unwrap() + after.len(), None)
+            };
+            let colors = |items: &[CompletionItem]| {
+                let colors = items
+                    .iter()
+                    .filter(|item| item.kind == Some(CompletionItemKind::COLOR));
+                colors
+                    .map(|item| (item.label.clone(), item.documentation.clone()))
+                    .collect_vec()
+            };
+            let hex = |hex: &str| Some(Documentation::String(hex.into()));
+
+            for after in ["fill: ", "#rgb("] {
+                let colors = colors(&complete(after));
+                assert!(
+                    colors.contains(&("red".into(), hex("#ff4136"))),
+                    "{colors:?}"
+                );
+                assert!(
+                    colors.contains(&("accent".into(), hex("#239dad"))),
+                    "{colors:?}"
+                );
+                assert!(
+                    colors.contains(&("rgb(\"#239dad\")".into(), hex("#239dad"))),
+                    "{colors:?}"
+                );
+            }
+            assert_eq!(colors(&complete("width: ")), vec![]);
+        });
+    }
+
+    #[test]
+    fn test_show_rule_recipes() {
+        let content = "#show heading: \n= Title";
+        run_with_ctx(content, |ctx, path| {
+            let items = complete_at(ctx, &path, "#show heading: ".len(), None);
+            let snippets = items
+                .iter()
+                .filter_map(|item| match &item.text_edit {
+                    Some(CompletionTextEdit::Edit(edit)) => Some(edit.new_text.as_str()),
+                    _ => None,
+                })
+                .collect_vec();
+
+            assert!(
+                snippets.iter().any(|s| s.starts_with("it => ")),
+                "{snippets:?}"
+            );
+            assert!(snippets.contains(&"set text(${1})"), "{snippets:?}");
+            assert!(snippets.contains(&"none"), "{snippets:?}");
+        });
+    }
+
+    #[test]
+    fn test_bib_citation() {
+        let content = r#"// path: /refs.bib
+@article{netwok,
+    title={At-scale impact of the {Net Wok}},
+    author={Astley, Rasmus and Morris, Laura},
+    year={2020},
+}
+-----
+// path: /works.yml
+distress:
+  type: book
+  title: Distress
+  author: Barnes, Jane
+  date: 2019
+-----
+// path: /main.typ
+#bibliography(("refs.bib", "works.yml"))
+@netwok"#;
+        run_with_ctx(content, |ctx, path| {
+            let doc = typst::compile(ctx.world(), &mut Default::default()).ok();
+            let doc = doc.map(|doc| VersionedDocument {
+                version: 0,
+                document: Arc::new(doc),
+            });
+
+            let source = ctx.source_by_path(&path).unwrap();
+            let items = complete_at(ctx, &path, source.text().find('@').unwrap() + 1, doc);
+
+            let detail = |key: &str| {
+                let item = items.iter().find(|item| item.label == key);
+                let item = item.unwrap_or_else(|| panic!("{key} is not completed"));
+                item.label_details
+                    .as_ref()
+                    .and_then(|d| d.description.clone())
+            };
+            assert_eq!(detail("netwok").as_deref(), Some("Astley et al. (2020)"));
+            assert_eq!(detail("distress").as_deref(), Some("Barnes (2019)"));
+        });
+    }
+
+    #[test]
+    fn test_raw_lang() {
+        let content = "```ru\nfn main() {}\n```\n#{ ```py\n``` }\n```";
+        run_with_ctx(content, |ctx, path| {
+            let mut labels = |cursor: usize| {
+                let items = complete_at(ctx, &path, cursor, None);
+                items.into_iter().map(|item| item.label).collect_vec()
+            };
+
+            let text = content;
+            for cursor in [
+                text.find("ru").unwrap() + 2,
+                text.find("py").unwrap() + 2,
+                text.len(),
+            ] {
+                let labels = labels(cursor);
+                for tag in ["rust", "rs", "python", "py", "typst", "typ"] {
+                    assert!(labels.iter().any(|l| l == tag), "{tag} at {cursor}");
+                }
+            }
+            // Not in the body of a raw block.
+            let labels = labels(text.find("main").unwrap());
+            assert!(!labels.iter().any(|l| l == "rust"));
+        });
+    }
+
+    #[test]
+    fn test_paper_sizes() {
+        let content = "#set page(paper: )\n#set page(paper: \"";
+        run_with_ctx(content, |ctx, path| {
+            for cursor in [content.find(": )").unwrap() + 2, content.len()] {
+                let items = complete_at(ctx, &path, cursor, None);
+                let detail = |name: &str| {
+                    let item = items
+                        .iter()
+                        .find(|item| item.label.trim_matches('"') == name);
+                    item.and_then(|item| item.detail.clone())
+                };
+                assert_eq!(detail("a4").as_deref(), Some("210mm × 297mm"));
+                assert_eq!(detail("us-letter").as_deref(), Some("215.9mm × 279.4mm"));
+            }
+        });
+    }
+
+    #[test]
+    fn test_math_symbols() {
+        run_with_ctx_and_fonts("$arrow$", |ctx, path| {
+            let items = complete_at(ctx, &path, "$arrow".len(), None);
+            let preview = |name: &str| {
+                let item = items.iter().find(|item| item.label == name)?;
+                match &item.documentation {
+                    Some(Documentation::MarkupContent(docs)) => Some(docs.value.clone()),
+                    _ => None,
+                }
+            };
+            let arrows = (items.iter())
+                .filter(|item| item.label.starts_with("arrow."))
+                .count();
+            assert!(arrows > 1, "arrows: {arrows}");
+            for name in ["arrow.r", "arrow.l", "arrow.l.long"] {
+                let preview = preview(name).unwrap_or_else(|| panic!("no preview of {name}"));
+                assert!(preview.contains("data:image/svg+xml"), "{preview}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_at_trigger() {
+        let content = r#"// path: /refs.typ
+See @
+#let mail = "me@x"
+#import "@"
+#import "@
+-----
+// path: /main.typ
+= Intro <intro>"#;
+        run_with_ctx(content, |ctx, path| {
+            let doc = typst::compile(ctx.world(), &mut Default::default()).ok();
+            let doc = doc.map(|doc| VersionedDocument {
+                version: 0,
+                document: Arc::new(doc),
+            });
+
+            let path = path.with_file_name("refs.typ");
+            let source = ctx.source_by_path(&path).unwrap();
+            let mut labels = |cursor: usize| {
+                let items = complete_at(ctx, &path, cursor, doc.clone());
+                items.into_iter().map(|item| item.label).collect_vec()
+            };
+
+            let text = source.text();
+            assert_eq!(labels(text.find('@').unwrap() + 1), ["intro"]);
+            assert_eq!(labels(text.find("me@").unwrap() + 3), Vec::<String>::new());
+            let namespaces = ["@preview/"];
+            assert_eq!(labels(text.find("\"@\"").unwrap() + 2), namespaces);
+            assert_eq!(labels(text.len()), namespaces);
+        });
+    }
+
+    #[test]
+    fn test_equation_label_preview() {
+        let content = r#"// path: /refs.typ
+See #ref(<
+-----
+// path: /main.typ
+$ E = m c^2 $ <energy>
+Mass is <mass>"#;
+        let docs = |enabled| {
+            run_with_ctx_and_fonts(content, |ctx, path| {
+                ctx.analysis.hover_math_preview = enabled;
+                let doc = typst::compile(ctx.world(), &mut Default::default()).ok();
+                let doc = doc.map(|doc| VersionedDocument {
+                    version: 0,
+                    document: Arc::new(doc),
+                });
+
+                let path = path.with_file_name("refs.typ");
+                let source = ctx.source_by_path(&path).unwrap();
+                let items = complete_at(ctx, &path, source.text().len(), doc);
+
+                // The previews are only rendered once the items are resolved.
+                let mut docs = |key: &str| {
+                    let item = items.iter().find(|item| item.label == key);
+                    let item = item.unwrap_or_else(|| panic!("{key} is not completed"));
+                    assert_eq!(item.documentation, None);
+                    let request = CompletionResolveRequest::new(item.clone())?;
+                    match request.request(ctx)?.documentation {
+                        Some(Documentation::MarkupContent(docs)) => Some(docs.value),
+                        _ => None,
+                    }
+                };
+                (docs("energy"), docs("mass"))
+            })
+        };
+
+        let (energy, mass) = docs(true);
+        let energy = energy.expect("equation label has no preview");
+        assert!(
+            energy.starts_with("![math](data:image/svg+xml;base64,"),
+            "{energy}"
+        );
+        assert_eq!(mass, None);
+
+        assert_eq!(docs(false), (None, None));
+    }
+
+    #[test]
+    fn test_data_fields() {
+        let content = r#"// path: /data.csv
+name,age,city
+Alice,30,Paris
+-----
+// path: /main.typ
+#let data = csv("data.csv", row-type: dictionary)
+#for row in data { row.at("") + row. }"#;
+        run_with_ctx(content, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let mut complete = |cursor: usize| {
+                complete_at(ctx, &path, cursor, None)
+                    .into_iter()
+                    .map(|item| match item.text_edit {
+                        Some(CompletionTextEdit::Edit(edit)) => edit.new_text,
+                        _ => panic!("no text edit for {}", item.label),
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            let in_str = source.text().find(r#""")"#).unwrap() + 1;
+            assert_eq!(complete(in_str), [r#""name""#, r#""age""#, r#""city""#]);
+            let after_dot = source.text().rfind('.').unwrap() + 1;
+            assert_eq!(complete(after_dot), ["name", "age", "city"]);
+        });
+    }
+
+    #[test]
+    fn test_sys_inputs() {
+        let content = r#"#{ sys.inputs. + sys.inputs.at("") }"#;
+        let inputs = Dict::from_iter([
+            ("author".into(), Value::Str("Alice".into())),
+            ("version".into(), Value::Str("1.0".into())),
+        ]);
+        run_with_ctx_and_inputs(content, inputs, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let mut complete = |cursor: usize| {
+                (complete_at(ctx, &path, cursor, None).into_iter())
+                    .map(|item| (item.label, item.detail.unwrap_or_default()))
+                    .collect::<Vec<_>>()
+            };
+
+            let expected = [
+                ("author".to_owned(), r#""Alice""#.to_owned()),
+                ("version".to_owned(), r#""1.0""#.to_owned()),
+            ];
+            let after_dot = source.text().find(". ").unwrap() + 1;
+            assert_eq!(complete(after_dot), expected);
+            let in_str = source.text().find(r#""")"#).unwrap() + 1;
+            assert_eq!(complete(in_str), expected);
+        });
+    }
+
+    #[test]
+    fn test_set_rule_completion() {
+        let complete = |content: &str, cursor: usize| {
+            run_with_ctx(content, |ctx, path| {
+                let mut items = complete_at(ctx, &path, cursor, None);
+                items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+                items
+            })
+        };
+
+        let items = complete("#set \nHello", "#set ".len());
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels[..3], ["par", "text", "heading"]);
+        assert!(!labels.contains(&"red"), "{labels:?}");
+
+        let items = complete("#set text(", "#set text(".len());
+        let params = |label: &str| {
+            let pos = items.iter().position(|item| item.label == label);
+            pos.unwrap_or_else(|| panic!("{label} is not completed"))
+        };
+        // The parameters come before the functions in scope.
+        let first_func = items
+            .iter()
+            .position(|item| item.kind == Some(CompletionItemKind::FUNCTION))
+            .unwrap_or(items.len());
+        assert!(params("font") < first_func);
+        assert!(params("size") < first_func);
+    }
+
+    #[test]
+    fn test_named_param_defaults() {
+        let complete = |content: &str| {
+            run_with_ctx(content, |ctx, path| {
+                complete_at(ctx, &path, content.len(), None)
+            })
+        };
+        let param = |items: &[CompletionItem], label: &str| {
+            let item = items.iter().find(|item| {
+                item.label == label && item.kind == Some(CompletionItemKind::VARIABLE)
+            });
+            item.map(|item| {
+                let Some(CompletionTextEdit::Edit(edit)) = &item.text_edit else {
+                    panic!("unexpected edit {:?}", item.text_edit);
+                };
+                (item.detail.clone().unwrap(), edit.new_text.clone())
+            })
+        };
+
+        let items = complete("#text(");
+        assert_eq!(
+            param(&items, "weight"),
+            Some((
+                "default: \"regular\"".to_owned(),
+                "weight: ${1:\"regular\"}".to_owned()
+            ))
+        );
+        assert_eq!(
+            param(&items, "size"),
+            Some(("default: 11pt".to_owned(), "size: ${1:11pt}".to_owned()))
+        );
+
+        let items = complete("#text(weight: \"bold\", ");
+        assert_eq!(param(&items, "weight"), None);
+        assert!(param(&items, "size").is_some());
+    }
+
+    #[test]
+    fn test_user_snippets() {
+        let snippets: Vec<UserSnippet> = serde_json::from_str(
+            r##"[
+                {
+                    "prefix": "thm",
+                    "body": "#theorem[${1:statement}]",
+                    "description": "A theorem",
+                    "scope": "markup"
+                },
+                {
+                    "prefix": "fig",
+                    "body": "figure(${1:body}, caption: [${2:caption}])",
+                    "scope": "code"
+                }
+            ]"##,
+        )
+        .unwrap();
+        let complete = |content: &str| {
+            run_with_ctx(content, |ctx, path| {
+                ctx.analysis.user_snippets = snippets.clone();
+                (complete_at(ctx, &path, content.len(), None).into_iter())
+                    .filter(|item| ["thm", "fig"].contains(&item.label.as_str()))
+                    .map(|item| {
+                        let Some(CompletionTextEdit::Edit(edit)) = item.text_edit else {
+                            panic!("unexpected edit {:?}", item.text_edit);
+                        };
+                        (item.label, item.detail.unwrap_or_default(), edit.new_text)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let theorem = (
+            "thm".to_owned(),
+            "A theorem".to_owned(),
+            "#theorem[${1:statement}]".to_owned(),
+        );
+        assert_eq!(complete("Some th"), [theorem]);
+        assert_eq!(complete("Some xyz"), []);
+
+        let figure = (
+            "fig".to_owned(),
+            String::new(),
+            "figure(${1:body}, caption: [${2:caption}])".to_owned(),
+        );
+        assert_eq!(complete("#fi"), [figure]);
+    }
+
+    #[test]
+    fn test_auto_import() {
+        run_with_ctx("#canv", |ctx, path| {
+            let items = complete_at(ctx, &path, "#canv".len(), None);
+            let item = items.iter().find(|item| item.label == "canvas");
+            let item = item.expect("canvas is not completed");
+            assert_eq!(item.kind, Some(CompletionItemKind::MODULE));
+
+            let range =
+                |start, end| LspRange::new(LspPosition::new(0, start), LspPosition::new(0, end));
+            assert_eq!(
+                item.text_edit,
+                Some(CompletionTextEdit::Edit(TextEdit {
+                    range: range(1, 5),
+                    new_text: "canvas".to_owned(),
+                }))
+            );
+            assert_eq!(
+                item.additional_text_edits,
+                Some(vec![TextEdit {
+                    range: range(0, 0),
+                    new_text: "#import \"@preview/cetz:0.2.2\": canvas\n".to_owned(),
+                }])
+            );
+        });
+
+        // Existing imports of the package are extended instead.
+        let cetz: PackageSpec = "@preview/cetz:0.2.2".parse().unwrap();
+        let source = Source::detached("#import \"@preview/cetz:0.2.1\": draw\n#canv");
+        let edit = import_edit(&source, &cetz, "canvas");
+        assert_eq!(edit, Some((35..35, ", canvas".to_owned())));
+        let source = Source::detached("#import \"@preview/cetz:0.2.2\": *\n#canv");
+        assert_eq!(import_edit(&source, &cetz, "canvas"), None);
+
+        // The packages are looked up once for the completions in a row.
+        run_with_ctx("#canv", |ctx, _| {
+            let index = ctx.package_index();
+            assert!(Arc::ptr_eq(&index, &ctx.package_index()));
+        });
+    }
+
+    #[test]
+    fn test_path_completion() {
+        let content = r#"// path: /main.typ
+#import "ch/in"
+#include "ch"
+#import "ap"
+#include "/ch/in""#;
+        run_with_ctx(content, |ctx, path| {
+            let files = ["main.typ", "appendix.typ", "chapters/intro.typ"]
+                .into_iter()
+                .chain(["chapters/outro.typ", "chapters/fig.png"]);
+            ctx.test_completion_files(|| files.map(PathBuf::from).collect());
+            let source = ctx.source_by_path(&path).unwrap();
+            let mut complete = |line: usize| {
+                // Before the closing quote at the end of the line.
+                let lines = source.text().lines().take(line + 1);
+                let cursor = lines.map(|line| line.len() + 1).sum::<usize>() - 2;
+                (complete_at(ctx, &path, cursor, None).into_iter())
+                    .map(|item| (item.label, item.kind.unwrap()))
+                    .collect::<Vec<_>>()
+            };
+
+            let file = |label: &str| (label.to_owned(), CompletionItemKind::FILE);
+            let folder = |label: &str| (label.to_owned(), CompletionItemKind::FOLDER);
+            assert_eq!(complete(0), [file("chapters/intro.typ")]);
+            assert_eq!(
+                complete(1),
+                [
+                    file("chapters/intro.typ"),
+                    file("chapters/outro.typ"),
+                    folder("chapters/"),
+                ]
+            );
+            assert_eq!(complete(2).first(), Some(&file("appendix.typ")));
+            assert_eq!(complete(3), [file("/chapters/intro.typ")]);
+        });
+    }
 }
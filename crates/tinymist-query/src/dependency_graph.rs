@@ -0,0 +1,187 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{prelude::*, syntax::resolve_id_by_path, SemanticRequest};
+
+/// How a file depends on another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DependencyKind {
+    /// The file is imported by `#import`.
+    Import,
+    /// The file is included by `#include`.
+    Include,
+}
+
+/// An edge of a dependency graph.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyEdge {
+    /// The depending node.
+    pub from: String,
+    /// The node depended on.
+    pub to: String,
+    /// How the node is depended on.
+    pub kind: DependencyKind,
+}
+
+/// The import and include dependencies between the files of a document.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyGraph {
+    /// The paths of the files, and the specs of packages, e.g.
+    /// `@preview/example:0.1.0`, which are not traversed.
+    pub nodes: Vec<String>,
+    /// The dependencies between the nodes, which may form cycles.
+    pub edges: Vec<DependencyEdge>,
+}
+
+/// A request to get the dependency graph of an entry file.
+///
+/// The files reached from the entry, and the sources depended on by the last
+/// compilation, are scanned for `#import` and `#include` of string paths.
+#[derive(Debug, Clone)]
+pub struct DependencyGraphRequest {
+    /// The path of the entry file.
+    pub path: PathBuf,
+}
+
+impl SemanticRequest for DependencyGraphRequest {
+    type Response = DependencyGraph;
+
+    fn request(self, ctx: &mut AnalysisContext) -> Option<Self::Response> {
+        let source = ctx.source_by_path(&self.path).ok()?;
+
+        let mut stack = ctx.dependent_sources();
+        // Visit the entry first.
+        stack.reverse();
+        stack.push(source);
+
+        let mut graph = DependencyGraph::default();
+        let mut seen = HashSet::new();
+        let mut add_node = |graph: &mut DependencyGraph, node: &String| {
+            if seen.insert(node.clone()) {
+                graph.nodes.push(node.clone());
+            }
+        };
+
+        let mut visited = HashSet::new();
+        while let Some(source) = stack.pop() {
+            if !visited.insert(source.id()) {
+                continue;
+            }
+            let Some(from) = node_name(ctx, source.id()) else {
+                continue;
+            };
+            add_node(&mut graph, &from);
+
+            let mut deps = vec![];
+            find_dependencies(&LinkedNode::new(source.root()), &mut deps);
+            for (kind, path) in deps {
+                let (to, dep) = if path.starts_with('@') {
+                    let Ok(spec) = path.parse::<PackageSpec>() else {
+                        continue;
+                    };
+                    (spec.to_string(), None)
+                } else {
+                    let Some(id) = resolve_id_by_path(ctx.world(), source.id(), &path) else {
+                        continue;
+                    };
+                    let Some(to) = node_name(ctx, id) else {
+                        continue;
+                    };
+                    (to, ctx.world().source(id).ok())
+                };
+
+                add_node(&mut graph, &to);
+                let edge = DependencyEdge {
+                    from: from.clone(),
+                    to,
+                    kind,
+                };
+                if !graph.edges.contains(&edge) {
+                    graph.edges.push(edge);
+                }
+                stack.extend(dep);
+            }
+        }
+
+        Some(graph)
+    }
+}
+
+/// Get the name of a file in the graph, which is the spec of the package for
+/// files in packages.
+fn node_name(ctx: &AnalysisContext, id: TypstFileId) -> Option<String> {
+    if let Some(spec) = id.package() {
+        return Some(spec.to_string());
+    }
+    let path = ctx.path_for_id(id).ok()?;
+    Some(path.to_string_lossy().into_owned())
+}
+
+/// Find the string paths imported or included in the node.
+fn find_dependencies(node: &LinkedNode, deps: &mut Vec<(DependencyKind, EcoString)>) {
+    let dep = if let Some(import) = node.cast::<ast::ModuleImport>() {
+        Some((DependencyKind::Import, import.source()))
+    } else {
+        let include = node.cast::<ast::ModuleInclude>();
+        include.map(|include| (DependencyKind::Include, include.source()))
+    };
+    if let Some((kind, ast::Expr::Str(path))) = dep {
+        deps.push((kind, path.get()));
+    }
+
+    for child in node.children() {
+        find_dependencies(&child, deps);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn test_dependency_graph() {
+        let content = r#"// path: /a.typ
+#import "b.typ": *
+-----
+// path: /b.typ
+#import "a.typ": *
+= Chapter
+-----
+// path: /main.typ
+#import "@preview/example:0.1.0": *
+#import "a.typ"
+#include "b.typ""#;
+
+        run_with_ctx(content, |ctx, path| {
+            let graph = DependencyGraphRequest { path }.request(ctx).unwrap();
+
+            let mut nodes = graph.nodes.clone();
+            nodes.sort();
+            assert_eq!(
+                nodes,
+                ["/a.typ", "/b.typ", "/main.typ", "@preview/example:0.1.0"]
+            );
+
+            use DependencyKind::*;
+            let mut edges: Vec<_> = (graph.edges.iter())
+                .map(|e| (e.from.as_str(), e.to.as_str(), e.kind))
+                .collect();
+            edges.sort_by_key(|&(from, to, _)| (from, to));
+            assert_eq!(
+                edges,
+                [
+                    ("/a.typ", "/b.typ", Import),
+                    ("/b.typ", "/a.typ", Import),
+                    ("/main.typ", "/a.typ", Import),
+                    ("/main.typ", "/b.typ", Include),
+                    ("/main.typ", "@preview/example:0.1.0", Import),
+                ]
+            );
+        });
+    }
+}
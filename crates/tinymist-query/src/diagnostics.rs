@@ -1,3 +1,5 @@
+use lsp_types::NumberOrString;
+
 use crate::prelude::*;
 
 /// Stores diagnostics for files.
@@ -50,6 +52,7 @@ fn convert_diagnostic(
         severity: Some(lsp_severity),
         message: lsp_message,
         source: Some("typst".to_owned()),
+        code: diagnostic_code(typst_message).map(|code| NumberOrString::String(code.to_owned())),
         related_information: Some(tracepoints),
         ..Default::default()
     };
@@ -139,3 +142,117 @@ fn diagnostic_hints(typst_hints: &[EcoString]) -> Format<impl Iterator<Item = Ec
         .interleave(typst_hints.iter().cloned())
         .format("")
 }
+
+/// An extended explanation of a common diagnostic.
+struct DiagnosticHelp {
+    /// The code attached to matching diagnostics.
+    code: &'static str,
+    /// Whether a diagnostic message matches.
+    matches: fn(&str) -> bool,
+    title: &'static str,
+    explanation: &'static str,
+    cause: &'static str,
+    fix: &'static str,
+}
+
+static DIAGNOSTIC_HELPS: &[DiagnosticHelp] = &[
+    DiagnosticHelp {
+        code: "unknown-variable",
+        matches: |msg| msg.starts_with("unknown variable"),
+        title: "Unknown variable",
+        explanation: "The identifier is not defined in the current scope. Typst resolves \
+            identifiers lexically, so a binding is only visible after its `#let` and \
+            inside the block defining it.",
+        cause: "The name is misspelled, used before its definition, defined in another \
+            file without being imported, or, in markup, a hyphen or underscore was \
+            parsed as part of the identifier.",
+        fix: "Check the spelling, move the definition up, or import it with \
+            `#import \"file.typ\": name`. In markup, write `#(name)` to delimit the \
+            identifier.",
+    },
+    DiagnosticHelp {
+        code: "type-mismatch",
+        matches: |msg| msg.starts_with("expected ") && msg.contains(", found "),
+        title: "Type mismatch",
+        explanation: "A value of one type was given where another type is expected, e.g. \
+            a string where a length is required.",
+        cause: "An argument was passed with a wrong type, a unit is missing from a \
+            number, or content was given where a string is expected.",
+        fix: "Convert the value to the expected type, e.g. add a unit such as `1em`, \
+            use `str(value)` for strings, or check the parameter documentation on hover.",
+    },
+    DiagnosticHelp {
+        code: "missing-package",
+        matches: |msg| {
+            msg.starts_with("package not found") || msg.starts_with("failed to download package")
+        },
+        title: "Missing package",
+        explanation: "The imported package is neither in the local package directories \
+            nor available for download.",
+        cause: "The package name or version is misspelled, the version does not exist, \
+            the package is local but not installed, or the network is unavailable.",
+        fix: "Check the name and version on https://typst.app/universe, install local \
+            packages to the `@local` namespace, or check the network connection.",
+    },
+    DiagnosticHelp {
+        code: "unclosed-delimiter",
+        matches: |msg| msg.starts_with("unclosed "),
+        title: "Unclosed delimiter",
+        explanation: "A delimiter, such as a bracket, a string quote, raw backticks or a \
+            label, was opened but never closed.",
+        cause: "A closing delimiter is missing or was removed, often far from the \
+            reported position because the parser consumes the rest of the block.",
+        fix: "Add the missing closing delimiter. Code folding or bracket matching helps \
+            to find the opening one.",
+    },
+];
+
+/// Gets the code of a diagnostic with an extended explanation.
+pub fn diagnostic_code(message: &str) -> Option<&'static str> {
+    let help = DIAGNOSTIC_HELPS
+        .iter()
+        .find(|help| (help.matches)(message))?;
+    Some(help.code)
+}
+
+/// Explains a diagnostic in markdown, given its code or message.
+///
+/// Unknown diagnostics get a generic message instead of an error.
+pub fn explain_diagnostic(code: Option<&str>, message: Option<&str>) -> String {
+    let help = DIAGNOSTIC_HELPS.iter().find(|help| {
+        code.is_some_and(|code| code == help.code) || message.is_some_and(help.matches)
+    });
+
+    match help {
+        Some(help) => format!(
+            "## {}\n\n{}\n\n**Likely cause**: {}\n\n**Fix**: {}\n",
+            help.title, help.explanation, help.cause, help.fix
+        ),
+        None => "No extended help available for this diagnostic.\n".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        assert_eq!(
+            diagnostic_code("unknown variable: foo"),
+            Some("unknown-variable")
+        );
+        assert_eq!(
+            diagnostic_code("expected length, found string"),
+            Some("type-mismatch")
+        );
+
+        let by_message = explain_diagnostic(None, Some("unclosed delimiter"));
+        assert!(by_message.starts_with("## Unclosed delimiter"));
+        let by_code = explain_diagnostic(Some("unclosed-delimiter"), None);
+        assert_eq!(by_message, by_code);
+
+        let unknown = explain_diagnostic(None, Some("panicked with: 1"));
+        assert!(unknown.starts_with("No extended help"));
+    }
+}
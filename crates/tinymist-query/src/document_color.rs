@@ -30,4 +30,42 @@ mod tests {
             assert_snapshot!(JsonRepr::new_redacted(result, &REDACT_LOC));
         });
     }
+
+    #[test]
+    fn test_named_and_bound() {
+        let content = "#let brand = rgb(\"#336699\")\n#let red-ish = red\n#text(fill: navy)[A] #brand #red-ish #let x = 1\n\
+                       #{ let navy = 1; navy }\n#let f(red) = red\n#for blue in () { blue }\n#blue";
+        run_with_ctx(content, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let request = DocumentColorRequest { path: path.clone() };
+            let colors = request.request(ctx).unwrap();
+            // Finds the color of `text`, which is at the end of `pattern`.
+            let found = |pattern: &str, text: &str| {
+                let offset = source.text().find(pattern).unwrap() + pattern.len() - text.len();
+                let range = ctx.to_lsp_range(offset..offset + text.len(), &source);
+                let info = colors.iter().find(|c| c.range == range)?;
+                Some([info.color.red, info.color.green, info.color.blue])
+            };
+
+            assert_eq!(
+                found("fill: navy", "navy").unwrap(),
+                [0.0, 0.121569, 0.247059]
+            );
+            assert_eq!(found("= red", "red").unwrap(), [1.0, 0.254902, 0.211765]);
+            assert_eq!(found("\n#blue", "blue").unwrap(), [0.0, 0.454902, 0.85098]);
+            assert!(found("rgb(\"#336699\")", "rgb(\"#336699\")").is_some());
+            // The uses of the variables bound to colors are colors as well.
+            assert_eq!(found("#brand", "brand").unwrap(), [0.2, 0.4, 0.6]);
+            assert_eq!(
+                found("#red-ish", "red-ish").unwrap(),
+                [1.0, 0.254902, 0.211765]
+            );
+            assert_eq!(found("let red-ish", "red-ish"), None);
+            // The predefined colors are shadowed by the variables in scope.
+            assert_eq!(found("1; navy", "navy"), None);
+            assert_eq!(found(") = red", "red"), None);
+            assert_eq!(found("{ blue", "blue"), None);
+            assert_eq!(colors.len(), 6);
+        });
+    }
 }
@@ -0,0 +1,148 @@
+use std::ops::Range;
+
+use lsp_types::DocumentLink;
+
+use crate::{prelude::*, syntax::resolve_id_by_path, SemanticRequest};
+
+/// The [`textDocument/documentLink`] request is sent from the client to the
+/// server to request the location of links in a document.
+///
+/// Tinymist links the paths of imports and includes to the files, and the URLs
+/// of `link` calls and markup links to the web. A path link whose file cannot
+/// be found has no target.
+///
+/// [`textDocument/documentLink`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_documentLink
+#[derive(Debug, Clone)]
+pub struct DocumentLinkRequest {
+    /// The path of the document to get links for.
+    pub path: PathBuf,
+}
+
+impl SemanticRequest for DocumentLinkRequest {
+    type Response = Vec<DocumentLink>;
+
+    fn request(self, ctx: &mut AnalysisContext) -> Option<Self::Response> {
+        let source = ctx.source_by_path(&self.path).ok()?;
+
+        let mut links = vec![];
+        find_links(ctx, &source, LinkedNode::new(source.root()), &mut links);
+        Some(links)
+    }
+}
+
+fn find_links(
+    ctx: &mut AnalysisContext,
+    source: &Source,
+    node: LinkedNode,
+    links: &mut Vec<DocumentLink>,
+) {
+    let path = if let Some(import) = node.cast::<ast::ModuleImport>() {
+        Some(import.source())
+    } else {
+        node.cast::<ast::ModuleInclude>().map(|i| i.source())
+    };
+    if let Some(ast::Expr::Str(path)) = path {
+        if let Some(path_node) = node.find(path.span()) {
+            links.push(path_link(ctx, source, &path_node, &path.get()));
+        }
+    }
+
+    if let Some(call) = node.cast::<ast::FuncCall>() {
+        let is_link =
+            matches!(call.callee(), ast::Expr::Ident(callee) if callee.as_str() == "link");
+        let dest = call.args().items().find_map(|arg| match arg {
+            ast::Arg::Pos(ast::Expr::Str(dest)) => Some(dest),
+            _ => None,
+        });
+        if let Some(dest) = dest.filter(|_| is_link) {
+            let url = Url::parse(&dest.get()).ok();
+            if let (Some(url), Some(dest_node)) = (url, node.find(dest.span())) {
+                links.push(DocumentLink {
+                    range: ctx.to_lsp_range(str_content_range(&dest_node), source),
+                    target: Some(url),
+                    tooltip: None,
+                    data: None,
+                });
+            }
+        }
+    }
+
+    if node.kind() == SyntaxKind::Link {
+        if let Ok(url) = Url::parse(node.text()) {
+            links.push(DocumentLink {
+                range: ctx.to_lsp_range(node.range(), source),
+                target: Some(url),
+                tooltip: None,
+                data: None,
+            });
+        }
+    }
+
+    for child in node.children() {
+        find_links(ctx, source, child, links);
+    }
+}
+
+/// Link the path of an import or include to the file, if it exists.
+fn path_link(
+    ctx: &mut AnalysisContext,
+    source: &Source,
+    node: &LinkedNode,
+    path: &str,
+) -> DocumentLink {
+    let id = resolve_id_by_path(ctx.world(), source.id(), path);
+    let id = id.filter(|id| ctx.world().file(*id).is_ok());
+    let target = id.and_then(|id| path_to_url(&ctx.path_for_id(id).ok()?).ok());
+
+    DocumentLink {
+        range: ctx.to_lsp_range(str_content_range(node), source),
+        tooltip: target.is_none().then(|| format!("cannot find {path}")),
+        target,
+        data: None,
+    }
+}
+
+/// Get the range of a string literal without the quotes.
+fn str_content_range(node: &LinkedNode) -> Range<usize> {
+    let range = node.range();
+    (range.start + 1)..(range.end - 1).max(range.start + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn test_document_links() {
+        let content = r#"// path: /chapter.typ
+= Chapter
+-----
+// path: /main.typ
+#import "chapter.typ"
+#include "missing.typ"
+#link("https://typst.app")[Typst]"#;
+
+        run_with_ctx(content, |ctx, path| {
+            let links = DocumentLinkRequest { path }.request(ctx).unwrap();
+            let found: Vec<_> = links
+                .iter()
+                .map(|link| (link.range, link.target.as_ref().map(Url::as_str)))
+                .collect();
+
+            let range = |line, start, end| {
+                LspRange::new(LspPosition::new(line, start), LspPosition::new(line, end))
+            };
+            let chapter = path_to_url(Path::new("/chapter.typ")).unwrap();
+            assert_eq!(
+                found,
+                vec![
+                    (range(0, 9, 20), Some(chapter.as_str())),
+                    (range(1, 10, 21), None),
+                    (range(2, 7, 24), Some("https://typst.app/")),
+                ]
+            );
+            assert!(links[1].tooltip.is_some());
+        });
+    }
+}
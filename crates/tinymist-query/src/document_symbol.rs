@@ -1,6 +1,8 @@
 use crate::{
     prelude::*,
-    syntax::{get_lexical_hierarchy, LexicalHierarchy, LexicalScopeKind},
+    syntax::{
+        get_lexical_hierarchy, LexicalHierarchy, LexicalKind, LexicalScopeKind, LexicalVarKind,
+    },
     SyntaxRequest,
 };
 
@@ -47,27 +49,55 @@ fn filter_document_symbols(
         .iter()
         .map(|e| {
             let rng = typst_to_lsp::range(e.info.range.clone(), source, position_encoding);
+            let children = e
+                .children
+                .as_ref()
+                .map(|ch| filter_document_symbols(ch, source, position_encoding));
+            // The range of a symbol covers its children, while its name is selected.
+            let range = children
+                .iter()
+                .flatten()
+                .fold(rng, |range, child| LspRange {
+                    start: range.start.min(child.range.start),
+                    end: range.end.max(child.range.end),
+                });
+
+            let detail = match e.info.kind {
+                LexicalKind::Var(LexicalVarKind::Function) => {
+                    function_signature(source, e.info.range.start)
+                }
+                _ => None,
+            };
 
             DocumentSymbol {
                 name: e.info.name.clone(),
-                detail: None,
+                detail,
                 kind: e.info.kind.clone().try_into().unwrap(),
                 tags: None,
                 deprecated: None,
-                range: rng,
+                range,
                 selection_range: rng,
                 //             .raw_range,
-                children: e
-                    .children
-                    .as_ref()
-                    .map(|ch| filter_document_symbols(ch, source, position_encoding)),
+                children,
             }
         })
         .collect()
 }
 
+/// Get the parameters of the function whose name starts at the offset, e.g.
+/// `(body, size: 1em)`, with whitespace collapsed.
+fn function_signature(source: &Source, offset: usize) -> Option<String> {
+    let name = LinkedNode::new(source.root()).leaf_at(offset + 1)?;
+    let closure = name.parent()?.cast::<ast::Closure>()?;
+    let params = name.parent()?.find(closure.params().span())?;
+    let params = source.text()[params.range()].split_whitespace();
+    Some(params.collect::<Vec<_>>().join(" "))
+}
+
 #[cfg(test)]
 mod tests {
+    use lsp_types::SymbolKind;
+
     use super::*;
     use crate::tests::*;
 
@@ -82,4 +112,43 @@ mod tests {
             assert_snapshot!(JsonRepr::new_redacted(result.unwrap(), &REDACT_LOC));
         });
     }
+
+    #[test]
+    fn test_function_signature() {
+        let content =
+            "#let frame(body, inset: 2pt) = {\n  let padded = pad(inset, body)\n  box(padded)\n}";
+        run_with_ctx(content, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let request = DocumentSymbolRequest { path };
+            let Some(DocumentSymbolResponse::Nested(symbols)) =
+                request.request(&source, PositionEncoding::Utf16)
+            else {
+                panic!("no nested symbols");
+            };
+
+            let [frame] = &symbols[..] else {
+                panic!("unexpected symbols {symbols:?}");
+            };
+            assert_eq!(frame.name, "frame");
+            assert_eq!(frame.kind, SymbolKind::FUNCTION);
+            assert_eq!(frame.detail.as_deref(), Some("(body, inset: 2pt)"));
+
+            let children = frame.children.as_deref().unwrap_or_default();
+            let names: Vec<_> = children.iter().map(|c| (c.name.as_str(), c.kind)).collect();
+            assert_eq!(names, [("padded", SymbolKind::VARIABLE)]);
+
+            // The function is selected by its name and contains its local bindings.
+            assert_eq!(
+                frame.selection_range,
+                LspRange::new(LspPosition::new(0, 5), LspPosition::new(0, 10))
+            );
+            assert_eq!(
+                frame.range,
+                LspRange::new(LspPosition::new(0, 5), LspPosition::new(1, 12))
+            );
+            let padded = &children[0];
+            assert!(frame.range.start <= padded.range.start);
+            assert!(padded.range.end <= frame.range.end);
+        });
+    }
 }
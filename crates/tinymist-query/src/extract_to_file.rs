@@ -0,0 +1,247 @@
+use std::ops::Range;
+
+use ecow::eco_format;
+use lsp_types::{
+    CreateFile, CreateFileOptions, DocumentChangeOperation, DocumentChanges, OneOf,
+    OptionalVersionedTextDocumentIdentifier, ResourceOp, TextDocumentEdit, TextEdit,
+};
+use reflexo::path::{unix_slash, PathClean};
+
+use crate::{prelude::*, SemanticRequest};
+
+/// A request to move the selected part of a document into a new file, which is
+/// included in place of the selection.
+///
+/// The selection must consist of complete top-level nodes. The new file starts
+/// with the imports of the document before the selection, whose relative paths
+/// are rewritten for the location of the new file. See
+/// [`ExtractToFileRequest::extract`] for the reason of rejection.
+#[derive(Debug, Clone)]
+pub struct ExtractToFileRequest {
+    /// The path of the document to extract from.
+    pub path: PathBuf,
+    /// The range of the selection to extract.
+    pub range: LspRange,
+    /// The path of the new file, which is relative to the document unless it
+    /// is absolute.
+    pub new_file: PathBuf,
+}
+
+impl SemanticRequest for ExtractToFileRequest {
+    type Response = WorkspaceEdit;
+
+    fn request(self, ctx: &mut AnalysisContext) -> Option<Self::Response> {
+        self.extract(ctx).ok()
+    }
+}
+
+impl ExtractToFileRequest {
+    /// Get the edit creating the new file and replacing the selection with an
+    /// `#include`, or the reason why the selection cannot be extracted.
+    pub fn extract(self, ctx: &mut AnalysisContext) -> Result<WorkspaceEdit, EcoString> {
+        let source = (ctx.source_by_path(&self.path))
+            .map_err(|err| eco_format!("cannot read the document: {err}"))?;
+        let range = (ctx.to_typst_range(self.range, &source))
+            .ok_or("the selection is out of the document")?;
+        let range = top_level_range(&source, range)?;
+
+        let dir = self
+            .path
+            .parent()
+            .ok_or("the document has no parent directory")?;
+        let new_file = dir.join(&self.new_file).clean();
+        if !new_file.starts_with(&ctx.analysis.root) {
+            return Err("the new file must be in the root of the workspace".into());
+        }
+        if ctx.source_by_path(&new_file).is_ok() {
+            return Err(eco_format!("{} exists already", new_file.display()));
+        }
+        let new_dir = new_file
+            .parent()
+            .ok_or("the new file has no parent directory")?;
+        let include = relative_path(dir, &new_file).ok_or("cannot include the new file")?;
+
+        let preamble = inherited_preamble(&source, range.start, dir, new_dir);
+        let content = format!("{preamble}{}\n", &source.text()[range.clone()]);
+
+        let new_url = path_to_url(&new_file).map_err(|err| eco_format!("{err}"))?;
+        let url = path_to_url(&self.path).map_err(|err| eco_format!("{err}"))?;
+        let edit = |url: Url, edit: TextEdit| {
+            DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: url,
+                    version: None,
+                },
+                edits: vec![OneOf::Left(edit)],
+            })
+        };
+        let operations = vec![
+            DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                uri: new_url.clone(),
+                options: Some(CreateFileOptions {
+                    overwrite: Some(false),
+                    ignore_if_exists: Some(false),
+                }),
+                annotation_id: None,
+            })),
+            edit(
+                new_url,
+                TextEdit {
+                    range: LspRange::default(),
+                    new_text: content,
+                },
+            ),
+            edit(
+                url,
+                TextEdit {
+                    range: ctx.to_lsp_range(range, &source),
+                    new_text: format!("#include \"{include}\""),
+                },
+            ),
+        ];
+
+        Ok(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(operations)),
+            ..Default::default()
+        })
+    }
+}
+
+/// Get the selection without surrounding whitespace, checking that it does not
+/// split any top-level node.
+fn top_level_range(source: &Source, range: Range<usize>) -> Result<Range<usize>, EcoString> {
+    let text = &source.text()[range.clone()];
+    let start = range.start + (text.len() - text.trim_start().len());
+    let end = range.end - (text.len() - text.trim_end().len());
+    if start >= end {
+        return Err("the selection is empty".into());
+    }
+
+    for child in LinkedNode::new(source.root()).children() {
+        let node = child.range();
+        let splits = |offset| node.start < offset && offset < node.end;
+        let is_space = matches!(child.kind(), SyntaxKind::Space | SyntaxKind::Parbreak);
+        // The hash of an embedded expression must be extracted with it.
+        let is_hash_before = child.kind() == SyntaxKind::Hash && node.end == start;
+        if (!is_space && (splits(start) || splits(end))) || is_hash_before {
+            return Err("the selection must consist of complete top-level nodes".into());
+        }
+    }
+
+    Ok(start..end)
+}
+
+/// Get the top-level imports before the offset, with the relative paths of
+/// modules rewritten from the directory of the document to the one of the new
+/// file.
+fn inherited_preamble(source: &Source, offset: usize, dir: &Path, new_dir: &Path) -> String {
+    let mut preamble = String::new();
+    for child in LinkedNode::new(source.root()).children() {
+        if child.range().start >= offset {
+            break;
+        }
+        let Some(import) = child.cast::<ast::ModuleImport>() else {
+            continue;
+        };
+
+        let mut text = source.text()[child.range()].to_owned();
+        if let ast::Expr::Str(path) = import.source() {
+            let path_node = child.find(path.span());
+            let path = path.get();
+            let is_relative = !path.starts_with('@') && !path.starts_with('/');
+            let rewritten = is_relative
+                .then(|| relative_path(new_dir, &dir.join(path.as_str()).clean()))
+                .flatten();
+            if let (Some(path_node), Some(rewritten)) = (path_node, rewritten) {
+                let start = path_node.offset() - child.offset();
+                let end = start + path_node.range().len();
+                text.replace_range(start..end, &format!("\"{rewritten}\""));
+            }
+        }
+        preamble.push('#');
+        preamble.push_str(&text);
+        preamble.push('\n');
+    }
+
+    if !preamble.is_empty() {
+        preamble.push('\n');
+    }
+    preamble
+}
+
+/// Get the path of the file relative to the directory, with forward slashes as
+/// in typst paths.
+fn relative_path(dir: &Path, file: &Path) -> Option<String> {
+    Some(unix_slash(&pathdiff::diff_paths(file, dir)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    const DOC: &str = r#"#import "lib.typ": helper
+#import "@preview/cetz:0.2.2"
+= Intro
+Hello.
+= Methods
+We use #helper.
+= End"#;
+
+    fn extract(range: LspRange) -> Result<WorkspaceEdit, EcoString> {
+        run_with_ctx(DOC, |ctx, path| {
+            let request = ExtractToFileRequest {
+                path,
+                range,
+                new_file: PathBuf::from("chapters/methods.typ"),
+            };
+            request.extract(ctx)
+        })
+    }
+
+    #[test]
+    fn test_extract_section() {
+        let range = LspRange::new(LspPosition::new(4, 0), LspPosition::new(6, 0));
+        let edit = extract(range).unwrap();
+        let Some(DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("unexpected edit {edit:?}");
+        };
+
+        let edits: Vec<_> = operations
+            .into_iter()
+            .filter_map(|op| match op {
+                DocumentChangeOperation::Edit(edit) => Some(edit),
+                DocumentChangeOperation::Op(ResourceOp::Create(create)) => {
+                    assert!(create.uri.path().ends_with("/chapters/methods.typ"));
+                    None
+                }
+                op => panic!("unexpected operation {op:?}"),
+            })
+            .map(|edit| match &edit.edits[..] {
+                [OneOf::Left(e)] => (edit.text_document.uri, e.range, e.new_text.clone()),
+                edits => panic!("unexpected edits {edits:?}"),
+            })
+            .collect();
+
+        let [(new_url, _, content), (url, range, include)] = &edits[..] else {
+            panic!("unexpected edits {edits:?}");
+        };
+        assert!(new_url.path().ends_with("/chapters/methods.typ"));
+        assert_eq!(
+            content,
+            "#import \"../lib.typ\": helper\n#import \"@preview/cetz:0.2.2\"\n\n\
+             = Methods\nWe use #helper.\n"
+        );
+        assert!(url.path().ends_with("/main.typ"));
+        let selection = LspRange::new(LspPosition::new(4, 0), LspPosition::new(5, 15));
+        assert_eq!(*range, selection);
+        assert_eq!(include, "#include \"chapters/methods.typ\"");
+    }
+
+    #[test]
+    fn test_extract_partial_node() {
+        // The selection ends in the middle of the embedded call.
+        let range = LspRange::new(LspPosition::new(4, 0), LspPosition::new(5, 10));
+        assert!(extract(range).is_err());
+    }
+}
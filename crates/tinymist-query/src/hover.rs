@@ -1,12 +1,30 @@
 use core::fmt;
+use std::fmt::Write;
+use std::ops::Range;
+
+use base64::Engine;
+use comemo::Track;
+use ecow::eco_format;
+use serde::Deserialize;
+use typst::engine::{Engine as TypstEngine, Route};
+use typst::eval::Tracer;
+use typst::foundations::{Context, IntoValue, Repr};
+use typst::introspection::{Counter, Location, Locator, State};
+use typst::layout::{Abs, Em, Frame, FrameItem, Point, Size};
+use typst::syntax::package::{PackageVersion, VersionlessPackageSpec};
+use typst::text::{Font, FontVariant, Glyph, Lang, TextItem};
+use typst::visualize::{Color, Paint};
 
 use crate::{
     analysis::{analyze_dyn_signature, find_definition, DefinitionLink, Signature},
     jump_from_cursor,
     prelude::*,
-    syntax::{find_docs_before, get_deref_target, LexicalKind, LexicalVarKind},
+    syntax::{
+        find_docs_before, get_deref_target, get_field_target, highlight_code, highlight_markdown,
+        is_sys_inputs, ColorTheme, LexicalKind, LexicalVarKind,
+    },
     upstream::{expr_tooltip, plain_docs_sentence, route_of_value, tooltip, Tooltip},
-    LspHoverContents, StatefulRequest,
+    LspHoverContents, OverlayWorld, StatefulRequest,
 };
 
 /// The [`textDocument/hover`] request asks the server for hover information at
@@ -39,42 +57,42 @@ impl StatefulRequest for HoverRequest {
         // the typst's cursor is 1-based, so we need to add 1 to the offset
         let cursor = offset + 1;
 
-        let contents = def_tooltip(ctx, &source, doc.as_ref(), cursor).or_else(|| {
+        let math = math_preview(ctx, &source, cursor);
+        let contents = package_tooltip(ctx, &source, cursor);
+        let contents = contents.or_else(|| sys_input_tooltip(ctx, &source, cursor));
+        let contents = contents.or_else(|| def_tooltip(ctx, &source, doc.as_ref(), cursor));
+        let contents = contents.or_else(|| {
             Some(typst_to_lsp::tooltip(&tooltip(
                 ctx.world(),
                 doc_ref,
                 &source,
                 cursor,
             )?))
-        })?;
+        });
 
-        let ast_node = LinkedNode::new(source.root()).leaf_at(cursor)?;
-        let range = ctx.to_lsp_range(ast_node.range(), &source);
+        let mut values = context_tooltip(ctx, &source, doc.as_ref(), cursor);
+        if let Some(rules) = set_rules_tooltip(ctx, &source, cursor) {
+            values = Some(match values {
+                Some(values) => format!("{values}\n---\n{rules}"),
+                None => rules,
+            });
+        }
 
-        // Neovim shows ugly hover if the hover content is in array, so we join them
-        // manually with divider bars.
-        let mut contents = match contents {
-            LspHoverContents::Array(contents) => contents
-                .into_iter()
-                .map(|e| match e {
-                    MarkedString::LanguageString(e) => {
-                        format!("```{}\n{}\n```", e.language, e.value)
-                    }
-                    MarkedString::String(e) => e,
-                })
-                .join("\n---\n"),
-            LspHoverContents::Scalar(MarkedString::String(contents)) => contents,
-            LspHoverContents::Scalar(MarkedString::LanguageString(contents)) => {
-                format!("```{}\n{}\n```", contents.language, contents.value)
-            }
-            lsp_types::HoverContents::Markup(e) => {
-                match e.kind {
-                    MarkupKind::Markdown => e.value,
-                    // todo: escape
-                    MarkupKind::PlainText => e.value,
-                }
-            }
+        let ast_node = LinkedNode::new(source.root()).leaf_at(cursor)?;
+        let theme = ctx.analysis.highlight_theme();
+        let (mut contents, range) = match (math, contents) {
+            (Some((math, _)), Some(contents)) => (
+                format!("{math}\n---\n{}", render_contents(contents, theme)),
+                ast_node.range(),
+            ),
+            (Some((math, range)), None) => (math, range),
+            (None, Some(contents)) => (render_contents(contents, theme), ast_node.range()),
+            (None, None) => (values.take()?, ast_node.range()),
         };
+        if let Some(values) = values {
+            contents = format!("{values}\n---\n{contents}");
+        }
+        let range = ctx.to_lsp_range(range, &source);
 
         if ctx.analysis.enable_periscope {
             if let Some(doc) = doc.clone() {
@@ -115,6 +133,254 @@ impl StatefulRequest for HoverRequest {
     }
 }
 
+/// Render the hover contents as markdown.
+///
+/// Neovim shows ugly hover if the hover content is in array, so we join them
+/// manually with divider bars.
+fn render_contents(contents: LspHoverContents, theme: Option<ColorTheme>) -> String {
+    match contents {
+        LspHoverContents::Array(contents) => contents
+            .into_iter()
+            .map(|e| match e {
+                MarkedString::LanguageString(e) => render_code(&e.language, &e.value, theme),
+                MarkedString::String(e) => render_markdown(e, theme),
+            })
+            .join("\n---\n"),
+        LspHoverContents::Scalar(MarkedString::String(contents)) => {
+            render_markdown(contents, theme)
+        }
+        LspHoverContents::Scalar(MarkedString::LanguageString(contents)) => {
+            render_code(&contents.language, &contents.value, theme)
+        }
+        lsp_types::HoverContents::Markup(e) => {
+            match e.kind {
+                MarkupKind::Markdown => render_markdown(e.value, theme),
+                // todo: escape
+                MarkupKind::PlainText => e.value,
+            }
+        }
+    }
+}
+
+/// Render a code snippet, which is highlighted with the colors of the theme if
+/// there is a preferred one, so that it is legible in dark themes.
+fn render_code(lang: &str, code: &str, theme: Option<ColorTheme>) -> String {
+    match theme {
+        Some(theme) if matches!(lang, "typ" | "typc" | "typm") => highlight_code(code, lang, theme),
+        _ => format!("```{lang}\n{code}\n```"),
+    }
+}
+
+/// Render markdown, highlighting the typst code blocks in it as well.
+fn render_markdown(markdown: String, theme: Option<ColorTheme>) -> String {
+    match theme {
+        Some(theme) => highlight_markdown(&markdown, theme),
+        None => markdown,
+    }
+}
+
+/// The maximum number of runs of equal values shown for a contextual read.
+const MAX_CONTEXT_RUNS: usize = 8;
+
+/// Show the values that a read of a counter or a state in a `context`
+/// expression resolves to, wherever the expression is shown in the document.
+/// Consecutive equal values are summarized with the range of their pages.
+fn context_tooltip(
+    ctx: &mut AnalysisContext,
+    source: &Source,
+    doc: Option<&VersionedDocument>,
+    cursor: usize,
+) -> Option<String> {
+    let doc = doc?;
+    let leaf = LinkedNode::new(source.root()).leaf_at(cursor)?;
+    let (read, method) = contextual_read(&leaf)?;
+
+    // The runs of equal values, as their first and last pages, their
+    // representation and their count.
+    let mut runs: Vec<(usize, usize, EcoString, usize)> = vec![];
+    let (_, values) = resolve_contextual_read(ctx, doc, &read, &method)?;
+    for (page, resolved) in values {
+        let repr = resolved.repr();
+        match runs.last_mut() {
+            Some((_, last, prev, count)) if *prev == repr => {
+                *last = page;
+                *count += 1;
+            }
+            _ => runs.push((page, page, repr, 1)),
+        }
+    }
+    if runs.is_empty() {
+        return None;
+    }
+
+    let mut lines = vec!["Evaluated in the document:".to_owned()];
+    for (first, last, repr, count) in runs.iter().take(MAX_CONTEXT_RUNS) {
+        let pages = if first == last {
+            format!("page {first}")
+        } else {
+            format!("pages {first}–{last}")
+        };
+        let count = if *count > 1 {
+            format!(" (x{count})")
+        } else {
+            String::new()
+        };
+        lines.push(format!("- {pages}: `{repr}`{count}"));
+    }
+    if runs.len() > MAX_CONTEXT_RUNS {
+        lines.push(format!("- …and {} more", runs.len() - MAX_CONTEXT_RUNS));
+    }
+    Some(lines.join("\n"))
+}
+
+/// Resolve a read of a counter or a state, e.g. `c.get()`, with the method of
+/// the read, wherever the enclosing `context` expression is shown in the
+/// document, in order, along with the pages showing it. The counter or the
+/// state read is returned as well.
+pub(crate) fn resolve_contextual_read(
+    ctx: &mut AnalysisContext,
+    doc: &VersionedDocument,
+    read: &LinkedNode,
+    method: &str,
+) -> Option<(Value, Vec<(usize, Value)>)> {
+    let contextual = std::iter::successors(read.parent().cloned(), |node| node.parent().cloned())
+        .find(|node| node.kind() == SyntaxKind::Contextual)?;
+
+    // The counter or state is the target of the read, e.g. `counter(page)`.
+    let target = read.children().next()?.children().next()?;
+    let world = ctx.world();
+    let value = analyze_expr(world, &target)
+        .into_iter()
+        .map(|(value, _)| value)
+        .find(|value| match value {
+            Value::Dyn(value) => value.is::<Counter>() || value.is::<State>(),
+            _ => false,
+        })?;
+
+    let locations = ctx.contextual_locations(doc);
+    let locations = locations.get(&contextual.span()).into_iter().flatten();
+    let introspector = &doc.document.introspector;
+    let mut locator = Locator::default();
+    let mut tracer = Tracer::new();
+    let mut engine = TypstEngine {
+        world: world.track(),
+        route: Route::default(),
+        introspector: introspector.track(),
+        locator: &mut locator,
+        tracer: tracer.track_mut(),
+    };
+
+    let mut values = vec![];
+    for &location in locations {
+        let Some(resolved) = read_at(&mut engine, &value, method, location) else {
+            continue;
+        };
+        values.push((introspector.page(location).get(), resolved));
+    }
+    Some((value, values))
+}
+
+/// Find the read of a counter or a state at the leaf, e.g. `c.get()`, along
+/// with the name of its method. Hovering the `context` keyword finds the body
+/// of the expression.
+fn contextual_read<'a>(leaf: &LinkedNode<'a>) -> Option<(LinkedNode<'a>, EcoString)> {
+    if leaf.kind() == SyntaxKind::Context {
+        let body = leaf.parent()?.children().last()?;
+        let method = read_method(&body)?;
+        return Some((body, method));
+    }
+    let mut node = leaf.clone();
+    loop {
+        if let Some(method) = read_method(&node) {
+            return Some((node, method));
+        }
+        if node.kind() == SyntaxKind::Contextual {
+            return None;
+        }
+        node = node.parent()?.clone();
+    }
+}
+
+/// Get the method of a call reading a counter or a state, i.e. `get` or
+/// `final`, e.g. `get` of `c.get()`.
+pub(crate) fn read_method(node: &LinkedNode) -> Option<EcoString> {
+    let call = node.cast::<ast::FuncCall>()?;
+    let ast::Expr::FieldAccess(access) = call.callee() else {
+        return None;
+    };
+    let method = access.field().get().clone();
+    matches!(method.as_str(), "get" | "final").then_some(method)
+}
+
+/// List the set rules in effect for the text at the cursor, which are the ones
+/// before it in the enclosing blocks, outermost first. It is only shown if
+/// `hoverSetRules` is enabled, not to clutter every hover on text.
+fn set_rules_tooltip(ctx: &AnalysisContext, source: &Source, cursor: usize) -> Option<String> {
+    if !ctx.analysis.hover_set_rules {
+        return None;
+    }
+
+    let leaf = LinkedNode::new(source.root()).leaf_at(cursor)?;
+    if !matches!(leaf.kind(), SyntaxKind::Text | SyntaxKind::Space)
+        || leaf.parent_kind() != Some(SyntaxKind::Markup)
+    {
+        return None;
+    }
+
+    let mut scopes = vec![];
+    let mut node = leaf;
+    while let Some(parent) = node.parent().cloned() {
+        if matches!(parent.kind(), SyntaxKind::Markup | SyntaxKind::Code) {
+            let rules: Vec<_> = (parent.children())
+                .take_while(|child| child.offset() < node.offset())
+                .filter(|child| child.kind() == SyntaxKind::SetRule)
+                .map(|child| child.get().clone().into_text())
+                .collect();
+            scopes.push(rules);
+        }
+        node = parent;
+    }
+    if scopes.iter().all(Vec::is_empty) {
+        return None;
+    }
+
+    let mut lines = vec!["Active set rules:".to_owned()];
+    for rule in scopes.into_iter().rev().flatten() {
+        lines.push(format!("- `{rule}`"));
+    }
+    Some(lines.join("\n"))
+}
+
+/// Resolve the read of the counter or the state at the location.
+fn read_at(
+    engine: &mut TypstEngine,
+    value: &Value,
+    method: &str,
+    location: Location,
+) -> Option<Value> {
+    let Value::Dyn(value) = value else {
+        return None;
+    };
+    let context = Context::none();
+    let (context, span) = (context.track(), TypstSpan::detached());
+
+    if let Some(counter) = value.downcast::<Counter>() {
+        let state = match method {
+            "final" => counter.final_(engine, context, span, Some(location)),
+            _ => counter.at_loc(engine, location),
+        };
+        return state.ok().map(IntoValue::into_value);
+    }
+    if let Some(state) = value.downcast::<State>() {
+        let value = match method {
+            "final" => state.final_(engine, context, span, Some(location)),
+            _ => state.at_loc(engine, location),
+        };
+        return value.ok();
+    }
+    None
+}
+
 enum CommandOrLink {
     Link(String),
 }
@@ -124,6 +390,247 @@ struct CommandLink {
     command_or_links: Vec<CommandOrLink>,
 }
 
+/// Show the information from the manifest of an imported package, and whether
+/// a newer version of it is available.
+fn package_tooltip(
+    ctx: &AnalysisContext,
+    source: &Source,
+    cursor: usize,
+) -> Option<LspHoverContents> {
+    let leaf = LinkedNode::new(source.root()).leaf_at(cursor)?;
+    let path = leaf.cast::<ast::Str>()?;
+    let import = leaf.parent()?.cast::<ast::ModuleImport>()?;
+    if import.source().span() != leaf.span() {
+        return None;
+    }
+    let spec: PackageSpec = path.get().parse().ok()?;
+
+    // Only the cached data is shown, as hover must not wait for the network.
+    // The manifest is available once the package is downloaded, and the
+    // package index is downloaded in the background.
+    let manifest = (ctx.resources.local_package_dir(&spec))
+        .and_then(|dir| std::fs::read_to_string(dir.join("typst.toml")).ok());
+    let latest = ctx
+        .resources
+        .latest_package_version(&VersionlessPackageSpec {
+            namespace: spec.namespace.clone(),
+            name: spec.name.clone(),
+        });
+
+    let docs = package_docs(&spec, manifest.as_deref(), latest);
+    Some(LspHoverContents::Scalar(MarkedString::String(docs)))
+}
+
+/// The fields of a package manifest shown in hover.
+#[derive(Debug, Deserialize)]
+struct ManifestDocs {
+    package: PackageDocs,
+}
+
+#[derive(Debug, Deserialize)]
+struct PackageDocs {
+    name: String,
+    version: String,
+    description: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    license: Option<String>,
+    repository: Option<String>,
+}
+
+/// Render the information of a package as markdown, from its manifest and the
+/// latest version in the package index.
+fn package_docs(
+    spec: &PackageSpec,
+    manifest: Option<&str>,
+    latest: Option<PackageVersion>,
+) -> String {
+    let mut docs = String::new();
+    match manifest.and_then(|manifest| toml::from_str::<ManifestDocs>(manifest).ok()) {
+        Some(ManifestDocs { package }) => {
+            let _ = writeln!(docs, "**{}** {}\n", package.name, package.version);
+            if let Some(description) = &package.description {
+                let _ = writeln!(docs, "{description}\n");
+            }
+            if !package.authors.is_empty() {
+                let _ = writeln!(docs, "Authors: {}\n", package.authors.join(", "));
+            }
+            if let Some(license) = &package.license {
+                let _ = writeln!(docs, "License: {license}\n");
+            }
+            if let Some(repository) = &package.repository {
+                let _ = writeln!(docs, "Repository: <{repository}>\n");
+            }
+        }
+        None => {
+            let _ = writeln!(docs, "**{}** {}\n", spec.name, spec.version);
+            let _ = writeln!(docs, "The manifest of the package is not available.\n");
+        }
+    }
+
+    let _ = match latest {
+        Some(latest) if latest > spec.version => {
+            writeln!(docs, "A newer version {latest} is available.")
+        }
+        Some(_) => writeln!(docs, "This is the latest version."),
+        None => writeln!(
+            docs,
+            "Version checks were skipped as the package index is unavailable."
+        ),
+    };
+    docs.trim_end().to_owned()
+}
+
+/// Show the value of an input accessed by `sys.inputs.key` or
+/// `sys.inputs.at("key")`.
+fn sys_input_tooltip(
+    ctx: &AnalysisContext,
+    source: &Source,
+    cursor: usize,
+) -> Option<LspHoverContents> {
+    let leaf = LinkedNode::new(source.root()).leaf_at(cursor)?;
+    let target = get_field_target(&leaf)?;
+    if !is_sys_inputs(&target.target) {
+        return None;
+    }
+    let key = match leaf.cast::<ast::Str>() {
+        Some(key) if target.quoted => key.get(),
+        _ => leaf.cast::<ast::Ident>()?.get().clone(),
+    };
+
+    let contents = match ctx.resources.inputs().get(&key) {
+        Ok(value) => vec![
+            MarkedString::LanguageString(LanguageString {
+                language: "typc".to_owned(),
+                value: value.repr().into(),
+            }),
+            MarkedString::String(format!("The input `{key}` provided to the compiler.")),
+        ],
+        Err(_) => vec![MarkedString::String(format!(
+            "The input `{key}` is not provided."
+        ))],
+    };
+    Some(LspHoverContents::Array(contents))
+}
+
+/// The maximum length of the equations rendered in hover, beyond which the
+/// source is shown instead.
+const MAX_MATH_PREVIEW_LEN: usize = 1024;
+/// The maximum width and height of the equations rendered in hover in points.
+const MAX_MATH_PREVIEW_SIZE: f64 = 600.;
+
+/// Preview the equation at the cursor, along with its range.
+///
+/// The equation is rendered to an image if `hoverMathPreview` is enabled, and
+/// shown as its source otherwise, or if it is too large or cannot be compiled
+/// on its own, e.g. it uses the definitions in the document.
+fn math_preview(
+    ctx: &AnalysisContext,
+    source: &Source,
+    cursor: usize,
+) -> Option<(String, Range<usize>)> {
+    let leaf = LinkedNode::new(source.root()).leaf_at(cursor)?;
+    let equation = iter::successors(Some(leaf), |node| node.parent().cloned())
+        .find(|node| node.kind() == SyntaxKind::Equation)?;
+    let code = &source.text()[equation.range()];
+
+    let contents = math_image(ctx, code)
+        .unwrap_or_else(|| render_code("typ", code, ctx.analysis.highlight_theme()));
+    Some((contents, equation.range()))
+}
+
+/// Render the equation to an image in markdown if `hoverMathPreview` is
+/// enabled, the client renders the images, and the equation is neither too
+/// large nor dependent on the document.
+pub(crate) fn math_image(ctx: &AnalysisContext, code: &str) -> Option<String> {
+    if !math_previewed(ctx, code) {
+        return None;
+    }
+    let svg = render_math(ctx.world(), code, ctx.analysis.preferred_theme)?;
+    let svg = base64::engine::general_purpose::STANDARD.encode(svg);
+    Some(format!("![math](data:image/svg+xml;base64,{svg})"))
+}
+
+/// Check whether the equation is to be rendered by [`math_image`].
+pub(crate) fn math_previewed(ctx: &AnalysisContext, code: &str) -> bool {
+    let enabled = ctx.analysis.hover_math_preview && ctx.analysis.markdown_images;
+    enabled && code.len() <= MAX_MATH_PREVIEW_LEN
+}
+
+/// The size of the glyphs rendered in previews, in points.
+const GLYPH_PREVIEW_SIZE: f64 = 24.;
+/// The fonts preferred to render the glyphs of symbols, as in equations.
+const GLYPH_PREVIEW_FONTS: &[&str] = &["new computer modern math", "latin modern math"];
+
+/// Render the glyph of a character to an image in markdown, with the first
+/// math font covering it, or a fallback font otherwise. The images are cached
+/// per glyph.
+pub(crate) fn glyph_image(ctx: &mut AnalysisContext, c: char) -> Option<EcoString> {
+    let world = ctx.world();
+    let book = world.book();
+    let variant = FontVariant::default();
+    let fallback = book.select_fallback(None, variant, c.encode_utf8(&mut [0; 4]));
+    let font = (GLYPH_PREVIEW_FONTS.iter())
+        .filter_map(|family| book.select(family, variant))
+        .chain(fallback)
+        .filter_map(|index| world.font(index))
+        .find(|font| font.info().coverage.contains(c as u32))?;
+    ctx.glyph_image(font, c, |ctx, font| render_glyph(ctx, font, c))
+}
+
+fn render_glyph(ctx: &AnalysisContext, font: Font, c: char) -> Option<EcoString> {
+    let id = font.ttf().glyph_index(c)?;
+    let advance = font.to_em(font.ttf().glyph_hor_advance(id)?);
+    let (ascender, descender) = (font.metrics().ascender, font.metrics().descender);
+    let size = Abs::pt(GLYPH_PREVIEW_SIZE);
+    let fill = match ctx.analysis.preferred_theme {
+        Some(ColorTheme::Dark) => Color::WHITE,
+        _ => Color::BLACK,
+    };
+    let text = TextItem {
+        font,
+        size,
+        fill: Paint::Solid(fill),
+        stroke: None,
+        lang: Lang::ENGLISH,
+        text: c.into(),
+        glyphs: vec![Glyph {
+            id: id.0,
+            x_advance: advance,
+            x_offset: Em::zero(),
+            range: 0..c.len_utf8() as u16,
+            span: (TypstSpan::detached(), 0),
+        }],
+    };
+
+    let mut frame = Frame::soft(Size::new(advance.at(size), (ascender - descender).at(size)));
+    frame.push(Point::with_y(ascender.at(size)), FrameItem::Text(text));
+    let svg = base64::engine::general_purpose::STANDARD.encode(typst_svg::svg(&frame));
+    Some(eco_format!("![{c}](data:image/svg+xml;base64,{svg})"))
+}
+
+/// Render the equation to an SVG image with the fonts of the world.
+fn render_math(world: &dyn World, code: &str, theme: Option<ColorTheme>) -> Option<String> {
+    let fill = match theme {
+        Some(ColorTheme::Dark) => "white",
+        _ => "black",
+    };
+    let main = Source::detached(format!(
+        "#set page(width: auto, height: auto, margin: 2pt, fill: none)\n\
+         #set text(fill: {fill})\n\
+         {code}"
+    ));
+    let world = OverlayWorld::new(world).with_main(main);
+    let doc = typst::compile(&world, &mut Tracer::new()).ok()?;
+
+    let frame = &doc.pages.first()?.frame;
+    let size = frame.size();
+    if size.x.to_pt() > MAX_MATH_PREVIEW_SIZE || size.y.to_pt() > MAX_MATH_PREVIEW_SIZE {
+        return None;
+    }
+    Some(typst_svg::svg(frame))
+}
+
 fn def_tooltip(
     ctx: &mut AnalysisContext,
     source: &Source,
@@ -402,6 +909,8 @@ impl DocTooltip {
 
 #[cfg(test)]
 mod tests {
+    use typst::foundations::Dict;
+
     use super::*;
     use crate::tests::*;
 
@@ -419,4 +928,205 @@ mod tests {
             assert_snapshot!(JsonRepr::new_redacted(result, &REDACT_LOC));
         });
     }
+
+    #[test]
+    fn test_dark_theme() {
+        let content = "#let f(x) = x\n#f(1)";
+        let hover = |markdown_html| {
+            run_with_ctx(content, |ctx, path| {
+                ctx.analysis.preferred_theme = Some(ColorTheme::Dark);
+                ctx.analysis.markdown_html = markdown_html;
+                let request = HoverRequest {
+                    path,
+                    position: LspPosition::new(1, 1),
+                };
+                match request.request(ctx, None).unwrap().contents {
+                    LspHoverContents::Scalar(MarkedString::String(contents)) => contents,
+                    contents => panic!("unexpected hover contents {contents:?}"),
+                }
+            })
+        };
+
+        let contents = hover(true);
+        assert!(!contents.contains("```typc"), "{contents}");
+        // The `let` keyword is in the dark palette.
+        let keyword = r#"<span style="color:#ff7b72;">let</span>"#;
+        assert!(contents.contains(keyword), "{contents}");
+        assert!(!contents.contains("#d73a49"), "{contents}");
+
+        // Clients not rendering the HTML get the fenced code instead.
+        let contents = hover(false);
+        assert!(contents.contains("```typc"), "{contents}");
+        assert!(!contents.contains("<span"), "{contents}");
+    }
+
+    #[test]
+    fn test_sys_input() {
+        let content = r#"#sys.inputs.version #sys.inputs.at("missing")"#;
+        let inputs = Dict::from_iter([("version".into(), Value::Str("1.0".into()))]);
+        let hover = |character| {
+            run_with_ctx_and_inputs(content, inputs.clone(), |ctx, path| {
+                let request = HoverRequest {
+                    path,
+                    position: LspPosition::new(0, character),
+                };
+                match request.request(ctx, None).unwrap().contents {
+                    LspHoverContents::Scalar(MarkedString::String(contents)) => contents,
+                    contents => panic!("unexpected hover contents {contents:?}"),
+                }
+            })
+        };
+
+        let contents = hover(14);
+        assert!(contents.contains("\"1.0\""), "{contents}");
+        let contents = hover(36);
+        assert!(contents.contains("`missing` is not provided"), "{contents}");
+    }
+
+    #[test]
+    fn test_math_preview() {
+        let content = "The area is $pi r^2$.";
+        let hover = |enabled, images| {
+            run_with_ctx_and_fonts(content, |ctx, path| {
+                ctx.analysis.hover_math_preview = enabled;
+                ctx.analysis.markdown_images = images;
+                let request = HoverRequest {
+                    path,
+                    position: LspPosition::new(0, 16),
+                };
+                let hover = request.request(ctx, None).unwrap();
+                let contents = match hover.contents {
+                    LspHoverContents::Scalar(MarkedString::String(contents)) => contents,
+                    contents => panic!("unexpected hover contents {contents:?}"),
+                };
+                (contents, hover.range.unwrap())
+            })
+        };
+
+        let (contents, range) = hover(true, true);
+        assert!(
+            contents.contains("data:image/svg+xml;base64,"),
+            "{contents}"
+        );
+        assert!(!contents.contains("$pi r^2$"), "{contents}");
+        let equation = LspRange::new(LspPosition::new(0, 12), LspPosition::new(0, 20));
+        assert_eq!(range, equation);
+
+        // The source is shown if disabled or if the client cannot show images.
+        for (enabled, images) in [(false, true), (true, false)] {
+            let (contents, range) = hover(enabled, images);
+            assert!(!contents.contains("data:image"), "{contents}");
+            assert!(contents.contains("```typ\n$pi r^2$\n```"), "{contents}");
+            assert_eq!(range, equation);
+        }
+    }
+
+    #[test]
+    fn test_context_values() {
+        let content =
+            "#set page(height: 100pt)\n#set page(footer: context counter(page).get())\n#lorem(100)";
+        let contents = run_with_ctx(content, |ctx, path| {
+            let doc = typst::compile(ctx.world(), &mut Default::default()).ok();
+            let doc = doc.map(|doc| VersionedDocument {
+                version: 0,
+                document: Arc::new(doc),
+            });
+            let source = ctx.source_by_path(&path).unwrap();
+            let request = HoverRequest {
+                path,
+                position: ctx.to_lsp_pos(source.text().find("get").unwrap(), &source),
+            };
+            match request.request(ctx, doc).unwrap().contents {
+                LspHoverContents::Scalar(MarkedString::String(contents)) => contents,
+                contents => panic!("unexpected hover contents {contents:?}"),
+            }
+        });
+
+        // The footer of each page reads its own page number.
+        assert!(
+            contents.starts_with("Evaluated in the document:\n"),
+            "{contents}"
+        );
+        assert!(contents.contains("- page 1: `(1,)`\n"), "{contents}");
+        assert!(contents.contains("- page 2: `(2,)`"), "{contents}");
+    }
+
+    #[test]
+    fn test_set_rules() {
+        let content = "#set text(size: 14pt)\n#[#set par(leading: 1em)]\n#block[\n  #set par(justify: true)\n  Nested text\n]";
+        let hover = |enabled| {
+            run_with_ctx(content, |ctx, path| {
+                ctx.analysis.hover_set_rules = enabled;
+                let source = ctx.source_by_path(&path).unwrap();
+                let request = HoverRequest {
+                    path,
+                    position: ctx.to_lsp_pos(source.text().find("Nested").unwrap(), &source),
+                };
+                let hover = request.request(ctx, None)?;
+                match hover.contents {
+                    LspHoverContents::Scalar(MarkedString::String(contents)) => Some(contents),
+                    contents => panic!("unexpected hover contents {contents:?}"),
+                }
+            })
+        };
+
+        // Hovering text shows nothing unless the rules are requested.
+        assert!(hover(false).is_none());
+
+        let contents = hover(true).unwrap();
+        assert!(contents.starts_with("Active set rules:\n"), "{contents}");
+        assert!(
+            contents.contains("- `set text(size: 14pt)`\n- `set par(justify: true)`"),
+            "{contents}"
+        );
+        // The rule is scoped to the previous block.
+        assert!(!contents.contains("leading"), "{contents}");
+    }
+
+    #[test]
+    fn test_package_docs() {
+        let spec: PackageSpec = "@preview/cetz:0.2.1".parse().unwrap();
+        let manifest = r#"[package]
+name = "cetz"
+version = "0.2.1"
+entrypoint = "src/lib.typ"
+authors = ["Johannes Wolf", "fenjalien"]
+license = "LGPL-3.0-or-later"
+description = "Drawing with Typst made easy."
+"#;
+        let docs = package_docs(&spec, Some(manifest), "0.2.2".parse().ok());
+        assert!(docs.starts_with("**cetz** 0.2.1\n"), "{docs}");
+        assert!(docs.contains("Drawing with Typst made easy."), "{docs}");
+        assert!(docs.contains("Authors: Johannes Wolf, fenjalien"), "{docs}");
+        assert!(
+            docs.ends_with("A newer version 0.2.2 is available."),
+            "{docs}"
+        );
+
+        // Offline, the cached manifest is shown without version checks.
+        let docs = package_docs(&spec, Some(manifest), None);
+        assert!(docs.starts_with("**cetz** 0.2.1\n"), "{docs}");
+        assert!(docs.contains("Version checks were skipped"), "{docs}");
+    }
+
+    #[test]
+    fn test_package_import_hover() {
+        // Packages in the local namespace are never downloaded.
+        let content = r#"#import "@local/nonexistent-pkg:0.1.0": *"#;
+        run_with_ctx(content, |ctx, path| {
+            let request = HoverRequest {
+                path,
+                position: LspPosition::new(0, 12),
+            };
+            let contents = match request.request(ctx, None).unwrap().contents {
+                LspHoverContents::Scalar(MarkedString::String(contents)) => contents,
+                contents => panic!("unexpected hover contents {contents:?}"),
+            };
+            assert!(
+                contents.starts_with("**nonexistent-pkg** 0.1.0"),
+                "{contents}"
+            );
+            assert!(contents.contains("not available"), "{contents}");
+        });
+    }
 }
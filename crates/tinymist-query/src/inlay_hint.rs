@@ -1,11 +1,13 @@
 use std::ops::Range;
 
+use ecow::eco_format;
 use lsp_types::{InlayHintKind, InlayHintLabel};
+use typst::introspection::Counter;
 
 use crate::{
     analysis::{analyze_call, ParamKind},
+    hover::{read_method, resolve_contextual_read},
     prelude::*,
-    SemanticRequest,
 };
 
 /// Configuration for inlay hints.
@@ -60,14 +62,18 @@ pub struct InlayHintRequest {
     pub range: LspRange,
 }
 
-impl SemanticRequest for InlayHintRequest {
+impl StatefulRequest for InlayHintRequest {
     type Response = Vec<InlayHint>;
 
-    fn request(self, ctx: &mut AnalysisContext) -> Option<Self::Response> {
+    fn request(
+        self,
+        ctx: &mut AnalysisContext,
+        doc: Option<VersionedDocument>,
+    ) -> Option<Self::Response> {
         let source = ctx.source_by_path(&self.path).ok()?;
         let range = ctx.to_typst_range(self.range, &source)?;
 
-        let hints = inlay_hint(ctx, &source, range, ctx.position_encoding()).ok()?;
+        let hints = inlay_hint(ctx, &source, doc.as_ref(), range, ctx.position_encoding()).ok()?;
         log::debug!(
             "got inlay hints on {source:?} => {hints:?}",
             source = source.id(),
@@ -85,6 +91,7 @@ impl SemanticRequest for InlayHintRequest {
 fn inlay_hint(
     ctx: &mut AnalysisContext,
     source: &Source,
+    doc: Option<&VersionedDocument>,
     range: Range<usize>,
     encoding: PositionEncoding,
 ) -> FileResult<Vec<InlayHint>> {
@@ -93,6 +100,7 @@ fn inlay_hint(
     struct InlayHintWorker<'a, 'w> {
         ctx: &'a mut AnalysisContext<'w>,
         source: &'a Source,
+        doc: Option<&'a VersionedDocument>,
         range: Range<usize>,
         encoding: PositionEncoding,
         hints: Vec<InlayHint>,
@@ -134,6 +142,9 @@ fn inlay_hint(
                 // Parameter inlay hints
                 SyntaxKind::FuncCall => {
                     trace!("func call found: {:?}", node);
+                    if self.ctx.analysis.inlay_hint_document_values {
+                        self.document_value_hint(node);
+                    }
                     let call_info = analyze_call(self.ctx, self.source.clone(), node.clone())?;
                     log::debug!("got call_info {call_info:?}");
 
@@ -284,11 +295,45 @@ fn inlay_hint(
 
             None
         }
+
+        /// Show the value that a read of a counter or a state resolves to in
+        /// the document after the read, e.g. `= 1` after
+        /// `counter(heading).get()`. The first value is shown if the read is
+        /// shown several times with different values.
+        fn document_value_hint(&mut self, node: &LinkedNode) -> Option<()> {
+            let method = read_method(node)?;
+            let (target, values) = resolve_contextual_read(self.ctx, self.doc?, node, &method)?;
+            let is_counter = matches!(&target, Value::Dyn(target) if target.is::<Counter>());
+            let values = (values.into_iter())
+                .map(|(_, value)| document_value_repr(&value, is_counter))
+                .dedup()
+                .collect::<Vec<_>>();
+            let first = values.first()?;
+            let label = match values.len() - 1 {
+                0 => eco_format!("= {first}"),
+                more => eco_format!("= {first} (+{more} more)"),
+            };
+
+            let lsp_pos =
+                typst_to_lsp::offset_to_position(node.range().end, self.encoding, self.source);
+            self.hints.push(InlayHint {
+                position: lsp_pos,
+                label: InlayHintLabel::String(label.into()),
+                kind: None,
+                text_edits: None,
+                tooltip: None,
+                padding_left: Some(true),
+                padding_right: None,
+                data: None,
+            });
+            Some(())
+        }
     }
 
     let mut worker = InlayHintWorker {
         ctx,
         source,
+        doc,
         range,
         encoding,
         hints: vec![],
@@ -300,6 +345,15 @@ fn inlay_hint(
     Ok(worker.hints)
 }
 
+/// Show a value of a counter as its numbers joined by dots, e.g. `1.2`, or any
+/// other value by its representation.
+fn document_value_repr(value: &Value, is_counter: bool) -> EcoString {
+    if let (true, Value::Array(numbers)) = (is_counter, value) {
+        return numbers.iter().map(|number| number.repr()).join(".").into();
+    }
+    value.repr()
+}
+
 fn is_one_line(src: &Source, arg_node: &LinkedNode<'_>) -> bool {
     is_one_line_(src, arg_node).unwrap_or(true)
 }
@@ -331,8 +385,42 @@ mod tests {
                 ),
             };
 
-            let result = request.request(ctx);
+            let result = request.request(ctx, None);
             assert_snapshot!(JsonRepr::new_redacted(result, &REDACT_LOC));
         });
     }
+
+    #[test]
+    fn test_document_values() {
+        let content =
+            "#set heading(numbering: \"1.\")\n= Intro\n== Scope\n#context counter(heading).get()";
+        let labels = run_with_ctx(content, |ctx, path| {
+            ctx.analysis.inlay_hint_document_values = true;
+            let doc = typst::compile(ctx.world(), &mut Default::default()).ok();
+            let doc = doc.map(|doc| VersionedDocument {
+                version: 0,
+                document: Arc::new(doc),
+            });
+            let source = ctx.source_by_path(&path).unwrap();
+            let request = InlayHintRequest {
+                path: path.clone(),
+                range: typst_to_lsp::range(
+                    0..source.text().len(),
+                    &source,
+                    PositionEncoding::Utf16,
+                ),
+            };
+            let hints = request.request(ctx, doc).unwrap();
+            (hints.into_iter())
+                .filter_map(|hint| match hint.label {
+                    InlayHintLabel::String(label) => Some((hint.position, label)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        });
+
+        // The hint is shown after the read of the counter.
+        let end = lsp_types::Position::new(3, "#context counter(heading).get()".len() as u32);
+        assert_eq!(labels, [(end, "= 1.1".to_owned())]);
+    }
 }
@@ -0,0 +1,246 @@
+use comemo::Track;
+use lsp_types::{InlineValue, InlineValueText};
+use typst::engine::{Engine, Route};
+use typst::eval::Tracer;
+use typst::foundations::{Context, IntoValue, Module, Repr};
+use typst::introspection::{Counter, Locator, State};
+use typst::model::Document;
+
+use crate::analysis::BindingValues;
+use crate::prelude::*;
+
+/// The maximum length of an inline value, in characters.
+const MAX_VALUE_LEN: usize = 80;
+
+/// The [`textDocument/inlineValue`] request is sent from the client to the
+/// server to compute inline values for a given text document that may be
+/// rendered in the editor at the end of lines.
+///
+/// Tinymist shows the values of top-level `let` bindings, evaluated with the
+/// document. Counters and states bound to variables are shown with their final
+/// values in the compiled document. Functions are omitted, and so are the
+/// bindings from the first top-level item failing to evaluate on. The values
+/// are evaluated once per version of the source and the document.
+///
+/// [`textDocument/inlineValue`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_inlineValue
+///
+/// # Compatibility
+///
+/// This request was introduced in specification version 3.17.0.
+#[derive(Debug, Clone)]
+pub struct InlineValueRequest {
+    /// The path of the document to get inline values for.
+    pub path: PathBuf,
+    /// The range of the document to get inline values for.
+    pub range: LspRange,
+}
+
+impl StatefulRequest for InlineValueRequest {
+    type Response = Vec<InlineValue>;
+
+    fn request(
+        self,
+        ctx: &mut AnalysisContext,
+        doc: Option<VersionedDocument>,
+    ) -> Option<Self::Response> {
+        let source = ctx.source_by_path(&self.path).ok()?;
+        let range = ctx.to_typst_range(self.range, &source)?;
+
+        let values = ctx.binding_values(&source, doc.as_ref(), |ctx| {
+            let doc = doc.as_ref().map(|doc| doc.document.as_ref());
+            binding_values(ctx.world(), &source, doc)
+        });
+        let values = values
+            .iter()
+            .filter(|(name, _)| name.end >= range.start && name.start <= range.end);
+        let values = values.map(|(name, text)| {
+            InlineValue::Text(InlineValueText {
+                range: ctx.to_lsp_range(name.clone(), &source),
+                text: text.clone(),
+            })
+        });
+        Some(values.collect())
+    }
+}
+
+/// Get the texts shown for the top-level bindings in the source, by the ranges
+/// of their names.
+fn binding_values(world: &dyn World, source: &Source, doc: Option<&Document>) -> BindingValues {
+    let Some((module, evaluated)) = eval_prefix(world, source) else {
+        return vec![];
+    };
+
+    let mut values = vec![];
+    for node in LinkedNode::new(source.root()).children() {
+        // The bindings after an error are not evaluated.
+        if node.offset() >= evaluated {
+            break;
+        }
+        let Some(binding) = node.cast::<ast::LetBinding>() else {
+            continue;
+        };
+        let ast::LetBindingKind::Normal(pattern) = binding.kind() else {
+            continue;
+        };
+
+        for ident in pattern.bindings() {
+            let Some(ident) = node.find(ident.span()) else {
+                continue;
+            };
+            let name = ident.text();
+            let Some(text) = binding_value(world, &module, name, doc) else {
+                continue;
+            };
+            values.push((ident.range(), format!("{name} = {text}")));
+        }
+    }
+
+    values
+}
+
+/// Evaluate the source, or the top-level items before the first error in it,
+/// so that the bindings before an error still have values. Returns the module
+/// along with the length of the evaluated text.
+fn eval_prefix(world: &dyn World, source: &Source) -> Option<(Module, usize)> {
+    let eval = |source: &Source| {
+        let mut tracer = Tracer::new();
+        let route = Route::default();
+        typst::eval::eval(world.track(), route.track(), tracer.track_mut(), source)
+    };
+
+    let mut prefix = source.clone();
+    loop {
+        let errors = match eval(&prefix) {
+            Ok(module) => return Some((module, prefix.text().len())),
+            Err(errors) => errors,
+        };
+
+        // Errors in imported files are located by the traces of them.
+        let root = LinkedNode::new(prefix.root());
+        let spans = errors.iter().flat_map(|error| {
+            std::iter::once(error.span).chain(error.trace.iter().map(|t| t.span))
+        });
+        let start = spans
+            .filter_map(|span| top_level_offset(&root, span))
+            .min()?;
+        if start >= prefix.text().len() {
+            return None;
+        }
+        prefix = Source::new(source.id(), source.text()[..start].to_owned());
+    }
+}
+
+/// Get the offset of the top-level item containing the span.
+fn top_level_offset(root: &LinkedNode, span: TypstSpan) -> Option<usize> {
+    let mut node = root.find(span)?;
+    while let Some(parent) = node.parent() {
+        if parent.parent().is_none() {
+            break;
+        }
+        let parent = parent.clone();
+        node = parent;
+    }
+    Some(node.offset())
+}
+
+/// Get the representation of the value bound to the name in the module.
+fn binding_value(
+    world: &dyn World,
+    module: &Module,
+    name: &str,
+    doc: Option<&Document>,
+) -> Option<String> {
+    let value = module.scope().get(name)?;
+    let value = match value {
+        Value::Func(..) => return None,
+        Value::Dyn(..) => final_value(world, value, doc?)?,
+        value => value.clone(),
+    };
+
+    let repr = value.repr();
+    Some(match repr.char_indices().nth(MAX_VALUE_LEN) {
+        Some((end, _)) => format!("{}…", &repr[..end]),
+        None => repr.into(),
+    })
+}
+
+/// Get the final value of a counter or a state in the document.
+fn final_value(world: &dyn World, value: &Value, doc: &Document) -> Option<Value> {
+    let Value::Dyn(value) = value else {
+        return None;
+    };
+
+    let mut locator = Locator::default();
+    let mut tracer = Tracer::new();
+    let mut engine = Engine {
+        world: world.track(),
+        route: Route::default(),
+        introspector: doc.introspector.track(),
+        locator: &mut locator,
+        tracer: tracer.track_mut(),
+    };
+    // The location only marks the call as contextual.
+    let location = doc.introspector.all().find_map(|elem| elem.location())?;
+    let context = Context::none();
+    let (context, span) = (context.track(), TypstSpan::detached());
+
+    if let Some(counter) = value.downcast::<Counter>() {
+        let state = counter.final_(&mut engine, context, span, Some(location));
+        return state.ok().map(IntoValue::into_value);
+    }
+    if let Some(state) = value.downcast::<State>() {
+        let value = state.final_(&mut engine, context, span, Some(location));
+        return value.ok();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    fn inline_values(content: &str) -> Vec<String> {
+        run_with_ctx(content, |ctx, path| {
+            let doc = typst::compile(ctx.world(), &mut Default::default()).ok();
+            let doc = doc.map(|doc| VersionedDocument {
+                version: 0,
+                document: Arc::new(doc),
+            });
+            let request = InlineValueRequest {
+                path,
+                range: LspRange::new(LspPosition::new(0, 0), LspPosition::new(u32::MAX, 0)),
+            };
+            let values = request.request(ctx, doc).unwrap();
+            values
+                .into_iter()
+                .map(|value| match value {
+                    InlineValue::Text(text) => text.text,
+                    _ => panic!("unexpected inline value {value:?}"),
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_let_values() {
+        let content = "#let total = 1 + 2\n#let (a, b) = (\"x\", 4pt)\n#let f(x) = x\n#let c = counter(\"c\")";
+        assert_eq!(
+            inline_values(content),
+            vec!["total = 3", "a = \"x\"", "b = 4pt"]
+        );
+    }
+
+    #[test]
+    fn test_counter_state_values() {
+        let content =
+            "#let c = counter(\"c\")\n#c.step()\n#c.step()\n#let s = state(\"s\", 1)\n#s.update(5)";
+        assert_eq!(inline_values(content), vec!["c = (2,)", "s = 5"]);
+    }
+
+    #[test]
+    fn test_partial_values() {
+        let content = "#let a = 1\n#let b = unknown\n#let c = 3";
+        assert_eq!(inline_values(content), vec!["a = 1"]);
+    }
+}
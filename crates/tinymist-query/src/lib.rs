@@ -31,8 +31,12 @@ mod color_presentation;
 pub use color_presentation::*;
 mod document_color;
 pub use document_color::*;
+mod document_link;
+pub use document_link::*;
 mod document_symbol;
 pub use document_symbol::*;
+mod dependency_graph;
+pub use dependency_graph::*;
 mod document_metrics;
 pub use document_metrics::*;
 mod folding_range;
@@ -45,8 +49,12 @@ mod hover;
 pub use hover::*;
 mod inlay_hint;
 pub use inlay_hint::*;
+mod inline_value;
+pub use inline_value::*;
 mod jump;
 pub use jump::*;
+mod moniker;
+pub use moniker::*;
 mod rename;
 pub use rename::*;
 mod selection_range;
@@ -65,11 +73,21 @@ mod prepare_rename;
 pub use prepare_rename::*;
 mod references;
 pub use references::*;
+mod extract_to_file;
+pub use extract_to_file::*;
+mod lint_document;
+pub use lint_document::*;
+mod reindex_workspace;
+pub use reindex_workspace::*;
+mod validate_labels;
+pub use validate_labels::*;
 
 mod lsp_typst_boundary;
 pub use lsp_typst_boundary::*;
 mod lsp_features;
 pub use lsp_features::*;
+mod overlay_world;
+pub use overlay_world::*;
 
 mod prelude;
 
@@ -138,11 +156,66 @@ mod polymorphic {
         Merged,
     }
 
+    /// How text is written to SVG.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub enum SvgTextMode {
+        /// Outline the glyphs as paths, which look the same everywhere.
+        #[default]
+        Outline,
+        /// Write text with the fonts embedded.
+        Embed,
+        /// Write text with the fonts referenced by their names, which must be
+        /// installed where the SVG is viewed.
+        Reference,
+    }
+
+    /// Where to split documents into the chapters of EPUB books.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub enum EpubSplit {
+        /// At the top-level headings.
+        #[default]
+        Heading1,
+        /// At the text of the included files.
+        Include,
+    }
+
+    /// The options of EPUB books.
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct EpubOptions {
+        /// The path to the cover image.
+        pub cover_image: Option<PathBuf>,
+        /// Where to split the document into chapters.
+        #[serde(default)]
+        pub split_by: EpubSplit,
+    }
+
     #[derive(Debug, Clone)]
     pub enum ExportKind {
         Pdf,
-        Svg { page: PageSelection },
-        Png { page: PageSelection },
+        Svg {
+            page: PageSelection,
+            text: SvgTextMode,
+        },
+        Png {
+            page: PageSelection,
+        },
+        ContactSheet {
+            columns: usize,
+            ppi: f32,
+        },
+        Pptx {
+            ppi: f32,
+            notes: bool,
+        },
+        Docx {
+            pandoc: PathBuf,
+        },
+        Epub {
+            opts: EpubOptions,
+        },
     }
 
     impl ExportKind {
@@ -150,7 +223,10 @@ mod polymorphic {
             match self {
                 Self::Pdf => "pdf",
                 Self::Svg { .. } => "svg",
-                Self::Png { .. } => "png",
+                Self::Png { .. } | Self::ContactSheet { .. } => "png",
+                Self::Pptx { .. } => "pptx",
+                Self::Docx { .. } => "docx",
+                Self::Epub { .. } => "epub",
             }
         }
     }
@@ -182,6 +258,18 @@ mod polymorphic {
         pub font_paths: Vec<PathBuf>,
         pub inputs: Dict,
         pub estimated_memory_usage: HashMap<String, usize>,
+        /// The statistics of requests handled by the server.
+        #[serde(default)]
+        pub requests: Vec<RequestStats>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RequestStats {
+        pub method: String,
+        pub count: u64,
+        pub p50_ms: f64,
+        pub p95_ms: f64,
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -201,16 +289,20 @@ mod polymorphic {
         GotoDeclaration(GotoDeclarationRequest),
         References(ReferencesRequest),
         InlayHint(InlayHintRequest),
+        InlineValue(InlineValueRequest),
         DocumentColor(DocumentColorRequest),
+        DocumentLink(DocumentLinkRequest),
         ColorPresentation(ColorPresentationRequest),
         CodeAction(CodeActionRequest),
         CodeLens(CodeLensRequest),
         Completion(CompletionRequest),
         SignatureHelp(SignatureHelpRequest),
+        Moniker(MonikerRequest),
         Rename(RenameRequest),
         PrepareRename(PrepareRenameRequest),
         DocumentSymbol(DocumentSymbolRequest),
         Symbol(SymbolRequest),
+        WorkspaceSymbolResolve(WorkspaceSymbolResolveRequest),
         SemanticTokensFull(SemanticTokensFullRequest),
         SemanticTokensDelta(SemanticTokensDeltaRequest),
         Formatting(FormattingRequest),
@@ -219,6 +311,11 @@ mod polymorphic {
         InteractCodeContext(InteractCodeContextRequest),
 
         DocumentMetrics(DocumentMetricsRequest),
+        ValidateLabels(ValidateLabelsRequest),
+        LintDocument(LintDocumentRequest),
+        ExtractToFile(ExtractToFileRequest),
+        DependencyGraph(DependencyGraphRequest),
+        ReindexWorkspace(ReindexWorkspaceRequest),
         ServerInfo(ServerInfoRequest),
     }
 
@@ -233,16 +330,20 @@ mod polymorphic {
                 Self::GotoDeclaration(..) => PinnedFirst,
                 Self::References(..) => PinnedFirst,
                 Self::InlayHint(..) => Unique,
+                Self::InlineValue(..) => PinnedFirst,
                 Self::DocumentColor(..) => PinnedFirst,
+                Self::DocumentLink(..) => PinnedFirst,
                 Self::ColorPresentation(..) => ContextFreeUnique,
                 Self::CodeAction(..) => Unique,
                 Self::CodeLens(..) => Unique,
                 Self::Completion(..) => Mergeable,
                 Self::SignatureHelp(..) => PinnedFirst,
+                Self::Moniker(..) => PinnedFirst,
                 Self::Rename(..) => Mergeable,
                 Self::PrepareRename(..) => Mergeable,
                 Self::DocumentSymbol(..) => ContextFreeUnique,
                 Self::Symbol(..) => Mergeable,
+                Self::WorkspaceSymbolResolve(..) => ContextFreeUnique,
                 Self::SemanticTokensFull(..) => ContextFreeUnique,
                 Self::SemanticTokensDelta(..) => ContextFreeUnique,
                 Self::Formatting(..) => ContextFreeUnique,
@@ -251,6 +352,11 @@ mod polymorphic {
                 Self::InteractCodeContext(..) => PinnedFirst,
 
                 Self::DocumentMetrics(..) => PinnedFirst,
+                Self::ValidateLabels(..) => PinnedFirst,
+                Self::LintDocument(..) => PinnedFirst,
+                Self::ExtractToFile(..) => Mergeable,
+                Self::DependencyGraph(..) => PinnedFirst,
+                Self::ReindexWorkspace(..) => Unique,
                 Self::ServerInfo(..) => Mergeable,
             }
         }
@@ -264,16 +370,20 @@ mod polymorphic {
                 Self::GotoDeclaration(req) => &req.path,
                 Self::References(req) => &req.path,
                 Self::InlayHint(req) => &req.path,
+                Self::InlineValue(req) => &req.path,
                 Self::DocumentColor(req) => &req.path,
+                Self::DocumentLink(req) => &req.path,
                 Self::ColorPresentation(req) => &req.path,
                 Self::CodeAction(req) => &req.path,
                 Self::CodeLens(req) => &req.path,
                 Self::Completion(req) => &req.path,
                 Self::SignatureHelp(req) => &req.path,
+                Self::Moniker(req) => &req.path,
                 Self::Rename(req) => &req.path,
                 Self::PrepareRename(req) => &req.path,
                 Self::DocumentSymbol(req) => &req.path,
                 Self::Symbol(..) => return None,
+                Self::WorkspaceSymbolResolve(req) => &req.path,
                 Self::SemanticTokensFull(req) => &req.path,
                 Self::SemanticTokensDelta(req) => &req.path,
                 Self::Formatting(req) => &req.path,
@@ -282,6 +392,11 @@ mod polymorphic {
                 Self::InteractCodeContext(req) => &req.path,
 
                 Self::DocumentMetrics(req) => &req.path,
+                Self::ValidateLabels(req) => &req.path,
+                Self::LintDocument(req) => &req.path,
+                Self::ExtractToFile(req) => &req.path,
+                Self::DependencyGraph(req) => &req.path,
+                Self::ReindexWorkspace(..) => return None,
                 Self::ServerInfo(..) => return None,
             })
         }
@@ -296,16 +411,20 @@ mod polymorphic {
         GotoDeclaration(Option<GotoDeclarationResponse>),
         References(Option<Vec<LspLocation>>),
         InlayHint(Option<Vec<InlayHint>>),
+        InlineValue(Option<Vec<lsp_types::InlineValue>>),
         DocumentColor(Option<Vec<ColorInformation>>),
+        DocumentLink(Option<Vec<lsp_types::DocumentLink>>),
         ColorPresentation(Option<Vec<ColorPresentation>>),
         CodeAction(Option<Vec<CodeActionOrCommand>>),
         CodeLens(Option<Vec<CodeLens>>),
         Completion(Option<CompletionResponse>),
         SignatureHelp(Option<SignatureHelp>),
+        Moniker(Option<Vec<lsp_types::Moniker>>),
         PrepareRename(Option<PrepareRenameResponse>),
         Rename(Option<WorkspaceEdit>),
         DocumentSymbol(Option<DocumentSymbolResponse>),
-        Symbol(Option<Vec<SymbolInformation>>),
+        Symbol(Option<lsp_types::WorkspaceSymbolResponse>),
+        WorkspaceSymbolResolve(Option<lsp_types::WorkspaceSymbol>),
         SemanticTokensFull(Option<SemanticTokensResult>),
         SemanticTokensDelta(Option<SemanticTokensFullDeltaResult>),
         Formatting(Option<Vec<TextEdit>>),
@@ -314,6 +433,11 @@ mod polymorphic {
         InteractCodeContext(Option<Vec<InteractCodeContextResponse>>),
 
         DocumentMetrics(Option<DocumentMetricsResponse>),
+        ValidateLabels(Option<DiagnosticsMap>),
+        LintDocument(Option<DiagnosticsMap>),
+        ExtractToFile(Option<WorkspaceEdit>),
+        DependencyGraph(Option<DependencyGraph>),
+        ReindexWorkspace(Option<WorkspaceIndexStats>),
         ServerInfo(Option<HashMap<String, ServerInfoResponse>>),
     }
 }
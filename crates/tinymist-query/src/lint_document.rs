@@ -0,0 +1,237 @@
+use std::ops::Range;
+
+use lsp_types::NumberOrString;
+
+use crate::{
+    prelude::*,
+    validate_labels::{collect_sources, visit_nodes},
+    DiagnosticsMap, SemanticRequest,
+};
+
+/// The maximum number of characters in a line before `line-length` reports it.
+pub const MAX_LINE_LENGTH: usize = 100;
+
+/// The ids of the lint rules, which are all enabled by default.
+pub const LINT_RULES: &[&str] = &[
+    "raw-trailing-whitespace",
+    "heading-skip",
+    "figure-caption",
+    "table-header",
+    "line-length",
+];
+
+/// The lint rules to toggle, keyed by their ids. Rules not in the map are
+/// enabled.
+pub type LintRules = HashMap<String, bool>;
+
+/// A request to lint a document for style issues, reporting the findings as
+/// hints or information keyed by file, with the rule ids as codes.
+///
+/// The sources included or imported by the document are linted as well.
+/// - `raw-trailing-whitespace`: lines of raw blocks ending with whitespace.
+/// - `heading-skip`: headings more than one level deeper than the previous
+///   one in the same file, e.g. `===` after `=`.
+/// - `figure-caption`: figures without captions.
+/// - `table-header`: tables without `table.header`.
+/// - `line-length`: lines longer than [`MAX_LINE_LENGTH`] characters.
+#[derive(Debug, Clone)]
+pub struct LintDocumentRequest {
+    /// The path of the document to lint.
+    pub path: PathBuf,
+    /// The rules to toggle.
+    pub rules: LintRules,
+}
+
+impl SemanticRequest for LintDocumentRequest {
+    type Response = DiagnosticsMap;
+
+    fn request(self, ctx: &mut AnalysisContext) -> Option<Self::Response> {
+        let source = ctx.source_by_path(&self.path).ok()?;
+        let enabled = |rule: &str| self.rules.get(rule).copied().unwrap_or(true);
+
+        let mut diagnostics = DiagnosticsMap::new();
+        for (path, source) in collect_sources(ctx, source) {
+            let mut findings = vec![];
+            let mut last_depth = None;
+            visit_nodes(&LinkedNode::new(source.root()), &mut |node| {
+                if node.kind() == SyntaxKind::Raw && enabled("raw-trailing-whitespace") {
+                    lint_raw(&source, node, &mut findings);
+                }
+                if let Some(heading) = node.cast::<ast::Heading>() {
+                    let depth = heading.depth().get();
+                    let skipped = last_depth.filter(|last| depth > last + 1);
+                    if let Some(last) = skipped.filter(|_| enabled("heading-skip")) {
+                        let message = format!("heading of level {depth} follows level {last}");
+                        findings.push((node.range(), "heading-skip", message));
+                    }
+                    last_depth = Some(depth);
+                }
+                if let Some(call) = node.cast::<ast::FuncCall>() {
+                    lint_call(call, node, &enabled, &mut findings);
+                }
+            });
+            if enabled("line-length") {
+                lint_lines(&source, &mut findings);
+            }
+
+            let mut file_diagnostics = vec![];
+            for (range, rule, message) in findings {
+                let severity = match rule {
+                    "raw-trailing-whitespace" | "line-length" => LspSeverity::HINT,
+                    _ => LspSeverity::INFORMATION,
+                };
+                file_diagnostics.push(LspDiagnostic {
+                    range: ctx.to_lsp_range(range, &source),
+                    severity: Some(severity),
+                    code: Some(NumberOrString::String(rule.to_owned())),
+                    message,
+                    source: Some("tinymist".to_owned()),
+                    ..Default::default()
+                });
+            }
+            file_diagnostics.sort_by_key(|d| (d.range.start.line, d.range.start.character));
+
+            if !file_diagnostics.is_empty() {
+                diagnostics.insert(path_to_url(&path).ok()?, file_diagnostics);
+            }
+        }
+
+        Some(diagnostics)
+    }
+}
+
+type Finding = (Range<usize>, &'static str, String);
+
+/// Find the lines of a raw block ending with whitespace.
+fn lint_raw(source: &Source, node: &LinkedNode, findings: &mut Vec<Finding>) {
+    let start = node.offset();
+    let text = &source.text()[node.range()];
+    let mut offset = start;
+    for line in text.split_inclusive('\n') {
+        // The last line ends with the closing backticks.
+        if let Some(content) = line.strip_suffix('\n') {
+            let content = content.strip_suffix('\r').unwrap_or(content);
+            let trimmed = content.trim_end();
+            if trimmed.len() < content.len() {
+                let range = offset + trimmed.len()..offset + content.len();
+                let message = "trailing whitespace in raw block".to_owned();
+                findings.push((range, "raw-trailing-whitespace", message));
+            }
+        }
+        offset += line.len();
+    }
+}
+
+/// Find the figures without captions and the tables without headers.
+fn lint_call(
+    call: ast::FuncCall,
+    node: &LinkedNode,
+    enabled: &impl Fn(&str) -> bool,
+    findings: &mut Vec<Finding>,
+) {
+    let ast::Expr::Ident(callee) = call.callee() else {
+        return;
+    };
+    let mut args = call.args().items();
+    match callee.as_str() {
+        "figure" if enabled("figure-caption") => {
+            let has_caption = args.any(|arg| match arg {
+                ast::Arg::Named(named) => named.name().as_str() == "caption",
+                _ => false,
+            });
+            if !has_caption {
+                let message = "figure has no caption".to_owned();
+                findings.push((node.range(), "figure-caption", message));
+            }
+        }
+        "table" if enabled("table-header") => {
+            let has_header = args.any(|arg| {
+                let ast::Arg::Pos(ast::Expr::FuncCall(call)) = arg else {
+                    return false;
+                };
+                let ast::Expr::FieldAccess(access) = call.callee() else {
+                    return false;
+                };
+                access.field().as_str() == "header"
+            });
+            if !has_header {
+                let message = "table has no header, add one with `table.header`".to_owned();
+                findings.push((node.range(), "table-header", message));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Find the lines longer than [`MAX_LINE_LENGTH`] characters, reporting the
+/// overlong part of them.
+fn lint_lines(source: &Source, findings: &mut Vec<Finding>) {
+    let mut offset = 0;
+    for line in source.text().split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        if let Some((overlong, _)) = content.char_indices().nth(MAX_LINE_LENGTH) {
+            let chars = content.chars().count();
+            let range = offset + overlong..offset + content.len();
+            let message = format!("line is {chars} characters long, over {MAX_LINE_LENGTH}");
+            findings.push((range, "line-length", message));
+        }
+        offset += line.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    fn lint(content: &str, rules: LintRules) -> Vec<(String, LspRange)> {
+        run_with_ctx(content, |ctx, path| {
+            let request = LintDocumentRequest { path, rules };
+            let diagnostics = request.request(ctx).unwrap();
+            let main = path_to_url(Path::new("/main.typ")).unwrap();
+            let findings = diagnostics.get(&main).cloned().unwrap_or_default();
+            (findings.into_iter())
+                .map(|d| match d.code {
+                    Some(NumberOrString::String(code)) => (code, d.range),
+                    code => panic!("unexpected code {code:?}"),
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_heading_skip() {
+        let content = "= Intro\n=== Details\n== Methods";
+        let only = |rule: &str| {
+            let rules = LINT_RULES.iter().map(|r| (r.to_string(), *r == rule));
+            rules.collect::<LintRules>()
+        };
+
+        let range = LspRange::new(LspPosition::new(1, 0), LspPosition::new(1, 11));
+        assert_eq!(
+            lint(content, only("heading-skip")),
+            vec![("heading-skip".to_owned(), range)]
+        );
+        assert_eq!(lint(content, only("line-length")), vec![]);
+    }
+
+    #[test]
+    fn test_rules() {
+        let content = format!(
+            "```py\nx = 1  \n```\n#figure(table(columns: 2)[a][b])\n{}",
+            "word ".repeat(30)
+        );
+        let rules: Vec<_> = (lint(&content, LintRules::new()).into_iter())
+            .map(|(rule, _)| rule)
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                "raw-trailing-whitespace",
+                "figure-caption",
+                "table-header",
+                "line-length"
+            ]
+        );
+    }
+}
@@ -117,6 +117,18 @@ pub fn url_to_path(uri: Url) -> PathBuf {
     uri.to_file_path().unwrap()
 }
 
+/// Get the byte offset of the end of a line before its line break, e.g. before
+/// the `\r` of a `\r\n`.
+///
+/// LSP positions beyond the content of a line refer to its end, while Typst
+/// counts the line break as a part of the line. Clamping the positions to the
+/// content keeps them from splitting a `\r\n`.
+fn line_content_end(source: &typst::syntax::Source, line: usize) -> Option<TypstOffset> {
+    let range = source.line_to_range(line)?;
+    let content = source.text()[range.clone()].trim_end_matches(typst::syntax::is_newline);
+    Some(range.start + content.len())
+}
+
 pub mod lsp_to_typst {
     use typst::syntax::Source;
 
@@ -151,7 +163,8 @@ pub mod lsp_to_typst {
             return Some(typst_source.len_bytes());
         }
 
-        match lsp_position_encoding {
+        let line_end = line_content_end(typst_source, lsp_position.line as usize)?;
+        let offset = match lsp_position_encoding {
             LspPositionEncoding::Utf8 => {
                 let line_index = lsp_position.line as usize;
                 let column_index = lsp_position.character as usize;
@@ -185,7 +198,9 @@ pub mod lsp_to_typst {
 
                 typst_source.utf16_to_byte(utf16_offset)
             }
-        }
+        };
+        // Columns beyond the end of the line fall back to its end.
+        Some(offset.map_or(line_end, |offset| offset.min(line_end)))
     }
 
     pub fn range(
@@ -227,6 +242,9 @@ pub mod typst_to_lsp {
         typst_source: &Source,
     ) -> LspPosition {
         let line_index = typst_source.byte_to_line(typst_offset).unwrap();
+        // An offset in a line break, i.e. between `\r` and `\n`, is at the end
+        // of the line for clients.
+        let typst_offset = typst_offset.min(line_content_end(typst_source, line_index).unwrap());
         let column_index = typst_source.byte_to_column(typst_offset).unwrap();
 
         let lsp_line = line_index as u32;
@@ -320,6 +338,13 @@ pub mod typst_to_lsp {
                 }
             }),
             text_edit: Some(text_edit),
+            documentation: typst_completion.docs.as_ref().map(|docs| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: docs.to_string(),
+                })
+            }),
+            data: (typst_completion.data.as_ref()).and_then(|data| serde_json::to_value(data).ok()),
             insert_text_format: Some(InsertTextFormat::SNIPPET),
             command: typst_completion.command.as_ref().map(|c| Command {
                 command: c.to_string(),
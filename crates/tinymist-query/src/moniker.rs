@@ -0,0 +1,181 @@
+use lsp_types::{Moniker, MonikerKind, UniquenessLevel};
+
+use crate::{analysis::find_definition, prelude::*, syntax::get_deref_target};
+
+/// The scheme of monikers provided by tinymist.
+pub const MONIKER_SCHEME: &str = "typst";
+
+/// The [`textDocument/moniker`] request is sent from the client to the server
+/// to get the symbol monikers for a given text document position.
+///
+/// An array of Moniker types is returned as response to indicate possible
+/// monikers at the given location. If no monikers can be calculated, an empty
+/// array or `null` should be returned.
+///
+/// The identifier is stable across requests and sessions:
+/// + `preview/cetz@0.2.2/src/draw.typ#line` for symbols in packages,
+/// + `local/chapters/intro.typ#title` for symbols in the workspace,
+/// + `std#text` for builtin symbols.
+///
+/// [`textDocument/moniker`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_moniker
+///
+/// # Compatibility
+///
+/// This request was introduced in specification version 3.16.0.
+#[derive(Debug, Clone)]
+pub struct MonikerRequest {
+    /// The path of the document to request for.
+    pub path: PathBuf,
+    /// The source code position to request for.
+    pub position: LspPosition,
+}
+
+impl StatefulRequest for MonikerRequest {
+    type Response = Vec<Moniker>;
+
+    fn request(
+        self,
+        ctx: &mut AnalysisContext,
+        doc: Option<VersionedDocument>,
+    ) -> Option<Self::Response> {
+        let source = ctx.source_by_path(&self.path).ok()?;
+        let offset = ctx.to_typst_pos(self.position, &source)?;
+        let cursor = offset + 1;
+
+        let ast_node = LinkedNode::new(source.root()).leaf_at(cursor)?;
+        let deref_target = get_deref_target(ast_node, cursor)?;
+
+        let def = find_definition(ctx, source.clone(), doc.as_ref(), deref_target)?;
+
+        let moniker = match def.def_at {
+            Some((fid, _)) => {
+                let kind = if fid == source.id() {
+                    MonikerKind::Export
+                } else {
+                    MonikerKind::Import
+                };
+                let unique = if fid.package().is_some() {
+                    UniquenessLevel::Global
+                } else {
+                    UniquenessLevel::Project
+                };
+                Moniker {
+                    scheme: MONIKER_SCHEME.to_owned(),
+                    identifier: moniker_identifier(fid, &def.name),
+                    unique,
+                    kind: Some(kind),
+                }
+            }
+            None if def.value.is_some() && !def.name.is_empty() => Moniker {
+                scheme: MONIKER_SCHEME.to_owned(),
+                identifier: format!("std#{}", def.name),
+                unique: UniquenessLevel::Scheme,
+                kind: Some(MonikerKind::Import),
+            },
+            None => return None,
+        };
+
+        log::debug!("moniker: {moniker:?}");
+        Some(vec![moniker])
+    }
+}
+
+/// Create a stable identifier for a symbol defined in the given file.
+fn moniker_identifier(fid: TypstFileId, name: &str) -> String {
+    let path = fid.vpath().as_rootless_path().to_string_lossy();
+    // Normalize windows paths so that the identifier is platform independent.
+    let path = path.replace('\\', "/");
+    let module = match fid.package() {
+        Some(spec) => format!("{}/{}@{}/{path}", spec.namespace, spec.name, spec.version),
+        None => format!("local/{path}"),
+    };
+
+    if name.is_empty() {
+        module
+    } else {
+        format!("{module}#{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    fn moniker_at(ctx: &mut AnalysisContext, path: &Path, cursor: usize) -> Option<Moniker> {
+        let source = ctx.source_by_path(path).unwrap();
+        let request = MonikerRequest {
+            path: path.to_owned(),
+            position: ctx.to_lsp_pos(cursor, &source),
+        };
+        request.request(ctx, None)?.into_iter().next()
+    }
+
+    #[test]
+    fn test_stable() {
+        let content = "#let x = 1;\n#x";
+        run_with_ctx(content, |ctx, path| {
+            let usage = content.rfind('x').unwrap();
+            let first = moniker_at(ctx, &path, usage).unwrap();
+            assert_eq!(moniker_at(ctx, &path, usage), Some(first.clone()));
+            assert_eq!(first.scheme, "typst");
+            assert_eq!(first.identifier, "local/s0.typ#x");
+            assert_eq!(first.unique, UniquenessLevel::Project);
+            assert_eq!(first.kind, Some(MonikerKind::Export));
+
+            // The definition and its usages share the moniker.
+            let def = content.find('x').unwrap();
+            assert_eq!(moniker_at(ctx, &path, def), Some(first));
+        });
+    }
+
+    #[test]
+    fn test_imported() {
+        let content = "// path: /lib/util.typ\n#let helper() = 1\n-----\n\
+                       #import \"lib/util.typ\": helper\n#helper()";
+        run_with_ctx(content, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let usage = source.text().rfind("helper").unwrap();
+            let moniker = moniker_at(ctx, &path, usage).unwrap();
+            // The identifier is the one of the definition in the other file.
+            assert_eq!(moniker.identifier, "local/lib/util.typ#helper");
+            assert_eq!(moniker.unique, UniquenessLevel::Project);
+            assert_eq!(moniker.kind, Some(MonikerKind::Import));
+        });
+    }
+
+    #[test]
+    fn test_builtin() {
+        run_with_ctx("#text", |ctx, path| {
+            let moniker = moniker_at(ctx, &path, 2).unwrap();
+            assert_eq!(moniker.identifier, "std#text");
+            assert_eq!(moniker.unique, UniquenessLevel::Scheme);
+            assert_eq!(moniker.kind, Some(MonikerKind::Import));
+        });
+    }
+
+    #[test]
+    fn test_package() {
+        // The packages are not downloaded by the tests, so the identifiers of
+        // their files are checked directly.
+        let spec: PackageSpec = "@preview/cetz:0.2.2".parse().unwrap();
+        let fid = TypstFileId::new(Some(spec.clone()), VirtualPath::new("src/draw.typ"));
+        assert_eq!(
+            moniker_identifier(fid, "line"),
+            "preview/cetz@0.2.2/src/draw.typ#line"
+        );
+        // Another version of the package is another symbol.
+        let other: PackageSpec = "@preview/cetz:0.2.1".parse().unwrap();
+        let other = TypstFileId::new(Some(other), VirtualPath::new("src/draw.typ"));
+        assert_ne!(
+            moniker_identifier(other, "line"),
+            moniker_identifier(fid, "line")
+        );
+        // The module itself is identified without a name.
+        let lib = TypstFileId::new(Some(spec), VirtualPath::new("src/lib.typ"));
+        assert_eq!(
+            moniker_identifier(lib, ""),
+            "preview/cetz@0.2.2/src/lib.typ"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! A world delegating to a base world, used to compile variants of a document,
+//! e.g. with another main source or other `sys.inputs`.
+
+use comemo::Prehashed;
+use typst::diag::FileResult;
+use typst::foundations::{Bytes, Datetime};
+use typst::syntax::{FileId, Source};
+use typst::text::{Font, FontBook};
+use typst::{Library, World};
+
+/// The hooks observing or altering the results of an [`OverlayWorld`]. All of
+/// them return the results of the base world by default.
+pub trait WorldHooks {
+    /// Called with the source of the file, after the main source is replaced.
+    fn source(&self, _id: FileId, source: FileResult<Source>) -> FileResult<Source> {
+        source
+    }
+
+    /// Called with the content of the file.
+    fn file(&self, _id: FileId, file: FileResult<Bytes>) -> FileResult<Bytes> {
+        file
+    }
+
+    /// Called with the current date of the base world.
+    fn today(&self, today: Option<Datetime>) -> Option<Datetime> {
+        today
+    }
+}
+
+impl WorldHooks for () {}
+
+/// A world delegating to a base world, optionally replacing its main source
+/// and its library, and passing the results through the hooks.
+pub struct OverlayWorld<'a, H = ()> {
+    base: &'a dyn World,
+    library: Option<Prehashed<Library>>,
+    main: Option<Source>,
+    /// The hooks of the world.
+    pub hooks: H,
+}
+
+impl<'a> OverlayWorld<'a> {
+    /// Create a world delegating everything to the base world.
+    pub fn new(base: &'a dyn World) -> Self {
+        Self {
+            base,
+            library: None,
+            main: None,
+            hooks: (),
+        }
+    }
+}
+
+impl<'a, H> OverlayWorld<'a, H> {
+    /// Replace the main source, which is also returned for its file id.
+    pub fn with_main(self, main: Source) -> Self {
+        Self {
+            main: Some(main),
+            ..self
+        }
+    }
+
+    /// Replace the library, e.g. to change `sys.inputs`.
+    pub fn with_library(self, library: Library) -> Self {
+        Self {
+            library: Some(Prehashed::new(library)),
+            ..self
+        }
+    }
+
+    /// Pass the results through the hooks.
+    pub fn with_hooks<G>(self, hooks: G) -> OverlayWorld<'a, G> {
+        OverlayWorld {
+            base: self.base,
+            library: self.library,
+            main: self.main,
+            hooks,
+        }
+    }
+}
+
+impl<H: WorldHooks> World for OverlayWorld<'_, H> {
+    fn library(&self) -> &Prehashed<Library> {
+        self.library.as_ref().unwrap_or_else(|| self.base.library())
+    }
+
+    fn book(&self) -> &Prehashed<FontBook> {
+        self.base.book()
+    }
+
+    fn main(&self) -> Source {
+        self.main.clone().unwrap_or_else(|| self.base.main())
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        let source = match &self.main {
+            Some(main) if main.id() == id => Ok(main.clone()),
+            _ => self.base.source(id),
+        };
+        self.hooks.source(id, source)
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        self.hooks.file(id, self.base.file(id))
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        self.base.font(index)
+    }
+
+    fn today(&self, offset: Option<i64>) -> Option<Datetime> {
+        self.hooks.today(self.base.today(offset))
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
     analysis::{find_definition, DefinitionLink},
     prelude::*,
+    rename::{find_label_locations, label_name},
     syntax::get_deref_target,
 };
 use log::debug;
@@ -50,6 +51,16 @@ impl StatefulRequest for PrepareRenameRequest {
         let use_site = deref_target.node().clone();
         let origin_selection_range = ctx.to_lsp_range(use_site.range(), &source);
 
+        // Labels are renamed by name in all files, if they are defined in some.
+        if let Some(label) = label_name(&deref_target) {
+            let locations = find_label_locations(ctx, source.id(), &label);
+            locations.iter().find(|(_, is_def)| *is_def)?;
+            return Some(PrepareRenameResponse::RangeWithPlaceholder {
+                range: origin_selection_range,
+                placeholder: label.to_string(),
+            });
+        }
+
         let lnk = find_definition(ctx, source.clone(), doc.as_ref(), deref_target)?;
         validate_renaming_definition(&lnk)?;
 
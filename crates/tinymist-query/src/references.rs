@@ -1,4 +1,5 @@
 use log::debug;
+use serde::Deserialize;
 
 use crate::{
     prelude::*,
@@ -17,6 +18,21 @@ pub struct ReferencesRequest {
     pub path: PathBuf,
     /// The source code position to request for.
     pub position: LspPosition,
+    /// Whether to include the declaration of the symbol.
+    pub include_declaration: bool,
+    /// The files to search for references.
+    pub scope: ReferencesScope,
+}
+
+/// The files searched for references, configured by `referencesScope`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReferencesScope {
+    /// Search the requested file only, which is faster in large workspaces.
+    File,
+    /// Search the files depending on the definition as well.
+    #[default]
+    Workspace,
 }
 
 impl SemanticRequest for ReferencesRequest {
@@ -32,7 +48,13 @@ impl SemanticRequest for ReferencesRequest {
         let deref_target = get_deref_target(ast_node, cursor)?;
 
         let def_use = ctx.def_use(source.clone())?;
-        let locations = find_references(ctx, def_use, deref_target, ctx.position_encoding())?;
+        let locations = find_references(
+            ctx,
+            def_use,
+            deref_target,
+            self.scope,
+            self.include_declaration,
+        )?;
 
         debug!("references: {locations:?}");
         Some(locations)
@@ -43,7 +65,8 @@ pub(crate) fn find_references(
     ctx: &mut AnalysisContext<'_>,
     def_use: Arc<crate::analysis::DefUseInfo>,
     deref_target: DerefTarget<'_>,
-    position_encoding: PositionEncoding,
+    scope: ReferencesScope,
+    include_declaration: bool,
 ) -> Option<Vec<LspLocation>> {
     let node = match deref_target {
         DerefTarget::VarAccess(node) => node,
@@ -96,18 +119,42 @@ pub(crate) fn find_references(
         range: def.range.clone(),
     };
 
+    let position_encoding = ctx.position_encoding();
+
+    // The definition in another file is out of the file scope, along with the
+    // references in other files.
+    if scope == ReferencesScope::File && def_fid != cur_fid {
+        let (id, _def) = def_use.get_def(def_fid, &def_ident)?;
+        let cur_source = ctx.source_by_id(cur_fid).ok()?;
+        let uri = path_to_url(&ctx.path_for_id(cur_fid).ok()?).ok()?;
+        let references = def_use.get_refs(id).map(|r| LspLocation {
+            uri: uri.clone(),
+            range: typst_to_lsp::range(r.range.clone(), &cur_source, position_encoding),
+        });
+        return Some(references.collect());
+    }
+
     let def_source = ctx.source_by_id(def_fid).ok()?;
-    let root_def_use = ctx.def_use(def_source)?;
+    let root_def_use = ctx.def_use(def_source.clone())?;
     let root_def_id = root_def_use.get_def(def_fid, &def_ident)?.0;
 
-    find_references_root(
+    let declaration = include_declaration.then(|| {
+        let uri = path_to_url(&ctx.path_for_id(def_fid).ok()?).ok()?;
+        let range = typst_to_lsp::range(def_ident.range.clone(), &def_source, position_encoding);
+        Some(LspLocation { uri, range })
+    });
+
+    let mut references = find_references_root(
         ctx,
         root_def_use,
         def_fid,
         root_def_id,
         def_ident,
+        scope == ReferencesScope::Workspace,
         position_encoding,
-    )
+    )?;
+    references.extend(declaration.flatten());
+    Some(references)
 }
 
 pub(crate) fn find_references_root(
@@ -116,6 +163,7 @@ pub(crate) fn find_references_root(
     def_fid: TypstFileId,
     def_id: DefId,
     def_ident: IdentRef,
+    search_dependents: bool,
     position_encoding: PositionEncoding,
 ) -> Option<Vec<LspLocation>> {
     let def_source = ctx.source_by_id(def_fid).ok()?;
@@ -135,7 +183,7 @@ pub(crate) fn find_references_root(
         })
         .collect::<Vec<_>>();
 
-    if def_use.is_exported(def_id) {
+    if search_dependents && def_use.is_exported(def_id) {
         // Find dependents
         let mut ctx = ctx.fork_for_search();
         ctx.push_dependents(def_fid);
@@ -176,44 +224,100 @@ mod tests {
     use super::*;
     use crate::{tests::*, url_to_path};
 
+    fn find_refs(
+        ctx: &mut AnalysisContext,
+        path: PathBuf,
+        include_declaration: bool,
+        scope: ReferencesScope,
+    ) -> Option<Vec<String>> {
+        let source = ctx.source_by_path(&path).unwrap();
+
+        let request = ReferencesRequest {
+            path: path.clone(),
+            position: find_test_position(&source),
+            include_declaration,
+            scope,
+        };
+
+        let mut result = request.request(ctx)?;
+        // sort
+        result.sort_by(|a, b| match a.range.start.cmp(&b.range.start) {
+            std::cmp::Ordering::Equal => a.range.end.cmp(&b.range.end),
+            e => e,
+        });
+
+        let result = result
+            .into_iter()
+            .map(|l| {
+                let fp = unix_slash(&url_to_path(l.uri));
+                let fp = fp.strip_prefix("C:").unwrap_or(&fp);
+                format!(
+                    "{fp}@{}:{}:{}:{}",
+                    l.range.start.line,
+                    l.range.start.character,
+                    l.range.end.line,
+                    l.range.end.character
+                )
+            })
+            .collect();
+        Some(result)
+    }
+
     #[test]
     fn test() {
         // goto_definition
         snapshot_testing("references", &|world, path| {
-            let source = world.source_by_path(&path).unwrap();
+            let result = find_refs(world, path, false, ReferencesScope::Workspace);
+            assert_snapshot!(JsonRepr::new_pure(result));
+        });
+    }
 
-            let request = ReferencesRequest {
-                path: path.clone(),
-                position: find_test_position(&source),
-            };
+    const CROSS_MODULE: &str = r#"#import "base.typ": *
+#x
+-----
+// path: base.typ
+#let /* ident after */ x = 1;
+#x"#;
 
-            let result = request.request(world);
-            // sort
-            let result = result.map(|mut e| {
-                e.sort_by(|a, b| match a.range.start.cmp(&b.range.start) {
-                    std::cmp::Ordering::Equal => a.range.end.cmp(&b.range.end),
-                    e => e,
-                });
-                e
-            });
-
-            let result = result.map(|v| {
-                v.into_iter()
-                    .map(|l| {
-                        let fp = unix_slash(&url_to_path(l.uri));
-                        let fp = fp.strip_prefix("C:").unwrap_or(&fp);
-                        format!(
-                            "{fp}@{}:{}:{}:{}",
-                            l.range.start.line,
-                            l.range.start.character,
-                            l.range.end.line,
-                            l.range.end.character
-                        )
-                    })
-                    .collect::<Vec<_>>()
-            });
+    #[test]
+    fn test_include_declaration() {
+        run_with_ctx(CROSS_MODULE, |ctx, path| {
+            let refs = find_refs(ctx, path.clone(), false, ReferencesScope::Workspace).unwrap();
+            assert_eq!(refs, ["/base.typ@1:1:1:2", "/s0.typ@1:1:1:2"]);
 
-            assert_snapshot!(JsonRepr::new_pure(result));
+            let refs = find_refs(ctx, path, true, ReferencesScope::Workspace).unwrap();
+            assert_eq!(
+                refs,
+                [
+                    "/base.typ@0:23:0:24",
+                    "/base.typ@1:1:1:2",
+                    "/s0.typ@1:1:1:2"
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_file_scope() {
+        run_with_ctx(CROSS_MODULE, |ctx, path| {
+            let refs = find_refs(ctx, path.clone(), false, ReferencesScope::File).unwrap();
+            assert_eq!(refs, ["/base.typ@1:1:1:2"]);
+
+            let refs = find_refs(ctx, path, true, ReferencesScope::File).unwrap();
+            assert_eq!(refs, ["/base.typ@0:23:0:24", "/base.typ@1:1:1:2"]);
+        });
+
+        // The definition in another file is out of the scope.
+        let source = r#"// path: base.typ
+#let x = 1;
+#x
+-----
+#import "base.typ": *
+#x /* ident */
+#x"#;
+        run_with_ctx(source, |ctx, path| {
+            let refs = find_refs(ctx, path, true, ReferencesScope::File).unwrap();
+            assert_eq!(refs, ["/s1.typ@1:1:1:2", "/s1.typ@2:1:2:2"]);
         });
     }
 }
@@ -0,0 +1,154 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{analysis::IndexProgress, prelude::*, SemanticRequest};
+
+/// The statistics of a rebuilt workspace index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceIndexStats {
+    /// The number of indexed source files.
+    pub files: usize,
+    /// The number of symbols in the files.
+    pub symbols: usize,
+    /// The number of labels in the files.
+    pub labels: usize,
+    /// The time taken to rebuild the index.
+    pub duration_ms: u64,
+    /// Whether the rebuild is cancelled, in which case the statistics are of
+    /// the files indexed before it and the last index is kept.
+    pub cancelled: bool,
+}
+
+/// A request to rebuild the index of the workspace from scratch, which picks up
+/// the files changed without notifications to the server.
+///
+/// The files ignored by `indexIgnore` are not indexed. Indexed files are
+/// searched by workspace symbols even if the compilation doesn't depend on
+/// them.
+#[derive(Debug, Clone, Default)]
+pub struct ReindexWorkspaceRequest {
+    /// The flag set to cancel the rebuild, which is checked before each file.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// The callback reporting the progress of the rebuild.
+    pub progress: Option<IndexProgress>,
+}
+
+impl SemanticRequest for ReindexWorkspaceRequest {
+    type Response = WorkspaceIndexStats;
+
+    fn request(self, ctx: &mut AnalysisContext) -> Option<Self::Response> {
+        let start = Instant::now();
+        let index = ctx.reindex_workspace(self.cancel.as_deref(), self.progress.as_ref());
+        Some(WorkspaceIndexStats {
+            files: index.files.len(),
+            symbols: index.symbols,
+            labels: index.labels,
+            duration_ms: start.elapsed().as_millis() as u64,
+            cancelled: index.cancelled,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::WorkspaceSymbolResponse;
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::{tests::*, SymbolRequest};
+
+    #[test]
+    fn test_reindex_workspace() {
+        let content = r#"// path: /notes.typ
+= Notes <notes>
+#let helper(x) = x
+-----
+// path: /main.typ
+= Main"#;
+
+        run_with_ctx(content, |ctx, _path| {
+            let stats = ReindexWorkspaceRequest::default().request(ctx).unwrap();
+            assert_eq!(stats.files, 2);
+            assert_eq!(stats.labels, 1);
+
+            let request = SymbolRequest {
+                pattern: Some("helper".to_owned()),
+                lazy: false,
+            };
+            let Some(WorkspaceSymbolResponse::Flat(symbols)) = request.request(ctx) else {
+                panic!("expected flat symbols");
+            };
+            let found = symbols
+                .iter()
+                .map(|s| (s.name.as_str(), s.location.uri.path()));
+            assert_eq!(found.collect::<Vec<_>>(), [("helper", "/notes.typ")]);
+        });
+    }
+
+    #[test]
+    fn test_cancel_reindex() {
+        let content = r#"// path: /notes.typ
+#let helper(x) = x
+-----
+// path: /main.typ
+= Main"#;
+
+        run_with_ctx(content, |ctx, _path| {
+            let stats = ReindexWorkspaceRequest::default().request(ctx).unwrap();
+            assert!(!stats.cancelled);
+            assert_eq!(stats.files, 2);
+
+            let request = ReindexWorkspaceRequest {
+                cancel: Some(Arc::new(AtomicBool::new(true))),
+                progress: None,
+            };
+            let stats = request.request(ctx).unwrap();
+            assert!(stats.cancelled);
+            assert_eq!(stats.files, 0);
+            // The complete index is kept.
+            assert_eq!(ctx.workspace_index().unwrap().files.len(), 2);
+        });
+    }
+
+    #[test]
+    fn test_reindex_unnotified_files() {
+        let root = std::env::temp_dir().join(format!("tinymist-reindex-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("main.typ"), "= Main").unwrap();
+        let mut workspace = DiskWorkspace::new(&root);
+
+        let stats = workspace.run(|ctx| ReindexWorkspaceRequest::default().request(ctx));
+        assert_eq!(stats.unwrap().files, 1);
+
+        // The file is added without notifying the server.
+        std::fs::write(root.join("notes.typ"), "#let fresh(x) = x").unwrap();
+        let reported = Arc::new(Mutex::new(vec![]));
+        let request = ReindexWorkspaceRequest {
+            cancel: None,
+            progress: Some(IndexProgress(Arc::new({
+                let reported = reported.clone();
+                move |done, total| reported.lock().push((done, total))
+            }))),
+        };
+        let stats = workspace.run(|ctx| request.request(ctx)).unwrap();
+        assert_eq!(stats.files, 2);
+        assert_eq!(*reported.lock(), [(0, 2), (1, 2)]);
+
+        let request = SymbolRequest {
+            pattern: Some("fresh".to_owned()),
+            lazy: false,
+        };
+        let symbols = workspace.run(|ctx| request.request(ctx));
+        let Some(WorkspaceSymbolResponse::Flat(symbols)) = symbols else {
+            panic!("expected flat symbols");
+        };
+        assert_eq!(symbols.len(), 1);
+        assert!(symbols[0].location.uri.path().ends_with("/notes.typ"));
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
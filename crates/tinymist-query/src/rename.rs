@@ -1,9 +1,16 @@
+use std::ops::Range;
+
+use ecow::eco_format;
 use log::debug;
 use lsp_types::TextEdit;
+use typst::syntax::is_id_continue;
 
 use crate::{
-    analysis::find_definition, find_references, prelude::*, syntax::get_deref_target,
-    validate_renaming_definition,
+    analysis::{find_definition, DefUseInfo},
+    find_references,
+    prelude::*,
+    syntax::{get_deref_target, DerefTarget, IdentRef},
+    validate_renaming_definition, ReferencesScope,
 };
 
 /// The [`textDocument/rename`] request is sent from the client to the server to
@@ -11,6 +18,11 @@ use crate::{
 /// a workspace-wide rename of a symbol.
 ///
 /// [`textDocument/rename`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_rename
+///
+/// The renaming is rejected if the new name is not a valid identifier, or if it
+/// collides with another binding, i.e. a binding of the new name in the same
+/// scope, or one that would shadow or be shadowed by the renamed binding at
+/// some reference. See [`RenameRequest::rename`] for the reason of rejection.
 #[derive(Debug, Clone)]
 pub struct RenameRequest {
     /// The path of the document to request for.
@@ -29,6 +41,99 @@ impl StatefulRequest for RenameRequest {
         ctx: &mut AnalysisContext,
         doc: Option<VersionedDocument>,
     ) -> Option<Self::Response> {
+        self.rename(ctx, doc).ok().flatten()
+    }
+}
+
+impl RenameRequest {
+    /// Rename the symbol, or get the reason why the new name is rejected.
+    pub fn rename(
+        self,
+        ctx: &mut AnalysisContext,
+        doc: Option<VersionedDocument>,
+    ) -> Result<Option<WorkspaceEdit>, EcoString> {
+        if let Some(label) = self.label_target(ctx) {
+            return self.rename_label(ctx, &label);
+        }
+
+        if !is_valid_identifier(&self.new_name) {
+            return Err(eco_format!("`{}` is not a valid identifier", self.new_name));
+        }
+
+        let Some((def_source, def_ident, locations)) = self.find_locations(ctx, doc) else {
+            return Ok(None);
+        };
+
+        let def_use = ctx.def_use(def_source.clone());
+        if let Some(def_use) = def_use {
+            check_conflict(&def_source, &def_use, &def_ident, &self.new_name)?;
+        }
+
+        Ok(Some(self.workspace_edit(locations)))
+    }
+
+    /// Rename a label along with the references to it in all files of the
+    /// workspace.
+    fn rename_label(
+        &self,
+        ctx: &mut AnalysisContext,
+        label: &str,
+    ) -> Result<Option<WorkspaceEdit>, EcoString> {
+        if !is_valid_label(&self.new_name) {
+            return Err(eco_format!("`{}` is not a valid label", self.new_name));
+        }
+        let Ok(source) = ctx.source_by_path(&self.path) else {
+            return Ok(None);
+        };
+
+        let existing = find_label_locations(ctx, source.id(), &self.new_name);
+        if existing.iter().any(|(_, is_def)| *is_def) {
+            return Err(eco_format!("`<{}>` is already defined", self.new_name));
+        }
+
+        // Only the labels in the sources can be renamed, not the keys of
+        // bibliographies.
+        let locations = find_label_locations(ctx, source.id(), label);
+        if !locations.iter().any(|(_, is_def)| *is_def) {
+            return Ok(None);
+        }
+        let locations = locations.into_iter().map(|(loc, _)| loc).collect();
+        Ok(Some(self.workspace_edit(locations)))
+    }
+
+    /// Get the name of the label at the position, if it is a label or a
+    /// reference.
+    fn label_target(&self, ctx: &mut AnalysisContext) -> Option<EcoString> {
+        let source = ctx.source_by_path(&self.path).ok()?;
+        let cursor = ctx.to_typst_pos(self.position, &source)? + 1;
+        let ast_node = LinkedNode::new(source.root()).leaf_at(cursor)?;
+        label_name(&get_deref_target(ast_node, cursor)?)
+    }
+
+    fn workspace_edit(&self, locations: Vec<LspLocation>) -> WorkspaceEdit {
+        let mut editions = HashMap::new();
+        for i in locations {
+            let uri = i.uri;
+            let range = i.range;
+            let edits = editions.entry(uri).or_insert_with(Vec::new);
+            edits.push(TextEdit {
+                range,
+                new_text: self.new_name.clone(),
+            });
+        }
+
+        WorkspaceEdit {
+            changes: Some(editions),
+            ..Default::default()
+        }
+    }
+
+    /// Find the definition and all the locations to rename.
+    fn find_locations(
+        &self,
+        ctx: &mut AnalysisContext,
+        doc: Option<VersionedDocument>,
+    ) -> Option<(Source, IdentRef, Vec<LspLocation>)> {
         let source = ctx.source_by_path(&self.path).ok()?;
 
         let offset = ctx.to_typst_pos(self.position, &source)?;
@@ -44,43 +149,308 @@ impl StatefulRequest for RenameRequest {
         validate_renaming_definition(&lnk)?;
 
         let def_use = ctx.def_use(source.clone())?;
-        let references = find_references(ctx, def_use, deref_target, ctx.position_encoding())?;
-
-        let mut editions = HashMap::new();
+        let references = find_references(
+            ctx,
+            def_use,
+            deref_target,
+            ReferencesScope::Workspace,
+            false,
+        )?;
 
         let (fid, _def_range) = lnk.def_at?;
+        let def_source = ctx.source_by_id(fid).ok()?;
 
-        let def_loc = {
-            let def_source = ctx.source_by_id(fid).ok()?;
+        let Some(range) = lnk.name_range else {
+            log::warn!("rename: no name range");
+            return None;
+        };
 
+        let def_loc = {
             let span_path = ctx.path_for_id(fid).ok()?;
             let uri = path_to_url(&span_path).ok()?;
 
-            let Some(range) = lnk.name_range else {
-                log::warn!("rename: no name range");
-                return None;
-            };
-
             LspLocation {
                 uri,
-                range: ctx.to_lsp_range(range, &def_source),
+                range: ctx.to_lsp_range(range.clone(), &def_source),
             }
         };
 
-        for i in (Some(def_loc).into_iter()).chain(references) {
-            let uri = i.uri;
-            let range = i.range;
-            let edits = editions.entry(uri).or_insert_with(Vec::new);
-            edits.push(TextEdit {
-                range,
-                new_text: self.new_name.clone(),
-            });
+        let def_ident = IdentRef {
+            name: lnk.name,
+            range,
+        };
+        let locations = (Some(def_loc).into_iter()).chain(references).collect();
+        Some((def_source, def_ident, locations))
+    }
+}
+
+/// Check whether the identifier can be written as a variable, which excludes
+/// the keywords.
+fn is_valid_identifier(name: &str) -> bool {
+    let root = typst::syntax::parse_code(name);
+    let mut children = root.children();
+    match (children.next(), children.next()) {
+        (Some(node), None) => node.kind() == SyntaxKind::Ident && node.text() == name,
+        _ => false,
+    }
+}
+
+/// Check whether the name can be written as a label and referenced by `@`,
+/// which excludes trailing dots and colons.
+fn is_valid_label(name: &str) -> bool {
+    !name.is_empty()
+        && !name.ends_with(['.', ':'])
+        && name
+            .chars()
+            .all(|c| is_id_continue(c) || matches!(c, '.' | ':'))
+}
+
+/// Get the name of the label attached by a label or referred to by a
+/// reference.
+pub(crate) fn label_name(target: &DerefTarget) -> Option<EcoString> {
+    match target {
+        DerefTarget::Label(node) => Some(node.cast::<ast::Label>()?.get().into()),
+        DerefTarget::Ref(node) => Some(node.cast::<ast::Ref>()?.target().into()),
+        _ => None,
+    }
+}
+
+/// Find the names of the labels and references of a label in all source files
+/// of the workspace, along with whether each one is a label.
+pub(crate) fn find_label_locations(
+    ctx: &mut AnalysisContext,
+    current: TypstFileId,
+    label: &str,
+) -> Vec<(LspLocation, bool)> {
+    fn collect(node: &LinkedNode, label: &str, found: &mut Vec<(Range<usize>, bool)>) {
+        if let Some(l) = node.cast::<ast::Label>() {
+            if l.get() == label {
+                let range = node.range();
+                found.push((range.start + 1..range.end - 1, true));
+            }
+        } else if let Some(r) = node.cast::<ast::Ref>() {
+            if r.target() == label {
+                let start = node.offset() + 1;
+                found.push((start..start + label.len(), false));
+            }
         }
+        for child in node.children() {
+            collect(&child, label, found);
+        }
+    }
 
-        // todo: name conflict analysis
-        Some(WorkspaceEdit {
-            changes: Some(editions),
-            ..Default::default()
+    let mut files = ctx.source_files().clone();
+    if !files.contains(&current) {
+        files.push(current);
+    }
+
+    let mut locations = vec![];
+    for fid in files {
+        let Ok(source) = ctx.source_by_id(fid) else {
+            continue;
+        };
+        let mut found = vec![];
+        collect(&LinkedNode::new(source.root()), label, &mut found);
+        if found.is_empty() {
+            continue;
+        }
+        let Some(uri) = ctx.path_for_id(fid).ok().and_then(|p| path_to_url(&p).ok()) else {
+            continue;
+        };
+        locations.extend(found.into_iter().map(|(range, is_def)| {
+            let range = ctx.to_lsp_range(range, &source);
+            (
+                LspLocation {
+                    uri: uri.clone(),
+                    range,
+                },
+                is_def,
+            )
+        }));
+    }
+    locations
+}
+
+/// Check whether renaming the definition to the new name changes the binding
+/// of any identifier in the file of the definition.
+fn check_conflict(
+    source: &Source,
+    def_use: &DefUseInfo,
+    def_ident: &IdentRef,
+    new_name: &str,
+) -> Result<(), EcoString> {
+    let fid = source.id();
+    let Some((def_id, _)) = def_use.get_def(fid, def_ident) else {
+        return Ok(());
+    };
+    let root = LinkedNode::new(source.root());
+    let Some(def_scope) = scope_at(&root, def_ident.range.start) else {
+        return Ok(());
+    };
+    let refs: Vec<_> = def_use.get_refs(def_id).map(|r| r.range.start).collect();
+
+    for ((other_fid, other), _) in def_use.ident_defs.iter() {
+        if *other_fid != fid || other.name != new_name {
+            continue;
+        }
+        let Some(scope) = scope_at(&root, other.range.start) else {
+            continue;
+        };
+        let conflict = || eco_format!("`{new_name}` is already defined in the scope");
+
+        // Both are bound in the same scope.
+        if scope == def_scope {
+            return Err(conflict());
+        }
+
+        // The other binding shadows the renamed one at some references.
+        let inner = scope.start >= def_scope.start && scope.end <= def_scope.end;
+        let shadowed = |at: &usize| scope.contains(at) && other.range.end <= *at;
+        if inner && refs.iter().any(shadowed) {
+            return Err(conflict());
+        }
+
+        // The renamed binding shadows the other one at some references.
+        let Some((other_id, _)) = def_use.get_def(fid, other) else {
+            continue;
+        };
+        let captured = |r: &IdentRef| {
+            def_scope.contains(&r.range.start) && def_ident.range.end <= r.range.start
+        };
+        if def_use.get_refs(other_id).any(captured) {
+            return Err(conflict());
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the range of the innermost scope binding the identifier at the offset.
+fn scope_at(root: &LinkedNode, offset: usize) -> Option<Range<usize>> {
+    let mut node = root.leaf_at(offset + 1)?;
+    while let Some(parent) = node.parent() {
+        let is_scope = match parent.kind() {
+            SyntaxKind::CodeBlock | SyntaxKind::ContentBlock | SyntaxKind::ForLoop => true,
+            // The name of a closure is bound outside of it.
+            SyntaxKind::Closure => node.kind() != SyntaxKind::Ident,
+            _ => false,
+        };
+        if is_scope {
+            return Some(parent.range());
+        }
+        node = parent.clone();
+    }
+    Some(root.range())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    fn rename(content: &str, marker: &str, new_name: &str) -> Result<Vec<LspRange>, EcoString> {
+        run_with_ctx(content, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let offset = source.text().rfind(marker).unwrap();
+            let request = RenameRequest {
+                path,
+                position: ctx.to_lsp_pos(offset, &source),
+                new_name: new_name.to_owned(),
+            };
+            let edit = request.rename(ctx, None)?.unwrap();
+            let mut ranges: Vec<_> = (edit.changes.unwrap().into_values())
+                .flatten()
+                .map(|edit| edit.range)
+                .collect();
+            ranges.sort_by_key(|range| (range.start.line, range.start.character));
+            Ok(ranges)
+        })
+    }
+
+    /// Rename at the marker in the requested file, and get the names of the
+    /// files edited.
+    fn renamed_files(content: &str, marker: &str, new_name: &str) -> Vec<String> {
+        run_with_ctx(content, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let offset = source.text().find(marker).unwrap();
+            let request = RenameRequest {
+                path,
+                position: ctx.to_lsp_pos(offset, &source),
+                new_name: new_name.to_owned(),
+            };
+            let edit = request.rename(ctx, None).unwrap().unwrap();
+            let mut files: Vec<_> = (edit.changes.unwrap().into_iter())
+                .inspect(|(_, edits)| assert!(edits.iter().all(|e| e.new_text == new_name)))
+                .map(|(uri, _)| uri.path().rsplit('/').next().unwrap().to_owned())
+                .collect();
+            files.sort();
+            files
         })
     }
+
+    fn range(line: u32, start: u32, end: u32) -> LspRange {
+        LspRange::new(LspPosition::new(line, start), LspPosition::new(line, end))
+    }
+
+    #[test]
+    fn test_shadowed() {
+        let content = "#let x = 1\n#{\n  let x = 2\n  x\n}\n#x";
+        assert_eq!(
+            rename(content, "x", "y").unwrap(),
+            vec![range(0, 5, 6), range(5, 1, 2)]
+        );
+        assert_eq!(
+            rename(content, "x\n}", "y").unwrap(),
+            vec![range(2, 6, 7), range(3, 2, 3)]
+        );
+    }
+
+    #[test]
+    fn test_conflict() {
+        let content = "#let x = 1\n#let y = 2\n#x";
+        assert!(rename(content, "x", "y").is_err());
+        assert!(rename(content, "x", "let").is_err());
+        assert!(rename(content, "x", "1x").is_err());
+        assert_eq!(
+            rename(content, "x", "z").unwrap(),
+            vec![range(0, 5, 6), range(2, 1, 2)]
+        );
+    }
+
+    #[test]
+    fn test_workspace_function() {
+        let content = r#"// path: /a.typ
+#import "lib.typ": greet
+#greet("a")
+-----
+// path: /b.typ
+#import "lib.typ": *
+#greet("b")
+-----
+// path: /lib.typ
+#let greet(name) = [Hello #name]"#;
+        assert_eq!(
+            renamed_files(content, "greet", "welcome"),
+            ["a.typ", "b.typ", "lib.typ"]
+        );
+    }
+
+    #[test]
+    fn test_workspace_label() {
+        let content = r#"// path: /intro.typ
+= Introduction <intro>
+-----
+// path: /main.typ
+#include "intro.typ"
+See @intro and #ref(<intro>)."#;
+        assert_eq!(
+            renamed_files(content, "@intro", "overview"),
+            ["intro.typ", "main.typ"]
+        );
+        assert_eq!(
+            rename(content, "@intro", "overview").unwrap(),
+            vec![range(0, 16, 21), range(1, 5, 10), range(1, 21, 26)]
+        );
+        assert!(rename(content, "@intro", "bad name").is_err());
+    }
 }
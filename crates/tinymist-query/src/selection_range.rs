@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 use crate::{prelude::*, SyntaxRequest};
 
 /// The [`textDocument/selectionRange`] request is sent from the client to the
@@ -35,23 +37,170 @@ impl SyntaxRequest for SelectionRangeRequest {
             let typst_offset = lsp_to_typst::position(position, position_encoding, source)?;
             let tree = LinkedNode::new(source.root());
             let leaf = tree.leaf_at(typst_offset + 1)?;
-            ranges.push(range_for_node(source, position_encoding, &leaf));
+            let chain = expansion_chain(&leaf, typst_offset);
+
+            let mut range = None;
+            for r in chain.into_iter().rev() {
+                range = Some(SelectionRange {
+                    range: typst_to_lsp::range(r, source, position_encoding),
+                    parent: range.map(Box::new),
+                });
+            }
+            ranges.push(range?);
         }
 
         Some(ranges)
     }
 }
 
-fn range_for_node(
-    source: &Source,
-    position_encoding: PositionEncoding,
+/// The relations splitting a math expression into sides.
+const MATH_RELATIONS: &[&str] = &[
+    "=", "<", ">", "<=", ">=", "!=", ":=", "=:", "->", "<-", "=>", "<=>", "≤", "≥", "≠", "≈",
+];
+
+/// Get the ranges to select around the cursor, from the innermost to the
+/// outermost.
+///
+/// Besides the syntax nodes, the chain stops at the word under the cursor, the
+/// side of a math relation, and the paragraph in markup.
+fn expansion_chain(leaf: &LinkedNode, cursor: usize) -> Vec<Range<usize>> {
+    let mut chain: Vec<Range<usize>> = Vec::new();
+    let mut push = |range: Range<usize>| {
+        let wider = chain.last().map_or(true, |last| {
+            range.start <= last.start && last.end <= range.end && range != *last
+        });
+        if wider && !range.is_empty() {
+            chain.push(range);
+        }
+    };
+
+    if let Some(word) = word_range(leaf, cursor) {
+        push(word);
+    }
+
+    let mut node = Some(leaf.clone());
+    while let Some(n) = node {
+        let segment = match n.kind() {
+            SyntaxKind::Math => segment_range(&n, cursor, is_relation),
+            SyntaxKind::Markup => segment_range(&n, cursor, is_parbreak),
+            _ => None,
+        };
+        if let Some(segment) = segment {
+            push(segment);
+        }
+        push(n.range());
+        node = n.parent().cloned();
+    }
+
+    chain
+}
+
+/// Get the range of the word under the cursor in a leaf.
+fn word_range(leaf: &LinkedNode, cursor: usize) -> Option<Range<usize>> {
+    let text = leaf.text();
+    let offset = cursor.checked_sub(leaf.offset())?;
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last()
+        .map_or(offset, |(idx, _)| idx);
+    let end = text[offset..]
+        .char_indices()
+        .find(|(_, c)| !is_word(*c))
+        .map_or(text.len(), |(idx, _)| offset + idx);
+
+    (start < end).then(|| leaf.offset() + start..leaf.offset() + end)
+}
+
+/// Get the range of the children between separators around the cursor,
+/// excluding spaces at both ends.
+fn segment_range(
     node: &LinkedNode,
-) -> SelectionRange {
-    let range = typst_to_lsp::range(node.range(), source, position_encoding);
-    SelectionRange {
-        range,
-        parent: node
-            .parent()
-            .map(|node| Box::new(range_for_node(source, position_encoding, node))),
+    cursor: usize,
+    is_separator: fn(&LinkedNode) -> bool,
+) -> Option<Range<usize>> {
+    let mut segment: Vec<LinkedNode> = Vec::new();
+    for child in node.children() {
+        if is_separator(&child) {
+            if child.offset() >= cursor {
+                break;
+            }
+            segment.clear();
+        } else {
+            segment.push(child);
+        }
+    }
+
+    let mut segment = segment
+        .iter()
+        .filter(|child| child.kind() != SyntaxKind::Space);
+    let first = segment.next()?;
+    let last = segment.last().unwrap_or(first);
+    (first.offset() <= cursor && cursor <= last.range().end)
+        .then(|| first.offset()..last.range().end)
+}
+
+fn is_relation(node: &LinkedNode) -> bool {
+    match node.kind() {
+        SyntaxKind::MathAlignPoint | SyntaxKind::Linebreak => true,
+        SyntaxKind::Text | SyntaxKind::Shorthand => MATH_RELATIONS.contains(&node.text().as_str()),
+        _ => false,
+    }
+}
+
+fn is_parbreak(node: &LinkedNode) -> bool {
+    node.kind() == SyntaxKind::Parbreak
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(text: &str, cursor_at: &str) -> Vec<String> {
+        let source = Source::detached(text);
+        let cursor = text.find(cursor_at).unwrap();
+        let tree = LinkedNode::new(source.root());
+        let leaf = tree.leaf_at(cursor + 1).unwrap();
+        expansion_chain(&leaf, cursor)
+            .into_iter()
+            .map(|range| text[range].to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_math_chain() {
+        assert_eq!(
+            chain("$f(x + 1) = y$", "x"),
+            [
+                "x",
+                "x + 1",
+                "(x + 1)",
+                "f(x + 1)",
+                "f(x + 1) = y",
+                "$f(x + 1) = y$"
+            ]
+        );
+        assert_eq!(
+            chain("$a + b = c$", "b"),
+            ["b", "a + b", "a + b = c", "$a + b = c$"]
+        );
+    }
+
+    #[test]
+    fn test_markup_chain() {
+        let text = "Some _very important_ words.\n\nNext paragraph.";
+        assert_eq!(
+            chain(text, "important"),
+            [
+                "important",
+                "very important",
+                "_very important_",
+                "Some _very important_ words.",
+                text,
+            ]
+        );
     }
 }
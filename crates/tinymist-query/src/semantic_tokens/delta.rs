@@ -1,45 +1,53 @@
+use indexmap::IndexMap;
 use lsp_types::{SemanticToken, SemanticTokensEdit};
 
-#[derive(Debug)]
-struct CachedTokens {
-    tokens: Vec<SemanticToken>,
-    id: u64,
-}
+/// The maximum number of token snapshots kept in the cache.
+const CACHE_CAPACITY: usize = 16;
 
+/// A cache of semantic tokens keyed by the hash of the source content.
+///
+/// The result id sent to the client is the content hash, so that a result is
+/// reusable for an unchanged document even after the client loses its result
+/// id, e.g. on reconnecting.
 #[derive(Default, Debug)]
 pub struct CacheInner {
-    last_sent: Option<CachedTokens>,
-    next_id: u64,
+    /// The token snapshots, in order of insertion.
+    snapshots: IndexMap<u128, Vec<SemanticToken>>,
 }
 
 impl CacheInner {
-    pub fn try_take_result(&mut self, id: &str) -> Option<Vec<SemanticToken>> {
-        let id = id.parse::<u64>().ok()?;
-        match self.last_sent.take() {
-            Some(cached) if cached.id == id => Some(cached.tokens),
-            Some(cached) => {
-                // replace after taking
-                self.last_sent = Some(cached);
-                None
-            }
-            None => None,
-        }
+    pub fn get(&self, hash: u128) -> Option<Vec<SemanticToken>> {
+        self.snapshots.get(&hash).cloned()
     }
 
-    pub fn cache_result(&mut self, tokens: Vec<SemanticToken>) -> String {
-        let id = self.get_next_id();
-        let cached = CachedTokens { tokens, id };
-        self.last_sent = Some(cached);
-        id.to_string()
+    pub fn get_result(&self, id: &str) -> Option<Vec<SemanticToken>> {
+        self.get(parse_result_id(id)?)
+    }
+
+    pub fn cache_result(&mut self, hash: u128, tokens: Vec<SemanticToken>) -> String {
+        self.snapshots.shift_remove(&hash);
+        if self.snapshots.len() >= CACHE_CAPACITY {
+            self.snapshots.shift_remove_index(0);
+        }
+        self.snapshots.insert(hash, tokens);
+        result_id(hash)
     }
 
-    fn get_next_id(&mut self) -> u64 {
-        let id = self.next_id;
-        self.next_id += 1;
-        id
+    pub fn evict_result(&mut self, id: &str) {
+        if let Some(hash) = parse_result_id(id) {
+            self.snapshots.shift_remove(&hash);
+        }
     }
 }
 
+pub fn result_id(hash: u128) -> String {
+    format!("{hash:032x}")
+}
+
+fn parse_result_id(id: &str) -> Option<u128> {
+    u128::from_str_radix(id, 16).ok()
+}
+
 pub fn token_delta(from: &[SemanticToken], to: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
     // Taken from `rust-analyzer`'s algorithm
     // https://github.com/rust-lang/rust-analyzer/blob/master/crates/rust-analyzer/src/semantic_tokens.rs#L219
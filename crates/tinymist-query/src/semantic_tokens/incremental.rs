@@ -0,0 +1,144 @@
+//! Reuse of the semantic tokens of the top-level nodes unchanged by an edit.
+//!
+//! Like the diffing of formatted sources, the top-level nodes of a source are
+//! compared with the last tokenized version of it from both ends, and only the
+//! nodes in between are tokenized again.
+
+use indexmap::IndexMap;
+use lsp_types::SemanticToken;
+use typst::syntax::{FileId, SyntaxNode};
+
+use crate::LspPosition;
+
+/// The maximum number of sources whose tokens are kept for reuse.
+const CACHE_CAPACITY: usize = 16;
+
+/// The semantic tokens of a top-level node.
+///
+/// The first token is relative to the start of the node instead of the
+/// previous token, so that the tokens are reusable wherever the node is moved
+/// by an edit.
+#[derive(Debug, Clone, Default)]
+pub struct NodeTokens {
+    pub tokens: Vec<SemanticToken>,
+    /// The position of the last token relative to the start of the node.
+    pub last: (u32, u32),
+}
+
+/// The last tokenized version of a source.
+struct Tokenized {
+    root: SyntaxNode,
+    /// The tokens of the children of the root.
+    nodes: Vec<NodeTokens>,
+}
+
+/// A cache of the tokens of the top-level nodes, keyed by the sources.
+#[derive(Default)]
+pub struct IncrementalCache {
+    sources: IndexMap<FileId, Tokenized>,
+}
+
+impl IncrementalCache {
+    /// Take the tokens reusable for each child of the root from the last
+    /// tokenized version of the source.
+    pub fn take_reusable(&mut self, id: FileId, root: &SyntaxNode) -> Vec<Option<NodeTokens>> {
+        let new = root.children().as_slice();
+        let mut reused = vec![None; new.len()];
+        let Some(mut last) = self.sources.shift_remove(&id) else {
+            return reused;
+        };
+        if last.root.kind() != root.kind() {
+            return reused;
+        }
+
+        let old = last.root.children().as_slice();
+        let unchanged = |(x, y): &(&SyntaxNode, &SyntaxNode)| x.spanless_eq(y);
+        let prefix = old.iter().zip(new).take_while(unchanged).count();
+        let suffix = (old.iter().rev().zip(new.iter().rev()))
+            .take_while(unchanged)
+            .count()
+            .min(old.len().min(new.len()) - prefix);
+        // The tokens of a node may depend on the next node, e.g. the ones of a
+        // hash on the embedded expression, so the last unchanged node before
+        // the edit is tokenized again.
+        let prefix = if prefix == old.len() && prefix == new.len() {
+            prefix
+        } else {
+            prefix.saturating_sub(1)
+        };
+
+        for (i, reused) in reused.iter_mut().enumerate().take(prefix) {
+            *reused = Some(std::mem::take(&mut last.nodes[i]));
+        }
+        for k in 0..suffix {
+            let node = std::mem::take(&mut last.nodes[old.len() - 1 - k]);
+            reused[new.len() - 1 - k] = Some(node);
+        }
+        reused
+    }
+
+    /// Store the tokens of the children of the root for the next edit.
+    pub fn store(&mut self, id: FileId, root: SyntaxNode, nodes: Vec<NodeTokens>) {
+        self.sources.shift_remove(&id);
+        if self.sources.len() >= CACHE_CAPACITY {
+            self.sources.shift_remove_index(0);
+        }
+        self.sources.insert(id, Tokenized { root, nodes });
+    }
+}
+
+/// Get the position relative to another one, in the sense of the deltas of
+/// semantic tokens, i.e. the character is relative only on the same line.
+pub fn relative(from: LspPosition, to: LspPosition) -> (u32, u32) {
+    let line = to.line - from.line;
+    let character = if line == 0 {
+        to.character - from.character
+    } else {
+        to.character
+    };
+    (line, character)
+}
+
+/// Get the position of a relative one, which inverts [`relative`].
+pub fn advance(from: LspPosition, (line, character): (u32, u32)) -> LspPosition {
+    if line == 0 {
+        LspPosition::new(from.line, from.character + character)
+    } else {
+        LspPosition::new(from.line + line, character)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use typst::syntax::Source;
+
+    use crate::SemanticTokenContext;
+
+    #[test]
+    fn test_reuse_unchanged_nodes() {
+        let text: String = (0..50)
+            .map(|i| format!("= Section {i}\n#let x{i} = {i}\n_emph_ *strong* #x{i}\n\n"))
+            .collect();
+        let ctx = SemanticTokenContext::default();
+        let mut source = Source::detached(text);
+        ctx.get_semantic_tokens_full(&source);
+        let full = ctx.tokenized_nodes.load(Ordering::Relaxed);
+
+        let offset = source.text().find("Section 25").unwrap();
+        source.edit(offset..offset + 1, "s");
+        let (tokens, _) = ctx.get_semantic_tokens_full(&source);
+        let tokenized = ctx.tokenized_nodes.load(Ordering::Relaxed) - full;
+        assert!(tokenized <= 3, "{tokenized} of {full} nodes are tokenized");
+        let (fresh, _) = SemanticTokenContext::default().get_semantic_tokens_full(&source);
+        assert_eq!(tokens, fresh);
+
+        // Edits shifting the lines and the characters of the following nodes.
+        let offset = source.text().find("#x10").unwrap();
+        source.edit(offset..offset, "ü\n#");
+        let (tokens, _) = ctx.get_semantic_tokens_full(&source);
+        let (fresh, _) = SemanticTokenContext::default().get_semantic_tokens_full(&source);
+        assert_eq!(tokens, fresh);
+    }
+}
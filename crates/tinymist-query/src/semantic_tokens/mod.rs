@@ -1,17 +1,20 @@
 use std::ops::Range;
 
 use lsp_types::{SemanticToken, SemanticTokensEdit};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use reflexo::hash::hash128;
 use typst::syntax::{ast, LinkedNode, Source, SyntaxKind};
 
-use crate::{LspPosition, PositionEncoding};
+use crate::{typst_to_lsp, LspPosition, PositionEncoding};
 
-use self::delta::token_delta;
+use self::delta::{result_id, token_delta};
+use self::incremental::{advance, relative, IncrementalCache, NodeTokens};
 use self::modifier_set::ModifierSet;
 
 use self::delta::CacheInner as TokenCacheInner;
 
 mod delta;
+mod incremental;
 mod modifier_set;
 mod typst_tokens;
 pub use self::typst_tokens::{Modifier, TokenType};
@@ -20,6 +23,11 @@ pub use self::typst_tokens::{Modifier, TokenType};
 #[derive(Default)]
 pub struct SemanticTokenContext {
     cache: RwLock<TokenCacheInner>,
+    /// The tokens of the top-level nodes, reused for the next edit.
+    incremental: Mutex<IncrementalCache>,
+    /// The number of the top-level nodes tokenized, i.e. not reused.
+    #[cfg(test)]
+    tokenized_nodes: std::sync::atomic::AtomicUsize,
     position_encoding: PositionEncoding,
     /// Whether to allow overlapping tokens.
     pub allow_overlapping_token: bool,
@@ -36,6 +44,9 @@ impl SemanticTokenContext {
     ) -> Self {
         Self {
             cache: RwLock::new(TokenCacheInner::default()),
+            incremental: Mutex::default(),
+            #[cfg(test)]
+            tokenized_nodes: Default::default(),
             position_encoding,
             allow_overlapping_token,
             allow_multiline_token,
@@ -43,35 +54,92 @@ impl SemanticTokenContext {
     }
 
     /// Get the semantic tokens for a source.
+    ///
+    /// The tokens of an unchanged source are served from the cache, and the
+    /// ones of the top-level nodes unchanged since the last edit are reused.
     pub fn get_semantic_tokens_full(&self, source: &Source) -> (Vec<SemanticToken>, String) {
-        let root = LinkedNode::new(source.root());
+        let hash = hash128(source.text());
+        if let Some(tokens) = self.cache.read().get(hash) {
+            return (tokens, result_id(hash));
+        }
+
+        let output = self.tokenize(source);
+
+        let result_id = self.cache.write().cache_result(hash, output.clone());
+        (output, result_id)
+    }
+
+    /// Tokenize a source, reusing the tokens of the top-level nodes unchanged
+    /// since it was last tokenized.
+    fn tokenize(&self, source: &Source) -> Vec<SemanticToken> {
+        let mut incremental = self.incremental.lock();
+        let reused = incremental.take_reusable(source.id(), source.root());
+
+        let mut output = vec![];
+        let mut nodes = Vec::with_capacity(reused.len());
+        let mut prev = LspPosition::new(0, 0);
+        for (child, reused) in LinkedNode::new(source.root()).children().zip(reused) {
+            let start =
+                typst_to_lsp::offset_to_position(child.offset(), self.position_encoding, source);
+            let node = reused.unwrap_or_else(|| self.tokenize_node(source, &child, start));
+            // Only the first token is relative to the start of the node.
+            if let Some((first, rest)) = node.tokens.split_first() {
+                let (delta_line, delta_start) =
+                    relative(prev, advance(start, (first.delta_line, first.delta_start)));
+                output.push(SemanticToken {
+                    delta_line,
+                    delta_start,
+                    ..*first
+                });
+                output.extend_from_slice(rest);
+                prev = advance(start, node.last);
+            }
+            nodes.push(node);
+        }
+
+        incremental.store(source.id(), source.root().clone(), nodes);
+        output
+    }
+
+    /// Tokenize a top-level node starting at the position.
+    fn tokenize_node(&self, source: &Source, node: &LinkedNode, start: LspPosition) -> NodeTokens {
+        #[cfg(test)]
+        self.tokenized_nodes
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
         let mut tokenizer = Tokenizer::new(
             source.clone(),
             self.allow_multiline_token,
             self.position_encoding,
         );
-        tokenizer.tokenize_tree(&root, ModifierSet::empty());
-        let output = tokenizer.output;
-
-        let result_id = self.cache.write().cache_result(output.clone());
-        (output, result_id)
+        tokenizer.curr_pos = start;
+        tokenizer.pos_offset = node.offset();
+        tokenizer.tokenize_tree(node, ModifierSet::empty());
+        NodeTokens {
+            last: relative(start, tokenizer.curr_pos),
+            tokens: tokenizer.output,
+        }
     }
 
     /// Get the semantic tokens delta for a source.
+    ///
+    /// The delta is computed against any cached snapshot, which is evicted if
+    /// the source has been changed since then.
     pub fn try_semantic_tokens_delta_from_result_id(
         &self,
         source: &Source,
         result_id: &str,
     ) -> (Result<Vec<SemanticTokensEdit>, Vec<SemanticToken>>, String) {
-        let cached = self.cache.write().try_take_result(result_id);
+        let cached = self.cache.read().get_result(result_id);
 
-        // this call will overwrite the cache, so need to read from cache first
-        let (tokens, result_id) = self.get_semantic_tokens_full(source);
+        let (tokens, next_result_id) = self.get_semantic_tokens_full(source);
+        if next_result_id != result_id {
+            self.cache.write().evict_result(result_id);
+        }
 
         match cached {
-            Some(cached) => (Ok(token_delta(&cached, &tokens)), result_id),
-            None => (Err(tokens), result_id),
+            Some(cached) => (Ok(token_delta(&cached, &tokens)), next_result_id),
+            None => (Err(tokens), next_result_id),
         }
     }
 }
@@ -146,7 +214,6 @@ impl Tokenizer {
     }
 
     fn push(&mut self, token: Token) {
-        use crate::typst_to_lsp;
         use lsp_types::Position;
         let utf8_start = token.range.start;
         if self.pos_offset > utf8_start {
@@ -50,3 +50,34 @@ impl SemanticTokensDeltaRequest {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached() {
+        let ctx = SemanticTokenContext::default();
+        let mut source = Source::detached("#let x = 1\n= Heading\n#x");
+        let (_, previous_result_id) = ctx.get_semantic_tokens_full(&source);
+
+        source.edit(0..0, "Hello ");
+        let request = || SemanticTokensDeltaRequest {
+            path: PathBuf::from("/s0.typ"),
+            previous_result_id: previous_result_id.clone(),
+        };
+        let delta = request().request(&ctx, source.clone()).unwrap();
+        let SemanticTokensFullDeltaResult::TokensDelta(delta) = delta else {
+            panic!("expected a delta, got {delta:?}");
+        };
+
+        let (tokens, result_id) = ctx.get_semantic_tokens_full(&source);
+        let (fresh, _) = SemanticTokenContext::default().get_semantic_tokens_full(&source);
+        assert_eq!(tokens, fresh);
+        assert_eq!(delta.result_id, Some(result_id));
+
+        // The snapshot of the edited source is evicted.
+        let delta = request().request(&ctx, source.clone()).unwrap();
+        assert!(matches!(delta, SemanticTokensFullDeltaResult::Tokens(_)));
+    }
+}
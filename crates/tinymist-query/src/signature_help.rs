@@ -1,7 +1,7 @@
 use crate::{
     analysis::{analyze_dyn_signature, find_definition, FlowType},
     prelude::*,
-    syntax::{get_check_target, get_deref_target, CheckTarget, ParamTarget},
+    syntax::{active_arg, get_deref_target, highlight_markdown, ActiveArg, ColorTheme},
     DocTooltip, LspParamInfo, SemanticRequest,
 };
 
@@ -22,20 +22,18 @@ impl SemanticRequest for SignatureHelpRequest {
 
     fn request(self, ctx: &mut AnalysisContext) -> Option<Self::Response> {
         let source = ctx.source_by_path(&self.path).ok()?;
-        let cursor = ctx.to_typst_pos(self.position, &source)? + 1;
+        let offset = ctx.to_typst_pos(self.position, &source)?;
+        let cursor = offset + 1;
 
         let ast_node = LinkedNode::new(source.root()).leaf_at(cursor)?;
-        let CheckTarget::Param { callee, target, .. } = get_check_target(ast_node)? else {
-            return None;
-        };
+        let (callee, target) = active_arg(&ast_node, offset)?;
 
         let deref_target = get_deref_target(callee, cursor)?;
 
         let def_link = find_definition(ctx, source.clone(), None, deref_target)?;
 
-        let documentation = DocTooltip::get(ctx, &def_link)
-            .as_deref()
-            .map(markdown_docs);
+        let theme = ctx.analysis.highlight_theme();
+        let documentation = DocTooltip::get(ctx, &def_link).map(|docs| markdown_docs(&docs, theme));
 
         let Some(Value::Func(function)) = def_link.value else {
             return None;
@@ -57,15 +55,24 @@ impl SemanticRequest for SignatureHelpRequest {
 
         named.sort_by_key(|x| &x.name);
 
+        // The parameters are labeled in the order of positional, named and rest
+        // ones. Arguments spread before the cursor may supply any number of
+        // positional ones, so only the rest parameter is known to match.
+        let rest_index = rest.as_ref().map(|_| pos.len() + named.len());
         let active_parameter = match &target {
-            ParamTarget::Positional { positional, .. } => Some((*positional) + param_shift),
-            ParamTarget::Named(name) => {
-                let name = name.get().clone().into_text();
-                named
-                    .iter()
-                    .position(|x| x.name.as_ref() == name.as_ref())
-                    .map(|i| pos.len() + i)
+            ActiveArg::Positional(positional) => {
+                let positional = positional + param_shift;
+                if positional < pos.len() {
+                    Some(positional)
+                } else {
+                    rest_index
+                }
             }
+            ActiveArg::AfterSpread => rest_index,
+            ActiveArg::Named(name) => named
+                .iter()
+                .position(|x| x.name == name.as_str())
+                .map(|i| pos.len() + i),
         };
 
         let mut label = def_link.name.clone();
@@ -91,10 +98,7 @@ impl SemanticRequest for SignatureHelpRequest {
             params.push(LspParamInfo {
                 label: lsp_types::ParameterLabel::Simple(ty.name.clone().into()),
                 documentation: if !ty.docs.is_empty() {
-                    Some(Documentation::MarkupContent(MarkupContent {
-                        value: ty.docs.clone().into(),
-                        kind: MarkupKind::Markdown,
-                    }))
+                    Some(markdown_docs(&ty.docs, theme))
                 } else {
                     None
                 },
@@ -121,9 +125,74 @@ impl SemanticRequest for SignatureHelpRequest {
     }
 }
 
-fn markdown_docs(docs: &str) -> Documentation {
+fn markdown_docs(docs: &str, theme: Option<ColorTheme>) -> Documentation {
     Documentation::MarkupContent(MarkupContent {
         kind: MarkupKind::Markdown,
-        value: docs.to_owned(),
+        value: match theme {
+            Some(theme) => highlight_markdown(docs, theme),
+            None => docs.to_owned(),
+        },
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    /// Get the label of the signature and of its active parameter, with the
+    /// cursor at the end of the marked text.
+    fn active_param(content: &str, before_cursor: &str) -> (String, Option<String>) {
+        run_with_ctx(content, |ctx, path| {
+            let source = ctx.source_by_path(&path).unwrap();
+            let offset = source.text().find(before_cursor).unwrap() + before_cursor.len();
+            let request = SignatureHelpRequest {
+                path,
+                position: ctx.to_lsp_pos(offset, &source),
+            };
+            let help = request.request(ctx).unwrap();
+            let signature = help.signatures.into_iter().next().unwrap();
+            let param = signature.active_parameter.map(|active| {
+                match &signature.parameters.as_ref().unwrap()[active as usize].label {
+                    lsp_types::ParameterLabel::Simple(label) => label.clone(),
+                    label => panic!("unexpected label {label:?}"),
+                }
+            });
+            (signature.label, param)
+        })
+    }
+
+    #[test]
+    fn test_positional_after_commas() {
+        let content = "#calc.clamp(1, 2, )";
+        let (label, param) = active_param(content, "clamp(1, 2, ");
+        assert!(label.starts_with("clamp("), "{label}");
+        assert_eq!(param.as_deref(), Some("max"));
+        let (_, param) = active_param(content, "clamp(1");
+        assert_eq!(param.as_deref(), Some("value"));
+    }
+
+    #[test]
+    fn test_named_argument() {
+        let content = "#rect(width: 1pt, fill: red)";
+        let (_, param) = active_param(content, "fill: re");
+        assert_eq!(param.as_deref(), Some("fill"));
+        // Named arguments do not advance the positional ones.
+        let (_, param) = active_param("#rect(width: 1pt, )", "width: 1pt, ");
+        assert_eq!(param.as_deref(), Some("body"));
+        // The value of the named argument is not typed yet.
+        let (_, param) = active_param("#rect(stroke: )", "stroke: ");
+        assert_eq!(param.as_deref(), Some("stroke"));
+    }
+
+    #[test]
+    fn test_nested_call() {
+        let content = "#calc.clamp(calc.pow(2, 3), 1, 10)";
+        let (label, param) = active_param(content, "pow(2, ");
+        assert!(label.starts_with("pow("), "{label}");
+        assert_eq!(param.as_deref(), Some("exponent"));
+        let (label, param) = active_param(content, "3), ");
+        assert!(label.starts_with("clamp("), "{label}");
+        assert_eq!(param.as_deref(), Some("min"));
+    }
+}
@@ -1,7 +1,11 @@
+use std::ops::Range;
+
+use lsp_types::{OneOf, WorkspaceLocation, WorkspaceSymbol, WorkspaceSymbolResponse};
+
 use crate::{
     prelude::*,
     syntax::{get_lexical_hierarchy, LexicalHierarchy, LexicalScopeKind},
-    SemanticRequest,
+    SemanticRequest, SyntaxRequest,
 };
 
 /// The [`workspace/symbol`] request is sent from the client to the server to
@@ -17,7 +21,7 @@ use crate::{
 /// then need to resolve the range when necessary using the `workspaceSymbol/
 /// resolve` request.
 ///
-/// // [`workspaceSymbol/resolve`]: Self::symbol_resolve
+/// [`workspaceSymbol/resolve`]: WorkspaceSymbolResolveRequest
 ///
 /// Servers can only use this new model if clients advertise support for it via
 /// the `workspace.symbol.resolve_support` capability.
@@ -26,73 +30,259 @@ pub struct SymbolRequest {
     /// The query string to filter symbols by. It is usually the exact content
     /// of the user's input box in the UI.
     pub pattern: Option<String>,
+    /// Whether to return symbols without ranges, which are resolved later by
+    /// [`WorkspaceSymbolResolveRequest`].
+    pub lazy: bool,
 }
 
 impl SemanticRequest for SymbolRequest {
-    type Response = Vec<SymbolInformation>;
+    type Response = WorkspaceSymbolResponse;
 
     fn request(self, ctx: &mut AnalysisContext) -> Option<Self::Response> {
         // todo: let typst.ts expose source
 
         let mut symbols = vec![];
+        let mut stubs = vec![];
+
+        let mut paths = vec![];
+        ctx.resources
+            .iter_dependencies(&mut |path, _| paths.push(path.clone()));
+        // The indexed files that the compilation doesn't depend on.
+        if let Some(index) = ctx.workspace_index() {
+            for id in &index.files {
+                let Ok(path) = ctx.path_for_id(*id) else {
+                    continue;
+                };
+                if !paths.iter().any(|p| p.as_ref() == path.as_path()) {
+                    paths.push(path.into());
+                }
+            }
+        }
 
-        ctx.resources.iter_dependencies(&mut |path, _| {
-            let Ok(source) = ctx.source_by_path(path) else {
-                return;
+        for path in paths {
+            let Some(pattern) = self.pattern.as_ref() else {
+                break;
+            };
+            let Ok(source) = ctx.source_by_path(&path) else {
+                continue;
+            };
+            // The names of symbols are slices of the source, so the files without
+            // the pattern are skipped before analyzing them.
+            if !source.text().contains(pattern.as_str()) {
+                continue;
+            }
+            let uri = path_to_url(&path).unwrap();
+            let Some(hierarchy) = get_lexical_hierarchy(source.clone(), LexicalScopeKind::Symbol)
+            else {
+                continue;
             };
-            let uri = path_to_url(path).unwrap();
-            let res = get_lexical_hierarchy(source.clone(), LexicalScopeKind::Symbol).and_then(
-                |symbols| {
-                    self.pattern.as_ref().map(|pattern| {
-                        filter_document_symbols(
-                            &symbols,
-                            pattern,
-                            &source,
-                            &uri,
-                            ctx.position_encoding(),
-                        )
-                    })
-                },
-            );
-
-            if let Some(mut res) = res {
-                symbols.append(&mut res)
+
+            if self.lazy {
+                let matched = filter_symbols(&hierarchy, pattern);
+                stubs.extend(matched.into_iter().map(|(e, _)| symbol_stub(e, &uri)));
+            } else {
+                let encoding = ctx.position_encoding();
+                let matched = filter_symbols(&hierarchy, pattern);
+                symbols.extend(
+                    matched
+                        .into_iter()
+                        .map(|(e, _)| symbol_information(e, &source, &uri, encoding)),
+                );
             }
+        }
+
+        Some(if self.lazy {
+            WorkspaceSymbolResponse::Nested(stubs)
+        } else {
+            WorkspaceSymbolResponse::Flat(symbols)
+        })
+    }
+}
+
+/// The [`workspaceSymbol/resolve`] request is sent from the client to the
+/// server to resolve additional information for a given workspace symbol.
+///
+/// Symbols returned by a lazy [`SymbolRequest`] only carry the document they
+/// belong to, and their byte range in the `data` field. The resolution fills in
+/// the full location and the name of the containing symbol from the current
+/// content of the document, without analyzing the compilation again.
+///
+/// [`workspaceSymbol/resolve`]: https://microsoft.github.io/language-server-protocol/specification#workspace_symbolResolve
+///
+/// # Compatibility
+///
+/// This request was introduced in specification version 3.17.0.
+#[derive(Debug, Clone)]
+pub struct WorkspaceSymbolResolveRequest {
+    /// The path of the document containing the symbol.
+    pub path: PathBuf,
+    /// The symbol to resolve.
+    pub symbol: WorkspaceSymbol,
+}
+
+impl SyntaxRequest for WorkspaceSymbolResolveRequest {
+    type Response = WorkspaceSymbol;
+
+    fn request(
+        self,
+        source: &Source,
+        position_encoding: PositionEncoding,
+    ) -> Option<Self::Response> {
+        let mut symbol = self.symbol;
+        let OneOf::Right(WorkspaceLocation { uri }) = &symbol.location else {
+            return Some(symbol);
+        };
+        let uri = uri.clone();
+
+        let hierarchy = get_lexical_hierarchy(source.clone(), LexicalScopeKind::Symbol)?;
+        let range = symbol
+            .data
+            .as_ref()
+            .and_then(|data| serde_json::from_value::<Range<usize>>(data.clone()).ok());
+
+        // Prefer the symbol at the recorded range, in case the document has
+        // been edited since the query.
+        let mut matched = filter_symbols(&hierarchy, &symbol.name);
+        matched.retain(|(e, _)| e.info.name == symbol.name);
+        matched.sort_by_key(|(e, _)| Some(&e.info.range) != range.as_ref());
+        let (e, container) = matched.into_iter().next()?;
+
+        symbol.location = OneOf::Left(LspLocation {
+            uri,
+            range: typst_to_lsp::range(e.info.range.clone(), source, position_encoding),
         });
+        symbol.container_name = container.map(|c| c.info.name.clone());
+        symbol.data = None;
+        Some(symbol)
+    }
+}
 
-        Some(symbols)
+/// Get the symbols whose names contain the query at any depth, along with
+/// their innermost containers.
+fn filter_symbols<'a>(
+    symbols: &'a [LexicalHierarchy],
+    query_string: &str,
+) -> Vec<(&'a LexicalHierarchy, Option<&'a LexicalHierarchy>)> {
+    fn walk<'a>(
+        symbols: &'a [LexicalHierarchy],
+        container: Option<&'a LexicalHierarchy>,
+        query_string: &str,
+        res: &mut Vec<(&'a LexicalHierarchy, Option<&'a LexicalHierarchy>)>,
+    ) {
+        for e in symbols {
+            if e.info.name.contains(query_string) {
+                res.push((e, container));
+            }
+            if let Some(children) = e.children.as_deref() {
+                walk(children, Some(e), query_string, res);
+            }
+        }
     }
+
+    let mut res = vec![];
+    walk(symbols, None, query_string, &mut res);
+    res
 }
 
 #[allow(deprecated)]
-fn filter_document_symbols(
-    symbols: &[LexicalHierarchy],
-    query_string: &str,
+fn symbol_information(
+    e: &LexicalHierarchy,
     source: &Source,
     uri: &Url,
     position_encoding: PositionEncoding,
-) -> Vec<SymbolInformation> {
-    symbols
-        .iter()
-        .flat_map(|e| {
-            [e].into_iter()
-                .chain(e.children.as_deref().into_iter().flatten())
-        })
-        .filter(|e| e.info.name.contains(query_string))
-        .map(|e| {
-            let rng = typst_to_lsp::range(e.info.range.clone(), source, position_encoding);
-
-            SymbolInformation {
-                name: e.info.name.clone(),
-                kind: e.info.kind.clone().try_into().unwrap(),
-                tags: None,
-                deprecated: None,
-                location: LspLocation {
-                    uri: uri.clone(),
-                    range: rng,
-                },
-                container_name: None,
-            }
-        })
-        .collect()
+) -> SymbolInformation {
+    let rng = typst_to_lsp::range(e.info.range.clone(), source, position_encoding);
+
+    SymbolInformation {
+        name: e.info.name.clone(),
+        kind: e.info.kind.clone().try_into().unwrap(),
+        tags: None,
+        deprecated: None,
+        location: LspLocation {
+            uri: uri.clone(),
+            range: rng,
+        },
+        container_name: None,
+    }
+}
+
+fn symbol_stub(e: &LexicalHierarchy, uri: &Url) -> WorkspaceSymbol {
+    WorkspaceSymbol {
+        name: e.info.name.clone(),
+        kind: e.info.kind.clone().try_into().unwrap(),
+        tags: None,
+        container_name: None,
+        location: OneOf::Right(WorkspaceLocation { uri: uri.clone() }),
+        data: serde_json::to_value(&e.info.range).ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn test_resolve_stub() {
+        run_with_ctx(
+            "= Intro\n#let greet(name) = name\n#let value = 1",
+            |ctx, path| {
+                let request = SymbolRequest {
+                    pattern: Some("val".to_owned()),
+                    lazy: true,
+                };
+                let Some(WorkspaceSymbolResponse::Nested(stubs)) = request.request(ctx) else {
+                    panic!("expected nested symbols");
+                };
+                let stub = stubs.into_iter().find(|s| s.name == "value").unwrap();
+                assert!(matches!(stub.location, OneOf::Right(_)));
+
+                let source = ctx.source_by_path(&path).unwrap();
+                let resolved = WorkspaceSymbolResolveRequest {
+                    path: path.clone(),
+                    symbol: stub,
+                }
+                .request(&source, ctx.position_encoding())
+                .unwrap();
+                let OneOf::Left(location) = resolved.location else {
+                    panic!("location is not resolved");
+                };
+                assert_eq!(location.uri, path_to_url(&path).unwrap());
+                assert_eq!(location.range.start, LspPosition::new(2, 5));
+                assert_eq!(location.range.end, LspPosition::new(2, 10));
+                assert_eq!(resolved.container_name.as_deref(), Some("Intro"));
+            },
+        );
+    }
+
+    #[test]
+    fn test_nested_symbols() {
+        run_with_ctx(
+            "= Intro\n== Usage\n#let greet(name) = {\n  let greeting = \"Hello\"\n  greeting + name\n}",
+            |ctx, path| {
+                let request = SymbolRequest {
+                    pattern: Some("greeting".to_owned()),
+                    lazy: true,
+                };
+                let Some(WorkspaceSymbolResponse::Nested(stubs)) = request.request(ctx) else {
+                    panic!("expected nested symbols");
+                };
+                let names = stubs.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+                assert_eq!(names, ["greeting"]);
+
+                let source = ctx.source_by_path(&path).unwrap();
+                let resolved = WorkspaceSymbolResolveRequest {
+                    path,
+                    symbol: stubs.into_iter().next().unwrap(),
+                }
+                .request(&source, ctx.position_encoding())
+                .unwrap();
+                let OneOf::Left(location) = resolved.location else {
+                    panic!("location is not resolved");
+                };
+                assert_eq!(location.range.start, LspPosition::new(3, 6));
+                assert_eq!(resolved.container_name.as_deref(), Some("greet"));
+            },
+        );
+    }
 }
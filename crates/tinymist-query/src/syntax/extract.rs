@@ -0,0 +1,433 @@
+//! Extracting a selected expression or markup into a `#let` binding.
+//!
+//! The binding is inserted at the start of the line of the top-level node
+//! containing the selection, so that it sees the same top-level bindings as
+//! the selection. If the selection references local variables, e.g. the
+//! parameters of a closure, the binding is a function taking them instead.
+//!
+//! The identical expressions after the binding are replaced as well if the
+//! identifiers in them refer to the same bindings as in the selection, which
+//! is resolved by the syntax.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use ecow::{eco_format, EcoString};
+use typst::syntax::{
+    ast::{self, AstNode},
+    is_id_continue, is_id_start, LinkedNode, Source, Span, SyntaxKind, SyntaxNode,
+};
+
+/// The name of extracted bindings, which is suffixed by a number on collision.
+const BASE_NAME: &str = "extracted";
+
+/// A selection extracted into a `#let` binding.
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    /// The name of the binding.
+    pub name: EcoString,
+    /// Whether the binding is a function of the referenced local variables.
+    pub is_function: bool,
+    /// The offset where the binding is inserted, which starts a line.
+    pub insert_at: usize,
+    /// The binding, e.g. `#let extracted = 1 + 2\n`.
+    pub binding: String,
+    /// The range of the selection and the reference to the binding replacing
+    /// it.
+    pub replacement: (Range<usize>, String),
+    /// The ranges of the expressions identical to the selected one and
+    /// referring to the same bindings, and the references replacing them,
+    /// including the selection. It is empty if the binding is a function or
+    /// the selection is markup.
+    pub occurrences: Vec<(Range<usize>, String)>,
+}
+
+/// Extract the selection into a binding, if it is an expression or a run of
+/// complete markup nodes.
+pub fn extract_to_let(source: &Source, range: Range<usize>) -> Option<Extraction> {
+    let text = source.text().get(range.clone())?;
+    let start = range.start + (text.len() - text.trim_start().len());
+    let end = range.end - (text.len() - text.trim_end().len());
+    if start >= end {
+        return None;
+    }
+
+    let root = LinkedNode::new(source.root());
+    let selected = select(root.leaf_at(start + 1)?, start..end)?;
+    let (nodes, value, expr) = match &selected {
+        Selected::Expr(node) => {
+            let value = source.text()[node.range()].to_owned();
+            (vec![node.clone()], value, Some(node))
+        }
+        Selected::Markup(nodes) => {
+            let value = format!("[{}]", &source.text()[start..end]);
+            (nodes.clone(), value, None)
+        }
+    };
+
+    let locals = local_bindings(&nodes[0]);
+    let mut params = Vec::<EcoString>::new();
+    for node in &nodes {
+        for ident in referenced_idents(node) {
+            if locals.contains(&ident) && !params.contains(&ident) {
+                params.push(ident);
+            }
+        }
+    }
+
+    let used = all_idents(source.root());
+    let name = std::iter::once(EcoString::from(BASE_NAME))
+        .chain((2..).map(|i| eco_format!("{BASE_NAME}{i}")))
+        .find(|name| !used.contains(name))?;
+
+    let is_function = !params.is_empty();
+    let call = if is_function {
+        format!("{name}({})", params.join(", "))
+    } else {
+        name.to_string()
+    };
+    let binding = if is_function {
+        format!("#let {call} = {value}\n")
+    } else {
+        format!("#let {name} = {value}\n")
+    };
+
+    let reference = |range: Range<usize>, embedded: bool| {
+        let hash = if embedded { "#" } else { "" };
+        let semicolon = if needs_semicolon(&source.text()[range.end..]) {
+            ";"
+        } else {
+            ""
+        };
+        (range, format!("{hash}{call}{semicolon}"))
+    };
+    let replacement = match expr {
+        Some(node) if is_embedded(node) => reference(node.range(), false),
+        Some(node) => (node.range(), call.clone()),
+        None => reference(start..end, true),
+    };
+
+    let insert_at = insertion_offset(&root, start);
+    let mut occurrences = vec![];
+    if let (Some(expr), false) = (expr, is_function) {
+        let mut names = referenced_idents(expr);
+        names.sort();
+        names.dedup();
+        let definitions = |node: &LinkedNode| {
+            let definitions = names.iter().map(|name| definition(node, name));
+            definitions.collect::<Vec<_>>()
+        };
+        let expected = definitions(expr);
+        identical_exprs(root, expr, insert_at, &mut |node| {
+            if definitions(&node) != expected {
+                return;
+            }
+            occurrences.push(if is_embedded(&node) {
+                reference(node.range(), false)
+            } else {
+                (node.range(), call.clone())
+            });
+        });
+    }
+
+    Some(Extraction {
+        name,
+        is_function,
+        insert_at,
+        binding,
+        replacement,
+        occurrences,
+    })
+}
+
+/// A selected expression or run of markup nodes.
+enum Selected<'a> {
+    Expr(LinkedNode<'a>),
+    Markup(Vec<LinkedNode<'a>>),
+}
+
+/// Find the outermost expression spanning exactly the range, or otherwise the
+/// markup nodes spanning it.
+fn select<'a>(leaf: LinkedNode<'a>, range: Range<usize>) -> Option<Selected<'a>> {
+    let mut expr = None;
+    let mut node = Some(leaf);
+    while let Some(current) = node {
+        let current_range = current.range();
+        if current_range.start <= range.start && range.end <= current_range.end {
+            if current_range == range && is_extractable(&current) {
+                expr = Some(current.clone());
+            } else if expr.is_none() && current.kind() == SyntaxKind::Markup {
+                return markup_run(&current, range);
+            }
+            if current_range != range {
+                break;
+            }
+        }
+        node = current.parent().cloned();
+    }
+    expr.map(Selected::Expr)
+}
+
+/// Get the children of the markup spanning exactly the range.
+fn markup_run<'a>(markup: &LinkedNode<'a>, range: Range<usize>) -> Option<Selected<'a>> {
+    let nodes: Vec<_> = markup
+        .children()
+        .filter(|node| range.start <= node.offset() && node.range().end <= range.end)
+        .collect();
+    let (first, last) = (nodes.first()?, nodes.last()?);
+    if first.offset() != range.start || last.range().end != range.end {
+        return None;
+    }
+
+    // An embedded expression along with its hash.
+    if let [hash, expr] = &nodes[..] {
+        if hash.kind() == SyntaxKind::Hash && is_extractable(expr) {
+            return Some(Selected::Expr(expr.clone()));
+        }
+    }
+    // Bindings and rules would be scoped to the extracted content.
+    let scoped = nodes.iter().any(|node| {
+        matches!(
+            node.kind(),
+            SyntaxKind::LetBinding
+                | SyntaxKind::SetRule
+                | SyntaxKind::ShowRule
+                | SyntaxKind::ModuleImport
+        )
+    });
+    (!scoped).then_some(Selected::Markup(nodes))
+}
+
+/// Whether the node is an expression evaluated in place, rather than e.g. the
+/// pattern of a binding or the name of a field.
+fn is_extractable(node: &LinkedNode) -> bool {
+    use SyntaxKind::*;
+    if !matches!(
+        node.kind(),
+        Ident
+            | None
+            | Auto
+            | Bool
+            | Int
+            | Float
+            | Numeric
+            | Str
+            | CodeBlock
+            | ContentBlock
+            | Parenthesized
+            | Array
+            | Dict
+            | Unary
+            | Binary
+            | FieldAccess
+            | FuncCall
+            | Closure
+            | Conditional
+    ) {
+        return false;
+    }
+
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+    let after = |kinds: &[SyntaxKind]| {
+        (parent.children().take(node.index())).any(|prev| kinds.contains(&prev.kind()))
+    };
+    match parent.kind() {
+        LetBinding | Closure => after(&[Eq, Arrow]),
+        ForLoop => after(&[In]),
+        Named | Keyed => after(&[Colon]),
+        FieldAccess => node.index() == 0,
+        Markup | Math => is_embedded(node),
+        Params | Destructuring | DestructAssignment | ImportItems | RenamedImportItem
+        | ModuleImport => false,
+        _ => true,
+    }
+}
+
+/// Whether the node is an expression embedded in markup or math by a hash.
+fn is_embedded(node: &LinkedNode) -> bool {
+    matches!(
+        node.parent_kind(),
+        Some(SyntaxKind::Markup | SyntaxKind::Math)
+    ) && node.prev_sibling_kind() == Some(SyntaxKind::Hash)
+}
+
+/// Whether a reference embedded in markup must be terminated by a semicolon
+/// before the text, which would otherwise continue the reference.
+fn needs_semicolon(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some('(' | '[') => true,
+        Some('.') => chars.next().is_some_and(is_id_start),
+        Some(c) => is_id_continue(c),
+        None => false,
+    }
+}
+
+/// Get the names of the local variables visible to the node, i.e. bound in
+/// its ancestors other than the top level.
+fn local_bindings(node: &LinkedNode) -> HashSet<EcoString> {
+    let mut names = HashSet::new();
+    let mut bind = |idents: Vec<ast::Ident>| {
+        names.extend(idents.into_iter().map(|ident| ident.get().clone()));
+    };
+
+    let mut child = node.clone();
+    while let Some(parent) = child.parent() {
+        // The bindings of the top level are visible to the inserted binding.
+        if parent.parent().is_none() {
+            break;
+        }
+
+        if let Some(closure) = parent.cast::<ast::Closure>() {
+            bind(closure.name().into_iter().collect());
+            for param in closure.params().children() {
+                bind(match param {
+                    ast::Param::Pos(pattern) => pattern.bindings(),
+                    ast::Param::Named(named) => vec![named.name()],
+                    ast::Param::Spread(spread) => spread.sink_ident().into_iter().collect(),
+                });
+            }
+        }
+        if let Some(for_loop) = parent.cast::<ast::ForLoop>() {
+            bind(for_loop.pattern().bindings());
+        }
+        for prev in parent.children().take(child.index()) {
+            if let Some(binding) = prev.cast::<ast::LetBinding>() {
+                bind(binding.kind().bindings());
+            }
+        }
+        child = parent.clone();
+    }
+    names
+}
+
+/// Find the binding the name refers to at the node by the syntax, i.e. the
+/// span of the identifier binding it, or of the wildcard import possibly
+/// binding it. It is `None` for the names bound outside the source, e.g. those
+/// of the standard library.
+fn definition(node: &LinkedNode, name: &str) -> Option<Span> {
+    let find = |idents: Vec<ast::Ident>| {
+        let ident = idents.into_iter().rev().find(|id| id.as_str() == name);
+        ident.map(|ident| ident.span())
+    };
+
+    let mut child = node.clone();
+    while let Some(parent) = child.parent() {
+        // The latest binding before the node shadows the earlier ones.
+        for prev in parent.children().take(child.index()).rev() {
+            if let Some(binding) = prev.cast::<ast::LetBinding>() {
+                if let Some(span) = find(binding.kind().bindings()) {
+                    return Some(span);
+                }
+            }
+            if let Some(import) = prev.cast::<ast::ModuleImport>() {
+                let idents = match import.imports() {
+                    Some(ast::Imports::Wildcard) => return Some(import.span()),
+                    Some(ast::Imports::Items(items)) => {
+                        items.iter().map(|item| item.bound_name()).collect()
+                    }
+                    None => vec![],
+                };
+                if let Some(span) = find(idents.into_iter().chain(import.new_name()).collect()) {
+                    return Some(span);
+                }
+            }
+        }
+
+        if let Some(closure) = parent.cast::<ast::Closure>() {
+            let mut idents: Vec<_> = closure.name().into_iter().collect();
+            for param in closure.params().children() {
+                idents.extend(match param {
+                    ast::Param::Pos(pattern) => pattern.bindings(),
+                    ast::Param::Named(named) => vec![named.name()],
+                    ast::Param::Spread(spread) => spread.sink_ident().into_iter().collect(),
+                });
+            }
+            if let Some(span) = find(idents) {
+                return Some(span);
+            }
+        }
+        if let Some(for_loop) = parent.cast::<ast::ForLoop>() {
+            // The pattern binds only in the body, after the iterable.
+            if for_loop.body().span() == child.span() {
+                if let Some(span) = find(for_loop.pattern().bindings()) {
+                    return Some(span);
+                }
+            }
+        }
+        child = parent.clone();
+    }
+    None
+}
+
+/// Get the identifiers referenced in the node, in order of appearance.
+fn referenced_idents(node: &LinkedNode) -> Vec<EcoString> {
+    let mut idents = vec![];
+    let mut nodes = vec![node.clone()];
+    while let Some(node) = nodes.pop() {
+        let is_name = match node.parent_kind() {
+            Some(SyntaxKind::FieldAccess) => node.index() != 0,
+            Some(SyntaxKind::Named) => node.index() == 0,
+            _ => false,
+        };
+        if matches!(node.kind(), SyntaxKind::Ident | SyntaxKind::MathIdent) && !is_name {
+            idents.push(node.text().clone());
+        }
+        nodes.extend(node.children().rev());
+    }
+    idents
+}
+
+/// Get the identifiers anywhere in the source, which the name of the binding
+/// must not collide with.
+fn all_idents(root: &SyntaxNode) -> HashSet<EcoString> {
+    let mut idents = HashSet::new();
+    let mut nodes = vec![root];
+    while let Some(node) = nodes.pop() {
+        if matches!(node.kind(), SyntaxKind::Ident | SyntaxKind::MathIdent) {
+            idents.insert(node.text().clone());
+        }
+        nodes.extend(node.children());
+    }
+    idents
+}
+
+/// Get the offset of the line start before the top-level node at the offset,
+/// which is not inside another top-level node.
+fn insertion_offset(root: &LinkedNode, offset: usize) -> usize {
+    let mut at = 0;
+    for child in root.children().take_while(|child| child.offset() < offset) {
+        if matches!(child.kind(), SyntaxKind::Space | SyntaxKind::Parbreak) {
+            if let Some(newline) = child.text().rfind('\n') {
+                at = child.offset() + newline + 1;
+            }
+        }
+    }
+    at
+}
+
+/// Visit the expressions identical to the given one after the offset, which
+/// may refer to other bindings.
+fn identical_exprs(
+    node: LinkedNode,
+    expr: &LinkedNode,
+    after: usize,
+    visit: &mut impl FnMut(LinkedNode),
+) {
+    if node.range().end <= after {
+        return;
+    }
+    if node.offset() >= after
+        && node.kind() == expr.kind()
+        && node.get().spanless_eq(expr.get())
+        && is_extractable(&node)
+    {
+        visit(node);
+        return;
+    }
+    for child in node.children() {
+        identical_exprs(child, expr, after, visit);
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use typst::syntax::{highlight, parse, parse_code, parse_math, LinkedNode, Tag};
+
+/// The color theme preferred by the editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ColorTheme {
+    /// Dark text on a light background.
+    Light,
+    /// Light text on a dark background.
+    Dark,
+}
+
+impl ColorTheme {
+    /// Get the color of a highlighting tag, which is left in the default color
+    /// of the editor if there is none.
+    fn color(self, tag: Tag) -> Option<&'static str> {
+        let (light, dark) = match tag {
+            Tag::Comment => ("#6a737d", "#8b949e"),
+            Tag::Escape | Tag::Number => ("#005cc5", "#79c0ff"),
+            Tag::Label | Tag::Ref | Tag::Heading => ("#005cc5", "#79c0ff"),
+            Tag::Link | Tag::Raw | Tag::String => ("#032f62", "#a5d6ff"),
+            Tag::ListMarker | Tag::ListTerm => ("#e36209", "#ffa657"),
+            Tag::MathDelimiter | Tag::MathOperator => ("#d73a49", "#ff7b72"),
+            Tag::Keyword | Tag::Operator => ("#d73a49", "#ff7b72"),
+            Tag::Function => ("#6f42c1", "#d2a8ff"),
+            Tag::Interpolated => ("#24292e", "#c9d1d9"),
+            Tag::Punctuation | Tag::Strong | Tag::Emph | Tag::Error => return None,
+        };
+        Some(match self {
+            Self::Light => light,
+            Self::Dark => dark,
+        })
+    }
+}
+
+/// Highlight a code snippet of the language, i.e. `typ`, `typc` or `typm`, as
+/// an HTML block with the colors of the theme inlined.
+pub fn highlight_code(code: &str, lang: &str, theme: ColorTheme) -> String {
+    let root = match lang {
+        "typc" => parse_code(code),
+        "typm" => parse_math(code),
+        _ => parse(code),
+    };
+
+    let mut html = String::from("<pre><code>");
+    highlight_node(&mut html, &LinkedNode::new(&root), theme);
+    html.push_str("</code></pre>");
+    html
+}
+
+/// Highlight the fenced typst code blocks in markdown with the theme, leaving
+/// other blocks fenced.
+pub fn highlight_markdown(markdown: &str, theme: ColorTheme) -> String {
+    let mut out = String::new();
+    let mut lines = markdown.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        let lang = line.trim().strip_prefix("```");
+        let Some(lang) = lang.filter(|lang| is_typst(lang)) else {
+            out.push_str(line);
+            continue;
+        };
+
+        let mut code = String::new();
+        for line in lines.by_ref() {
+            if line.trim() == "```" {
+                break;
+            }
+            code.push_str(line);
+        }
+        out.push_str(&highlight_code(code.trim_end(), lang, theme));
+        out.push('\n');
+    }
+    out
+}
+
+fn is_typst(lang: &str) -> bool {
+    matches!(lang, "typ" | "typc" | "typm" | "typst" | "example")
+}
+
+fn highlight_node(html: &mut String, node: &LinkedNode, theme: ColorTheme) {
+    let color = highlight(node).and_then(|tag| theme.color(tag));
+    if let Some(color) = color {
+        html.push_str(&format!("<span style=\"color:{color};\">"));
+    }
+
+    let text = node.text();
+    if !text.is_empty() {
+        escape_into(html, text);
+    } else {
+        for child in node.children() {
+            highlight_node(html, &child, theme);
+        }
+    }
+
+    if color.is_some() {
+        html.push_str("</span>");
+    }
+}
+
+fn escape_into(html: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '&' => html.push_str("&amp;"),
+            '"' => html.push_str("&quot;"),
+            c => html.push(c),
+        }
+    }
+}
@@ -367,7 +367,7 @@ impl LexicalHierarchyWorker {
                 'heading_break: while let Some((w, _)) = self.stack.last() {
                     match w.kind {
                         LexicalKind::Heading(l) if l < level => break 'heading_break,
-                        LexicalKind::Block => break 'heading_break,
+                        LexicalKind::Block | LexicalKind::Var(..) => break 'heading_break,
                         _ if self.stack.len() <= 1 => break 'heading_break,
                         _ => {}
                     }
@@ -417,6 +417,14 @@ impl LexicalHierarchyWorker {
 
                     // reverse order for correct symbol affection
                     let name_offset = pattern.as_ref().map(|e| e.offset());
+                    if self.g == LexicalScopeKind::Symbol {
+                        if let Some(name) = pattern.clone().filter(|n| n.is::<ast::Ident>()) {
+                            let init = node.children().rev().find(|n| n.is::<ast::Expr>());
+                            let init = init.filter(|n| Some(n.offset()) > name_offset);
+                            self.get_symbols_nested(name, IdentContext::Var, init)?;
+                            break 'let_binding;
+                        }
+                    }
                     if self.g == LexicalScopeKind::DefUse {
                         self.get_symbols_in_first_expr(node.children().rev(), name_offset)?;
                         self.get_symbols_in_opt_with(pattern, IdentContext::Var)?;
@@ -438,38 +446,44 @@ impl LexicalHierarchyWorker {
                     self.get_symbols_in_first_expr(node.children().rev(), iterable_offset)?;
                 }
                 SyntaxKind::Closure => {
-                    let n = node.children().next();
-                    if let Some(n) = n {
-                        if n.kind() == SyntaxKind::Ident {
-                            self.get_symbols_with(n, IdentContext::Func)?;
-                        }
-                    }
+                    let name = node.children().next();
+                    let name = name.filter(|n| n.kind() == SyntaxKind::Ident);
                     let body = node
                         .children()
                         .rev()
                         .find(|n| n.cast::<ast::Expr>().is_some());
-                    if let Some(body) = body {
-                        if self.g == LexicalScopeKind::DefUse {
-                            let symbol = LexicalInfo {
-                                name: String::new(),
-                                kind: LexicalKind::Block,
-                                range: body.range(),
-                            };
-                            self.stack.push((symbol, eco_vec![]));
-                            let stack_height = self.stack.len();
 
+                    // The outline nests the symbols in the body under the function.
+                    let nested = name.clone().filter(|_| self.g == LexicalScopeKind::Symbol);
+                    if let Some(name) = nested {
+                        self.get_symbols_nested(name, IdentContext::Func, body)?;
+                    } else {
+                        if let Some(name) = name {
+                            self.get_symbols_with(name, IdentContext::Func)?;
+                        }
+                        if let Some(body) = body {
                             if self.g == LexicalScopeKind::DefUse {
-                                let param =
-                                    node.children().find(|n| n.kind() == SyntaxKind::Params);
-                                self.get_symbols_in_opt_with(param, IdentContext::Params)?;
-                            }
-
-                            self.get_symbols_with(body, IdentContext::Ref)?;
-                            while stack_height <= self.stack.len() {
-                                self.symbreak();
+                                let symbol = LexicalInfo {
+                                    name: String::new(),
+                                    kind: LexicalKind::Block,
+                                    range: body.range(),
+                                };
+                                self.stack.push((symbol, eco_vec![]));
+                                let stack_height = self.stack.len();
+
+                                if self.g == LexicalScopeKind::DefUse {
+                                    let param =
+                                        node.children().find(|n| n.kind() == SyntaxKind::Params);
+                                    self.get_symbols_in_opt_with(param, IdentContext::Params)?;
+                                }
+
+                                self.get_symbols_with(body, IdentContext::Ref)?;
+                                while stack_height <= self.stack.len() {
+                                    self.symbreak();
+                                }
+                            } else {
+                                self.get_symbols_with(body, IdentContext::Ref)?;
                             }
-                        } else {
-                            self.get_symbols_with(body, IdentContext::Ref)?;
                         }
                     }
                 }
@@ -557,6 +571,32 @@ impl LexicalHierarchyWorker {
         res
     }
 
+    /// Get the symbol of a name, with the symbols in the body of its
+    /// definition as its children, e.g. the local bindings of a function.
+    fn get_symbols_nested(
+        &mut self,
+        name: LinkedNode,
+        context: IdentContext,
+        body: Option<LinkedNode>,
+    ) -> anyhow::Result<()> {
+        let c = self.ident_context;
+        self.ident_context = context;
+        let symbol = self.get_ident(&name);
+        self.ident_context = c;
+
+        let Some(symbol) = symbol? else {
+            return self.get_symbols_in_opt_with(body, IdentContext::Ref);
+        };
+        self.stack.push((symbol, eco_vec![]));
+        let stack_height = self.stack.len();
+        self.get_symbols_in_opt_with(body, IdentContext::Ref)?;
+        while stack_height <= self.stack.len() {
+            self.symbreak();
+        }
+
+        Ok(())
+    }
+
     /// Get symbol for a leaf node of a valid type, or `None` if the node is an
     /// invalid type.
     #[allow(deprecated)]
@@ -1,4 +1,6 @@
-use ecow::EcoVec;
+use std::ops::Range;
+
+use ecow::{EcoString, EcoVec};
 use typst::{
     foundations::{Func, ParamInfo},
     syntax::{
@@ -481,3 +483,145 @@ fn find_param_index(deciding: &LinkedNode, params: &[ParamInfo], args: ast::Args
         _ => None,
     }
 }
+
+/// A field accessed on a target, whose name is being typed at a leaf.
+pub struct FieldTarget<'a> {
+    /// The target of the field access.
+    pub target: LinkedNode<'a>,
+    /// The range of the field name, which is empty right after the dot.
+    pub range: Range<usize>,
+    /// Whether the field name is quoted, i.e. accessed by `at`.
+    pub quoted: bool,
+}
+
+/// Get the field accessed at a leaf, i.e. in `target.`, `target.field` or
+/// `target.at("field")`.
+pub fn get_field_target<'a>(leaf: &LinkedNode<'a>) -> Option<FieldTarget<'a>> {
+    let (target, range, quoted) = match leaf.kind() {
+        // `target.`
+        SyntaxKind::Dot | SyntaxKind::Text if leaf.text() == "." => {
+            let target = leaf.prev_sibling()?;
+            (target, leaf.range().end..leaf.range().end, false)
+        }
+        // `target.field`
+        SyntaxKind::Ident if leaf.parent_kind() == Some(SyntaxKind::FieldAccess) => {
+            let access = leaf.parent()?.cast::<ast::FieldAccess>()?;
+            if access.field().span() != leaf.span() {
+                return None;
+            }
+            let target = leaf.parent()?.find(access.target().span())?;
+            (target, leaf.range(), false)
+        }
+        // `target.at("field")`
+        SyntaxKind::Str if leaf.parent_kind() == Some(SyntaxKind::Args) => {
+            let call = leaf.parent()?.parent()?;
+            let callee = call.cast::<ast::FuncCall>()?.callee();
+            let ast::Expr::FieldAccess(access) = callee else {
+                return None;
+            };
+            if access.field().as_str() != "at" {
+                return None;
+            }
+            let target = call.find(access.target().span())?;
+            (target, leaf.range(), true)
+        }
+        _ => return None,
+    };
+
+    Some(FieldTarget {
+        target,
+        range,
+        quoted,
+    })
+}
+
+/// Check whether the node is `sys.inputs`.
+pub fn is_sys_inputs(node: &LinkedNode) -> bool {
+    let Some(access) = node.cast::<ast::FieldAccess>() else {
+        return false;
+    };
+    let is_sys = matches!(access.target(), ast::Expr::Ident(sys) if sys.as_str() == "sys");
+    is_sys && access.field().as_str() == "inputs"
+}
+
+/// The argument of a call at the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActiveArg {
+    /// The positional argument of the index, counting the positional arguments
+    /// before it.
+    Positional(usize),
+    /// A positional argument after a spread argument.
+    AfterSpread,
+    /// The named argument of the name.
+    Named(EcoString),
+}
+
+/// Find the innermost call whose parentheses contain the cursor, returning its
+/// callee and the argument at the cursor.
+pub fn active_arg<'a>(leaf: &LinkedNode<'a>, cursor: usize) -> Option<(LinkedNode<'a>, ActiveArg)> {
+    let mut node = leaf.clone();
+    let (args, callee) = loop {
+        let parent = node.parent()?.clone();
+        if node.kind() == SyntaxKind::Args && in_parens(&node, cursor) {
+            let callee = match parent.cast::<ast::Expr>() {
+                Some(ast::Expr::FuncCall(call)) => call.callee().span(),
+                Some(ast::Expr::Set(set)) => set.target().span(),
+                _ => return None,
+            };
+            break (node, parent.find(callee)?);
+        }
+        node = parent;
+    };
+
+    let mut positional = 0;
+    let mut spread = false;
+    for child in args.children() {
+        let Some(arg) = child.cast::<ast::Arg>() else {
+            continue;
+        };
+        if child.offset() <= cursor && cursor <= child.range().end {
+            return Some(match arg {
+                ast::Arg::Named(named) => (callee, ActiveArg::Named(named.name().get().clone())),
+                ast::Arg::Spread(_) => (callee, ActiveArg::AfterSpread),
+                ast::Arg::Pos(_) if spread => (callee, ActiveArg::AfterSpread),
+                ast::Arg::Pos(_) => (callee, ActiveArg::Positional(positional)),
+            });
+        }
+        if child.range().end > cursor {
+            break;
+        }
+        match arg {
+            ast::Arg::Pos(_) => positional += 1,
+            ast::Arg::Spread(_) => spread = true,
+            ast::Arg::Named(_) => {}
+        }
+    }
+
+    // The value of a named argument is not typed yet, e.g. `key: |`.
+    let mut prev = args.leaf_at(cursor)?;
+    while prev.kind().is_trivia() || prev.offset() >= cursor {
+        prev = prev.prev_leaf()?;
+    }
+    if prev.kind() == SyntaxKind::Colon {
+        if let Some(name) = prev.prev_leaf().and_then(|name| name.cast::<ast::Ident>()) {
+            return Some((callee, ActiveArg::Named(name.get().clone())));
+        }
+    }
+
+    let target = if spread {
+        ActiveArg::AfterSpread
+    } else {
+        ActiveArg::Positional(positional)
+    };
+    Some((callee, target))
+}
+
+/// Whether the cursor is inside the parentheses of the arguments.
+pub fn in_parens(args: &LinkedNode, cursor: usize) -> bool {
+    let mut children = args.children();
+    let Some(left) = children.find(|child| child.kind() == SyntaxKind::LeftParen) else {
+        return false;
+    };
+    let right = children.find(|child| child.kind() == SyntaxKind::RightParen);
+    left.range().end <= cursor && right.map_or(true, |right| cursor <= right.offset())
+}
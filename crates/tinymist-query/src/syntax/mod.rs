@@ -15,6 +15,10 @@ pub(crate) mod module;
 pub use module::*;
 pub(crate) mod comment;
 pub use comment::*;
+pub(crate) mod highlight;
+pub use highlight::*;
+pub(crate) mod extract;
+pub use extract::*;
 
 use core::fmt;
 use std::ops::Range;
@@ -71,12 +71,66 @@ fn is_hidden(entry: &walkdir::DirEntry) -> bool {
         .unwrap_or(false)
 }
 
+/// Glob patterns of paths in the workspace that are not indexed, e.g.
+/// `drafts/**` or `*.bak.typ`.
+///
+/// A pattern without a slash matches the name of a file or directory at any
+/// depth, and a matched directory ignores everything inside it.
+#[derive(Debug, Clone, Default)]
+pub struct IgnorePatterns(Option<RegexSet>);
+
+impl IgnorePatterns {
+    /// Compile the glob patterns, where `*` and `?` match within a path
+    /// segment and `**` matches across segments.
+    pub fn new(globs: &[String]) -> Result<Self, regex::Error> {
+        if globs.is_empty() {
+            return Ok(Self::default());
+        }
+        RegexSet::new(globs.iter().map(|glob| glob_to_regex(glob))).map(|set| Self(Some(set)))
+    }
+
+    /// Whether the path relative to the workspace root is ignored.
+    pub fn is_match(&self, relative_path: &Path) -> bool {
+        let Some(set) = &self.0 else {
+            return false;
+        };
+        let path = relative_path.to_string_lossy().replace('\\', "/");
+        set.is_match(&path)
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let glob = glob.trim_start_matches("./").trim_end_matches('/');
+    let mut re = String::from(if glob.contains('/') { "^" } else { "^(?:.*/)?" });
+    let mut chars = glob.trim_start_matches('/').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `**/` also matches no directory at all.
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    re.push_str("(?:.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re.push_str("(?:/.*)?$");
+    re
+}
+
 /// Scan the files in the workspace and return the file ids.
 ///
 /// Note: this function will touch the physical file system.
 pub(crate) fn scan_workspace_files<T>(
     root: &Path,
     ext: &RegexSet,
+    ignore: &IgnorePatterns,
     f: impl Fn(&Path) -> T,
 ) -> Vec<T> {
     let mut res = vec![];
@@ -117,6 +171,18 @@ pub(crate) fn scan_workspace_files<T>(
             continue;
         }
 
+        if de.depth() > 0
+            && de
+                .path()
+                .strip_prefix(root)
+                .is_ok_and(|path| ignore.is_match(path))
+        {
+            if de.file_type().is_dir() {
+                it.skip_current_dir();
+            }
+            continue;
+        }
+
         if !de.file_type().is_file() {
             continue;
         }
@@ -148,3 +214,28 @@ pub(crate) fn scan_workspace_files<T>(
 
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_patterns() {
+        let globs = ["drafts", "chapters/old/**", "*.bak.typ", "**/tmp?.typ"];
+        let ignore = IgnorePatterns::new(&globs.map(String::from)).unwrap();
+        let is_ignored = |path: &str| ignore.is_match(Path::new(path));
+
+        assert!(is_ignored("drafts"));
+        assert!(is_ignored("notes/drafts/intro.typ"));
+        assert!(is_ignored("chapters/old/a/b.typ"));
+        assert!(is_ignored("main.bak.typ"));
+        assert!(is_ignored("tmp1.typ"));
+        assert!(is_ignored("a/tmp1.typ"));
+
+        assert!(!is_ignored("chapters/intro.typ"));
+        assert!(!is_ignored("old/chapters.typ"));
+        assert!(!is_ignored("drafts.typ"));
+        assert!(!is_ignored("tmp10.typ"));
+        assert!(!IgnorePatterns::default().is_match(Path::new("drafts")));
+    }
+}
@@ -1,10 +1,15 @@
 use core::fmt;
 use std::{
+    borrow::Cow,
     collections::{HashMap, HashSet},
-    ops::Range,
+    ops::{Deref, Range},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
+use comemo::Prehashed;
+use ecow::{eco_vec, EcoVec};
+use lsp_types::{CompletionItem, CompletionResponse};
 use once_cell::sync::Lazy;
 pub use serde::Serialize;
 use serde_json::{ser::PrettyFormatter, Serializer, Value};
@@ -12,7 +17,10 @@ use typst::syntax::{
     ast::{self, AstNode},
     FileId as TypstFileId, LinkedNode, Source, SyntaxKind, VirtualPath,
 };
-use typst::{diag::PackageError, foundations::Bytes};
+use typst::{
+    diag::PackageError,
+    foundations::{Bytes, Dict},
+};
 use typst_ts_compiler::{
     service::{CompileDriver, Compiler, EntryManager},
     NotifyApi, ShadowApi,
@@ -29,7 +37,8 @@ pub use typst_ts_compiler::TypstSystemWorld;
 use crate::{
     analysis::{Analysis, AnalysisResources},
     prelude::AnalysisContext,
-    typst_to_lsp, LspPosition, PositionEncoding,
+    typst_to_lsp, CompletionRequest, LspPosition, PositionEncoding, StatefulRequest,
+    VersionedDocument,
 };
 
 struct WrapWorld<'a>(&'a mut TypstSystemWorld);
@@ -46,6 +55,20 @@ impl<'a> AnalysisResources for WrapWorld<'a> {
     fn iter_dependencies(&self, f: &mut dyn FnMut(&reflexo::ImmutPath, typst_ts_compiler::Time)) {
         self.0.iter_dependencies(f)
     }
+
+    fn inputs(&self) -> Dict {
+        self.0.inputs.as_ref().deref().clone()
+    }
+
+    fn local_package_dir(&self, spec: &PackageSpec) -> Option<Arc<Path>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/packages");
+        let dir = dir.join(format!("{}/{}/{}", spec.namespace, spec.name, spec.version));
+        dir.is_dir().then(|| dir.into())
+    }
+
+    fn preview_packages(&self) -> EcoVec<PackageSpec> {
+        eco_vec!["@preview/cetz:0.2.2".parse().unwrap()]
+    }
 }
 
 pub fn snapshot_testing(name: &str, f: &impl Fn(&mut AnalysisContext, PathBuf)) {
@@ -59,33 +82,97 @@ pub fn snapshot_testing(name: &str, f: &impl Fn(&mut AnalysisContext, PathBuf))
             #[cfg(windows)]
             let contents = contents.replace("\r\n", "\n");
 
-            run_with_sources(&contents, |w: &mut TypstSystemWorld, p| {
-                let root = w.workspace_root().unwrap();
-                let paths = w
-                    .shadow_paths()
-                    .into_iter()
-                    .map(|p| {
-                        TypstFileId::new(None, VirtualPath::new(p.strip_prefix(&root).unwrap()))
-                    })
-                    .collect::<Vec<_>>();
-                let w = WrapWorld(w);
-                let mut ctx = AnalysisContext::new(
-                    &w,
-                    Analysis {
-                        root,
-                        position_encoding: PositionEncoding::Utf16,
-                        enable_periscope: false,
-                        caches: Default::default(),
-                    },
-                );
-                ctx.test_completion_files(Vec::new);
-                ctx.test_files(|| paths);
-                f(&mut ctx, p);
-            });
+            run_with_ctx(&contents, f);
         });
     });
 }
 
+pub fn run_with_ctx<T>(source: &str, f: impl FnOnce(&mut AnalysisContext, PathBuf) -> T) -> T {
+    run_with_ctx_and_inputs(source, Dict::new(), f)
+}
+
+/// Run with the inputs provided to the world, i.e. `sys.inputs`.
+pub fn run_with_ctx_and_inputs<T>(
+    source: &str,
+    inputs: Dict,
+    f: impl FnOnce(&mut AnalysisContext, PathBuf) -> T,
+) -> T {
+    run_with_sources(source, |w, p| run_in_world(w, p, inputs, f))
+}
+
+/// Run with the fonts embedded in Typst, for the tests rendering text, which
+/// must not depend on the fonts of the system.
+pub fn run_with_ctx_and_fonts<T>(
+    source: &str,
+    f: impl FnOnce(&mut AnalysisContext, PathBuf) -> T,
+) -> T {
+    let fonts = typst_assets::fonts().map(Cow::Borrowed).collect();
+    run_with_sources_and_fonts(source, fonts, |w, p| run_in_world(w, p, Dict::new(), f))
+}
+
+fn run_in_world<T>(
+    w: &mut TypstSystemWorld,
+    p: PathBuf,
+    inputs: Dict,
+    f: impl FnOnce(&mut AnalysisContext, PathBuf) -> T,
+) -> T {
+    w.inputs = Arc::new(Prehashed::new(inputs));
+    let root = w.workspace_root().unwrap();
+    let paths = w
+        .shadow_paths()
+        .into_iter()
+        .map(|p| TypstFileId::new(None, VirtualPath::new(p.strip_prefix(&root).unwrap())))
+        .collect::<Vec<_>>();
+    let w = WrapWorld(w);
+    let mut ctx = AnalysisContext::new(&w, test_analysis(root));
+    ctx.test_completion_files(Vec::new);
+    ctx.test_files(|| paths);
+    f(&mut ctx, p)
+}
+
+fn test_analysis(root: Arc<Path>) -> Analysis {
+    Analysis {
+        root,
+        position_encoding: PositionEncoding::Utf16,
+        enable_periscope: false,
+        preferred_theme: None,
+        markdown_html: false,
+        markdown_images: true,
+        hover_math_preview: false,
+        hover_set_rules: false,
+        inlay_hint_document_values: false,
+        index_ignore: Default::default(),
+        user_snippets: Vec::new(),
+        caches: Default::default(),
+    }
+}
+
+/// A workspace in a directory on disk, whose files are scanned and read from
+/// the disk rather than provided to the world.
+pub struct DiskWorkspace {
+    world: TypstSystemWorld,
+    analysis: Analysis,
+}
+
+impl DiskWorkspace {
+    pub fn new(root: &Path) -> Self {
+        let world = TypstSystemWorld::new(CompileOpts {
+            entry: EntryOpts::new_rooted(root.into(), None),
+            ..Default::default()
+        })
+        .unwrap();
+        let analysis = test_analysis(root.into());
+        Self { world, analysis }
+    }
+
+    /// Run with a new context, as the server creates one per request.
+    pub fn run<T>(&mut self, f: impl FnOnce(&mut AnalysisContext) -> T) -> T {
+        let w = WrapWorld(&mut self.world);
+        let mut ctx = AnalysisContext::new_borrow(&w, &mut self.analysis);
+        f(&mut ctx)
+    }
+}
+
 pub fn get_test_properties(s: &str) -> HashMap<&'_ str, &'_ str> {
     let mut props = HashMap::new();
     for line in s.lines() {
@@ -98,6 +185,14 @@ pub fn get_test_properties(s: &str) -> HashMap<&'_ str, &'_ str> {
 }
 
 pub fn run_with_sources<T>(source: &str, f: impl FnOnce(&mut TypstSystemWorld, PathBuf) -> T) -> T {
+    run_with_sources_and_fonts(source, Vec::new(), f)
+}
+
+fn run_with_sources_and_fonts<T>(
+    source: &str,
+    fonts: Vec<Cow<'static, [u8]>>,
+    f: impl FnOnce(&mut TypstSystemWorld, PathBuf) -> T,
+) -> T {
     let root = if cfg!(windows) {
         PathBuf::from("C:\\")
     } else {
@@ -105,6 +200,7 @@ pub fn run_with_sources<T>(source: &str, f: impl FnOnce(&mut TypstSystemWorld, P
     };
     let mut world = TypstSystemWorld::new(CompileOpts {
         entry: EntryOpts::new_rooted(root.as_path().into(), None),
+        with_embedded_fonts: fonts,
         ..Default::default()
     })
     .unwrap();
@@ -156,6 +252,26 @@ pub fn run_with_sources<T>(source: &str, f: impl FnOnce(&mut TypstSystemWorld, P
     f(driver.world_mut(), pw)
 }
 
+/// Complete at the cursor in the file, returning the items of the completion
+/// list, which are empty if no list is completed.
+pub fn complete_at(
+    ctx: &mut AnalysisContext,
+    path: &Path,
+    cursor: usize,
+    doc: Option<VersionedDocument>,
+) -> Vec<CompletionItem> {
+    let source = ctx.source_by_path(path).unwrap();
+    let request = CompletionRequest {
+        path: path.to_owned(),
+        position: ctx.to_lsp_pos(cursor, &source),
+        explicit: false,
+    };
+    match request.request(ctx, doc) {
+        Some(CompletionResponse::List(list)) => list.items,
+        _ => vec![],
+    }
+}
+
 pub fn find_test_range(s: &Source) -> Range<usize> {
     // /* range -3..-1 */
     fn find_prefix(s: &str, sub: &str, left: bool) -> Option<(usize, usize, bool)> {
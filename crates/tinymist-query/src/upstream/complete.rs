@@ -6,9 +6,10 @@ use ecow::{eco_format, EcoString};
 use if_chain::if_chain;
 use serde::{Deserialize, Serialize};
 use typst::foundations::{
-    fields_on, format_str, mutable_methods_on, repr, AutoValue, CastInfo, Func, Label, NoneValue,
-    Repr, StyleChain, Styles, Type, Value,
+    fields_on, format_str, mutable_methods_on, AutoValue, CastInfo, Func, Label, NoneValue, Repr,
+    StyleChain, Styles, Type, Value,
 };
+use typst::math::EquationElem;
 use typst::model::Document;
 use typst::syntax::ast::AstNode;
 use typst::syntax::{ast, is_id_continue, is_id_start, is_ident, LinkedNode, Source, SyntaxKind};
@@ -18,7 +19,9 @@ use unscanny::Scanner;
 
 use super::{plain_docs_sentence, summarize_font_family};
 use crate::analysis::{analyze_expr, analyze_import, analyze_labels, DynLabel};
-use crate::AnalysisContext;
+use crate::completion::CompletionData;
+use crate::hover::math_previewed;
+use crate::{AnalysisContext, SnippetScope};
 
 mod ext;
 pub use ext::complete_path;
@@ -39,6 +42,7 @@ pub fn autocomplete(
     mut ctx: CompletionContext,
 ) -> Option<(usize, bool, Vec<Completion>, Vec<lsp_types::CompletionItem>)> {
     let _ = complete_comments(&mut ctx)
+        || complete_raw_lang(&mut ctx)
         || complete_literal(&mut ctx).is_none() && {
             log::info!("continue after completing literal");
             complete_field_accesses(&mut ctx)
@@ -72,6 +76,10 @@ pub struct Completion {
     pub apply: Option<EcoString>,
     /// An optional short description, at most one sentence.
     pub detail: Option<EcoString>,
+    /// An optional documentation in markdown, shown when the item is selected.
+    pub docs: Option<EcoString>,
+    /// The data to resolve the item lazily, e.g. the equation to preview.
+    pub data: Option<CompletionData>,
     /// An optional command to run when the completion is selected.
     pub command: Option<&'static str>,
 }
@@ -113,6 +121,36 @@ fn complete_comments(ctx: &mut CompletionContext) -> bool {
     )
 }
 
+/// Complete the language tag of a raw block: "```|" or "```ru|", either
+/// closed or not.
+fn complete_raw_lang(ctx: &mut CompletionContext) -> bool {
+    let start = match ctx.leaf.kind() {
+        SyntaxKind::RawLang => ctx.leaf.parent().map(|raw| raw.offset()),
+        SyntaxKind::RawDelim if ctx.leaf.index() == 0 => Some(ctx.leaf.offset()),
+        SyntaxKind::Error if ctx.leaf.text().starts_with("```") => Some(ctx.leaf.offset()),
+        _ => None,
+    };
+    let Some(start) = start else {
+        return false;
+    };
+
+    let mut s = Scanner::new(ctx.text);
+    s.jump(start);
+    if !s.eat_if("```") {
+        return false;
+    }
+    s.eat_while('`');
+    let from = s.cursor();
+    if s.eat_if(is_id_start) {
+        s.eat_while(is_id_continue);
+    }
+    if s.cursor() == ctx.cursor {
+        ctx.from = from;
+        ctx.raw_completions();
+    }
+    true
+}
+
 /// Complete in markup mode.
 fn complete_markup(ctx: &mut CompletionContext) -> bool {
     // Bail if we aren't even in markup.
@@ -167,19 +205,8 @@ fn complete_markup(ctx: &mut CompletionContext) -> bool {
         }
     }
 
-    // Directly after a raw block.
-    let mut s = Scanner::new(ctx.text);
-    s.jump(ctx.leaf.offset());
-    if s.eat_if("```") {
-        s.eat_while('`');
-        let start = s.cursor();
-        if s.eat_if(is_id_start) {
-            s.eat_while(is_id_continue);
-        }
-        if s.cursor() == ctx.cursor {
-            ctx.from = start;
-            ctx.raw_completions();
-        }
+    // A prefix of the snippets configured by the user: "thm|".
+    if ctx.leaf.kind() == SyntaxKind::Text && ctx.user_snippet_prefix_completions() {
         return true;
     }
 
@@ -291,6 +318,8 @@ fn markup_completions(ctx: &mut CompletionContext) {
         "$ ${sum_x^2} $",
         "Inserts a block-level mathematical equation.",
     );
+
+    ctx.user_snippet_completions(SnippetScope::Markup);
 }
 
 /// Complete in math mode.
@@ -316,6 +345,8 @@ fn complete_math(ctx: &mut CompletionContext) -> bool {
     if matches!(ctx.leaf.kind(), SyntaxKind::Text | SyntaxKind::MathIdent) {
         ctx.from = ctx.leaf.offset();
         math_completions(ctx);
+        let typed = slice_at(ctx.text, ctx.leaf.offset()..ctx.cursor);
+        symbol_completions(ctx, typed);
         return true;
     }
 
@@ -351,6 +382,8 @@ fn math_completions(ctx: &mut CompletionContext) {
         "${x}/${y}",
         "Inserts a fraction.",
     );
+
+    ctx.user_snippet_completions(SnippetScope::Math);
 }
 
 /// Complete field accesses.
@@ -493,22 +526,16 @@ fn complete_open_labels(ctx: &mut CompletionContext) -> bool {
 
 /// Complete imports.
 fn complete_imports(ctx: &mut CompletionContext) -> bool {
-    // In an import path for a package:
+    // In an import path for a package, which may be unclosed while typing:
     // "#import "@|",
-    if_chain! {
-        if matches!(
-            ctx.leaf.parent_kind(),
-            Some(SyntaxKind::ModuleImport | SyntaxKind::ModuleInclude)
-        );
-        if let Some(ast::Expr::Str(str)) = ctx.leaf.cast();
-        let value = str.get();
-        if value.starts_with('@');
-        then {
-            let all_versions = value.contains(':');
-            ctx.from = ctx.leaf.offset();
-            ctx.package_completions(all_versions);
-            return true;
+    if let Some(value) = package_import_str(&ctx.leaf) {
+        ctx.from = ctx.leaf.offset();
+        if value.contains('/') {
+            ctx.package_completions(value.contains(':'));
+        } else {
+            ctx.package_namespace_completions();
         }
+        return true;
     }
 
     // Behind an import list:
@@ -546,6 +573,28 @@ fn complete_imports(ctx: &mut CompletionContext) -> bool {
     false
 }
 
+/// The value of the path of an import or include starting a package spec, e.g.
+/// `"@preview/"`, or of an unclosed one, e.g. `"@pre`.
+pub(crate) fn package_import_str(leaf: &LinkedNode) -> Option<EcoString> {
+    let is_import = |kind: Option<SyntaxKind>| {
+        matches!(
+            kind,
+            Some(SyntaxKind::ModuleImport | SyntaxKind::ModuleInclude)
+        )
+    };
+    let value = match leaf.cast::<ast::Expr>() {
+        Some(ast::Expr::Str(str)) if is_import(leaf.parent_kind()) => str.get(),
+        // The parser leaves an unclosed string behind the import as an error.
+        _ if leaf.kind() == SyntaxKind::Error
+            && is_import(leaf.prev_sibling().map(|prev| prev.kind())) =>
+        {
+            leaf.text().strip_prefix('"')?.into()
+        }
+        _ => return None,
+    };
+    value.starts_with('@').then_some(value)
+}
+
 /// Add completions for all exports of a module.
 fn import_item_completions<'a>(
     ctx: &mut CompletionContext<'a, '_>,
@@ -578,10 +627,18 @@ fn complete_rules(ctx: &mut CompletionContext) -> bool {
         return false;
     }
 
-    let Some(prev) = ctx.leaf.prev_leaf() else {
+    let Some(mut prev) = ctx.leaf.prev_leaf() else {
         return false;
     };
 
+    // Skip the error of the missing target or selector: "set |".
+    while prev.kind().is_error() {
+        let Some(leaf) = prev.prev_leaf() else {
+            return false;
+        };
+        prev = leaf;
+    }
+
     // Behind the set keyword: "set |".
     if matches!(prev.kind(), SyntaxKind::Set) {
         ctx.from = ctx.cursor;
@@ -662,11 +719,34 @@ fn show_rule_recipe_completions(ctx: &mut CompletionContext) {
 
     ctx.snippet_completion(
         "transformation",
-        "element => [${content}]",
-        "Transform the element with a function.",
+        "it => [#${it}]",
+        "Transform the element with a function taking it.",
+    );
+
+    ctx.snippet_completion(
+        "transformation (code)",
+        "it => {\n\t${}\n\tit\n}",
+        "Transform the element with code, e.g. set rules applying to it only.",
+    );
+
+    ctx.snippet_completion(
+        "set rule",
+        "set text(${})",
+        "Style the selected element with a set rule.",
     );
 
+    ctx.snippet_completion("none", "none", "Hide the selected element.");
+
+    // Suggest the recipes before the functions transforming the element.
+    let start = ctx.completions.len();
     ctx.scope_completions(false, |value| matches!(value, Value::Func(_)));
+    for (i, compl) in ctx.completions.iter_mut().enumerate() {
+        compl.sort_text = Some(if i < start {
+            eco_format!("0{i:02}")
+        } else {
+            eco_format!("1{}", compl.label)
+        });
+    }
 }
 
 /// Complete call and set rule parameters.
@@ -945,6 +1025,8 @@ fn code_completions(ctx: &mut CompletionContext, hash: bool) {
             "Creates an unnamed function.",
         );
     }
+
+    ctx.user_snippet_completions(SnippetScope::Code);
 }
 
 /// Context for autocompletion.
@@ -1069,8 +1151,31 @@ impl<'a, 'w> CompletionContext<'a, 'w> {
         }
     }
 
-    /// Add completions for raw block tags.
+    /// Add completions for the namespaces of packages, which complete the
+    /// packages in them next.
+    fn package_namespace_completions(&mut self) {
+        let mut namespaces: Vec<EcoString> = (self.world().packages().iter())
+            .map(|(spec, _)| spec.namespace.clone())
+            .chain(["preview".into()])
+            .collect();
+        namespaces.sort();
+        namespaces.dedup();
+        for namespace in namespaces {
+            self.completions.push(Completion {
+                kind: CompletionKind::Module,
+                label: eco_format!("@{namespace}/"),
+                apply: Some(eco_format!("\"@{namespace}/${{}}\"")),
+                detail: Some(eco_format!("Packages in the {namespace} namespace.")),
+                command: Some("editor.action.triggerSuggest"),
+                ..Completion::default()
+            });
+        }
+    }
+
+    /// Add completions for raw block tags, one for each tag of a language
+    /// so that the aliases like `py` are matched as well.
     fn raw_completions(&mut self) {
+        let mut seen = HashSet::new();
         for (name, mut tags) in RawElem::languages() {
             let lower = name.to_lowercase();
             if !tags.contains(&lower.as_str()) {
@@ -1078,17 +1183,17 @@ impl<'a, 'w> CompletionContext<'a, 'w> {
             }
 
             tags.retain(|tag| is_ident(tag));
-            if tags.is_empty() {
-                continue;
+            for tag in tags {
+                if !seen.insert(tag.to_owned()) {
+                    continue;
+                }
+                self.completions.push(Completion {
+                    kind: CompletionKind::Constant,
+                    label: tag.into(),
+                    detail: Some(name.into()),
+                    ..Completion::default()
+                });
             }
-
-            self.completions.push(Completion {
-                kind: CompletionKind::Constant,
-                label: name.into(),
-                apply: Some(tags[0].into()),
-                detail: Some(repr::separated_list(&tags, " or ").into()),
-                ..Completion::default()
-            });
         }
     }
 
@@ -1113,12 +1218,38 @@ impl<'a, 'w> CompletionContext<'a, 'w> {
             (0, split)
         };
 
+        // Bibliography keys parsed from the files referenced by the document,
+        // described by author and year.
+        let bib = (at || citation)
+            .then(|| self.ctx.analyze_document_bib(document))
+            .flatten();
+        let mut bib_keys = bib
+            .iter()
+            .flat_map(|bib| bib.entries.iter())
+            .map(|(key, entry)| (key.as_str(), entry.citation_detail()))
+            .collect::<indexmap::IndexMap<_, _>>();
+
+        let mut labels = labels.into_iter().skip(skip).take(take).collect::<Vec<_>>();
+        for label in labels.iter_mut() {
+            if let Some(detail) = bib_keys.shift_remove(label.label.as_str()).flatten() {
+                label.label_desc = Some(detail.into());
+            }
+        }
+        // Keys of bibliography files that typst doesn't know yet, e.g. the
+        // document is not compiled with the latest bibliography files.
+        labels.extend(bib_keys.into_iter().map(|(key, detail)| DynLabel {
+            label: Label::new(key),
+            label_desc: detail.map(Into::into),
+            detail: None,
+        }));
+
         for DynLabel {
             label,
             label_desc,
             detail,
-        } in labels.into_iter().skip(skip).take(take)
+        } in labels
         {
+            let data = self.equation_preview(document, label);
             self.completions.push(Completion {
                 kind: CompletionKind::Constant,
                 apply: (open || close).then(|| {
@@ -1132,11 +1263,33 @@ impl<'a, 'w> CompletionContext<'a, 'w> {
                 label_detail: label_desc,
                 label: label.as_str().into(),
                 detail,
+                data,
                 ..Completion::default()
             });
         }
     }
 
+    /// Locate the equation with the label, which is rendered to an image once
+    /// the item is resolved, so that equations can be told apart by more than
+    /// their labels. Other labels, or equations which are not rendered, have
+    /// no preview.
+    fn equation_preview(&mut self, document: &Document, label: Label) -> Option<CompletionData> {
+        let elem = document.introspector.query_label(label).ok()?;
+        if !elem.is::<EquationElem>() {
+            return None;
+        }
+        let id = elem.span().id()?;
+        let source = self.ctx.source_by_id(id).ok()?;
+        let range = LinkedNode::new(source.root()).find(elem.span())?.range();
+        if !math_previewed(self.ctx, &source.text()[range.clone()]) {
+            return None;
+        }
+        let path = self.ctx.path_for_id(id).ok()?;
+        Some(CompletionData {
+            equation: Some((path, range)),
+        })
+    }
+
     /// Add a completion for a specific value.
     fn value_completion(
         &mut self,
@@ -2,13 +2,16 @@ use std::collections::{BTreeMap, HashSet};
 
 use ecow::{eco_format, EcoString};
 use lsp_types::{CompletionItem, CompletionTextEdit, InsertTextFormat, TextEdit};
-use once_cell::sync::OnceCell;
+use once_cell::sync::{Lazy, OnceCell};
 use parking_lot::Mutex;
 use reflexo::path::{unix_slash, PathClean};
-use typst::foundations::{AutoValue, Func, Label, NoneValue, Repr, Type, Value};
-use typst::layout::{Dir, Length};
+use regex::{NoExpand, Regex};
+use typst::foundations::{
+    AutoValue, CastInfo, Element, Func, Label, NoneValue, Reflect, Repr, Type, Value,
+};
+use typst::layout::{Abs, Dir, Length, PageElem, Paper};
 use typst::syntax::ast::AstNode;
-use typst::syntax::{ast, Span, SyntaxKind};
+use typst::syntax::{ast, is_id_continue, Span, SyntaxKind};
 use typst::visualize::Color;
 
 use super::{Completion, CompletionContext, CompletionKind};
@@ -17,11 +20,12 @@ use crate::analysis::{
     FlowType, PathPreference, FLOW_INSET_DICT, FLOW_MARGIN_DICT, FLOW_OUTSET_DICT,
     FLOW_RADIUS_DICT, FLOW_STROKE_DICT,
 };
+use crate::hover::glyph_image;
 use crate::syntax::param_index_at_leaf;
 use crate::upstream::complete::complete_code;
 use crate::upstream::plain_docs_sentence;
 
-use crate::{prelude::*, typst_to_lsp::completion_kind, LspCompletion};
+use crate::{prelude::*, typst_to_lsp::completion_kind, LspCompletion, SnippetScope};
 
 impl<'a, 'w> CompletionContext<'a, 'w> {
     pub fn world(&self) -> &'w dyn typst::World {
@@ -276,10 +280,17 @@ impl<'a, 'w> CompletionContext<'a, 'w> {
             .or_else(|| check_previous_syntax(&self.leaf))
             .unwrap_or(SurroundingSyntax::Regular);
 
+        let start = self.completions.len();
         for (name, (kind, def_kind)) in defined {
             if !filter(None) || name.is_empty() {
                 continue;
             }
+            // Only element functions can be the target of a set rule.
+            if matches!(surrounding_syntax, SurroundingSyntax::SetRule)
+                && kind == CompletionKind::Variable
+            {
+                continue;
+            }
             let span = match def_kind {
                 DefKind::Syntax(span) => span,
                 DefKind::Instance(span, _) => span,
@@ -394,6 +405,10 @@ impl<'a, 'w> CompletionContext<'a, 'w> {
             }
         }
 
+        if matches!(surrounding_syntax, SurroundingSyntax::SetRule) {
+            prioritize_set_targets(&mut self.completions[start..]);
+        }
+
         fn check_surrounding_syntax(mut leaf: &LinkedNode) -> Option<SurroundingSyntax> {
             use SurroundingSyntax::*;
             let mut met_args = false;
@@ -458,6 +473,48 @@ impl<'a, 'w> CompletionContext<'a, 'w> {
             None
         }
     }
+
+    /// Add completions for the snippets configured by the user in the mode.
+    pub fn user_snippet_completions(&mut self, scope: SnippetScope) {
+        static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$\{\d+:?").unwrap());
+
+        let snippets = self.ctx.analysis.user_snippets.iter();
+        let snippets = snippets.filter(|snippet| snippet.scope.map_or(true, |s| s == scope));
+        let completions = snippets.map(|snippet| Completion {
+            kind: CompletionKind::Syntax,
+            label: snippet.prefix.as_str().into(),
+            // The placeholders of Typst snippets are numbered in order.
+            apply: Some(
+                (PLACEHOLDER.replace_all(&snippet.body, NoExpand("${")))
+                    .as_ref()
+                    .into(),
+            ),
+            detail: Some(snippet.description.as_str().into()),
+            ..Completion::default()
+        });
+        let completions: Vec<_> = completions.collect();
+        self.completions.extend(completions);
+    }
+
+    /// Add completions for the snippets configured by the user in markup, if
+    /// the word before the cursor is a prefix of some of them.
+    pub fn user_snippet_prefix_completions(&mut self) -> bool {
+        let start = self.before.trim_end_matches(is_id_continue).len();
+        let word = &self.before[start..];
+        let mut snippets = self.ctx.analysis.user_snippets.iter();
+        if word.is_empty()
+            || !snippets.any(|snippet| {
+                snippet.scope.map_or(true, |s| s == SnippetScope::Markup)
+                    && snippet.prefix.starts_with(word)
+            })
+        {
+            return false;
+        }
+
+        self.from = start;
+        self.user_snippet_completions(SnippetScope::Markup);
+        true
+    }
 }
 
 fn describe_value(ctx: &mut AnalysisContext, v: &Value) -> EcoString {
@@ -492,6 +549,24 @@ fn describe_value(ctx: &mut AnalysisContext, v: &Value) -> EcoString {
     }
 }
 
+/// The elements most commonly configured by set rules, in the order they are
+/// suggested.
+const COMMON_SET_TARGETS: &[&str] = &[
+    "par", "text", "heading", "page", "figure", "table", "list", "enum", "block",
+];
+
+/// Sort the completions of set rule targets, putting the common ones first.
+fn prioritize_set_targets(completions: &mut [Completion]) {
+    for compl in completions.iter_mut() {
+        let rank = COMMON_SET_TARGETS.iter().position(|t| *t == compl.label);
+        compl.sort_text = Some(match rank {
+            Some(rank) => eco_format!("0{rank:02}"),
+            None => eco_format!("1{}", compl.label),
+        });
+    }
+    completions.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+}
+
 fn encolsed_by(parent: &LinkedNode, s: Option<Span>, leaf: &LinkedNode) -> bool {
     s.and_then(|s| parent.find(s)?.find(leaf.span())).is_some()
 }
@@ -636,11 +711,22 @@ pub fn param_completions<'a>(
         }
 
         if param.named {
+            let default = param.default_repr();
+            // The default value is the placeholder unless it clashes with the
+            // snippet syntax.
+            let placeholder = default
+                .as_deref()
+                .filter(|default| !default.contains(['$', '{', '}', '\\']))
+                .unwrap_or_default();
             let compl = Completion {
                 kind: CompletionKind::Param,
                 label: param.name.clone().into(),
-                apply: Some(eco_format!("{}: ${{}}", param.name)),
-                detail: Some(plain_docs_sentence(&param.docs)),
+                apply: Some(eco_format!("{}: ${{{placeholder}}}", param.name)),
+                detail: Some(match &default {
+                    Some(default) => eco_format!("default: {default}"),
+                    None => plain_docs_sentence(&param.docs),
+                }),
+                docs: default.is_some().then(|| plain_docs_sentence(&param.docs)),
                 label_detail: None,
                 command: Some("tinymist.triggerNamedCompletion"),
                 ..Completion::default()
@@ -1038,12 +1124,40 @@ pub fn named_param_value_completions<'a>(
         ctx.cast_completions(&param.input);
     }
 
+    if name == "paper" && func.element() == Some(Element::of::<PageElem>()) {
+        paper_completions(ctx);
+    }
+
     sort_and_explicit_code_completion(ctx);
     if ctx.before.ends_with(':') {
         ctx.enrich(" ", "");
     }
 }
 
+/// Describe the papers with their dimensions, adding the ones not completed
+/// by their type yet.
+fn paper_completions(ctx: &mut CompletionContext) {
+    let mut papers = vec![];
+    Paper::input().walk(|info| {
+        if let CastInfo::Value(value @ Value::Str(name), _) = info {
+            if let Ok(paper) = name.parse::<Paper>() {
+                papers.push((value.clone(), paper));
+            }
+        }
+    });
+
+    for (value, paper) in papers {
+        let label = value.repr();
+        // Round off the error of converting from points.
+        let mm = |abs: Abs| (abs.to_mm() * 10.).round() / 10.;
+        let detail = eco_format!("{}mm × {}mm", mm(paper.width()), mm(paper.height()));
+        match ctx.completions.iter_mut().find(|c| c.label == label) {
+            Some(completion) => completion.detail = Some(detail),
+            None => ctx.value_completion(None, &value, false, Some(&detail)),
+        }
+    }
+}
+
 pub fn complete_literal(ctx: &mut CompletionContext) -> Option<()> {
     let parent = ctx.leaf.clone();
     log::info!("check complete_literal: {:?}", ctx.leaf);
@@ -1274,12 +1388,16 @@ pub fn complete_path(
         return None;
     }
 
+    // The typed path, fuzzily matched against the candidates.
+    let query = text.strip_prefix("./").unwrap_or(text);
+    // Folders are completed one level deeper than the typed path.
+    let folder_depth = query.matches('/').count() + 1;
+
     let dirs = ctx.analysis.root.clone();
     log::debug!("compl_dirs: {dirs:?}");
     // find directory or files in the path
-    let mut folder_completions = vec![];
+    let mut folder_completions: Vec<(EcoString, CompletionKind)> = vec![];
     let mut module_completions = vec![];
-    // todo: test it correctly
     for path in ctx.completion_files(p) {
         log::debug!("compl_check_path: {path:?}");
 
@@ -1302,6 +1420,22 @@ pub fn complete_path(
         };
         log::debug!("compl_label: {label:?}");
 
+        // Complete the folders containing the file, with a trailing separator.
+        for (idx, _) in label.match_indices('/') {
+            let folder = &label[..=idx];
+            let is_parent = folder.chars().all(|c| c == '.' || c == '/');
+            if is_parent || folder.matches('/').count() != folder_depth {
+                continue;
+            }
+            let is_new = folder_completions.iter().all(|(f, _)| f != folder);
+            if is_new && fuzzy_path_match(query, folder) {
+                folder_completions.push((folder.into(), CompletionKind::Folder));
+            }
+        }
+
+        if !fuzzy_path_match(query, &label) {
+            continue;
+        }
         if path.is_dir() {
             folder_completions.push((label, CompletionKind::Folder));
         } else {
@@ -1324,8 +1458,17 @@ pub fn complete_path(
         a.cmp(b)
     };
 
-    module_completions.sort_by(|a, b| path_priority_cmp(&a.0, &b.0));
-    folder_completions.sort_by(|a, b| path_priority_cmp(&a.0, &b.0));
+    // paths starting with the typed path are more important than fuzzy matches
+    let path_cmp = |a: &str, b: &str| {
+        let a_prefixed = a.starts_with(query);
+        let b_prefixed = b.starts_with(query);
+        b_prefixed
+            .cmp(&a_prefixed)
+            .then_with(|| path_priority_cmp(a, b))
+    };
+
+    module_completions.sort_by(|a, b| path_cmp(&a.0, &b.0));
+    folder_completions.sort_by(|a, b| path_cmp(&a.0, &b.0));
 
     let mut sorter = 0;
     let digits = (module_completions.len() + folder_completions.len())
@@ -1348,6 +1491,15 @@ pub fn complete_path(
                 let sort_text = format!("{sorter:0>digits$}");
                 sorter += 1;
 
+                // continue completing in the folder
+                let command = matches!(typst_completion.1, CompletionKind::Folder).then(|| {
+                    lsp_types::Command::new(
+                        String::new(),
+                        "editor.action.triggerSuggest".to_owned(),
+                        None,
+                    )
+                });
+
                 // todo: no all clients support label details
                 let res = LspCompletion {
                     label: typst_completion.0.to_string(),
@@ -1356,8 +1508,10 @@ pub fn complete_path(
                     text_edit: Some(text_edit),
                     // don't sort me
                     sort_text: Some(sort_text),
-                    filter_text: Some("".to_owned()),
+                    // already filtered by the typed path
+                    filter_text: Some(text.to_owned()),
                     insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                    command,
                     ..Default::default()
                 };
 
@@ -1369,6 +1523,75 @@ pub fn complete_path(
     )
 }
 
+/// The maximum number of symbol completions rendered with a preview of their
+/// glyphs.
+const SYMBOL_PREVIEW_LIMIT: usize = 64;
+
+/// Add completions for the symbols whose full names, e.g. `arrow.r.long`,
+/// fuzzily match the typed name, with previews of their glyphs.
+pub(crate) fn symbol_completions(ctx: &mut CompletionContext, typed: &str) {
+    let Some(first) = typed.chars().next() else {
+        return;
+    };
+
+    let mut symbols = vec![];
+    for (name, value) in typst::symbols::sym().scope().iter() {
+        let Value::Symbol(symbol) = value else {
+            continue;
+        };
+        if !name.starts_with(first) {
+            continue;
+        }
+        for (modifiers, ch) in symbol.variants() {
+            let label: EcoString = match modifiers {
+                "" => name.clone(),
+                _ => eco_format!("{name}.{modifiers}"),
+            };
+            if fuzzy_path_match(typed, &label) {
+                symbols.push((label, ch));
+            }
+        }
+    }
+    // The names extending the typed one come first, the shorter the better.
+    symbols
+        .sort_by_cached_key(|(label, _)| (!label.starts_with(typed), label.len(), label.clone()));
+
+    // The symbols may have been completed already, e.g. as math identifiers.
+    let completed: HashMap<EcoString, usize> = (ctx.completions.iter().enumerate())
+        .map(|(idx, completion)| (completion.label.clone(), idx))
+        .collect();
+    for (idx, (label, ch)) in symbols.into_iter().enumerate() {
+        let docs = if idx < SYMBOL_PREVIEW_LIMIT {
+            glyph_image(ctx.ctx, ch)
+        } else {
+            None
+        };
+        match completed.get(&label) {
+            Some(&index) => {
+                let completion = &mut ctx.completions[index];
+                completion.docs = completion.docs.take().or(docs);
+            }
+            None => ctx.completions.push(Completion {
+                kind: CompletionKind::Symbol(ch),
+                label,
+                label_detail: Some(symbol_label_detail(ch)),
+                detail: Some(symbol_detail(ch)),
+                docs,
+                ..Completion::default()
+            }),
+        }
+    }
+}
+
+/// Whether the characters of the typed path appear in order in the path, e.g.
+/// `ch/in` matches `chapters/intro.typ`.
+fn fuzzy_path_match(query: &str, path: &str) -> bool {
+    let mut chars = path.chars();
+    query
+        .chars()
+        .all(|q| chars.any(|c| c.eq_ignore_ascii_case(&q)))
+}
+
 /// If is printable, return the symbol itself.
 /// Otherwise, return the symbol's unicode detailed description.
 pub fn symbol_detail(ch: char) -> EcoString {
@@ -0,0 +1,196 @@
+use std::collections::HashSet;
+
+use comemo::Track;
+use typst::model::BibliographyElem;
+
+use crate::{prelude::*, syntax::find_source_by_expr, DiagnosticsMap};
+
+/// A request to check the labels and references of a document, reporting
+/// labels defined more than once as errors and references to undefined labels
+/// as warnings.
+///
+/// The sources included or imported by the document are checked together, so
+/// that a reference may target a label in another file. When the document is
+/// compiled, labels created at runtime and bibliography keys count as defined
+/// as well.
+#[derive(Debug, Clone)]
+pub struct ValidateLabelsRequest {
+    /// The path of the document to check.
+    pub path: PathBuf,
+}
+
+impl StatefulRequest for ValidateLabelsRequest {
+    type Response = DiagnosticsMap;
+
+    fn request(
+        self,
+        ctx: &mut AnalysisContext,
+        doc: Option<VersionedDocument>,
+    ) -> Option<Self::Response> {
+        let source = ctx.source_by_path(&self.path).ok()?;
+        let sources = collect_sources(ctx, source);
+
+        // The number of definitions of each label, in the sources and in the
+        // document.
+        let mut defined = HashMap::<EcoString, usize>::new();
+        for (_, source) in &sources {
+            visit_nodes(&LinkedNode::new(source.root()), &mut |node| {
+                if let Some(label) = attached_label(node) {
+                    *defined.entry(label.get().into()).or_default() += 1;
+                }
+            });
+        }
+        if let Some(doc) = &doc {
+            let introspector = &doc.document.introspector;
+            let mut in_doc = HashMap::<EcoString, usize>::new();
+            for elem in introspector.all() {
+                if let Some(label) = elem.label() {
+                    *in_doc.entry(label.as_str().into()).or_default() += 1;
+                }
+            }
+            for (key, _) in BibliographyElem::keys(introspector.track()) {
+                in_doc.entry(key).or_insert(1);
+            }
+            for (label, count) in in_doc {
+                let entry = defined.entry(label).or_default();
+                *entry = (*entry).max(count);
+            }
+        }
+
+        let mut diagnostics = DiagnosticsMap::new();
+        for (path, source) in &sources {
+            let mut file_diagnostics = vec![];
+            visit_nodes(&LinkedNode::new(source.root()), &mut |node| {
+                let (severity, message) = if let Some(label) = attached_label(node) {
+                    let name = label.get();
+                    if defined.get(name).copied().unwrap_or_default() < 2 {
+                        return;
+                    }
+                    let message = format!("label `<{name}>` is defined multiple times");
+                    (LspSeverity::ERROR, message)
+                } else if let Some(reference) = node.cast::<ast::Ref>() {
+                    let target = reference.target();
+                    if defined.contains_key(target) {
+                        return;
+                    }
+                    let message = format!("label `<{target}>` does not exist in the document");
+                    (LspSeverity::WARNING, message)
+                } else {
+                    return;
+                };
+
+                file_diagnostics.push(LspDiagnostic {
+                    range: ctx.to_lsp_range(node.range(), source),
+                    severity: Some(severity),
+                    message,
+                    source: Some("typst".to_owned()),
+                    ..Default::default()
+                });
+            });
+
+            if !file_diagnostics.is_empty() {
+                diagnostics.insert(path_to_url(path).ok()?, file_diagnostics);
+            }
+        }
+
+        Some(diagnostics)
+    }
+}
+
+/// Collect the source and the sources it includes or imports, transitively,
+/// together with the dependencies of the last compilation.
+pub(crate) fn collect_sources(ctx: &mut AnalysisContext, source: Source) -> Vec<(PathBuf, Source)> {
+    let mut seen = HashSet::new();
+    let mut sources = vec![];
+
+    let mut stack = ctx.dependent_sources();
+    stack.push(source);
+
+    while let Some(source) = stack.pop() {
+        if !seen.insert(source.id()) {
+            continue;
+        }
+        visit_nodes(&LinkedNode::new(source.root()), &mut |node| {
+            let expr = if let Some(include) = node.cast::<ast::ModuleInclude>() {
+                include.source()
+            } else if let Some(import) = node.cast::<ast::ModuleImport>() {
+                import.source()
+            } else {
+                return;
+            };
+            if let Some(dep) = find_source_by_expr(ctx.world(), source.id(), expr) {
+                stack.push(dep);
+            }
+        });
+
+        // Packages are not checked.
+        if source.id().package().is_some() {
+            continue;
+        }
+        if let Ok(path) = ctx.path_for_id(source.id()) {
+            sources.push((path, source));
+        }
+    }
+
+    sources.sort_by(|a, b| a.0.cmp(&b.0));
+    sources
+}
+
+/// Get the label of the node if it is attached to the content before it. The
+/// labels in code, e.g. in show rules or in the arguments of `ref` and
+/// `query`, only refer to labels.
+fn attached_label<'a>(node: &'a LinkedNode) -> Option<ast::Label<'a>> {
+    let label = node.cast::<ast::Label>()?;
+    (node.parent_kind() == Some(SyntaxKind::Markup)).then_some(label)
+}
+
+pub(crate) fn visit_nodes(node: &LinkedNode, f: &mut impl FnMut(&LinkedNode)) {
+    f(node);
+    for child in node.children() {
+        visit_nodes(&child, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn test_duplicate_and_dangling() {
+        let content = r#"// path: /chapter.typ
+= Methods <methods>
+-----
+// path: /main.typ
+#include "chapter.typ"
+= Intro <intro>
+= Again <intro>
+See @methods and @missing.
+#show <methods>: set text(red)
+#context query(<methods>).len()"#;
+
+        run_with_ctx(content, |ctx, path| {
+            let request = ValidateLabelsRequest { path };
+            let diagnostics = request.request(ctx, None).unwrap();
+
+            // The labels in code only refer to `<methods>`.
+            assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+            let main = &diagnostics[&path_to_url(Path::new("/main.typ")).unwrap()];
+            let mut found: Vec<_> = main
+                .iter()
+                .map(|d| (d.severity.unwrap(), d.range))
+                .collect();
+            found.sort_by_key(|(_, range)| (range.start.line, range.start.character));
+
+            let range = |line, start, end| {
+                LspRange::new(LspPosition::new(line, start), LspPosition::new(line, end))
+            };
+            let expected = vec![
+                (LspSeverity::ERROR, range(1, 8, 15)),
+                (LspSeverity::ERROR, range(2, 8, 15)),
+                (LspSeverity::WARNING, range(3, 17, 25)),
+            ];
+            assert_eq!(found, expected);
+        });
+    }
+}
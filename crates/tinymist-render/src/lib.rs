@@ -8,7 +8,7 @@
 use core::fmt;
 
 use base64::Engine;
-use tinymist_query::{AnalysisContext, FramePosition, VersionedDocument};
+use tinymist_query::{syntax::ColorTheme, AnalysisContext, FramePosition, VersionedDocument};
 use typst_ts_svg_exporter::{ExportFeature, SvgExporter, SvgText};
 
 struct PeriscopeExportFeature {}
@@ -35,7 +35,8 @@ pub struct PeriscopeArgs {
     pub y_below: f32,
     /// The scale of the image.
     pub scale: f32,
-    /// Whether to invert the color. (will become smarter in the future)
+    /// Whether to invert the color, which is `always`, `never`, or `auto` to
+    /// invert it when the preferred theme is dark.
     pub invert_color: String,
 }
 
@@ -93,7 +94,7 @@ impl PeriscopeRenderer {
     /// Render the periscope image for the given document.
     pub fn render(
         &self,
-        _ctx: &mut AnalysisContext,
+        ctx: &mut AnalysisContext,
         doc: VersionedDocument,
         pos: FramePosition,
     ) -> Option<(String, f32, f32)> {
@@ -114,12 +115,17 @@ impl PeriscopeRenderer {
         let width = page0.size.x.0;
         let height = y_hi - y_lo;
 
+        let invert_color = match self.p.invert_color.as_str() {
+            "always" => true,
+            "auto" => ctx.analysis.preferred_theme == Some(ColorTheme::Dark),
+            _ => false,
+        };
         *svg_header = SvgText::Plain(header_inner(
             page0.size.x.0,
             y_lo,
             y_hi,
             self.p.scale,
-            self.p.invert_color == "always",
+            invert_color,
         ));
 
         Some((SvgText::join(svg_text), width, height))
@@ -1,8 +1,14 @@
 //! Generates project metadata.
 
+use std::path::PathBuf;
+
 use anyhow::Result;
 use vergen::EmitBuilder;
 
+/// The page served by the preview if its frontend is not found at build time.
+const MISSING_FRONTEND: &str = "<!DOCTYPE html>\n\
+    <p>The frontend of typst-preview is not built into this tinymist.</p>\n";
+
 fn main() -> Result<()> {
     // Emit the instructions
     EmitBuilder::builder()
@@ -21,5 +27,40 @@ fn main() -> Result<()> {
         .expect("Typst should be a dependency");
 
     println!("cargo:rustc-env=TYPST_VERSION={}", typst.version);
+
+    // The frontend of typst-preview is embedded to serve the preview.
+    if std::env::var_os("CARGO_FEATURE_PREVIEW").is_some() {
+        println!("cargo:rerun-if-env-changed=TYPST_PREVIEW_FRONTEND");
+        let frontend = match std::env::var_os("TYPST_PREVIEW_FRONTEND") {
+            Some(frontend) => PathBuf::from(frontend),
+            None => {
+                let preview = metadata
+                    .packages
+                    .iter()
+                    .find(|package| package.name == "typst-preview")
+                    .expect("typst-preview should be a dependency");
+                let dir = preview.manifest_path.parent().unwrap();
+                dir.join("addons/frontend/dist/index.html").into()
+            }
+        };
+        println!("cargo:rerun-if-changed={}", frontend.display());
+        // A missing frontend only breaks the preview, so a page telling so is
+        // served instead of failing the build.
+        let frontend = if frontend.exists() {
+            frontend
+        } else {
+            println!(
+                "cargo:warning=the frontend of typst-preview is not found at {frontend:?}, \
+                set TYPST_PREVIEW_FRONTEND to its built index.html"
+            );
+            let placeholder = PathBuf::from(std::env::var_os("OUT_DIR").unwrap())
+                .join("typst-preview-missing.html");
+            std::fs::write(&placeholder, MISSING_FRONTEND)?;
+            placeholder
+        };
+        let frontend = frontend.display();
+        println!("cargo:rustc-env=TYPST_PREVIEW_FRONTEND={frontend}");
+    }
+
     Ok(())
 }
@@ -1,29 +1,127 @@
 //! The actor that send notifications to the client.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
 
 use async_lsp::ClientSocket;
-use lsp_types::notification::PublishDiagnostics;
-use lsp_types::{Diagnostic, PublishDiagnosticsParams, Url};
+use lsp_types::notification::{PublishDiagnostics, ShowMessage};
+use lsp_types::{Diagnostic, MessageType, PublishDiagnosticsParams, ShowMessageParams, Url};
+use serde::Deserialize;
 use tinymist_query::{DiagnosticsMap, LspDiagnostic};
 use tokio::sync::mpsc;
+use tokio::time::Instant;
 
 use crate::tools::word_count::WordsCount;
 
 pub enum EditorRequest {
     Diag(String, Option<DiagnosticsMap>),
     Status(String, TinymistCompileStatusEnum),
+    CompileStatus(TypstCompileStatus),
     WordCount(String, WordsCount),
+    /// An internal error the user should be told about, e.g. a panic of the
+    /// compiler.
+    InternalError(String),
+    /// A warning the user should be told about, e.g. invalid settings.
+    Warning(String),
+    /// The settings of the editor changed by the client.
+    ChangeConfig(EditorConfig),
+}
+
+/// The settings of the editor that are changeable by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditorConfig {
+    pub notify_compile_status: bool,
+    pub diagnostic_source: DiagnosticSource,
+}
+
+/// How the diagnostics of the entries including a same file are published.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticSource {
+    /// The diagnostics are merged, showing the ones reported by several
+    /// entries once.
+    #[default]
+    Merged,
+    /// The `source` of each diagnostic is tagged with its entry, so that
+    /// editors can filter them.
+    PerEntry,
+}
+
+/// The minimum interval between two publishes of the diagnostics of a group.
+const DIAG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Coalesces the diagnostics of a group sent in a burst, e.g. by recompiles
+/// during fast typing. The first diagnostics of a burst are published at once,
+/// and the rest are held, so that only the latest of them is published when
+/// the interval since the last publish passes.
+#[derive(Default)]
+struct DiagThrottle {
+    last_published: HashMap<String, Instant>,
+    pending: HashMap<String, Option<DiagnosticsMap>>,
+}
+
+impl DiagThrottle {
+    /// Returns the diagnostics if they are to be published now, or holds them
+    /// in place of the held ones of the group otherwise.
+    fn push(
+        &mut self,
+        group: String,
+        diagnostics: Option<DiagnosticsMap>,
+        now: Instant,
+    ) -> Option<(String, Option<DiagnosticsMap>)> {
+        let due =
+            (self.last_published.get(&group)).map_or(true, |last| *last + DIAG_INTERVAL <= now);
+        if !due {
+            self.pending.insert(group, diagnostics);
+            return None;
+        }
+
+        self.pending.remove(&group);
+        self.last_published.insert(group.clone(), now);
+        Some((group, diagnostics))
+    }
+
+    /// Takes the held diagnostics whose interval has passed.
+    fn take_due(&mut self, now: Instant) -> Vec<(String, Option<DiagnosticsMap>)> {
+        let due: Vec<_> = (self.pending.keys())
+            .filter(|group| self.last_published[*group] + DIAG_INTERVAL <= now)
+            .cloned()
+            .collect();
+        due.into_iter()
+            .filter_map(|group| {
+                let diagnostics = self.pending.remove(&group)?;
+                self.last_published.insert(group.clone(), now);
+                Some((group, diagnostics))
+            })
+            .collect()
+    }
+
+    /// Takes all the held diagnostics.
+    fn take_all(&mut self) -> Vec<(String, Option<DiagnosticsMap>)> {
+        self.pending.drain().collect()
+    }
+
+    /// Gets the time when the next held diagnostics are due.
+    fn deadline(&self) -> Option<Instant> {
+        (self.pending.keys())
+            .map(|group| self.last_published[group] + DIAG_INTERVAL)
+            .min()
+    }
 }
 
 pub struct EditorActor {
     client: ClientSocket,
     editor_rx: mpsc::UnboundedReceiver<EditorRequest>,
+    throttle: DiagThrottle,
 
     diagnostics: HashMap<Url, HashMap<String, Vec<LspDiagnostic>>>,
     affect_map: HashMap<String, Vec<Url>>,
     published_primary: bool,
     notify_compile_status: bool,
+    /// Whether the client opts in the detailed compile status.
+    notify_compile_detail: bool,
+    diagnostic_source: DiagnosticSource,
 }
 
 impl EditorActor {
@@ -31,40 +129,51 @@ impl EditorActor {
         client: ClientSocket,
         editor_rx: mpsc::UnboundedReceiver<EditorRequest>,
         notify_compile_status: bool,
+        notify_compile_detail: bool,
+        diagnostic_source: DiagnosticSource,
     ) -> Self {
         Self {
             client,
             editor_rx,
+            throttle: DiagThrottle::default(),
 
             diagnostics: HashMap::new(),
             affect_map: HashMap::new(),
             published_primary: false,
             notify_compile_status,
+            notify_compile_detail,
+            diagnostic_source,
         }
     }
 
     pub async fn run(mut self) {
         let mut compile_status = TinymistCompileStatusEnum::Compiling;
         let mut words_count = None;
-        while let Some(req) = self.editor_rx.recv().await {
+        loop {
+            let deadline = self.throttle.deadline();
+            let flush = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now));
+            let req = tokio::select! {
+                req = self.editor_rx.recv() => req,
+                _ = flush, if deadline.is_some() => {
+                    for (group, diagnostics) in self.throttle.take_due(Instant::now()) {
+                        self.publish_diag(group, diagnostics).await;
+                    }
+                    continue;
+                }
+            };
+            let Some(req) = req else {
+                break;
+            };
+
             match req {
                 EditorRequest::Diag(group, diagnostics) => {
                     let diag = diagnostics.as_ref().map(|e| e.len());
                     log::info!("received diagnostics from {group}: diag({diag:?})");
 
-                    let with_primary = self.affect_map.len() == 1
-                        && self.affect_map.contains_key("primary")
-                        && group == "primary";
-
-                    self.publish(group, diagnostics, with_primary).await;
-
-                    // Check with primary again after publish
-                    let again_with_primary =
-                        self.affect_map.len() == 1 && self.affect_map.contains_key("primary");
-
-                    if !with_primary && self.published_primary != again_with_primary {
-                        self.flush_primary_diagnostics(again_with_primary).await;
-                        self.published_primary = again_with_primary;
+                    if let Some((group, diagnostics)) =
+                        self.throttle.push(group, diagnostics, Instant::now())
+                    {
+                        self.publish_diag(group, diagnostics).await;
                     }
                 }
                 EditorRequest::Status(group, status) => {
@@ -78,6 +187,11 @@ impl EditorActor {
                             });
                     }
                 }
+                EditorRequest::CompileStatus(status) => {
+                    if self.notify_compile_detail {
+                        self.client.notify::<TypstCompileStatus>(status);
+                    }
+                }
                 EditorRequest::WordCount(group, wc) => {
                     log::debug!("received word count request");
                     if self.notify_compile_status && group == "primary" {
@@ -89,20 +203,72 @@ impl EditorActor {
                             });
                     }
                 }
+                EditorRequest::InternalError(message) => {
+                    self.client.notify::<ShowMessage>(ShowMessageParams {
+                        typ: MessageType::ERROR,
+                        message,
+                    });
+                }
+                EditorRequest::Warning(message) => {
+                    self.client.notify::<ShowMessage>(ShowMessageParams {
+                        typ: MessageType::WARNING,
+                        message,
+                    });
+                }
+                EditorRequest::ChangeConfig(config) => self.change_config(config),
             }
         }
+
+        // The diagnostics after the last burst are always delivered.
+        for (group, diagnostics) in self.throttle.take_all() {
+            self.publish_diag(group, diagnostics).await;
+        }
         log::info!("compile cluster actor is stopped");
     }
 
+    async fn publish_diag(&mut self, group: String, diagnostics: Option<DiagnosticsMap>) {
+        let with_primary = self.affect_map.len() == 1
+            && self.affect_map.contains_key("primary")
+            && group == "primary";
+
+        self.publish(group, diagnostics, with_primary).await;
+
+        // Check with primary again after publish
+        let again_with_primary =
+            self.affect_map.len() == 1 && self.affect_map.contains_key("primary");
+
+        if !with_primary && self.published_primary != again_with_primary {
+            self.flush_primary_diagnostics(again_with_primary).await;
+            self.published_primary = again_with_primary;
+        }
+    }
+
+    /// Applies the settings, publishing the diagnostics of all files again if
+    /// they are sourced differently.
+    fn change_config(&mut self, config: EditorConfig) {
+        self.notify_compile_status = config.notify_compile_status;
+        if self.diagnostic_source == config.diagnostic_source {
+            return;
+        }
+        self.diagnostic_source = config.diagnostic_source;
+
+        let only_primary = self.affect_map.len() == 1 && self.affect_map.contains_key("primary");
+        for url in self.diagnostics.keys() {
+            let to_publish = self.diagnostics_to_publish(url, |g| g != "primary" || only_primary);
+            self.client
+                .notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+                    uri: url.clone(),
+                    diagnostics: to_publish,
+                    version: None,
+                });
+        }
+    }
+
     async fn flush_primary_diagnostics(&mut self, enable: bool) {
         let affected = self.affect_map.get("primary");
 
         for url in affected.into_iter().flatten() {
-            let path_diags = self.diagnostics.get(url);
-
-            let diags = path_diags.into_iter().flatten();
-            let diags = diags.filter_map(|(g, diags)| (g != "primary" || enable).then_some(diags));
-            let to_publish = diags.flatten().cloned().collect();
+            let to_publish = self.diagnostics_to_publish(url, |g| g != "primary" || enable);
 
             self.client
                 .notify::<PublishDiagnostics>(PublishDiagnosticsParams {
@@ -113,6 +279,34 @@ impl EditorActor {
         }
     }
 
+    /// Collects the diagnostics of the file from the groups passing the filter.
+    fn diagnostics_to_publish(&self, url: &Url, filter: impl Fn(&str) -> bool) -> Vec<Diagnostic> {
+        let path_diags = self.diagnostics.get(url).into_iter().flatten();
+        let groups = path_diags.filter(|(g, _)| filter(g));
+
+        let mut to_publish = Vec::new();
+        if self.diagnostic_source == DiagnosticSource::PerEntry {
+            for (group, diags) in groups {
+                for diag in diags {
+                    let mut diag = diag.clone();
+                    let source = diag.source.as_deref().unwrap_or("typst");
+                    diag.source = Some(format!("{source} ({group})"));
+                    to_publish.push(diag);
+                }
+            }
+            return to_publish;
+        }
+
+        // The same diagnostic reported by several entries is shown once.
+        let mut seen = HashSet::new();
+        for diag in groups.flat_map(|(_, diags)| diags) {
+            if seen.insert((diag.range, &diag.message, &diag.source)) {
+                to_publish.push(diag.clone());
+            }
+        }
+        to_publish
+    }
+
     pub async fn publish(
         &mut self,
         group: String,
@@ -152,26 +346,16 @@ impl EditorActor {
         url: Url,
         next: Option<Vec<Diagnostic>>,
     ) {
-        let mut to_publish = Vec::new();
-
-        // Get the diagnostics from other groups
         let path_diags = self.diagnostics.entry(url.clone()).or_default();
-        for (g, diags) in &*path_diags {
-            if (with_primary || g != "primary") && g != group {
-                to_publish.extend(diags.iter().cloned());
-            }
-        }
-
-        // Get the diagnostics from this group
-        if let Some(diags) = &next {
-            to_publish.extend(diags.iter().cloned())
-        }
-
         match next {
             Some(next) => path_diags.insert(group.to_owned(), next),
             None => path_diags.remove(group),
         };
 
+        // Get the diagnostics from this group and other groups
+        let to_publish =
+            self.diagnostics_to_publish(&url, |g| g == group || with_primary || g != "primary");
+
         if group != "primary" || with_primary {
             self.client
                 .notify::<PublishDiagnostics>(PublishDiagnosticsParams {
@@ -203,3 +387,125 @@ impl lsp_types::notification::Notification for TinymistCompileStatus {
     type Params = Self;
     const METHOD: &'static str = "tinymist/compileStatus";
 }
+
+/// The detailed status of a compilation, sent on each status transition to
+/// clients opting in with the `typstCompileStatus` experimental capability.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypstCompileStatus {
+    /// The group of the compiler, e.g. `primary`.
+    pub group: String,
+    pub status: TinymistCompileStatusEnum,
+    /// The path of the compiled entry.
+    pub entry: Option<PathBuf>,
+    /// The time elapsed in the compilation, zero when it begins.
+    pub elapsed_ms: u64,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl lsp_types::notification::Notification for TypstCompileStatus {
+    type Params = Self;
+    const METHOD: &'static str = "$/typst/compileStatus";
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{Position, Range};
+
+    use super::*;
+
+    fn actor(diagnostic_source: DiagnosticSource) -> EditorActor {
+        let (_, editor_rx) = mpsc::unbounded_channel();
+        let client = ClientSocket::new_closed();
+        EditorActor::new(client, editor_rx, false, false, diagnostic_source)
+    }
+
+    /// Publishes the same diagnostic of a file included by two entries.
+    async fn publish_shared(actor: &mut EditorActor, url: &Url) -> Vec<Diagnostic> {
+        let diag = Diagnostic {
+            range: Range::new(Position::new(0, 0), Position::new(0, 4)),
+            source: Some("typst".to_owned()),
+            message: "unknown variable: x".to_owned(),
+            ..Default::default()
+        };
+        for group in ["primary", "chapter"] {
+            let diags = DiagnosticsMap::from_iter([(url.clone(), vec![diag.clone()])]);
+            actor.publish(group.to_owned(), Some(diags), true).await;
+        }
+        actor.diagnostics_to_publish(url, |_| true)
+    }
+
+    #[tokio::test]
+    async fn test_diagnostic_source() {
+        let url = Url::parse("file:///ws/shared.typ").unwrap();
+
+        let mut merged = actor(DiagnosticSource::Merged);
+        let diags = publish_shared(&mut merged, &url).await;
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].source.as_deref(), Some("typst"));
+
+        let mut per_entry = actor(DiagnosticSource::PerEntry);
+        let mut sources: Vec<_> = (publish_shared(&mut per_entry, &url).await)
+            .into_iter()
+            .filter_map(|diag| diag.source)
+            .collect();
+        sources.sort();
+        assert_eq!(sources, ["typst (chapter)", "typst (primary)"]);
+    }
+
+    #[tokio::test]
+    async fn test_change_diagnostic_source() {
+        let url = Url::parse("file:///ws/shared.typ").unwrap();
+        let mut actor = actor(DiagnosticSource::Merged);
+        assert_eq!(publish_shared(&mut actor, &url).await.len(), 1);
+
+        // The diagnostics published before are tagged by the new setting.
+        actor.change_config(EditorConfig {
+            notify_compile_status: true,
+            diagnostic_source: DiagnosticSource::PerEntry,
+        });
+        assert!(actor.notify_compile_status);
+        let diags = actor.diagnostics_to_publish(&url, |_| true);
+        assert_eq!(diags.len(), 2);
+        assert!(diags.iter().all(|d| d.source.as_deref() != Some("typst")));
+
+        actor.change_config(EditorConfig {
+            notify_compile_status: true,
+            diagnostic_source: DiagnosticSource::Merged,
+        });
+        let diags = actor.diagnostics_to_publish(&url, |_| true);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].source.as_deref(), Some("typst"));
+    }
+
+    #[test]
+    fn test_diag_throttle() {
+        let url = Url::parse("file:///ws/main.typ").unwrap();
+        let update = |i: usize| {
+            let diag = Diagnostic {
+                message: format!("error {i}"),
+                ..Default::default()
+            };
+            Some(DiagnosticsMap::from_iter([(url.clone(), vec![diag])]))
+        };
+
+        let mut throttle = DiagThrottle::default();
+        let start = Instant::now();
+        let mut published = vec![];
+        for i in 0..10 {
+            let now = start + Duration::from_millis(i as u64);
+            published.extend(throttle.push("primary".to_owned(), update(i), now));
+        }
+        assert_eq!(published.len(), 1);
+        assert_eq!(throttle.deadline(), Some(start + DIAG_INTERVAL));
+        assert!(throttle.take_due(start + DIAG_INTERVAL / 2).is_empty());
+
+        published.extend(throttle.take_due(start + DIAG_INTERVAL));
+        assert!(published.len() < 10);
+        let (group, last) = published.pop().unwrap();
+        assert_eq!(group, "primary");
+        assert_eq!(last.unwrap()[&url][0].message, "error 9");
+        assert_eq!(throttle.deadline(), None);
+    }
+}
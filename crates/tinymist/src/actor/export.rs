@@ -1,24 +1,163 @@
 //! The actor that handles PDF/SVG/PNG export.
 
+use std::borrow::Cow;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::{bail, Context};
 use once_cell::sync::Lazy;
+use tinymist_query::syntax::IgnorePatterns;
 use tinymist_query::{ExportKind, PageSelection};
 use tokio::sync::{mpsc, oneshot, watch};
 use typst::{foundations::Smart, layout::Abs, layout::Frame, visualize::Color};
 use typst_ts_core::{config::compiler::EntryState, path::PathClean, ImmutPath, TypstDocument};
 
-use crate::{tools::word_count, ExportMode};
+use crate::{
+    tools::{
+        contact_sheet::contact_sheets,
+        epub::{document_date, epub},
+        manifest::{CompileRecord, ExportManifest, ManifestOutput},
+        persistent_cache::PersistentCache,
+        pptx::pptx,
+        svg_text, word_count,
+    },
+    ExportMode, OutputPathByKind,
+};
 
 use super::editor::EditorRequest;
 
 #[derive(Debug, Clone, Default)]
 pub struct ExportConfig {
     pub substitute_pattern: String,
+    /// The patterns overriding `substitute_pattern` for some kinds of export.
+    pub pattern_by_kind: OutputPathByKind,
     pub entry: EntryState,
     pub mode: ExportMode,
+    /// The pages to export, which applies to all exports until cleared.
+    pub page_filter: Option<PageFilter>,
+    /// The on-disk cache of the exported artifacts, if enabled.
+    pub cache: Option<Arc<PersistentCache>>,
+    /// The globs of the files whose saves trigger exports, relative to the
+    /// root. Saves of all files trigger exports if absent.
+    pub save_glob: Option<IgnorePatterns>,
+    /// Whether to count each CJK character as a word.
+    pub cjk_mode: bool,
+}
+
+impl ExportConfig {
+    /// Whether saving the file triggers an export in the `onSave` modes.
+    fn exports_on_save(&self, path: &Path) -> bool {
+        let Some(glob) = &self.save_glob else {
+            return true;
+        };
+        let Some(root) = self.entry.root() else {
+            return false;
+        };
+        path.strip_prefix(&root)
+            .is_ok_and(|relative| glob.is_match(relative))
+    }
+
+    /// Get the document with only the pages selected by the page filter.
+    fn select_pages<'a>(&self, doc: &'a TypstDocument) -> anyhow::Result<Cow<'a, TypstDocument>> {
+        let Some(filter) = &self.page_filter else {
+            return Ok(Cow::Borrowed(doc));
+        };
+
+        let selected = filter.select(doc.pages.len())?;
+        let mut filtered = doc.clone();
+        filtered.pages = selected.into_iter().map(|i| doc.pages[i].clone()).collect();
+        // The outline and the links locate the elements by the selected pages.
+        filtered.introspector.rebuild(&filtered.pages);
+        Ok(Cow::Owned(filtered))
+    }
+
+    /// Get the path to export the document at `path` to, with the pattern for
+    /// the kind of export if there is one.
+    fn output_path(&self, kind: &ExportKind, root: &Path, path: &Path) -> anyhow::Result<PathBuf> {
+        let extension = kind.extension();
+        let pattern = self
+            .pattern_by_kind
+            .pattern(extension, &self.substitute_pattern);
+        let Some(to) = substitute_path(pattern, root, path) else {
+            bail!("RenderActor({kind:?}): failed to substitute path");
+        };
+        if to.is_relative() {
+            bail!("RenderActor({kind:?}): path is relative: {to:?}");
+        }
+        if to.is_dir() {
+            bail!("RenderActor({kind:?}): path is a directory: {to:?}");
+        }
+
+        Ok(to.with_extension(extension))
+    }
+}
+
+/// A set of page ranges to export, e.g. `3-8,10` or `5-`. Pages are numbered
+/// from one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageFilter {
+    /// The inclusive ranges of pages, where an open end extends to the last
+    /// page.
+    ranges: Vec<(usize, Option<usize>)>,
+}
+
+impl PageFilter {
+    /// Get the indices of the selected pages in a document with `page_count`
+    /// pages.
+    pub fn select(&self, page_count: usize) -> anyhow::Result<Vec<usize>> {
+        let mut selected = vec![false; page_count];
+        for &(start, end) in &self.ranges {
+            let end = end.unwrap_or(page_count.max(start));
+            if end > page_count {
+                bail!("page {end} is out of range, the document has {page_count} pages");
+            }
+            selected[start - 1..end].fill(true);
+        }
+
+        let selected = (0..page_count).filter(|&i| selected[i]).collect();
+        Ok(selected)
+    }
+}
+
+impl FromStr for PageFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Empty bounds of ranges are open.
+        let page = |s: &str| -> anyhow::Result<Option<usize>> {
+            let s = s.trim();
+            if s.is_empty() {
+                return Ok(None);
+            }
+            let page = s.parse().with_context(|| format!("invalid page {s:?}"))?;
+            if page == 0 {
+                bail!("pages are numbered from 1");
+            }
+            Ok(Some(page))
+        };
+
+        let mut ranges = vec![];
+        for range in s.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (page(start)?.unwrap_or(1), page(end)?),
+                None => {
+                    let page = page(range)?.unwrap_or(1);
+                    (page, Some(page))
+                }
+            };
+            if end.is_some_and(|end| end < start) {
+                bail!("invalid page range {range:?}");
+            }
+            ranges.push((start, end));
+        }
+
+        if ranges.is_empty() {
+            bail!("no page is selected");
+        }
+        Ok(Self { ranges })
+    }
 }
 
 #[derive(Debug)]
@@ -26,10 +165,41 @@ pub enum ExportRequest {
     OnTyped,
     OnSaved(PathBuf),
     Oneshot(Option<ExportKind>, oneshot::Sender<Option<PathBuf>>),
-    /// Change config except entry.
+    /// Export a document other than the compiled one, e.g. a watermarked one,
+    /// with the suffix appended to the stem of the output path so that the
+    /// regular export is kept.
+    OneshotDoc(
+        ExportKind,
+        Arc<TypstDocument>,
+        &'static str,
+        oneshot::Sender<Option<PathBuf>>,
+    ),
+    /// Export the compiled document and write a manifest describing the export
+    /// next to the file written, responding with the path of the manifest.
+    Manifest(
+        ExportKind,
+        oneshot::Sender<Option<(PathBuf, ExportManifest)>>,
+    ),
+    /// Change config except entry and page filter.
     ChangeConfig(ExportConfig),
     /// Change entry.
     ChangeExportPath(EntryState),
+    /// Change page filter, or clear it.
+    ChangePageFilter(Option<PageFilter>),
+    /// Change the globs of the files whose saves trigger exports, or clear
+    /// them.
+    ChangeSaveGlob(Option<IgnorePatterns>),
+    /// Change whether to count each CJK character as a word.
+    ChangeCjkMode(bool),
+    /// Record the hash of the inputs of the compiled document, which keys the
+    /// persistent cache.
+    DocumentInputs(Arc<TypstDocument>, u128),
+    /// Record the compilation of the compiled document, which is described in
+    /// the manifests of its exports.
+    DocumentCompiled(Arc<TypstDocument>, CompileRecord),
+    /// Export the artifact cached for the hash of the inputs recorded before
+    /// compiling, while the document is not compiled yet.
+    RecordedInputs(u128),
 }
 
 pub struct ExportActor {
@@ -41,6 +211,10 @@ pub struct ExportActor {
     config: ExportConfig,
     kind: ExportKind,
     count_words: bool,
+    /// The compiled document and the hash of its inputs.
+    inputs: Option<(Arc<TypstDocument>, u128)>,
+    /// The compiled document and the record of its compilation.
+    compiled: Option<(Arc<TypstDocument>, CompileRecord)>,
 }
 
 impl ExportActor {
@@ -61,11 +235,17 @@ impl ExportActor {
             config,
             kind,
             count_words,
+            inputs: None,
+            compiled: None,
         }
     }
 
     pub async fn run(mut self) {
         while let Some(mut req) = self.export_rx.recv().await {
+            if let ExportRequest::RecordedInputs(inputs) = req {
+                self.export_recorded(inputs);
+                continue;
+            }
             let Some(doc) = self.document.borrow().clone() else {
                 log::info!("RenderActor: document is not ready");
                 continue;
@@ -79,11 +259,23 @@ impl ExportActor {
                     ExportRequest::ChangeConfig(config) => {
                         self.config = ExportConfig {
                             entry: self.config.entry,
+                            page_filter: self.config.page_filter.take(),
                             ..config
                         }
                     }
                     ExportRequest::ChangeExportPath(entry) => self.config.entry = entry,
+                    ExportRequest::ChangePageFilter(filter) => self.config.page_filter = filter,
+                    ExportRequest::ChangeSaveGlob(glob) => self.config.save_glob = glob,
+                    ExportRequest::ChangeCjkMode(cjk_mode) => self.config.cjk_mode = cjk_mode,
+                    ExportRequest::DocumentInputs(doc, hash) => self.inputs = Some((doc, hash)),
+                    ExportRequest::DocumentCompiled(doc, record) => {
+                        self.compiled = Some((doc, record))
+                    }
+                    ExportRequest::RecordedInputs(inputs) => self.export_recorded(inputs),
                     ExportRequest::OnTyped => need_export |= self.config.mode == ExportMode::OnType,
+                    ExportRequest::OnSaved(path) if !self.config.exports_on_save(&path) => {
+                        log::debug!("RenderActor: {path:?} does not match exportOnSaveGlob");
+                    }
                     ExportRequest::OnSaved(..) => match self.config.mode {
                         ExportMode::OnSave => need_export = true,
                         ExportMode::OnDocumentHasTitle => need_export |= doc.title.is_some(),
@@ -92,7 +284,19 @@ impl ExportActor {
                     ExportRequest::Oneshot(kind, callback) => {
                         // Do oneshot export instantly without accumulation.
                         let kind = kind.as_ref().unwrap_or(&self.kind);
-                        let resp = self.check_mode_and_export(kind, &doc).await;
+                        let resp = self.check_mode_and_export(kind, &doc, None).await;
+                        if let Err(err) = callback.send(resp) {
+                            log::error!("RenderActor(@{kind:?}): failed to send response: {err:?}");
+                        }
+                    }
+                    ExportRequest::Manifest(kind, callback) => {
+                        let resp = self.export_manifest(&kind, &doc).await;
+                        if let Err(err) = callback.send(resp) {
+                            log::error!("RenderActor(@{kind:?}): failed to send response: {err:?}");
+                        }
+                    }
+                    ExportRequest::OneshotDoc(kind, doc, suffix, callback) => {
+                        let resp = self.check_mode_and_export(&kind, &doc, Some(suffix)).await;
                         if let Err(err) = callback.send(resp) {
                             log::error!("RenderActor(@{kind:?}): failed to send response: {err:?}");
                         }
@@ -107,11 +311,11 @@ impl ExportActor {
             }
 
             if need_export {
-                self.check_mode_and_export(&self.kind, &doc).await;
+                self.check_mode_and_export(&self.kind, &doc, None).await;
             }
 
             if self.count_words {
-                let wc = word_count::word_count(&doc);
+                let wc = word_count::word_count(&doc, self.config.cjk_mode);
                 log::debug!("word count: {wc:?}");
                 let _ = self
                     .editor_tx
@@ -125,7 +329,21 @@ impl ExportActor {
         &self,
         kind: &ExportKind,
         doc: &TypstDocument,
+        suffix: Option<&str>,
     ) -> Option<PathBuf> {
+        let (root, path) = self.entry_path()?;
+
+        match self.export(kind, doc, &root, &path, suffix).await {
+            Ok(pdf) => Some(pdf),
+            Err(err) => {
+                log::error!("RenderActor({kind:?}): failed to export {err}");
+                None
+            }
+        }
+    }
+
+    /// Get the root and the path of the main file of the entry.
+    fn entry_path(&self) -> Option<(ImmutPath, PathBuf)> {
         // pub entry: EntryState,
         let root = self.config.entry.root();
         let main = self.config.entry.main();
@@ -144,13 +362,37 @@ impl ExportActor {
         }
 
         let path = main.vpath().resolve(&root)?;
+        Some((root, path))
+    }
 
-        match self.export(kind, doc, &root, &path).await {
-            Ok(pdf) => Some(pdf),
-            Err(err) => {
-                log::error!("RenderActor({kind:?}): failed to export {err}");
-                None
+    /// Export the artifact cached for the inputs recorded before compiling,
+    /// which is exported again once the document is compiled, hitting the
+    /// cache.
+    fn export_recorded(&self, inputs: u128) {
+        if self.config.mode != ExportMode::OnType {
+            return;
+        }
+        let Some(cache) = self.config.cache.as_deref() else {
+            return;
+        };
+        let Some(data) = (self.artifact_key(&self.kind, inputs)).and_then(|key| cache.get(key))
+        else {
+            return;
+        };
+        let Some((root, path)) = self.entry_path() else {
+            return;
+        };
+        let kind = &self.kind;
+        let res = self.config.output_path(kind, &root, &path).and_then(|to| {
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent).context("failed to create directory")?;
             }
+            std::fs::write(&to, data).context("failed to write")?;
+            Ok(to)
+        });
+        match res {
+            Ok(to) => log::info!("RenderActor({kind:?}): exported {to:?} before compiling"),
+            Err(err) => log::error!("RenderActor({kind:?}): failed to export cached {err}"),
         }
     }
 
@@ -160,21 +402,14 @@ impl ExportActor {
         doc: &TypstDocument,
         root: &Path,
         path: &Path,
+        suffix: Option<&str>,
     ) -> anyhow::Result<PathBuf> {
         use ExportKind::*;
-        use PageSelection::*;
 
-        let Some(to) = substitute_path(&self.config.substitute_pattern, root, path) else {
-            bail!("RenderActor({kind:?}): failed to substitute path");
-        };
-        if to.is_relative() {
-            bail!("RenderActor({kind:?}): path is relative: {to:?}");
-        }
-        if to.is_dir() {
-            bail!("RenderActor({kind:?}): path is a directory: {to:?}");
+        let mut to = self.config.output_path(kind, root, path)?;
+        if let Some(suffix) = suffix {
+            to = with_suffix(&to, suffix);
         }
-
-        let to = to.with_extension(kind.extension());
         log::info!("RenderActor({kind:?}): exporting {path:?} to {to:?}");
 
         if let Some(e) = to.parent() {
@@ -185,16 +420,148 @@ impl ExportActor {
             }
         }
 
+        let selected = self.config.select_pages(doc)?;
+        let selected = selected.as_ref();
+
+        if let ContactSheet { columns, ppi } = kind {
+            let sheets = contact_sheets(selected, *columns, *ppi)?;
+            let stem = to.file_stem().unwrap_or_default().to_string_lossy();
+            let mut first = None;
+            for (idx, data) in sheets.into_iter().enumerate() {
+                let name = match idx {
+                    0 => format!("{stem}-contact.png"),
+                    idx => format!("{stem}-contact-{}.png", idx + 1),
+                };
+                let to = to.with_file_name(name);
+                std::fs::write(&to, data)
+                    .with_context(|| format!("RenderActor({kind:?}): failed to export"))?;
+                first.get_or_insert(to);
+            }
+
+            log::info!("RenderActor({kind:?}): export complete");
+            return first.context("no contact sheet is exported");
+        }
+
+        let data = match (kind, self.cache_key(kind, doc)) {
+            #[cfg(feature = "pandoc")]
+            (Docx { pandoc }, cache) => pandoc_docx(cache, selected, pandoc).await?,
+            (_, Some((cache, key))) => cache.get_or_build(key, || self.render(kind, selected))?,
+            (_, None) => self.render(kind, selected)?,
+        };
+
+        std::fs::write(&to, data)
+            .with_context(|| format!("RenderActor({kind:?}): failed to export"))?;
+
+        log::info!("RenderActor({kind:?}): export complete");
+        Ok(to)
+    }
+
+    /// Export the document and write a manifest describing the export next to
+    /// the file written, returning the path of the manifest.
+    async fn export_manifest(
+        &self,
+        kind: &ExportKind,
+        doc: &Arc<TypstDocument>,
+    ) -> Option<(PathBuf, ExportManifest)> {
+        let start = Instant::now();
+        let (_, entry) = self.entry_path()?;
+        let to = self.check_mode_and_export(kind, doc, None).await?;
+        let output = self.manifest_output(kind, doc, to)?;
+        let duration = start.elapsed();
+
+        // Only the compilation of the exported document is described.
+        let compiled = self.compiled.as_ref();
+        let compiled = compiled.filter(|(compiled, _)| Arc::ptr_eq(compiled, doc));
+        let manifest = ExportManifest::new(entry, vec![output], compiled.map(|c| &c.1), duration);
+        match manifest.write() {
+            Ok(to) => Some((to, manifest)),
+            Err(err) => {
+                log::error!("RenderActor({kind:?}): failed to write manifest {err}");
+                None
+            }
+        }
+    }
+
+    /// Describe the file exported from the document, counting the pages
+    /// selected.
+    fn manifest_output(
+        &self,
+        kind: &ExportKind,
+        doc: &TypstDocument,
+        to: PathBuf,
+    ) -> Option<ManifestOutput> {
+        let pages = match self.config.select_pages(doc) {
+            Ok(selected) => selected.pages.len(),
+            Err(err) => {
+                log::error!("RenderActor({kind:?}): failed to select pages {err}");
+                return None;
+            }
+        };
+        let pages = match kind {
+            ExportKind::Svg {
+                page: PageSelection::First,
+                ..
+            }
+            | ExportKind::Png {
+                page: PageSelection::First,
+            } => pages.min(1),
+            _ => pages,
+        };
+        match ManifestOutput::new(to, kind.extension(), pages) {
+            Ok(output) => Some(output),
+            Err(err) => {
+                log::error!("RenderActor({kind:?}): failed to describe export {err}");
+                None
+            }
+        }
+    }
+
+    /// Get the persistent cache and the key of the export, if the inputs of the
+    /// document are known.
+    fn cache_key(
+        &self,
+        kind: &ExportKind,
+        doc: &TypstDocument,
+    ) -> Option<(&PersistentCache, u128)> {
+        let cache = self.config.cache.as_deref()?;
+        let (compiled, inputs) = self.inputs.as_ref()?;
+        // Documents other than the compiled one, e.g. watermarked ones, are not
+        // cached.
+        if !std::ptr::eq(compiled.as_ref(), doc) {
+            return None;
+        }
+        Some((cache, self.artifact_key(kind, *inputs)?))
+    }
+
+    /// Get the key of the artifact exported from the inputs with the options,
+    /// if it is cacheable.
+    fn artifact_key(&self, kind: &ExportKind, inputs: u128) -> Option<u128> {
+        // The books with cover images are not cached, since the images are not
+        // read by the compilation.
+        if let ExportKind::Epub { opts } = kind {
+            if opts.cover_image.is_some() {
+                return None;
+            }
+        }
+        let options = format!("{kind:?} {:?}", self.config.page_filter);
+        Some(typst::util::hash128(&(inputs, options)))
+    }
+
+    /// Render the selected pages of the document.
+    fn render(&self, kind: &ExportKind, doc: &TypstDocument) -> anyhow::Result<Vec<u8>> {
+        use ExportKind::*;
+        use PageSelection::*;
+
         static BLANK: Lazy<Frame> = Lazy::new(Frame::default);
         let first_frame = || doc.pages.first().map(|f| &f.frame).unwrap_or(&*BLANK);
-        let data = match kind {
+        Ok(match kind {
             Pdf => {
                 // todo: Some(pdf_uri.as_str())
                 // todo: timestamp world.now()
                 typst_pdf::pdf(doc, Smart::Auto, None)
             }
-            Svg { page: First } => typst_svg::svg(first_frame()).into_bytes(),
-            Svg { page: Merged } => typst_svg::svg_merged(doc, Abs::zero()).into_bytes(),
+            Svg { page: First, text } => svg_text::svg(first_frame(), *text).into_bytes(),
+            Svg { page: Merged, text } => svg_text::svg_merged(doc, *text).into_bytes(),
             Png { page: First } => typst_render::render(first_frame(), 3., Color::WHITE)
                 .encode_png()
                 .map_err(|err| anyhow::anyhow!("failed to encode PNG ({err})"))?,
@@ -203,18 +570,66 @@ impl ExportActor {
                     .encode_png()
                     .map_err(|err| anyhow::anyhow!("failed to encode PNG ({err})"))?
             }
-        };
+            Pptx { ppi, notes } => pptx(doc, *ppi, *notes)?,
+            #[cfg(not(feature = "pandoc"))]
+            Docx { .. } => bail!("tinymist is built without the pandoc feature"),
+            Epub { opts } => {
+                let cover = match &opts.cover_image {
+                    Some(path) => {
+                        let data = std::fs::read(path)
+                            .with_context(|| format!("failed to read cover image {path:?}"))?;
+                        let ext = path.extension().and_then(|ext| ext.to_str());
+                        Some((data, ext.unwrap_or_default().to_owned()))
+                    }
+                    None => None,
+                };
+                let cover = (cover.as_ref()).map(|(data, ext)| (data.as_slice(), ext.as_str()));
+                // The date of the document keeps the book reproducible.
+                let modified = document_date(doc).unwrap_or_else(chrono::Utc::now);
+                epub(doc, opts.split_by, cover, modified)?
+            }
+            #[cfg(feature = "pandoc")]
+            Docx { .. } => unreachable!(),
+            ContactSheet { .. } => unreachable!(),
+        })
+    }
+}
 
-        std::fs::write(&to, data)
-            .with_context(|| format!("RenderActor({kind:?}): failed to export"))?;
+/// Convert the document to DOCX with pandoc, which is awaited instead of
+/// blocking the actor, and store it in the cache if any.
+#[cfg(feature = "pandoc")]
+async fn pandoc_docx(
+    cache: Option<(&PersistentCache, u128)>,
+    doc: &TypstDocument,
+    pandoc: &Path,
+) -> anyhow::Result<Vec<u8>> {
+    if let Some(data) = cache.and_then(|(cache, key)| cache.get(key)) {
+        return Ok(data);
+    }
+    let data = crate::tools::pandoc::docx(doc, pandoc).await?;
+    match cache {
+        Some((cache, key)) => cache.get_or_build(key, || Ok(data)),
+        None => Ok(data),
+    }
+}
 
-        log::info!("RenderActor({kind:?}): export complete");
-        Ok(to)
+/// Append the suffix to the stem of the path, keeping its extension.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{stem}-{suffix}");
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
     }
+    path.with_file_name(name)
 }
 
 #[comemo::memoize]
-fn substitute_path(substitute_pattern: &str, root: &Path, path: &Path) -> Option<ImmutPath> {
+pub(crate) fn substitute_path(
+    substitute_pattern: &str,
+    root: &Path,
+    path: &Path,
+) -> Option<ImmutPath> {
     if let Ok(path) = path.strip_prefix("/untitled") {
         let tmp = std::env::temp_dir();
         let path = tmp.join("typst").join(path);
@@ -245,7 +660,19 @@ fn substitute_path(substitute_pattern: &str, root: &Path, path: &Path) -> Option
 
 #[cfg(test)]
 mod tests {
+    use std::time::SystemTime;
+
+    use typst::eval::Tracer;
+    use typst::foundations::{Dict, NativeElement};
+    use typst::model::HeadingElem;
+    use typst::syntax::{FileId, VirtualPath};
+
+    use tinymist_query::SvgTextMode;
+
     use super::*;
+    use crate::compile_init::CompileConfig;
+    use crate::tools::manifest::ManifestDiagnostics;
+    use crate::tools::tests::TestWorld;
 
     #[test]
     fn test_substitute_path() {
@@ -269,4 +696,231 @@ mod tests {
             Some(PathBuf::from("/substitute/target/dir1/dir2/file.txt").into())
         );
     }
+
+    #[test]
+    fn test_output_path_by_kind() {
+        let root = Path::new("/root");
+        let path = Path::new("/root/dir/main.typ");
+        let config = ExportConfig {
+            substitute_pattern: "$root/out/$dir/$name".to_owned(),
+            pattern_by_kind: OutputPathByKind {
+                pdf: Some("$root/dist/$name".to_owned()),
+                png: Some("$root/previews/$name".to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let output_path = |kind| config.output_path(&kind, root, path).unwrap();
+        assert_eq!(
+            output_path(ExportKind::Pdf),
+            Path::new("/root/dist/main.pdf")
+        );
+        let page = PageSelection::First;
+        assert_eq!(
+            output_path(ExportKind::Png { page }),
+            Path::new("/root/previews/main.png")
+        );
+        let text = SvgTextMode::Outline;
+        assert_eq!(
+            output_path(ExportKind::Svg { page, text }),
+            Path::new("/root/out/dir/main.svg")
+        );
+        assert_eq!(
+            with_suffix(&output_path(ExportKind::Pdf), "watermarked"),
+            Path::new("/root/dist/main-watermarked.pdf")
+        );
+    }
+
+    #[test]
+    fn test_parse_page_filter() {
+        let filter: PageFilter = "3-8, 10".parse().unwrap();
+        assert_eq!(filter.select(12).unwrap(), vec![2, 3, 4, 5, 6, 7, 9]);
+        assert!(filter.select(9).is_err());
+
+        let filter: PageFilter = "5-,2".parse().unwrap();
+        assert_eq!(filter.select(6).unwrap(), vec![1, 4, 5]);
+        let filter: PageFilter = "-2".parse().unwrap();
+        assert_eq!(filter.select(6).unwrap(), vec![0, 1]);
+
+        assert!("0".parse::<PageFilter>().is_err());
+        assert!("4-2".parse::<PageFilter>().is_err());
+        assert!("a-b".parse::<PageFilter>().is_err());
+        assert!(" , ".parse::<PageFilter>().is_err());
+    }
+
+    #[test]
+    fn test_select_pages() {
+        let world = TestWorld::new("#for i in range(1, 6) [Page #i #if i < 5 { pagebreak() }]");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        assert_eq!(doc.pages.len(), 5);
+
+        let mut config = ExportConfig {
+            page_filter: Some("2-3".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(config.select_pages(&doc).unwrap().pages.len(), 2);
+
+        config.page_filter = None;
+        assert_eq!(config.select_pages(&doc).unwrap().pages.len(), 5);
+
+        // Only the elements in the selected pages are introspected.
+        let world = TestWorld::new("= One\n#pagebreak()\n= Two\n#pagebreak()\n= Three");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        config.page_filter = Some("2-".parse().unwrap());
+        let selected = config.select_pages(&doc).unwrap();
+        let headings = selected.introspector.query(&HeadingElem::elem().select());
+        assert_eq!(headings.len(), 2);
+    }
+
+    #[test]
+    fn test_exports_on_save() {
+        let root: ImmutPath = Path::new("/root").into();
+        let main = FileId::new(None, VirtualPath::new("main.typ"));
+        let mut config = ExportConfig {
+            entry: EntryState::new_rooted(root, Some(main)),
+            ..Default::default()
+        };
+        let chapter = Path::new("/root/chapters/intro.typ");
+        let main = Path::new("/root/main.typ");
+        assert!(config.exports_on_save(chapter));
+
+        config.save_glob = Some(IgnorePatterns::new(&["main.typ".to_owned()]).unwrap());
+        assert!(!config.exports_on_save(chapter));
+        assert!(config.exports_on_save(main));
+        assert!(!config.exports_on_save(Path::new("/elsewhere/main.typ")));
+    }
+
+    #[tokio::test]
+    async fn test_export_manifest() {
+        let dir = std::env::temp_dir().join(format!("tinymist-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let root: ImmutPath = dir.as_path().into();
+        let main = FileId::new(None, VirtualPath::new("main.typ"));
+        let config = ExportConfig {
+            entry: EntryState::new_rooted(root, Some(main)),
+            ..Default::default()
+        };
+
+        let world = TestWorld::new("= One\n#pagebreak()\n= Two");
+        let doc = Arc::new(typst::compile(&world, &mut Tracer::new()).unwrap());
+        let (_doc_tx, doc_rx) = watch::channel(Some(doc.clone()));
+        let (editor_tx, _editor_rx) = mpsc::unbounded_channel();
+        let (export_tx, export_rx) = mpsc::unbounded_channel();
+        let actor = ExportActor::new(
+            "primary".to_owned(),
+            doc_rx,
+            editor_tx,
+            export_rx,
+            config,
+            ExportKind::Pdf,
+            false,
+        );
+        tokio::spawn(actor.run());
+
+        let compiled = |doc: &Arc<TypstDocument>, warnings| {
+            let record = CompileRecord {
+                inputs: Dict::new(),
+                compiled_at: SystemTime::now(),
+                diagnostics: ManifestDiagnostics {
+                    errors: 0,
+                    warnings,
+                },
+            };
+            let req = ExportRequest::DocumentCompiled(doc.clone(), record);
+            export_tx.send(req).unwrap();
+        };
+        let export = || {
+            let (tx, rx) = oneshot::channel();
+            let req = ExportRequest::Manifest(ExportKind::Pdf, tx);
+            export_tx.send(req).unwrap();
+            rx
+        };
+
+        // The compilation of another document is not described.
+        compiled(&Arc::new(doc.as_ref().clone()), 1);
+        let (_, manifest) = export().await.unwrap().unwrap();
+        assert_eq!(manifest.diagnostics, None);
+
+        compiled(&doc, 2);
+        let (to, manifest) = export().await.unwrap().unwrap();
+        assert_eq!(to, dir.join("main.manifest.json"));
+        let diagnostics = ManifestDiagnostics {
+            errors: 0,
+            warnings: 2,
+        };
+        assert_eq!(manifest.diagnostics, Some(diagnostics));
+
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&to).unwrap()).unwrap();
+        assert_eq!(written["entry"], dir.join("main.typ").to_str().unwrap());
+        let outputs = written["outputs"].as_array().unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0]["path"], dir.join("main.pdf").to_str().unwrap());
+        assert_eq!(outputs[0]["pages"], 2);
+        assert_eq!(written["diagnostics"]["warnings"], 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_by_kind() {
+        let dir = std::env::temp_dir().join(format!("tinymist-by-kind-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let root: ImmutPath = dir.as_path().into();
+        let main = FileId::new(None, VirtualPath::new("main.typ"));
+
+        // The patterns are configured as users do.
+        let mut compile_config = CompileConfig::default();
+        let update = serde_json::json!({
+            "outputPath": {
+                "pdf": "$root/dist/$name",
+                "png": "$root/previews/$name",
+            },
+        });
+        compile_config
+            .update_by_map(update.as_object().unwrap())
+            .unwrap();
+        let config = ExportConfig {
+            substitute_pattern: compile_config.output_path.clone(),
+            pattern_by_kind: compile_config.output_path_by_kind.clone(),
+            entry: EntryState::new_rooted(root, Some(main)),
+            ..Default::default()
+        };
+
+        let world = TestWorld::new("Hello");
+        let doc = Arc::new(typst::compile(&world, &mut Tracer::new()).unwrap());
+        let (_doc_tx, doc_rx) = watch::channel(Some(doc));
+        let (editor_tx, _editor_rx) = mpsc::unbounded_channel();
+        let (export_tx, export_rx) = mpsc::unbounded_channel();
+        let actor = ExportActor::new(
+            "primary".to_owned(),
+            doc_rx,
+            editor_tx,
+            export_rx,
+            config,
+            ExportKind::Pdf,
+            false,
+        );
+        tokio::spawn(actor.run());
+
+        let export = |kind| {
+            let (tx, rx) = oneshot::channel();
+            let req = ExportRequest::Oneshot(Some(kind), tx);
+            export_tx.send(req).unwrap();
+            async { rx.await.unwrap().unwrap() }
+        };
+        let pdf = export(ExportKind::Pdf).await;
+        assert_eq!(pdf, dir.join("dist/main.pdf"));
+        assert!(std::fs::read(&pdf).unwrap().starts_with(b"%PDF"));
+        let (page, text) = (PageSelection::First, SvgTextMode::default());
+        let png = export(ExportKind::Png { page }).await;
+        assert_eq!(png, dir.join("previews/main.png"));
+        assert!(std::fs::read(&png).unwrap().starts_with(b"\x89PNG"));
+        // The kinds without a pattern are exported next to the document.
+        let svg = export(ExportKind::Svg { page, text }).await;
+        assert_eq!(svg, dir.join("main.svg"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
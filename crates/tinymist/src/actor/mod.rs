@@ -5,6 +5,7 @@ pub mod export;
 pub mod typ_client;
 pub mod typ_server;
 
+use std::future::Future;
 use std::path::Path;
 
 use tinymist_query::analysis::Analysis;
@@ -19,13 +20,14 @@ use typst_ts_compiler::{
 use typst_ts_core::config::compiler::EntryState;
 
 use self::{
+    editor::EditorRequest,
     export::{ExportActor, ExportConfig},
-    typ_client::{CompileClientActor, CompileDriver, CompileHandler},
+    typ_client::{CompileClientActor, CompileDriver, CompileHandler, RecompileSummary},
     typ_server::CompileServerActor,
 };
 use crate::{
     compile::CompileState,
-    world::{ImmutDict, LspWorld, LspWorldBuilder},
+    world::{ImmutDict, LspWorld, LspWorldBuilder, SharedFontResolver},
 };
 
 type CompileDriverInner = CompileDriverImpl<LspWorld>;
@@ -40,6 +42,7 @@ impl CompileState {
     ) -> CompileClientActor {
         let (doc_tx, doc_rx) = watch::channel(None);
         let (export_tx, export_rx) = mpsc::unbounded_channel();
+        let export_config = self.export_config(entry.clone());
 
         // Run Export actors before preparing cluster to avoid loss of events
         tokio::spawn(
@@ -48,11 +51,7 @@ impl CompileState {
                 doc_rx,
                 self.editor_tx.clone(),
                 export_rx,
-                ExportConfig {
-                    substitute_pattern: self.config.output_path.clone(),
-                    entry: entry.clone(),
-                    mode: self.config.export_pdf,
-                },
+                export_config.clone(),
                 ExportKind::Pdf,
                 self.config.notify_compile_status,
             )
@@ -60,27 +59,45 @@ impl CompileState {
         );
 
         // Create the server
+        #[cfg(feature = "preview")]
+        let preview_handle = std::sync::Arc::new(parking_lot::Mutex::new(None));
         let inner = Deferred::new({
             let handler = CompileHandler {
                 #[cfg(feature = "preview")]
-                inner: std::sync::Arc::new(parking_lot::Mutex::new(None)),
+                inner: preview_handle.clone(),
                 diag_group: editor_group.clone(),
                 doc_tx,
                 export_tx: export_tx.clone(),
                 editor_tx: self.editor_tx.clone(),
+                compile_log: self.compile_log.clone(),
             };
 
             let position_encoding = self.const_config.position_encoding;
             let enable_periscope = self.config.periscope_args.is_some();
+            let preferred_theme = self.config.preferred_theme;
+            let markdown_html = self.const_config.markdown_html;
+            let markdown_images = self.const_config.markdown_images;
+            let hover_math_preview = self.config.hover_math_preview;
+            let hover_set_rules = self.config.hover_set_rules;
+            let inlay_hint_document_values = self.config.inlay_hint_document_values;
+            let index_ignore = self.config.index_ignore.clone();
+            let user_snippets = self.config.user_snippets.clone();
             let periscope_args = self.config.periscope_args.clone();
             let diag_group = editor_group.clone();
             let entry = entry.clone();
             let font_resolver = self.font.clone();
+            let font_fallback = self.config.font_fallback.clone();
+            let compile_timeout = self.config.compile_timeout;
+            let persistent_cache = export_config.cache;
+            let lint_rules = self.config.lint_rules.clone();
+            let editor_tx = self.editor_tx.clone();
+            // The compiler is created on another thread, out of the runtime.
+            let handle = self.handle.clone();
             move || {
                 log::info!("TypstActor: creating server for {diag_group}, entry: {entry:?}, inputs: {inputs:?}");
 
                 // Create the world
-                let font_resolver = font_resolver.wait().clone();
+                let font_resolver = font_resolver.wait().with_fallback(&font_fallback);
                 let world = LspWorldBuilder::build(entry.clone(), font_resolver, inputs)
                     .expect("incorrect options");
 
@@ -93,13 +110,30 @@ impl CompileState {
                         position_encoding,
                         root: Path::new("").into(),
                         enable_periscope,
+                        preferred_theme,
+                        markdown_html,
+                        markdown_images,
+                        hover_math_preview,
+                        hover_set_rules,
+                        inlay_hint_document_values,
+                        index_ignore,
+                        user_snippets,
                         caches: Default::default(),
                     },
                     periscope: PeriscopeRenderer::new(periscope_args.unwrap_or_default()),
+                    compile_timeout,
+                    persistent_cache,
+                    cold: true,
+                    last_compiled: None,
+                    lint_rules,
                 };
 
                 // Create the actor
-                let server = CompileServerActor::new(driver, entry).with_watch(true);
+                let server = CompileServerActor::new(driver, entry)
+                    .with_watch(true)
+                    .with_panic_handler(move |message| {
+                        let _ = editor_tx.send(EditorRequest::InternalError(message));
+                    });
                 let client = server.client();
 
                 // We do send memory changes instead of initializing compiler with them.
@@ -107,12 +141,75 @@ impl CompileState {
                 // must update them.
                 client.add_memory_changes(MemoryEvent::Update(snapshot));
 
-                tokio::spawn(server.run());
+                handle.spawn(server.run());
 
                 client
             }
         });
 
-        CompileClientActor::new(editor_group, self.config.clone(), entry, inner, export_tx)
+        CompileClientActor::new(
+            editor_group,
+            self.config.clone(),
+            entry,
+            inner,
+            export_tx,
+            #[cfg(feature = "preview")]
+            preview_handle,
+        )
+    }
+
+    /// Gets the export configuration of the entry from the configuration.
+    pub(crate) fn export_config(&self, entry: EntryState) -> ExportConfig {
+        ExportConfig {
+            substitute_pattern: self.config.output_path.clone(),
+            pattern_by_kind: self.config.output_path_by_kind.clone(),
+            entry,
+            mode: self.config.export_pdf,
+            page_filter: None,
+            cache: self.config.persistent_cache().map(std::sync::Arc::new),
+            save_glob: self.config.export_on_save_glob.clone(),
+            cjk_mode: self.config.cjk_mode,
+        }
+    }
+
+    /// Tear down the compiler actor and create a fresh one in place.
+    ///
+    /// The current entry is preserved and the memory overlay is sent to the
+    /// new compiler, so no unsaved edits are lost. The overlay is given by the
+    /// [`vfs_snapshot`] of the primary state, as the dedicated ones don't
+    /// record the unsaved buffers.
+    ///
+    /// [`vfs_snapshot`]: CompileState::vfs_snapshot
+    pub fn restart_server(&mut self, snapshot: FileChangeSet) {
+        let Some(mut prev) = self.compiler.take() else {
+            log::warn!("TypstActor: no compiler to restart");
+            return;
+        };
+
+        let editor_group = prev.diag_group.clone();
+        log::info!("TypstActor({editor_group}): restarting compiler");
+        let next = self.server(
+            editor_group,
+            prev.entry().clone(),
+            self.config.determine_inputs(),
+            snapshot,
+        );
+        self.compiler = Some(next);
+        // The previewer is bound to the old compiler.
+        self.preview.shutdown();
+
+        // The old compiler may be stuck, so we settle it in the background.
+        tokio::spawn(async move { prev.settle().await });
+    }
+
+    /// Switch to the given fonts and compile the document again from a clean
+    /// state. See [`CompileClientActor::recompile`] for details.
+    pub fn recompile(
+        &mut self,
+        fonts: Deferred<SharedFontResolver>,
+    ) -> impl Future<Output = anyhow::Result<RecompileSummary>> + Send + 'static {
+        // The compilers created later use the fonts as well.
+        self.font = fonts.clone();
+        self.compiler().recompile(fonts)
     }
 }
@@ -27,16 +27,21 @@
 
 use std::{
     collections::HashMap,
+    future::Future,
     ops::Deref,
     path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, SystemTime},
 };
 
-use anyhow::{anyhow, bail};
+use anyhow::{anyhow, bail, Context};
 use parking_lot::Mutex;
+use serde::Serialize;
 use tinymist_query::{
     analysis::{Analysis, AnalysisContext, AnalysisResources},
-    DiagnosticsMap, ExportKind, ServerInfoResponse, VersionedDocument,
+    syntax::IgnorePatterns,
+    DiagnosticsMap, ExportKind, LintDocumentRequest, LintRules, SemanticRequest,
+    ServerInfoResponse, VersionedDocument,
 };
 use tinymist_render::PeriscopeRenderer;
 use tokio::sync::{mpsc, oneshot, watch};
@@ -44,34 +49,73 @@ use typst::{
     diag::{PackageError, SourceDiagnostic, SourceResult},
     layout::Position,
     model::Document as TypstDocument,
-    syntax::package::PackageSpec,
+    syntax::package::{PackageSpec, PackageVersion, VersionlessPackageSpec},
+    syntax::{FileId, Source, Span, VirtualPath},
     util::Deferred,
     World as TypstWorld,
 };
 use typst_ts_compiler::{
+    package::http::HttpRegistry,
     service::{CompileDriverImpl, CompileEnv, CompileMiddleware, Compiler, EnvWorld},
     vfs::notify::MemoryEvent,
     Time,
 };
 use typst_ts_core::{
     config::compiler::EntryState, debug_loc::DataSource, error::prelude::*, typst::prelude::EcoVec,
-    Error, ImmutPath, TypstFont,
+    Error, ImmutPath, TypstDict, TypstFont,
 };
 
 use super::{
-    editor::{EditorRequest, TinymistCompileStatusEnum},
-    export::ExportConfig,
+    editor::{EditorRequest, TinymistCompileStatusEnum, TypstCompileStatus},
+    export::{ExportConfig, PageFilter},
     typ_server::{CompileClient as TsCompileClient, CompileServerActor, Interrupt},
 };
 use crate::{
     actor::export::ExportRequest,
     actor::typ_server::EntryStateExt,
     compile_init::CompileConfig,
-    tools::preview::{CompilationHandle, CompileStatus},
-    world::LspWorld,
+    logging::COMPILE_EVENT,
+    state::normalize_path,
+    telemetry::CompileLog,
+    tools::manifest::{CompileRecord, ExportManifest, ManifestDiagnostics},
+    tools::package::{
+        cached_latest_version, cached_package_dir, cached_preview_packages,
+        determine_latest_version,
+    },
+    tools::persistent_cache::PersistentCache,
+    tools::preview::{CompilationHandle, CompileStatus, PreviewUrls},
+    tools::watermark::{self, Watermark},
+    world::{probe_compile, CompileTimeout, LspWorld, LspWorldBuilder, SharedFontResolver},
 };
 
 type CompileDriverInner = CompileDriverImpl<LspWorld>;
+
+/// Fingerprint a file read by a compilation, which is stable across restarts
+/// as long as the file is unchanged. The files in the root are fingerprinted
+/// by their contents as the world sees them, i.e. with unsaved edits, and the
+/// others, e.g. those of packages, by their modification times.
+fn fingerprint(world: &LspWorld, path: &Path) -> Option<u128> {
+    let root = world.entry_state().root();
+    match root.and_then(|root| VirtualPath::within_root(path, &root)) {
+        Some(vpath) => {
+            let data = world.file(FileId::new(None, vpath)).ok()?;
+            Some(typst::util::hash128(&data))
+        }
+        None => {
+            let modified = std::fs::metadata(path).and_then(|meta| meta.modified());
+            Some(typst::util::hash128(&modified.ok()?))
+        }
+    }
+}
+
+/// A document compiled with the persistent cache enabled, which is reused
+/// while the inputs of its compilation are unchanged.
+pub struct LastCompiled {
+    scope: u128,
+    fingerprints: Vec<(PathBuf, u128)>,
+    doc: Arc<TypstDocument>,
+}
+
 type CompileService = CompileServerActor<CompileDriver>;
 type CompileClient = TsCompileClient<CompileService>;
 
@@ -84,6 +128,8 @@ pub struct CompileHandler {
     pub(super) doc_tx: watch::Sender<Option<Arc<TypstDocument>>>,
     pub(super) export_tx: mpsc::UnboundedSender<ExportRequest>,
     pub(super) editor_tx: mpsc::UnboundedSender<EditorRequest>,
+    /// The log of the latest compilations, shared by the compilers.
+    pub(super) compile_log: Arc<CompileLog>,
 }
 
 impl CompilationHandle for CompileHandler {
@@ -119,6 +165,56 @@ impl CompilationHandle for CompileHandler {
 }
 
 impl CompileHandler {
+    /// Notifies that a compilation of the entry begins.
+    fn compile_begin(&self, entry: Option<PathBuf>) {
+        self.push_compile_status(TypstCompileStatus {
+            group: self.diag_group.clone(),
+            status: TinymistCompileStatusEnum::Compiling,
+            entry,
+            elapsed_ms: 0,
+            errors: 0,
+            warnings: 0,
+        });
+    }
+
+    /// Notifies that a compilation of the entry ends with some diagnostics.
+    fn compile_end(
+        &self,
+        entry: Option<PathBuf>,
+        elapsed: Duration,
+        errors: usize,
+        warnings: usize,
+    ) {
+        let status = if errors == 0 {
+            TinymistCompileStatusEnum::CompileSuccess
+        } else {
+            TinymistCompileStatusEnum::CompileError
+        };
+        self.compile_log.record(
+            self.diag_group.clone(),
+            entry.clone(),
+            status.clone(),
+            errors,
+            warnings,
+            elapsed,
+        );
+        self.push_compile_status(TypstCompileStatus {
+            group: self.diag_group.clone(),
+            status,
+            entry,
+            elapsed_ms: elapsed.as_millis() as u64,
+            errors,
+            warnings,
+        });
+    }
+
+    fn push_compile_status(&self, status: TypstCompileStatus) {
+        let res = self.editor_tx.send(EditorRequest::CompileStatus(status));
+        if let Err(err) = res {
+            log::error!("failed to send compile status: {err:#}");
+        }
+    }
+
     fn push_diagnostics(&mut self, diagnostics: Option<DiagnosticsMap>) {
         let res = self
             .editor_tx
@@ -134,6 +230,19 @@ pub struct CompileDriver {
     pub(super) handler: CompileHandler,
     pub(super) analysis: Analysis,
     pub(super) periscope: PeriscopeRenderer,
+    /// The time budget of a compilation, exceeding which aborts it.
+    pub(super) compile_timeout: Option<Duration>,
+    /// The on-disk cache of the exported artifacts, if enabled.
+    pub(super) persistent_cache: Option<Arc<PersistentCache>>,
+    /// Whether no compilation has run yet, before which the persistent cache
+    /// is checked.
+    pub(super) cold: bool,
+    /// The last document compiled with the persistent cache enabled, with the
+    /// scope and the fingerprints of the files read by its compilation.
+    pub(super) last_compiled: Option<LastCompiled>,
+    /// The lint rules to toggle, whose findings are published along with the
+    /// diagnostics of the compilation.
+    pub(super) lint_rules: LintRules,
 }
 
 impl CompileMiddleware for CompileDriver {
@@ -148,6 +257,18 @@ impl CompileMiddleware for CompileDriver {
     }
 
     fn wrap_compile(&mut self, env: &mut CompileEnv) -> SourceResult<Arc<typst::model::Document>> {
+        let entry = self.inner.world().entry_state();
+        let entry = entry.main().zip(entry.root());
+        let entry = entry.and_then(|(main, root)| main.vpath().resolve(&root));
+        let scope = self.inputs_scope(entry.as_deref());
+        if let Some(doc) = self.unchanged_doc(scope) {
+            log::info!(
+                "TypstActor({}): inputs are unchanged, skip compiling",
+                self.handler.diag_group
+            );
+            return Ok(doc);
+        }
+
         self.handler
             .editor_tx
             .send(EditorRequest::Status(
@@ -156,19 +277,56 @@ impl CompileMiddleware for CompileDriver {
             ))
             .unwrap();
         self.handler.status(CompileStatus::Compiling);
-        match self.inner_mut().compile(env) {
+        self.handler.compile_begin(entry.clone());
+        if std::mem::take(&mut self.cold) {
+            self.export_recorded(scope);
+        }
+        let start = std::time::Instant::now();
+        let probe = (self.compile_timeout).map(|budget| probe_compile(self.inner.world(), budget));
+        let res = match probe {
+            Some(Err(timeout)) => {
+                log::warn!("TypstActor({}): {timeout}", self.handler.diag_group);
+                Err(self.timeout_diagnostic(timeout))
+            }
+            _ => self.inner_mut().compile(env),
+        };
+        let elapsed = start.elapsed();
+        let status = if res.is_ok() { "ok" } else { "error" };
+        log::info!(
+            target: COMPILE_EVENT,
+            "compiled {}: {status} in {elapsed:?}",
+            self.handler.diag_group,
+        );
+        let warnings = env.tracer.as_ref().map(|e| e.clone().warnings());
+        let warning_count = warnings.as_ref().map_or(0, |w| w.len());
+        match res {
             Ok(doc) => {
+                let inputs = self.record_inputs(scope, &doc);
+                self.handler.compile_end(entry, elapsed, 0, warning_count);
                 self.handler.notify_compile(Ok(doc.clone()));
-                self.notify_diagnostics(
-                    EcoVec::new(),
-                    env.tracer.as_ref().map(|e| e.clone().warnings()),
-                );
+                if let Some(inputs) = inputs {
+                    let inputs = ExportRequest::DocumentInputs(doc.clone(), inputs);
+                    let _ = self.handler.export_tx.send(inputs);
+                }
+                let record = CompileRecord {
+                    inputs: TypstDict::clone(&self.inner.world().inputs),
+                    compiled_at: SystemTime::now(),
+                    diagnostics: ManifestDiagnostics {
+                        errors: 0,
+                        warnings: warning_count,
+                    },
+                };
+                let compiled = ExportRequest::DocumentCompiled(doc.clone(), record);
+                let _ = self.handler.export_tx.send(compiled);
+                self.notify_diagnostics(EcoVec::new(), warnings);
                 Ok(doc)
             }
             Err(err) => {
+                self.handler
+                    .compile_end(entry, elapsed, err.len(), warning_count);
                 self.handler
                     .notify_compile(Err(CompileStatus::CompileError));
-                self.notify_diagnostics(err, env.tracer.as_ref().map(|e| e.clone().warnings()));
+                self.notify_diagnostics(err, warnings);
                 Err(EcoVec::new())
             }
         }
@@ -176,6 +334,79 @@ impl CompileMiddleware for CompileDriver {
 }
 
 impl CompileDriver {
+    /// Report an aborted compilation on the main file, which is the only file
+    /// known to be involved.
+    fn timeout_diagnostic(&self, timeout: CompileTimeout) -> EcoVec<SourceDiagnostic> {
+        let world = self.inner.world();
+        let main = world.main_id().and_then(|id| world.source(id).ok());
+        let span = main.map_or_else(Span::detached, |main| main.root().span());
+        let mut diagnostics = EcoVec::new();
+        diagnostics.push(SourceDiagnostic::error(span, timeout.to_string()));
+        diagnostics
+    }
+
+    /// Hash the scope of the compilation, i.e. the entry, the inputs from
+    /// `sys.inputs` and the fonts, which is known before compiling.
+    fn inputs_scope(&self, entry: Option<&Path>) -> u128 {
+        let world = self.inner.world();
+        typst::util::hash128(&(entry, world.inputs.as_ref(), world.book()))
+    }
+
+    /// Export the artifacts recorded in the persistent cache for the scope
+    /// before compiling, if the files read by the last compilation in it are
+    /// unchanged, e.g. on a cold start of an unchanged project.
+    fn export_recorded(&self, scope: u128) {
+        let Some(cache) = &self.persistent_cache else {
+            return;
+        };
+        let world = self.inner.world();
+        if let Some(inputs) = cache.recorded_inputs(scope, |path| fingerprint(world, path)) {
+            log::info!("TypstActor: found recorded inputs {inputs:032x} before compiling");
+            let _ = (self.handler.export_tx).send(ExportRequest::RecordedInputs(inputs));
+        }
+    }
+
+    /// Get the last compiled document if neither the scope nor the files read
+    /// by its compilation are changed, which needs no compilation then.
+    fn unchanged_doc(&self, scope: u128) -> Option<Arc<TypstDocument>> {
+        self.persistent_cache.as_ref()?;
+        let last = self.last_compiled.as_ref()?;
+        let world = self.inner.world();
+        let unchanged = last.scope == scope
+            && (last.fingerprints.iter())
+                .all(|(path, recorded)| fingerprint(world, path) == Some(*recorded));
+        unchanged.then(|| last.doc.clone())
+    }
+
+    /// Record the fingerprints of the files read by the last compilation in
+    /// the persistent cache, and get the hash of its inputs.
+    fn record_inputs(&mut self, scope: u128, doc: &Arc<TypstDocument>) -> Option<u128> {
+        use typst_ts_compiler::NotifyApi;
+
+        let cache = self.persistent_cache.clone()?;
+        let world = self.inner.world();
+        let mut deps = vec![];
+        world.iter_dependencies(&mut |path, _| deps.push(path.to_path_buf()));
+        deps.sort();
+        let fingerprints = deps.into_iter().map(|path| {
+            let fingerprint = fingerprint(world, &path)?;
+            Some((path, fingerprint))
+        });
+        let fingerprints = fingerprints.collect::<Option<Vec<_>>>()?;
+        self.last_compiled = Some(LastCompiled {
+            scope,
+            fingerprints: fingerprints.clone(),
+            doc: doc.clone(),
+        });
+        match cache.record_inputs(scope, fingerprints) {
+            Ok(inputs) => Some(inputs),
+            Err(err) => {
+                log::warn!("TypstActor: failed to record inputs: {err:#}");
+                None
+            }
+        }
+    }
+
     fn notify_diagnostics(
         &mut self,
         errors: EcoVec<SourceDiagnostic>,
@@ -183,8 +414,19 @@ impl CompileDriver {
     ) {
         log::trace!("notify diagnostics: {errors:#?} {warnings:#?}");
 
+        let entry = self.inner.world().entry_state();
+        let main = entry.main().zip(entry.root());
+        let main = main.and_then(|(main, root)| main.vpath().resolve(&root));
+        let rules = self.lint_rules.clone();
         let diagnostics = self.run_analysis(|ctx| {
-            tinymist_query::convert_diagnostics(ctx, errors.iter().chain(warnings.iter().flatten()))
+            let diagnostics = errors.iter().chain(warnings.iter().flatten());
+            let mut diagnostics = tinymist_query::convert_diagnostics(ctx, diagnostics);
+            // The style issues are published in the group of the compiler.
+            let lints = main.and_then(|path| LintDocumentRequest { path, rules }.request(ctx));
+            for (url, lints) in lints.into_iter().flatten() {
+                diagnostics.entry(url).or_default().extend(lints);
+            }
+            diagnostics
         });
 
         match diagnostics {
@@ -242,6 +484,44 @@ impl CompileDriver {
                 self.0.iter_dependencies(f)
             }
 
+            fn latest_package_version(
+                &self,
+                spec: &VersionlessPackageSpec,
+            ) -> Option<PackageVersion> {
+                cached_latest_version(self.0, spec)
+            }
+
+            fn local_package_dir(&self, spec: &PackageSpec) -> Option<Arc<Path>> {
+                cached_package_dir(self.0, spec)
+            }
+
+            fn inputs(&self) -> TypstDict {
+                self.0.inputs.as_ref().deref().clone()
+            }
+
+            fn local_packages(&self) -> EcoVec<PackageSpec> {
+                use typst_ts_compiler::package::Registry;
+                let dirs = self.0.registry.local_path().into_iter();
+                let names = dirs
+                    .flat_map(|dir| std::fs::read_dir(dir.join("typst/packages/local")).ok())
+                    .flatten()
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok());
+                names
+                    .filter_map(|name| {
+                        let spec = VersionlessPackageSpec {
+                            namespace: "local".into(),
+                            name: name.into(),
+                        };
+                        let version = determine_latest_version(self.0, &spec).ok()?;
+                        Some(spec.at(version))
+                    })
+                    .collect()
+            }
+
+            fn preview_packages(&self) -> EcoVec<PackageSpec> {
+                cached_preview_packages(self.0)
+            }
+
             /// Resolve extra font information.
             fn font_info(&self, font: TypstFont) -> Option<Arc<DataSource>> {
                 self.0.font_resolver.inner.describe_font(&font)
@@ -265,12 +545,29 @@ impl CompileDriver {
     }
 }
 
+/// The result of compiling a document again from a clean state.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecompileSummary {
+    pub group: String,
+    pub entry: Option<PathBuf>,
+    /// Whether the document is compiled again, which is not the case if no
+    /// entry is focused.
+    pub compiled: bool,
+    pub success: bool,
+    pub pages: usize,
+}
+
+#[derive(Clone)]
 pub struct CompileClientActor {
     pub diag_group: String,
     pub config: CompileConfig,
     entry: EntryState,
     inner: Deferred<CompileClient>,
     export_tx: mpsc::UnboundedSender<ExportRequest>,
+
+    #[cfg(feature = "preview")]
+    preview_handle: Arc<Mutex<Option<typst_preview::CompilationHandleImpl>>>,
 }
 
 impl CompileClientActor {
@@ -280,6 +577,9 @@ impl CompileClientActor {
         entry: EntryState,
         inner: Deferred<CompileClient>,
         export_tx: mpsc::UnboundedSender<ExportRequest>,
+        #[cfg(feature = "preview")] preview_handle: Arc<
+            Mutex<Option<typst_preview::CompilationHandleImpl>>,
+        >,
     ) -> Self {
         Self {
             diag_group,
@@ -287,6 +587,24 @@ impl CompileClientActor {
             entry,
             inner,
             export_tx,
+            #[cfg(feature = "preview")]
+            preview_handle,
+        }
+    }
+
+    /// Start a preview server sharing the compiler, returning its addresses.
+    /// The server is stopped once the `stop` receiver is notified.
+    pub fn start_preview(&self, stop: oneshot::Receiver<()>) -> anyhow::Result<PreviewUrls> {
+        #[cfg(feature = "preview")]
+        return crate::tools::preview::start_preview(
+            self.clone(),
+            self.preview_handle.clone(),
+            stop,
+        );
+        #[cfg(not(feature = "preview"))]
+        {
+            let _ = stop;
+            bail!("tinymist is built without the preview feature");
         }
     }
 
@@ -306,9 +624,17 @@ impl CompileClientActor {
         &self,
         f: impl FnOnce(&mut AnalysisContext, Option<VersionedDocument>) -> T + Send + Sync + 'static,
     ) -> anyhow::Result<T> {
+        let theme = self.config.preferred_theme;
+        let hover_math_preview = self.config.hover_math_preview;
+        let hover_set_rules = self.config.hover_set_rules;
+        let inlay_hint_document_values = self.config.inlay_hint_document_values;
         self.steal(move |compiler| {
             let doc = compiler.success_doc();
             let c = &mut compiler.compiler.compiler;
+            c.analysis.preferred_theme = theme;
+            c.analysis.hover_math_preview = hover_math_preview;
+            c.analysis.hover_set_rules = hover_set_rules;
+            c.analysis.inlay_hint_document_values = inlay_hint_document_values;
             c.run_analysis(move |ctx| f(ctx, doc))
         })
         .await?
@@ -318,8 +644,35 @@ impl CompileClientActor {
         &self,
         f: impl FnOnce(&mut AnalysisContext) -> T + Send + Sync + 'static,
     ) -> anyhow::Result<T> {
-        self.steal(move |compiler| compiler.compiler.compiler.run_analysis(f))
-            .await?
+        let theme = self.config.preferred_theme;
+        let hover_math_preview = self.config.hover_math_preview;
+        let hover_set_rules = self.config.hover_set_rules;
+        let inlay_hint_document_values = self.config.inlay_hint_document_values;
+        self.steal(move |compiler| {
+            let c = &mut compiler.compiler.compiler;
+            c.analysis.preferred_theme = theme;
+            c.analysis.hover_math_preview = hover_math_preview;
+            c.analysis.hover_set_rules = hover_set_rules;
+            c.analysis.inlay_hint_document_values = inlay_hint_document_values;
+            c.run_analysis(f)
+        })
+        .await?
+    }
+
+    /// Fork the world of the compiler along with the source of the file, for
+    /// long tasks to compile the file off the compiler thread.
+    pub async fn fork_world(&self, path: PathBuf) -> anyhow::Result<(LspWorld, Source)> {
+        self.steal(move |compiler| {
+            let world = compiler.compiler.compiler.world();
+            let root = world.entry_state().root().context("root is not set")?;
+            let vpath = VirtualPath::within_root(&path, &root)
+                .with_context(|| format!("{path:?} is not in the root {root:?}"))?;
+            let main = world.source(FileId::new(None, vpath))?;
+            let fork = LspWorldBuilder::fork(world, world.font_resolver.clone())
+                .map_err(|err| anyhow!("failed to fork the world: {err}"))?;
+            anyhow::Ok((fork, main))
+        })
+        .await?
     }
 
     pub async fn settle(&mut self) {
@@ -332,6 +685,17 @@ impl CompileClientActor {
     }
 
     pub fn change_config(&mut self, config: CompileConfig) {
+        if config.lint_rules != self.config.lint_rules {
+            // The rules take effect from the next compilation.
+            let rules = config.lint_rules.clone();
+            let client = self.inner().clone();
+            tokio::spawn(async move {
+                let res = client.steal(move |c| c.compiler.compiler.lint_rules = rules);
+                if let Err(err) = res.await {
+                    log::error!("TypstActor: failed to change lint rules: {err:#}");
+                }
+            });
+        }
         self.config = config;
     }
 
@@ -343,15 +707,37 @@ impl CompileClientActor {
             return Err(error_once!("entry file must be absolute", path: path.unwrap().display()));
         }
 
+        let path = path.map(|p| normalize_path(&p, &self.config.roots));
         let entry = self.config.determine_entry(path);
+        self.apply_entry(entry);
+
+        Ok(true)
+    }
+
+    /// Resolves the entry again with the current configuration, e.g. after the
+    /// workspace roots are changed. Returns whether the entry is changed.
+    pub fn reload_entry(&mut self) -> bool {
+        let main = self.entry.main().zip(self.entry.root());
+        let main = main.and_then(|(main, root)| main.vpath().resolve(&root));
+        let entry = self.config.determine_entry(main.map(ImmutPath::from));
+        if entry.root() == self.entry.root() {
+            return false;
+        }
+
+        let group = &self.diag_group;
+        log::info!("TypstActor({group}): entry is reloaded as {entry:?}");
+        self.apply_entry(entry);
+        true
+    }
+
+    fn apply_entry(&mut self, entry: EntryState) {
+        self.entry = entry.clone();
 
         let _ = self
             .inner()
             .intr_tx
             .send(Interrupt::ChangeEntry(entry.clone()));
         let _ = self.export_tx.send(ExportRequest::ChangeExportPath(entry));
-
-        Ok(true)
     }
 
     pub fn add_memory_changes(&self, event: MemoryEvent) {
@@ -362,6 +748,18 @@ impl CompileClientActor {
         let _ = self.export_tx.send(ExportRequest::ChangeConfig(config));
     }
 
+    pub(crate) fn change_page_filter(&self, filter: Option<PageFilter>) {
+        let _ = self.export_tx.send(ExportRequest::ChangePageFilter(filter));
+    }
+
+    pub(crate) fn change_save_glob(&self, glob: Option<IgnorePatterns>) {
+        let _ = self.export_tx.send(ExportRequest::ChangeSaveGlob(glob));
+    }
+
+    pub(crate) fn change_cjk_mode(&self, cjk_mode: bool) {
+        let _ = self.export_tx.send(ExportRequest::ChangeCjkMode(cjk_mode));
+    }
+
     pub async fn clear_cache(&self) {
         let _ = self
             .steal(|c| {
@@ -370,25 +768,75 @@ impl CompileClientActor {
             .await;
     }
 
-    pub async fn collect_server_info(&self) -> anyhow::Result<HashMap<String, ServerInfoResponse>> {
+    /// Evict all caches, switch to the given fonts and a fresh package
+    /// registry, and compile the document again, waiting for the compilation
+    /// to finish.
+    pub fn recompile(
+        &self,
+        fonts: Deferred<SharedFontResolver>,
+    ) -> impl Future<Output = anyhow::Result<RecompileSummary>> + Send + 'static {
+        let group = self.diag_group.clone();
+        let entry = self.entry.main().zip(self.entry.root());
+        let entry = entry.and_then(|(main, root)| main.vpath().resolve(&root));
+        let client = self.inner().clone();
+        async move {
+            let before = client
+                .steal(move |c| {
+                    comemo::evict(0);
+                    c.compiler.compiler.analysis.caches = Default::default();
+                    let world = c.compiler.world_mut();
+                    world.font_resolver = world.font_resolver.with_fonts(fonts.wait());
+                    // The registry remembers the package index and the
+                    // packages failed to download.
+                    world.registry = HttpRegistry::default();
+                    c.compile_count()
+                })
+                .await?;
+            client.compile();
+            // Tasks are run after the compilations requested before them.
+            let (after, doc) = client.steal(|c| (c.compile_count(), c.doc())).await?;
+
+            Ok(RecompileSummary {
+                group,
+                entry,
+                compiled: after > before,
+                success: doc.is_some(),
+                pages: doc.map_or(0, |doc| doc.document.pages.len()),
+            })
+        }
+    }
+
+    pub fn entry(&self) -> &EntryState {
+        &self.entry
+    }
+
+    pub fn collect_server_info(
+        &self,
+    ) -> impl Future<Output = anyhow::Result<HashMap<String, ServerInfoResponse>>> + Send + 'static
+    {
         let dg = self.diag_group.clone();
-        self.steal(move |c| {
-            let cc = &c.compiler.compiler;
-
-            let info = ServerInfoResponse {
-                root: cc.world().entry.root().map(|e| e.as_ref().to_owned()),
-                font_paths: cc.world().font_resolver.font_paths().to_owned(),
-                inputs: cc.world().inputs.as_ref().deref().clone(),
-                estimated_memory_usage: HashMap::from_iter([
-                    ("vfs".to_owned(), cc.world().vfs.memory_usage()),
-                    ("analysis".to_owned(), cc.analysis.estimated_memory()),
-                ]),
-            };
+        let client = self.inner().clone();
+        async move {
+            client
+                .steal(move |c| {
+                    let cc = &c.compiler.compiler;
 
-            HashMap::from_iter([(dg, info)])
-        })
-        .await
-        .map_err(|e| e.into())
+                    let info = ServerInfoResponse {
+                        root: cc.world().entry.root().map(|e| e.as_ref().to_owned()),
+                        font_paths: cc.world().font_resolver.font_paths().to_owned(),
+                        inputs: cc.world().inputs.as_ref().deref().clone(),
+                        estimated_memory_usage: HashMap::from_iter([
+                            ("vfs".to_owned(), cc.world().vfs.memory_usage()),
+                            ("analysis".to_owned(), cc.analysis.estimated_memory()),
+                        ]),
+                        requests: Vec::new(),
+                    };
+
+                    HashMap::from_iter([(dg, info)])
+                })
+                .await
+                .map_err(|e| e.into())
+        }
     }
 
     pub fn on_export(&self, kind: ExportKind, path: PathBuf) -> oneshot::Receiver<Option<PathBuf>> {
@@ -399,8 +847,249 @@ impl CompileClientActor {
         rx
     }
 
+    /// Export the latest successfully compiled document and write a manifest
+    /// describing the export, receiving the path of the manifest.
+    pub fn on_export_manifest(
+        &self,
+        kind: ExportKind,
+    ) -> oneshot::Receiver<Option<(PathBuf, ExportManifest)>> {
+        log::info!("CompileActor: on export with manifest: {kind:?}");
+        let (tx, rx) = oneshot::channel();
+        let _ = self.export_tx.send(ExportRequest::Manifest(kind, tx));
+        rx
+    }
+
+    /// Export the latest successfully compiled document with the watermark
+    /// overlaid on its pages.
+    pub fn on_export_watermarked(
+        &self,
+        kind: ExportKind,
+        path: PathBuf,
+        watermark: Watermark,
+    ) -> impl Future<Output = anyhow::Result<Option<PathBuf>>> + Send + 'static {
+        log::info!("CompileActor: on watermarked export: {}", path.display());
+        let client = self.inner().clone();
+        let export_tx = self.export_tx.clone();
+        async move {
+            let doc = client
+                .steal(move |c| {
+                    let doc = c
+                        .success_doc()
+                        .context("the document is not compiled yet")?;
+                    let world = c.compiler.compiler.world();
+                    watermark::watermark(world, &doc.document, &watermark)
+                })
+                .await??;
+
+            // The watermarked export is written next to the regular one.
+            let (tx, rx) = oneshot::channel();
+            let doc = Arc::new(doc);
+            let _ = export_tx.send(ExportRequest::OneshotDoc(kind, doc, "watermarked", tx));
+            Ok(rx.await?)
+        }
+    }
+
     pub fn on_save_export(&self, path: PathBuf) {
         log::info!("CompileActor: on save export: {}", path.display());
         let _ = self.export_tx.send(ExportRequest::OnSaved(path));
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use lsp_types::NumberOrString;
+    use typst_ts_compiler::vfs::notify::FileChangeSet;
+
+    use super::*;
+    use crate::compile::CompileState;
+
+    /// Compile the content as `/doc/main.typ` by the primary compiler of the
+    /// state, returning the compile statuses sent to the editor meanwhile.
+    async fn compile_cycle(
+        state: &CompileState,
+        editor_rx: &mut mpsc::UnboundedReceiver<EditorRequest>,
+        content: &str,
+    ) -> Vec<TypstCompileStatus> {
+        let changes = MemoryEvent::Update(main_overlay(content));
+        state.compiler().inner().add_memory_changes(changes);
+        state.compiler().steal(|_| ()).await.unwrap();
+
+        let mut statuses = vec![];
+        while let Ok(req) = editor_rx.try_recv() {
+            if let EditorRequest::CompileStatus(status) = req {
+                statuses.push(status);
+            }
+        }
+        statuses
+    }
+
+    #[tokio::test]
+    async fn test_compile_status() {
+        use TinymistCompileStatusEnum::*;
+
+        let (state, mut editor_rx) = compile_state("");
+        compile_cycle(&state, &mut editor_rx, "").await;
+
+        let statuses = compile_cycle(&state, &mut editor_rx, "Hello ** __").await;
+        let [begin, end] = statuses.try_into().unwrap();
+        assert!(matches!(begin.status, Compiling));
+        assert_eq!(begin.entry.as_deref(), Some(Path::new("/doc/main.typ")));
+        assert!(matches!(end.status, CompileSuccess));
+        assert_eq!(end.group, "primary");
+        assert_eq!((end.errors, end.warnings), (0, 2));
+
+        let statuses = compile_cycle(&state, &mut editor_rx, "#unknown").await;
+        let [begin, end] = statuses.try_into().unwrap();
+        assert!(matches!(begin.status, Compiling));
+        assert!(matches!(end.status, CompileError));
+        assert_eq!((end.errors, end.warnings), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_compile_log() {
+        let (state, mut editor_rx) = compile_state("");
+        state.compile_log.set_capacity(3);
+
+        for content in ["One", "#unknown", "Three", "Four"] {
+            compile_cycle(&state, &mut editor_rx, content).await;
+        }
+        let events = state.compile_log.snapshot();
+        let errors: Vec<_> = events.iter().map(|e| e.errors).collect();
+        assert_eq!(errors, [1, 0, 0]);
+        assert!(events
+            .windows(2)
+            .all(|w| w[0].timestamp_ms <= w[1].timestamp_ms));
+        assert_eq!(events[0].entry.as_deref(), Some(Path::new("/doc/main.typ")));
+
+        state.compile_log.set_capacity(1);
+        assert_eq!(state.compile_log.snapshot().len(), 1);
+    }
+
+    /// Create the memory overlay of `/doc/main.typ` with the content.
+    fn main_overlay(content: &str) -> FileChangeSet {
+        use typst::diag::FileResult;
+        use typst_ts_core::Bytes;
+
+        let content: Bytes = content.as_bytes().into();
+        let snapshot = FileResult::Ok((Time::now(), content)).into();
+        FileChangeSet::new_inserts(vec![(Path::new("/doc/main.typ").into(), snapshot)])
+    }
+
+    /// Create a compile state with a primary compiler of an in-memory
+    /// `/doc/main.typ`, along with the receiver of its editor requests.
+    pub(crate) fn compile_state(
+        content: &'static str,
+    ) -> (CompileState, mpsc::UnboundedReceiver<EditorRequest>) {
+        use comemo::Prehashed;
+
+        use crate::world::CompileFontOpts;
+
+        let (editor_tx, editor_rx) = mpsc::unbounded_channel();
+        let opts = CompileFontOpts {
+            no_system_fonts: true,
+            ..Default::default()
+        };
+        let fonts = Deferred::new(move || SharedFontResolver::new(opts).unwrap());
+        let handle = tokio::runtime::Handle::current();
+        let mut state = CompileState::new(editor_tx, fonts, handle);
+
+        let main = FileId::new(None, VirtualPath::new("main.typ"));
+        let entry = EntryState::new_rooted(Path::new("/doc").into(), Some(main));
+        let inputs = Arc::new(Prehashed::new(Default::default()));
+        let files = main_overlay(content);
+        state.compiler = Some(state.server("primary".to_owned(), entry, inputs, files));
+        (state, editor_rx)
+    }
+
+    #[tokio::test]
+    async fn test_recompile() {
+        let (mut state, _editor_rx) = compile_state("Hello");
+
+        let fonts = state.font.clone();
+        let reloaded = Deferred::new(move || fonts.wait().reload().unwrap());
+        let summary = state.recompile(reloaded.clone()).await.unwrap();
+        assert_eq!(summary.group, "primary");
+        assert!(summary.compiled && summary.success);
+        assert_eq!(summary.pages, 1);
+
+        // The compiler uses the reloaded fonts, and so do the ones created
+        // later.
+        let inner = reloaded.wait().inner.clone();
+        assert!(Arc::ptr_eq(&state.font.wait().inner, &inner));
+        let switched = (state.compiler())
+            .steal(move |c| Arc::ptr_eq(&c.compiler.world().font_resolver.inner, &inner))
+            .await;
+        assert!(switched.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_restart_with_overlay() {
+        let (mut state, _editor_rx) = compile_state("Hello");
+
+        // The overlay is given by the primary state, e.g. to a dedicated state
+        // which doesn't record the unsaved buffers.
+        state.restart_server(main_overlay("Restarted"));
+        let text = state.compiler().steal(|c| {
+            let main = FileId::new(None, VirtualPath::new("main.typ"));
+            let source = c.compiler.world().source(main);
+            source.map(|source| source.text().to_owned()).ok()
+        });
+        assert_eq!(text.await.unwrap().as_deref(), Some("Restarted"));
+    }
+
+    #[tokio::test]
+    async fn test_lint_diagnostics() {
+        let (state, mut editor_rx) = compile_state("= A\n=== C");
+        // The compiler finishes the first compilation before stealing.
+        state.compiler().steal(|_| ()).await.unwrap();
+
+        // The lint findings are published in the group of the compiler.
+        let mut codes = vec![];
+        while let Ok(req) = editor_rx.try_recv() {
+            let EditorRequest::Diag(group, Some(diagnostics)) = req else {
+                continue;
+            };
+            assert_eq!(group, "primary");
+            let diagnostics = diagnostics.into_values().flatten();
+            codes.extend(diagnostics.filter_map(|diag| diag.code));
+        }
+        assert!(codes.contains(&NumberOrString::String("heading-skip".to_owned())));
+    }
+
+    #[tokio::test]
+    async fn test_skip_unchanged_compile() {
+        use crate::tools::persistent_cache::PersistentCache;
+
+        let (state, _editor_rx) = compile_state("= Unchanged");
+        let dir = std::env::temp_dir().join(format!("tinymist-skip-{}", std::process::id()));
+        let compiler = state.compiler();
+        let cache_dir = dir.clone();
+        compiler
+            .steal(move |c| {
+                let cache = PersistentCache::new(cache_dir, 1024 * 1024);
+                c.compiler.compiler.persistent_cache = Some(Arc::new(cache));
+            })
+            .await
+            .unwrap();
+        let compiles = || state.compile_log.snapshot().len();
+        let settle = || compiler.steal(|_| ());
+
+        // The compilation with the cache enabled records its inputs.
+        compiler.inner().compile();
+        settle().await.unwrap();
+        let evaluated = compiles();
+
+        // Compiling the unchanged inputs again doesn't evaluate the document.
+        compiler.inner().compile();
+        settle().await.unwrap();
+        assert_eq!(compiles(), evaluated);
+
+        // A changed file is compiled again.
+        let changes = MemoryEvent::Update(main_overlay("= Changed"));
+        compiler.inner().add_memory_changes(changes);
+        settle().await.unwrap();
+        assert_eq!(compiles(), evaluated + 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -3,8 +3,10 @@
 //! Please check `tinymist::actor::typ_client` for architecture details.
 
 use std::{
+    any::Any,
     collections::HashSet,
     num::NonZeroUsize,
+    panic::{catch_unwind, AssertUnwindSafe},
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -23,7 +25,7 @@ use typst_ts_compiler::{
         features::{FeatureSet, WITH_COMPILING_STATUS_FEATURE},
         watch_deps, CompileEnv, CompileReporter, Compiler, ConsoleDiagReporter, EntryManager,
     },
-    vfs::notify::{FilesystemEvent, MemoryEvent, NotifyMessage},
+    vfs::notify::{FileChangeSet, FilesystemEvent, MemoryEvent, NotifyMessage},
     world::{CompilerFeat, CompilerWorld},
     ShadowApi,
 };
@@ -96,10 +98,15 @@ pub struct CompileServerActor<C: Compiler> {
 
     /// Estimated latest set of shadow files.
     estimated_shadow_files: HashSet<Arc<Path>>,
+    /// The files read by the latest compilation, including non-Typst assets
+    /// like images and data files.
+    dependencies: HashSet<Arc<Path>>,
     /// The latest compiled document.
     latest_doc: Option<Arc<TypstDocument>>,
     /// The latest successfully compiled document.
     latest_success_doc: Option<Arc<TypstDocument>>,
+    /// The number of compilations run, to tell whether a document is fresh.
+    compile_count: usize,
     /// feature set for compile_once mode.
     once_feature_set: Arc<FeatureSet>,
     /// Shared feature set for watch mode.
@@ -110,6 +117,10 @@ pub struct CompileServerActor<C: Compiler> {
     steal_rx: mpsc::UnboundedReceiver<Interrupt<Self>>,
 
     suspend_state: SuspendState,
+
+    /// The handler of panics caught in the compiler thread. Panics are only
+    /// caught in builds unwinding on panic, which all the profiles do.
+    on_panic: Option<Box<dyn Fn(String) + Send>>,
 }
 
 impl<C: Compiler + ShadowApi + Send + 'static> CompileServerActor<C>
@@ -128,8 +139,10 @@ where
             dirty_shadow_logical_tick: 0,
 
             estimated_shadow_files: Default::default(),
+            dependencies: Default::default(),
             latest_doc: None,
             latest_success_doc: None,
+            compile_count: 0,
             once_feature_set: Arc::new(feature_set.clone()),
             watch_feature_set: Arc::new(
                 feature_set.configure(&WITH_COMPILING_STATUS_FEATURE, true),
@@ -142,6 +155,8 @@ where
                 suspended: entry.is_inactive(),
                 dirty: false,
             },
+
+            on_panic: None,
         }
     }
 
@@ -166,6 +181,10 @@ where
         })
     }
 
+    pub fn compile_count(&self) -> usize {
+        self.compile_count
+    }
+
     fn make_env(&self, feature_set: Arc<FeatureSet>) -> CompileEnv {
         CompileEnv::default().configure_shared(feature_set)
     }
@@ -243,8 +262,16 @@ where
         }
 
         // Compile the document.
+        self.compile_count += 1;
         let mut env = self.make_env(self.watch_feature_set.clone());
-        self.latest_doc = self.compiler.compile(&mut env).ok();
+        let compiled = catch_unwind(AssertUnwindSafe(|| self.compiler.compile(&mut env)));
+        self.latest_doc = match compiled {
+            Ok(doc) => doc.ok(),
+            Err(panic) => {
+                self.report_panic("compilation", panic);
+                None
+            }
+        };
         if self.latest_doc.is_some() {
             self.latest_success_doc.clone_from(&self.latest_doc);
         }
@@ -255,20 +282,43 @@ where
         let elapsed = evict_start.elapsed();
         log::info!("CompileServerActor: evict compilation cache in {elapsed:?}",);
 
-        // Notify the new file dependencies.
+        // Notify the new file dependencies, so that exactly the files read by
+        // the compilation are watched.
         let mut deps = vec![];
         self.compiler
             .iter_dependencies(&mut |dep, _| deps.push(dep.clone()));
+        let assets = deps.iter().filter(|dep| !is_typst_file(dep)).count();
+        log::debug!(
+            "CompileServerActor: watching {} dependencies, {assets} of them are assets",
+            deps.len()
+        );
+        self.dependencies = deps.iter().cloned().collect();
         send(NotifyMessage::SyncDependency(deps));
     }
 
+    /// Log a panic caught in the compiler thread and pass it to the handler.
+    fn report_panic(&self, during: &str, panic: Box<dyn Any + Send>) {
+        let payload = (panic.downcast_ref::<&str>().copied())
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        let message = format!("the compiler panicked during {during}: {payload}");
+        log::error!("CompileServerActor: {message}");
+        if let Some(on_panic) = &self.on_panic {
+            on_panic(message);
+        }
+    }
+
     /// Process some interrupt. Return whether it needs compilation.
     fn process(&mut self, event: Interrupt<Self>, send: impl Fn(NotifyMessage)) -> bool {
         match event {
             Interrupt::Compile => true,
             Interrupt::Task(task) => {
                 log::debug!("CompileServerActor: execute task");
-                task(self);
+                // The sender of the result is dropped on panic, so the caller
+                // gets an error while the actor keeps serving.
+                if let Err(panic) = catch_unwind(AssertUnwindSafe(|| task(self))) {
+                    self.report_panic("a task", panic);
+                }
                 false
             }
             Interrupt::Memory(event) => {
@@ -314,6 +364,16 @@ where
             Interrupt::Fs(mut event) => {
                 log::debug!("CompileServerActor: fs event incoming {event:?}");
 
+                // Changes of files unread by the latest compilation, e.g. an
+                // asset that is no longer referenced, don't affect the document.
+                let affected = match &event {
+                    FilesystemEvent::Update(changes) => {
+                        self.dependencies.is_empty()
+                            || affects_dependencies(&self.dependencies, changes)
+                    }
+                    FilesystemEvent::UpstreamUpdate { .. } => true,
+                };
+
                 // Handle delayed upstream update event before applying file system changes
                 if self.apply_delayed_memory_changes(&mut event).is_none() {
                     log::warn!("CompileServerActor: unknown upstream update event");
@@ -322,7 +382,7 @@ where
                 // Apply file system changes.
                 self.compiler.notify_fs_event(event);
 
-                true
+                affected
             }
             Interrupt::ChangeEntry(entry) => {
                 log::debug!("CompileServerActor: changing entry {entry:?}");
@@ -415,6 +475,13 @@ impl<C: Compiler> CompileServerActor<C> {
         self
     }
 
+    /// Set the handler of panics caught in the compiler thread, which are
+    /// recovered from instead of stopping the actor.
+    pub fn with_panic_handler(mut self, on_panic: impl Fn(String) + Send + 'static) -> Self {
+        self.on_panic = Some(Box::new(on_panic));
+        self
+    }
+
     pub fn client(&self) -> CompileClient<Self> {
         let intr_tx = self.steal_tx.clone();
         CompileClient { intr_tx }
@@ -455,7 +522,9 @@ impl<Ctx> CompileClient<Ctx> {
             .send(Interrupt::Task(task))
             .map_err(map_string_err("failed to send steal request"))?;
 
-        rx.await.map_err(map_string_err("failed to call steal"))
+        rx.await.map_err(map_string_err(
+            "failed to call steal, the task may have panicked",
+        ))
     }
 
     pub async fn settle(&self) -> ZResult<()> {
@@ -470,6 +539,11 @@ impl<Ctx> CompileClient<Ctx> {
     pub fn add_memory_changes(&self, event: MemoryEvent) {
         log_send_error("mem_event", self.intr_tx.send(Interrupt::Memory(event)));
     }
+
+    /// Request a compilation, even if nothing is changed.
+    pub fn compile(&self) {
+        log_send_error("compile", self.intr_tx.send(Interrupt::Compile));
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -649,3 +723,128 @@ fn log_send_error<T>(chan: &'static str, res: Result<(), mpsc::error::SendError<
     res.map_err(|err| log::warn!("CompileServerActor: send to {chan} error: {err}"))
         .is_ok()
 }
+
+fn is_typst_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "typ")
+}
+
+/// Whether the file changes touch any of the dependencies.
+fn affects_dependencies(deps: &HashSet<Arc<Path>>, changes: &FileChangeSet) -> bool {
+    let inserted = changes.inserts.iter().map(|(path, _)| path);
+    let mut changed = changes.removes.iter().chain(inserted);
+    changed.any(|path| deps.contains(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use comemo::Prehashed;
+    use parking_lot::Mutex;
+    use typst::diag::FileResult;
+    use typst_ts_compiler::{service::CompileDriverImpl, Time};
+    use typst_ts_core::Bytes;
+
+    use super::*;
+    use crate::world::{CompileFontOpts, LspWorld, LspWorldBuilder, SharedFontResolver};
+
+    #[test]
+    fn test_asset_changes() {
+        let deps: HashSet<Arc<Path>> = ["/doc/main.typ", "/doc/fig.png"]
+            .into_iter()
+            .map(|p| Path::new(p).into())
+            .collect();
+        let change = |path: &str| {
+            let content: Bytes = b"".as_slice().into();
+            let snapshot = FileResult::Ok((Time::now(), content)).into();
+            FileChangeSet::new_inserts(vec![(Path::new(path).into(), snapshot)])
+        };
+
+        assert!(affects_dependencies(&deps, &change("/doc/fig.png")));
+        assert!(!affects_dependencies(&deps, &change("/doc/unused.png")));
+
+        let removed = FileChangeSet::new_removes(vec![Path::new("/doc/fig.png").into()]);
+        assert!(affects_dependencies(&deps, &removed));
+    }
+
+    fn new_server(entry: EntryState) -> CompileServerActor<CompileDriverImpl<LspWorld>> {
+        let font = SharedFontResolver::new(CompileFontOpts {
+            no_system_fonts: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let inputs = Arc::new(Prehashed::new(Default::default()));
+        let world = LspWorldBuilder::build(entry.clone(), font, inputs).unwrap();
+        CompileServerActor::new(CompileDriverImpl::new(world), entry).with_watch(true)
+    }
+
+    #[tokio::test]
+    async fn test_recover_from_panicking_task() {
+        let panics = Arc::new(Mutex::new(vec![]));
+        let reported = panics.clone();
+        let server = new_server(EntryState::new_detached())
+            .with_panic_handler(move |message| reported.lock().push(message));
+        let client = server.client();
+        tokio::spawn(server.run());
+
+        let res = client.steal(|_| -> () { panic!("injected panic") }).await;
+        assert!(res.is_err());
+        assert_eq!(client.steal(|_| 1).await.unwrap(), 1);
+
+        let panics = panics.lock();
+        assert_eq!(panics.len(), 1);
+        assert!(panics[0].contains("injected panic"));
+    }
+
+    #[tokio::test]
+    async fn test_compile_on_request() {
+        let main = TypstFileId::new(None, VirtualPath::new("main.typ"));
+        let entry = EntryState::new_rooted(Path::new("/doc").into(), Some(main));
+        let server = new_server(entry);
+        let client = server.client();
+        tokio::spawn(server.run());
+        let server = new_server(EntryState::new_detached());
+        let detached = server.client();
+        tokio::spawn(server.run());
+
+        for count in 1..=2 {
+            client.compile();
+            assert_eq!(client.steal(|c| c.compile_count()).await.unwrap(), count);
+        }
+        // Nothing is compiled without an entry.
+        detached.compile();
+        assert_eq!(detached.steal(|c| c.compile_count()).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_recompile_on_asset_changes() {
+        let dir = std::env::temp_dir().join(format!("tinymist-assets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.typ"), "#read(\"data.txt\")").unwrap();
+        std::fs::write(dir.join("data.txt"), "data").unwrap();
+
+        let main = TypstFileId::new(None, VirtualPath::new("main.typ"));
+        let entry = EntryState::new_rooted(dir.as_path().into(), Some(main));
+        let server = new_server(entry);
+        let client = server.client();
+        tokio::spawn(server.run());
+
+        client.compile();
+        assert_eq!(client.steal(|c| c.compile_count()).await.unwrap(), 1);
+
+        let change = |name: &str| {
+            let content: Bytes = b"changed".as_slice().into();
+            let snapshot = FileResult::Ok((Time::now(), content)).into();
+            let changes = FileChangeSet::new_inserts(vec![(dir.join(name).into(), snapshot)]);
+            let event = Interrupt::Fs(FilesystemEvent::Update(changes));
+            client.intr_tx.send(event).unwrap();
+        };
+
+        // The data file is read by the document.
+        change("data.txt");
+        assert_eq!(client.steal(|c| c.compile_count()).await.unwrap(), 2);
+        // The unused file is not.
+        change("unused.txt");
+        assert_eq!(client.steal(|c| c.compile_count()).await.unwrap(), 2);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
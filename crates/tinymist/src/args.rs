@@ -1,5 +1,7 @@
+use std::path::PathBuf;
+
 use once_cell::sync::Lazy;
-use tinymist::compile_init::{CompileOnceArgs, FontArgs};
+use tinymist::compile_init::{CompileOnceArgs, DiagnosticFormat, FontArgs};
 use tinymist::io::MirrorArgs;
 
 #[derive(Debug, Clone)]
@@ -9,6 +11,9 @@ pub struct CliArguments {
     /// Mode of the binary
     #[cfg_attr(feature = "clap", clap(subcommand))]
     pub command: Option<Commands>,
+    /// Write structured logs to the file, as JSON lines
+    #[cfg_attr(feature = "clap", clap(long, global = true, value_name = "FILE"))]
+    pub log_file: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +38,10 @@ impl Default for Commands {
 pub struct CompileArgs {
     #[cfg_attr(feature = "clap", clap(long, default_value = "false"))]
     pub persist: bool,
+    /// The format of the diagnostics printed after compiling. The persistent
+    /// compile server prints the JSON lines to stderr
+    #[cfg_attr(feature = "clap", clap(long, value_enum, default_value_t))]
+    pub diagnostic_format: DiagnosticFormat,
     #[cfg_attr(feature = "clap", clap(flatten))]
     pub mirror: MirrorArgs,
     #[cfg_attr(feature = "clap", clap(flatten))]
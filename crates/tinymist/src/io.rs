@@ -1,11 +1,22 @@
 use std::io::{self, Write};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task;
+use std::time::{Duration, Instant};
 
 use futures::{AsyncRead, AsyncWrite};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
 use tokio_util::compat::TokioAsyncReadCompatExt;
 
-#[derive(Debug, Clone, Default)]
+/// The header recording the time a mirrored message is received, in
+/// microseconds since the start of mirroring. Like any unknown header, it is
+/// ignored by the LSP message parser.
+const TIMESTAMP_HEADER: &str = "X-Tinymist-Timestamp";
+
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
 pub struct MirrorArgs {
     /// Mirror the stdin to the file
@@ -14,13 +25,104 @@ pub struct MirrorArgs {
     /// Replay input from the file
     #[cfg_attr(feature = "clap", clap(long, default_value = "", value_name = "FILE"))]
     pub replay: String,
+    /// Reproduce the recorded delays between messages when replaying
+    #[cfg_attr(feature = "clap", clap(long, requires = "replay"))]
+    pub preserve_timing: bool,
+    /// Speed up (or slow down) the recorded delays by the multiplier
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, default_value = "1", value_name = "N", value_parser = parse_speed)
+    )]
+    pub speed: f64,
+}
+
+#[cfg(feature = "clap")]
+fn parse_speed(speed: &str) -> Result<f64, String> {
+    let speed = speed.parse::<f64>().map_err(|err| err.to_string())?;
+    check_speed(speed).map_err(|err| err.to_string())?;
+    Ok(speed)
+}
+
+/// Checks that the speed multiplier is a positive number.
+fn check_speed(speed: f64) -> io::Result<()> {
+    if speed > 0. && speed.is_finite() {
+        return Ok(());
+    }
+    let msg = format!("invalid speed {speed}, expected a positive number");
+    Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+impl Default for MirrorArgs {
+    fn default() -> Self {
+        Self {
+            mirror: String::new(),
+            replay: String::new(),
+            preserve_timing: false,
+            speed: 1.,
+        }
+    }
+}
+
+/// The mode of reading the input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MirrorMode {
+    /// The input is read from stdin.
+    #[default]
+    Live,
+    /// The input is read from stdin and mirrored to a file.
+    Record,
+    /// The input is replayed from a file.
+    Replay,
+}
+
+/// The progress of recording or replaying the input.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MirrorStatus {
+    /// The mode of reading the input.
+    pub mode: MirrorMode,
+    /// The number of messages passed to the server. Messages are not counted
+    /// in live mode.
+    pub messages_processed: usize,
+    /// The number of complete messages in the replayed file.
+    pub total_messages: Option<usize>,
+    /// The method of the last message passed to the server, which is `None`
+    /// for responses.
+    pub current_method: Option<String>,
+}
+
+static MIRROR_STATUS: Lazy<Arc<Mutex<MirrorStatus>>> = Lazy::new(Default::default);
+
+/// Gets the progress of recording or replaying the input.
+pub fn mirror_status() -> MirrorStatus {
+    MIRROR_STATUS.lock().clone()
 }
 
 pub async fn get_io(args: MirrorArgs) -> (Box<dyn AsyncRead>, Box<dyn AsyncWrite>) {
     let input: Box<dyn AsyncRead> = if !args.replay.is_empty() {
-        // Get input from file.
-        let file = tokio::fs::File::open(&args.replay).await.unwrap();
-        Box::new(TokioAsyncReadCompatExt::compat(file))
+        let data = tokio::fs::read(&args.replay).await.unwrap();
+        *MIRROR_STATUS.lock() = MirrorStatus {
+            mode: MirrorMode::Replay,
+            total_messages: Some(count_messages(&data)),
+            ..Default::default()
+        };
+        let status = MIRROR_STATUS.clone();
+
+        if args.preserve_timing {
+            // Get input from file, with the recorded delays.
+            let (reader, writer) = tokio::io::duplex(64 * 1024);
+            tokio::spawn(async move {
+                if let Err(err) = replay_timed(data, args.speed, writer).await {
+                    log::error!("failed to replay input: {err}");
+                }
+            });
+            let reader = TokioAsyncReadCompatExt::compat(reader);
+            Box::new(StatusReader::new(reader, status))
+        } else {
+            // Get input from file.
+            Box::new(StatusReader::new(futures::io::Cursor::new(data), status))
+        }
     } else {
         // Get input from stdin.
         #[cfg(unix)]
@@ -29,8 +131,10 @@ pub async fn get_io(args: MirrorArgs) -> (Box<dyn AsyncRead>, Box<dyn AsyncWrite
         let stdin = TokioAsyncReadCompatExt::compat(tokio::io::stdin());
         if !args.mirror.is_empty() {
             // Mirror to file.
-            let file = std::fs::File::create(&args.replay).unwrap();
-            Box::new(MirrorWriter(Box::pin(stdin), file))
+            let file = std::fs::File::create(&args.mirror).unwrap();
+            MIRROR_STATUS.lock().mode = MirrorMode::Record;
+            let mirror = MirrorWriter(Box::pin(stdin), TimedRecorder::new(file));
+            Box::new(StatusReader::new(mirror, MIRROR_STATUS.clone()))
         } else {
             Box::new(stdin)
         }
@@ -46,7 +150,7 @@ pub async fn get_io(args: MirrorArgs) -> (Box<dyn AsyncRead>, Box<dyn AsyncWrite
 
 // Pin<Box<R>> introduces an extra layer of indirection.
 // But this is not a hotspot and it makes the code simpler.
-struct MirrorWriter<R, W>(Pin<Box<R>>, W);
+struct MirrorWriter<R, W>(Pin<Box<R>>, TimedRecorder<W>);
 
 impl<R: AsyncRead, W: Write + Unpin> AsyncRead for MirrorWriter<R, W> {
     fn poll_read(
@@ -62,10 +166,257 @@ impl<R: AsyncRead, W: Write + Unpin> AsyncRead for MirrorWriter<R, W> {
         };
 
         // Write to file.
-        if let Err(err) = this.1.write(&buf[..res]) {
+        if let Err(err) = this.1.record(&buf[..res]) {
             log::warn!("failed to write to mirror: {err}");
         }
 
         task::Poll::Ready(Ok(res))
     }
 }
+
+/// Counts the messages read from the input in the status.
+struct StatusReader<R> {
+    inner: Pin<Box<R>>,
+    status: Arc<Mutex<MirrorStatus>>,
+    /// The bytes of an incomplete message.
+    pending: Vec<u8>,
+}
+
+impl<R: AsyncRead> StatusReader<R> {
+    fn new(inner: R, status: Arc<Mutex<MirrorStatus>>) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            status,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<R: AsyncRead> AsyncRead for StatusReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut [u8],
+    ) -> task::Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        let task::Poll::Ready(res) = this.inner.as_mut().poll_read(cx, buf)? else {
+            return task::Poll::Pending;
+        };
+
+        this.pending.extend_from_slice(&buf[..res]);
+        while let Some(len) = message_len(&this.pending) {
+            let mut status = this.status.lock();
+            status.messages_processed += 1;
+            status.current_method = message_method(&this.pending[..len]);
+            drop(status);
+            this.pending.drain(..len);
+        }
+
+        task::Poll::Ready(Ok(res))
+    }
+}
+
+/// Records LSP messages, each with a monotonic timestamp of its arrival.
+struct TimedRecorder<W> {
+    writer: W,
+    start: Instant,
+    /// The bytes of an incomplete message.
+    pending: Vec<u8>,
+}
+
+impl<W: Write> TimedRecorder<W> {
+    fn new(writer: W) -> Self {
+        Self {
+            writer,
+            start: Instant::now(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.pending.extend_from_slice(bytes);
+
+        while let Some(len) = message_len(&self.pending) {
+            let timestamp = self.start.elapsed().as_micros();
+            write!(self.writer, "{TIMESTAMP_HEADER}: {timestamp}\r\n")?;
+            self.writer.write_all(&self.pending[..len])?;
+            self.pending.drain(..len);
+        }
+
+        self.writer.flush()
+    }
+}
+
+/// Gets the length of the first complete message in the bytes, including its
+/// headers.
+fn message_len(bytes: &[u8]) -> Option<usize> {
+    let header_end = bytes.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    let headers = std::str::from_utf8(&bytes[..header_end]).ok()?;
+    let content_len = headers.split("\r\n").find_map(|line| {
+        let (name, value) = line.split_once(": ")?;
+        if !name.eq_ignore_ascii_case("Content-Length") {
+            return None;
+        }
+        value.parse::<usize>().ok()
+    })?;
+
+    let len = header_end + content_len;
+    (bytes.len() >= len).then_some(len)
+}
+
+/// Counts the complete messages in the bytes.
+fn count_messages(mut bytes: &[u8]) -> usize {
+    let mut count = 0;
+    while let Some(len) = message_len(bytes) {
+        bytes = &bytes[len..];
+        count += 1;
+    }
+    count
+}
+
+/// Gets the method of a request or notification.
+fn message_method(message: &[u8]) -> Option<String> {
+    let header_end = message.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    let content: serde_json::Value = serde_json::from_slice(&message[header_end..]).ok()?;
+    Some(content.get("method")?.as_str()?.to_owned())
+}
+
+/// Gets the recorded timestamp of a message.
+fn message_timestamp(message: &[u8]) -> Option<Duration> {
+    let header_end = message.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let headers = std::str::from_utf8(&message[..header_end]).ok()?;
+    headers.split("\r\n").find_map(|line| {
+        let micros = line.strip_prefix(TIMESTAMP_HEADER)?.strip_prefix(": ")?;
+        Some(Duration::from_micros(micros.parse().ok()?))
+    })
+}
+
+/// Replays the recorded messages, reproducing the delays between them.
+///
+/// Messages without timestamps are replayed immediately. The speed must be
+/// positive.
+async fn replay_timed(
+    data: Vec<u8>,
+    speed: f64,
+    mut writer: tokio::io::DuplexStream,
+) -> io::Result<()> {
+    check_speed(speed)?;
+    let start = tokio::time::Instant::now();
+    let mut rest = data.as_slice();
+
+    while !rest.is_empty() {
+        // Replays the trailing bytes as is if they are not a complete message.
+        let len = message_len(rest).unwrap_or(rest.len());
+        let (message, next) = rest.split_at(len);
+        rest = next;
+
+        if let Some(timestamp) = message_timestamp(message) {
+            tokio::time::sleep_until(start + timestamp.div_f64(speed)).await;
+        }
+        writer.write_all(message).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    fn message(content: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{content}", content.len())
+    }
+
+    async fn read_message(reader: &mut tokio::io::DuplexStream) -> Instant {
+        let mut buf = vec![0u8; 1024];
+        let mut len = 0;
+        while message_len(&buf[..len]).is_none() {
+            len += reader.read(&mut buf[len..]).await.unwrap();
+        }
+        Instant::now()
+    }
+
+    #[test]
+    fn test_record() {
+        let mut recorder = TimedRecorder::new(Vec::new());
+        let first = message("{}");
+        let (head, tail) = first.split_at(5);
+        recorder.record(head.as_bytes()).unwrap();
+        assert!(recorder.writer.is_empty());
+        recorder.record(tail.as_bytes()).unwrap();
+
+        let recorded = recorder.writer;
+        assert!(recorded.starts_with(TIMESTAMP_HEADER.as_bytes()));
+        assert!(recorded.ends_with(first.as_bytes()));
+        assert!(message_timestamp(&recorded).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_replay_status() {
+        use futures::AsyncReadExt;
+
+        let mut recorder = TimedRecorder::new(Vec::new());
+        let messages = [
+            r#"{"id":1,"method":"initialize"}"#,
+            r#"{"id":1,"result":null}"#,
+            r#"{"method":"initialized"}"#,
+        ];
+        for content in messages {
+            recorder.record(message(content).as_bytes()).unwrap();
+        }
+        let data = recorder.writer;
+        let total = count_messages(&data);
+        assert_eq!(total, 3);
+
+        let status = Arc::new(Mutex::new(MirrorStatus {
+            mode: MirrorMode::Replay,
+            total_messages: Some(total),
+            ..Default::default()
+        }));
+        let mut reader = StatusReader::new(futures::io::Cursor::new(data), status.clone());
+
+        let mut processed = vec![];
+        let mut buf = [0u8; 16];
+        while reader.read(&mut buf).await.unwrap() > 0 {
+            processed.push(status.lock().messages_processed);
+        }
+
+        // The count advances message by message until it reaches the total.
+        assert!(processed.windows(2).all(|w| w[0] <= w[1]));
+        assert!(processed.contains(&1) && processed.contains(&2));
+        let status = status.lock();
+        assert_eq!(status.messages_processed, total);
+        assert_eq!(status.current_method.as_deref(), Some("initialized"));
+    }
+
+    #[tokio::test]
+    async fn test_replay_timing() {
+        let mut recorder = TimedRecorder::new(Vec::new());
+        recorder.record(message("1").as_bytes()).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        recorder.record(message("2").as_bytes()).unwrap();
+
+        let (mut reader, writer) = tokio::io::duplex(1024);
+        tokio::spawn(replay_timed(recorder.writer, 2., writer));
+
+        let first = read_message(&mut reader).await;
+        let second = read_message(&mut reader).await;
+
+        // The delay of 200ms is replayed at double speed.
+        let delay = second - first;
+        assert!(delay >= Duration::from_millis(80), "delay: {delay:?}");
+        assert!(delay <= Duration::from_millis(180), "delay: {delay:?}");
+    }
+
+    #[tokio::test]
+    async fn test_replay_invalid_speed() {
+        for speed in [0., -1., f64::NAN, f64::INFINITY] {
+            let (_reader, writer) = tokio::io::duplex(1024);
+            let data = message("1").into_bytes();
+            let err = replay_timed(data, speed, writer).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "speed: {speed}");
+        }
+    }
+}
@@ -25,13 +25,30 @@
 //! # Replay the input
 //! tinymist lsp --replay input.txt
 //! ```
+//!
+//! Each mirrored message is recorded with the time it arrives. The delays
+//! between messages can be reproduced to debug timing-dependent issues,
+//! optionally scaled by a speed multiplier:
+//!
+//! ```sh
+//! tinymist lsp --replay input.txt --preserve-timing --speed 2
+//! ```
+//!
+//! To capture the behavior of the server as well, write structured logs to a
+//! file, which is rotated when it grows too large:
+//!
+//! ```sh
+//! tinymist lsp --log-file tinymist.log
+//! ```
 
 mod actor;
 pub mod io;
+pub mod logging;
 mod resource;
 mod server;
 mod state;
 mod task;
+mod telemetry;
 mod tools;
 mod world;
 
@@ -0,0 +1,217 @@
+//! Structured logging to a rotating file.
+//!
+//! Each record is written as a JSON line, alongside the human-readable log
+//! printed to stderr, so the file never interferes with the stdio transport.
+//! The `logLevel` setting caps the level of the records of both.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use parking_lot::Mutex;
+use serde_json::json;
+
+/// The target of compilation events.
+pub const COMPILE_EVENT: &str = "tinymist::event::compile";
+/// The target of request events.
+pub const REQUEST_EVENT: &str = "tinymist::event::request";
+
+/// The size of a log file to rotate at, in bytes.
+const ROTATE_SIZE: u64 = 16 * 1024 * 1024;
+/// The number of rotated log files to keep.
+const ROTATE_KEEP: usize = 3;
+
+/// The maximum level of records at initialization, restored when the level
+/// is unset.
+static DEFAULT_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+
+/// Initializes the global logger, printing to stderr and optionally writing
+/// to a rotating log file.
+pub fn init(stderr: env_logger::Logger, log_file: Option<&Path>) -> anyhow::Result<()> {
+    let file = match log_file {
+        Some(path) => Some(Mutex::new(RotatingFile::open(path, ROTATE_SIZE)?)),
+        None => None,
+    };
+
+    let level = stderr.filter().max(LevelFilter::Info);
+    DEFAULT_LEVEL.store(level as usize, Ordering::Relaxed);
+    log::set_boxed_logger(Box::new(TeeLogger { stderr, file }))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Sets the maximum level of records logged, or restores the one at
+/// initialization if unset.
+pub fn set_level(level: Option<LevelFilter>) {
+    let default = DEFAULT_LEVEL.load(Ordering::Relaxed);
+    let default = LevelFilter::iter().find(|filter| *filter as usize == default);
+    log::set_max_level(level.or(default).unwrap_or(LevelFilter::Info));
+}
+
+/// A logger printing to stderr and writing to a log file.
+struct TeeLogger {
+    stderr: env_logger::Logger,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata) || self.file.is_some()
+    }
+
+    fn log(&self, record: &Record) {
+        self.stderr.log(record);
+
+        // The records are already capped by the maximum level.
+        if let Some(file) = &self.file {
+            let mut line = json_record(record).to_string();
+            line.push('\n');
+            // Errors cannot be logged, otherwise the logger would recurse.
+            let _ = file.lock().write_line(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some(file) = &self.file {
+            let _ = file.lock().file.flush();
+        }
+    }
+}
+
+/// Converts a record to a structured JSON object.
+fn json_record(record: &Record) -> serde_json::Value {
+    let event = match record.target() {
+        COMPILE_EVENT => "compile",
+        REQUEST_EVENT => "request",
+        _ if record.level() == Level::Error => "error",
+        _ => "log",
+    };
+
+    json!({
+        "time": chrono::Local::now().to_rfc3339(),
+        "level": record.level().as_str(),
+        "event": event,
+        "target": record.target(),
+        "message": record.args().to_string(),
+    })
+}
+
+/// A file rotated when it grows beyond a size.
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64) -> io::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_owned(),
+            max_size,
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Moves `log` to `log.1`, `log.1` to `log.2`, and so on, dropping the
+    /// oldest one.
+    fn rotate(&mut self) -> io::Result<()> {
+        for idx in (1..ROTATE_KEEP).rev() {
+            let from = rotated_path(&self.path, idx);
+            if from.exists() {
+                fs::rename(from, rotated_path(&self.path, idx + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, idx: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{idx}"));
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tinymist-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// The only test installing the global logger, as it is installed once in
+    /// a process.
+    #[test]
+    fn test_log_level() {
+        let path = temp_log("events.log");
+        let stderr = env_logger::builder().filter_level(LevelFilter::Off).build();
+        init(stderr, Some(&path)).unwrap();
+
+        set_level(Some(LevelFilter::Warn));
+        log::info!(target: COMPILE_EVENT, "test_log_level: compiled");
+        log::warn!(target: COMPILE_EVENT, "test_log_level: compile slowly");
+        set_level(Some(LevelFilter::Debug));
+        log::debug!(target: REQUEST_EVENT, "test_log_level: request");
+        set_level(None);
+        assert_eq!(log::max_level(), LevelFilter::Info);
+        log::debug!(target: REQUEST_EVENT, "test_log_level: another request");
+        log::logger().flush();
+
+        // Other tests may log to the file meanwhile.
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = (content.lines())
+            .map(|line| serde_json::from_str(line).unwrap())
+            .filter(|line: &serde_json::Value| {
+                let message = line["message"].as_str().unwrap_or_default();
+                message.starts_with("test_log_level: ")
+            })
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "compile");
+        assert_eq!(lines[0]["level"], "WARN");
+        assert_eq!(lines[0]["message"], "test_log_level: compile slowly");
+        assert_eq!(lines[1]["event"], "request");
+        assert_eq!(lines[1]["level"], "DEBUG");
+    }
+
+    #[test]
+    fn test_rotation() {
+        let path = temp_log("rotated.log");
+        let _ = fs::remove_file(rotated_path(&path, 1));
+        let mut file = RotatingFile::open(&path, 8).unwrap();
+
+        file.write_line(b"first\n").unwrap();
+        file.write_line(b"second\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "first\n"
+        );
+    }
+}
@@ -2,7 +2,7 @@
 
 mod args;
 
-use std::{path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc, time::Instant};
 
 use anyhow::bail;
 use clap::Parser;
@@ -17,7 +17,9 @@ use typst_ts_core::{typst::prelude::EcoVec, TypstDict};
 
 use crate::args::{CliArguments, Commands, CompileArgs, LspArgs};
 use tinymist::{
-    compile_init::{CompileInit, CompileInitializeParams},
+    compile_init::{
+        print_compile_events, CompileEvent, CompileInit, CompileInitializeParams, DiagnosticFormat,
+    },
     harness::{lsp_harness, InitializedLspDriver, LspDriver, LspHost},
     io::with_stdio_transport,
     CompileFontOpts, Init, LspWorld, TypstLanguageServer,
@@ -50,21 +52,22 @@ fn main() -> anyhow::Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
 
+    // Parse command line arguments
+    let args = CliArguments::parse();
+
     // Start logging
     {
         use log::LevelFilter::*;
-        env_logger::builder()
+        let stderr = env_logger::builder()
             .filter_module("tinymist", Info)
             .filter_module("typst_preview", Debug)
             .filter_module("typst_ts", Info)
             .filter_module("typst_ts_compiler::service::compile", Info)
             .filter_module("typst_ts_compiler::service::watch", Info)
-            .init();
+            .build();
+        tinymist::logging::init(stderr, args.log_file.as_deref())?;
     }
 
-    // Parse command line arguments
-    let args = CliArguments::parse();
-
     match args.command.unwrap_or_default() {
         Commands::Lsp(args) => lsp_main(args),
         Commands::Compile(args) => compiler_main(args),
@@ -115,7 +118,7 @@ pub fn lsp_main(args: LspArgs) -> anyhow::Result<()> {
 }
 
 pub fn compiler_main(args: CompileArgs) -> anyhow::Result<()> {
-    let (editor_tx, _editor_rx) = mpsc::unbounded_channel();
+    let (editor_tx, editor_rx) = mpsc::unbounded_channel();
 
     let mut input = PathBuf::from(args.compile.input.unwrap());
 
@@ -150,6 +153,11 @@ pub fn compiler_main(args: CompileArgs) -> anyhow::Result<()> {
     };
     if args.persist {
         log::info!("starting compile server");
+        if args.diagnostic_format == DiagnosticFormat::Json {
+            RUNTIMES
+                .tokio_runtime
+                .spawn(print_compile_events(editor_rx));
+        }
 
         with_stdio_transport(args.mirror.clone(), |conn, force_exit| {
             lsp_harness(init, conn, force_exit)
@@ -179,7 +187,7 @@ pub fn compiler_main(args: CompileArgs) -> anyhow::Result<()> {
             service.initialized(InitializedParams {});
 
             let entry = service.config.determine_entry(Some(input.as_path().into()));
-            let (timings, _doc, diagnostics) = service
+            let (timings, doc, duration, diagnostics) = service
                 .compiler()
                 .steal(|c| {
                     c.compiler.world_mut().mutate_entry(entry).unwrap();
@@ -191,6 +199,7 @@ pub fn compiler_main(args: CompileArgs) -> anyhow::Result<()> {
                     };
                     typst_timing::enable();
                     let mut errors = EcoVec::new();
+                    let started = Instant::now();
                     let res = match c.compiler.pure_compile(&mut env) {
                         Ok(doc) => Some(doc),
                         Err(e) => {
@@ -198,6 +207,7 @@ pub fn compiler_main(args: CompileArgs) -> anyhow::Result<()> {
                             None
                         }
                     };
+                    let duration = started.elapsed();
                     let world = c.compiler.world();
                     let mut writer = std::io::BufWriter::new(Vec::new());
                     let _ = typst_timing::export_json(&mut writer, |span| {
@@ -217,10 +227,16 @@ pub fn compiler_main(args: CompileArgs) -> anyhow::Result<()> {
 
                     let diagnostics = diagnostics.unwrap_or_default();
 
-                    (s, res, diagnostics)
+                    (s, res, duration, diagnostics)
                 })
                 .unwrap();
 
+            if args.diagnostic_format == DiagnosticFormat::Json {
+                CompileEvent::compiled(doc.is_some(), duration, diagnostics)
+                    .write_line(std::io::stdout().lock())?;
+                return Ok(());
+            }
+
             lsp_server::Message::Notification(lsp_server::Notification {
                 method: "tinymistExt/diagnostics".to_owned(),
                 params: serde_json::json!(diagnostics),
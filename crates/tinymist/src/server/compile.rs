@@ -1,18 +1,22 @@
 //! tinymist compile mode
 
+use std::collections::{HashMap, HashSet};
 use std::ops::ControlFlow;
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::{path::Path, sync::Arc};
 
 use async_lsp::{LanguageServer, ResponseError};
 use lsp_types::request::*;
 use lsp_types::*;
 use tokio::sync::mpsc;
 use typst::util::Deferred;
+use typst_ts_core::ImmutPath;
 
 use super::*;
 use crate::actor::{editor::EditorRequest, typ_client::CompileClientActor};
 use crate::compile_init::{CompileConfig, ConstCompileConfig};
 use crate::state::MemoryFileMeta;
+use crate::telemetry::CompileLog;
+use crate::tools::preview::PreviewServer;
 use crate::world::SharedFontResolver;
 
 /// The object providing the language server functionality.
@@ -28,14 +32,22 @@ pub struct CompileState {
     pub exec_cmds: ExecCmdMap<Self>,
 
     /* Resources */
+    /// The runtime to run the compiler actors on.
+    pub handle: tokio::runtime::Handle,
     /// The font resolver to use.
     pub font: Deferred<SharedFontResolver>,
     /// Source synchronized with client
     pub memory_changes: HashMap<Arc<Path>, MemoryFileMeta>,
+    /// The sources refused for exceeding the maximum document size.
+    pub oversized_sources: HashSet<ImmutPath>,
     /// The diagnostics sender to send diagnostics to `crate::actor::cluster`.
     pub editor_tx: mpsc::UnboundedSender<EditorRequest>,
     /// The compiler actor.
     pub compiler: Option<CompileClientActor>,
+    /// The preview server sharing the compiler actor.
+    pub preview: PreviewServer,
+    /// The log of the latest compilations.
+    pub compile_log: Arc<CompileLog>,
 }
 
 impl CompileState {
@@ -50,10 +62,14 @@ impl CompileState {
 
             exec_cmds: Self::get_exec_cmds(),
 
+            handle,
             editor_tx,
             font,
             compiler: None,
+            preview: PreviewServer::default(),
             memory_changes: HashMap::new(),
+            oversized_sources: HashSet::new(),
+            compile_log: Default::default(),
         }
     }
 
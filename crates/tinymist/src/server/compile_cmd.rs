@@ -1,15 +1,50 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 
+use anyhow::{bail, Context};
+use base64::Engine;
 use serde::Deserialize;
-use serde_json::{to_value, Value as JsonValue};
-use tinymist_query::{ExportKind, PageSelection};
+use serde_json::{json, to_value, Value as JsonValue};
+use tinymist_query::syntax::IgnorePatterns;
+use tinymist_query::{EpubOptions, ExportKind, PageSelection, SvgTextMode};
+use typst::syntax::VirtualPath;
+use typst_ts_core::ImmutPath;
 
 use super::compile::*;
+use super::progress::{Progress, WorkDone};
 use super::*;
+use crate::actor::export::{substitute_path, PageFilter};
+use crate::compile_init::EntryRepairKind;
+use crate::tools::animated_svg::{self, animated_svg};
+use crate::tools::benchmark::{self, benchmark_document};
+use crate::tools::bundle::bundle_document;
+use crate::tools::contact_sheet::{validate_options, DEFAULT_COLUMNS, DEFAULT_PPI};
+use crate::tools::crop::{self, export_crop, CropRect};
+use crate::tools::diff_report::diff_report;
+use crate::tools::flatten::flatten_document;
+use crate::tools::pptx;
+use crate::tools::reproducible::{verify_reproducible, ReproducibleFormat};
+use crate::tools::selection::SelectionFormat;
+use crate::tools::series::{self, export_series, SeriesFormat};
+use crate::tools::split_pdf::{split_pdf, PdfSplit};
+use crate::tools::tagged_pdf::tagged_pdf;
+use crate::tools::variant::render_variant;
+use crate::tools::watermark::Watermark;
+
+/// The message telling users how to set up pandoc for DOCX export.
+const PANDOC_SETUP_HINT: &str = "exporting DOCX requires pandoc, install it from \
+    https://pandoc.org/installing.html and set `pandocPath` to its executable";
 
 #[derive(Debug, Clone, Default, Deserialize)]
 struct ExportOpts {
     page: PageSelection,
+    watermark: Option<Watermark>,
+    /// Whether to export a tagged PDF, see [`CompileState::export_pdf_tagged`].
+    #[serde(default)]
+    tagged: bool,
+    /// How text is written to SVG, see [`SvgTextMode`].
+    text: Option<SvgTextMode>,
 }
 
 impl CompileState {
@@ -17,28 +52,634 @@ impl CompileState {
     pub fn get_exec_cmds() -> ExecCmdMap<Self> {
         HashMap::from_iter([
             ("tinymist.exportPdf", Self::export_pdf as _),
+            ("tinymist.exportPdfTagged", Self::export_pdf_tagged as _),
             ("tinymist.exportSvg", Self::export_svg as _),
+            ("tinymist.exportSvgWithFonts", Self::export_svg_with_fonts as _),
             ("tinymist.exportPng", Self::export_png as _),
+            ("tinymist.exportContactSheet", Self::export_contact_sheet as _),
+            ("tinymist.exportPptx", Self::export_pptx as _),
+            ("tinymist.exportDocxViaPandoc", Self::export_docx_via_pandoc as _),
+            ("tinymist.exportEpub", Self::export_epub as _),
+            ("tinymist.exportZip", Self::export_zip as _),
+            ("tinymist.exportIncludeTree", Self::export_include_tree as _),
+            ("tinymist.renderVariant", Self::render_variant as _),
+            ("tinymist.exportAnimatedSvg", Self::export_animated_svg as _),
+            ("tinymist.exportWithWatermark", Self::export_with_watermark as _),
+            ("tinymist.exportSplitPdf", Self::export_split_pdf as _),
+            ("tinymist.exportRangeOfDocument", Self::export_range_of_document as _),
+            ("tinymist.exportDiffReport", Self::export_diff_report as _),
+            ("tinymist.exportSeries", Self::export_series as _),
+            ("tinymist.exportManifest", Self::export_manifest as _),
+            ("tinymist.benchmarkDocument", Self::benchmark_document as _),
+            ("tinymist.verifyReproducible", Self::verify_reproducible as _),
+            ("tinymist.setPageRange", Self::set_page_range as _),
+            ("tinymist.setExportOnSavePattern", Self::set_export_on_save_pattern as _),
             ("tinymist.doClearCache", Self::clear_cache as _),
             ("tinymist.changeEntry", Self::change_entry as _),
+            ("tinymist.repairEntry", Self::repair_entry as _),
+            ("tinymist.previewServerUrl", Self::preview_server_url as _),
         ])
     }
 
     /// Export the current document as a PDF file.
-    pub fn export_pdf(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
-        self.export(ExportKind::Pdf, args)
+    pub fn export_pdf(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let opts = get_arg_or_default!(args[1] as ExportOpts);
+        if opts.tagged {
+            return self.export_pdf_tagged(args);
+        }
+        self.export(ExportKind::Pdf, opts.watermark, args)
+    }
+
+    /// Export the current document as a tagged PDF file, whose structure tree
+    /// describes its headings, figures and lists, e.g. `main.tagged.pdf`. The
+    /// written path is returned, along with warnings about figures without
+    /// alternative text.
+    pub fn export_pdf_tagged(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        let path = get_arg!(args[0] as PathBuf);
+        let root = self.compiler().entry().root();
+        let to = root.and_then(|root| {
+            let to = substitute_path(self.config.output_pattern("pdf"), &root, &path)?;
+            Some(to.with_extension("tagged.pdf"))
+        });
+        let Some(to) = to else {
+            let path = path.display();
+            let err = format!("cannot determine the output path of {path}");
+            return resp!(Err(invalid_params(err)));
+        };
+
+        let fut = self.compiler().steal(move |c| {
+            let doc = c
+                .success_doc()
+                .context("the document is not compiled yet")?;
+            let tagged = tagged_pdf(c.compiler.compiler.world(), &doc.document)?;
+            if let Some(dir) = to.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&to, tagged.pdf)?;
+            for warning in &tagged.warnings {
+                log::warn!("exportPdfTagged: {warning}");
+            }
+            anyhow::Ok(json!({ "path": to, "warnings": tagged.warnings }))
+        });
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(res)) => Ok(Some(res)),
+                Ok(Err(err)) => Err(invalid_params(format!("cannot export tagged PDF: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot export tagged PDF: {err}"))),
+            }
+        })
     }
 
     /// Export the current document as a Svg file.
     pub fn export_svg(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
         let opts = get_arg_or_default!(args[1] as ExportOpts);
-        self.export(ExportKind::Svg { page: opts.page }, args)
+        let text = opts.text.unwrap_or_default();
+        let kind = ExportKind::Svg {
+            page: opts.page,
+            text,
+        };
+        self.export(kind, opts.watermark, args)
+    }
+
+    /// Export the current document as a Svg file with selectable text, whose
+    /// fonts are embedded unless another `text` mode is given.
+    pub fn export_svg_with_fonts(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        let opts = get_arg_or_default!(args[1] as ExportOpts);
+        let text = opts.text.unwrap_or(SvgTextMode::Embed);
+        let kind = ExportKind::Svg {
+            page: opts.page,
+            text,
+        };
+        self.export(kind, opts.watermark, args)
     }
 
     /// Export the current document as a Png file.
     pub fn export_png(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
         let opts = get_arg_or_default!(args[1] as ExportOpts);
-        self.export(ExportKind::Png { page: opts.page }, args)
+        self.export(ExportKind::Png { page: opts.page }, opts.watermark, args)
+    }
+
+    /// Export all pages of the current document tiled into a grid, next to the
+    /// PNG export with a `-contact.png` suffix.
+    pub fn export_contact_sheet(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ContactSheetParams {
+            path: PathBuf,
+            columns: Option<usize>,
+            ppi: Option<f32>,
+        }
+        let params = get_arg!(args[0] as ContactSheetParams);
+        let columns = params.columns.unwrap_or(DEFAULT_COLUMNS);
+        let ppi = params.ppi.unwrap_or(DEFAULT_PPI);
+        if let Err(err) = validate_options(columns, ppi) {
+            return resp!(Err(invalid_params(err)));
+        }
+        self.export_to(ExportKind::ContactSheet { columns, ppi }, params.path)
+    }
+
+    /// Export the pages of the current document as slides of a PPTX deck.
+    pub fn export_pptx(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct PptxParams {
+            path: PathBuf,
+            ppi: Option<f32>,
+            #[serde(default)]
+            notes_from_labels: bool,
+        }
+        let params = get_arg!(args[0] as PptxParams);
+        let ppi = params.ppi.unwrap_or(pptx::DEFAULT_PPI);
+        if let Err(err) = pptx::validate_ppi(ppi) {
+            return resp!(Err(invalid_params(err)));
+        }
+        let notes = params.notes_from_labels;
+        self.export_to(ExportKind::Pptx { ppi, notes }, params.path)
+    }
+
+    /// Export the current document as a DOCX file, converted by the pandoc
+    /// configured with `pandocPath`.
+    pub fn export_docx_via_pandoc(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        let path = get_arg!(args[0] as PathBuf);
+        if cfg!(not(feature = "pandoc")) {
+            return resp!(Err(invalid_params(
+                "tinymist is built without the pandoc feature"
+            )));
+        }
+        let Some(pandoc) = self.config.pandoc_path.clone() else {
+            return resp!(Err(invalid_params(PANDOC_SETUP_HINT)));
+        };
+        self.export_to(ExportKind::Docx { pandoc }, path)
+    }
+
+    /// Export the current document as an EPUB book, split into chapters at the
+    /// top-level headings or at the included files.
+    pub fn export_epub(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        struct EpubParams {
+            path: PathBuf,
+            #[serde(flatten)]
+            opts: EpubOptions,
+        }
+        let EpubParams { path, mut opts } = get_arg!(args[0] as EpubParams);
+        // The cover image is relative to the document.
+        if let (Some(cover), Some(dir)) = (&mut opts.cover_image, path.parent()) {
+            *cover = dir.join(&*cover);
+        }
+        self.export_to(ExportKind::Epub { opts }, path)
+    }
+
+    /// Bundle a document along with the sources, images, data, packages and
+    /// fonts it depends on into a zip archive, next to the PDF export by
+    /// default. The written path and the bundled files are returned.
+    pub fn export_zip(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ZipParams {
+            path: PathBuf,
+            output: Option<PathBuf>,
+            #[serde(default)]
+            exclude_fonts: bool,
+        }
+        let params = get_arg!(args[0] as ZipParams);
+        let root = self.compiler().entry().root();
+        let to = params.output.or_else(|| {
+            let to = substitute_path(self.config.output_pattern("pdf"), &root?, &params.path)?;
+            Some(to.with_extension("zip"))
+        });
+        let Some(to) = to else {
+            let path = params.path.display();
+            let err = format!("cannot determine the output path of {path}");
+            return resp!(Err(invalid_params(err)));
+        };
+
+        let (path, exclude_fonts) = (params.path, params.exclude_fonts);
+        let fut = self.compiler().steal_world(move |ctx| {
+            let main = ctx.source_by_path(&path)?;
+            let inputs = ctx.resources.inputs();
+            let bundle = bundle_document(ctx.world(), main, &inputs, exclude_fonts)?;
+            if let Some(dir) = to.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&to, bundle.zip)?;
+            anyhow::Ok(json!({ "path": to, "files": bundle.files }))
+        });
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(res)) => Ok(Some(res)),
+                Ok(Err(err)) => Err(invalid_params(format!("cannot export zip: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot export zip: {err}"))),
+            }
+        })
+    }
+
+    /// Flatten a document and the local files it includes and imports into a
+    /// single file, next to it with a `-flat` suffix by default. The written
+    /// path is returned.
+    pub fn export_include_tree(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct IncludeTreeParams {
+            path: PathBuf,
+            output: Option<PathBuf>,
+        }
+        let params = get_arg!(args[0] as IncludeTreeParams);
+        let to = params.output.unwrap_or_else(|| {
+            let stem = params
+                .path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            params.path.with_file_name(format!("{stem}-flat.typ"))
+        });
+        let Some(root) = self.compiler().entry().root() else {
+            return resp!(Err(invalid_params(
+                "cannot flatten a document without a root"
+            )));
+        };
+        let Some(output) = VirtualPath::within_root(&to, &root) else {
+            let err = format!("the output {} is not in the root", to.display());
+            return resp!(Err(invalid_params(err)));
+        };
+
+        let path = params.path;
+        let fut = self.compiler().steal_world(move |ctx| {
+            let main = ctx.source_by_path(&path)?;
+            let text = flatten_document(ctx.world(), &main, &output)?;
+            std::fs::write(&to, text)?;
+            anyhow::Ok(json!({ "path": to }))
+        });
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(res)) => Ok(Some(res)),
+                Ok(Err(err)) => Err(invalid_params(format!("cannot flatten document: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot flatten document: {err}"))),
+            }
+        })
+    }
+
+    /// Render the variant of a document by calling a function of it, e.g.
+    /// `main-print`, exporting it as a PDF next to it with the name of the
+    /// function as a suffix. The written path is returned.
+    pub fn render_variant(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct VariantParams {
+            path: PathBuf,
+            function: String,
+        }
+        let params = get_arg!(args[0] as VariantParams);
+        let (path, function) = (params.path, params.function);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let to = path.with_file_name(format!("{stem}-{function}.pdf"));
+
+        let fut = self.compiler().steal_world(move |ctx| {
+            let main = ctx.source_by_path(&path)?;
+            let pdf = render_variant(ctx.world(), &main, &function)?;
+            std::fs::write(&to, pdf)?;
+            anyhow::Ok(to)
+        });
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(to)) => Ok(to_value(to).ok()),
+                Ok(Err(err)) => Err(invalid_params(format!("cannot render variant: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot render variant: {err}"))),
+            }
+        })
+    }
+
+    /// Export the first page of the current document compiled at several steps,
+    /// driven by `sys.inputs.step`, as an animated SVG next to the SVG export
+    /// with an `-animated.svg` suffix.
+    pub fn export_animated_svg(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct AnimatedSvgParams {
+            path: PathBuf,
+            steps: Option<usize>,
+            delay_ms: Option<u64>,
+        }
+        let params = get_arg!(args[0] as AnimatedSvgParams);
+        let steps = params.steps.unwrap_or(animated_svg::DEFAULT_STEPS);
+        let delay_ms = params.delay_ms.unwrap_or(animated_svg::DEFAULT_DELAY_MS);
+        if let Err(err) = animated_svg::validate_options(steps, delay_ms) {
+            return resp!(Err(invalid_params(err)));
+        }
+
+        let root = self.compiler().entry().root();
+        let to = root.and_then(|root| {
+            let to = substitute_path(self.config.output_pattern("svg"), &root, &params.path)?;
+            let stem = to.file_stem()?.to_string_lossy();
+            Some(to.with_file_name(format!("{stem}-animated.svg")))
+        });
+        let Some(to) = to else {
+            let path = params.path.display();
+            let err = format!("cannot determine the output path of {path}");
+            return resp!(Err(invalid_params(err)));
+        };
+
+        let fut = self.compiler().steal(move |c| {
+            let svg = animated_svg(c.compiler.compiler.world(), steps, delay_ms)?;
+            std::fs::write(&to, svg)?;
+            anyhow::Ok(to)
+        });
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(to)) => Ok(to_value(to).ok()),
+                Ok(Err(err)) => Err(invalid_params(format!("cannot export animated SVG: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot export animated SVG: {err}"))),
+            }
+        })
+    }
+
+    /// Compile two files as the entry, and write a report comparing their
+    /// pages side by side as an HTML file, next to the export of the right
+    /// file with a `-diff.html` suffix unless `output` is given.
+    pub fn export_diff_report(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct DiffReportParams {
+            left: PathBuf,
+            right: PathBuf,
+            output: Option<PathBuf>,
+        }
+        let params = get_arg!(args[0] as DiffReportParams);
+
+        let root = self.compiler().entry().root();
+        let to = params.output.or_else(|| {
+            let to = substitute_path(self.config.output_pattern("html"), &root?, &params.right)?;
+            let stem = to.file_stem()?.to_string_lossy();
+            Some(to.with_file_name(format!("{stem}-diff.html")))
+        });
+        let Some(to) = to else {
+            let path = params.right.display();
+            let err = format!("cannot determine the output path of {path}");
+            return resp!(Err(invalid_params(err)));
+        };
+
+        let (left, right) = (params.left, params.right);
+        let fut = self.compiler().steal_world(move |ctx| {
+            let left = ctx.source_by_path(&left)?;
+            let right = ctx.source_by_path(&right)?;
+            let report = diff_report(ctx.world(), left, right)?;
+            std::fs::write(&to, report.html)?;
+            anyhow::Ok(json!({ "path": to, "summary": report.summary }))
+        });
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(res)) => Ok(Some(res)),
+                Ok(Err(err)) => Err(invalid_params(format!("cannot export diff report: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot export diff report: {err}"))),
+            }
+        })
+    }
+
+    /// Split the current document into PDFs, each of a page or a page range,
+    /// next to the PDF export with the page numbers or the names of the ranges
+    /// as suffixes, e.g. `main-1.pdf`. The written paths are returned.
+    pub fn export_split_pdf(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.export_split_pdf_with_progress(args, None)
+    }
+
+    /// Split the current document into PDFs, stopping before the next PDF once
+    /// the progress is cancelled. The PDFs written before are still returned,
+    /// and the end of the progress tells the cancellation.
+    pub fn export_split_pdf_with_progress(
+        &mut self,
+        mut args: Vec<JsonValue>,
+        progress: Option<(Progress, WorkDone)>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct SplitPdfParams {
+            path: PathBuf,
+            split: PdfSplit,
+        }
+        let params = get_arg!(args[0] as SplitPdfParams);
+
+        let root = self.compiler().entry().root();
+        let to = root.and_then(|root| {
+            let to = substitute_path(self.config.output_pattern("pdf"), &root, &params.path)?;
+            let stem = to.file_stem()?.to_string_lossy().into_owned();
+            Some((to, stem))
+        });
+        let Some((to, stem)) = to else {
+            let path = params.path.display();
+            let err = format!("cannot determine the output path of {path}");
+            return resp!(Err(invalid_params(err)));
+        };
+
+        let split = params.split;
+        let cancel = progress
+            .as_ref()
+            .map(|(progress, _)| progress.cancel_flag());
+        let cancelled = move || {
+            cancel
+                .as_ref()
+                .is_some_and(|flag| flag.load(Ordering::Relaxed))
+        };
+        let fut = self.compiler().steal(move |c| {
+            let doc = c
+                .success_doc()
+                .context("the document is not compiled yet")?;
+            if let Some(dir) = to.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let split = split_pdf(&doc.document, &split, cancelled)?;
+            let mut written = vec![];
+            for (suffix, pdf) in split.pdfs {
+                let to = to.with_file_name(format!("{stem}-{suffix}.pdf"));
+                std::fs::write(&to, pdf)?;
+                written.push(to);
+            }
+            anyhow::Ok((written, split.cancelled))
+        });
+        if let Some((_, work_done)) = &progress {
+            work_done.begin("Splitting PDF");
+        }
+        Box::pin(async move {
+            let res = fut.await;
+            if let Some((_, work_done)) = progress {
+                let cancelled = matches!(res, Ok(Ok((_, true))));
+                work_done.end(cancelled.then(|| "cancelled".to_owned()));
+            }
+            match res {
+                Ok(Ok((written, _))) => Ok(to_value(written).ok()),
+                Ok(Err(err)) => Err(invalid_params(format!("cannot split PDF: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot split PDF: {err}"))),
+            }
+        })
+    }
+
+    /// Export a region of a page of the current document, given in points from
+    /// the top left corner of the page, as a PNG or SVG image in base64. A
+    /// region exceeding the page is clamped to it with a warning.
+    pub fn export_range_of_document(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct RangeParams {
+            path: PathBuf,
+            /// The one-based page number.
+            page: usize,
+            #[serde(flatten)]
+            rect: CropRect,
+            kind: String,
+            ppi: Option<f32>,
+        }
+        let params = get_arg!(args[0] as RangeParams);
+        let Some(format) = SelectionFormat::from_name(&params.kind) else {
+            let msg = format!("unsupported kind: {}", params.kind);
+            return resp!(Err(invalid_params(msg)));
+        };
+        let ppi = params.ppi.unwrap_or(crop::DEFAULT_PPI);
+        if let Err(err) = crop::validate_ppi(ppi) {
+            return resp!(Err(invalid_params(err)));
+        }
+
+        let entry = self.compiler().entry();
+        let main = entry.main().zip(entry.root());
+        let main = main.and_then(|(main, root)| main.vpath().resolve(&root));
+        if main.as_deref() != Some(params.path.as_path()) {
+            let err = format!("{} is not the entry file", params.path.display());
+            return resp!(Err(invalid_params(err)));
+        }
+
+        let (page, rect, kind) = (params.page, params.rect, params.kind);
+        let fut = self.compiler().steal(move |c| {
+            let doc = c
+                .success_doc()
+                .context("the document is not compiled yet")?;
+            let Some(frame) = page.checked_sub(1).and_then(|i| doc.document.pages.get(i)) else {
+                bail!("page {page} is out of the document");
+            };
+            let frame = &frame.frame;
+            let (clamped, is_clamped) = rect.clamp_to(frame.size())?;
+            let warning = is_clamped.then(|| {
+                let warning = format!("the region is clamped to the page: {clamped:?}");
+                log::warn!("exportRangeOfDocument: {warning}");
+                warning
+            });
+            let data = export_crop(frame, clamped, format, ppi)?;
+            anyhow::Ok(json!({
+                "kind": kind,
+                "data": base64::engine::general_purpose::STANDARD.encode(data),
+                "width": clamped.width,
+                "height": clamped.height,
+                "warning": warning,
+            }))
+        });
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(res)) => Ok(Some(res)),
+                Ok(Err(err)) => Err(invalid_params(format!("cannot export range: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot export range: {err}"))),
+            }
+        })
+    }
+
+    /// Export the current document as a PDF, SVG or PNG file with a watermark,
+    /// e.g. `DRAFT`, stamped on each page, next to the regular export with a
+    /// `-watermarked` suffix.
+    pub fn export_with_watermark(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct WatermarkParams {
+            path: PathBuf,
+            format: Option<String>,
+            #[serde(default)]
+            page: PageSelection,
+            watermark: Watermark,
+        }
+        let params = get_arg!(args[0] as WatermarkParams);
+        let page = params.page;
+        let kind = match params.format.as_deref().unwrap_or("pdf") {
+            "pdf" => ExportKind::Pdf,
+            "svg" => ExportKind::Svg {
+                page,
+                text: SvgTextMode::Outline,
+            },
+            "png" => ExportKind::Png { page },
+            format => {
+                let err = format!("cannot export {format} with a watermark");
+                return resp!(Err(invalid_params(err)));
+            }
+        };
+        self.export_watermarked(kind, params.path, params.watermark)
+    }
+
+    /// Restrict all subsequent exports of the entry to some pages, e.g.
+    /// `3-8,10`, until cleared by an empty or absent range.
+    pub fn set_page_range(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct PageRangeParams {
+            path: Option<PathBuf>,
+            pages: Option<String>,
+        }
+        let params = get_arg!(args[0] as PageRangeParams);
+
+        let compiler = self.compiler();
+        if let Some(path) = &params.path {
+            let entry = compiler.entry();
+            let main = entry.main().zip(entry.root());
+            let main = main.and_then(|(main, root)| main.vpath().resolve(&root));
+            if main.as_deref() != Some(path.as_path()) {
+                let err = format!("{} is not the entry file", path.display());
+                return resp!(Err(invalid_params(err)));
+            }
+        }
+
+        let pages = params.pages.filter(|pages| !pages.trim().is_empty());
+        let filter = match pages.map(|pages| pages.parse::<PageFilter>()).transpose() {
+            Ok(filter) => filter,
+            Err(err) => return resp!(Err(invalid_params(err))),
+        };
+        compiler.change_page_filter(filter);
+        resp!(Ok(Some(JsonValue::Null)))
+    }
+
+    /// Restrict the exports on save to the files matching some globs relative
+    /// to the root, until cleared by an empty list.
+    pub fn set_export_on_save_pattern(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        let globs = get_arg!(args[0] as Vec<String>);
+        let glob = match (!globs.is_empty()).then(|| IgnorePatterns::new(&globs)) {
+            Some(Ok(glob)) => Some(glob),
+            Some(Err(err)) => return resp!(Err(invalid_params(format!("invalid glob: {err}")))),
+            None => None,
+        };
+        self.config.export_on_save_glob.clone_from(&glob);
+        self.compiler().change_save_glob(glob);
+        resp!(Ok(Some(JsonValue::Null)))
     }
 
     /// Export the current document as some format. The client is responsible
@@ -46,9 +687,17 @@ impl CompileState {
     pub fn export(
         &mut self,
         kind: ExportKind,
+        watermark: Option<Watermark>,
         mut args: Vec<JsonValue>,
     ) -> ResponseFuture<ExecuteCommand> {
         let path = get_arg!(args[0] as PathBuf);
+        match watermark {
+            Some(watermark) => self.export_watermarked(kind, path, watermark),
+            None => self.export_to(kind, path),
+        }
+    }
+
+    fn export_to(&mut self, kind: ExportKind, path: PathBuf) -> ResponseFuture<ExecuteCommand> {
         let rx = self.compiler().on_export(kind, path);
         Box::pin(async move {
             match rx.await {
@@ -58,6 +707,222 @@ impl CompileState {
         })
     }
 
+    fn export_watermarked(
+        &mut self,
+        kind: ExportKind,
+        path: PathBuf,
+        watermark: Watermark,
+    ) -> ResponseFuture<ExecuteCommand> {
+        if let Err(err) = watermark.validate() {
+            return resp!(Err(invalid_params(err)));
+        }
+        let fut = self.compiler().on_export_watermarked(kind, path, watermark);
+        Box::pin(async move {
+            match fut.await {
+                Ok(res) => Ok(to_value(res).ok()),
+                Err(err) => Err(internal_error(format!(
+                    "cannot export with watermark: {err}"
+                ))),
+            }
+        })
+    }
+
+    /// Export a variant of a file as the entry for each set of inputs in
+    /// `inputsList`, visible through `sys.inputs`, named by `outputPattern`
+    /// with `{key}` replaced by the input values, e.g. `posters/{title}`. A
+    /// relative pattern is resolved against the directory of the file. The
+    /// failed variants are reported along with the exported ones.
+    pub fn export_series(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.export_series_with_progress(args, None)
+    }
+
+    /// Export the variants on a fork of the world, leaving the compiler thread
+    /// to the edits meanwhile. The export stops before the next variant once
+    /// the progress is cancelled, returning the variants exported before.
+    pub fn export_series_with_progress(
+        &mut self,
+        mut args: Vec<JsonValue>,
+        progress: Option<(Progress, WorkDone)>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct SeriesParams {
+            path: PathBuf,
+            inputs_list: Vec<BTreeMap<String, String>>,
+            kind: String,
+            output_pattern: PathBuf,
+        }
+        let params = get_arg!(args[0] as SeriesParams);
+        let Some(format) = SeriesFormat::from_name(&params.kind) else {
+            let msg = format!("unsupported kind: {}", params.kind);
+            return resp!(Err(invalid_params(msg)));
+        };
+        if let Err(err) = series::validate_variants(params.inputs_list.len()) {
+            return resp!(Err(invalid_params(err)));
+        }
+
+        let pattern = match params.path.parent() {
+            Some(dir) => dir.join(&params.output_pattern),
+            None => params.output_pattern,
+        };
+        let inputs_list = params.inputs_list;
+        let fork = self.compiler().fork_world(params.path);
+        if let Some((_, work_done)) = &progress {
+            work_done.begin("Exporting series");
+        }
+        Box::pin(async move {
+            let (world, main) = match fork.await {
+                Ok(fork) => fork,
+                Err(err) => {
+                    if let Some((_, work_done)) = &progress {
+                        work_done.end(None);
+                    }
+                    return Err(invalid_params(format!("cannot export series: {err}")));
+                }
+            };
+            let cancel = progress.as_ref().map(|(p, _)| p.cancel_flag());
+            let work_done = progress.as_ref().map(|(_, w)| w.clone());
+            let res = tokio::task::spawn_blocking(move || {
+                let cancelled = || {
+                    cancel
+                        .as_ref()
+                        .is_some_and(|flag| flag.load(Ordering::Relaxed))
+                };
+                let report = |done: usize, total: usize| {
+                    if let Some(work_done) = &work_done {
+                        let percentage = (done * 100 / total) as u32;
+                        work_done.report(format!("{done}/{total} variants"), percentage);
+                    }
+                };
+                let res = export_series(
+                    &world,
+                    main,
+                    inputs_list,
+                    format,
+                    &pattern,
+                    cancelled,
+                    report,
+                );
+                (res, cancelled())
+            })
+            .await;
+            if let Some((_, work_done)) = progress {
+                let cancelled = matches!(res, Ok((_, true)));
+                work_done.end(cancelled.then(|| "cancelled".to_owned()));
+            }
+            match res {
+                Ok((Ok(exports), _)) => Ok(to_value(exports).ok()),
+                Ok((Err(err), _)) => Err(invalid_params(format!("cannot export series: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot export series: {err}"))),
+            }
+        })
+    }
+
+    /// Export the current document as `pdf` by default, `svg` or `png`, to the
+    /// configured output path, and write a JSON manifest next to it. The
+    /// manifest records the files written with their sizes and pages, the
+    /// inputs, and the time and diagnostics of the compilation exported, for
+    /// build pipelines.
+    pub fn export_manifest(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Default, Deserialize)]
+        struct ManifestOpts {
+            kind: Option<String>,
+            #[serde(default)]
+            page: PageSelection,
+            text: Option<SvgTextMode>,
+        }
+        let path = get_arg!(args[0] as PathBuf);
+        let opts = get_arg_or_default!(args[1] as ManifestOpts);
+        let kind = match opts.kind.as_deref().unwrap_or("pdf") {
+            "pdf" => ExportKind::Pdf,
+            "svg" => ExportKind::Svg {
+                page: opts.page,
+                text: opts.text.unwrap_or_default(),
+            },
+            "png" => ExportKind::Png { page: opts.page },
+            kind => return resp!(Err(invalid_params(format!("unsupported kind: {kind}")))),
+        };
+
+        // The manifest describes the entry of the compiler, whatever the path.
+        log::info!("CompileActor: export manifest for {}", path.display());
+        let export = self.compiler().on_export_manifest(kind);
+        Box::pin(async move {
+            match export.await {
+                Ok(Some((to, manifest))) => Ok(Some(json!({ "path": to, "manifest": manifest }))),
+                Ok(None) => Err(internal_error("cannot export, see the log for details")),
+                Err(err) => Err(internal_error(format!("cannot export: {err}"))),
+            }
+        })
+    }
+
+    /// Compile a file as the entry once cold and `iterations` times warm,
+    /// returning the timings of the compilations. The compilations run on a
+    /// fork of the world off the compiler thread, keeping the entry of the
+    /// compiler.
+    pub fn benchmark_document(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct BenchmarkParams {
+            path: PathBuf,
+            iterations: Option<usize>,
+        }
+        let params = get_arg!(args[0] as BenchmarkParams);
+        let iterations = params.iterations.unwrap_or(benchmark::DEFAULT_ITERATIONS);
+        if let Err(err) = benchmark::validate_iterations(iterations) {
+            return resp!(Err(invalid_params(err)));
+        }
+
+        let fork = self.compiler().fork_world(params.path);
+        Box::pin(async move {
+            let (world, main) = fork
+                .await
+                .map_err(|err| invalid_params(format!("cannot benchmark document: {err}")))?;
+            let res =
+                tokio::task::spawn_blocking(move || benchmark_document(&world, main, iterations));
+            match res.await {
+                Ok(Ok(stats)) => Ok(to_value(stats).ok()),
+                Ok(Err(err)) => Err(invalid_params(format!("cannot benchmark document: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot benchmark document: {err}"))),
+            }
+        })
+    }
+
+    /// Compile a file as the entry twice and compare the exports, as `pdf` by
+    /// default or `png`, returning whether they are identical and which pages
+    /// differ.
+    pub fn verify_reproducible(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ReproducibleParams {
+            path: PathBuf,
+            format: Option<String>,
+        }
+        let params = get_arg!(args[0] as ReproducibleParams);
+        let format = params.format.as_deref().unwrap_or("pdf");
+        let Some(format) = ReproducibleFormat::from_name(format) else {
+            return resp!(Err(invalid_params(format!("unknown format: {format}"))));
+        };
+
+        let path = params.path;
+        let fut = self.compiler().steal_world(move |ctx| {
+            let main = ctx.source_by_path(&path)?;
+            verify_reproducible(ctx.world(), main, format)
+        });
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(report)) => Ok(to_value(report).ok()),
+                Ok(Err(err)) => Err(invalid_params(format!("cannot verify document: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot verify document: {err}"))),
+            }
+        })
+    }
+
     /// Clear all cached resources.
     pub fn clear_cache(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
         comemo::evict(0);
@@ -73,4 +938,62 @@ impl CompileState {
         };
         resp!(Ok(Some(JsonValue::Null)))
     }
+
+    /// Propose the fixes of the root directory for an entry outside of it, or
+    /// apply the fix of the given kind and focus the entry.
+    ///
+    /// Without a kind, returns the `entry`, the current `root` and the
+    /// `repairs`, which are empty if the entry is in the root. With a kind,
+    /// returns the applied repair.
+    pub fn repair_entry(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let entry = get_arg!(args[0] as PathBuf);
+        let kind = get_arg_or_default!(args[1] as Option<EntryRepairKind>);
+        if !entry.is_absolute() {
+            return resp!(Err(invalid_params("entry file must be absolute")));
+        }
+
+        let entry = ImmutPath::from(entry);
+        let repairs = self.config.entry_repairs(&entry);
+        let Some(kind) = kind else {
+            let root = self.config.determine_root(Some(&entry));
+            return resp!(Ok(Some(json!({
+                "entry": entry,
+                "root": root,
+                "repairs": repairs,
+            }))));
+        };
+
+        let Some(repair) = repairs.into_iter().find(|repair| repair.kind == kind) else {
+            return resp!(Err(invalid_params(format!(
+                "no {kind:?} repair applies to {entry:?}"
+            ))));
+        };
+        log::info!("repairing entry {entry:?} with {repair:?}");
+        self.config.repair_entry(&repair);
+        if let Some(compiler) = self.compiler.as_mut() {
+            compiler.change_config(self.config.clone());
+        }
+        if let Err(err) = self.do_change_entry(Some(entry)) {
+            return resp!(Err(internal_error(format!("cannot change entry: {err}"))));
+        };
+        resp!(Ok(to_value(repair).ok()))
+    }
+
+    /// Start a preview server for the current entry, or get the running one,
+    /// returning its `url` and `dataPlaneUrl`.
+    pub fn preview_server_url(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        if cfg!(not(feature = "preview")) {
+            return resp!(Err(invalid_params(
+                "tinymist is built without the preview feature"
+            )));
+        }
+        let compiler = self.compiler.as_ref().unwrap();
+        let start = |stop| compiler.start_preview(stop);
+        match self.preview.get_or_start(start) {
+            Ok(urls) => resp!(Ok(Some(json!(urls)))),
+            Err(err) => resp!(Err(internal_error(format!(
+                "cannot start preview server: {err}"
+            )))),
+        }
+    }
 }
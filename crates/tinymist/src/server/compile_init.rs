@@ -1,15 +1,19 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::bail;
 use async_lsp::ClientSocket;
 use clap::builder::ValueParser;
 use clap::{ArgAction, Parser};
 use comemo::Prehashed;
+use lsp_types::Url;
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value as JsonValue};
-use tinymist_query::PositionEncoding;
+use tinymist_query::syntax::{ColorTheme, IgnorePatterns};
+use tinymist_query::{DiagnosticsMap, LintRules, LspDiagnostic, PositionEncoding, UserSnippet};
 use tinymist_render::PeriscopeArgs;
 use tokio::sync::mpsc;
 use typst::foundations::IntoValue;
@@ -19,10 +23,13 @@ use typst_ts_core::config::compiler::EntryState;
 use typst_ts_core::{ImmutPath, TypstDict};
 
 use super::*;
-use crate::actor::editor::EditorRequest;
+use crate::actor::editor::{
+    DiagnosticSource, EditorRequest, TinymistCompileStatusEnum, TypstCompileStatus,
+};
 use crate::compile::CompileState;
+use crate::tools::persistent_cache::{PersistentCache, DEFAULT_CACHE_LIMIT_MB};
 use crate::world::{ImmutDict, SharedFontResolver};
-use crate::{CompileExtraOpts, CompileFontOpts, ExportMode};
+use crate::{CompileExtraOpts, CompileFontOpts, ExportMode, OutputPathByKind};
 
 #[cfg(feature = "clap")]
 const ENV_PATH_SEP: char = if cfg!(windows) { ';' } else { ':' };
@@ -84,13 +91,146 @@ fn parse_input_pair(raw: &str) -> Result<(String, String), String> {
     Ok((key, val))
 }
 
+/// The format of the diagnostics printed by the compile mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum DiagnosticFormat {
+    /// Print the diagnostics and the timings as messages of the language
+    /// server protocol.
+    #[default]
+    Human,
+    /// Print a line of JSON per compilation, see [`CompileEvent`].
+    Json,
+}
+
+/// An event printed as a line of JSON per compilation when the diagnostics
+/// are printed in the [`DiagnosticFormat::Json`] format.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompileEvent {
+    /// The kind of the event, which is always `compiled` for now.
+    pub event: &'static str,
+    /// Whether the document is compiled without errors.
+    pub ok: bool,
+    /// The time taken by the compilation in milliseconds.
+    pub duration_ms: u64,
+    /// The errors and warnings of the compilation.
+    pub diagnostics: Vec<EventDiagnostic>,
+}
+
+/// A diagnostic of a [`CompileEvent`], along with the file it belongs to.
+#[derive(Debug, Clone, Serialize)]
+pub struct EventDiagnostic {
+    /// The file the diagnostic belongs to.
+    pub uri: Url,
+    #[serde(flatten)]
+    pub diagnostic: LspDiagnostic,
+}
+
+impl CompileEvent {
+    /// Creates the event of a finished compilation.
+    pub fn compiled(ok: bool, duration: Duration, diagnostics: DiagnosticsMap) -> Self {
+        let mut diagnostics: Vec<_> = (diagnostics.into_iter())
+            .flat_map(|(uri, diags)| diags.into_iter().map(move |d| (uri.clone(), d)))
+            .map(|(uri, diagnostic)| EventDiagnostic { uri, diagnostic })
+            .collect();
+        diagnostics.sort_by(|a, b| a.uri.as_str().cmp(b.uri.as_str()));
+        Self {
+            event: "compiled",
+            ok,
+            duration_ms: duration.as_millis() as u64,
+            diagnostics,
+        }
+    }
+
+    /// Writes the event as a line of JSON and flushes it, so that consumers
+    /// can read the events one by one while compiling.
+    pub fn write_line(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+        serde_json::to_writer(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+}
+
+/// Pairs the status at the end of each compilation with the diagnostics
+/// published after it, making a [`CompileEvent`] per compilation of the
+/// compile server.
+#[derive(Debug, Default)]
+pub struct CompileEvents {
+    /// The last finished compilation of each compiler, by its group.
+    finished: HashMap<String, TypstCompileStatus>,
+}
+
+impl CompileEvents {
+    /// Takes a request to the editor, returning the event of a compilation
+    /// once its diagnostics are published.
+    pub fn push(&mut self, req: EditorRequest) -> Option<CompileEvent> {
+        match req {
+            EditorRequest::CompileStatus(status)
+                if !matches!(status.status, TinymistCompileStatusEnum::Compiling) =>
+            {
+                self.finished.insert(status.group.clone(), status);
+                None
+            }
+            EditorRequest::Diag(group, diagnostics) => {
+                let status = self.finished.remove(&group)?;
+                let duration = Duration::from_millis(status.elapsed_ms);
+                let diagnostics = diagnostics.unwrap_or_default();
+                Some(CompileEvent::compiled(
+                    status.errors == 0,
+                    duration,
+                    diagnostics,
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Prints the [`CompileEvent`]s of the compile server as lines of JSON to
+/// stderr, since stdout carries the protocol.
+pub async fn print_compile_events(mut editor_rx: mpsc::UnboundedReceiver<EditorRequest>) {
+    let mut events = CompileEvents::default();
+    while let Some(req) = editor_rx.recv().await {
+        let Some(event) = events.push(req) else {
+            continue;
+        };
+        if let Err(err) = event.write_line(std::io::stderr().lock()) {
+            log::warn!("failed to print the compile event: {err}");
+        }
+    }
+}
+
+/// The kind of a fix of the root directory for an entry outside of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EntryRepairKind {
+    /// Widen the root to the closest ancestor containing both the root and
+    /// the entry.
+    WidenRoot,
+    /// Re-root to the directory of the entry.
+    RerootToEntry,
+}
+
+/// A fix of the root directory for an entry outside of it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryRepair {
+    pub kind: EntryRepairKind,
+    /// The root directory to use.
+    pub root: PathBuf,
+}
+
 /// The user configuration read from the editor.
 #[derive(Debug, Default, Clone)]
 pub struct CompileConfig {
     /// The workspace roots from initialization.
     pub roots: Vec<PathBuf>,
-    /// The output directory for PDF export.
+    /// The output path pattern for export.
     pub output_path: String,
+    /// The output path patterns overriding `output_path` for some kinds of
+    /// export.
+    pub output_path_by_kind: OutputPathByKind,
     /// The mode of PDF export.
     pub export_pdf: ExportMode,
     /// Specifies the root path of the project manually.
@@ -102,11 +242,64 @@ pub struct CompileConfig {
     /// Typst extra arguments.
     pub typst_extra_args: Option<CompileExtraOpts>,
     /// The preferred theme for the document.
-    pub preferred_theme: Option<String>,
+    pub preferred_theme: Option<ColorTheme>,
+    /// Whether to render the equations in hover to images.
+    pub hover_math_preview: bool,
+    /// Whether to list the set rules in effect when hovering text.
+    pub hover_set_rules: bool,
+    /// Whether to show the values read from counters and states in the
+    /// document as inlay hints.
+    pub inlay_hint_document_values: bool,
+    /// The path to the pandoc executable, used to export DOCX.
+    pub pandoc_path: Option<PathBuf>,
+    /// The paths in the workspace that are not indexed.
+    pub index_ignore: IgnorePatterns,
+    /// The lint rules to toggle, keyed by their ids.
+    pub lint_rules: LintRules,
+    /// The font families tried in order before the default fallback.
+    pub font_fallback: Vec<String>,
+    /// The globs of the files whose saves trigger exports, or all files if
+    /// absent.
+    pub export_on_save_glob: Option<IgnorePatterns>,
+    /// Whether to cache the exported artifacts on disk across restarts.
+    pub persistent_cache: bool,
+    /// The size limit of the persistent cache, in megabytes.
+    pub persistent_cache_limit: u64,
+    /// Whether the documents mix CJK and Latin text, which excludes CJK
+    /// punctuation from the word count and keeps the formatter from wrapping
+    /// text.
+    pub cjk_mode: bool,
+    /// The CJK mode set by the settings when the mode is toggled, see
+    /// [`Self::toggle_cjk_mode`].
+    pub cjk_toggle: Option<bool>,
+    /// The snippets configured by the user, which are offered as completions.
+    pub user_snippets: Vec<UserSnippet>,
+    /// Whether the diagnostics of the entries sharing a file are merged or
+    /// tagged with their entries.
+    pub diagnostic_source: DiagnosticSource,
+    /// The time budget of a compilation, exceeding which aborts it.
+    pub compile_timeout: Option<Duration>,
+    /// The fix applied to the root directory, along with the root path set by
+    /// the settings then, see [`Self::repair_entry`].
+    pub root_repair: Option<(Option<PathBuf>, EntryRepair)>,
     pub has_default_entry_path: bool,
 }
 
 impl CompileConfig {
+    /// Gets the persistent cache of the exported artifacts, if enabled.
+    pub fn persistent_cache(&self) -> Option<PersistentCache> {
+        if !self.persistent_cache {
+            return None;
+        }
+        PersistentCache::in_user_cache(self.persistent_cache_limit)
+    }
+
+    /// Gets the output path pattern for exports with the extension.
+    pub fn output_pattern(&self, extension: &str) -> &str {
+        self.output_path_by_kind
+            .pattern(extension, &self.output_path)
+    }
+
     /// Updates the configuration with a JSON object.
     ///
     /// # Errors
@@ -124,15 +317,108 @@ impl CompileConfig {
     /// # Errors
     /// Errors if the update is invalid.
     pub fn update_by_map(&mut self, update: &Map<String, JsonValue>) -> anyhow::Result<()> {
-        self.output_path = try_or_default(|| Some(update.get("outputPath")?.as_str()?.to_owned()));
+        // A string is the pattern shared by all kinds of export, while a map may
+        // also specify the patterns for some kinds.
+        (self.output_path, self.output_path_by_kind) = match update.get("outputPath") {
+            Some(JsonValue::String(pattern)) => (pattern.clone(), Default::default()),
+            Some(JsonValue::Object(patterns)) => {
+                let shared = try_or_default(|| Some(patterns.get("default")?.as_str()?.to_owned()));
+                match OutputPathByKind::deserialize(JsonValue::Object(patterns.clone())) {
+                    Ok(by_kind) => (shared, by_kind),
+                    Err(e) => bail!("failed to parse outputPath: {e}"),
+                }
+            }
+            _ => Default::default(),
+        };
         self.export_pdf = try_or_default(|| ExportMode::deserialize(update.get("exportPdf")?).ok());
-        self.root_path = try_(|| Some(update.get("rootPath")?.as_str()?.into()));
+        let root_path = try_(|| Some(update.get("rootPath")?.as_str()?.into()));
+        // The repaired root is kept until the settings change the root path.
+        self.root_repair = (self.root_repair.take()).filter(|(repaired, _)| *repaired == root_path);
+        self.root_path = match &self.root_repair {
+            Some((_, repair)) => Some(repair.root.clone()),
+            None => root_path,
+        };
         self.notify_compile_status = match try_(|| update.get("compileStatus")?.as_str()) {
             Some("enable") => true,
             Some("disable") | None => false,
             _ => bail!("compileStatus must be either 'enable' or 'disable'"),
         };
-        self.preferred_theme = try_(|| Some(update.get("preferredTheme")?.as_str()?.to_owned()));
+        self.preferred_theme = try_(|| ColorTheme::deserialize(update.get("preferredTheme")?).ok());
+        self.hover_math_preview = try_or_default(|| update.get("hoverMathPreview")?.as_bool());
+        self.hover_set_rules = try_or_default(|| update.get("hoverSetRules")?.as_bool());
+        self.inlay_hint_document_values =
+            try_or_default(|| update.get("inlayHints")?.get("documentValues")?.as_bool());
+        self.pandoc_path = try_(|| Some(update.get("pandocPath")?.as_str()?.into()));
+        let index_ignore: Vec<String> = match update.get("indexIgnore") {
+            Some(globs) => match serde_json::from_value(globs.clone()) {
+                Ok(globs) => globs,
+                Err(e) => bail!("failed to parse indexIgnore: {e}"),
+            },
+            None => vec![],
+        };
+        self.index_ignore = match IgnorePatterns::new(&index_ignore) {
+            Ok(ignore) => ignore,
+            Err(e) => bail!("invalid glob in indexIgnore: {e}"),
+        };
+
+        self.lint_rules = match update.get("lintRules") {
+            Some(rules) => match serde_json::from_value(rules.clone()) {
+                Ok(rules) => rules,
+                Err(e) => bail!("failed to parse lintRules: {e}"),
+            },
+            None => LintRules::default(),
+        };
+        self.font_fallback = match update.get("fontFallback") {
+            Some(families) => match serde_json::from_value(families.clone()) {
+                Ok(families) => families,
+                Err(e) => bail!("failed to parse fontFallback: {e}"),
+            },
+            None => vec![],
+        };
+        self.export_on_save_glob = match update.get("exportOnSaveGlob") {
+            Some(globs) => match serde_json::from_value::<Vec<String>>(globs.clone()) {
+                Ok(globs) if globs.is_empty() => None,
+                Ok(globs) => match IgnorePatterns::new(&globs) {
+                    Ok(glob) => Some(glob),
+                    Err(e) => bail!("invalid glob in exportOnSaveGlob: {e}"),
+                },
+                Err(e) => bail!("failed to parse exportOnSaveGlob: {e}"),
+            },
+            None => None,
+        };
+        self.persistent_cache = try_or_default(|| update.get("persistentCache")?.as_bool());
+        self.persistent_cache_limit =
+            try_(|| update.get("persistentCacheLimit")?.as_u64()).unwrap_or(DEFAULT_CACHE_LIMIT_MB);
+        let cjk_mode = try_or_default(|| update.get("cjkMode")?.as_bool());
+        // The toggled mode is kept until the settings change the mode.
+        self.cjk_toggle = (self.cjk_toggle.take()).filter(|toggled| *toggled == cjk_mode);
+        self.cjk_mode = match self.cjk_toggle {
+            Some(_) => !cjk_mode,
+            None => cjk_mode,
+        };
+        self.user_snippets = match update.get("userSnippets") {
+            Some(snippets) => match serde_json::from_value(snippets.clone()) {
+                Ok(snippets) => snippets,
+                Err(e) => bail!("failed to parse userSnippets: {e}"),
+            },
+            None => vec![],
+        };
+        self.diagnostic_source = match update.get("diagnosticSource") {
+            Some(source) => match DiagnosticSource::deserialize(source) {
+                Ok(source) => source,
+                Err(e) => bail!("failed to parse diagnosticSource: {e}"),
+            },
+            None => DiagnosticSource::default(),
+        };
+
+        self.compile_timeout = match update.get("compileTimeoutMs") {
+            Some(JsonValue::Null) | None => None,
+            Some(ms) => match ms.as_u64() {
+                Some(0) => None,
+                Some(ms) => Some(Duration::from_millis(ms)),
+                None => bail!("compileTimeoutMs must be a non-negative integer, got {ms}"),
+            },
+        };
 
         // periscope_args
         self.periscope_args = match update.get("hoverPeriscope") {
@@ -143,11 +429,6 @@ impl CompileConfig {
                 Err(e) => bail!("failed to parse hoverPeriscope: {e}"),
             },
         };
-        if let Some(args) = self.periscope_args.as_mut() {
-            if args.invert_color == "auto" && self.preferred_theme.as_deref() == Some("dark") {
-                "always".clone_into(&mut args.invert_color);
-            }
-        }
 
         'parse_extra_args: {
             if let Some(typst_extra_args) = update.get("typstExtraArgs") {
@@ -190,6 +471,17 @@ impl CompileConfig {
         self.validate()
     }
 
+    /// Adds and removes workspace roots, keeping the order of existing roots.
+    pub fn change_roots(&mut self, added: &[PathBuf], removed: &[PathBuf]) {
+        self.roots.retain(|root| !removed.contains(root));
+        for root in added {
+            if !self.roots.contains(root) {
+                self.roots.push(root.clone());
+            }
+        }
+        self.has_default_entry_path = self.determine_default_entry_path().is_some();
+    }
+
     pub fn determine_root(&self, entry: Option<&ImmutPath>) -> Option<ImmutPath> {
         if let Some(path) = &self.root_path {
             return Some(path.as_path().into());
@@ -222,6 +514,56 @@ impl CompileConfig {
         None
     }
 
+    /// Gets the fixes of the root directory for an entry outside of it, which
+    /// is otherwise compiled without a root. Returns nothing if the entry is
+    /// in the root.
+    pub fn entry_repairs(&self, entry: &ImmutPath) -> Vec<EntryRepair> {
+        let Some(root) = self.determine_root(Some(entry)) else {
+            return vec![];
+        };
+        if entry.starts_with(&root) {
+            return vec![];
+        }
+
+        let mut repairs = vec![];
+        // Widening to the root of the file system would expose all files.
+        let ancestor = root.ancestors().find(|dir| entry.starts_with(dir));
+        if let Some(ancestor) = ancestor.filter(|dir| dir.parent().is_some()) {
+            repairs.push(EntryRepair {
+                kind: EntryRepairKind::WidenRoot,
+                root: ancestor.to_owned(),
+            });
+        }
+        if let Some(dir) = entry.parent() {
+            repairs.push(EntryRepair {
+                kind: EntryRepairKind::RerootToEntry,
+                root: dir.to_owned(),
+            });
+        }
+        repairs
+    }
+
+    /// Applies a fix of the root directory by setting the root path, which
+    /// is kept across updates not changing the root path in the settings.
+    pub fn repair_entry(&mut self, repair: &EntryRepair) {
+        let repaired = match self.root_repair.take() {
+            Some((repaired, _)) => repaired,
+            None => self.root_path.clone(),
+        };
+        self.root_repair = Some((repaired, repair.clone()));
+        self.root_path = Some(repair.root.clone());
+        self.has_default_entry_path = self.determine_default_entry_path().is_some();
+    }
+
+    /// Toggles the CJK mode and returns whether it is on. The toggled mode is
+    /// kept across updates not changing the mode in the settings.
+    pub fn toggle_cjk_mode(&mut self) -> bool {
+        let setting = self.cjk_toggle.take().unwrap_or(self.cjk_mode);
+        self.cjk_mode = !self.cjk_mode;
+        self.cjk_toggle = (self.cjk_mode != setting).then_some(setting);
+        self.cjk_mode
+    }
+
     pub fn determine_default_entry_path(&self) -> Option<ImmutPath> {
         let extras = self.typst_extra_args.as_ref()?;
         // todo: pre-compute this when updating config
@@ -303,6 +645,11 @@ pub struct ConstCompileConfig {
     /// Determined position encoding, either UTF-8 or UTF-16.
     /// Defaults to UTF-16 if not specified.
     pub position_encoding: PositionEncoding,
+    /// Whether the client renders the HTML tags highlighting the code in
+    /// markdown.
+    pub markdown_html: bool,
+    /// Whether the client renders the images in markdown.
+    pub markdown_images: bool,
 }
 
 pub struct CompileInit {
@@ -346,6 +693,8 @@ impl CompileInit {
                         _ => PositionEncoding::Utf8,
                     })
                     .unwrap_or_default(),
+                markdown_html: false,
+                markdown_images: false,
             },
             self.editor_tx,
             font,
@@ -363,3 +712,189 @@ impl CompileInit {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{Diagnostic, Position, Range};
+
+    use super::*;
+
+    #[test]
+    fn test_compile_event_lines() {
+        let uri = Url::parse("file:///main.typ").unwrap();
+        let error = Diagnostic {
+            range: Range::new(Position::new(0, 1), Position::new(0, 4)),
+            message: "unknown variable: foo".to_owned(),
+            ..Default::default()
+        };
+        let failed = DiagnosticsMap::from_iter([(uri, vec![error])]);
+
+        let mut stdout = Vec::new();
+        CompileEvent::compiled(false, Duration::from_millis(12), failed)
+            .write_line(&mut stdout)
+            .unwrap();
+        CompileEvent::compiled(true, Duration::from_millis(3), DiagnosticsMap::new())
+            .write_line(&mut stdout)
+            .unwrap();
+
+        let stdout = String::from_utf8(stdout).unwrap();
+        let lines: Vec<JsonValue> = (stdout.lines())
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                serde_json::json!({
+                    "event": "compiled",
+                    "ok": false,
+                    "durationMs": 12,
+                    "diagnostics": [{
+                        "uri": "file:///main.typ",
+                        "range": {
+                            "start": { "line": 0, "character": 1 },
+                            "end": { "line": 0, "character": 4 },
+                        },
+                        "message": "unknown variable: foo",
+                    }],
+                }),
+                serde_json::json!({
+                    "event": "compiled",
+                    "ok": true,
+                    "durationMs": 3,
+                    "diagnostics": [],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_events() {
+        let status = |status, elapsed_ms, errors| {
+            EditorRequest::CompileStatus(TypstCompileStatus {
+                group: "primary".to_owned(),
+                status,
+                entry: None,
+                elapsed_ms,
+                errors,
+                warnings: 0,
+            })
+        };
+        let uri = Url::parse("file:///main.typ").unwrap();
+        let failed = DiagnosticsMap::from_iter([(uri, vec![Diagnostic::default()])]);
+
+        let mut events = CompileEvents::default();
+        let begin = status(TinymistCompileStatusEnum::Compiling, 0, 0);
+        assert!(events.push(begin).is_none());
+        let end = status(TinymistCompileStatusEnum::CompileError, 12, 1);
+        assert!(events.push(end).is_none());
+        // The diagnostics of other compilers are not paired.
+        let other = EditorRequest::Diag("other".to_owned(), None);
+        assert!(events.push(other).is_none());
+
+        let diag = EditorRequest::Diag("primary".to_owned(), Some(failed));
+        let event = events.push(diag).unwrap();
+        assert!(!event.ok);
+        assert_eq!(event.duration_ms, 12);
+        assert_eq!(event.diagnostics.len(), 1);
+
+        // The diagnostics are printed once per compilation.
+        let again = EditorRequest::Diag("primary".to_owned(), None);
+        assert!(events.push(again).is_none());
+        let end = status(TinymistCompileStatusEnum::CompileSuccess, 3, 0);
+        assert!(events.push(end).is_none());
+        let event = events.push(EditorRequest::Diag("primary".to_owned(), None));
+        assert!(event.unwrap().ok);
+    }
+
+    #[test]
+    fn test_repair_entry() {
+        let (root, entry) = if cfg!(windows) {
+            ("C:\\ws\\proj", "C:\\ws\\other\\main.typ")
+        } else {
+            ("/ws/proj", "/ws/other/main.typ")
+        };
+        let entry = ImmutPath::from(Path::new(entry));
+        let mut config = CompileConfig {
+            root_path: Some(PathBuf::from(root)),
+            ..Default::default()
+        };
+        assert_eq!(config.determine_entry(Some(entry.clone())).root(), None);
+
+        let repairs = config.entry_repairs(&entry);
+        let kinds: Vec<_> = repairs.iter().map(|r| r.kind).collect();
+        assert_eq!(
+            kinds,
+            [EntryRepairKind::WidenRoot, EntryRepairKind::RerootToEntry]
+        );
+
+        let resolved = |repair: &EntryRepair| {
+            let mut config = config.clone();
+            config.repair_entry(repair);
+            assert!(config.entry_repairs(&entry).is_empty());
+            let state = config.determine_entry(Some(entry.clone()));
+            let main = state.main().unwrap().vpath().as_rootless_path().to_owned();
+            (state.root().unwrap().to_path_buf(), main)
+        };
+        let other = entry.parent().unwrap().to_owned();
+        let ws = other.parent().unwrap().to_owned();
+        assert_eq!(resolved(&repairs[0]), (ws, "other/main.typ".into()));
+        assert_eq!(resolved(&repairs[1]), (other, "main.typ".into()));
+
+        config.root_path = None;
+        assert!(config.entry_repairs(&entry).is_empty());
+    }
+
+    #[test]
+    fn test_keep_repaired_root() {
+        let (root, other) = if cfg!(windows) {
+            ("C:\\ws\\proj", "C:\\ws\\other")
+        } else {
+            ("/ws/proj", "/ws/other")
+        };
+        let update = |root: &str| {
+            let update = serde_json::json!({ "rootPath": root });
+            update.as_object().unwrap().clone()
+        };
+        let mut config = CompileConfig::default();
+        config.update_by_map(&update(root)).unwrap();
+        let repair = EntryRepair {
+            kind: EntryRepairKind::RerootToEntry,
+            root: PathBuf::from(other),
+        };
+        config.repair_entry(&repair);
+
+        // The settings not changing the root path keep the repair.
+        config.update_by_map(&update(root)).unwrap();
+        assert_eq!(config.root_path, Some(PathBuf::from(other)));
+
+        config.update_by_map(&update(other)).unwrap();
+        assert_eq!(config.root_repair, None);
+        config.update_by_map(&update(root)).unwrap();
+        assert_eq!(config.root_path, Some(PathBuf::from(root)));
+    }
+
+    #[test]
+    fn test_keep_toggled_cjk_mode() {
+        let update = |cjk_mode: bool| {
+            let update = serde_json::json!({ "cjkMode": cjk_mode });
+            update.as_object().unwrap().clone()
+        };
+        let mut config = CompileConfig::default();
+        config.update_by_map(&update(false)).unwrap();
+        assert!(config.toggle_cjk_mode());
+
+        // The settings not changing the mode keep the toggled one.
+        config.update_by_map(&update(false)).unwrap();
+        assert!(config.cjk_mode);
+
+        // Toggling back to the settings drops the toggled mode.
+        assert!(!config.toggle_cjk_mode());
+        assert_eq!(config.cjk_toggle, None);
+
+        // The toggled mode persisted by the client is the one of the settings.
+        config.toggle_cjk_mode();
+        config.update_by_map(&update(true)).unwrap();
+        assert_eq!(config.cjk_toggle, None);
+        assert!(config.cjk_mode);
+    }
+}
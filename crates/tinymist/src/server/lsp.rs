@@ -1,27 +1,40 @@
 //! tinymist LSP mode
 
+use std::collections::HashMap;
 use std::ops::ControlFlow;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use async_lsp::router::Router;
 use async_lsp::{LanguageServer, ResponseError};
 use lsp_types::request::*;
 use lsp_types::*;
-use tinymist_query::{self as q, url_to_path, SemanticTokenContext};
+use tinymist_query::{
+    self as q, url_to_path, SemanticRequest, SemanticTokenContext, SyntaxRequest,
+};
+use typst::syntax::Source;
 use typst_ts_core::{Error as TypError, ImmutPath};
 
 use super::lsp_init::*;
+use super::progress::{Progress, ProgressTokens};
 use super::*;
 use crate::actor::typ_client::CompileClientActor;
 use crate::compile::CompileState;
+use crate::logging::REQUEST_EVENT;
+use crate::state::{route_scoped_config, ChangedConfigEvent};
 use crate::task;
+use crate::telemetry::{CompileLog, RequestTelemetry};
+use crate::tools::recovery::RecoveryStore;
 use crate::world::CompileFontOpts;
 
 // todo: parallelization
 // todo: create a trait for these requests and make it a function
 macro_rules! query_source {
     ($self:ident, $req:ident) => {{
-        let path = $req.path;
-        let Some(mem_file) = $self.primary.memory_changes.get(path.as_path()) else {
+        log::debug!(target: $crate::logging::REQUEST_EVENT, "{:?}", $req);
+        let _timer = $self.telemetry.start(&$req);
+        let path = $self.path_key(&$req.path);
+        let Some(mem_file) = $self.primary.memory_changes.get(&path) else {
             return resp!(Err(internal_error(format!("file missing: {path:?}"))));
         };
         let source = mem_file.content.clone();
@@ -37,8 +50,10 @@ pub(super) use query_source;
 // todo: create a trait for these requests and make it a function
 macro_rules! query_tokens_cache {
     ($self:ident, $req:ident) => {{
-        let path = $req.path;
-        let Some(mem_file) = $self.primary.memory_changes.get(path.as_path()) else {
+        log::debug!(target: $crate::logging::REQUEST_EVENT, "{:?}", $req);
+        let _timer = $self.telemetry.start(&$req);
+        let path = $self.path_key(&$req.path);
+        let Some(mem_file) = $self.primary.memory_changes.get(&path) else {
             return resp!(Err(internal_error(format!("file missing: {path:?}"))));
         };
         let source = mem_file.content.clone();
@@ -50,11 +65,19 @@ pub(super) use query_tokens_cache;
 // todo: create a trait for these requests and make it a function
 macro_rules! query_state {
     ($self:ident, $req:ident) => {{
+        log::debug!(target: $crate::logging::REQUEST_EVENT, "{:?}", $req);
+        let timer = $self.telemetry.start(&$req);
+        // The request is routed by the same key as the documents in memory.
+        let mut $req = $req;
+        $req.path = $self.path_key(&$req.path).to_path_buf();
         if let Err(err) = $self.update_entry(&$req.path) {
             return resp!(Err(internal_error(format!("cannot update entry: {err:?}"))));
         }
         let fut = $self.primary().steal_state(move |w, d| $req.request(w, d));
-        Box::pin(async move { fut.await.or_else(internal_error) })
+        Box::pin(async move {
+            let _timer = timer;
+            fut.await.or_else(internal_error)
+        })
     }};
 }
 pub(super) use query_state;
@@ -62,11 +85,19 @@ pub(super) use query_state;
 // todo: create a trait for these requests and make it a function
 macro_rules! query_world {
     ($self:ident, $req:ident) => {{
+        log::debug!(target: $crate::logging::REQUEST_EVENT, "{:?}", $req);
+        let timer = $self.telemetry.start(&$req);
+        // The request is routed by the same key as the documents in memory.
+        let mut $req = $req;
+        $req.path = $self.path_key(&$req.path).to_path_buf();
         if let Err(err) = $self.update_entry(&$req.path) {
             return resp!(Err(internal_error(format!("cannot update entry: {err:?}"))));
         }
         let fut = $self.primary().steal_world(move |w| $req.request(w));
-        Box::pin(async move { fut.await.or_else(internal_error) })
+        Box::pin(async move {
+            let _timer = timer;
+            fut.await.or_else(internal_error)
+        })
     }};
 }
 pub(super) use query_world;
@@ -86,6 +117,14 @@ pub struct LanguageState {
     pub ever_focusing_by_activities: bool,
     /// The client ever sent manual focusing request.
     pub ever_manual_focusing: bool,
+    /// The keys of the paths from the client, which are normalized once per
+    /// opened document rather than on every change.
+    pub path_keys: HashMap<PathBuf, ImmutPath>,
+    /// The progresses of the running commands, cancelable by the client.
+    pub progress: ProgressTokens,
+    /// The progress of the command being dispatched, which is taken by the
+    /// cancelable commands.
+    pub pending_progress: Option<Progress>,
 
     /* Configurations */
     /// User configuration from the editor.
@@ -108,6 +147,12 @@ pub struct LanguageState {
     pub primary: CompileState,
     /// The compilers for tasks
     pub dedicates: Vec<CompileState>,
+    /// The statistics of handled requests.
+    pub telemetry: Arc<RequestTelemetry>,
+    /// The log of the latest compilations of all compilers.
+    pub compile_log: Arc<CompileLog>,
+    /// The store of the unsaved buffers, if crash recovery is enabled.
+    pub recovery: Option<RecoveryStore>,
 }
 
 impl LanguageState {
@@ -119,6 +164,9 @@ impl LanguageState {
             ever_manual_focusing: false,
             pinning: false,
             focusing: None,
+            path_keys: HashMap::new(),
+            progress: ProgressTokens::default(),
+            pending_progress: None,
 
             config: Default::default(),
             const_config: Default::default(),
@@ -130,6 +178,9 @@ impl LanguageState {
             tokens_ctx: Default::default(),
             primary: todo!(),
             dedicates: Vec::new(),
+            telemetry: Default::default(),
+            compile_log: Default::default(),
+            recovery: None,
         }
     }
 
@@ -144,10 +195,30 @@ impl LanguageState {
             return Ok(false);
         }
         // todo: race condition, we need atomic primary query
-        self.primary.do_change_entry(Some(path.into())).await
+        let path = self.path_key(path);
+        let changed = self.primary.do_change_entry(Some(path)).await?;
+        if changed {
+            self.request_primary_scoped_config();
+        }
+        Ok(changed)
     }
 }
 
+/// Creates the router serving the language server, including the events looped
+/// back to the server.
+pub fn router(state: LanguageState) -> Router<LanguageState> {
+    let mut router = Router::from_language_server(state);
+    route_scoped_config(&mut router);
+    router.event::<ChangedConfigEvent>(|state, event| {
+        let values = LanguageConfig::values_to_map(event.values);
+        if let Err(err) = state.on_changed_configuration(values) {
+            log::error!("{err}");
+        }
+        ControlFlow::Continue(())
+    });
+    router
+}
+
 impl LanguageServer for LanguageState {
     type Error = ResponseError;
     type NotifyResult = ControlFlow<async_lsp::Result<()>>;
@@ -158,6 +229,16 @@ impl LanguageServer for LanguageState {
         resp!(self.init(params))
     }
 
+    fn shutdown(&mut self, (): ()) -> ResponseFuture<Shutdown> {
+        // The buffers are not to be restored after a clean shutdown, so they
+        // are removed before the client exits the server.
+        if let Some(recovery) = &self.recovery {
+            recovery.clear();
+            recovery.flush();
+        }
+        resp!(Ok(()))
+    }
+
     fn initialized(&mut self, params: InitializedParams) -> Self::NotifyResult {
         self.inited(params);
         ControlFlow::Continue(())
@@ -201,11 +282,66 @@ impl LanguageServer for LanguageState {
         ControlFlow::Continue(())
     }
 
+    fn did_change_workspace_folders(
+        &mut self,
+        params: DidChangeWorkspaceFoldersParams,
+    ) -> Self::NotifyResult {
+        log::info!("did change workspace folders {:?}", params.event);
+        let to_paths = |folders: Vec<WorkspaceFolder>| {
+            let paths = folders.into_iter().map(|folder| folder.uri.to_file_path());
+            paths.filter_map(Result::ok).collect::<Vec<_>>()
+        };
+        let added = to_paths(params.event.added);
+        let removed = to_paths(params.event.removed);
+
+        self.config.compile.change_roots(&added, &removed);
+        // The symlinks are resolved only within the roots.
+        self.path_keys.clear();
+        self.primary.change_roots(&added, &removed);
+        for v in &mut self.dedicates {
+            v.change_roots(&added, &removed);
+        }
+        self.request_folder_scoped_config(&added);
+        ControlFlow::Continue(())
+    }
+
+    fn work_done_progress_cancel(
+        &mut self,
+        params: WorkDoneProgressCancelParams,
+    ) -> Self::NotifyResult {
+        log::info!("cancel progress {:?}", params.token);
+        if !self.progress.cancel(&params.token) {
+            log::warn!("progress {:?} is not active", params.token);
+        }
+        ControlFlow::Continue(())
+    }
+
     fn did_change_configuration(
         &mut self,
         params: DidChangeConfigurationParams,
     ) -> Self::NotifyResult {
-        todo!();
+        if let JsonValue::Object(values) = params.settings {
+            if let Err(err) = self.on_changed_configuration(values) {
+                log::error!("{err}");
+            }
+            return ControlFlow::Continue(());
+        }
+
+        // Some clients only notify the change, so the settings are pulled.
+        let params = ConfigurationParams {
+            items: LanguageConfig::get_items(None),
+        };
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            match client.request::<WorkspaceConfiguration>(params).await {
+                Ok(values) => {
+                    if let Err(err) = client.emit(ChangedConfigEvent { values }) {
+                        log::warn!("failed to apply the changed configuration: {err}");
+                    }
+                }
+                Err(err) => log::warn!("failed to request the changed configuration: {err}"),
+            }
+        });
         ControlFlow::Continue(())
     }
 
@@ -221,6 +357,23 @@ impl LanguageServer for LanguageState {
         query_state!(self, req)
     }
 
+    fn completion_item_resolve(
+        &mut self,
+        params: CompletionItem,
+    ) -> ResponseFuture<ResolveCompletionItem> {
+        let Some(req) = q::CompletionResolveRequest::new(params.clone()) else {
+            return resp!(Ok(params));
+        };
+        log::debug!(target: REQUEST_EVENT, "{req:?}");
+        let timer = self.telemetry.start(&req);
+        let fut = self.primary().steal_world(move |w| req.request(w));
+        Box::pin(async move {
+            let _timer = timer;
+            // The item is kept as is if it cannot be resolved.
+            Ok(fut.await.ok().flatten().unwrap_or(params))
+        })
+    }
+
     fn semantic_tokens_full(
         &mut self,
         params: SemanticTokensParams,
@@ -269,14 +422,15 @@ impl LanguageServer for LanguageState {
         if self.config.formatter == FormatterMode::Disable {
             return resp!(Ok(None));
         }
-        let path = url_to_path(params.text_document.uri);
-        let Some(mem_file) = self.primary.memory_changes.get(path.as_path()) else {
+        let path = self.path_key(&url_to_path(params.text_document.uri));
+        let Some(mem_file) = self.primary.memory_changes.get(&path) else {
             return resp!(Err(internal_error(format!("file missing: {path:?}"))));
         };
         let fut = tokio::spawn(task::format(
             mem_file.content.clone(),
             self.config.formatter,
             self.config.formatter_print_width as _,
+            self.config.compile.cjk_mode,
             self.const_config.position_encoding,
         ));
         Box::pin(async move { fut.await.unwrap() })
@@ -289,7 +443,15 @@ impl LanguageServer for LanguageState {
             path: url_to_path(params.text_document.uri),
             range: params.range,
         };
-        query_world!(self, req)
+        query_state!(self, req)
+    }
+
+    fn inline_value(&mut self, params: InlineValueParams) -> ResponseFuture<InlineValueRequest> {
+        let req = q::InlineValueRequest {
+            path: url_to_path(params.text_document.uri),
+            range: params.range,
+        };
+        query_state!(self, req)
     }
 
     fn document_color(&mut self, params: DocumentColorParams) -> ResponseFuture<DocumentColor> {
@@ -311,10 +473,18 @@ impl LanguageServer for LanguageState {
         resp!(Ok(req.request().unwrap()))
     }
 
+    fn document_link(&mut self, params: DocumentLinkParams) -> ResponseFuture<DocumentLinkRequest> {
+        let req = q::DocumentLinkRequest {
+            path: url_to_path(params.text_document.uri),
+        };
+        query_world!(self, req)
+    }
+
     fn code_action(&mut self, params: CodeActionParams) -> ResponseFuture<CodeActionRequest> {
         let req = q::CodeActionRequest {
             path: url_to_path(params.text_document.uri),
             range: params.range,
+            context: params.context,
         };
         query_world!(self, req)
     }
@@ -372,7 +542,22 @@ impl LanguageServer for LanguageState {
             position: params.text_document_position.position,
             new_name: params.new_name,
         };
-        query_state!(self, req)
+
+        // Rejected names are reported to the user instead of renaming nothing.
+        log::debug!(target: REQUEST_EVENT, "{:?}", req);
+        let timer = self.telemetry.start(&req);
+        if let Err(err) = self.update_entry(&req.path) {
+            return resp!(Err(internal_error(format!("cannot update entry: {err:?}"))));
+        }
+        let fut = self.primary().steal_state(move |w, d| req.rename(w, d));
+        Box::pin(async move {
+            let _timer = timer;
+            match fut.await {
+                Ok(Ok(edit)) => Ok(edit),
+                Ok(Err(err)) => Err(invalid_params(err)),
+                Err(err) => Err(internal_error(err)),
+            }
+        })
     }
 
     fn definition(&mut self, params: GotoDefinitionParams) -> ResponseFuture<GotoDefinition> {
@@ -395,22 +580,76 @@ impl LanguageServer for LanguageState {
         let req = q::ReferencesRequest {
             path: url_to_path(params.text_document_position.text_document.uri),
             position: params.text_document_position.position,
+            include_declaration: params.context.include_declaration,
+            scope: self.config.references_scope,
         };
         query_world!(self, req)
     }
 
+    fn moniker(&mut self, params: MonikerParams) -> ResponseFuture<MonikerRequest> {
+        let req = q::MonikerRequest {
+            path: url_to_path(params.text_document_position_params.text_document.uri),
+            position: params.text_document_position_params.position,
+        };
+        query_state!(self, req)
+    }
+
     fn symbol(&mut self, params: WorkspaceSymbolParams) -> ResponseFuture<WorkspaceSymbolRequest> {
         let req = q::SymbolRequest {
             pattern: (!params.query.is_empty()).then_some(params.query),
+            lazy: self.const_config.ws_symbol_resolve,
         };
         query_world!(self, req)
     }
 
+    fn workspace_symbol_resolve(
+        &mut self,
+        params: WorkspaceSymbol,
+    ) -> ResponseFuture<WorkspaceSymbolResolve> {
+        let uri = match &params.location {
+            OneOf::Left(location) => location.uri.clone(),
+            OneOf::Right(location) => location.uri.clone(),
+        };
+        let req = q::WorkspaceSymbolResolveRequest {
+            path: self.path_key(&url_to_path(uri)).to_path_buf(),
+            symbol: params,
+        };
+        log::debug!(target: REQUEST_EVENT, "{req:?}");
+        let timer = self.telemetry.start(&req);
+
+        // Resolves against the document in memory, or else on disk, without
+        // retargeting the entry of the compiler.
+        let mem_file = self.primary.memory_changes.get(req.path.as_path());
+        let content = mem_file.map(|mem_file| mem_file.content.clone());
+        let position_encoding = self.const_config.position_encoding;
+        Box::pin(async move {
+            let _timer = timer;
+            let source = match content {
+                Some(source) => source,
+                None => {
+                    let path = req.path.clone();
+                    let text = tokio::task::spawn_blocking(move || std::fs::read_to_string(path))
+                        .await
+                        .map_err(|err| internal_error(format!("cannot read file: {err}")))?
+                        .map_err(|err| internal_error(format!("cannot read file: {err}")))?;
+                    Source::detached(text)
+                }
+            };
+            Ok(req.request(&source, position_encoding))
+        })
+    }
+
     fn execute_command(&mut self, params: ExecuteCommandParams) -> ResponseFuture<ExecuteCommand> {
         let cmd = params.command;
+        log::debug!(target: REQUEST_EVENT, "execute command {cmd}");
         let Some(handler) = self.exec_cmds.get(cmd.as_str()) else {
             return resp!(Err(method_not_found(format!("unknown command: {cmd}"))));
         };
-        handler(self, params.arguments)
+        let token = params.work_done_progress_params.work_done_token;
+        self.pending_progress = token.map(|token| self.progress.begin(token));
+        let res = handler(self, params.arguments);
+        // The progress is over with the command if it is not cancelable.
+        self.pending_progress = None;
+        res
     }
 }
@@ -1,35 +1,92 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use lsp_types::TextDocumentIdentifier;
+use anyhow::bail;
+use base64::Engine;
+use lsp_types::request::WorkDoneProgressCreate;
+use lsp_types::{Range as LspRange, TextDocumentIdentifier, WorkDoneProgressCreateParams};
 use serde::{Deserialize, Serialize};
 use serde_json::to_value;
-use tinymist_query::{self as q, url_to_path};
+use tinymist_query::{self as q, url_to_path, SemanticRequest};
 use typst::diag::StrResult;
 use typst::syntax::package::{PackageSpec, VersionlessPackageSpec};
+use typst::util::Deferred;
 use typst_ts_compiler::service::Compiler;
 use typst_ts_core::error::prelude::*;
+use typst_ts_core::FontResolver;
 
 use super::lsp::*;
+use super::progress::Progress;
 use super::*;
+use crate::actor::editor::EditorRequest;
+use crate::logging::REQUEST_EVENT;
+use crate::state::normalize_path;
+use crate::tools::diff::diff_preview;
+use crate::tools::glyph_coverage::glyph_coverage;
 use crate::tools::package::InitTask;
 use crate::tools::package::{self, determine_latest_version, TemplateSource};
+use crate::tools::selection::{export_selection, SelectionFormat};
+use crate::tools::units::{convert_units, LengthUnit};
 
 impl LanguageState {
     #[rustfmt::skip]
     pub fn get_exec_cmds() -> ExecCmdMap<Self> {
         HashMap::from_iter([
             ("tinymist.exportPdf", Self::export_pdf as _),
+            ("tinymist.exportPdfTagged", Self::export_pdf_tagged as _),
             ("tinymist.exportSvg", Self::export_svg as _),
+            ("tinymist.exportSvgWithFonts", Self::export_svg_with_fonts as _),
             ("tinymist.exportPng", Self::export_png as _),
+            ("tinymist.exportContactSheet", Self::export_contact_sheet as _),
+            ("tinymist.exportPptx", Self::export_pptx as _),
+            ("tinymist.exportDocxViaPandoc", Self::export_docx_via_pandoc as _),
+            ("tinymist.exportEpub", Self::export_epub as _),
+            ("tinymist.exportZip", Self::export_zip as _),
+            ("tinymist.exportAnimatedSvg", Self::export_animated_svg as _),
+            ("tinymist.exportWithWatermark", Self::export_with_watermark as _),
+            ("tinymist.exportSplitPdf", Self::export_split_pdf as _),
+            ("tinymist.exportRangeOfDocument", Self::export_range_of_document as _),
+            ("tinymist.exportDiffReport", Self::export_diff_report as _),
+            ("tinymist.exportSeries", Self::export_series as _),
+            ("tinymist.exportIncludeTree", Self::export_include_tree as _),
+            ("tinymist.renderVariant", Self::render_variant as _),
+            ("tinymist.verifyReproducible", Self::verify_reproducible as _),
+            ("tinymist.exportManifest", Self::export_manifest as _),
+            ("tinymist.repairEntry", Self::repair_entry as _),
+            ("tinymist.benchmarkDocument", Self::benchmark_document as _),
+            ("tinymist.setPageRange", Self::set_page_range as _),
+            ("tinymist.setExportOnSavePattern", Self::set_export_on_save_pattern as _),
+            ("tinymist.exportSelection", Self::export_selection as _),
+            ("tinymist.diffPreview", Self::diff_preview as _),
             ("tinymist.doClearCache", Self::clear_cache as _),
+            ("tinymist.recompileAll", Self::recompile_all as _),
+            ("tinymist.resetTelemetry", Self::reset_telemetry as _),
+            ("tinymist.getCompileLog", Self::get_compile_log as _),
+            ("tinymist.setTheme", Self::set_theme as _),
+            ("tinymist.setFontFallback", Self::set_font_fallback as _),
+            ("tinymist.toggleCjkMode", Self::toggle_cjk_mode as _),
+            ("tinymist.restartCompiler", Self::restart_compiler as _),
+            ("tinymist.previewServerUrl", Self::preview_server_url as _),
             ("tinymist.pinMain", Self::pin_document as _),
             ("tinymist.focusMain", Self::focus_document as _),
+            ("tinymist.normalizeEntry", Self::normalize_entry as _),
+            ("tinymist.getActiveEntry", Self::get_active_entry as _),
+            ("tinymist.listOpenEntries", Self::list_open_entries as _),
             ("tinymist.doInitTemplate", Self::init_template as _),
             ("tinymist.doGetTemplateEntry", Self::get_template_entry as _),
             ("tinymist.interactCodeContext", Self::interact_code_context as _),
             // ("tinymist.getDocumentTrace", Self::get_document_trace as _),
             ("tinymist.getDocumentMetrics", Self::get_document_metrics as _),
+            ("tinymist.validateLabels", Self::validate_labels as _),
+            ("tinymist.lintDocument", Self::lint_document as _),
+            ("tinymist.extractToFile", Self::extract_to_file as _),
+            ("tinymist.getDependencyGraph", Self::get_dependency_graph as _),
+            ("tinymist.reindexWorkspace", Self::reindex_workspace as _),
             ("tinymist.getServerInfo", Self::get_server_info as _),
+            ("tinymist.mirrorStatus", Self::mirror_status as _),
+            ("tinymist.explainDiagnostic", Self::explain_diagnostic as _),
+            ("tinymist.convertUnits", Self::convert_units as _),
+            ("tinymist.getGlyphCoverage", Self::get_glyph_coverage as _),
             ("tinymist.getResources", Self::get_resources as _),
         ])
     }
@@ -39,25 +96,327 @@ impl LanguageState {
         self.primary.export_pdf(args)
     }
 
+    /// Export the current document as a tagged PDF file.
+    pub fn export_pdf_tagged(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_pdf_tagged(args)
+    }
+
     /// Export the current document as a Svg file.
     pub fn export_svg(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
         self.primary.export_svg(args)
     }
 
+    /// Export the current document as a Svg file with selectable text.
+    pub fn export_svg_with_fonts(
+        &mut self,
+        args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_svg_with_fonts(args)
+    }
+
     /// Export the current document as a Png file.
     pub fn export_png(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
         self.primary.export_png(args)
     }
 
+    /// Export all pages of the current document tiled into a grid image.
+    pub fn export_contact_sheet(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_contact_sheet(args)
+    }
+
+    /// Export the pages of the current document as slides of a PPTX deck.
+    pub fn export_pptx(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_pptx(args)
+    }
+
+    /// Export the current document as a DOCX file via pandoc.
+    pub fn export_docx_via_pandoc(
+        &mut self,
+        args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_docx_via_pandoc(args)
+    }
+
+    /// Export the current document as an EPUB book.
+    pub fn export_epub(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_epub(args)
+    }
+
+    /// Bundle a document along with its dependencies into a zip archive.
+    pub fn export_zip(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_zip(args)
+    }
+
+    /// Export the current document compiled at several steps as an animated
+    /// SVG.
+    pub fn export_animated_svg(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_animated_svg(args)
+    }
+
+    /// Split the current document into PDFs of pages or page ranges, which is
+    /// cancelable by the client.
+    pub fn export_split_pdf(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let progress = self.pending_progress.take().map(|progress| {
+            let work_done = progress.work_done(self.client.clone());
+            (progress, work_done)
+        });
+        self.primary.export_split_pdf_with_progress(args, progress)
+    }
+
+    /// Export a region of a page of the current document by its position.
+    pub fn export_range_of_document(
+        &mut self,
+        args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_range_of_document(args)
+    }
+
+    /// Export the current document with a watermark stamped on each page.
+    pub fn export_with_watermark(
+        &mut self,
+        args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_with_watermark(args)
+    }
+
+    /// Export a report comparing the pages of two files side by side.
+    pub fn export_diff_report(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_diff_report(args)
+    }
+
+    /// Export a variant of a file as the entry for each set of inputs, which is
+    /// cancelable by the client.
+    pub fn export_series(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let progress = self.pending_progress.take().map(|progress| {
+            let work_done = progress.work_done(self.client.clone());
+            (progress, work_done)
+        });
+        self.primary.export_series_with_progress(args, progress)
+    }
+
+    /// Flatten a document and the local files it includes and imports into a
+    /// single file.
+    pub fn export_include_tree(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_include_tree(args)
+    }
+
+    /// Render the variant of a document by calling a function of it.
+    pub fn render_variant(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.render_variant(args)
+    }
+
+    /// Compile a file as the entry twice and compare the exports.
+    pub fn verify_reproducible(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.verify_reproducible(args)
+    }
+
+    /// Export the current document and write a manifest describing the export.
+    pub fn export_manifest(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.export_manifest(args)
+    }
+
+    /// Propose or apply a fix of the root directory for an entry outside of
+    /// it.
+    pub fn repair_entry(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let res = self.primary.repair_entry(args);
+        // The repair is applied again by later changes of the settings.
+        self.config.compile.root_path = self.primary.config.root_path.clone();
+        self.config.compile.root_repair = self.primary.config.root_repair.clone();
+        res
+    }
+
+    /// Time repeated compilations of a file as the entry.
+    pub fn benchmark_document(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.benchmark_document(args)
+    }
+
+    /// Restrict all subsequent exports of the entry to some pages.
+    pub fn set_page_range(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.set_page_range(args)
+    }
+
+    /// Restrict the exports on save to the files matching some globs.
+    pub fn set_export_on_save_pattern(
+        &mut self,
+        args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        self.primary.set_export_on_save_pattern(args)
+    }
+
+    /// Export the selected range of a document as a PNG or SVG image, which is
+    /// returned inline as base64 encoded bytes.
+    pub fn export_selection(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ExportSelectionParams {
+            path: PathBuf,
+            range: LspRange,
+            kind: String,
+        }
+        #[derive(Debug, Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ExportSelectionResult {
+            kind: String,
+            data: String,
+        }
+        let params = get_arg!(args[0] as ExportSelectionParams);
+        let Some(format) = SelectionFormat::from_name(&params.kind) else {
+            let msg = format!("unsupported kind: {}", params.kind);
+            return resp!(Err(invalid_params(msg)));
+        };
+
+        let fut = self.primary().steal_world(move |ctx| {
+            let source = ctx.source_by_path(&params.path)?;
+            let Some(range) = ctx.to_typst_range(params.range, &source) else {
+                bail!("invalid range: {:?}", params.range);
+            };
+            export_selection(ctx.world(), &source, range, format)
+        });
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(data)) => match to_value(ExportSelectionResult {
+                    kind: params.kind,
+                    data: base64::engine::general_purpose::STANDARD.encode(data),
+                }) {
+                    Ok(res) => Ok(Some(res)),
+                    Err(_) => Err(internal_error("cannot serialize selection")),
+                },
+                Ok(Err(err)) => Err(invalid_params(format!("cannot export selection: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot export selection: {err}"))),
+            }
+        })
+    }
+
+    /// Diff the rendered output of the saved and the current version of the
+    /// entry, returning the changed regions per page.
+    pub fn diff_preview(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let fut = self.primary().steal_world(move |ctx| {
+            let main = ctx.world().main();
+            let path = ctx.path_for_id(main.id())?;
+            let on_disk = std::fs::read_to_string(&path)?;
+            diff_preview(ctx.world(), on_disk)
+        });
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(diff)) => match to_value(diff) {
+                    Ok(res) => Ok(Some(res)),
+                    Err(_) => Err(internal_error("cannot serialize diff")),
+                },
+                Ok(Err(err)) => Err(invalid_params(format!("cannot diff preview: {err}"))),
+                Err(err) => Err(internal_error(format!("cannot diff preview: {err}"))),
+            }
+        })
+    }
+
     /// Clear all cached resources.
     pub fn clear_cache(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
         self.primary.clear_cache(Vec::new());
         for v in &mut self.dedicates {
             v.clear_cache(Vec::new());
         }
+        self.telemetry.reset();
         Box::pin(ready(Ok(Some(JsonValue::Null))))
     }
 
+    /// Evict all caches and compile the documents of all compilers again,
+    /// returning the results by group.
+    pub fn recompile_all(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let fonts = self.primary.font.clone();
+        let fonts = Deferred::new(move || {
+            let fonts = fonts.wait();
+            fonts.reload().unwrap_or_else(|err| {
+                log::error!("TypstActor: failed to reload fonts: {err}");
+                fonts.clone()
+            })
+        });
+        let futs: Vec<_> = std::iter::once(&mut self.primary)
+            .chain(&mut self.dedicates)
+            .map(|v| v.recompile(fonts.clone()))
+            .collect();
+        Box::pin(async move {
+            let mut summaries = Vec::with_capacity(futs.len());
+            for fut in futs {
+                match fut.await {
+                    Ok(summary) => summaries.push(summary),
+                    Err(err) => return Err(internal_error(format!("cannot recompile: {err}"))),
+                }
+            }
+            Ok(to_value(summaries).ok())
+        })
+    }
+
+    /// Start a preview server for the current entry, or get the running one.
+    pub fn preview_server_url(&mut self, args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.primary.preview_server_url(args)
+    }
+
+    /// Clear the statistics of handled requests.
+    pub fn reset_telemetry(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        self.telemetry.reset();
+        resp!(Ok(Some(JsonValue::Null)))
+    }
+
+    /// Get the latest compilations, oldest first.
+    pub fn get_compile_log(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        resp!(Ok(to_value(self.compile_log.snapshot()).ok()))
+    }
+
+    /// Set the preferred theme, `light` or `dark`, which styles the code and
+    /// the periscope preview in hover from the next request.
+    pub fn set_theme(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let theme = get_arg!(args[0] as Option<q::syntax::ColorTheme>);
+        self.config.compile.preferred_theme = theme;
+        self.primary.change_theme(theme);
+        for v in &mut self.dedicates {
+            v.change_theme(theme);
+        }
+        resp!(Ok(Some(JsonValue::Null)))
+    }
+
+    /// Set the font families tried in order before the default fallback, and
+    /// recompile the documents with them.
+    pub fn set_font_fallback(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        let families = get_arg!(args[0] as Vec<String>);
+        self.config.compile.font_fallback.clone_from(&families);
+        // Only the primary compiler records the unsaved buffers.
+        let snapshot = self.primary.vfs_snapshot();
+        for v in &mut self.dedicates {
+            v.change_font_fallback(families.clone(), snapshot.clone());
+        }
+        self.primary.change_font_fallback(families, snapshot);
+        resp!(Ok(Some(JsonValue::Null)))
+    }
+
+    /// Toggle the CJK mode, which affects the formatter and the word count, and
+    /// return whether it is on. Clients may persist it as the `cjkMode`
+    /// setting, otherwise it is kept until the setting is changed.
+    pub fn toggle_cjk_mode(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let cjk_mode = self.config.compile.toggle_cjk_mode();
+        self.primary.change_cjk_mode(cjk_mode);
+        for v in &mut self.dedicates {
+            v.change_cjk_mode(cjk_mode);
+        }
+        resp!(Ok(Some(JsonValue::Bool(cjk_mode))))
+    }
+
+    /// Restart the primary compiler, or all compilers if the first argument is
+    /// `true`, and return the new server info.
+    pub fn restart_compiler(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let all = get_arg_or_default!(args[0] as bool);
+        // Only the primary compiler records the unsaved buffers.
+        let snapshot = self.primary.vfs_snapshot();
+        if all {
+            for v in &mut self.dedicates {
+                v.restart_server(snapshot.clone());
+            }
+        }
+        self.primary.restart_server(snapshot);
+        self.get_server_info(Vec::new())
+    }
+
     /// Pin main file to some path.
     pub fn pin_document(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
         let entry = get_arg!(args[0] as Option<PathBuf>).map(Into::into);
@@ -85,6 +444,35 @@ impl LanguageState {
         })
     }
 
+    /// Get the normalized form of a path, or of the current entry if no path is
+    /// given, which is the key of the file in the server.
+    pub fn normalize_entry(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let path = get_arg!(args[0] as Option<PathBuf>);
+        let path = path.or_else(|| {
+            let entry = self.primary().entry();
+            let (main, root) = entry.main().zip(entry.root())?;
+            main.vpath().resolve(&root)
+        });
+        let Some(path) = path else {
+            return resp!(Err(invalid_params("no entry is set")));
+        };
+
+        let path = normalize_path(&path, &self.config.compile.roots);
+        resp!(Ok(to_value(path.as_ref()).ok()))
+    }
+
+    /// Get the entry of the primary compiler, and whether it is pinned, focused
+    /// or the default one.
+    pub fn get_active_entry(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        resp!(Ok(to_value(self.active_entry()).ok()))
+    }
+
+    /// List the documents opened in the client, along with the groups of the
+    /// compilers taking them as entries.
+    pub fn list_open_entries(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        resp!(Ok(to_value(self.open_entries()).ok()))
+    }
+
     /// Initialize a new template.
     pub fn init_template(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
         #[derive(Debug, Serialize)]
@@ -201,10 +589,222 @@ impl LanguageState {
         query_state!(self, req)
     }
 
+    /// Check the labels of the document, publishing the diagnostics of
+    /// duplicate labels and references to undefined labels along with the
+    /// diagnostics of the compilation. The diagnostics are also returned, keyed
+    /// by file.
+    pub fn validate_labels(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let path = get_arg!(args[0] as PathBuf);
+        let path = self.path_key(&path).to_path_buf();
+        if let Err(err) = self.update_entry(&path) {
+            return resp!(Err(internal_error(format!("cannot update entry: {err:?}"))));
+        }
+        // The diagnostics of the last check of the document are replaced.
+        let group = format!("labels:{}", path.display());
+        let req = q::ValidateLabelsRequest { path };
+        log::debug!(target: REQUEST_EVENT, "{req:?}");
+        let timer = self.telemetry.start(&req);
+        let editor_tx = self.primary.editor_tx.clone();
+        let fut = self.primary().steal_state(move |w, d| req.request(w, d));
+        Box::pin(async move {
+            let _timer = timer;
+            let diagnostics = fut.await.map_err(internal_error)?;
+            let _ = editor_tx.send(EditorRequest::Diag(group, diagnostics.clone()));
+            Ok(to_value(diagnostics).ok())
+        })
+    }
+
+    /// Lint the document and the files it includes or imports for style issues,
+    /// returning the findings keyed by file, e.g. to check documents in CI.
+    ///
+    /// The rules toggled by the optional second argument override `lintRules`.
+    pub fn lint_document(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let path = get_arg!(args[0] as PathBuf);
+        let overrides = get_arg_or_default!(args[1] as q::LintRules);
+        let mut rules = self.config.compile.lint_rules.clone();
+        rules.extend(overrides);
+        let req = q::LintDocumentRequest {
+            path: path.into(),
+            rules,
+        };
+        query_world!(self, req)
+    }
+
+    /// Move the selected top-level nodes of a document into a new file, and
+    /// return the edit creating the file and including it in place of the
+    /// selection.
+    pub fn extract_to_file(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ExtractToFileParams {
+            path: PathBuf,
+            range: LspRange,
+            new_file: PathBuf,
+        }
+        let params = get_arg!(args[0] as ExtractToFileParams);
+        let req = q::ExtractToFileRequest {
+            path: params.path,
+            range: params.range,
+            new_file: params.new_file,
+        };
+
+        if let Err(err) = self.update_entry(&req.path) {
+            return resp!(Err(internal_error(format!("cannot update entry: {err:?}"))));
+        }
+        let fut = self.primary().steal_world(move |ctx| req.extract(ctx));
+        Box::pin(async move {
+            match fut.await {
+                Ok(Ok(edit)) => Ok(to_value(edit).ok()),
+                Ok(Err(err)) => Err(invalid_params(err)),
+                Err(err) => Err(internal_error(err)),
+            }
+        })
+    }
+
+    /// Get the graph of the files imported or included by the document, with
+    /// packages as leaf nodes.
+    pub fn get_dependency_graph(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        let path = get_arg!(args[0] as PathBuf);
+        let req = q::DependencyGraphRequest { path: path.into() };
+        query_world!(self, req)
+    }
+
+    /// Rebuild the index of the workspace from scratch on the compiler thread,
+    /// e.g. after files are changed without notifications to the server. The
+    /// progress is created by the server if the client doesn't pass a token.
+    pub fn reindex_workspace(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        let mut progress = self.pending_progress.take();
+        let created = (progress.is_none() && self.const_config.work_done_progress)
+            .then(|| self.progress.create("reindexWorkspace"));
+        let client = self.client.clone();
+        let primary = self.primary().clone();
+        Box::pin(async move {
+            if let Some(created) = created {
+                let params = WorkDoneProgressCreateParams {
+                    token: created.token().clone(),
+                };
+                match client.request::<WorkDoneProgressCreate>(params).await {
+                    Ok(()) => progress = Some(created),
+                    Err(err) => log::warn!("failed to create the progress of reindexing: {err}"),
+                }
+            }
+
+            let work_done = progress.as_ref().map(|p| p.work_done(client));
+            if let Some(work_done) = &work_done {
+                work_done.begin("Indexing workspace");
+            }
+            let req = q::ReindexWorkspaceRequest {
+                cancel: progress.as_ref().map(Progress::cancel_flag),
+                progress: work_done.clone().map(|work_done| {
+                    q::IndexProgress(Arc::new(move |done, total| {
+                        let percentage = (done * 100 / total) as u32;
+                        work_done.report(format!("{done}/{total} files"), percentage);
+                    }))
+                }),
+            };
+            let res = primary.steal_world(move |ctx| req.request(ctx)).await;
+            if let Some(work_done) = work_done {
+                work_done.end(None);
+            }
+
+            match res {
+                Ok(stats) => Ok(to_value(stats).ok()),
+                Err(err) => Err(internal_error(format!("cannot reindex workspace: {err}"))),
+            }
+        })
+    }
+
+    /// Get the progress of recording or replaying the input, e.g. to wait for a
+    /// replay to complete.
+    pub fn mirror_status(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        resp!(Ok(to_value(crate::io::mirror_status()).ok()))
+    }
+
     /// Get the server info.
     pub fn get_server_info(&mut self, _args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
-        self.primary().collect_server_info();
-        todo!("make collect_server_info async")
+        let fut = self.primary().collect_server_info();
+        let primary = self.primary().diag_group.clone();
+        let requests = self.telemetry.snapshot();
+        Box::pin(async move {
+            match fut.await {
+                Ok(mut res) => {
+                    if let Some(info) = res.get_mut(&primary) {
+                        info.requests = requests;
+                    }
+                    match to_value(res) {
+                        Ok(res) => Ok(Some(res)),
+                        Err(err) => Err(internal_error(format!(
+                            "cannot serialize server info: {err}"
+                        ))),
+                    }
+                }
+                Err(err) => Err(internal_error(format!("cannot collect server info: {err}"))),
+            }
+        })
+    }
+
+    /// Explain a diagnostic by its code or message, returning markdown.
+    pub fn explain_diagnostic(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        struct ExplainDiagnosticParams {
+            code: Option<String>,
+            message: Option<String>,
+        }
+        let params = get_arg!(args[0] as ExplainDiagnosticParams);
+        let help = q::explain_diagnostic(params.code.as_deref(), params.message.as_deref());
+        resp!(Ok(Some(JsonValue::String(help))))
+    }
+
+    /// Convert a length between `pt`, `mm`, `cm`, `in` and `em`, with the
+    /// `fontSize` in points to resolve `em`.
+    pub fn convert_units(&mut self, mut args: Vec<JsonValue>) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ConvertUnitsParams {
+            value: f64,
+            from: String,
+            to: String,
+            font_size: Option<f64>,
+        }
+        let params = get_arg!(args[0] as ConvertUnitsParams);
+        let converted = (|| {
+            let from: LengthUnit = params.from.parse()?;
+            let to: LengthUnit = params.to.parse()?;
+            convert_units(params.value, from, to, params.font_size)
+        })();
+        match converted {
+            Ok(value) => {
+                let res = serde_json::json!({ "value": value, "unit": params.to });
+                resp!(Ok(Some(res)))
+            }
+            Err(err) => resp!(Err(invalid_params(format!("cannot convert units: {err}")))),
+        }
+    }
+
+    /// Report the characters of `text` covered by the font `family`, or the
+    /// default font if absent, and the families the others fall back to.
+    pub fn get_glyph_coverage(
+        &mut self,
+        mut args: Vec<JsonValue>,
+    ) -> ResponseFuture<ExecuteCommand> {
+        #[derive(Debug, Clone, Deserialize)]
+        struct GlyphCoverageParams {
+            text: String,
+            family: Option<String>,
+        }
+        let params = get_arg!(args[0] as GlyphCoverageParams);
+        let font = (self.primary.font.wait()).with_fallback(&self.config.compile.font_fallback);
+        let coverage = glyph_coverage(font.font_book(), &params.text, params.family.as_deref());
+        match coverage {
+            Ok(coverage) => resp!(Ok(Some(to_value(coverage).unwrap()))),
+            Err(err) => resp!(Err(invalid_params(format!("cannot check coverage: {err}")))),
+        }
     }
 
     // Get static resources with help of tinymist service, for example, a
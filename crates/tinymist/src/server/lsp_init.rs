@@ -1,12 +1,14 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use anyhow::bail;
 use itertools::Itertools;
+use log::LevelFilter;
 use lsp_types::request::*;
 use lsp_types::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value as JsonValue};
-use tinymist_query::{get_semantic_tokens_options, PositionEncoding};
+use tinymist_query::{get_semantic_tokens_options, PositionEncoding, ReferencesScope};
 use tokio::sync::mpsc;
 use typst::util::Deferred;
 use typst_ts_core::ImmutPath;
@@ -14,7 +16,9 @@ use typst_ts_core::ImmutPath;
 use super::compile_init::*;
 use super::lsp::*;
 use super::*;
-use crate::actor::editor::EditorActor;
+use crate::actor::editor::{EditorActor, EditorConfig, EditorRequest};
+use crate::telemetry::DEFAULT_COMPILE_LOG_SIZE;
+use crate::tools::recovery::RecoveryStore;
 use crate::world::{ImmutDict, SharedFontResolver};
 
 // todo: svelte-language-server responds to a Goto Definition request with
@@ -34,8 +38,156 @@ pub enum FormatterMode {
     Typstfmt,
 }
 
+macro_rules! lsp_features {
+    ($($(#[$attr:meta])* $field:ident: $request:ty => $provider:ident, $client:ident;)*) => {
+        /// The language features toggled by the `features` setting, which are
+        /// all enabled by default.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+        #[serde(rename_all = "camelCase", default)]
+        pub struct LspFeatures {
+            $($(#[$attr])* pub $field: bool,)*
+        }
+
+        impl Default for LspFeatures {
+            fn default() -> Self {
+                Self::all(true)
+            }
+        }
+
+        impl LspFeatures {
+            /// Gets the features all enabled or all disabled.
+            pub fn all(enabled: bool) -> Self {
+                Self {
+                    $($field: enabled,)*
+                }
+            }
+
+            /// Gets the features supporting dynamic registration by the client.
+            fn dynamic_registration(doc: Option<&TextDocumentClientCapabilities>) -> Self {
+                Self {
+                    $($field: try_or(|| doc?.$client.as_ref()?.dynamic_registration, false),)*
+                }
+            }
+
+            /// Combines the features with others field by field.
+            fn zip_with(&self, other: &Self, f: impl Fn(bool, bool) -> bool) -> Self {
+                Self {
+                    $($field: f(self.$field, other.$field),)*
+                }
+            }
+
+            /// Gets the methods of the enabled features.
+            fn methods(&self) -> Vec<&'static str> {
+                let methods = [$((self.$field, <$request>::METHOD),)*];
+                methods.into_iter().filter_map(|(enabled, method)| enabled.then_some(method)).collect()
+            }
+
+            /// Removes the capabilities of the disabled features.
+            pub fn retain_capabilities(&self, caps: &mut ServerCapabilities) {
+                $(if !self.$field {
+                    caps.$provider = None;
+                })*
+            }
+
+            /// Gets the options to register the feature of the method with
+            /// dynamically, which are the ones advertised statically.
+            pub fn register_options(method: &str, caps: &ServerCapabilities) -> Option<JsonValue> {
+                $(if method == <$request>::METHOD {
+                    return Some(provider_register_options(&caps.$provider));
+                })*
+                None
+            }
+        }
+    };
+}
+
+/// Converts the options of a provider to the options registering it, which
+/// apply to the documents selected by the client.
+fn provider_register_options(provider: &impl Serialize) -> JsonValue {
+    let mut options = match serde_json::to_value(provider) {
+        Ok(JsonValue::Object(options)) => options,
+        _ => Map::new(),
+    };
+    options.insert("documentSelector".to_owned(), JsonValue::Null);
+    JsonValue::Object(options)
+}
+
+/// Gets the capabilities of the features toggled by the `features` setting.
+fn feature_capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+            retrigger_characters: None,
+            ..Default::default()
+        }),
+        color_provider: Some(ColorProviderCapability::Simple(true)),
+        document_link_provider: Some(DocumentLinkOptions {
+            resolve_provider: Some(false),
+            work_done_progress_options: Default::default(),
+        }),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        inlay_hint_provider: Some(OneOf::Left(true)),
+        inline_value_provider: Some(OneOf::Left(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        ..Default::default()
+    }
+}
+
+lsp_features! {
+    /// Whether to provide hover.
+    hover: HoverRequest => hover_provider, hover;
+    /// Whether to provide signature help.
+    signature_help: SignatureHelpRequest => signature_help_provider, signature_help;
+    /// Whether to provide inlay hints.
+    inlay_hints: InlayHintRequest => inlay_hint_provider, inlay_hint;
+    /// Whether to provide code lens.
+    code_lens: CodeLensRequest => code_lens_provider, code_lens;
+    /// Whether to provide code actions.
+    code_action: CodeActionRequest => code_action_provider, code_action;
+    /// Whether to provide document colors.
+    document_color: DocumentColor => color_provider, color_provider;
+    /// Whether to provide document links.
+    document_link: DocumentLinkRequest => document_link_provider, document_link;
+    /// Whether to provide document symbols.
+    document_symbol: DocumentSymbolRequest => document_symbol_provider, document_symbol;
+    /// Whether to provide folding ranges.
+    folding_range: FoldingRangeRequest => folding_range_provider, folding_range;
+    /// Whether to provide selection ranges.
+    selection_range: SelectionRangeRequest => selection_range_provider, selection_range;
+    /// Whether to provide inline values.
+    inline_value: InlineValueRequest => inline_value_provider, inline_value;
+}
+
+impl LspFeatures {
+    /// Gets the features advertised statically in the server capabilities,
+    /// which are enabled but not registered dynamically.
+    pub fn static_features(&self, dynamic: &Self) -> Self {
+        self.zip_with(dynamic, |enabled, dynamic| enabled && !dynamic)
+    }
+
+    /// Gets the methods of the features to register and to unregister
+    /// dynamically after the features change from `old`.
+    pub fn changed_methods(
+        &self,
+        old: &Self,
+        dynamic: &Self,
+    ) -> (Vec<&'static str>, Vec<&'static str>) {
+        let new = self.zip_with(dynamic, |enabled, dynamic| enabled && dynamic);
+        let old = old.zip_with(dynamic, |enabled, dynamic| enabled && dynamic);
+        let register = new.zip_with(&old, |new, old| new && !old);
+        let unregister = old.zip_with(&new, |old, new| old && !new);
+        (register.methods(), unregister.methods())
+    }
+}
+
 /// The mode of PDF/SVG/PNG export.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum ExportMode {
     #[default]
@@ -51,6 +203,32 @@ pub enum ExportMode {
     OnDocumentHasTitle,
 }
 
+/// The output path patterns for some kinds of export, which override the
+/// pattern shared by all kinds, e.g. to put PDFs in `dist/` and PNGs in
+/// `previews/`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct OutputPathByKind {
+    pub pdf: Option<String>,
+    pub svg: Option<String>,
+    pub png: Option<String>,
+    pub html: Option<String>,
+}
+
+impl OutputPathByKind {
+    /// Get the pattern for exports with the extension, falling back to the
+    /// shared pattern.
+    pub fn pattern<'a>(&'a self, extension: &str, shared: &'a str) -> &'a str {
+        let pattern = match extension {
+            "pdf" => &self.pdf,
+            "svg" => &self.svg,
+            "png" => &self.png,
+            "html" => &self.html,
+            _ => &None,
+        };
+        pattern.as_deref().unwrap_or(shared)
+    }
+}
+
 /// The mode of semantic tokens.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -74,19 +252,180 @@ pub struct CompileExtraOpts {
     pub font_paths: Vec<PathBuf>,
 }
 
-const CONFIG_ITEMS: &[&str] = &[
-    "outputPath",
-    "exportPdf",
-    "rootPath",
-    "semanticTokens",
-    "formatterMode",
-    "formatterPrintWidth",
-    "typstExtraArgs",
-    "compileStatus",
-    "preferredTheme",
-    "hoverPeriscope",
+/// The project file that is read from workspace roots.
+pub const PROJECT_FILE: &str = "tinymist.toml";
+
+/// The project configuration read from a `tinymist.toml` file, which can be
+/// committed to share configuration among developers of a project.
+///
+/// Editor settings take precedence over the project configuration.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectConfig {
+    /// The entry file compiled by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry: Option<PathBuf>,
+    /// The root directory for compilation routine.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+    /// Additional input arguments visible through `sys.inputs`.
+    #[serde(default)]
+    pub inputs: BTreeMap<String, String>,
+    /// Additional font paths.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub font_paths: Vec<PathBuf>,
+    /// The mode of PDF export.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub export_pdf: Option<ExportMode>,
+    /// The output path pattern for PDF export.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_path: Option<String>,
+}
+
+impl ProjectConfig {
+    /// Loads the project configuration from the first workspace root that
+    /// contains a project file.
+    ///
+    /// A malformed project file is ignored with a warning.
+    pub fn load(roots: &[PathBuf]) -> Option<Self> {
+        roots.iter().find_map(|root| {
+            let path = root.join(PROJECT_FILE);
+            let content = std::fs::read_to_string(&path).ok()?;
+            match Self::parse(&content, root) {
+                Ok(config) => {
+                    log::info!("loaded project config from {path:?}: {config:?}");
+                    Some(config)
+                }
+                Err(err) => {
+                    log::warn!("ignored malformed project config {path:?}: {err}");
+                    None
+                }
+            }
+        })
+    }
+
+    /// Parses the project configuration. Relative paths are resolved against
+    /// the directory containing the project file.
+    pub fn parse(content: &str, dir: &Path) -> anyhow::Result<Self> {
+        let mut config: Self = toml::from_str(content)?;
+        if let Some(entry) = config.entry.as_mut() {
+            *entry = dir.join(&entry);
+        }
+        if let Some(root) = config.root.as_mut() {
+            *root = dir.join(&root);
+        }
+        for font_path in config.font_paths.iter_mut() {
+            *font_path = dir.join(&font_path);
+        }
+        Ok(config)
+    }
+
+    /// Merges the editor settings over the project configuration.
+    ///
+    /// The inputs and font paths of the project are passed before the
+    /// `typstExtraArgs` of the editor, whose inputs override the ones of the
+    /// same keys. The entry of the project is only passed without the
+    /// arguments of the editor, which may have an entry as well.
+    pub fn merge_under(&self, update: &Map<String, JsonValue>) -> Map<String, JsonValue> {
+        let mut merged = Map::new();
+        if let Some(root) = &self.root {
+            merged.insert("rootPath".into(), root.to_string_lossy().into());
+        }
+        if let Some(export_pdf) = self.export_pdf {
+            merged.insert(
+                "exportPdf".into(),
+                serde_json::to_value(export_pdf).unwrap(),
+            );
+        }
+        if let Some(output_path) = &self.output_path {
+            merged.insert("outputPath".into(), output_path.as_str().into());
+        }
+
+        let inputs = (self.inputs.iter()).flat_map(|(k, v)| ["--input".into(), format!("{k}={v}")]);
+        let font_paths = (self.font_paths.iter())
+            .flat_map(|p| ["--font-path".into(), p.to_string_lossy().into_owned()]);
+        let entry = (self.entry.iter()).map(|entry| entry.to_string_lossy().into_owned());
+        let editor_args = update.get("typstExtraArgs").and_then(JsonValue::as_array);
+        let extra_args = match editor_args {
+            Some(editor_args) => (inputs.chain(font_paths).map(JsonValue::from))
+                .chain(editor_args.iter().cloned())
+                .collect::<Vec<_>>(),
+            None => (entry.chain(inputs).chain(font_paths).map(JsonValue::from)).collect(),
+        };
+        if !extra_args.is_empty() {
+            merged.insert("typstExtraArgs".into(), extra_args.into());
+        }
+
+        for (k, v) in update {
+            if !v.is_null() && !(k == "typstExtraArgs" && editor_args.is_some()) {
+                merged.insert(k.clone(), v.clone());
+            }
+        }
+        merged
+    }
+}
+
+/// The settings read from the editor, with the types of their values, which
+/// are checked if `strictConfig` is enabled. `null` is accepted for all
+/// settings, as clients send it for the unset ones.
+const CONFIG_ITEMS: &[(&str, &[&str])] = &[
+    ("outputPath", &["string", "object"]),
+    ("exportPdf", &["string"]),
+    ("rootPath", &["string"]),
+    ("semanticTokens", &["string"]),
+    ("formatterMode", &["string"]),
+    ("formatterPrintWidth", &["number"]),
+    ("typstExtraArgs", &["array"]),
+    ("compileStatus", &["string"]),
+    ("preferredTheme", &["string"]),
+    ("pandocPath", &["string"]),
+    ("indexIgnore", &["array"]),
+    ("hoverPeriscope", &["string", "object"]),
+    ("hoverMathPreview", &["boolean"]),
+    ("hoverSetRules", &["boolean"]),
+    ("inlayHints", &["object"]),
+    ("lintRules", &["object"]),
+    ("fontFallback", &["array"]),
+    ("exportOnSaveGlob", &["array"]),
+    ("persistentCache", &["boolean"]),
+    ("persistentCacheLimit", &["number"]),
+    ("cjkMode", &["boolean"]),
+    ("userSnippets", &["array"]),
+    ("maxDocumentBytes", &["number"]),
+    ("compileLogSize", &["number"]),
+    ("crashRecovery", &["boolean"]),
+    ("logLevel", &["string"]),
+    ("diagnosticSource", &["string"]),
+    ("features", &["object"]),
+    ("compileTimeoutMs", &["number"]),
+    ("referencesScope", &["string"]),
+    ("strictConfig", &["boolean"]),
 ];
 
+/// The settings handled by the clients rather than the server, which are sent
+/// along with the server settings, e.g. `trace.server`.
+const CLIENT_ITEMS: &[&str] = &["serverPath", "systemFonts", "fontPaths", "trace"];
+
+/// Gets the names of the settings read from the editor.
+fn config_items() -> impl Iterator<Item = &'static str> {
+    CONFIG_ITEMS.iter().map(|(item, _)| *item)
+}
+
+/// Gets the type of a JSON value, named as in JSON schemas.
+fn json_type(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "boolean",
+        JsonValue::Number(_) => "number",
+        JsonValue::String(_) => "string",
+        JsonValue::Array(_) => "array",
+        JsonValue::Object(_) => "object",
+    }
+}
+
+/// The default maximum size of a document kept in memory, 16 MiB.
+pub const DEFAULT_MAX_DOCUMENT_BYTES: usize = 16 * 1024 * 1024;
+
 /// The user configuration read from the editor.
 #[derive(Debug, Default, Clone)]
 pub struct LanguageConfig {
@@ -100,23 +439,71 @@ pub struct LanguageConfig {
     pub formatter: FormatterMode,
     /// Dynamic configuration for the experimental formatter.
     pub formatter_print_width: u32,
+    /// The files searched for references.
+    pub references_scope: ReferencesScope,
+    /// The maximum size of a document kept in memory, in bytes.
+    pub max_document_bytes: Option<usize>,
+    /// The number of latest compilations kept in the compile log.
+    pub compile_log_size: Option<usize>,
+    /// Whether to save the unsaved buffers to restore them after a crash.
+    pub crash_recovery: bool,
+    /// The maximum level of records logged.
+    pub log_level: Option<LevelFilter>,
+    /// The language features to provide.
+    pub features: LspFeatures,
+    /// Whether to warn about unrecognized settings and settings of unexpected
+    /// types.
+    pub strict_config: bool,
+    /// The project configuration read from the workspace.
+    pub project: Option<ProjectConfig>,
 }
 
 impl LanguageConfig {
-    /// Gets items for serialization.
-    pub fn get_items() -> Vec<ConfigurationItem> {
-        let sections = CONFIG_ITEMS
-            .iter()
-            .flat_map(|item| [format!("tinymist.{item}"), item.to_string()]);
+    /// Gets items for serialization, scoped to a resource if given, so that
+    /// clients answer with the settings of the folder containing it.
+    pub fn get_items(scope_uri: Option<&Url>) -> Vec<ConfigurationItem> {
+        let sections =
+            config_items().flat_map(|item| [format!("tinymist.{item}"), item.to_string()]);
 
         sections
             .map(|section| ConfigurationItem {
+                scope_uri: scope_uri.cloned(),
                 section: Some(section),
-                ..Default::default()
             })
             .collect()
     }
 
+    /// Gets the compile configuration of an entry from the values of the items
+    /// scoped to it. The project configuration still applies under them.
+    ///
+    /// # Errors
+    /// Errors if the values are invalid.
+    pub fn scoped_compile(&self, values: Vec<JsonValue>) -> anyhow::Result<CompileConfig> {
+        let update = self.with_project(&Self::values_to_map(values));
+        let mut compile = self.compile.clone();
+        compile.update_by_map(&update)?;
+        Ok(compile)
+    }
+
+    /// Merges the editor settings over the project configuration, if any.
+    fn with_project(&self, update: &Map<String, JsonValue>) -> Map<String, JsonValue> {
+        match &self.project {
+            Some(project) => project.merge_under(update),
+            None => update.clone(),
+        }
+    }
+
+    /// Gets the maximum size of a document kept in memory, in bytes.
+    pub fn max_document_bytes(&self) -> usize {
+        self.max_document_bytes
+            .unwrap_or(DEFAULT_MAX_DOCUMENT_BYTES)
+    }
+
+    /// Gets the number of latest compilations kept in the compile log.
+    pub fn compile_log_size(&self) -> usize {
+        self.compile_log_size.unwrap_or(DEFAULT_COMPILE_LOG_SIZE)
+    }
+
     /// Converts values to a map.
     pub fn values_to_map(values: Vec<JsonValue>) -> Map<String, JsonValue> {
         let unpaired_values = values
@@ -124,13 +511,43 @@ impl LanguageConfig {
             .tuples()
             .map(|(a, b)| if !a.is_null() { a } else { b });
 
-        CONFIG_ITEMS
-            .iter()
+        config_items()
             .map(|item| item.to_string())
             .zip(unpaired_values)
             .collect()
     }
 
+    /// Checks the settings in a JSON object if `strictConfig` is enabled,
+    /// returning a warning listing the unrecognized settings and the settings
+    /// of unexpected types, if any. The settings are still applied regardless.
+    pub fn strict_warning(&self, update: &JsonValue) -> Option<String> {
+        if !self.strict_config {
+            return None;
+        }
+        let JsonValue::Object(update) = update else {
+            return None;
+        };
+
+        let mut problems = vec![];
+        for (key, value) in update {
+            let item = key.strip_prefix("tinymist.").unwrap_or(key);
+            let head = item.split('.').next().unwrap_or(item);
+            if CLIENT_ITEMS.contains(&head) {
+                continue;
+            }
+            let Some((_, types)) = CONFIG_ITEMS.iter().find(|(name, _)| *name == item) else {
+                problems.push(format!("unknown setting `{key}`"));
+                continue;
+            };
+            let ty = json_type(value);
+            if !value.is_null() && !types.contains(&ty) {
+                let expected = types.join(" or ");
+                problems.push(format!("`{key}` expects {expected} but got {ty}"));
+            }
+        }
+        (!problems.is_empty()).then(|| format!("Invalid settings: {}", problems.join("; ")))
+    }
+
     /// Updates the configuration with a JSON object.
     ///
     /// # Errors
@@ -148,12 +565,28 @@ impl LanguageConfig {
     /// # Errors
     /// Errors if the update is invalid.
     pub fn update_by_map(&mut self, update: &Map<String, JsonValue>) -> anyhow::Result<()> {
+        let update = &self.with_project(update);
+
         try_(|| SemanticTokensMode::deserialize(update.get("semanticTokens")?).ok())
             .inspect(|v| self.semantic_tokens = *v);
         try_(|| FormatterMode::deserialize(update.get("formatterMode")?).ok())
             .inspect(|v| self.formatter = *v);
         try_(|| u32::deserialize(update.get("formatterPrintWidth")?).ok())
             .inspect(|v| self.formatter_print_width = *v);
+        try_(|| ReferencesScope::deserialize(update.get("referencesScope")?).ok())
+            .inspect(|v| self.references_scope = *v);
+        self.max_document_bytes = try_(|| usize::deserialize(update.get("maxDocumentBytes")?).ok());
+        self.compile_log_size = try_(|| usize::deserialize(update.get("compileLogSize")?).ok());
+        self.crash_recovery = try_(|| update.get("crashRecovery")?.as_bool()).unwrap_or_default();
+        self.log_level = try_(|| update.get("logLevel")?.as_str()?.parse().ok());
+        self.strict_config = try_(|| update.get("strictConfig")?.as_bool()).unwrap_or_default();
+        self.features = match update.get("features") {
+            Some(features) => match LspFeatures::deserialize(features) {
+                Ok(features) => features,
+                Err(e) => bail!("failed to parse features: {e}"),
+            },
+            None => LspFeatures::default(),
+        };
         self.compile.update_by_map(update)?;
         self.compile.validate()
     }
@@ -177,8 +610,26 @@ pub struct ConstLanguageConfig {
     pub doc_line_folding_only: bool,
     /// Allow dynamic registration of document formatting.
     pub doc_fmt_dynamic_registration: bool,
+    /// Allow dynamic registration of the features toggled by the `features`
+    /// setting.
+    pub features_dynamic_registration: LspFeatures,
+    /// Allow resolving locations of workspace symbols lazily.
+    pub ws_symbol_resolve: bool,
+    /// Allow the progresses created by the server.
+    pub work_done_progress: bool,
+    /// Accept the detailed `$/typst/compileStatus` notifications, opted in by
+    /// the `typstCompileStatus` experimental capability.
+    pub compile_status_detail: bool,
+    /// Allow the HTML tags highlighting the code in markdown, i.e. the client
+    /// lists them in `general.markdown.allowedTags`.
+    pub markdown_html: bool,
+    /// Allow the images in markdown, i.e. the client renders markdown in hover.
+    pub markdown_images: bool,
 }
 
+/// The HTML tags emitted by highlighting the code in markdown.
+const HIGHLIGHT_TAGS: &[&str] = &["pre", "code", "span"];
+
 impl From<&InitializeParams> for ConstLanguageConfig {
     fn from(params: &InitializeParams) -> Self {
         const DEFAULT_ENCODING: &[PositionEncodingKind] = &[PositionEncodingKind::UTF16];
@@ -198,6 +649,15 @@ impl From<&InitializeParams> for ConstLanguageConfig {
         let sema = try_(|| doc?.semantic_tokens.as_ref());
         let fold = try_(|| doc?.folding_range.as_ref());
         let format = try_(|| doc?.formatting.as_ref());
+        let hover_format = try_(|| doc?.hover.as_ref()?.content_format.as_ref());
+        let ws_symbol = try_(|| workspace?.symbol.as_ref());
+        let ws_symbol_resolve = try_(|| Some(&ws_symbol?.resolve_support.as_ref()?.properties));
+        let experimental = params.capabilities.experimental.as_ref();
+        let status_detail = try_(|| experimental?.get("typstCompileStatus")?.as_bool());
+        let window = params.capabilities.window.as_ref();
+        let general = params.capabilities.general.as_ref();
+        let allowed_tags = try_(|| general?.markdown.as_ref()?.allowed_tags.as_ref());
+        let allows = |tag: &&str| allowed_tags.is_some_and(|tags| tags.iter().any(|t| t == tag));
 
         Self {
             position_encoding,
@@ -207,6 +667,13 @@ impl From<&InitializeParams> for ConstLanguageConfig {
             tokens_multiline_token_support: try_or(|| sema?.multiline_token_support, false),
             doc_line_folding_only: try_or(|| fold?.line_folding_only, true),
             doc_fmt_dynamic_registration: try_or(|| format?.dynamic_registration, false),
+            features_dynamic_registration: LspFeatures::dynamic_registration(doc),
+            ws_symbol_resolve: ws_symbol_resolve
+                .is_some_and(|props| props.iter().any(|p| p == "location.range")),
+            work_done_progress: try_or(|| window?.work_done_progress, false),
+            compile_status_detail: status_detail.unwrap_or(false),
+            markdown_html: HIGHLIGHT_TAGS.iter().all(allows),
+            markdown_images: hover_format.is_some_and(|f| f.contains(&MarkupKind::Markdown)),
         }
     }
 }
@@ -237,9 +704,15 @@ impl LanguageState {
             },
             ..LanguageConfig::default()
         };
+        config.project = ProjectConfig::load(&config.compile.roots);
+        let mut config_warning = None;
         if let Some(init) = &params.initialization_options {
             config.update(init).or_else(invalid_params)?;
+            config_warning = config.strict_warning(init);
         };
+        if config.log_level.is_some() {
+            crate::logging::set_level(config.log_level);
+        }
 
         // Prepare fonts.
         // todo: on font resolving failure, downgrade to a fake font book
@@ -260,9 +733,24 @@ impl LanguageState {
 
         // Bootstrap server.
         let (editor_tx, editor_rx) = mpsc::unbounded_channel();
+        if let Some(warning) = config_warning {
+            log::warn!("{warning}");
+            let _ = editor_tx.send(EditorRequest::Warning(warning));
+        }
 
         log::info!("initialized with config {:?}", config);
         self.primary.config = config.compile.clone();
+        self.primary.const_config.markdown_html = cc.markdown_html;
+        self.primary.const_config.markdown_images = cc.markdown_images;
+        // All the compilers record their compilations in the same log.
+        self.compile_log.set_capacity(config.compile_log_size());
+        let states = std::iter::once(&mut self.primary).chain(&mut self.dedicates);
+        for state in states {
+            state.compile_log = self.compile_log.clone();
+        }
+        if config.crash_recovery {
+            self.recovery = RecoveryStore::in_user_data(&config.compile.roots);
+        }
         self.config = config;
 
         self.run_format_thread();
@@ -272,6 +760,8 @@ impl LanguageState {
             self.host.clone(),
             editor_rx,
             self.config.compile.notify_compile_status,
+            cc.compile_status_detail,
+            self.config.compile.diagnostic_source,
         );
 
         let fallback = self.config.compile.determine_default_entry_path();
@@ -297,70 +787,67 @@ impl LanguageState {
             && self.config.formatter != FormatterMode::Disable)
             .then(|| OneOf::Left(true));
 
-        Ok(InitializeResult {
-            capabilities: ServerCapabilities {
-                // todo: respect position_encoding
-                // position_encoding: Some(cc.position_encoding.into()),
-                hover_provider: Some(HoverProviderCapability::Simple(true)),
-                signature_help_provider: Some(SignatureHelpOptions {
-                    trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
-                    retrigger_characters: None,
-                    ..Default::default()
-                }),
-                definition_provider: Some(OneOf::Left(true)),
-                references_provider: Some(OneOf::Left(true)),
-                completion_provider: Some(CompletionOptions {
-                    // Please update the language-configurations.json if you are changing this
-                    // setting.
-                    trigger_characters: Some(vec![
-                        String::from("#"),
-                        String::from("("),
-                        String::from(","),
-                        String::from("."),
-                        String::from(":"),
-                        String::from("/"),
-                        String::from("\""),
-                        String::from("@"),
-                    ]),
-                    ..Default::default()
-                }),
-                text_document_sync: Some(TextDocumentSyncCapability::Options(
-                    TextDocumentSyncOptions {
-                        open_close: Some(true),
-                        change: Some(TextDocumentSyncKind::INCREMENTAL),
-                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
-                        ..Default::default()
-                    },
-                )),
-                semantic_tokens_provider,
-                execute_command_provider: Some(ExecuteCommandOptions {
-                    commands: self.exec_cmds.keys().map(ToString::to_string).collect(),
-                    ..Default::default()
-                }),
-                color_provider: Some(ColorProviderCapability::Simple(true)),
-                document_symbol_provider: Some(OneOf::Left(true)),
-                workspace_symbol_provider: Some(OneOf::Left(true)),
-                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
-                rename_provider: Some(OneOf::Right(RenameOptions {
-                    prepare_provider: Some(true),
-                    work_done_progress_options: Default::default(),
-                })),
-                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
-                workspace: Some(WorkspaceServerCapabilities {
-                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
-                        supported: Some(true),
-                        change_notifications: Some(OneOf::Left(true)),
-                    }),
+        let mut capabilities = ServerCapabilities {
+            // todo: respect position_encoding
+            // position_encoding: Some(cc.position_encoding.into()),
+            definition_provider: Some(OneOf::Left(true)),
+            references_provider: Some(OneOf::Left(true)),
+            moniker_provider: Some(OneOf::Left(true)),
+            completion_provider: Some(CompletionOptions {
+                // Please update the language-configurations.json if you are changing this
+                // setting.
+                trigger_characters: Some(vec![
+                    String::from("#"),
+                    String::from("("),
+                    String::from(","),
+                    String::from("."),
+                    String::from(":"),
+                    String::from("/"),
+                    String::from("\""),
+                    String::from("@"),
+                ]),
+                // The previews of equations are rendered lazily.
+                resolve_provider: Some(true),
+                ..Default::default()
+            }),
+            text_document_sync: Some(TextDocumentSyncCapability::Options(
+                TextDocumentSyncOptions {
+                    open_close: Some(true),
+                    change: Some(TextDocumentSyncKind::INCREMENTAL),
+                    save: Some(TextDocumentSyncSaveOptions::Supported(true)),
                     ..Default::default()
-                }),
-                document_formatting_provider,
-                inlay_hint_provider: Some(OneOf::Left(true)),
-                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
-                code_lens_provider: Some(CodeLensOptions {
-                    resolve_provider: Some(false),
+                },
+            )),
+            semantic_tokens_provider,
+            execute_command_provider: Some(ExecuteCommandOptions {
+                commands: self.exec_cmds.keys().map(ToString::to_string).collect(),
+                ..Default::default()
+            }),
+            workspace_symbol_provider: Some(OneOf::Right(WorkspaceSymbolOptions {
+                resolve_provider: Some(true),
+                ..Default::default()
+            })),
+            rename_provider: Some(OneOf::Right(RenameOptions {
+                prepare_provider: Some(true),
+                work_done_progress_options: Default::default(),
+            })),
+            workspace: Some(WorkspaceServerCapabilities {
+                workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                    supported: Some(true),
+                    change_notifications: Some(OneOf::Left(true)),
                 }),
                 ..Default::default()
-            },
+            }),
+            document_formatting_provider,
+            ..feature_capabilities()
+        };
+        // The features supporting dynamic registration are registered once initialized.
+        (self.config.features)
+            .static_features(&cc.features_dynamic_registration)
+            .retain_capabilities(&mut capabilities);
+
+        Ok(InitializeResult {
+            capabilities,
             ..Default::default()
         })
     }
@@ -384,6 +871,8 @@ impl LanguageState {
             }
         }
 
+        self.change_features(LspFeatures::all(false));
+
         if self.const_config.cfg_change_registration {
             log::trace!("setting up to request config change notifications");
 
@@ -404,20 +893,127 @@ impl LanguageState {
         }
 
         self.primary.initialized(params);
+        self.offer_recovery();
         log::info!("server initialized");
     }
+
+    /// Applies the settings changed by the client, keeping the old settings if
+    /// the new ones are invalid.
+    pub fn on_changed_configuration(
+        &mut self,
+        values: Map<String, JsonValue>,
+    ) -> anyhow::Result<()> {
+        let old = self.config.clone();
+        if let Err(err) = self.config.update_by_map(&values) {
+            self.config = old;
+            bail!("error applying new settings: {err}");
+        }
+        if let Some(warning) = self.config.strict_warning(&JsonValue::Object(values)) {
+            log::warn!("{warning}");
+        }
+
+        if self.const_config.tokens_dynamic_registration
+            && old.semantic_tokens != self.config.semantic_tokens
+        {
+            let enable = self.config.semantic_tokens == SemanticTokensMode::Enable;
+            if let Err(err) = self.enable_sema_token_caps(enable) {
+                log::error!("could not change semantic tokens config: {err}");
+            }
+        }
+
+        if self.const_config.doc_fmt_dynamic_registration && old.formatter != self.config.formatter
+        {
+            let enable = self.config.formatter != FormatterMode::Disable;
+            if let Err(err) = self.enable_formatter_caps(enable) {
+                log::error!("could not change formatter config: {err}");
+            }
+        }
+
+        self.change_features(old.features);
+
+        if old.log_level != self.config.log_level {
+            crate::logging::set_level(self.config.log_level);
+        }
+        if old.compile_log_size != self.config.compile_log_size {
+            self.compile_log
+                .set_capacity(self.config.compile_log_size());
+        }
+        if old.crash_recovery != self.config.crash_recovery {
+            self.change_crash_recovery();
+        }
+
+        let editor_config = |config: &CompileConfig| EditorConfig {
+            notify_compile_status: config.notify_compile_status,
+            diagnostic_source: config.diagnostic_source,
+        };
+        let new_editor_config = editor_config(&self.config.compile);
+        if editor_config(&old.compile) != new_editor_config {
+            let req = EditorRequest::ChangeConfig(new_editor_config);
+            let _ = self.primary.editor_tx.send(req);
+        }
+
+        let states = std::iter::once(&mut self.primary).chain(&mut self.dedicates);
+        for state in states {
+            state.change_scoped_config(self.config.compile.clone());
+        }
+        // The settings scoped to the entries override the new ones again.
+        self.request_scoped_config(self.entry_paths());
+
+        log::info!("new settings applied");
+        Ok(())
+    }
+
+    /// Registers and unregisters the features changed from `old` by the
+    /// configuration, if the client supports dynamic registration of them.
+    pub fn change_features(&mut self, old: LspFeatures) {
+        let dynamic = &self.const_config.features_dynamic_registration;
+        let (register, unregister) = self.config.features.changed_methods(&old, dynamic);
+
+        if !register.is_empty() {
+            let caps = feature_capabilities();
+            let registrations = register.into_iter().map(|method| Registration {
+                id: method.to_owned(),
+                method: method.to_owned(),
+                register_options: LspFeatures::register_options(method, &caps),
+            });
+            let err = self
+                .client
+                .register_capability(registrations.collect())
+                .err();
+            if let Some(err) = err {
+                log::error!("could not register features: {err}");
+            }
+        }
+
+        if !unregister.is_empty() {
+            let unregistrations = unregister.into_iter().map(|method| Unregistration {
+                id: method.to_owned(),
+                method: method.to_owned(),
+            });
+            let err = self
+                .client
+                .unregister_capability(unregistrations.collect())
+                .err();
+            if let Some(err) = err {
+                log::error!("could not unregister features: {err}");
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
+    use typst::foundations::IntoValue;
 
     #[test]
     fn test_config_update() {
         let mut config = LanguageConfig::default();
 
         let root_path = if cfg!(windows) { "C:\\root" } else { "/root" };
+        assert_eq!(config.max_document_bytes(), DEFAULT_MAX_DOCUMENT_BYTES);
+        assert_eq!(config.compile_log_size(), DEFAULT_COMPILE_LOG_SIZE);
 
         let update = json!({
             "outputPath": "out",
@@ -425,6 +1021,14 @@ mod tests {
             "rootPath": root_path,
             "semanticTokens": "enable",
             "formatterMode": "typstyle",
+            "maxDocumentBytes": 1024,
+            "compileLogSize": 3,
+            "crashRecovery": true,
+            "hoverSetRules": true,
+            "logLevel": "debug",
+            "compileTimeoutMs": 500,
+            "referencesScope": "file",
+            "inlayHints": { "documentValues": true },
             "typstExtraArgs": ["--root", root_path]
         });
 
@@ -435,6 +1039,17 @@ mod tests {
         assert_eq!(config.compile.root_path, Some(PathBuf::from(root_path)));
         assert_eq!(config.semantic_tokens, SemanticTokensMode::Enable);
         assert_eq!(config.formatter, FormatterMode::Typstyle);
+        assert_eq!(config.references_scope, ReferencesScope::File);
+        assert!(config.compile.inlay_hint_document_values);
+        assert!(config.compile.hover_set_rules);
+        assert_eq!(config.max_document_bytes(), 1024);
+        assert_eq!(config.compile_log_size(), 3);
+        assert!(config.crash_recovery);
+        assert_eq!(config.log_level, Some(LevelFilter::Debug));
+        assert_eq!(
+            config.compile.compile_timeout,
+            Some(std::time::Duration::from_millis(500))
+        );
         assert_eq!(
             config.compile.typst_extra_args,
             Some(CompileExtraOpts {
@@ -444,6 +1059,224 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_strict_config() {
+        let mut config = LanguageConfig::default();
+        let update = json!({
+            "strictConfig": true,
+            "exportPdf": "onSave",
+            "exprotPdf": "onSave",
+            "tinymist.cjkMode": "yes",
+            "rootPath": null
+        });
+        config.update(&update).unwrap();
+        assert_eq!(config.compile.export_pdf, ExportMode::OnSave);
+
+        let warning = config.strict_warning(&update).unwrap();
+        assert!(warning.contains("unknown setting `exprotPdf`"), "{warning}");
+        assert!(
+            warning.contains("`tinymist.cjkMode` expects boolean but got string"),
+            "{warning}"
+        );
+        assert!(!warning.contains("exportPdf`"), "{warning}");
+        assert!(!warning.contains("rootPath"), "{warning}");
+
+        let valid = json!({ "strictConfig": true, "exportPdf": "onSave", "cjkMode": true });
+        config.update(&valid).unwrap();
+        assert_eq!(config.strict_warning(&valid), None);
+        // The settings of the clients are not reported.
+        let client = json!({
+            "serverPath": "tinymist",
+            "tinymist.systemFonts": false,
+            "fontPaths": ["fonts"],
+            "trace": { "server": "verbose" },
+            "tinymist.trace.server": "off"
+        });
+        assert_eq!(config.strict_warning(&client), None);
+        // Every setting is checked against its types.
+        for (item, types) in CONFIG_ITEMS {
+            assert!(!types.is_empty(), "{item}");
+        }
+
+        // The settings are not checked unless enabled.
+        let lenient = json!({ "exprotPdf": "onSave" });
+        config.update(&lenient).unwrap();
+        assert_eq!(config.strict_warning(&lenient), None);
+    }
+
+    #[test]
+    fn test_features() {
+        let mut config = LanguageConfig::default();
+        config
+            .update(&json!({ "features": { "codeLens": false } }))
+            .unwrap();
+        assert!(!config.features.code_lens);
+        assert!(config.features.inlay_hints);
+
+        let mut caps = ServerCapabilities {
+            code_lens_provider: Some(CodeLensOptions {
+                resolve_provider: Some(false),
+            }),
+            inlay_hint_provider: Some(OneOf::Left(true)),
+            ..Default::default()
+        };
+        let no_dynamic = LspFeatures::all(false);
+        (config.features)
+            .static_features(&no_dynamic)
+            .retain_capabilities(&mut caps);
+        assert!(caps.code_lens_provider.is_none());
+        assert!(caps.inlay_hint_provider.is_some());
+
+        // Re-enabling registers the feature dynamically.
+        let dynamic = LspFeatures::all(true);
+        let old = config.features;
+        config.update(&json!({})).unwrap();
+        let (register, unregister) = config.features.changed_methods(&old, &dynamic);
+        assert_eq!(register, ["textDocument/codeLens"]);
+        assert!(unregister.is_empty());
+
+        let (register, unregister) = old.changed_methods(&config.features, &dynamic);
+        assert!(register.is_empty());
+        assert_eq!(unregister, ["textDocument/codeLens"]);
+        // Features not registered dynamically are left alone.
+        assert_eq!(
+            old.changed_methods(&config.features, &no_dynamic).1.len(),
+            0
+        );
+
+        // The features are registered with their static options.
+        let caps = feature_capabilities();
+        let options = LspFeatures::register_options(SignatureHelpRequest::METHOD, &caps);
+        assert_eq!(
+            options,
+            Some(json!({ "triggerCharacters": ["(", ","], "documentSelector": null }))
+        );
+        let options = LspFeatures::register_options(CodeLensRequest::METHOD, &caps);
+        assert_eq!(
+            options,
+            Some(json!({ "resolveProvider": false, "documentSelector": null }))
+        );
+        let options = LspFeatures::register_options(InlayHintRequest::METHOD, &caps);
+        assert_eq!(options, Some(json!({ "documentSelector": null })));
+
+        assert!(config
+            .update(&json!({ "features": { "codeLens": 1 } }))
+            .is_err());
+    }
+
+    #[test]
+    fn test_output_path_by_kind() {
+        let mut config = LanguageConfig::default();
+        let update = json!({
+            "outputPath": { "default": "$root/out/$name", "pdf": "$root/dist/$name" }
+        });
+        config.update(&update).unwrap();
+
+        let compile = &config.compile;
+        assert_eq!(compile.output_pattern("pdf"), "$root/dist/$name");
+        assert_eq!(compile.output_pattern("png"), "$root/out/$name");
+
+        config.update(&json!({ "outputPath": "out" })).unwrap();
+        assert_eq!(config.compile.output_pattern("pdf"), "out");
+        assert_eq!(
+            config.compile.output_path_by_kind,
+            OutputPathByKind::default()
+        );
+
+        let update = json!({ "outputPath": { "png": 1 } });
+        assert!(config.update(&update).is_err());
+    }
+
+    #[test]
+    fn test_scoped_config() {
+        use crate::actor::export::substitute_path;
+
+        let ws = if cfg!(windows) { "C:\\ws" } else { "/ws" };
+        let mut config = LanguageConfig::default();
+        config
+            .update(&json!({ "outputPath": "$root/out/$name" }))
+            .unwrap();
+
+        let scope = Url::from_file_path(Path::new(ws).join("a/main.typ")).unwrap();
+        let items = LanguageConfig::get_items(Some(&scope));
+        assert!(items
+            .iter()
+            .all(|item| item.scope_uri.as_ref() == Some(&scope)));
+
+        // The values answered for each folder, paired as the items.
+        let scoped = |output_path: &str| {
+            let mut values = vec![JsonValue::Null; items.len()];
+            values[0] = output_path.into();
+            config.scoped_compile(values).unwrap()
+        };
+        let a = scoped("$root/a-out/$name");
+        let b = scoped("$root/b-out/$name");
+        assert_eq!(config.compile.output_path, "$root/out/$name");
+
+        let export = |compile: &CompileConfig, dir: &str| {
+            let root = Path::new(ws).join(dir);
+            substitute_path(compile.output_pattern("pdf"), &root, &root.join("main.typ"))
+                .unwrap()
+                .to_path_buf()
+        };
+        assert_eq!(export(&a, "a"), Path::new(ws).join("a/a-out/main.typ"));
+        assert_eq!(export(&b, "b"), Path::new(ws).join("b/b-out/main.typ"));
+    }
+
+    #[test]
+    fn test_project_config() {
+        let dir = if cfg!(windows) { "C:\\root" } else { "/root" };
+        let project = ProjectConfig::parse(
+            r#"
+entry = "main.typ"
+root = "."
+fontPaths = ["fonts"]
+exportPdf = "onSave"
+outputPath = "out"
+
+[inputs]
+theme = "dark"
+"#,
+            Path::new(dir),
+        )
+        .unwrap();
+
+        let mut config = LanguageConfig {
+            project: Some(project),
+            ..LanguageConfig::default()
+        };
+        config.update(&json!({ "outputPath": "editor" })).unwrap();
+
+        assert_eq!(config.compile.root_path, Some(Path::new(dir).join(".")));
+        assert_eq!(config.compile.export_pdf, ExportMode::OnSave);
+        assert_eq!(config.compile.output_path, "editor");
+
+        assert!(config.compile.has_default_entry_path);
+        let extra_args = config.compile.typst_extra_args.unwrap();
+        let entry = Path::new(dir).join("main.typ");
+        assert_eq!(extra_args.entry.as_deref(), Some(entry.as_path()));
+        assert_eq!(extra_args.font_paths, vec![Path::new(dir).join("fonts")]);
+        assert_eq!(
+            extra_args.inputs.get("theme").unwrap(),
+            &"dark".into_value()
+        );
+
+        // The inputs and font paths are merged with the arguments of the editor.
+        let args = json!({ "typstExtraArgs": ["--input", "theme=light", "--input", "mode=print"] });
+        config.update(&args).unwrap();
+        let extra_args = config.compile.typst_extra_args.unwrap();
+        assert_eq!(extra_args.entry, None);
+        assert_eq!(extra_args.font_paths, vec![Path::new(dir).join("fonts")]);
+        let input = |key: &str| extra_args.inputs.get(key).unwrap().clone();
+        assert_eq!(input("theme"), "light".into_value());
+        assert_eq!(input("mode"), "print".into_value());
+    }
+
+    #[test]
+    fn test_malformed_project_config() {
+        assert!(ProjectConfig::parse("root = ", Path::new("/root")).is_err());
+    }
+
     #[test]
     fn test_empty_extra_args() {
         let mut config = LanguageConfig::default();
@@ -475,4 +1308,64 @@ mod tests {
         let err = format!("{}", config.update(&update).unwrap_err());
         assert!(err.contains("absolute path"), "unexpected error: {}", err);
     }
+
+    #[test]
+    fn test_change_roots() {
+        let (first, second) = if cfg!(windows) {
+            ("C:\\first", "C:\\second")
+        } else {
+            ("/first", "/second")
+        };
+        let second = PathBuf::from(second);
+        let chapter = second.join("chapters");
+        let entry: ImmutPath = chapter.join("main.typ").into();
+
+        let mut config = CompileConfig {
+            roots: vec![PathBuf::from(first)],
+            ..CompileConfig::default()
+        };
+        let root = config.determine_entry(Some(entry.clone())).root();
+        assert_eq!(root.as_deref(), Some(chapter.as_path()));
+
+        config.change_roots(&[second.clone()], &[]);
+        let root = config.determine_entry(Some(entry.clone())).root();
+        assert_eq!(root.as_deref(), Some(second.as_path()));
+
+        config.change_roots(&[], &[second.clone()]);
+        assert_eq!(config.roots, vec![PathBuf::from(first)]);
+        let root = config.determine_entry(Some(entry)).root();
+        assert_eq!(root.as_deref(), Some(chapter.as_path()));
+    }
+
+    #[test]
+    fn test_markdown_html() {
+        let markdown_html = |markdown: serde_json::Value| {
+            let params = json!({
+                "capabilities": { "general": { "markdown": markdown } },
+            });
+            let params: InitializeParams = serde_json::from_value(params).unwrap();
+            ConstLanguageConfig::from(&params).markdown_html
+        };
+
+        let tags = json!({ "parser": "marked", "allowedTags": ["pre", "code", "span"] });
+        assert!(markdown_html(tags));
+        let tags = json!({ "parser": "marked", "allowedTags": ["code", "span"] });
+        assert!(!markdown_html(tags));
+        assert!(!markdown_html(json!({ "parser": "marked" })));
+    }
+
+    #[test]
+    fn test_markdown_images() {
+        let markdown_images = |hover: serde_json::Value| {
+            let params = json!({
+                "capabilities": { "textDocument": { "hover": hover } },
+            });
+            let params: InitializeParams = serde_json::from_value(params).unwrap();
+            ConstLanguageConfig::from(&params).markdown_images
+        };
+
+        assert!(markdown_images(json!({ "contentFormat": ["markdown"] })));
+        assert!(!markdown_images(json!({ "contentFormat": ["plaintext"] })));
+        assert!(!markdown_images(json!({})));
+    }
 }
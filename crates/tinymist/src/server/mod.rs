@@ -4,6 +4,7 @@ pub mod compile_init;
 pub mod lsp;
 pub mod lsp_cmd;
 pub mod lsp_init;
+pub mod progress;
 
 use std::collections::HashMap;
 use std::fmt::Display;
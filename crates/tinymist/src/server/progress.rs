@@ -0,0 +1,177 @@
+//! The cancellation of long-running commands from the progress UI of clients.
+//!
+//! A command executed with a `workDoneToken` registers the token until the
+//! command completes, and `window/workDoneProgress/cancel` sets the flag of the
+//! token. Cancelable commands check the flag at each file they process.
+//!
+//! The progress of a command is reported to the client with `$/progress`
+//! notifications. Without a token from the client, a command may create its
+//! progress by `window/workDoneProgress/create`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use async_lsp::ClientSocket;
+use lsp_types::notification::Progress as ProgressNotification;
+use lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, ProgressToken, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+use parking_lot::Mutex;
+
+/// The cancellation flags of the active progresses, keyed by their tokens.
+#[derive(Debug, Default, Clone)]
+pub struct ProgressTokens {
+    flags: Arc<Mutex<HashMap<ProgressToken, Arc<AtomicBool>>>>,
+}
+
+impl ProgressTokens {
+    /// Start a progress with the token, which is active until the returned
+    /// progress is dropped.
+    pub fn begin(&self, token: ProgressToken) -> Progress {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.flags.lock().insert(token.clone(), cancelled.clone());
+        Progress {
+            token,
+            cancelled,
+            tokens: self.clone(),
+        }
+    }
+
+    /// Start a progress created by the server, whose token is unique in the
+    /// session.
+    pub fn create(&self, name: &str) -> Progress {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        self.begin(NumberOrString::String(format!("tinymist/{name}/{id}")))
+    }
+
+    /// Cancel the progress with the token, returning whether it is active.
+    pub fn cancel(&self, token: &ProgressToken) -> bool {
+        let flags = self.flags.lock();
+        let Some(cancelled) = flags.get(token) else {
+            return false;
+        };
+        cancelled.store(true, Ordering::Relaxed);
+        true
+    }
+}
+
+/// An active progress of a command.
+#[derive(Debug)]
+pub struct Progress {
+    token: ProgressToken,
+    cancelled: Arc<AtomicBool>,
+    tokens: ProgressTokens,
+}
+
+impl Progress {
+    /// Get the token of the progress.
+    pub fn token(&self) -> &ProgressToken {
+        &self.token
+    }
+
+    /// Get the flag set once the progress is cancelled.
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+
+    /// Get the reporter of the progress to the client.
+    pub fn work_done(&self, client: ClientSocket) -> WorkDone {
+        WorkDone {
+            client,
+            token: self.token.clone(),
+        }
+    }
+}
+
+/// The reporter of the progress of a command to the client.
+#[derive(Debug, Clone)]
+pub struct WorkDone {
+    client: ClientSocket,
+    token: ProgressToken,
+}
+
+impl WorkDone {
+    /// Report that the command starts, showing a cancel button.
+    pub fn begin(&self, title: &str) {
+        self.notify(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: title.to_owned(),
+            cancellable: Some(true),
+            message: None,
+            percentage: Some(0),
+        }));
+    }
+
+    /// Report the percentage of the work done, from 0 to 100.
+    pub fn report(&self, message: String, percentage: u32) {
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: None,
+            message: Some(message),
+            percentage: Some(percentage),
+        }));
+    }
+
+    /// Report that the command completes.
+    pub fn end(&self, message: Option<String>) {
+        self.notify(WorkDoneProgress::End(WorkDoneProgressEnd { message }));
+    }
+
+    fn notify(&self, value: WorkDoneProgress) {
+        let params = ProgressParams {
+            token: self.token.clone(),
+            value: ProgressParamsValue::WorkDone(value),
+        };
+        if let Err(err) = self.client.notify::<ProgressNotification>(params) {
+            log::warn!("failed to report progress {:?}: {err}", self.token);
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let mut flags = self.tokens.flags.lock();
+        // The token may be reused by a later progress.
+        if flags
+            .get(&self.token)
+            .is_some_and(|flag| Arc::ptr_eq(flag, &self.cancelled))
+        {
+            flags.remove(&self.token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_progress() {
+        let tokens = ProgressTokens::default();
+        let token = NumberOrString::String("export".to_owned());
+        let progress = tokens.begin(token.clone());
+        let flag = progress.cancel_flag();
+        assert!(!flag.load(Ordering::Relaxed));
+
+        assert!(!tokens.cancel(&NumberOrString::Number(1)));
+        assert!(tokens.cancel(&token));
+        assert!(flag.load(Ordering::Relaxed));
+
+        // The token is inactive once the command completes.
+        drop(progress);
+        assert!(!tokens.cancel(&token));
+    }
+    #[test]
+    fn test_create_progress() {
+        let tokens = ProgressTokens::default();
+        let first = tokens.create("reindexWorkspace");
+        let second = tokens.create("reindexWorkspace");
+        assert_ne!(first.token(), second.token());
+
+        // The created progress is cancelable as well.
+        assert!(tokens.cancel(first.token()));
+        assert!(first.cancel_flag().load(Ordering::Relaxed));
+        assert!(!second.cancel_flag().load(Ordering::Relaxed));
+    }
+}
@@ -1,15 +1,66 @@
 //! Bootstrap actors for Tinymist.
 
-use std::path::PathBuf;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use lsp_types::TextDocumentContentChangeEvent;
-use tinymist_query::{lsp_to_typst, PositionEncoding};
+use async_lsp::router::Router;
+use lsp_types::request::{ApplyWorkspaceEdit, ShowMessageRequest, WorkspaceConfiguration};
+use lsp_types::{
+    ApplyWorkspaceEditParams, ConfigurationParams, DiagnosticSeverity, MessageActionItem,
+    MessageType, ShowMessageRequestParams, TextDocumentContentChangeEvent, TextEdit, WorkspaceEdit,
+};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tinymist_query::syntax::ColorTheme;
+use tinymist_query::{
+    lsp_to_typst, path_to_url, typst_to_lsp, DiagnosticsMap, LspDiagnostic, LspRange,
+    PositionEncoding,
+};
 use typst::{diag::FileResult, syntax::Source};
 use typst_ts_compiler::vfs::notify::{FileChangeSet, MemoryEvent};
 use typst_ts_compiler::Time;
-use typst_ts_core::{error::prelude::*, Bytes, Error as TypError, ImmutPath};
+use typst_ts_core::{error::prelude::*, path::PathClean, Bytes, Error as TypError, ImmutPath};
 
-use crate::{compile::CompileState, LanguageState};
+use crate::compile_init::CompileConfig;
+use crate::tools::recovery::{RecoveredFile, RecoveryStore};
+use crate::{actor::editor::EditorRequest, compile::CompileState, LanguageConfig, LanguageState};
+
+/// Normalizes a path to the key of files in memory and in worlds.
+///
+/// The path is cleaned lexically, resolving `.` and `..` components. If the
+/// file exists, symlinks are resolved as well, as long as the resolved path
+/// stays in one of the roots. Resolving the path also recovers its case on disk
+/// on case-insensitive file systems.
+pub fn normalize_path(path: &Path, roots: &[PathBuf]) -> ImmutPath {
+    let path = path.clean();
+    let Ok(resolved) = path.canonicalize() else {
+        return path.into();
+    };
+    let resolved = strip_verbatim(resolved);
+
+    if roots.is_empty() || roots.iter().any(|root| resolved.starts_with(root)) {
+        resolved.into()
+    } else {
+        path.into()
+    }
+}
+
+/// Strips the `\\?\` prefix added by canonicalization on Windows, which is
+/// not understood by most tools.
+#[cfg(windows)]
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    let stripped = path.to_str().and_then(|p| p.strip_prefix(r"\\?\"));
+    match stripped {
+        Some(stripped) if !stripped.starts_with("UNC\\") => PathBuf::from(stripped),
+        _ => path,
+    }
+}
+
+#[cfg(not(windows))]
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    path
+}
 
 impl CompileState {
     /// Focus main file to some path.
@@ -23,11 +74,93 @@ impl CompileState {
             .change_entry(new_entry)
             .await
     }
+
+    /// Adds and removes workspace roots. The entry is resolved again if it
+    /// lived in a removed root or now lives in an added root.
+    pub fn change_roots(&mut self, added: &[PathBuf], removed: &[PathBuf]) {
+        self.config.change_roots(added, removed);
+        if let Some(compiler) = self.compiler.as_mut() {
+            compiler.change_config(self.config.clone());
+            compiler.reload_entry();
+        }
+    }
+
+    /// Changes the preferred theme, which applies from the next request.
+    pub fn change_theme(&mut self, theme: Option<ColorTheme>) {
+        self.config.preferred_theme = theme;
+        if let Some(compiler) = self.compiler.as_mut() {
+            compiler.change_config(self.config.clone());
+        }
+    }
+
+    /// Changes whether the documents are in CJK mode, which applies from the
+    /// next word count.
+    pub fn change_cjk_mode(&mut self, cjk_mode: bool) {
+        self.config.cjk_mode = cjk_mode;
+        if let Some(compiler) = self.compiler.as_mut() {
+            compiler.change_config(self.config.clone());
+            compiler.change_cjk_mode(cjk_mode);
+        }
+    }
+
+    /// Changes the font families tried before the default fallback, which
+    /// restarts the compiler with the memory overlay to recompile with them.
+    pub fn change_font_fallback(&mut self, families: Vec<String>, snapshot: FileChangeSet) {
+        self.config.font_fallback = families;
+        self.restart_server(snapshot);
+    }
+
+    /// Gets the path of the entry served by the compiler, if any.
+    pub fn entry_path(&self) -> Option<PathBuf> {
+        let entry = self.compiler.as_ref()?.entry();
+        entry.main()?.vpath().resolve(&entry.root()?)
+    }
+
+    /// Changes the configuration to the one scoped to the served entry, e.g.
+    /// by the settings of the folder containing it, which applies to the
+    /// following requests and exports.
+    pub fn change_scoped_config(&mut self, config: CompileConfig) {
+        self.config = config;
+        let Some(entry) = self.compiler.as_ref().map(|c| c.entry().clone()) else {
+            return;
+        };
+        let export = self.export_config(entry);
+        let compiler = self.compiler.as_mut().unwrap();
+        compiler.change_config(self.config.clone());
+        compiler.change_export_pdf(export);
+    }
+
+    /// Snapshot the memory overlay as a file change set, which is used to
+    /// initialize a fresh compiler without losing unsaved edits.
+    pub fn vfs_snapshot(&self) -> FileChangeSet {
+        FileChangeSet::new_inserts(
+            self.memory_changes
+                .iter()
+                .map(|(path, meta)| {
+                    let content = meta.content.text().as_bytes().into();
+                    (path.clone(), FileResult::Ok((meta.mt, content)).into())
+                })
+                .collect(),
+        )
+    }
 }
 
 impl LanguageState {
+    /// Gets the key of a path from the client in memory and in worlds, see
+    /// [`normalize_path`]. The key is cached, so that the file system is not
+    /// queried on every change of a document.
+    pub fn path_key(&mut self, path: &Path) -> ImmutPath {
+        if let Some(key) = self.path_keys.get(path) {
+            return key.clone();
+        }
+        let key = normalize_path(path, &self.config.compile.roots);
+        self.path_keys.insert(path.to_owned(), key.clone());
+        key
+    }
+
     /// Pin the entry to the given path
     pub async fn pin_entry(&mut self, new_entry: Option<ImmutPath>) -> Result<(), TypError> {
+        let new_entry = new_entry.map(|p| self.path_key(&p));
         self.pinning = new_entry.is_some();
         self.primary.do_change_entry(new_entry).await?;
 
@@ -39,17 +172,23 @@ impl LanguageState {
             }
         }
 
+        self.request_primary_scoped_config();
         Ok(())
     }
 
     /// Updates the primary (focusing) entry
     pub async fn focus_entry(&mut self, new_entry: Option<ImmutPath>) -> Result<bool, TypError> {
+        let new_entry = new_entry.map(|p| self.path_key(&p));
         if self.pinning || self.config.compile.has_default_entry_path {
             self.focusing = new_entry;
             return Ok(false);
         }
 
-        self.primary.do_change_entry(new_entry.clone()).await
+        let changed = self.primary.do_change_entry(new_entry.clone()).await?;
+        if changed {
+            self.request_primary_scoped_config();
+        }
+        Ok(changed)
     }
 
     /// This is used for tracking activating document status if a client is not
@@ -90,57 +229,430 @@ impl LanguageState {
             Ok(false) => {}
         }
     }
+
+    /// Gets the entry of the primary compiler and how it is determined.
+    pub fn active_entry(&self) -> ActiveEntry {
+        let has_default_entry_path = self.config.compile.has_default_entry_path;
+        ActiveEntry::new(&self.primary, self.pinning, has_default_entry_path)
+    }
+
+    /// Gets the documents opened in the client, along with the groups of the
+    /// compilers taking them as entries.
+    pub fn open_entries(&self) -> Vec<OpenEntry> {
+        OpenEntry::collect(&self.primary, &self.dedicates)
+    }
+}
+
+/// How the entry of the primary compiler is determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EntrySource {
+    /// The entry is pinned by `tinymist.pinMain`.
+    Pin,
+    /// The entry follows the document focused by the client.
+    Focus,
+    /// The entry is the default one given by the configuration.
+    Default,
+}
+
+/// The entry of the primary compiler, see `tinymist.getActiveEntry`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveEntry {
+    pub entry: Option<PathBuf>,
+    pub pinned: bool,
+    pub source: EntrySource,
+}
+
+impl ActiveEntry {
+    fn new(primary: &CompileState, pinning: bool, has_default_entry_path: bool) -> Self {
+        let source = if pinning {
+            EntrySource::Pin
+        } else if has_default_entry_path {
+            EntrySource::Default
+        } else {
+            EntrySource::Focus
+        };
+        Self {
+            entry: primary.entry_path(),
+            pinned: pinning,
+            source,
+        }
+    }
+}
+
+/// A document opened in the client, see `tinymist.listOpenEntries`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenEntry {
+    pub path: PathBuf,
+    /// The group of the first compiler taking the document as its entry.
+    pub group: Option<String>,
+}
+
+impl OpenEntry {
+    /// Collects the documents opened in the primary state, which records the
+    /// buffers of all compilers.
+    fn collect(primary: &CompileState, dedicates: &[CompileState]) -> Vec<Self> {
+        let compilers = std::iter::once(primary).chain(dedicates);
+        let compilers: Vec<_> = compilers
+            .filter_map(|state| {
+                let group = state.compiler.as_ref()?.diag_group.as_str();
+                Some((group, state.entry_path()?))
+            })
+            .collect();
+        let mut entries: Vec<_> = (primary.memory_changes.keys())
+            .map(|path| Self {
+                path: path.to_path_buf(),
+                group: (compilers.iter())
+                    .find(|(_, entry)| entry == path.as_ref())
+                    .map(|(group, _)| group.to_string()),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct MemoryFileMeta {
     pub mt: Time,
     pub content: Source,
+    /// Whether the content misses a refused change, so that the ranges of
+    /// further changes don't match it until the full text is synchronized.
+    pub stale: bool,
+}
+
+impl MemoryFileMeta {
+    /// Applies the changes to the content, refusing them if the resulting
+    /// content exceeds `limit` bytes. The last valid content is kept on
+    /// failure and the size of the refused content is returned.
+    ///
+    /// Once a change is refused, the content is stale and the incremental
+    /// changes are ignored until the full text is sent again. Returns whether
+    /// the content is changed.
+    pub fn apply_changes(
+        &mut self,
+        mut changes: Vec<TextDocumentContentChangeEvent>,
+        position_encoding: PositionEncoding,
+        limit: usize,
+    ) -> Result<bool, usize> {
+        if self.stale {
+            let Some(full) = changes.iter().rposition(|change| change.range.is_none()) else {
+                return Ok(false);
+            };
+            changes.drain(..full);
+        }
+
+        // Edits in place if the changes cannot exceed the limit, which avoids
+        // copying the source on each keystroke.
+        let inserted: usize = changes.iter().map(|change| change.text.len()).sum();
+        if self.content.len_bytes() + inserted <= limit {
+            apply_changes(&mut self.content, changes, position_encoding);
+            self.stale = false;
+            return Ok(true);
+        }
+
+        let mut content = self.content.clone();
+        apply_changes(&mut content, changes, position_encoding);
+        let size = content.len_bytes();
+        if size > limit {
+            self.stale = true;
+            return Err(size);
+        }
+
+        self.content = content;
+        self.stale = false;
+        Ok(true)
+    }
+}
+
+fn apply_changes(
+    content: &mut Source,
+    changes: Vec<TextDocumentContentChangeEvent>,
+    position_encoding: PositionEncoding,
+) {
+    for change in changes {
+        let replacement = change.text;
+        match change.range {
+            Some(lsp_range) => {
+                let range = lsp_to_typst::range(lsp_range, position_encoding, content)
+                    .expect("invalid range");
+                content.edit(range, &replacement);
+            }
+            None => {
+                content.replace(&replacement);
+            }
+        }
+    }
+}
+
+/// The configuration answered by the client for the items scoped to an entry,
+/// looped back to the server to be applied by the handler registered with
+/// [`route_scoped_config`].
+#[derive(Debug)]
+pub struct ScopedConfigEvent {
+    /// The entry the configuration is scoped to.
+    pub entry: PathBuf,
+    /// The values answered for the items of [`LanguageConfig::get_items`].
+    pub values: Vec<JsonValue>,
+}
+
+/// The configuration answered by the client after it notified the server of a
+/// change without sending the new settings.
+#[derive(Debug)]
+pub struct ChangedConfigEvent {
+    /// The values answered for the items of [`LanguageConfig::get_items`].
+    pub values: Vec<JsonValue>,
+}
+
+/// The parameters requesting the configuration scoped to an entry.
+fn scoped_config_params(entry: &Path) -> Option<ConfigurationParams> {
+    let scope = path_to_url(entry).ok()?;
+    Some(ConfigurationParams {
+        items: LanguageConfig::get_items(Some(&scope)),
+    })
+}
+
+/// Filters the entries living in any of the folders.
+fn entries_in(entries: impl IntoIterator<Item = PathBuf>, folders: &[PathBuf]) -> Vec<PathBuf> {
+    let entries = entries.into_iter();
+    entries
+        .filter(|entry| folders.iter().any(|folder| entry.starts_with(folder)))
+        .collect()
 }
 
 impl LanguageState {
-    fn update_source(&self, files: FileChangeSet) -> Result<(), TypError> {
-        let primary = Some(self.primary());
-        let clients_to_notify =
-            (primary.into_iter()).chain(self.dedicates.iter().map(CompileState::compiler));
+    /// Gets the entries served by the compilers.
+    pub(crate) fn entry_paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        let states = std::iter::once(&self.primary).chain(&self.dedicates);
+        states.filter_map(CompileState::entry_path)
+    }
 
-        for client in clients_to_notify {
-            client.add_memory_changes(MemoryEvent::Update(files.clone()));
+    /// Requests the configuration scoped to the entry of the primary compiler,
+    /// e.g. after the entry is changed.
+    pub fn request_primary_scoped_config(&self) {
+        self.request_scoped_config(self.primary.entry_path());
+    }
+
+    /// Requests the configuration scoped to the entries living in the added
+    /// workspace folders, whose settings may now apply to them.
+    pub fn request_folder_scoped_config(&self, added: &[PathBuf]) {
+        self.request_scoped_config(entries_in(self.entry_paths(), added));
+    }
+
+    /// Requests the configuration scoped to the entries, e.g. by the settings
+    /// of the folders containing them. The answers are looped back as
+    /// [`ScopedConfigEvent`]s.
+    pub fn request_scoped_config(&self, entries: impl IntoIterator<Item = PathBuf>) {
+        if !self.const_config.cfg_change_registration {
+            return;
         }
 
+        for entry in entries {
+            let Some(params) = scoped_config_params(&entry) else {
+                continue;
+            };
+            let client = self.client.clone();
+            tokio::spawn(async move {
+                match client.request::<WorkspaceConfiguration>(params).await {
+                    Ok(values) => {
+                        if let Err(err) = client.emit(ScopedConfigEvent { entry, values }) {
+                            log::warn!("failed to apply the scoped configuration: {err}");
+                        }
+                    }
+                    Err(err) => {
+                        log::warn!("failed to request the configuration scoped to {entry:?}: {err}")
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// A server applying the configuration scoped to entries.
+pub trait ScopedConfigHandler {
+    /// Applies the configuration answered for the items scoped to the entry,
+    /// see [`LanguageConfig::get_items`].
+    fn apply_scoped_config(&mut self, entry: &Path, values: Vec<JsonValue>) -> anyhow::Result<()>;
+}
+
+impl ScopedConfigHandler for LanguageState {
+    /// Applies the configuration to the compilers serving the entry. The other
+    /// compilers keep their configuration.
+    fn apply_scoped_config(&mut self, entry: &Path, values: Vec<JsonValue>) -> anyhow::Result<()> {
+        let config = self.config.scoped_compile(values)?;
+        let states = std::iter::once(&mut self.primary).chain(&mut self.dedicates);
+        for state in states.filter(|state| state.entry_path().as_deref() == Some(entry)) {
+            state.change_scoped_config(config.clone());
+        }
         Ok(())
     }
+}
 
-    pub fn create_source(&mut self, path: PathBuf, content: String) -> Result<(), TypError> {
+/// Registers the handler of the configuration looped back by
+/// [`LanguageState::request_scoped_config`]. The main loop is broken by events
+/// without a handler.
+pub fn route_scoped_config<S: ScopedConfigHandler>(router: &mut Router<S>) {
+    router.event::<ScopedConfigEvent>(on_scoped_config);
+}
+
+fn on_scoped_config<S: ScopedConfigHandler>(
+    state: &mut S,
+    event: ScopedConfigEvent,
+) -> ControlFlow<async_lsp::Result<()>> {
+    log::info!("scoped configuration for {:?}", event.entry);
+    if let Err(err) = state.apply_scoped_config(&event.entry, event.values) {
+        log::warn!("invalid configuration scoped to {:?}: {err}", event.entry);
+    }
+    ControlFlow::Continue(())
+}
+
+impl CompileState {
+    /// Opens the source in memory, unless it exceeds `limit` bytes, in which
+    /// case it is refused and reported. Returns the change of the files, if
+    /// the source is opened.
+    fn open_memory_source(
+        &mut self,
+        path: ImmutPath,
+        content: String,
+        limit: usize,
+    ) -> Option<FileChangeSet> {
         let now = Time::now();
-        let path: ImmutPath = path.into();
+        if content.len() > limit {
+            self.refuse_oversized_source(path, content.len(), limit);
+            return None;
+        }
+        self.accept_sized_source(&path);
 
-        self.primary.memory_changes.insert(
+        self.memory_changes.insert(
             path.clone(),
             MemoryFileMeta {
                 mt: now,
                 content: Source::detached(content.clone()),
+                stale: false,
             },
         );
 
         let content: Bytes = content.as_bytes().into();
         log::info!("create source: {:?}", path);
 
-        // todo: is it safe to believe that the path is normalized?
         let files = FileChangeSet::new_inserts(vec![(path, FileResult::Ok((now, content)).into())]);
+        Some(files)
+    }
+
+    /// Applies the changes to the source in memory, refusing them if the
+    /// source would exceed `limit` bytes, in which case the last valid content
+    /// is kept and the refusal is reported. Returns the change of the files, if
+    /// the source is changed.
+    fn edit_memory_source(
+        &mut self,
+        path: ImmutPath,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        position_encoding: PositionEncoding,
+        limit: usize,
+    ) -> Result<Option<FileChangeSet>, TypError> {
+        let now = Time::now();
+        let Some(meta) = self.memory_changes.get_mut(&path) else {
+            // The source was refused at opening, so its edits are ignored as well.
+            if self.oversized_sources.contains(&path) {
+                return Ok(None);
+            }
+            return Err(error_once!("file missing", path: path.display()));
+        };
+
+        match meta.apply_changes(changes, position_encoding, limit) {
+            Ok(true) => {}
+            // The content is stale, and it is reported already.
+            Ok(false) => return Ok(None),
+            Err(size) => {
+                self.refuse_oversized_source(path, size, limit);
+                return Ok(None);
+            }
+        }
+
+        meta.mt = now;
+
+        let snapshot = FileResult::Ok((now, meta.content.text().as_bytes().into())).into();
+
+        let files = FileChangeSet::new_inserts(vec![(path.clone(), snapshot)]);
+
+        self.accept_sized_source(&path);
+        Ok(Some(files))
+    }
+
+    /// Reports a source refused for exceeding the size limit.
+    fn refuse_oversized_source(&mut self, path: ImmutPath, size: usize, limit: usize) {
+        log::warn!("refused source {path:?} of {size} bytes, exceeding the limit of {limit} bytes");
+
+        let Ok(uri) = path_to_url(&path) else {
+            return;
+        };
+        let diag = LspDiagnostic {
+            range: LspRange::default(),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("tinymist".to_owned()),
+            message: format!(
+                "The document ({size} bytes) exceeds the maximum document size \
+                 ({limit} bytes), so further changes are ignored until it is reopened. \
+                 The limit can be raised by the `tinymist.maxDocumentBytes` setting."
+            ),
+            ..Default::default()
+        };
+        let group = oversized_group(&path);
+        let diags = DiagnosticsMap::from_iter([(uri, vec![diag])]);
+        let _ = self.editor_tx.send(EditorRequest::Diag(group, Some(diags)));
+        self.oversized_sources.insert(path);
+    }
+
+    /// Clears the diagnostic of a source previously refused for exceeding the
+    /// size limit.
+    fn accept_sized_source(&mut self, path: &ImmutPath) {
+        if self.oversized_sources.remove(path) {
+            let group = oversized_group(path);
+            let _ = self.editor_tx.send(EditorRequest::Diag(group, None));
+        }
+    }
+}
+
+impl LanguageState {
+    fn update_source(&self, files: FileChangeSet) -> Result<(), TypError> {
+        let primary = Some(self.primary());
+        let clients_to_notify =
+            (primary.into_iter()).chain(self.dedicates.iter().map(CompileState::compiler));
+
+        for client in clients_to_notify {
+            client.add_memory_changes(MemoryEvent::Update(files.clone()));
+        }
 
+        Ok(())
+    }
+
+    pub fn create_source(&mut self, path: PathBuf, content: String) -> Result<(), TypError> {
+        // Resolve the path again on opening, as the file may have been moved
+        // or created since.
+        self.path_keys.remove(&path);
+        let path = self.path_key(&path);
+
+        let limit = self.config.max_document_bytes();
+        let Some(files) = self.primary.open_memory_source(path, content, limit) else {
+            return Ok(());
+        };
+
+        self.persist_overlay(true);
         self.update_source(files)
     }
 
     pub fn remove_source(&mut self, path: PathBuf) -> Result<(), TypError> {
-        let path: ImmutPath = path.into();
+        let path = self.path_key(&path);
 
+        self.primary.accept_sized_source(&path);
         self.primary.memory_changes.remove(&path);
         log::info!("remove source: {:?}", path);
 
-        // todo: is it safe to believe that the path is normalized?
         let files = FileChangeSet::new_removes(vec![path]);
 
+        self.persist_overlay(true);
         self.update_source(files)
     }
 
@@ -150,35 +662,492 @@ impl LanguageState {
         content: Vec<TextDocumentContentChangeEvent>,
         position_encoding: PositionEncoding,
     ) -> Result<(), TypError> {
-        let now = Time::now();
-        let path: ImmutPath = path.into();
+        let path = self.path_key(&path);
+        let limit = self.config.max_document_bytes();
 
-        let meta = self
+        let changed = self
             .primary
-            .memory_changes
-            .get_mut(&path)
-            .ok_or_else(|| error_once!("file missing", path: path.display()))?;
-
-        for change in content {
-            let replacement = change.text;
-            match change.range {
-                Some(lsp_range) => {
-                    let range = lsp_to_typst::range(lsp_range, position_encoding, &meta.content)
-                        .expect("invalid range");
-                    meta.content.edit(range, &replacement);
-                }
-                None => {
-                    meta.content.replace(&replacement);
+            .edit_memory_source(path, content, position_encoding, limit)?;
+        let Some(files) = changed else {
+            return Ok(());
+        };
+
+        self.persist_overlay(false);
+        self.update_source(files)
+    }
+
+    /// Saves the unsaved buffers for crash recovery, if enabled. The edits are
+    /// saved at most once in an interval, or at the end of it, while opening
+    /// and closing a buffer saves them at once. The buffers are written to
+    /// disk by the recovery store in the background.
+    fn persist_overlay(&mut self, force: bool) {
+        let Some(recovery) = self.recovery.as_ref() else {
+            return;
+        };
+        let buffers = (self.primary.memory_changes.iter())
+            .map(|(path, meta)| (path.clone(), meta.content.clone()))
+            .collect();
+        if force {
+            recovery.save(buffers);
+        } else {
+            recovery.save_if_due(buffers, Instant::now());
+        }
+    }
+
+    /// Starts or stops saving the unsaved buffers for crash recovery by the
+    /// configuration. The buffers saved before are removed once disabled.
+    pub(crate) fn change_crash_recovery(&mut self) {
+        match (self.config.crash_recovery, self.recovery.take()) {
+            (true, None) => {
+                self.recovery = RecoveryStore::in_user_data(&self.config.compile.roots);
+                self.persist_overlay(true);
+            }
+            (true, recovery) => self.recovery = recovery,
+            (false, Some(recovery)) => recovery.clear(),
+            (false, None) => {}
+        }
+    }
+
+    /// Offers to restore the unsaved buffers left by a session that ended
+    /// unexpectedly. The restored buffers are applied to the editor as edits,
+    /// which are synchronized back as usual.
+    pub(crate) fn offer_recovery(&self) {
+        let Some(recovery) = &self.recovery else {
+            return;
+        };
+        let files = recovery.load();
+        let open = |path: &Path| {
+            let meta = self.primary.memory_changes.get(path)?;
+            Some(meta.content.text().to_owned())
+        };
+        let edit = recovery_edit(files, open, self.const_config.position_encoding);
+        let count = edit.changes.as_ref().map_or(0, |changes| changes.len());
+        if count == 0 {
+            return;
+        }
+
+        let client = self.client.clone();
+        let action = |title: &str| MessageActionItem {
+            title: title.to_owned(),
+            properties: Default::default(),
+        };
+        let (restore, discard) = (action("Restore"), action("Discard"));
+        let params = ShowMessageRequestParams {
+            typ: MessageType::WARNING,
+            message: format!(
+                "Tinymist found unsaved changes of {count} file(s) from a session that ended \
+                 unexpectedly. Do you want to restore them?"
+            ),
+            actions: Some(vec![restore.clone(), discard]),
+        };
+        tokio::spawn(async move {
+            match client.request::<ShowMessageRequest>(params).await {
+                Ok(Some(action)) if action == restore => {
+                    let params = ApplyWorkspaceEditParams {
+                        label: Some("Restore unsaved changes".to_owned()),
+                        edit,
+                    };
+                    if let Err(err) = client.request::<ApplyWorkspaceEdit>(params).await {
+                        log::warn!("failed to restore unsaved changes: {err}");
+                    }
                 }
+                Ok(_) => log::info!("unsaved changes are discarded"),
+                Err(err) => log::warn!("failed to offer restoring unsaved changes: {err}"),
             }
+        });
+    }
+}
+
+/// The edit replacing the content of the files with the recovered buffers.
+/// The whole content of an open document is replaced, and the files already
+/// having the recovered content are skipped.
+fn recovery_edit(
+    files: Vec<RecoveredFile>,
+    open: impl Fn(&Path) -> Option<String>,
+    encoding: PositionEncoding,
+) -> WorkspaceEdit {
+    let changes = files.into_iter().filter_map(|file| {
+        let uri = path_to_url(&file.path).ok()?;
+        let current = open(&file.path).or_else(|| std::fs::read_to_string(&file.path).ok());
+        let current = Source::detached(current.unwrap_or_default());
+        if current.text() == file.content {
+            return None;
         }
+        let range = typst_to_lsp::range(0..current.text().len(), &current, encoding);
+        let edit = TextEdit {
+            range,
+            new_text: file.content,
+        };
+        Some((uri, vec![edit]))
+    });
+    WorkspaceEdit {
+        changes: Some(changes.collect()),
+        ..Default::default()
+    }
+}
 
-        meta.mt = now;
+/// The diagnostics group of a source refused for exceeding the size limit.
+fn oversized_group(path: &Path) -> String {
+    format!("oversized:{}", path.display())
+}
 
-        let snapshot = FileResult::Ok((now, meta.content.text().as_bytes().into())).into();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
 
-        let files = FileChangeSet::new_inserts(vec![(path.clone(), snapshot)]);
+    use async_lsp::MainLoop;
+    use lsp_types::{Position, Range};
+    use parking_lot::Mutex;
 
-        self.update_source(files)
+    use crate::actor::typ_client::tests::compile_state;
+
+    /// Opens a document in the compile state, as `textDocument/didOpen` does.
+    fn open_document(state: &mut CompileState, path: &str) {
+        let meta = MemoryFileMeta {
+            mt: Time::now(),
+            content: Source::detached(""),
+            stale: false,
+        };
+        state.memory_changes.insert(Path::new(path).into(), meta);
+    }
+
+    #[tokio::test]
+    async fn test_active_entry() {
+        let (mut state, _editor_rx) = compile_state("");
+        let main = Some(PathBuf::from("/doc/main.typ"));
+        let focused = ActiveEntry::new(&state, false, false);
+        assert_eq!(focused.entry, main);
+        assert!(!focused.pinned);
+        assert_eq!(focused.source, EntrySource::Focus);
+        assert_eq!(
+            ActiveEntry::new(&state, false, true).source,
+            EntrySource::Default
+        );
+
+        // The entry is reported by the compiler after pinning a file.
+        let chapter = Path::new("/doc/chapter.typ");
+        state.do_change_entry(Some(chapter.into())).await.unwrap();
+        let pinned = ActiveEntry::new(&state, true, false);
+        assert_eq!(pinned.entry.as_deref(), Some(chapter));
+        assert!(pinned.pinned);
+        assert_eq!(pinned.source, EntrySource::Pin);
+    }
+
+    #[tokio::test]
+    async fn test_open_entries() {
+        let (mut state, _editor_rx) = compile_state("");
+        for path in ["/doc/slides.typ", "/doc/main.typ", "/doc/chapter.typ"] {
+            open_document(&mut state, path);
+        }
+
+        let entries = OpenEntry::collect(&state, &[]);
+        let paths: Vec<_> = entries.iter().map(|e| e.path.to_str().unwrap()).collect();
+        assert_eq!(
+            paths,
+            ["/doc/chapter.typ", "/doc/main.typ", "/doc/slides.typ"]
+        );
+        let groups: Vec<_> = entries.iter().map(|e| e.group.as_deref()).collect();
+        assert_eq!(groups, [None, Some("primary"), None]);
+
+        // The group follows the entry of the compiler.
+        let chapter = Path::new("/doc/chapter.typ");
+        state.do_change_entry(Some(chapter.into())).await.unwrap();
+        let entries = OpenEntry::collect(&state, &[]);
+        let groups: Vec<_> = entries.iter().map(|e| e.group.as_deref()).collect();
+        assert_eq!(groups, [Some("primary"), None, None]);
+    }
+
+    fn insert_at_start(text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(Position::new(0, 0), Position::new(0, 0))),
+            range_length: None,
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_refuse_oversized_changes() {
+        let mut meta = MemoryFileMeta {
+            mt: Time::now(),
+            content: Source::detached("Hello"),
+            stale: false,
+        };
+        let utf16 = PositionEncoding::Utf16;
+
+        let res = meta.apply_changes(vec![insert_at_start("Hi. ")], utf16, 10);
+        assert_eq!(res, Ok(true));
+        assert_eq!(meta.content.text(), "Hi. Hello");
+
+        let res = meta.apply_changes(vec![insert_at_start("Hey. ")], utf16, 10);
+        assert_eq!(res, Err(14));
+        assert_eq!(meta.content.text(), "Hi. Hello");
+
+        // The ranges of the client refer to the refused content, so the
+        // incremental edits are ignored.
+        let res = meta.apply_changes(vec![insert_at_start("!")], utf16, 10);
+        assert_eq!(res, Ok(false));
+        assert_eq!(meta.content.text(), "Hi. Hello");
+
+        // Until the full text is synchronized.
+        let full = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "Hey.".to_owned(),
+        };
+        let res = meta.apply_changes(vec![insert_at_start("?"), full], utf16, 10);
+        assert_eq!(res, Ok(true));
+        assert_eq!(meta.content.text(), "Hey.");
+        let res = meta.apply_changes(vec![insert_at_start("!")], utf16, 10);
+        assert_eq!(res, Ok(true));
+        assert_eq!(meta.content.text(), "!Hey.");
+    }
+
+    #[tokio::test]
+    async fn test_refuse_oversized_sources() {
+        let (mut state, mut editor_rx) = compile_state("");
+        let utf16 = PositionEncoding::Utf16;
+        let mut refused = || match editor_rx.try_recv() {
+            Ok(EditorRequest::Diag(group, diags)) => {
+                assert!(group.starts_with("oversized:"), "{group}");
+                let diags = diags.map(|diags| diags.into_values().flatten().collect::<Vec<_>>());
+                diags.map(|diags| diags[0].message.clone())
+            }
+            _ => panic!("no diagnostics are sent"),
+        };
+
+        // A source too large is refused at opening, with a diagnostic.
+        let big: ImmutPath = Path::new("/doc/big.typ").into();
+        let files = state.open_memory_source(big.clone(), "x".repeat(11), 10);
+        assert!(files.is_none());
+        assert!(!state.memory_changes.contains_key(&big));
+        let message = refused().unwrap();
+        assert!(message.contains("(11 bytes)"), "{message}");
+        // Its edits are ignored rather than failing.
+        let edit = state.edit_memory_source(big.clone(), vec![insert_at_start("!")], utf16, 10);
+        assert!(edit.unwrap().is_none());
+
+        // An edit making a source too large is refused, keeping its content.
+        let main: ImmutPath = Path::new("/doc/main.typ").into();
+        let files = state.open_memory_source(main.clone(), "Hello".to_owned(), 10);
+        assert!(files.is_some());
+        let changes = vec![insert_at_start("Hey there. ")];
+        let edit = state.edit_memory_source(main.clone(), changes, utf16, 10);
+        assert!(edit.unwrap().is_none());
+        assert_eq!(state.memory_changes[&main].content.text(), "Hello");
+        let message = refused().unwrap();
+        assert!(message.contains("(16 bytes)"), "{message}");
+
+        // The diagnostic is cleared once the source fits again.
+        let full = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "Hey.".to_owned(),
+        };
+        let edit = state.edit_memory_source(main.clone(), vec![full], utf16, 10);
+        assert!(edit.unwrap().is_some());
+        assert_eq!(state.memory_changes[&main].content.text(), "Hey.");
+        assert_eq!(refused(), None);
+    }
+
+    #[test]
+    fn test_edit_crlf() {
+        use typst::eval::Tracer;
+
+        use crate::tools::tests::TestWorld;
+
+        let mut meta = MemoryFileMeta {
+            mt: Time::now(),
+            content: Source::detached("#let x = 1\r\n#x\r\n"),
+            stale: false,
+        };
+        let replace = |range: Range, text: &str| TextDocumentContentChangeEvent {
+            range: Some(range),
+            range_length: None,
+            text: text.to_owned(),
+        };
+        let changes = vec![
+            replace(Range::new(Position::new(1, 1), Position::new(1, 2)), "y"),
+            // Columns beyond the end of a line refer to its end, before `\r\n`.
+            replace(Range::new(Position::new(0, 11), Position::new(0, 11)), "0"),
+        ];
+        let res = meta.apply_changes(changes, PositionEncoding::Utf16, usize::MAX);
+        assert_eq!(res, Ok(true));
+        assert_eq!(meta.content.text(), "#let x = 10\r\n#y\r\n");
+
+        let world = TestWorld::new(meta.content.text());
+        let errors = typst::compile(&world, &mut Tracer::new()).unwrap_err();
+        assert_eq!(errors[0].message, "unknown variable: y");
+        let source = world.main();
+        let range = source.range(errors[0].span).unwrap();
+        let range = typst_to_lsp::range(range, &source, PositionEncoding::Utf16);
+        assert_eq!(range, Range::new(Position::new(1, 1), Position::new(1, 2)));
+
+        // Offsets in a line break are at the end of the line.
+        let position = typst_to_lsp::offset_to_position(12, PositionEncoding::Utf16, &source);
+        assert_eq!(position, Position::new(0, 11));
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        let root = std::env::temp_dir().join(format!("tinymist-norm-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        std::fs::write(root.join("a/main.typ"), "").unwrap();
+        let root = normalize_path(&root, &[]).to_path_buf();
+        let roots = [root.clone()];
+
+        let direct = normalize_path(&root.join("a/main.typ"), &roots);
+        let dotted = normalize_path(&root.join("a/../a/./main.typ"), &roots);
+        assert_eq!(direct, dotted);
+        assert_eq!(direct.as_ref(), root.join("a").join("main.typ"));
+
+        // Files not yet on disk are normalized lexically.
+        let missing = normalize_path(&root.join("a/../b/main.typ"), &roots);
+        assert_eq!(missing.as_ref(), root.join("b").join("main.typ"));
+
+        let config = CompileConfig {
+            roots: roots.to_vec(),
+            ..CompileConfig::default()
+        };
+        let direct = config.determine_entry(Some(direct));
+        let dotted = config.determine_entry(Some(dotted));
+        assert_eq!(direct.main(), dotted.main());
+        assert_eq!(direct.root(), dotted.root());
+
+        #[cfg(unix)]
+        {
+            let link = root.join("link");
+            let _ = std::fs::remove_file(&link);
+            std::os::unix::fs::symlink(root.join("a"), &link).unwrap();
+            let linked = normalize_path(&link.join("main.typ"), &roots);
+            assert_eq!(linked.as_ref(), root.join("a").join("main.typ"));
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_recovery_edit() {
+        let recovered = |path: &str, content: &str| RecoveredFile {
+            path: PathBuf::from(path),
+            content: content.to_owned(),
+        };
+        let files = vec![
+            recovered("/ws/open.typ", "= Recovered"),
+            recovered("/ws/same.typ", "= Same"),
+        ];
+        let open = |path: &Path| match path.to_str()? {
+            "/ws/open.typ" => Some("= Open\nwith two lines".to_owned()),
+            "/ws/same.typ" => Some("= Same".to_owned()),
+            _ => None,
+        };
+
+        let edit = recovery_edit(files, open, PositionEncoding::Utf16);
+        let changes = edit.changes.unwrap();
+        // The documents already having the recovered content are skipped.
+        assert_eq!(changes.len(), 1);
+        let (uri, edits) = changes.into_iter().next().unwrap();
+        assert!(uri.path().ends_with("open.typ"), "{uri}");
+        // The whole open document is replaced.
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(0, 0), Position::new(1, 14))
+        );
+        assert_eq!(edits[0].new_text, "= Recovered");
+    }
+
+    #[test]
+    fn test_scoped_config_request() {
+        let ws = |path: &str| {
+            let root = if cfg!(windows) { "C:\\ws" } else { "/ws" };
+            Path::new(root).join(path)
+        };
+        let entries = [ws("a/main.typ"), ws("b/main.typ"), ws("ab/main.typ")];
+
+        // Only the entries in the added folders are requested again.
+        let requested = entries_in(entries.clone(), &[ws("a")]);
+        assert_eq!(requested, vec![ws("a/main.typ")]);
+        assert!(entries_in(entries, &[]).is_empty());
+
+        // The items are scoped to the entry.
+        let params = scoped_config_params(&requested[0]).unwrap();
+        let scope = path_to_url(&requested[0]).unwrap();
+        assert!(params
+            .items
+            .iter()
+            .all(|item| item.scope_uri.as_ref() == Some(&scope)));
+    }
+
+    /// A server recording the configuration applied to each entry.
+    #[derive(Default)]
+    struct ScopedServer {
+        config: LanguageConfig,
+        applied: Arc<Mutex<Vec<(PathBuf, CompileConfig)>>>,
+    }
+
+    impl ScopedConfigHandler for ScopedServer {
+        fn apply_scoped_config(
+            &mut self,
+            entry: &Path,
+            values: Vec<JsonValue>,
+        ) -> anyhow::Result<()> {
+            let config = self.config.scoped_compile(values)?;
+            self.applied.lock().push((entry.to_owned(), config));
+            Ok(())
+        }
+    }
+
+    /// Stops the main loop once the events before it are handled.
+    struct Stop;
+
+    async fn run_events(
+        router: Router<ScopedServer>,
+        events: Vec<ScopedConfigEvent>,
+    ) -> async_lsp::Result<()> {
+        let (main_loop, client) = MainLoop::new_server(|_| {
+            let mut router = router;
+            router.event::<Stop>(|_, Stop| ControlFlow::Break(Ok(())));
+            router
+        });
+        for event in events {
+            client.emit(event).unwrap();
+        }
+        client.emit(Stop).unwrap();
+        main_loop
+            .run(futures::io::empty(), futures::io::sink())
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_route_scoped_config() {
+        let mut server = ScopedServer::default();
+        server
+            .config
+            .update(&serde_json::json!({ "outputPath": "$root/out/$name" }))
+            .unwrap();
+        let applied = server.applied.clone();
+        let entry = PathBuf::from("/ws/a/main.typ");
+        let event = || {
+            let mut values = vec![JsonValue::Null; LanguageConfig::get_items(None).len()];
+            values[0] = "$root/a-out/$name".into();
+            ScopedConfigEvent {
+                entry: entry.clone(),
+                values,
+            }
+        };
+
+        // Without the handler, the event breaks the main loop.
+        let res = run_events(Router::new(ScopedServer::default()), vec![event()]).await;
+        assert!(res.is_err());
+
+        let mut router = Router::new(server);
+        route_scoped_config(&mut router);
+        run_events(router, vec![event()]).await.unwrap();
+
+        // The answered values are applied over the configuration of the
+        // workspace.
+        let applied = applied.lock();
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].0, entry);
+        assert_eq!(applied[0].1.output_path, "$root/a-out/$name");
     }
 }
@@ -1,4 +1,5 @@
 use std::iter::zip;
+use std::ops::Range;
 
 use lsp_types::request::Formatting;
 use lsp_types::TextEdit;
@@ -11,58 +12,221 @@ pub async fn format(
     src: Source,
     mode: FormatterMode,
     width: usize,
+    cjk_mode: bool,
     position_encoding: PositionEncoding,
 ) -> ResponseResult<Formatting> {
     match mode {
         FormatterMode::Typstyle => {
             let res = typstyle_core::Typstyle::new_with_src(src.clone(), width).pretty_print();
+            let res = with_line_endings_of(&src, res);
             Ok(calc_diff(src, res, position_encoding))
         }
         FormatterMode::Typstfmt => {
-            let config = typstfmt_lib::Config {
-                max_line_length: width,
-                ..typstfmt_lib::Config::default()
-            };
-            let res = typstfmt_lib::format(src.text(), config);
+            let res = typstfmt_lib::format(src.text(), typstfmt_config(width, cjk_mode));
+            let res = with_line_endings_of(&src, res);
             Ok(calc_diff(src, res, position_encoding))
         }
         FormatterMode::Disable => Ok(None),
     }
 }
 
-/// A simple implementation of the diffing algorithm, borrowed from
-/// [`Source::replace`].
+/// Get the configuration of `typstfmt`.
+///
+/// Text is not wrapped in the CJK mode, as a line break between CJK and Latin
+/// text is rendered as a space, unlike one between CJK characters.
+fn typstfmt_config(width: usize, cjk_mode: bool) -> typstfmt_lib::Config {
+    typstfmt_lib::Config {
+        max_line_length: width,
+        line_wrap: !cjk_mode,
+        ..typstfmt_lib::Config::default()
+    }
+}
+
+/// Use the line endings of the source in the formatted text, which is `\r\n`
+/// if the first line of the source ends with it, so that formatting keeps the
+/// line endings of the client.
+fn with_line_endings_of(src: &Source, formatted: String) -> String {
+    let crlf = src.text().split_inclusive('\n').next();
+    let crlf = crlf.is_some_and(|line| line.ends_with("\r\n"));
+    let formatted = formatted.replace("\r\n", "\n");
+    if crlf {
+        formatted.replace('\n', "\r\n")
+    } else {
+        formatted
+    }
+}
+
+/// The maximum product of the numbers of changed old and new lines to diff by
+/// lines, beyond which they are replaced as a whole.
+const MAX_DIFF_CELLS: usize = 4 << 20;
+
+/// Diff the formatted text by lines, so that the unchanged lines between the
+/// changed ones are kept, along with the cursors of clients in them.
 fn calc_diff(prev: Source, next: String, encoding: PositionEncoding) -> Option<Vec<TextEdit>> {
-    let old = prev.text();
-    let new = &next;
+    let old: Vec<_> = prev.text().split_inclusive('\n').collect();
+    let new: Vec<_> = next.split_inclusive('\n').collect();
 
-    let mut prefix = zip(old.bytes(), new.bytes())
+    let prefix = zip(&old, &new).take_while(|(x, y)| x == y).count();
+    let suffix = zip(old[prefix..].iter().rev(), new[prefix..].iter().rev())
         .take_while(|(x, y)| x == y)
         .count();
+    let hunks = diff_lines(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
 
-    if prefix == old.len() && prefix == new.len() {
-        return Some(vec![]);
+    // The byte offsets of the old lines.
+    let mut offsets = Vec::with_capacity(old.len() + 1);
+    offsets.push(0);
+    for line in &old {
+        offsets.push(offsets.last().unwrap() + line.len());
     }
 
-    while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) {
-        prefix -= 1;
+    let edits = hunks.into_iter().map(|hunk| {
+        let (old_lines, new_lines) = (hunk.old.start + prefix, hunk.new.start + prefix);
+        let replace = offsets[old_lines]..offsets[old_lines + hunk.old.len()];
+        TextEdit {
+            range: typst_to_lsp::range(replace, &prev, encoding),
+            new_text: new[new_lines..new_lines + hunk.new.len()].concat(),
+        }
+    });
+    Some(edits.collect())
+}
+
+/// Replaced lines in the old text and the lines replacing them.
+#[derive(Debug, PartialEq)]
+struct Hunk {
+    old: Range<usize>,
+    new: Range<usize>,
+}
+
+/// Find the hunks turning the old lines into the new ones, by the longest
+/// common subsequence of the lines.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Hunk> {
+    if old.is_empty() && new.is_empty() {
+        return vec![];
+    }
+    if old.len().saturating_mul(new.len()) > MAX_DIFF_CELLS {
+        return vec![Hunk {
+            old: 0..old.len(),
+            new: 0..new.len(),
+        }];
     }
 
-    let mut suffix = zip(old[prefix..].bytes().rev(), new[prefix..].bytes().rev())
-        .take_while(|(x, y)| x == y)
-        .count();
+    // The length of the longest common subsequence of `old[i..]` and `new[j..]`.
+    let width = new.len() + 1;
+    let mut lcs = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i * width + j] = if old[i] == new[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let mut hunk: Option<Hunk> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            hunks.extend(hunk.take());
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        let hunk = hunk.get_or_insert(Hunk {
+            old: i..i,
+            new: j..j,
+        });
+        let delete = i < old.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1];
+        if j == new.len() || delete {
+            i += 1;
+            hunk.old.end = i;
+        } else {
+            j += 1;
+            hunk.new.end = j;
+        }
+    }
+    hunks.extend(hunk);
+    hunks
+}
 
-    while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix) {
-        suffix += 1;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cjk_mode_config() {
+        assert!(typstfmt_config(120, false).line_wrap);
+        let config = typstfmt_config(120, true);
+        assert!(!config.line_wrap);
+        assert_eq!(config.max_line_length, 120);
+    }
+
+    fn lines(n: usize) -> String {
+        (1..=n).map(|i| format!("line {i}\n")).collect()
+    }
+
+    #[test]
+    fn test_diff_changed_line() {
+        let prev = Source::detached(lines(20));
+        let next = prev.text().replace("line 10\n", "line  10\n");
+        let edits = calc_diff(prev, next, PositionEncoding::Utf16).unwrap();
+        assert_eq!(edits.len(), 1, "{edits:?}");
+        assert_eq!(edits[0].range.start, lsp_types::Position::new(9, 0));
+        assert_eq!(edits[0].range.end, lsp_types::Position::new(10, 0));
+        assert_eq!(edits[0].new_text, "line  10\n");
     }
 
-    let replace = prefix..old.len() - suffix;
-    let with = &new[prefix..new.len() - suffix];
+    #[test]
+    fn test_keep_line_endings() {
+        let crlf = Source::detached("#let a = 1\r\n#a\r\n");
+        let formatted = with_line_endings_of(&crlf, "#let a = 1\n#a\n".to_owned());
+        assert_eq!(formatted, crlf.text());
+        let edits = calc_diff(crlf, formatted, PositionEncoding::Utf16);
+        assert_eq!(edits, Some(vec![]));
+
+        let lf = Source::detached("#let a = 1\n#a\n");
+        let formatted = with_line_endings_of(&lf, "#let a = 1\r\n#a\r\n".to_owned());
+        assert_eq!(formatted, lf.text());
+    }
 
-    let range = typst_to_lsp::range(replace, &prev, encoding);
+    #[test]
+    fn test_diff_hunks() {
+        let old = lines(6);
+        let old: Vec<_> = old.split_inclusive('\n').collect();
+        let new = [
+            "line 1\n",
+            "inserted\n",
+            "line 2\n",
+            "line 3\n",
+            "line 5\n",
+            "line 6\n",
+        ];
+        let hunks = diff_lines(&old, &new);
+        assert_eq!(
+            hunks,
+            [
+                Hunk {
+                    old: 1..1,
+                    new: 1..2,
+                },
+                Hunk {
+                    old: 3..4,
+                    new: 4..4,
+                },
+            ]
+        );
 
-    Some(vec![TextEdit {
-        new_text: with.to_owned(),
-        range,
-    }])
+        let prev = Source::detached(lines(20));
+        let edits = calc_diff(
+            prev.clone(),
+            prev.text().to_owned(),
+            PositionEncoding::Utf16,
+        );
+        assert_eq!(edits, Some(vec![]));
+    }
 }
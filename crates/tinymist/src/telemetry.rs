@@ -0,0 +1,233 @@
+//! Counters and latencies of requests, to help diagnosing performance issues,
+//! and a log of the latest compilations, to help diagnosing intermittent
+//! failures.
+//!
+//! The latencies are kept in a fixed-size window per method, so that recording
+//! a request never allocates once the method is seen.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tinymist_query::RequestStats;
+
+use crate::actor::editor::TinymistCompileStatusEnum;
+
+/// The number of latest latencies kept for each method.
+const WINDOW: usize = 128;
+
+/// The statistics of requests, keyed by the type name of requests.
+#[derive(Default)]
+pub struct RequestTelemetry {
+    methods: Mutex<HashMap<&'static str, MethodStats>>,
+}
+
+impl RequestTelemetry {
+    /// Start timing a request, which is recorded when the returned timer is
+    /// dropped.
+    pub fn start<T>(self: &Arc<Self>, _req: &T) -> RequestTimer {
+        RequestTimer {
+            telemetry: self.clone(),
+            method: std::any::type_name::<T>(),
+            start: Instant::now(),
+        }
+    }
+
+    fn record(&self, method: &'static str, elapsed: Duration) {
+        let micros = elapsed.as_micros().min(u32::MAX as u128) as u32;
+        let mut methods = self.methods.lock();
+        let stats = methods.entry(method).or_insert_with(|| MethodStats {
+            count: 0,
+            latencies: [0; WINDOW],
+        });
+        stats.latencies[(stats.count % WINDOW as u64) as usize] = micros;
+        stats.count += 1;
+    }
+
+    /// Get the statistics of all requests, sorted by method.
+    pub fn snapshot(&self) -> Vec<RequestStats> {
+        let methods = self.methods.lock();
+        let mut stats: Vec<_> = (methods.iter())
+            .map(|(method, stats)| {
+                let len = (stats.count as usize).min(WINDOW);
+                let mut latencies = stats.latencies;
+                latencies[..len].sort_unstable();
+                let percentile = |p: f64| {
+                    let idx = ((len - 1) as f64 * p).round() as usize;
+                    latencies[idx] as f64 / 1000.
+                };
+                RequestStats {
+                    method: method_name(method),
+                    count: stats.count,
+                    p50_ms: percentile(0.5),
+                    p95_ms: percentile(0.95),
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.method.cmp(&b.method));
+        stats
+    }
+
+    /// Clear the statistics of all requests.
+    pub fn reset(&self) {
+        self.methods.lock().clear();
+    }
+}
+
+/// The number of compilations kept in the compile log by default.
+pub const DEFAULT_COMPILE_LOG_SIZE: usize = 50;
+
+/// A compilation recorded in the compile log.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompileEvent {
+    /// The time the compilation ended, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// The group of the compiler, e.g. `primary`.
+    pub group: String,
+    pub entry: Option<PathBuf>,
+    pub status: TinymistCompileStatusEnum,
+    pub errors: usize,
+    pub warnings: usize,
+    pub duration_ms: u64,
+}
+
+/// The latest compilations, oldest first, dropping the oldest ones beyond the
+/// capacity.
+pub struct CompileLog {
+    events: Mutex<VecDeque<CompileEvent>>,
+    capacity: Mutex<usize>,
+}
+
+impl Default for CompileLog {
+    fn default() -> Self {
+        Self {
+            events: Mutex::default(),
+            capacity: Mutex::new(DEFAULT_COMPILE_LOG_SIZE),
+        }
+    }
+}
+
+impl CompileLog {
+    /// Record a compilation ending now.
+    pub fn record(
+        &self,
+        group: String,
+        entry: Option<PathBuf>,
+        status: TinymistCompileStatusEnum,
+        errors: usize,
+        warnings: usize,
+        duration: Duration,
+    ) {
+        let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+        let event = CompileEvent {
+            timestamp_ms: timestamp.map_or(0, |t| t.as_millis() as u64),
+            group,
+            entry,
+            status,
+            errors,
+            warnings,
+            duration_ms: duration.as_millis() as u64,
+        };
+
+        let capacity = *self.capacity.lock();
+        let mut events = self.events.lock();
+        events.push_back(event);
+        while events.len() > capacity {
+            events.pop_front();
+        }
+    }
+
+    /// Change the number of compilations kept, dropping the oldest ones
+    /// beyond it.
+    pub fn set_capacity(&self, capacity: usize) {
+        *self.capacity.lock() = capacity;
+        let mut events = self.events.lock();
+        while events.len() > capacity {
+            events.pop_front();
+        }
+    }
+
+    /// Get the recorded compilations, oldest first.
+    pub fn snapshot(&self) -> Vec<CompileEvent> {
+        self.events.lock().iter().cloned().collect()
+    }
+}
+
+struct MethodStats {
+    count: u64,
+    /// The latencies in microseconds, as a ring buffer indexed by the count.
+    latencies: [u32; WINDOW],
+}
+
+/// A timer recording the latency of a request when dropped.
+pub struct RequestTimer {
+    telemetry: Arc<RequestTelemetry>,
+    method: &'static str,
+    start: Instant,
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        self.telemetry.record(self.method, self.start.elapsed());
+    }
+}
+
+/// Get the method name from the type name of a request, e.g.
+/// `tinymist_query::hover::HoverRequest` is named `hover`.
+fn method_name(type_name: &str) -> String {
+    let name = type_name.rsplit("::").next().unwrap_or(type_name);
+    let name = name.strip_suffix("Request").unwrap_or(name);
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tinymist_query::{CompletionRequest, HoverRequest};
+
+    use super::*;
+
+    fn hover() -> HoverRequest {
+        HoverRequest {
+            path: "/s0.typ".into(),
+            position: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_count_requests() {
+        let telemetry = Arc::new(RequestTelemetry::default());
+        for _ in 0..200 {
+            drop(telemetry.start(&hover()));
+        }
+        drop(telemetry.start(&CompletionRequest {
+            path: "/s0.typ".into(),
+            position: Default::default(),
+            explicit: false,
+        }));
+
+        let stats = telemetry.snapshot();
+        let methods: Vec<_> = stats.iter().map(|s| (s.method.as_str(), s.count)).collect();
+        assert_eq!(methods, vec![("completion", 1), ("hover", 200)]);
+        assert!(stats[1].p50_ms <= stats[1].p95_ms);
+
+        telemetry.reset();
+        assert!(telemetry.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_method_name() {
+        assert_eq!(
+            method_name("tinymist_query::semantic_tokens_full::SemanticTokensFullRequest"),
+            "semanticTokensFull"
+        );
+        assert_eq!(method_name("HoverRequest"), "hover");
+    }
+}
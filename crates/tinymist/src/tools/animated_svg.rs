@@ -0,0 +1,129 @@
+//! Export documents rendered at several steps as an animated SVG.
+//!
+//! The document is compiled once for each step, with the step number visible
+//! as `sys.inputs.step`. The first page of each step becomes a frame, and the
+//! frames are shown one after another by CSS keyframes.
+
+use std::fmt::Write;
+
+use anyhow::bail;
+use tinymist_query::OverlayWorld;
+use typst::eval::Tracer;
+use typst::foundations::{Dict, Value};
+use typst::{Library, World};
+
+/// The default number of steps.
+pub const DEFAULT_STEPS: usize = 2;
+/// The default duration of each step in milliseconds.
+pub const DEFAULT_DELAY_MS: u64 = 1000;
+/// The maximum number of steps, as the document is compiled for each step.
+const MAX_STEPS: usize = 100;
+
+/// Check the options of an animated SVG.
+pub fn validate_options(steps: usize, delay_ms: u64) -> anyhow::Result<()> {
+    if steps == 0 || steps > MAX_STEPS {
+        bail!("steps must be between 1 and {MAX_STEPS}, got {steps}");
+    }
+    if delay_ms == 0 {
+        bail!("delayMs must be positive");
+    }
+    Ok(())
+}
+
+/// Compile the main file of the world with `sys.inputs.step` set to `1` to
+/// `steps`, and animate the first pages of the steps, each shown for
+/// `delay_ms` milliseconds.
+pub fn animated_svg(world: &dyn World, steps: usize, delay_ms: u64) -> anyhow::Result<String> {
+    validate_options(steps, delay_ms)?;
+
+    let mut frames = vec![];
+    for step in 1..=steps {
+        let mut inputs = Dict::new();
+        inputs.insert("step".into(), Value::Str(step.to_string().into()));
+        let world = OverlayWorld::new(world).with_library(inputs_library(world, inputs));
+        let doc = match typst::compile(&world, &mut Tracer::new()) {
+            Ok(doc) => doc,
+            Err(errors) => {
+                let message = errors.first().map(|e| e.message.as_str()).unwrap_or("");
+                bail!("step {step} cannot be compiled: {message}");
+            }
+        };
+        let Some(page) = doc.pages.first() else {
+            bail!("step {step} produces no page");
+        };
+        frames.push(page.frame.clone());
+    }
+
+    let width = frames.iter().map(|f| f.width().to_pt()).fold(0., f64::max);
+    let height = frames.iter().map(|f| f.height().to_pt()).fold(0., f64::max);
+    let total_ms = delay_ms * steps as u64;
+    let shown = 100. / steps as f64;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {width} {height}" width="{width}pt" height="{height}pt">"#
+    )?;
+    writeln!(
+        svg,
+        "<style>@keyframes step {{ 0% {{ visibility: visible }} {shown}% {{ visibility: hidden }} }} \
+         .frame {{ visibility: hidden; animation: step {total_ms}ms step-end infinite }}</style>"
+    )?;
+    for (idx, frame) in frames.iter().enumerate() {
+        let delay = delay_ms * idx as u64;
+        writeln!(
+            svg,
+            r#"<g class="frame" style="animation-delay: {delay}ms">{}</g>"#,
+            typst_svg::svg(frame)
+        )?;
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// Get the library of a world with some of its `sys.inputs` overridden.
+pub(crate) fn inputs_library(base: &dyn World, overrides: Dict) -> Library {
+    let mut inputs = base_inputs(base.library()).unwrap_or_default();
+    for (key, value) in overrides {
+        inputs.insert(key, value);
+    }
+    Library::builder().with_inputs(inputs).build()
+}
+
+/// Get the inputs of a library, which are visible through `sys.inputs`.
+fn base_inputs(library: &Library) -> Option<Dict> {
+    let Value::Module(sys) = library.global.scope().get("sys")? else {
+        return None;
+    };
+    match sys.scope().get("inputs")? {
+        Value::Dict(inputs) => Some(inputs.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    #[test]
+    fn test_two_steps() {
+        let world = TestWorld::new(
+            "#set page(width: 60pt, height: 40pt)\n\
+             #let step = int(sys.inputs.at(\"step\", default: \"1\"))\n\
+             Step #step #if step > 1 [and more]",
+        );
+        let svg = animated_svg(&world, 2, 500).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches(r#"<g class="frame""#).count(), 2);
+        assert!(svg.contains("animation: step 1000ms"));
+        assert!(svg.contains("animation-delay: 500ms"));
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let world = TestWorld::new("Hello");
+        assert!(animated_svg(&world, 0, 500).is_err());
+        assert!(animated_svg(&world, 2, 0).is_err());
+    }
+}
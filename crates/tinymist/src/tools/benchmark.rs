@@ -0,0 +1,93 @@
+//! Time repeated compilations of documents, to get stable numbers when
+//! optimizing slow documents.
+//!
+//! The first compilation is cold, as the main file gets a fresh id that no
+//! memoized result refers to, and the rest are warm, reusing the results of the
+//! previous ones. The memoized results of other compilations are kept, so the
+//! files imported by the main file may still be reused by the cold one.
+
+use std::time::Instant;
+
+use anyhow::bail;
+use serde::Serialize;
+use tinymist_query::OverlayWorld;
+use typst::eval::Tracer;
+use typst::syntax::{FileId, Source};
+use typst::World;
+
+/// The number of warm compilations if not given.
+pub const DEFAULT_ITERATIONS: usize = 10;
+const MAX_ITERATIONS: usize = 100;
+
+/// The timings of the compilations of a document, in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchmarkStats {
+    pub cold_ms: f64,
+    pub warm_ms: Vec<f64>,
+    /// The mean of the warm compilations.
+    pub mean_ms: f64,
+    /// The standard deviation of the warm compilations.
+    pub std_dev_ms: f64,
+}
+
+/// Check the number of warm compilations.
+pub fn validate_iterations(iterations: usize) -> anyhow::Result<()> {
+    if !(1..=MAX_ITERATIONS).contains(&iterations) {
+        bail!("iterations must be between 1 and {MAX_ITERATIONS}, got {iterations}");
+    }
+    Ok(())
+}
+
+/// Compile the source as the entry once cold and then `iterations` times warm.
+pub fn benchmark_document(
+    world: &dyn World,
+    main: Source,
+    iterations: usize,
+) -> anyhow::Result<BenchmarkStats> {
+    let cold_id = FileId::new_fake(main.id().vpath().clone());
+    let cold_main = Source::new(cold_id, main.text().to_owned());
+    let cold_ms = time_compile(&OverlayWorld::new(world).with_main(cold_main))?;
+
+    let world = OverlayWorld::new(world).with_main(main);
+    let warm_ms = (0..iterations)
+        .map(|_| time_compile(&world))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mean_ms = warm_ms.iter().sum::<f64>() / warm_ms.len() as f64;
+    let variance =
+        warm_ms.iter().map(|ms| (ms - mean_ms).powi(2)).sum::<f64>() / warm_ms.len() as f64;
+    Ok(BenchmarkStats {
+        cold_ms,
+        warm_ms,
+        mean_ms,
+        std_dev_ms: variance.sqrt(),
+    })
+}
+
+fn time_compile(world: &dyn World) -> anyhow::Result<f64> {
+    let start = Instant::now();
+    if let Err(errors) = typst::compile(world, &mut Tracer::new()) {
+        let message = errors.first().map(|e| e.message.as_str()).unwrap_or("");
+        bail!("the document cannot be compiled: {message}");
+    }
+    Ok(start.elapsed().as_secs_f64() * 1000.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    #[test]
+    fn test_benchmark_document() {
+        let world = TestWorld::new("= Benchmark\n#lorem(100)");
+        let stats = benchmark_document(&world, world.main(), 3).unwrap();
+        assert_eq!(stats.warm_ms.len(), 3);
+        assert!(stats.cold_ms > 0.);
+        assert!(stats.mean_ms > 0. && stats.std_dev_ms >= 0.);
+
+        assert!(validate_iterations(0).is_err());
+        assert!(validate_iterations(MAX_ITERATIONS + 1).is_err());
+    }
+}
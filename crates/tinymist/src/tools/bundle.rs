@@ -0,0 +1,249 @@
+//! Bundle a document along with the files it depends on into a zip archive,
+//! which can be compiled elsewhere without the workspace or package cache.
+//!
+//! The files are recorded while compiling the document, so only the files
+//! actually read are bundled. Files of packages are placed in a `packages`
+//! directory structured as the package cache, which can be used as the package
+//! cache path, and the used fonts in a `fonts` directory. A `tinymist.toml`
+//! project file records the entry, the root, the inputs and the font paths,
+//! so that the extracted bundle is opened as a project.
+
+use std::collections::{BTreeSet, HashSet};
+use std::path::PathBuf;
+
+use anyhow::bail;
+use parking_lot::Mutex;
+use tinymist_query::{OverlayWorld, WorldHooks};
+use typst::diag::FileResult;
+use typst::eval::Tracer;
+use typst::foundations::{Bytes, Dict, Repr, Value};
+use typst::layout::{Frame, FrameItem};
+use typst::model::Document;
+use typst::syntax::{FileId, Source};
+use typst::text::Font;
+use typst::World;
+
+use super::zip::ZipWriter;
+use crate::{ProjectConfig, PROJECT_FILE};
+
+/// The name of the manifest in the archive, which is the project file.
+pub const MANIFEST: &str = PROJECT_FILE;
+
+/// A document bundled into a zip archive.
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    pub zip: Vec<u8>,
+    /// The names of the files in the archive, starting with the entry.
+    pub files: Vec<String>,
+}
+
+/// Compile the source as the entry and bundle it along with its dependencies.
+pub fn bundle_document(
+    world: &dyn World,
+    main: Source,
+    inputs: &Dict,
+    exclude_fonts: bool,
+) -> anyhow::Result<Bundle> {
+    let entry = archive_path(main.id());
+    let world = OverlayWorld::new(world)
+        .with_main(main)
+        .with_hooks(RecordingHooks::default());
+    let doc = match typst::compile(&world, &mut Tracer::new()) {
+        Ok(doc) => doc,
+        Err(errors) => {
+            let message = errors.first().map(|e| e.message.as_str()).unwrap_or("");
+            bail!("the document cannot be compiled: {message}");
+        }
+    };
+
+    let mut zip = ZipWriter::default();
+    let mut files = vec![];
+    // Taken before reading the files to bundle, which are recorded as well.
+    let ids = std::mem::take(&mut *world.hooks.accessed.lock());
+    // The entry comes first, so that it is easy to spot.
+    let main_id = world.main().id();
+    for id in std::iter::once(main_id).chain(ids.into_iter().filter(|id| *id != main_id)) {
+        let content = match id.vpath().as_rooted_path().extension() {
+            Some(ext) if ext == "typ" => world.source(id).map(|s| s.text().into()),
+            _ => world.file(id).map(|data| data.to_vec()),
+        };
+        // Files failing to load are reported by the compilation if needed.
+        let Ok(content) = content else {
+            continue;
+        };
+        let name = archive_path(id);
+        zip.add(&name, content);
+        files.push(name);
+    }
+
+    let fonts = if exclude_fonts {
+        vec![]
+    } else {
+        used_fonts(&doc)
+    };
+    for font in &fonts {
+        let name = font_path(font);
+        zip.add(&name, font.data().to_vec());
+        files.push(name);
+    }
+
+    let manifest = ProjectConfig {
+        entry: Some(entry.into()),
+        root: Some(".".into()),
+        font_paths: (!fonts.is_empty())
+            .then(|| "fonts".into())
+            .into_iter()
+            .collect(),
+        inputs: inputs
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::Str(s) => s.to_string(),
+                    value => value.repr().to_string(),
+                };
+                (key.to_string(), value)
+            })
+            .collect(),
+        ..ProjectConfig::default()
+    };
+    zip.add(MANIFEST, toml::to_string(&manifest)?);
+    files.push(MANIFEST.into());
+
+    Ok(Bundle {
+        zip: zip.finish()?,
+        files,
+    })
+}
+
+/// Get the path of the file in the archive, where package files are placed
+/// as in the package cache.
+fn archive_path(id: FileId) -> String {
+    let path = id.vpath().as_rootless_path();
+    let path = match id.package() {
+        Some(spec) => {
+            let dir = format!("packages/{}/{}/{}", spec.namespace, spec.name, spec.version);
+            PathBuf::from(dir).join(path)
+        }
+        None => path.to_owned(),
+    };
+    // Zip archives always separate components by slashes.
+    let components: Vec<_> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    components.join("/")
+}
+
+/// Get the path of the font in the archive, keeping the extension of its
+/// format.
+fn font_path(font: &Font) -> String {
+    let data = font.data();
+    let ext = match data.get(..4) {
+        Some(b"ttcf") => "ttc",
+        Some(b"OTTO") => "otf",
+        _ => "ttf",
+    };
+    let family: String = (font.info().family.chars())
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    format!(
+        "fonts/{family}-{:016x}.{ext}",
+        typst::util::hash128(data) as u64
+    )
+}
+
+/// Get the fonts used by the text of the document, once for each font file.
+fn used_fonts(doc: &Document) -> Vec<Font> {
+    fn visit(frame: &Frame, seen: &mut HashSet<u128>, fonts: &mut Vec<Font>) {
+        for (_, item) in frame.items() {
+            match item {
+                FrameItem::Group(group) => visit(&group.frame, seen, fonts),
+                FrameItem::Text(text) => {
+                    if seen.insert(typst::util::hash128(text.font.data())) {
+                        fonts.push(text.font.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut fonts = vec![];
+    for page in &doc.pages {
+        visit(&page.frame, &mut seen, &mut fonts);
+    }
+    fonts
+}
+
+/// The hooks recording the files read by the compilation.
+#[derive(Default)]
+struct RecordingHooks {
+    accessed: Mutex<BTreeSet<FileId>>,
+}
+
+impl RecordingHooks {
+    fn record<T>(&self, id: FileId, result: FileResult<T>) -> FileResult<T> {
+        if result.is_ok() {
+            self.accessed.lock().insert(id);
+        }
+        result
+    }
+}
+
+impl WorldHooks for RecordingHooks {
+    fn source(&self, id: FileId, source: FileResult<Source>) -> FileResult<Source> {
+        self.record(id, source)
+    }
+
+    fn file(&self, id: FileId, file: FileResult<Bytes>) -> FileResult<Bytes> {
+        self.record(id, file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+    use crate::tools::zip::zip_entries;
+
+    const SVG: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><rect width="10" height="10"/></svg>"#;
+
+    fn bundle(exclude_fonts: bool) -> Vec<(String, Vec<u8>)> {
+        let world = TestWorld::new("#include \"chapter.typ\"\n#image(\"images/cat.svg\")")
+            .with_file("chapter.typ", b"= Chapter")
+            .with_file("images/cat.svg", SVG);
+        let mut inputs = Dict::new();
+        inputs.insert("draft".into(), Value::Str("true".into()));
+        let bundle = bundle_document(&world, world.main(), &inputs, exclude_fonts).unwrap();
+        let entries = zip_entries(&bundle.zip);
+        let names: Vec<_> = entries.iter().map(|(name, _)| name.clone()).collect();
+        assert_eq!(names, bundle.files);
+        entries
+    }
+
+    #[test]
+    fn test_bundle_document() {
+        let entries = bundle(false);
+        let get = |name: &str| entries.iter().find(|(n, _)| n == name).map(|(_, c)| c);
+        assert_eq!(entries[0].0, "main.typ");
+        assert_eq!(get("chapter.typ").unwrap(), b"= Chapter");
+        assert_eq!(get("images/cat.svg").unwrap(), SVG);
+        assert!(entries.iter().any(|(name, _)| name.starts_with("fonts/")));
+
+        // The manifest is read as the project file of the extracted bundle.
+        let manifest = String::from_utf8(get(MANIFEST).unwrap().clone()).unwrap();
+        let dir = if cfg!(windows) { "C:\\ws" } else { "/ws" };
+        let dir = Path::new(dir);
+        let project = ProjectConfig::parse(&manifest, dir).unwrap();
+        assert_eq!(project.entry, Some(dir.join("main.typ")));
+        assert_eq!(project.root, Some(dir.join(".")));
+        assert_eq!(project.font_paths, vec![dir.join("fonts")]);
+        assert_eq!(project.inputs["draft"], "true");
+
+        let entries = bundle(true);
+        assert!(!entries.iter().any(|(name, _)| name.starts_with("fonts/")));
+    }
+}
@@ -0,0 +1,183 @@
+//! Tile the pages of a document into contact sheets.
+//!
+//! Pages are laid out in a grid of cells as large as the largest page, each
+//! labelled with its page number. Labels are drawn with a tiny built-in bitmap
+//! font, so that no font has to be loaded for them.
+
+use anyhow::{bail, Context};
+use typst::layout::{Abs, Frame, FrameItem, Page, Point, Size};
+use typst::model::Document;
+use typst::syntax::Span;
+use typst::visualize::{Color, FixedStroke, Geometry};
+
+/// The default number of pages per row.
+pub const DEFAULT_COLUMNS: usize = 4;
+/// The default resolution of contact sheets, in pixels per inch.
+pub const DEFAULT_PPI: f32 = 48.;
+/// The maximum number of pages on a contact sheet. Documents with more pages
+/// are split into several sheets.
+pub const MAX_PAGES_PER_SHEET: usize = 64;
+
+const MAX_COLUMNS: usize = 16;
+const MAX_PPI: f32 = 300.;
+
+/// The gap around cells, in points.
+const GAP: f64 = 16.;
+/// The height of the label below a page, in points.
+const LABEL_HEIGHT: f64 = 16.;
+/// The size of a dot of the label font, in points.
+const DOT: f64 = 2.;
+
+/// The digits of the label font, each made of five rows of three dots.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Render the pages of the document into contact sheets, returning the PNG
+/// encoded images.
+pub fn contact_sheets(doc: &Document, columns: usize, ppi: f32) -> anyhow::Result<Vec<Vec<u8>>> {
+    validate_options(columns, ppi)?;
+    if doc.pages.is_empty() {
+        bail!("the document has no pages");
+    }
+
+    let cell = doc.pages.iter().fold(Size::zero(), |cell, page| {
+        let size = page.frame.size();
+        Size::new(cell.x.max(size.x), cell.y.max(size.y))
+    });
+
+    doc.pages
+        .chunks(MAX_PAGES_PER_SHEET)
+        .enumerate()
+        .map(|(idx, pages)| {
+            let frame = sheet_frame(pages, idx * MAX_PAGES_PER_SHEET, columns, cell);
+            typst_render::render(&frame, ppi / 72., Color::WHITE)
+                .encode_png()
+                .context("failed to encode PNG")
+        })
+        .collect()
+}
+
+/// Check the options of contact sheets.
+pub fn validate_options(columns: usize, ppi: f32) -> anyhow::Result<()> {
+    if !(1..=MAX_COLUMNS).contains(&columns) {
+        bail!("columns must be between 1 and {MAX_COLUMNS}, got {columns}");
+    }
+    if !(ppi > 0. && ppi <= MAX_PPI) {
+        bail!("ppi must be positive and at most {MAX_PPI}, got {ppi}");
+    }
+    Ok(())
+}
+
+/// Lay out the pages into a grid, numbering them from `first + 1`.
+fn sheet_frame(pages: &[Page], first: usize, columns: usize, cell: Size) -> Frame {
+    let gap = Abs::pt(GAP);
+    let columns = columns.min(pages.len());
+    let rows = pages.len().div_ceil(columns);
+    let cell_width = cell.x + gap;
+    let cell_height = cell.y + Abs::pt(LABEL_HEIGHT) + gap;
+
+    let size = Size::new(
+        gap + cell_width * columns as f64,
+        gap + cell_height * rows as f64,
+    );
+    let mut frame = Frame::hard(size);
+    let border = FixedStroke::from_pair(Color::GRAY, Abs::pt(0.5));
+
+    for (idx, page) in pages.iter().enumerate() {
+        let origin = Point::new(
+            gap + cell_width * (idx % columns) as f64,
+            gap + cell_height * (idx / columns) as f64,
+        );
+        let page_size = page.frame.size();
+        frame.push_frame(origin, page.frame.clone());
+        let shape = Geometry::Rect(page_size).stroked(border.clone());
+        frame.push(origin, FrameItem::Shape(shape, Span::detached()));
+
+        let label = (first + idx + 1).to_string();
+        let label_width = Abs::pt(DOT * (4 * label.len() - 1) as f64);
+        let label_at = Point::new(
+            origin.x + (page_size.x - label_width) / 2.,
+            origin.y + page_size.y + Abs::pt((LABEL_HEIGHT - 5. * DOT) / 2.),
+        );
+        push_label(&mut frame, label_at, &label);
+    }
+
+    frame
+}
+
+/// Draw a number with the label font.
+fn push_label(frame: &mut Frame, pos: Point, label: &str) {
+    let dot = Geometry::Rect(Size::splat(Abs::pt(DOT))).filled(Color::BLACK.into());
+    for (idx, digit) in label.bytes().enumerate() {
+        let rows = DIGITS[(digit - b'0') as usize];
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..3 {
+                if row & (0b100 >> x) != 0 {
+                    let offset =
+                        Point::new(Abs::pt(DOT * (idx * 4 + x) as f64), Abs::pt(DOT * y as f64));
+                    let item = FrameItem::Shape(dot.clone(), Span::detached());
+                    frame.push(pos + offset, item);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    /// Get the dimensions of a PNG image from its header.
+    fn png_size(png: &[u8]) -> (u32, u32) {
+        let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+        let height = u32::from_be_bytes(png[20..24].try_into().unwrap());
+        (width, height)
+    }
+
+    #[test]
+    fn test_contact_sheet() {
+        let world = TestWorld::new(
+            "#set page(width: 100pt, height: 50pt)\n#for i in range(1, 6) [Page #i #if i < 5 { pagebreak() }]",
+        );
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        assert_eq!(doc.pages.len(), 5);
+
+        let sheets = contact_sheets(&doc, DEFAULT_COLUMNS, 72.).unwrap();
+        assert_eq!(sheets.len(), 1);
+        assert!(sheets[0].starts_with(b"\x89PNG"));
+
+        // Four columns and two rows, each cell with a gap and a label.
+        let (width, height) = png_size(&sheets[0]);
+        assert_eq!(width, 16 + 4 * (100 + 16));
+        assert_eq!(height, 16 + 2 * (50 + 16 + 16));
+
+        // The grid is narrowed to the number of pages.
+        let sheets = contact_sheets(&doc, 8, 72.).unwrap();
+        assert_eq!(
+            png_size(&sheets[0]),
+            (16 + 5 * (100 + 16), 16 + 50 + 16 + 16)
+        );
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let world = TestWorld::new("Hello");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        assert!(contact_sheets(&doc, 0, 72.).is_err());
+        assert!(contact_sheets(&doc, 4, 0.).is_err());
+        assert!(contact_sheets(&doc, 4, f32::NAN).is_err());
+    }
+}
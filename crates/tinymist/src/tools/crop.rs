@@ -0,0 +1,172 @@
+//! Export a region of a page by its physical position, e.g. a detail of a
+//! poster.
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use typst::layout::{Abs, Frame, Point, Size};
+use typst::visualize::{Color, Path};
+
+use super::selection::SelectionFormat;
+
+/// The default resolution of exported regions, in pixels per inch.
+pub const DEFAULT_PPI: f32 = 144.;
+const MAX_PPI: f32 = 1200.;
+/// The maximum number of pixels of an exported region.
+const MAX_PIXELS: f64 = 1e8;
+
+/// A rectangle on a page in points, from the top left corner of the page.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct CropRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl CropRect {
+    /// Clamp the rectangle to a page of the size, returning whether it is
+    /// clamped.
+    pub fn clamp_to(self, size: Size) -> anyhow::Result<(Self, bool)> {
+        let Self {
+            x,
+            y,
+            width,
+            height,
+        } = self;
+        if ![x, y, width, height].iter().all(|v| v.is_finite()) {
+            bail!("the rectangle is not finite: {self:?}");
+        }
+        if width <= 0. || height <= 0. {
+            bail!("the rectangle is empty: {self:?}");
+        }
+
+        let (page_width, page_height) = (size.x.to_pt(), size.y.to_pt());
+        let (x0, y0) = (x.clamp(0., page_width), y.clamp(0., page_height));
+        let x1 = (x + width).clamp(0., page_width);
+        let y1 = (y + height).clamp(0., page_height);
+        if x1 <= x0 || y1 <= y0 {
+            bail!("the rectangle is outside of the page: {self:?}");
+        }
+
+        let clamped = Self {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        };
+        Ok((clamped, clamped != self))
+    }
+
+    fn size(&self) -> Size {
+        Size::new(Abs::pt(self.width), Abs::pt(self.height))
+    }
+}
+
+/// Check the resolution of exported regions.
+pub fn validate_ppi(ppi: f32) -> anyhow::Result<()> {
+    if !(ppi > 0. && ppi <= MAX_PPI) {
+        bail!("ppi must be positive and at most {MAX_PPI}, got {ppi}");
+    }
+    Ok(())
+}
+
+/// Crop the frame of a page to the rectangle, which is within the page.
+pub fn crop_frame(frame: &Frame, rect: CropRect) -> Frame {
+    let size = rect.size();
+    let mut cropped = Frame::hard(size);
+    cropped.push_frame(
+        Point::new(Abs::pt(-rect.x), Abs::pt(-rect.y)),
+        frame.clone(),
+    );
+    cropped.clip(Path::rect(size));
+    cropped
+}
+
+/// Render the rectangle of the page in the format, at the resolution in pixels
+/// per inch for PNG.
+pub fn export_crop(
+    frame: &Frame,
+    rect: CropRect,
+    format: SelectionFormat,
+    ppi: f32,
+) -> anyhow::Result<Vec<u8>> {
+    let pixel_per_pt = ppi / 72.;
+    let pixels = rect.width * rect.height * f64::from(pixel_per_pt).powi(2);
+    if format == SelectionFormat::Png && pixels > MAX_PIXELS {
+        bail!("the region is too large to render at {ppi} ppi");
+    }
+
+    let cropped = crop_frame(frame, rect);
+    Ok(match format {
+        SelectionFormat::Png => typst_render::render(&cropped, pixel_per_pt, Color::WHITE)
+            .encode_png()
+            .context("failed to encode PNG")?,
+        SelectionFormat::Svg => typst_svg::svg(&cropped).into_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    fn page() -> Frame {
+        let world = TestWorld::new("#set page(width: 400pt, height: 300pt)\n#lorem(50)");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        doc.pages[0].frame.clone()
+    }
+
+    fn png_size(png: &[u8]) -> (u32, u32) {
+        assert!(png.starts_with(b"\x89PNG"));
+        let dim = |at: usize| u32::from_be_bytes(png[at..at + 4].try_into().unwrap());
+        (dim(16), dim(20))
+    }
+
+    #[test]
+    fn test_crop_region() {
+        let page = page();
+        let rect = CropRect {
+            x: 36.,
+            y: 72.,
+            width: 144.,
+            height: 72.,
+        };
+        let (rect, clamped) = rect.clamp_to(page.size()).unwrap();
+        assert!(!clamped);
+
+        // 2 inches by 1 inch at 150 ppi.
+        let png = export_crop(&page, rect, SelectionFormat::Png, 150.).unwrap();
+        assert_eq!(png_size(&png), (300, 150));
+
+        let svg = export_crop(&page, rect, SelectionFormat::Svg, 150.).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.contains(r#"viewBox="0 0 144 72""#), "{svg}");
+    }
+
+    #[test]
+    fn test_clamp_region() {
+        let size = page().size();
+        let rect = CropRect {
+            x: 300.,
+            y: -50.,
+            width: 200.,
+            height: 100.,
+        };
+        let (rect, clamped) = rect.clamp_to(size).unwrap();
+        assert!(clamped);
+        assert_eq!(
+            rect,
+            CropRect {
+                x: 300.,
+                y: 0.,
+                width: 100.,
+                height: 50.,
+            }
+        );
+
+        let outside = CropRect { x: 500., ..rect };
+        assert!(outside.clamp_to(size).is_err());
+    }
+}
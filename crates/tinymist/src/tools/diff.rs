@@ -0,0 +1,250 @@
+//! Diff the rendered output of two revisions of a document.
+//!
+//! Frames are flattened into positioned items. Items which are not present at
+//! the same position in the other revision are considered changed, and their
+//! bounding boxes are merged into regions.
+
+use std::collections::HashSet;
+
+use anyhow::bail;
+use serde::Serialize;
+use tinymist_query::OverlayWorld;
+use typst::eval::Tracer;
+use typst::layout::{Abs, Frame, FrameItem, Point, Transform};
+use typst::model::Document;
+use typst::syntax::Source;
+use typst::World;
+
+/// The gap between changed items to merge them into a region, in points.
+const MERGE_GAP: f64 = 2.;
+
+/// How a page is changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PageDiffKind {
+    Changed,
+    Added,
+    Removed,
+}
+
+/// A changed region of a page, in points.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffRegion {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// The changes of a page.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageDiff {
+    /// The zero-based index of the page.
+    pub page: usize,
+    pub kind: PageDiffKind,
+    /// The changed regions of the page, which is empty for added or removed
+    /// pages.
+    pub regions: Vec<DiffRegion>,
+}
+
+/// Compile the main file of the world both with the given on-disk content and
+/// as is, and diff the rendered output.
+pub fn diff_preview(world: &dyn World, on_disk: String) -> anyhow::Result<Vec<PageDiff>> {
+    let main = world.main();
+    let disk_world = OverlayWorld::new(world).with_main(Source::new(main.id(), on_disk));
+
+    let Ok(old) = typst::compile(&disk_world, &mut Tracer::new()) else {
+        bail!("the saved version cannot be compiled");
+    };
+    let Ok(new) = typst::compile(world, &mut Tracer::new()) else {
+        bail!("the current version cannot be compiled");
+    };
+
+    Ok(diff_documents(&old, &new))
+}
+
+/// Diff two documents page by page, only reporting changed pages.
+pub fn diff_documents(old: &Document, new: &Document) -> Vec<PageDiff> {
+    let pages = old.pages.len().max(new.pages.len());
+    (0..pages)
+        .filter_map(|page| {
+            let (kind, regions) = match (old.pages.get(page), new.pages.get(page)) {
+                (Some(old), Some(new)) => {
+                    let regions = diff_frames(&old.frame, &new.frame);
+                    if regions.is_empty() {
+                        return None;
+                    }
+                    (PageDiffKind::Changed, regions)
+                }
+                (None, Some(_)) => (PageDiffKind::Added, vec![]),
+                (Some(_), None) => (PageDiffKind::Removed, vec![]),
+                (None, None) => return None,
+            };
+            Some(PageDiff {
+                page,
+                kind,
+                regions,
+            })
+        })
+        .collect()
+}
+
+/// Get the regions of items that are changed between two frames.
+fn diff_frames(old: &Frame, new: &Frame) -> Vec<DiffRegion> {
+    let mut old_items = vec![];
+    flatten_frame(old, Transform::identity(), &mut old_items);
+    let mut new_items = vec![];
+    flatten_frame(new, Transform::identity(), &mut new_items);
+
+    let old_keys: HashSet<_> = old_items.iter().map(|(key, _)| *key).collect();
+    let new_keys: HashSet<_> = new_items.iter().map(|(key, _)| *key).collect();
+    let removed = old_items.iter().filter(|(key, _)| !new_keys.contains(key));
+    let added = new_items.iter().filter(|(key, _)| !old_keys.contains(key));
+    let changed = removed.chain(added).map(|(_, bounds)| *bounds).collect();
+
+    merge_bounds(changed)
+        .into_iter()
+        .map(|(min, max)| DiffRegion {
+            x: min.x.to_pt(),
+            y: min.y.to_pt(),
+            width: (max.x - min.x).to_pt(),
+            height: (max.y - min.y).to_pt(),
+        })
+        .collect()
+}
+
+type Bounds = (Point, Point);
+
+/// Flatten the frame into items identified by their content and position,
+/// along with their bounding boxes on the page.
+fn flatten_frame(frame: &Frame, ts: Transform, items: &mut Vec<(u128, Bounds)>) {
+    for (pos, item) in frame.items() {
+        let ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+        let origin = Point::zero().transform(ts);
+        let (key, local) = match item {
+            FrameItem::Group(group) => {
+                flatten_frame(&group.frame, ts.pre_concat(group.transform), items);
+                continue;
+            }
+            FrameItem::Text(text) => {
+                // Spans are excluded since they are renumbered on edits.
+                let glyphs: Vec<_> = (text.glyphs.iter())
+                    .map(|glyph| (glyph.id, glyph.x_advance, glyph.x_offset))
+                    .collect();
+                let metrics = text.font.metrics();
+                let key = (&text.font, text.size, &text.fill, &text.stroke, glyphs);
+                let local = (
+                    Point::new(Abs::zero(), -metrics.ascender.at(text.size)),
+                    Point::new(text.width(), -metrics.descender.at(text.size)),
+                );
+                (typst::util::hash128(&(key, origin)), local)
+            }
+            FrameItem::Shape(shape, _) => {
+                let local = (Point::zero(), shape.geometry.bbox_size().to_point());
+                (typst::util::hash128(&(shape, origin)), local)
+            }
+            FrameItem::Image(image, size, _) => {
+                let local = (Point::zero(), size.to_point());
+                (typst::util::hash128(&(image, size, origin)), local)
+            }
+            FrameItem::Meta(..) => continue,
+        };
+
+        items.push((key, transform_bounds(local, ts)));
+    }
+}
+
+/// Get the axis-aligned bounding box of transformed bounds.
+fn transform_bounds((min, max): Bounds, ts: Transform) -> Bounds {
+    let corners = [min, Point::new(max.x, min.y), Point::new(min.x, max.y), max]
+        .map(|corner| corner.transform(ts));
+
+    let mut bounds = (corners[0], corners[0]);
+    for corner in &corners[1..] {
+        bounds.0 = bounds.0.min(*corner);
+        bounds.1 = bounds.1.max(*corner);
+    }
+    bounds
+}
+
+/// Merge bounding boxes which overlap or are close to each other.
+fn merge_bounds(mut bounds: Vec<Bounds>) -> Vec<Bounds> {
+    let gap = Abs::pt(MERGE_GAP);
+    let mut merged = true;
+    while merged {
+        merged = false;
+        let mut result: Vec<Bounds> = vec![];
+        for b in bounds {
+            let near = result.iter_mut().find(|r| {
+                b.0.x <= r.1.x + gap
+                    && r.0.x <= b.1.x + gap
+                    && b.0.y <= r.1.y + gap
+                    && r.0.y <= b.1.y + gap
+            });
+            match near {
+                Some(r) => {
+                    *r = (r.0.min(b.0), r.1.max(b.1));
+                    merged = true;
+                }
+                None => result.push(b),
+            }
+        }
+        bounds = result;
+    }
+
+    let key = |b: &Bounds| (b.0.y.to_raw(), b.0.x.to_raw());
+    bounds.sort_by(|a, b| {
+        let ((ay, ax), (by, bx)) = (key(a), key(b));
+        ay.total_cmp(&by).then(ax.total_cmp(&bx))
+    });
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    const DOC: &str = "#set page(width: 10cm, height: auto, margin: 1cm)
+First paragraph.
+
+#v(8cm)
+Second paragraph.
+
+#v(8cm)
+Third paragraph.";
+
+    #[test]
+    fn test_diff_paragraph() {
+        let world = TestWorld::new(&DOC.replace("Second paragraph", "Second sentence"));
+        let diff = diff_preview(&world, DOC.to_owned()).unwrap();
+
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].kind, PageDiffKind::Changed);
+        let [region] = diff[0].regions[..] else {
+            panic!("expected one region, got {:?}", diff[0].regions);
+        };
+
+        // The region lies between the first and the third paragraph.
+        let cm = Abs::cm(1.).to_pt();
+        assert!(region.y > 3. * cm, "region: {region:?}");
+        assert!(region.y + region.height < 17. * cm, "region: {region:?}");
+    }
+
+    #[test]
+    fn test_diff_pages() {
+        let world = TestWorld::new(&format!("{DOC}\n#pagebreak()\nFourth paragraph."));
+        let diff = diff_preview(&world, DOC.to_owned()).unwrap();
+
+        assert_eq!(
+            diff,
+            vec![PageDiff {
+                page: 1,
+                kind: PageDiffKind::Added,
+                regions: vec![],
+            }]
+        );
+    }
+}
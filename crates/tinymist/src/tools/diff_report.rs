@@ -0,0 +1,234 @@
+//! Report the differences between two documents as an HTML page.
+//!
+//! The pages of both documents are rendered side by side, with the changed
+//! regions outlined. The shorter document is padded with empty pages, so that
+//! pages with the same index are compared.
+
+use std::fmt::Write;
+
+use anyhow::{bail, Context};
+use base64::Engine;
+use serde::Serialize;
+use tinymist_query::OverlayWorld;
+use typst::eval::Tracer;
+use typst::layout::{Frame, Size};
+use typst::model::Document;
+use typst::syntax::Source;
+use typst::visualize::Color;
+use typst::World;
+
+use super::diff::{diff_documents, DiffRegion, PageDiffKind};
+
+/// The resolution of the rendered pages, in pixels per point.
+const PIXEL_PER_PT: f32 = 2.;
+
+const STYLE: &str = "body { font-family: sans-serif; background: #f0f0f0; }
+.pair { display: flex; gap: 16px; align-items: flex-start; }
+.side { position: relative; background: white; box-shadow: 0 0 4px #0004; }
+.side img { display: block; width: 100%; height: 100%; }
+.missing { display: flex; align-items: center; justify-content: center; color: #888; }
+.region { position: absolute; outline: 2px solid #e0443e; background: #e0443e22; }
+.changed h2 { color: #b07000; } .added h2 { color: #1a7f37; } .removed h2 { color: #cf222e; }";
+
+/// A summary of the differences between two documents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffSummary {
+    /// The number of pages of the left document.
+    pub left_pages: usize,
+    /// The number of pages of the right document.
+    pub right_pages: usize,
+    /// The zero-based indices of the pages changed.
+    pub changed: Vec<usize>,
+    /// The zero-based indices of the pages only in the right document.
+    pub added: Vec<usize>,
+    /// The zero-based indices of the pages only in the left document.
+    pub removed: Vec<usize>,
+}
+
+/// A report of the differences between two documents.
+#[derive(Debug, Clone)]
+pub struct DiffReport {
+    pub summary: DiffSummary,
+    /// The report as a self-contained HTML page.
+    pub html: String,
+}
+
+/// Compile the two sources as the main file of the world, and report the
+/// differences of the rendered output.
+pub fn diff_report(world: &dyn World, left: Source, right: Source) -> anyhow::Result<DiffReport> {
+    let titles = [&left, &right].map(|source| {
+        let path = source.id().vpath().as_rootless_path();
+        path.display().to_string()
+    });
+    let left = compile(world, left, "left")?;
+    let right = compile(world, right, "right")?;
+    report_documents(&left, &right, &titles)
+}
+
+fn compile(world: &dyn World, main: Source, side: &str) -> anyhow::Result<Document> {
+    let world = OverlayWorld::new(world).with_main(main);
+    match typst::compile(&world, &mut Tracer::new()) {
+        Ok(doc) => Ok(doc),
+        Err(errors) => {
+            let message = errors.first().map(|e| e.message.as_str()).unwrap_or("");
+            bail!("the {side} document cannot be compiled: {message}");
+        }
+    }
+}
+
+/// Report the differences between two documents, whose titles are shown above
+/// the pages.
+pub fn report_documents(
+    left: &Document,
+    right: &Document,
+    [left_title, right_title]: &[String; 2],
+) -> anyhow::Result<DiffReport> {
+    let diffs = diff_documents(left, right);
+    let pages_of = |kind| {
+        (diffs.iter())
+            .filter(|diff| diff.kind == kind)
+            .map(|diff| diff.page)
+            .collect::<Vec<_>>()
+    };
+    let summary = DiffSummary {
+        left_pages: left.pages.len(),
+        right_pages: right.pages.len(),
+        changed: pages_of(PageDiffKind::Changed),
+        added: pages_of(PageDiffKind::Added),
+        removed: pages_of(PageDiffKind::Removed),
+    };
+
+    let mut html = String::new();
+    let (left_title, right_title) = (escape(left_title), escape(right_title));
+    writeln!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{left_title} vs {right_title}</title>\n<style>\n{STYLE}\n</style>\n\
+         </head>\n<body>\n<h1>{left_title} vs {right_title}</h1>"
+    )?;
+    writeln!(
+        html,
+        "<p>{} pages on the left and {} pages on the right: {} changed, {} added, {} removed.</p>",
+        summary.left_pages,
+        summary.right_pages,
+        summary.changed.len(),
+        summary.added.len(),
+        summary.removed.len(),
+    )?;
+
+    for page in 0..left.pages.len().max(right.pages.len()) {
+        let diff = diffs.iter().find(|diff| diff.page == page);
+        let status = match diff.map(|diff| diff.kind) {
+            None => "unchanged",
+            Some(PageDiffKind::Changed) => "changed",
+            Some(PageDiffKind::Added) => "added",
+            Some(PageDiffKind::Removed) => "removed",
+        };
+        let regions = diff.map_or(&[][..], |diff| diff.regions.as_slice());
+        let left = left.pages.get(page).map(|page| &page.frame);
+        let right = right.pages.get(page).map(|page| &page.frame);
+
+        writeln!(html, "<section class=\"{status}\">")?;
+        writeln!(html, "<h2>Page {} ({status})</h2>", page + 1)?;
+        writeln!(html, "<div class=\"pair\">")?;
+        write_side(&mut html, left, right, regions)?;
+        write_side(&mut html, right, left, regions)?;
+        writeln!(html, "</div>\n</section>")?;
+    }
+    writeln!(html, "</body>\n</html>")?;
+
+    Ok(DiffReport { summary, html })
+}
+
+/// Write the rendered page with the changed regions outlined, or an empty page
+/// as large as the other one if it is missing.
+fn write_side(
+    html: &mut String,
+    frame: Option<&Frame>,
+    other: Option<&Frame>,
+    regions: &[DiffRegion],
+) -> anyhow::Result<()> {
+    let Some(frame) = frame else {
+        let size = other.map_or(Size::zero(), |other| other.size());
+        writeln!(
+            html,
+            "<div class=\"side missing\" style=\"width: {}pt; height: {}pt\">no page</div>",
+            size.x.to_pt(),
+            size.y.to_pt(),
+        )?;
+        return Ok(());
+    };
+
+    let size = frame.size();
+    let png = typst_render::render(frame, PIXEL_PER_PT, Color::WHITE)
+        .encode_png()
+        .context("failed to encode PNG")?;
+    let png = base64::engine::general_purpose::STANDARD.encode(png);
+    writeln!(
+        html,
+        "<div class=\"side\" style=\"width: {}pt; height: {}pt\">\n\
+         <img src=\"data:image/png;base64,{png}\">",
+        size.x.to_pt(),
+        size.y.to_pt(),
+    )?;
+    for region in regions {
+        writeln!(
+            html,
+            "<div class=\"region\" style=\"left: {}pt; top: {}pt; width: {}pt; height: {}pt\"></div>",
+            region.x, region.y, region.width, region.height,
+        )?;
+    }
+    writeln!(html, "</div>")?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    const PAGE: &str = "#set page(width: 8cm, height: 4cm)\n";
+
+    #[test]
+    fn test_diff_report() {
+        let world = TestWorld::new("");
+        let left = Source::detached(format!("{PAGE}Left version."));
+        let right = Source::detached(format!("{PAGE}Right version."));
+        let report = diff_report(&world, left, right).unwrap();
+
+        assert_eq!(
+            report.summary,
+            DiffSummary {
+                left_pages: 1,
+                right_pages: 1,
+                changed: vec![0],
+                added: vec![],
+                removed: vec![],
+            }
+        );
+        // Both pages are rendered.
+        assert_eq!(report.html.matches("data:image/png;base64,").count(), 2);
+        assert!(report.html.contains("class=\"region\""));
+    }
+
+    #[test]
+    fn test_diff_report_padding() {
+        let world = TestWorld::new("");
+        let left = Source::detached(format!("{PAGE}Same."));
+        let right = Source::detached(format!("{PAGE}Same.\n#pagebreak()\nMore."));
+        let report = diff_report(&world, left, right).unwrap();
+
+        assert_eq!(report.summary.added, vec![1]);
+        assert!(report.summary.changed.is_empty());
+        assert_eq!(report.html.matches("data:image/png;base64,").count(), 3);
+        assert_eq!(report.html.matches("no page").count(), 1);
+    }
+}
@@ -0,0 +1,281 @@
+//! Package documents as EPUB books.
+//!
+//! The document is written as XHTML from its Markdown blocks, and split into
+//! chapters at the top-level headings or at the included files. Images and
+//! block equations fall back to pictures, and the table of contents is made of
+//! the headings.
+
+use std::fmt::Write;
+
+use anyhow::bail;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use tinymist_query::EpubSplit;
+use typst::foundations::Smart;
+use typst::layout::{Frame, FrameItem};
+use typst::model::Document;
+
+use super::markdown::{markdown_blocks, MarkdownBlock};
+use super::zip::ZipWriter;
+
+const XML_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+
+const CONTAINER: &str = r#"<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#;
+
+/// A chapter of a book.
+struct Chapter<'a> {
+    title: String,
+    blocks: Vec<&'a MarkdownBlock>,
+}
+
+/// Package the document as an EPUB book, with the cover image and its
+/// extension if given, and the date of its last modification.
+pub fn epub(
+    doc: &Document,
+    split: EpubSplit,
+    cover: Option<(&[u8], &str)>,
+    modified: DateTime<Utc>,
+) -> anyhow::Result<Vec<u8>> {
+    let blocks = markdown_blocks(doc, true);
+    let chapters = chapters(&blocks, split);
+    if chapters.is_empty() {
+        bail!("there is no text to export");
+    }
+
+    let title = escape(doc.title.as_deref().unwrap_or("Untitled"));
+    let lang = (doc.pages.iter())
+        .find_map(|page| lang(&page.frame))
+        .unwrap_or_else(|| "en".to_owned());
+
+    let mut zip = ZipWriter::default();
+    // The mimetype must be the first entry of the archive.
+    zip.add("mimetype", "application/epub+zip");
+    zip.add("META-INF/container.xml", format!("{XML_HEADER}{CONTAINER}"));
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    manifest.push_str(
+        r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#,
+    );
+    if let Some((data, ext)) = cover {
+        let media_type = match ext.to_ascii_lowercase().as_str() {
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "svg" => "image/svg+xml",
+            "webp" => "image/webp",
+            _ => bail!("unsupported format of cover image: {ext:?}"),
+        };
+        zip.add(&format!("OEBPS/cover.{ext}"), data);
+        let body = format!(r#"<img src="cover.{ext}" alt="Cover"/>"#);
+        zip.add("OEBPS/cover.xhtml", xhtml(&lang, &title, &body));
+        let _ = write!(
+            manifest,
+            r#"<item id="cover-image" href="cover.{ext}" media-type="{media_type}" properties="cover-image"/><item id="cover" href="cover.xhtml" media-type="application/xhtml+xml"/>"#
+        );
+        spine.push_str(r#"<itemref idref="cover" linear="no"/>"#);
+    }
+
+    let mut toc = String::new();
+    for (i, chapter) in chapters.iter().enumerate() {
+        let num = i + 1;
+        let mut body = String::new();
+        let mut headings = String::new();
+        for (k, block) in chapter.blocks.iter().enumerate() {
+            if let Some(picture) = &block.picture {
+                let href = format!("images/chapter{num}-{k}.png");
+                zip.add(&format!("OEBPS/{href}"), picture.clone());
+                let _ = write!(
+                    manifest,
+                    r#"<item id="chapter{num}-{k}" href="{href}" media-type="image/png"/>"#
+                );
+                let _ = write!(body, r#"<p><img src="{href}" alt=""/></p>"#);
+            } else if let Some(level) = block.heading {
+                let level = level.clamp(1, 6);
+                let text = escape(&block.plain);
+                let _ = write!(body, r#"<h{level} id="heading-{k}">{text}</h{level}>"#);
+                if k > 0 {
+                    let _ = write!(
+                        headings,
+                        r#"<li><a href="chapter{num}.xhtml#heading-{k}">{text}</a></li>"#
+                    );
+                }
+            } else {
+                let text = escape(&block.plain).replace('\n', "<br/>");
+                let _ = write!(body, "<p>{text}</p>");
+            }
+        }
+
+        let chapter_title = escape(&chapter.title);
+        zip.add(
+            &format!("OEBPS/chapter{num}.xhtml"),
+            xhtml(&lang, &chapter_title, &body),
+        );
+        let _ = write!(
+            manifest,
+            r#"<item id="chapter{num}" href="chapter{num}.xhtml" media-type="application/xhtml+xml"/>"#
+        );
+        let _ = write!(spine, r#"<itemref idref="chapter{num}"/>"#);
+        let _ = write!(
+            toc,
+            r#"<li><a href="chapter{num}.xhtml">{chapter_title}</a>"#
+        );
+        if !headings.is_empty() {
+            let _ = write!(toc, "<ol>{headings}</ol>");
+        }
+        toc.push_str("</li>");
+    }
+
+    let nav = format!(r#"<nav epub:type="toc" id="toc"><h1>{title}</h1><ol>{toc}</ol></nav>"#);
+    zip.add("OEBPS/nav.xhtml", xhtml(&lang, &title, &nav));
+
+    let id = typst::util::hash128(&(blocks.iter()).map(|block| &block.text).collect::<Vec<_>>());
+    let mut metadata = format!(
+        r#"<dc:identifier id="book-id">urn:tinymist:{id:032x}</dc:identifier><dc:title>{title}</dc:title><dc:language>{lang}</dc:language>"#
+    );
+    for author in &doc.author {
+        let _ = write!(metadata, "<dc:creator>{}</dc:creator>", escape(author));
+    }
+    let modified = modified.format("%Y-%m-%dT%H:%M:%SZ");
+    let _ = write!(
+        metadata,
+        r#"<meta property="dcterms:modified">{modified}</meta>"#
+    );
+    zip.add(
+        "OEBPS/content.opf",
+        format!(
+            r#"{XML_HEADER}<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" xml:lang="{lang}"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/">{metadata}</metadata><manifest>{manifest}</manifest><spine>{spine}</spine></package>"#
+        ),
+    );
+
+    zip.finish()
+}
+
+/// Get the date set for the document, as a UTC time at midnight if it has no
+/// time.
+pub fn document_date(doc: &Document) -> Option<DateTime<Utc>> {
+    let Smart::Custom(Some(date)) = &doc.date else {
+        return None;
+    };
+    let day = NaiveDate::from_ymd_opt(date.year()?, date.month()?.into(), date.day()?.into())?;
+    let (hour, minute, second) = (date.hour(), date.minute(), date.second());
+    let time = NaiveTime::from_hms_opt(
+        hour.unwrap_or_default().into(),
+        minute.unwrap_or_default().into(),
+        second.unwrap_or_default().into(),
+    )?;
+    Some(day.and_time(time).and_utc())
+}
+
+/// Split the blocks into chapters, each starting at a top-level heading or at
+/// the text of another file.
+fn chapters(blocks: &[MarkdownBlock], split: EpubSplit) -> Vec<Chapter<'_>> {
+    let mut chapters: Vec<Vec<&MarkdownBlock>> = vec![];
+    let mut file = None;
+    for block in blocks {
+        let starts = match split {
+            EpubSplit::Heading1 => block.heading == Some(1),
+            EpubSplit::Include => block.file.is_some() && block.file != file,
+        };
+        file = block.file.or(file);
+        match chapters.last_mut() {
+            Some(chapter) if !starts => chapter.push(block),
+            _ => chapters.push(vec![block]),
+        }
+    }
+
+    (chapters.into_iter().enumerate())
+        .map(|(i, blocks)| Chapter {
+            title: (blocks.iter())
+                .find(|block| block.heading.is_some())
+                .map(|block| block.plain.clone())
+                .unwrap_or_else(|| format!("Chapter {}", i + 1)),
+            blocks,
+        })
+        .collect()
+}
+
+/// Get the language of the first text in the frame.
+fn lang(frame: &Frame) -> Option<String> {
+    frame.items().find_map(|(_, item)| match item {
+        FrameItem::Group(group) => lang(&group.frame),
+        FrameItem::Text(text) => Some(text.lang.as_str().to_owned()),
+        _ => None,
+    })
+}
+
+fn xhtml(lang: &str, title: &str, body: &str) -> String {
+    format!(
+        r#"{XML_HEADER}<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{lang}" lang="{lang}"><head><title>{title}</title></head><body>{body}</body></html>"#
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::{tests::TestWorld, zip::zip_entries};
+
+    #[test]
+    fn test_epub() {
+        let world = TestWorld::new(
+            "#set document(title: \"Book\", author: \"Alice\")\n\
+             #set document(date: datetime(year: 2024, month: 5, day: 6))\n\
+             = One\nFirst chapter.\n\n$ x^2 $\n\n\
+             = Two\nSecond chapter.\n== Details\nMore 1 < 2 & 3.",
+        );
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        let modified = document_date(&doc).unwrap();
+        let epub = epub(&doc, EpubSplit::Heading1, None, modified).unwrap();
+        let entries = zip_entries(&epub);
+        assert_eq!(entries[0].0, "mimetype");
+        assert_eq!(entries[0].1, b"application/epub+zip");
+
+        let entry = |name: &str| {
+            let (_, data) = (entries.iter())
+                .find(|(entry, _)| entry == name)
+                .unwrap_or_else(|| panic!("missing entry {name}"));
+            String::from_utf8(data.clone()).unwrap()
+        };
+        assert!(entry("META-INF/container.xml").contains("OEBPS/content.opf"));
+
+        let opf = entry("OEBPS/content.opf");
+        assert!(
+            opf.contains(
+                r#"<spine><itemref idref="chapter1"/><itemref idref="chapter2"/></spine>"#
+            ),
+            "{opf}"
+        );
+        assert!(opf.contains("<dc:title>Book</dc:title>"), "{opf}");
+        assert!(opf.contains("<dc:creator>Alice</dc:creator>"), "{opf}");
+        assert!(opf.contains(r#"media-type="image/png""#), "{opf}");
+        let modified = r#"<meta property="dcterms:modified">2024-05-06T00:00:00Z</meta>"#;
+        assert!(opf.contains(modified), "{opf}");
+
+        let nav = entry("OEBPS/nav.xhtml");
+        assert!(nav.contains(r#"<nav epub:type="toc""#), "{nav}");
+        assert!(nav.contains(r#"<a href="chapter1.xhtml">One</a>"#), "{nav}");
+        assert!(nav.contains(r#"<a href="chapter2.xhtml">Two</a>"#), "{nav}");
+        assert!(nav.contains("Details</a>"), "{nav}");
+
+        let chapter = entry("OEBPS/chapter2.xhtml");
+        assert!(
+            chapter.contains("<p>More 1 &lt; 2 &amp; 3.</p>"),
+            "{chapter}"
+        );
+    }
+}
@@ -0,0 +1,351 @@
+//! Flatten a document into a single self-contained source file, for the
+//! submission systems not supporting multiple files.
+//!
+//! The local files included are inlined as content blocks, and the items
+//! imported from local files are bound by code blocks evaluating the
+//! definitions of the files. The paths of the files read, e.g. images, are
+//! rewritten to be relative to the flattened file. The imports of packages are
+//! kept as they are.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use anyhow::{bail, Context};
+use typst::foundations::{Repr, Str};
+use typst::syntax::{ast, FileId, LinkedNode, Source, SyntaxKind, VirtualPath};
+use typst::World;
+
+/// The functions reading a file from a path given as their first argument.
+const PATH_FUNCS: &[&str] = &[
+    "image",
+    "read",
+    "csv",
+    "json",
+    "yaml",
+    "toml",
+    "xml",
+    "cbor",
+    "bibliography",
+    "plugin",
+];
+
+/// Flatten the source as the entry into the content of a file at `output`,
+/// which must be in the same root.
+pub fn flatten_document(
+    world: &dyn World,
+    main: &Source,
+    output: &VirtualPath,
+) -> anyhow::Result<String> {
+    let mut flattener = Flattener {
+        world,
+        output: output.clone(),
+        stack: vec![],
+    };
+    flattener.flatten(main.clone())
+}
+
+struct Flattener<'a> {
+    world: &'a dyn World,
+    output: VirtualPath,
+    /// The files being flattened, to detect circular references.
+    stack: Vec<FileId>,
+}
+
+impl Flattener<'_> {
+    fn flatten(&mut self, source: Source) -> anyhow::Result<String> {
+        let id = source.id();
+        if self.stack.contains(&id) {
+            let cycle = (self.stack.iter().chain([&id]))
+                .map(|id| display(id.vpath()))
+                .collect::<Vec<_>>();
+            bail!("circular reference: {}", cycle.join(" -> "));
+        }
+
+        self.stack.push(id);
+        let mut edits = vec![];
+        let res = self.collect_edits(id, LinkedNode::new(source.root()), &mut edits);
+        self.stack.pop();
+        res?;
+
+        let mut text = source.text().to_owned();
+        for (range, replacement) in edits.into_iter().rev() {
+            text.replace_range(range, &replacement);
+        }
+        Ok(text)
+    }
+
+    fn collect_edits(
+        &mut self,
+        id: FileId,
+        node: LinkedNode,
+        edits: &mut Vec<(Range<usize>, String)>,
+    ) -> anyhow::Result<()> {
+        match node.kind() {
+            SyntaxKind::ModuleInclude => {
+                let include = node.cast::<ast::ModuleInclude>().unwrap();
+                if let Some(target) = local_file(id, include.source()) {
+                    let mut text = self.flatten_file(target)?;
+                    // Keep a line comment at the end from commenting out the
+                    // closing bracket.
+                    if text.lines().last().is_some_and(|line| line.contains("//")) {
+                        text.push('\n');
+                    }
+                    edits.push((node.range(), format!("[{text}]")));
+                    return Ok(());
+                }
+            }
+            SyntaxKind::ModuleImport => {
+                let import = node.cast::<ast::ModuleImport>().unwrap();
+                if let Some(target) = local_file(id, import.source()) {
+                    let replacement = self.flatten_import(target, import)?;
+                    edits.push((node.range(), replacement));
+                    return Ok(());
+                }
+            }
+            SyntaxKind::FuncCall => {
+                let call = node.cast::<ast::FuncCall>().unwrap();
+                if let Some(arg) = path_arg(call) {
+                    self.rewrite_paths(id, &node, arg, edits);
+                }
+            }
+            _ => {}
+        }
+
+        for child in node.children() {
+            self.collect_edits(id, child, edits)?;
+        }
+        Ok(())
+    }
+
+    fn flatten_file(&mut self, id: FileId) -> anyhow::Result<String> {
+        let source = self.world.source(id);
+        let source = source.with_context(|| format!("cannot read {}", display(id.vpath())))?;
+        self.flatten(source)
+    }
+
+    /// Bind the items imported from a file by a code block evaluating its
+    /// definitions, e.g. `let (a, c) = { ..; (a, b) }` for
+    /// `import "lib.typ": a, b as c`, so that the other definitions stay
+    /// private.
+    fn flatten_import(&mut self, id: FileId, import: ast::ModuleImport) -> anyhow::Result<String> {
+        let path = display(id.vpath());
+        if import.new_name().is_some() {
+            bail!("cannot flatten the import of {path} as a module, import its items instead");
+        }
+
+        let text = self.flatten_file(id)?;
+        let module = Source::new(id, text);
+        let mut statements = vec![];
+        let mut exported = vec![];
+        let markup = module.root().cast::<ast::Markup>().unwrap();
+        for expr in markup.exprs() {
+            match expr {
+                ast::Expr::Let(binding) => {
+                    let names = binding.kind().bindings();
+                    exported.extend(names.into_iter().map(|name| name.get().clone()));
+                }
+                ast::Expr::Import(import) => match import.imports() {
+                    Some(ast::Imports::Items(items)) => {
+                        let names = items.iter().map(|item| item.bound_name().get().clone());
+                        exported.extend(names);
+                    }
+                    Some(ast::Imports::Wildcard) => exported.push("*".into()),
+                    None => {}
+                },
+                _ => continue,
+            }
+            statements.push(expr.to_untyped().clone().into_text());
+        }
+
+        let (originals, bound): (Vec<_>, Vec<_>) = match import.imports() {
+            Some(ast::Imports::Items(items)) => items
+                .iter()
+                .map(|item| {
+                    (
+                        item.original_name().get().clone(),
+                        item.bound_name().get().clone(),
+                    )
+                })
+                .unzip(),
+            Some(ast::Imports::Wildcard) => {
+                if exported.iter().any(|name| name == "*") {
+                    bail!(
+                        "cannot flatten a wildcard import of {path}, \
+                         which imports all items of another module"
+                    );
+                }
+                // Keep the last binding of a name redefined.
+                let mut seen = HashSet::new();
+                let mut names: Vec<_> = exported.into_iter().rev().collect();
+                names.retain(|name| seen.insert(name.clone()));
+                names.reverse();
+                (names.clone(), names)
+            }
+            None => {
+                bail!("cannot flatten the import of {path} as a module, import its items instead")
+            }
+        };
+        if bound.is_empty() {
+            return Ok("none".to_owned());
+        }
+
+        Ok(format!(
+            "let ({},) = {{\n{}\n({},)\n}}",
+            bound.join(", "),
+            statements.join("\n"),
+            originals.join(", "),
+        ))
+    }
+
+    /// Rewrite the relative paths read by a call to be relative to the
+    /// output.
+    fn rewrite_paths(
+        &self,
+        id: FileId,
+        node: &LinkedNode,
+        arg: ast::Expr,
+        edits: &mut Vec<(Range<usize>, String)>,
+    ) {
+        let strings: Vec<_> = match arg {
+            ast::Expr::Str(path) => vec![path],
+            ast::Expr::Array(array) => (array.items())
+                .filter_map(|item| match item {
+                    ast::ArrayItem::Pos(ast::Expr::Str(path)) => Some(path),
+                    _ => None,
+                })
+                .collect(),
+            _ => return,
+        };
+
+        for path in strings {
+            // Absolute paths are resolved from the root, wherever the file is.
+            if path.get().starts_with('/') {
+                continue;
+            }
+            let Some(path_node) = node.find(path.span()) else {
+                continue;
+            };
+            let target = id.vpath().join(path.get().as_str());
+            let relative = relative_path(&self.output, &target);
+            edits.push((
+                path_node.range(),
+                Str::from(relative.as_str()).repr().into(),
+            ));
+        }
+    }
+}
+
+/// Get the local file referenced by a path, which is not a package.
+fn local_file(id: FileId, source: ast::Expr) -> Option<FileId> {
+    let ast::Expr::Str(path) = source else {
+        return None;
+    };
+    let path = path.get();
+    (!path.starts_with('@')).then(|| id.join(&path))
+}
+
+/// Get the path argument of a call reading a file.
+fn path_arg(call: ast::FuncCall) -> Option<ast::Expr> {
+    let ast::Expr::Ident(callee) = call.callee() else {
+        return None;
+    };
+    if !PATH_FUNCS.contains(&callee.as_str()) {
+        return None;
+    }
+    call.args().items().find_map(|arg| match arg {
+        ast::Arg::Pos(expr) => Some(expr),
+        _ => None,
+    })
+}
+
+/// Get the path to `target` relative to the directory of `from`, both in the
+/// root.
+fn relative_path(from: &VirtualPath, target: &VirtualPath) -> String {
+    let from: Vec<_> = (from.as_rootless_path().parent())
+        .map(|dir| dir.components().collect())
+        .unwrap_or_default();
+    let target: Vec<_> = target.as_rootless_path().components().collect();
+    let common = from.iter().zip(&target).take_while(|(a, b)| a == b).count();
+
+    let parents = std::iter::repeat("..".to_owned()).take(from.len() - common);
+    let rest = target[common..]
+        .iter()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned());
+    parents.chain(rest).collect::<Vec<_>>().join("/")
+}
+
+fn display(path: &VirtualPath) -> String {
+    path.as_rootless_path().display().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    const FIG: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><rect width="10" height="10" fill="red"/></svg>"#;
+
+    fn render(world: &TestWorld) -> Vec<String> {
+        let doc = typst::compile(world, &mut Tracer::new()).unwrap();
+        doc.pages
+            .iter()
+            .map(|page| typst_svg::svg(&page.frame))
+            .collect()
+    }
+
+    #[test]
+    fn test_flatten() {
+        let chapter = b"#import \"../lib.typ\": greet\n\
+            == One\n\
+            #greet(\"chapter\")\n\
+            #image(\"fig.svg\", width: 1cm)";
+        let lib = b"#let _suffix = \"!\"\n\
+            #let greet(name) = [Hello, #name#_suffix]\n\
+            Not exported";
+        let world = TestWorld::new(
+            "#import \"lib.typ\": greet\n\
+             #let _suffix = \"?\"\n\
+             = Title\n\
+             #include \"chapters/one.typ\"\n\
+             #greet(\"main\")#_suffix",
+        )
+        .with_file("lib.typ", lib)
+        .with_file("chapters/one.typ", chapter)
+        .with_file("chapters/fig.svg", FIG);
+
+        let output = VirtualPath::new("main-flat.typ");
+        let flat = flatten_document(&world, &world.main(), &output).unwrap();
+        assert!(!flat.contains("include") && !flat.contains("import"));
+        assert!(flat.contains("\"chapters/fig.svg\""));
+
+        let flat_world = TestWorld::new(&flat).with_file("chapters/fig.svg", FIG);
+        assert_eq!(render(&flat_world), render(&world));
+    }
+
+    #[test]
+    fn test_circular() {
+        let world = TestWorld::new("#include \"a.typ\"")
+            .with_file("a.typ", b"#include \"b.typ\"")
+            .with_file("b.typ", b"#include \"a.typ\"");
+        let output = VirtualPath::new("flat.typ");
+        let err = flatten_document(&world, &world.main(), &output).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "circular reference: main.typ -> a.typ -> b.typ -> a.typ"
+        );
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let path =
+            |from: &str, to: &str| relative_path(&VirtualPath::new(from), &VirtualPath::new(to));
+        assert_eq!(path("main.typ", "chapters/fig.svg"), "chapters/fig.svg");
+        assert_eq!(
+            path("out/flat.typ", "chapters/fig.svg"),
+            "../chapters/fig.svg"
+        );
+        assert_eq!(path("chapters/flat.typ", "chapters/fig.svg"), "fig.svg");
+    }
+}
@@ -0,0 +1,89 @@
+//! Check whether a font covers some text, and which fonts the characters not
+//! covered by it fall back to, without compiling a document.
+
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use serde::Serialize;
+use typst::text::{FontBook, FontVariant};
+
+/// The font of text by default, which is the font stack of documents not
+/// setting `text.font`.
+const DEFAULT_FAMILY: &str = "linux libertine";
+
+/// The characters of some text covered by a font, and the fonts selected for
+/// the missing ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GlyphCoverage {
+    pub covered: Vec<char>,
+    pub missing: Vec<char>,
+    /// The families the missing characters fall back to. The characters not
+    /// covered by any font are absent.
+    pub fallbacks: BTreeMap<char, String>,
+}
+
+/// Check the coverage of the distinct characters of the text other than
+/// whitespace by the family, or the default font if absent.
+pub fn glyph_coverage(
+    book: &FontBook,
+    text: &str,
+    family: Option<&str>,
+) -> anyhow::Result<GlyphCoverage> {
+    let family = family.unwrap_or(DEFAULT_FAMILY);
+    let infos: Vec<_> = (book.select_family(&family.to_lowercase()))
+        .filter_map(|idx| book.info(idx))
+        .collect();
+    let Some(like) = infos.first() else {
+        bail!("unknown font family {family:?}");
+    };
+
+    let mut coverage = GlyphCoverage::default();
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        if coverage.covered.contains(&c) || coverage.missing.contains(&c) {
+            continue;
+        }
+        if infos.iter().any(|info| info.coverage.contains(c as u32)) {
+            coverage.covered.push(c);
+            continue;
+        }
+
+        coverage.missing.push(c);
+        let fallback = book.select_fallback(Some(like), FontVariant::default(), &c.to_string());
+        if let Some(info) = fallback.and_then(|idx| book.info(idx)) {
+            coverage.fallbacks.insert(c, info.family.clone());
+        }
+    }
+    Ok(coverage)
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::text::{Coverage, FontInfo};
+
+    use super::*;
+
+    #[test]
+    fn test_glyph_coverage() {
+        let mut infos: Vec<_> = typst_assets::fonts().flat_map(FontInfo::iter).collect();
+        let cjk = FontInfo {
+            family: "Noto Serif CJK SC".into(),
+            coverage: Coverage::from_vec(vec!['中' as u32, '文' as u32]),
+            ..infos[0].clone()
+        };
+        infos.push(cjk);
+        let book = FontBook::from_infos(infos);
+
+        let coverage = glyph_coverage(&book, "Hi 中文字", None).unwrap();
+        assert_eq!(coverage.covered, ['H', 'i']);
+        assert_eq!(coverage.missing, ['中', '文', '字']);
+        assert_eq!(
+            coverage.fallbacks,
+            BTreeMap::from_iter([
+                ('中', "Noto Serif CJK SC".to_owned()),
+                ('文', "Noto Serif CJK SC".to_owned()),
+            ])
+        );
+
+        assert!(glyph_coverage(&book, "Hi", Some("No Such Font")).is_err());
+    }
+}
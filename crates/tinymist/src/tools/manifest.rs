@@ -0,0 +1,171 @@
+//! Describe an export run in a JSON manifest written next to the exported
+//! files, so that build pipelines get a machine-readable record of what was
+//! produced.
+//!
+//! The manifest lists the files written along with the inputs, i.e.
+//! `sys.inputs`, and the diagnostics of the compilation exported.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use serde::Serialize;
+use typst::foundations::Dict;
+
+/// The suffix replacing the extension of an exported file to name its
+/// manifest, e.g. `main.manifest.json` for `main.pdf`.
+const MANIFEST_EXTENSION: &str = "manifest.json";
+
+/// A file written by an export.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestOutput {
+    pub path: PathBuf,
+    /// The kind of the file, e.g. `pdf`.
+    pub kind: String,
+    pub bytes: u64,
+    /// The number of pages of the document in the file.
+    pub pages: usize,
+}
+
+impl ManifestOutput {
+    /// Describe a file written to `path`, reading its size from the disk.
+    pub fn new(path: PathBuf, kind: &str, pages: usize) -> anyhow::Result<Self> {
+        let metadata = std::fs::metadata(&path)
+            .with_context(|| format!("failed to read the exported file {path:?}"))?;
+        Ok(Self {
+            path,
+            kind: kind.to_owned(),
+            bytes: metadata.len(),
+            pages,
+        })
+    }
+}
+
+/// The numbers of the diagnostics of a compilation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestDiagnostics {
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+/// The record of a successful compilation, kept along with its document.
+#[derive(Debug, Clone)]
+pub struct CompileRecord {
+    /// The inputs of the compilation, i.e. `sys.inputs`.
+    pub inputs: Dict,
+    /// The time the compilation ended.
+    pub compiled_at: SystemTime,
+    pub diagnostics: ManifestDiagnostics,
+}
+
+/// The record of an export run.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportManifest {
+    pub entry: PathBuf,
+    pub outputs: Vec<ManifestOutput>,
+    pub inputs: Dict,
+    /// The time the compilation exported ended, in RFC 3339, if known.
+    pub compiled_at: Option<String>,
+    /// The duration of the export, excluding the compilation.
+    pub duration_ms: u64,
+    /// The diagnostics of the compilation exported, if known.
+    pub diagnostics: Option<ManifestDiagnostics>,
+}
+
+impl ExportManifest {
+    /// Describe the export of the entry, whose document is compiled by the
+    /// recorded compilation if known.
+    pub fn new(
+        entry: PathBuf,
+        outputs: Vec<ManifestOutput>,
+        compiled: Option<&CompileRecord>,
+        duration: Duration,
+    ) -> Self {
+        let compiled_at = compiled.map(|record| {
+            let time = chrono::DateTime::<chrono::Utc>::from(record.compiled_at);
+            time.to_rfc3339()
+        });
+        let inputs = compiled.map(|record| record.inputs.clone());
+        Self {
+            entry,
+            outputs,
+            inputs: inputs.unwrap_or_default(),
+            compiled_at,
+            duration_ms: duration.as_millis() as u64,
+            diagnostics: compiled.map(|record| record.diagnostics.clone()),
+        }
+    }
+
+    /// Write the manifest next to the first output, returning its path.
+    pub fn write(&self) -> anyhow::Result<PathBuf> {
+        let output = self.outputs.first().context("no file is exported")?;
+        let to = manifest_path(&output.path);
+        let data = serde_json::to_vec_pretty(self)?;
+        std::fs::write(&to, data).with_context(|| format!("failed to write manifest {to:?}"))?;
+        Ok(to)
+    }
+}
+
+/// Get the path of the manifest describing an exported file.
+fn manifest_path(output: &Path) -> PathBuf {
+    output.with_extension(MANIFEST_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+    use typst::foundations::{IntoValue, Smart};
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    #[test]
+    fn test_write_manifest() {
+        let dir = std::env::temp_dir().join(format!("tinymist-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let world = TestWorld::new("= One\n#pagebreak()\n= Two\n#pagebreak()\n= Three");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        let pdf = dir.join("main.pdf");
+        std::fs::write(&pdf, typst_pdf::pdf(&doc, Smart::Auto, None)).unwrap();
+
+        let output = ManifestOutput::new(pdf.clone(), "pdf", doc.pages.len()).unwrap();
+        let mut inputs = Dict::new();
+        inputs.insert("draft".into(), "true".into_value());
+        let record = CompileRecord {
+            inputs,
+            compiled_at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_714_953_600_000),
+            diagnostics: ManifestDiagnostics {
+                errors: 0,
+                warnings: 1,
+            },
+        };
+        let manifest = ExportManifest::new(
+            dir.join("main.typ"),
+            vec![output],
+            Some(&record),
+            Duration::from_millis(34),
+        );
+        let to = manifest.write().unwrap();
+        assert_eq!(to, dir.join("main.manifest.json"));
+
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&to).unwrap()).unwrap();
+        let outputs = written["outputs"].as_array().unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0]["path"], pdf.to_str().unwrap());
+        assert_eq!(outputs[0]["kind"], "pdf");
+        assert_eq!(outputs[0]["pages"], 3);
+        let bytes = std::fs::metadata(&pdf).unwrap().len();
+        assert_eq!(outputs[0]["bytes"], bytes);
+        assert_eq!(written["inputs"]["draft"], "true");
+        assert_eq!(written["compiledAt"], "2024-05-06T00:00:00+00:00");
+        assert_eq!(written["durationMs"], 34);
+        assert_eq!(written["diagnostics"]["warnings"], 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+//! Write the laid out text of documents as Markdown.
+//!
+//! Headings and paragraphs are recovered from the positions of the text, which
+//! is enough for converters like pandoc and the EPUB export.
+
+use typst::foundations::StyleChain;
+use typst::introspection::Meta;
+use typst::layout::{Abs, Frame, FrameItem, Point, Transform};
+use typst::math::EquationElem;
+use typst::model::{Document, HeadingElem};
+use typst::syntax::FileId;
+use typst::text::TextItem;
+use typst::visualize::Color;
+
+/// The resolution of the pictures of blocks, in pixels per point.
+const PICTURE_PIXEL_PER_PT: f32 = 2.;
+
+/// A heading, paragraph or picture of a document.
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownBlock {
+    /// The block in Markdown.
+    pub text: String,
+    /// The text of the block without Markdown markup.
+    pub plain: String,
+    /// The level of the heading, if the block is one.
+    pub heading: Option<usize>,
+    /// The file of the first text in the block.
+    pub file: Option<FileId>,
+    /// The picture of an image or a block equation in PNG, if the block is
+    /// one.
+    pub picture: Option<Vec<u8>>,
+}
+
+/// Write the text of the document as Markdown.
+pub fn markdown(doc: &Document) -> String {
+    let blocks = markdown_blocks(doc, false);
+    let mut out = (blocks.iter())
+        .map(|block| block.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    out.push('\n');
+    out
+}
+
+/// Split the text of the document into Markdown blocks. Images and block
+/// equations are rendered as pictures if `pictures` is set, and are skipped
+/// or written as text otherwise.
+pub fn markdown_blocks(doc: &Document, pictures: bool) -> Vec<MarkdownBlock> {
+    let mut writer = MarkdownWriter {
+        pictures,
+        ..Default::default()
+    };
+    for page in &doc.pages {
+        writer.frame(&page.frame, Transform::identity());
+        writer.break_paragraph();
+    }
+    writer.blocks
+}
+
+#[derive(Default)]
+struct MarkdownWriter {
+    blocks: Vec<MarkdownBlock>,
+    /// The block being written.
+    block: MarkdownBlock,
+    /// Whether to render images and block equations as pictures.
+    pictures: bool,
+    /// The baseline of the last written text.
+    last_y: Option<Abs>,
+    /// The level of the heading starting at the next text.
+    heading: Option<usize>,
+    /// Whether the current line is in a heading.
+    in_heading: bool,
+}
+
+impl MarkdownWriter {
+    fn frame(&mut self, frame: &Frame, ts: Transform) {
+        let mut items = frame.items();
+        while let Some((pos, item)) = items.next() {
+            match item {
+                FrameItem::Group(group) => {
+                    let ts = ts
+                        .pre_concat(Transform::translate(pos.x, pos.y))
+                        .pre_concat(group.transform);
+                    self.frame(&group.frame, ts);
+                }
+                FrameItem::Text(text) => self.text(pos.transform(ts).y, text),
+                FrameItem::Image(image, size, span) if self.pictures => {
+                    let mut frame = Frame::soft(*size);
+                    frame.push(Point::zero(), FrameItem::Image(image.clone(), *size, *span));
+                    self.picture(&frame);
+                }
+                FrameItem::Meta(Meta::Elem(elem), size) => {
+                    if let Some(heading) = elem.to_packed::<HeadingElem>() {
+                        let level = heading.resolve_level(StyleChain::default());
+                        self.heading = Some(level.get());
+                    }
+                    let equation = elem.to_packed::<EquationElem>();
+                    if self.pictures && equation.is_some_and(|eq| eq.block(StyleChain::default())) {
+                        // The items of the equation follow its metadata within
+                        // its bounds, whether its frame is inlined into this
+                        // one or not.
+                        let end = *pos + size.to_point();
+                        let within = |p: &Point| {
+                            (pos.x..=end.x).contains(&p.x) && (pos.y..=end.y).contains(&p.y)
+                        };
+                        let mut equation = Frame::soft(*size);
+                        while let Some((p, item)) = items.as_slice().first() {
+                            if !within(p) {
+                                break;
+                            }
+                            equation.push(*p - *pos, item.clone());
+                            items.next();
+                        }
+                        if !self.picture(&equation) {
+                            let ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+                            self.frame(&equation, ts);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Write a picture of the frame as a block, returning whether it is
+    /// rendered.
+    fn picture(&mut self, frame: &Frame) -> bool {
+        let Ok(png) = typst_render::render(frame, PICTURE_PIXEL_PER_PT, Color::WHITE).encode_png()
+        else {
+            return false;
+        };
+        self.break_paragraph();
+        self.blocks.push(MarkdownBlock {
+            text: "![](picture.png)".to_owned(),
+            picture: Some(png),
+            ..Default::default()
+        });
+        true
+    }
+
+    fn text(&mut self, y: Abs, text: &TextItem) {
+        if let Some(last_y) = self.last_y {
+            let gap = (y - last_y).abs();
+            if self.heading.is_some() || self.in_heading || gap > text.size * 1.6 {
+                self.break_paragraph();
+            } else if gap > text.size * 0.5 {
+                self.block.text.push('\n');
+                self.block.plain.push('\n');
+            }
+        }
+
+        if self.block.text.is_empty() {
+            self.block.file = text.glyphs.first().and_then(|glyph| glyph.span.0.id());
+        }
+        if let Some(level) = self.heading.take() {
+            self.block.text.push_str(&"#".repeat(level));
+            self.block.text.push(' ');
+            self.block.heading = Some(level);
+            self.in_heading = true;
+        }
+        for c in text.text.chars() {
+            if matches!(c, '\\' | '*' | '_' | '`' | '#' | '[' | ']' | '<' | '>') {
+                self.block.text.push('\\');
+            }
+            self.block.text.push(c);
+            self.block.plain.push(c);
+        }
+        self.last_y = Some(y);
+    }
+
+    fn break_paragraph(&mut self) {
+        let mut block = std::mem::take(&mut self.block);
+        block.text.truncate(block.text.trim_end().len());
+        block.plain.truncate(block.plain.trim_end().len());
+        if !block.text.is_empty() {
+            self.blocks.push(block);
+        }
+        self.last_y = None;
+        self.in_heading = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    #[test]
+    fn test_markdown() {
+        let world = TestWorld::new("= Intro\nFirst paragraph.\n\nSecond *one* [\\#1].");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        let markdown = markdown(&doc);
+        let paragraphs: Vec<_> = markdown.trim_end().split("\n\n").collect();
+        assert_eq!(paragraphs.len(), 3, "{markdown:?}");
+        assert_eq!(paragraphs[0], "# Intro");
+        assert_eq!(paragraphs[1], "First paragraph.");
+        assert!(paragraphs[2].ends_with("\\[\\#1\\]."), "{markdown:?}");
+
+        let blocks = markdown_blocks(&doc, false);
+        assert_eq!(blocks[0].heading, Some(1));
+        assert_eq!(blocks[0].plain, "Intro");
+        assert!(blocks[2].plain.ends_with("[#1]."));
+    }
+
+    #[test]
+    fn test_block_equation() {
+        let world = TestWorld::new("Before.\n\n$ a + b $\n\nAfter.");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+
+        let blocks = markdown_blocks(&doc, true);
+        let texts: Vec<_> = blocks.iter().map(|block| block.text.as_str()).collect();
+        assert_eq!(texts, ["Before.", "![](picture.png)", "After."]);
+        assert!(blocks[1].picture.is_some());
+
+        let blocks = markdown_blocks(&doc, false);
+        assert!(blocks.iter().all(|block| block.picture.is_none()));
+        let plus = blocks.iter().any(|block| block.plain.contains('+'));
+        assert!(plus, "{blocks:?}");
+    }
+}
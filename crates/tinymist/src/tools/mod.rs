@@ -1,3 +1,33 @@
+pub mod animated_svg;
+pub mod benchmark;
+pub mod bundle;
+pub mod contact_sheet;
+pub mod crop;
+pub mod diff;
+pub mod diff_report;
+pub mod epub;
+pub mod flatten;
+pub mod glyph_coverage;
+pub mod manifest;
+pub mod markdown;
 pub mod package;
+#[cfg(feature = "pandoc")]
+pub mod pandoc;
+pub mod persistent_cache;
+pub mod pptx;
 pub mod preview;
+pub mod recovery;
+pub mod reproducible;
+pub mod selection;
+pub mod series;
+pub mod split_pdf;
+pub mod svg_text;
+pub mod tagged_pdf;
+pub mod units;
+pub mod variant;
+pub mod watermark;
 pub mod word_count;
+pub mod zip;
+
+#[cfg(test)]
+pub(crate) mod tests;
@@ -1,6 +1,13 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
 use typst::diag::{eco_format, StrResult};
-use typst::syntax::package::{PackageVersion, VersionlessPackageSpec};
+use typst::syntax::package::{PackageSpec, PackageVersion, VersionlessPackageSpec};
 use typst_ts_compiler::package::Registry;
+use typst_ts_core::typst::prelude::EcoVec;
 
 use crate::world::LspWorld;
 
@@ -38,3 +45,71 @@ pub fn determine_latest_version(
             .ok_or_else(|| eco_format!("please specify the desired version"))
     }
 }
+
+/// The package index of the preview namespace, which is downloaded in the
+/// background, so that the language features never wait for the network.
+static PACKAGE_INDEX: Lazy<RwLock<Option<Arc<[PackageSpec]>>>> = Lazy::new(Default::default);
+/// Whether the package index is being downloaded.
+static INDEX_REFRESHING: AtomicBool = AtomicBool::new(false);
+
+/// Get the package index of the preview namespace if it is downloaded already.
+/// Otherwise, it is downloaded in the background and `None` is returned.
+pub fn cached_package_index(world: &LspWorld) -> Option<Arc<[PackageSpec]>> {
+    let index = PACKAGE_INDEX.read().clone();
+    if index.is_none() && !INDEX_REFRESHING.swap(true, Ordering::SeqCst) {
+        let registry = world.registry.clone();
+        std::thread::spawn(move || {
+            let packages = registry.packages().iter();
+            let index = packages.map(|(spec, _)| spec.clone()).collect();
+            *PACKAGE_INDEX.write() = Some(index);
+            INDEX_REFRESHING.store(false, Ordering::SeqCst);
+        });
+    }
+    index
+}
+
+/// Determine the latest version of a package like [`determine_latest_version`],
+/// but only from the cached package index for the preview namespace.
+pub fn cached_latest_version(
+    world: &LspWorld,
+    spec: &VersionlessPackageSpec,
+) -> Option<PackageVersion> {
+    if spec.namespace != "preview" {
+        return determine_latest_version(world, spec).ok();
+    }
+    let index = cached_package_index(world)?;
+    let versions = index.iter().filter(|package| package.name == spec.name);
+    versions.map(|package| package.version).max()
+}
+
+/// Get the latest version of each package in the cached package index of the
+/// preview namespace, among the versions downloaded already.
+pub fn cached_preview_packages(world: &LspWorld) -> EcoVec<PackageSpec> {
+    let Some(index) = cached_package_index(world) else {
+        return EcoVec::new();
+    };
+    let mut specs: Vec<_> = index.iter().collect();
+    specs.sort_by(|a, b| (a.name.cmp(&b.name)).then(b.version.cmp(&a.version)));
+
+    let mut packages = EcoVec::<PackageSpec>::new();
+    for spec in specs {
+        let found = packages.last().is_some_and(|last| last.name == spec.name);
+        if !found && cached_package_dir(world, spec).is_some() {
+            packages.push(spec.clone());
+        }
+    }
+    packages
+}
+
+/// Find the directory of a package in the data or the cache directory, without
+/// downloading it.
+pub fn cached_package_dir(world: &LspWorld, spec: &PackageSpec) -> Option<Arc<Path>> {
+    let subdir = format!(
+        "typst/packages/{}/{}/{}",
+        spec.namespace, spec.name, spec.version
+    );
+    let data_dir = world.registry.local_path().map(|dir| dir.join(&subdir));
+    let cache_dir = dirs::cache_dir().map(|dir| dir.join(&subdir));
+    let mut candidates = data_dir.into_iter().chain(cache_dir);
+    Some(candidates.find(|dir| dir.is_dir())?.into())
+}
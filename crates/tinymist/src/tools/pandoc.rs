@@ -0,0 +1,72 @@
+//! Convert documents to DOCX with an external pandoc.
+//!
+//! The laid out text of the document is written as Markdown, keeping headings
+//! and paragraphs, which pandoc then converts to DOCX.
+
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::{bail, Context};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use typst::model::Document;
+
+use super::markdown::markdown;
+
+/// Convert the document to DOCX with the pandoc executable at the path.
+///
+/// Pandoc is awaited as a child process, so that the runtime is not blocked
+/// while it converts. The standard error of pandoc is included in the error on
+/// failure.
+pub async fn docx(doc: &Document, pandoc: &Path) -> anyhow::Result<Vec<u8>> {
+    let markdown = markdown(doc);
+
+    let mut child = Command::new(pandoc)
+        .args(["--from", "markdown", "--to", "docx", "--output", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run pandoc at {}", pandoc.display()))?;
+
+    // Pandoc reads all of its input before writing any output.
+    let mut stdin = child.stdin.take().context("no stdin of pandoc")?;
+    stdin.write_all(markdown.as_bytes()).await?;
+    drop(stdin);
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("pandoc failed ({}): {}", output.status, stderr.trim());
+    }
+    if output.stdout.is_empty() {
+        bail!("pandoc produced no output");
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    #[tokio::test]
+    async fn test_docx() {
+        let pandoc = Path::new("pandoc");
+        let version = Command::new(pandoc).arg("--version").output().await;
+        if version.is_err() {
+            eprintln!("pandoc is not installed, skipping");
+            return;
+        }
+
+        let world = TestWorld::new("= Report\nHello pandoc.");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        let data = docx(&doc, pandoc).await.unwrap();
+        assert!(data.starts_with(b"PK"));
+
+        let missing = docx(&doc, Path::new("/nonexistent/pandoc")).await;
+        assert!(missing.is_err());
+    }
+}
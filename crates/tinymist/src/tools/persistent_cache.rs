@@ -0,0 +1,410 @@
+//! An on-disk cache of exported artifacts, which lets an unchanged project be
+//! exported without rendering it again after a restart.
+//!
+//! The artifacts are keyed by the hash of the inputs of the compilation, i.e.
+//! the scope of it, which is the entry, the inputs from `sys.inputs` and the
+//! fonts, and the fingerprints of the files read by it, together with the
+//! export options. Any change of them misses the cache. The fingerprints read
+//! by the last compilation in each scope are recorded as well, so that the
+//! artifacts are found before compiling again. The least recently used
+//! artifacts are evicted once the cache outgrows its size limit.
+//!
+//! The cache directory is shared by all processes. Files are written
+//! atomically, and the index is updated under a lock file.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// The default size limit of the cache, in megabytes.
+pub const DEFAULT_CACHE_LIMIT_MB: u64 = 256;
+
+const INDEX_FILE: &str = "index.json";
+const LOCK_FILE: &str = "index.lock";
+/// The number of scopes whose fingerprints are recorded.
+const MAX_SNAPSHOTS: usize = 1024;
+/// The time to wait for the lock held by another process.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+/// The age of a lock considered left by a crashed process.
+const STALE_LOCK: Duration = Duration::from_secs(30);
+
+/// The index of the artifacts in the cache directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheIndex {
+    /// The number of artifacts built on cache misses, across all runs.
+    builds: u64,
+    /// The logical clock ordering the uses of the artifacts.
+    clock: u64,
+    /// The artifacts, keyed by their file names.
+    entries: BTreeMap<String, CacheEntry>,
+    /// The fingerprints read by the last compilation, keyed by the scopes.
+    #[serde(default)]
+    snapshots: BTreeMap<String, Snapshot>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    /// The size of the artifact in bytes.
+    size: u64,
+    /// The tick of the clock when the artifact was last used.
+    last_used: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Snapshot {
+    /// The files read by the compilation and their fingerprints.
+    files: Vec<(PathBuf, String)>,
+    /// The tick of the clock when the snapshot was last recorded.
+    last_used: u64,
+}
+
+/// Hash the inputs of a compilation in the scope, which keys its artifacts
+/// together with the export options.
+pub fn inputs_hash(scope: u128, fingerprints: &[(PathBuf, u128)]) -> u128 {
+    typst::util::hash128(&(env!("CARGO_PKG_VERSION"), scope, fingerprints))
+}
+
+/// A cache of artifacts in a directory, which is shared by all runs.
+#[derive(Debug)]
+pub struct PersistentCache {
+    dir: PathBuf,
+    /// The size limit of the artifacts in bytes.
+    limit: u64,
+    lock: Mutex<()>,
+}
+
+impl PersistentCache {
+    /// Create a cache in the directory, which is created on the first store.
+    pub fn new(dir: PathBuf, limit: u64) -> Self {
+        Self {
+            dir,
+            limit,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Create a cache in the cache directory of the user, with the size limit
+    /// in megabytes.
+    pub fn in_user_cache(limit_mb: u64) -> Option<Self> {
+        let dir = dirs::cache_dir()?.join("tinymist").join("exports");
+        Some(Self::new(dir, limit_mb.saturating_mul(1024 * 1024)))
+    }
+
+    /// The number of artifacts built on cache misses, across all runs.
+    pub fn builds(&self) -> u64 {
+        let _guard = self.lock.lock();
+        self.read_index().builds
+    }
+
+    /// Record the fingerprints of the files read by the compilation in the
+    /// scope, and get the hash of its inputs.
+    pub fn record_inputs(
+        &self,
+        scope: u128,
+        fingerprints: Vec<(PathBuf, u128)>,
+    ) -> anyhow::Result<u128> {
+        let inputs = inputs_hash(scope, &fingerprints);
+        let files = fingerprints
+            .into_iter()
+            .map(|(path, fingerprint)| (path, format!("{fingerprint:032x}")))
+            .collect::<Vec<_>>();
+
+        let _guard = self.lock_index()?;
+        let mut index = self.read_index();
+        let name = format!("{scope:032x}");
+        if index.snapshots.get(&name).is_some_and(|s| s.files == files) {
+            return Ok(inputs);
+        }
+        index.clock += 1;
+        let last_used = index.clock;
+        index.snapshots.insert(name, Snapshot { files, last_used });
+        while index.snapshots.len() > MAX_SNAPSHOTS {
+            let oldest = (index.snapshots.iter())
+                .min_by_key(|(_, snapshot)| snapshot.last_used)
+                .map(|(name, _)| name.clone());
+            index.snapshots.remove(&oldest.unwrap());
+        }
+        self.write_index(&index)?;
+        Ok(inputs)
+    }
+
+    /// Get the hash of the inputs recorded in the scope, if the files read by
+    /// the compilation still have the same fingerprints, which is checked
+    /// before compiling.
+    pub fn recorded_inputs(
+        &self,
+        scope: u128,
+        fingerprint: impl Fn(&Path) -> Option<u128>,
+    ) -> Option<u128> {
+        let index = {
+            let _guard = self.lock.lock();
+            self.read_index()
+        };
+        let snapshot = index.snapshots.get(&format!("{scope:032x}"))?;
+        let fingerprints = (snapshot.files.iter())
+            .map(|(path, recorded)| {
+                let current = fingerprint(path)?;
+                (format!("{current:032x}") == *recorded).then(|| (path.clone(), current))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(inputs_hash(scope, &fingerprints))
+    }
+
+    /// Get the artifact with the key, if cached.
+    pub fn get(&self, key: u128) -> Option<Vec<u8>> {
+        self.lookup(key).ok().flatten()
+    }
+
+    /// Get the artifact with the key, or build and store it.
+    pub fn get_or_build(
+        &self,
+        key: u128,
+        build: impl FnOnce() -> anyhow::Result<Vec<u8>>,
+    ) -> anyhow::Result<Vec<u8>> {
+        if let Some(data) = self.lookup(key)? {
+            return Ok(data);
+        }
+
+        // Build without holding the lock, since rendering may take long.
+        let data = build()?;
+        let name = format!("{key:032x}");
+        fs::create_dir_all(&self.dir).context("failed to create cache directory")?;
+        write_atomic(&self.dir.join(&name), &data).context("failed to write cache")?;
+
+        let _guard = self.lock_index()?;
+        let mut index = self.read_index();
+        index.clock += 1;
+        index.builds += 1;
+        let entry = CacheEntry {
+            size: data.len() as u64,
+            last_used: index.clock,
+        };
+        index.entries.insert(name, entry);
+        self.evict(&mut index);
+        self.write_index(&index)?;
+
+        Ok(data)
+    }
+
+    /// Read the artifact with the key and mark it as used.
+    fn lookup(&self, key: u128) -> anyhow::Result<Option<Vec<u8>>> {
+        let _guard = self.lock_index()?;
+        let mut index = self.read_index();
+        let name = format!("{key:032x}");
+        if !index.entries.contains_key(&name) {
+            return Ok(None);
+        }
+
+        index.clock += 1;
+        let data = match fs::read(self.dir.join(&name)) {
+            Ok(data) => {
+                index.entries.get_mut(&name).unwrap().last_used = index.clock;
+                log::info!("PersistentCache: hit {name}");
+                Some(data)
+            }
+            Err(err) => {
+                log::warn!("PersistentCache: failed to read {name}: {err}");
+                index.entries.remove(&name);
+                None
+            }
+        };
+        self.write_index(&index)?;
+        Ok(data)
+    }
+
+    /// Lock the index against other threads and processes.
+    fn lock_index(&self) -> anyhow::Result<IndexLock<'_>> {
+        let guard = self.lock.lock();
+        fs::create_dir_all(&self.dir).context("failed to create cache directory")?;
+        let path = self.dir.join(LOCK_FILE);
+        let start = SystemTime::now();
+        loop {
+            let created = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path);
+            match created {
+                Ok(_) => break,
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err).context("failed to lock cache index"),
+            }
+
+            let age = fs::metadata(&path).and_then(|meta| meta.modified());
+            let age = age.ok().and_then(|modified| modified.elapsed().ok());
+            if age.is_some_and(|age| age > STALE_LOCK) {
+                log::warn!("PersistentCache: break stale lock {path:?}");
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            if start.elapsed().unwrap_or_default() > LOCK_TIMEOUT {
+                anyhow::bail!("timed out waiting for the lock of cache index");
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        Ok(IndexLock {
+            path,
+            _guard: guard,
+        })
+    }
+
+    /// Evict the least recently used artifacts until the cache fits in the
+    /// size limit.
+    fn evict(&self, index: &mut CacheIndex) {
+        let mut size: u64 = index.entries.values().map(|entry| entry.size).sum();
+        while size > self.limit {
+            let Some(name) = (index.entries.iter())
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(name, _)| name.clone())
+            else {
+                break;
+            };
+            let entry = index.entries.remove(&name).unwrap();
+            size -= entry.size;
+            if let Err(err) = fs::remove_file(self.dir.join(&name)) {
+                log::warn!("PersistentCache: failed to evict {name}: {err}");
+            }
+        }
+    }
+
+    fn read_index(&self) -> CacheIndex {
+        let Ok(index) = fs::read(self.dir.join(INDEX_FILE)) else {
+            return CacheIndex::default();
+        };
+        serde_json::from_slice(&index).unwrap_or_else(|err| {
+            log::warn!("PersistentCache: discard corrupted index: {err}");
+            CacheIndex::default()
+        })
+    }
+
+    fn write_index(&self, index: &CacheIndex) -> anyhow::Result<()> {
+        let index = serde_json::to_vec(index)?;
+        write_atomic(&self.dir.join(INDEX_FILE), &index).context("failed to write cache index")
+    }
+}
+
+/// The lock of the index, which is released on drop.
+struct IndexLock<'a> {
+    path: PathBuf,
+    _guard: parking_lot::MutexGuard<'a, ()>,
+}
+
+impl Drop for IndexLock<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!("PersistentCache: failed to unlock {:?}: {err}", self.path);
+        }
+    }
+}
+
+/// Write the file through a temporary file, so that readers in other
+/// processes never see it partially written.
+fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    fs::write(&tmp, data)?;
+    let res = fs::rename(&tmp, path);
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tinymist-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_cache_across_runs() {
+        let dir = cache_dir("cache-runs");
+
+        // The first cold start builds the artifact.
+        let cache = PersistentCache::new(dir.clone(), 1024);
+        let data = cache.get_or_build(1, || Ok(b"%PDF".to_vec())).unwrap();
+        assert_eq!(data, b"%PDF");
+        assert_eq!(cache.builds(), 1);
+
+        // The second one with unchanged inputs reuses it.
+        let cache = PersistentCache::new(dir.clone(), 1024);
+        let data = cache.get_or_build(1, || panic!("rebuilt")).unwrap();
+        assert_eq!(data, b"%PDF");
+        assert_eq!(cache.builds(), 1);
+
+        // Changed inputs have another key.
+        cache.get_or_build(2, || Ok(b"%PDF2".to_vec())).unwrap();
+        assert_eq!(cache.builds(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_recorded_inputs_across_runs() {
+        let dir = cache_dir("cache-inputs");
+        let files = vec![
+            (PathBuf::from("/ws/main.typ"), 1),
+            (PathBuf::from("/ws/chapter.typ"), 2),
+        ];
+        let current = |path: &Path| {
+            let file = files.iter().find(|(file, _)| file == path);
+            file.map(|(_, fingerprint)| *fingerprint)
+        };
+
+        // The first cold start compiles, records the files read and builds the
+        // artifact.
+        let cache = PersistentCache::new(dir.clone(), 1024);
+        assert_eq!(cache.recorded_inputs(7, current), None);
+        let inputs = cache.record_inputs(7, files.clone()).unwrap();
+        cache.get_or_build(inputs, || Ok(b"%PDF".to_vec())).unwrap();
+
+        // The second one finds the artifact before compiling.
+        let cache = PersistentCache::new(dir.clone(), 1024);
+        let recorded = cache.recorded_inputs(7, current).unwrap();
+        assert_eq!(recorded, inputs);
+        assert_eq!(cache.get(recorded).as_deref(), Some(&b"%PDF"[..]));
+        assert_eq!(cache.builds(), 1);
+
+        // A changed file or another scope misses.
+        let changed = |path: &Path| Some(if path.ends_with("chapter.typ") { 3 } else { 1 });
+        assert_eq!(cache.recorded_inputs(7, changed), None);
+        assert_eq!(cache.recorded_inputs(8, current), None);
+        assert!(!dir.join(LOCK_FILE).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let dir = cache_dir("cache-evict");
+        let cache = PersistentCache::new(dir.clone(), 8);
+        let artifact = || Ok(vec![0; 4]);
+
+        cache.get_or_build(1, artifact).unwrap();
+        cache.get_or_build(2, artifact).unwrap();
+        // Use the first one, so that the second one is evicted for the third.
+        cache.get_or_build(1, artifact).unwrap();
+        cache.get_or_build(3, artifact).unwrap();
+        assert_eq!(cache.builds(), 3);
+
+        cache.get_or_build(1, artifact).unwrap();
+        cache.get_or_build(3, artifact).unwrap();
+        assert_eq!(cache.builds(), 3);
+        cache.get_or_build(2, artifact).unwrap();
+        assert_eq!(cache.builds(), 4);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
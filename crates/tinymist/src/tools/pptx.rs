@@ -0,0 +1,407 @@
+//! Assemble the pages of a document into a PowerPoint deck.
+//!
+//! Each page becomes a slide showing the rendered page as a picture, which is
+//! enough to present slides made by packages like `polylux` in office suites.
+//! The deck is a minimal OOXML package, stored in a zip archive without
+//! compression since the pictures are compressed already.
+
+use std::fmt::Write;
+
+use anyhow::{bail, Context};
+use typst::foundations::{Label, Repr, Value};
+use typst::introspection::{Meta, MetadataElem};
+use typst::layout::{Abs, Frame, FrameItem, Size};
+use typst::model::Document;
+use typst::visualize::Color;
+
+use super::zip::ZipWriter;
+
+/// The label of metadata holding the speaker notes of a slide, e.g.
+/// `#metadata[Greet the audience] <notes>`.
+pub const NOTES_LABEL: &str = "notes";
+
+/// The default resolution of slide pictures, in pixels per inch.
+pub const DEFAULT_PPI: f32 = 144.;
+const MAX_PPI: f32 = 600.;
+
+/// The number of English Metric Units per point.
+const EMU_PER_PT: f64 = 12700.;
+
+const XML_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#;
+const NAMESPACES: &str = r#"xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main""#;
+const CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument";
+const REL_TYPE: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const EMPTY_TREE: &str =
+    r#"<p:nvGrpSpPr><p:cNvPr id="1" name=""/><p:cNvGrpSpPr/><p:nvPr/></p:nvGrpSpPr><p:grpSpPr/>"#;
+const COLOR_MAP: &str = r#"<p:clrMap bg1="lt1" tx1="dk1" bg2="lt2" tx2="dk2" accent1="accent1" accent2="accent2" accent3="accent3" accent4="accent4" accent5="accent5" accent6="accent6" hlink="hlink" folHlink="folHlink"/>"#;
+
+/// Render the pages of the document into a PPTX deck, pulling speaker notes
+/// from metadata labelled with [`NOTES_LABEL`] if `notes` is set.
+pub fn pptx(doc: &Document, ppi: f32, notes: bool) -> anyhow::Result<Vec<u8>> {
+    validate_ppi(ppi)?;
+    if doc.pages.is_empty() {
+        bail!("the document has no pages");
+    }
+
+    let slide_size = doc.pages.iter().fold(Size::zero(), |size, page| {
+        let page = page.frame.size();
+        Size::new(size.x.max(page.x), size.y.max(page.y))
+    });
+    let notes: Vec<Option<String>> = doc
+        .pages
+        .iter()
+        .map(|page| notes.then(|| slide_notes(&page.frame)).flatten())
+        .collect();
+    let has_notes = notes.iter().any(Option::is_some);
+
+    let mut zip = ZipWriter::default();
+    zip.add(
+        "[Content_Types].xml",
+        content_types(doc.pages.len(), &notes),
+    );
+    zip.add(
+        "_rels/.rels",
+        rels(&[("officeDocument", "ppt/presentation.xml")]),
+    );
+    zip.add(
+        "ppt/presentation.xml",
+        presentation(doc.pages.len(), slide_size, has_notes),
+    );
+
+    let mut targets = vec![("slideMaster", "slideMasters/slideMaster1.xml".to_owned())];
+    for idx in 1..=doc.pages.len() {
+        targets.push(("slide", format!("slides/slide{idx}.xml")));
+    }
+    targets.push(("theme", "theme/theme1.xml".to_owned()));
+    if has_notes {
+        targets.push(("notesMaster", "notesMasters/notesMaster1.xml".to_owned()));
+    }
+    let targets: Vec<_> = targets.iter().map(|(ty, t)| (*ty, t.as_str())).collect();
+    zip.add("ppt/_rels/presentation.xml.rels", rels(&targets));
+
+    zip.add("ppt/slideMasters/slideMaster1.xml", slide_master());
+    zip.add(
+        "ppt/slideMasters/_rels/slideMaster1.xml.rels",
+        rels(&[
+            ("slideLayout", "../slideLayouts/slideLayout1.xml"),
+            ("theme", "../theme/theme1.xml"),
+        ]),
+    );
+    zip.add("ppt/slideLayouts/slideLayout1.xml", slide_layout());
+    zip.add(
+        "ppt/slideLayouts/_rels/slideLayout1.xml.rels",
+        rels(&[("slideMaster", "../slideMasters/slideMaster1.xml")]),
+    );
+    zip.add("ppt/theme/theme1.xml", theme());
+
+    if has_notes {
+        zip.add("ppt/notesMasters/notesMaster1.xml", notes_master());
+        zip.add(
+            "ppt/notesMasters/_rels/notesMaster1.xml.rels",
+            rels(&[("theme", "../theme/theme2.xml")]),
+        );
+        zip.add("ppt/theme/theme2.xml", theme());
+    }
+
+    for (idx, (page, notes)) in doc.pages.iter().zip(&notes).enumerate() {
+        let num = idx + 1;
+        let png = typst_render::render(&page.frame, ppi / 72., Color::WHITE)
+            .encode_png()
+            .context("failed to encode PNG")?;
+        zip.add(&format!("ppt/media/image{num}.png"), png);
+
+        let image = format!("../media/image{num}.png");
+        let notes_slide = format!("../notesSlides/notesSlide{num}.xml");
+        let mut targets = vec![
+            ("slideLayout", "../slideLayouts/slideLayout1.xml"),
+            ("image", image.as_str()),
+        ];
+        if notes.is_some() {
+            targets.push(("notesSlide", notes_slide.as_str()));
+        }
+        zip.add(
+            &format!("ppt/slides/slide{num}.xml"),
+            slide(num, page.frame.size()),
+        );
+        zip.add(
+            &format!("ppt/slides/_rels/slide{num}.xml.rels"),
+            rels(&targets),
+        );
+
+        if let Some(notes) = notes {
+            let slide = format!("../slides/slide{num}.xml");
+            zip.add(
+                &format!("ppt/notesSlides/notesSlide{num}.xml"),
+                notes_slide_xml(notes),
+            );
+            zip.add(
+                &format!("ppt/notesSlides/_rels/notesSlide{num}.xml.rels"),
+                rels(&[
+                    ("notesMaster", "../notesMasters/notesMaster1.xml"),
+                    ("slide", slide.as_str()),
+                ]),
+            );
+        }
+    }
+
+    zip.finish()
+}
+
+/// Check the resolution of slide pictures.
+pub fn validate_ppi(ppi: f32) -> anyhow::Result<()> {
+    if !(ppi > 0. && ppi <= MAX_PPI) {
+        bail!("ppi must be positive and at most {MAX_PPI}, got {ppi}");
+    }
+    Ok(())
+}
+
+/// Collect the text of labelled metadata on a page.
+fn slide_notes(frame: &Frame) -> Option<String> {
+    fn collect(frame: &Frame, label: Label, notes: &mut Vec<String>) {
+        for (_, item) in frame.items() {
+            match item {
+                FrameItem::Group(group) => collect(&group.frame, label, notes),
+                FrameItem::Meta(Meta::Elem(content), _) if content.label() == Some(label) => {
+                    let Some(elem) = content.to_packed::<MetadataElem>() else {
+                        continue;
+                    };
+                    notes.push(match elem.value() {
+                        Value::Str(s) => s.to_string(),
+                        Value::Content(c) => c.plain_text().to_string(),
+                        v => v.repr().to_string(),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut notes = vec![];
+    collect(frame, Label::new(NOTES_LABEL), &mut notes);
+    (!notes.is_empty()).then(|| notes.join("\n"))
+}
+
+fn emu(abs: Abs) -> i64 {
+    (abs.to_pt() * EMU_PER_PT).round() as i64
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn content_types(slides: usize, notes: &[Option<String>]) -> String {
+    let ml = format!("{CONTENT_TYPE}.presentationml");
+    let mut xml = format!(
+        r#"{XML_HEADER}<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Default Extension="png" ContentType="image/png"/>"#
+    );
+    let mut part = |name: &str, ty: &str| {
+        let _ = write!(
+            xml,
+            r#"<Override PartName="/ppt/{name}" ContentType="{ty}"/>"#
+        );
+    };
+    part("presentation.xml", &format!("{ml}.presentation.main+xml"));
+    part(
+        "slideMasters/slideMaster1.xml",
+        &format!("{ml}.slideMaster+xml"),
+    );
+    part(
+        "slideLayouts/slideLayout1.xml",
+        &format!("{ml}.slideLayout+xml"),
+    );
+    part("theme/theme1.xml", &format!("{CONTENT_TYPE}.theme+xml"));
+    for idx in 1..=slides {
+        part(
+            &format!("slides/slide{idx}.xml"),
+            &format!("{ml}.slide+xml"),
+        );
+    }
+    if notes.iter().any(Option::is_some) {
+        part(
+            "notesMasters/notesMaster1.xml",
+            &format!("{ml}.notesMaster+xml"),
+        );
+        part("theme/theme2.xml", &format!("{CONTENT_TYPE}.theme+xml"));
+    }
+    for (idx, _) in notes.iter().enumerate().filter(|(_, n)| n.is_some()) {
+        let name = format!("notesSlides/notesSlide{}.xml", idx + 1);
+        part(&name, &format!("{ml}.notesSlide+xml"));
+    }
+    xml.push_str("</Types>");
+    xml
+}
+
+/// Relationships to the targets, identified by `rId1`, `rId2`, and so on.
+fn rels(targets: &[(&str, &str)]) -> String {
+    let mut xml = format!(
+        r#"{XML_HEADER}<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#
+    );
+    for (idx, (ty, target)) in targets.iter().enumerate() {
+        let id = idx + 1;
+        let _ = write!(
+            xml,
+            r#"<Relationship Id="rId{id}" Type="{REL_TYPE}/{ty}" Target="{target}"/>"#
+        );
+    }
+    xml.push_str("</Relationships>");
+    xml
+}
+
+fn presentation(slides: usize, size: Size, has_notes: bool) -> String {
+    let mut xml = format!(
+        r#"{XML_HEADER}<p:presentation {NAMESPACES}><p:sldMasterIdLst><p:sldMasterId id="2147483648" r:id="rId1"/></p:sldMasterIdLst>"#
+    );
+    if has_notes {
+        // The notes master follows the slides and the theme.
+        let id = slides + 3;
+        let _ = write!(
+            xml,
+            r#"<p:notesMasterIdLst><p:notesMasterId r:id="rId{id}"/></p:notesMasterIdLst>"#
+        );
+    }
+    xml.push_str("<p:sldIdLst>");
+    for idx in 1..=slides {
+        let _ = write!(
+            xml,
+            r#"<p:sldId id="{}" r:id="rId{}"/>"#,
+            255 + idx,
+            idx + 1
+        );
+    }
+    let _ = write!(
+        xml,
+        r#"</p:sldIdLst><p:sldSz cx="{}" cy="{}"/><p:notesSz cx="6858000" cy="9144000"/></p:presentation>"#,
+        emu(size.x),
+        emu(size.y)
+    );
+    xml
+}
+
+fn slide_master() -> String {
+    format!(
+        r#"{XML_HEADER}<p:sldMaster {NAMESPACES}><p:cSld><p:spTree>{EMPTY_TREE}</p:spTree></p:cSld>{COLOR_MAP}<p:sldLayoutIdLst><p:sldLayoutId id="2147483649" r:id="rId1"/></p:sldLayoutIdLst></p:sldMaster>"#
+    )
+}
+
+fn slide_layout() -> String {
+    format!(
+        r#"{XML_HEADER}<p:sldLayout {NAMESPACES}><p:cSld><p:spTree>{EMPTY_TREE}</p:spTree></p:cSld><p:clrMapOvr><a:masterClrMapping/></p:clrMapOvr></p:sldLayout>"#
+    )
+}
+
+fn notes_master() -> String {
+    format!(
+        r#"{XML_HEADER}<p:notesMaster {NAMESPACES}><p:cSld><p:spTree>{EMPTY_TREE}</p:spTree></p:cSld>{COLOR_MAP}</p:notesMaster>"#
+    )
+}
+
+/// A slide showing the page picture `rId2` at the top left corner.
+fn slide(num: usize, size: Size) -> String {
+    let (cx, cy) = (emu(size.x), emu(size.y));
+    format!(
+        r#"{XML_HEADER}<p:sld {NAMESPACES}><p:cSld><p:spTree>{EMPTY_TREE}<p:pic><p:nvPicPr><p:cNvPr id="2" name="Page {num}"/><p:cNvPicPr><a:picLocks noChangeAspect="1"/></p:cNvPicPr><p:nvPr/></p:nvPicPr><p:blipFill><a:blip r:embed="rId2"/><a:stretch><a:fillRect/></a:stretch></p:blipFill><p:spPr><a:xfrm><a:off x="0" y="0"/><a:ext cx="{cx}" cy="{cy}"/></a:xfrm><a:prstGeom prst="rect"><a:avLst/></a:prstGeom></p:spPr></p:pic></p:spTree></p:cSld><p:clrMapOvr><a:masterClrMapping/></p:clrMapOvr></p:sld>"#
+    )
+}
+
+fn notes_slide_xml(notes: &str) -> String {
+    let paragraphs: String = notes
+        .lines()
+        .map(|line| {
+            format!(
+                r#"<a:p><a:r><a:rPr lang="en-US"/><a:t>{}</a:t></a:r></a:p>"#,
+                escape(line)
+            )
+        })
+        .collect();
+    format!(
+        r#"{XML_HEADER}<p:notes {NAMESPACES}><p:cSld><p:spTree>{EMPTY_TREE}<p:sp><p:nvSpPr><p:cNvPr id="2" name="Notes"/><p:cNvSpPr><a:spLocks noGrp="1"/></p:cNvSpPr><p:nvPr><p:ph type="body" idx="1"/></p:nvPr></p:nvSpPr><p:spPr/><p:txBody><a:bodyPr/><a:lstStyle/>{paragraphs}</p:txBody></p:sp></p:spTree></p:cSld><p:clrMapOvr><a:masterClrMapping/></p:clrMapOvr></p:notes>"#
+    )
+}
+
+/// A plain theme, which is required by the masters.
+fn theme() -> String {
+    let colors = [
+        ("dk1", "000000"),
+        ("lt1", "FFFFFF"),
+        ("dk2", "44546A"),
+        ("lt2", "E7E6E6"),
+        ("accent1", "4472C4"),
+        ("accent2", "ED7D31"),
+        ("accent3", "A5A5A5"),
+        ("accent4", "FFC000"),
+        ("accent5", "5B9BD5"),
+        ("accent6", "70AD47"),
+        ("hlink", "0563C1"),
+        ("folHlink", "954F72"),
+    ];
+    let colors: String = colors
+        .iter()
+        .map(|(name, rgb)| format!(r#"<a:{name}><a:srgbClr val="{rgb}"/></a:{name}>"#))
+        .collect();
+    let font = r#"<a:latin typeface="Calibri"/><a:ea typeface=""/><a:cs typeface=""/>"#;
+    let fill = r#"<a:solidFill><a:schemeClr val="phClr"/></a:solidFill>"#.repeat(3);
+    let line =
+        r#"<a:ln w="9525"><a:solidFill><a:schemeClr val="phClr"/></a:solidFill></a:ln>"#.repeat(3);
+    let effect = "<a:effectStyle><a:effectLst/></a:effectStyle>".repeat(3);
+    format!(
+        r#"{XML_HEADER}<a:theme xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" name="Tinymist"><a:themeElements><a:clrScheme name="Tinymist">{colors}</a:clrScheme><a:fontScheme name="Tinymist"><a:majorFont>{font}</a:majorFont><a:minorFont>{font}</a:minorFont></a:fontScheme><a:fmtScheme name="Tinymist"><a:fillStyleLst>{fill}</a:fillStyleLst><a:lnStyleLst>{line}</a:lnStyleLst><a:effectStyleLst>{effect}</a:effectStyleLst><a:bgFillStyleLst>{fill}</a:bgFillStyleLst></a:fmtScheme></a:themeElements></a:theme>"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::{tests::TestWorld, zip::zip_entries};
+
+    #[test]
+    fn test_pptx() {
+        let world = TestWorld::new(
+            "#set page(width: 160pt, height: 90pt)\n\
+             = Intro #metadata[Say hello] <notes>\n#pagebreak()\n\
+             = Body\n#pagebreak()\n\
+             = End #metadata(\"Thanks & bye\") <notes>",
+        );
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        assert_eq!(doc.pages.len(), 3);
+
+        let deck = pptx(&doc, 72., true).unwrap();
+        let entries = zip_entries(&deck);
+        let slides: Vec<_> = (entries.iter())
+            .filter(|(name, _)| name.starts_with("ppt/slides/slide"))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(
+            slides,
+            vec![
+                "ppt/slides/slide1.xml",
+                "ppt/slides/slide2.xml",
+                "ppt/slides/slide3.xml"
+            ]
+        );
+
+        let file = |name: &str| {
+            let (_, content) = entries.iter().find(|(n, _)| n == name).unwrap();
+            String::from_utf8(content.clone()).unwrap()
+        };
+        assert!(file("ppt/presentation.xml").contains(r#"<p:sldSz cx="2032000" cy="1143000"/>"#));
+        assert!(file("ppt/notesSlides/notesSlide1.xml").contains("Say hello"));
+        assert!(file("ppt/notesSlides/notesSlide3.xml").contains("Thanks &amp; bye"));
+        assert!(!entries.iter().any(|(n, _)| n.ends_with("notesSlide2.xml")));
+
+        let deck = pptx(&doc, 72., false).unwrap();
+        let entries = zip_entries(&deck);
+        assert!(!entries.iter().any(|(n, _)| n.starts_with("ppt/notes")));
+    }
+}
@@ -1,3 +1,11 @@
+//! The embedded preview server, which shares the compiler of the language
+//! server with the previewer.
+
+use std::net::{SocketAddr, TcpListener};
+
+use serde::Serialize;
+use tokio::sync::oneshot;
+
 #[cfg(feature = "preview")]
 pub use typst_preview::CompileStatus;
 #[cfg(not(feature = "preview"))]
@@ -20,15 +28,88 @@ pub trait CompilationHandle: Send + 'static {
     );
 }
 
+/// The addresses of a running preview server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewUrls {
+    /// The URL serving the preview page.
+    pub url: String,
+    /// The URL of the WebSocket streaming the rendered document.
+    pub data_plane_url: String,
+}
+
+impl PreviewUrls {
+    /// The URLs of a preview server whose data plane listens on the address.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            url: format!("http://{addr}"),
+            data_plane_url: format!("ws://{addr}"),
+        }
+    }
+}
+
+/// The preview server of a compiler, which is started on demand and lives
+/// until the compiler is restarted.
+#[derive(Debug, Default)]
+pub struct PreviewServer {
+    running: Option<(PreviewUrls, oneshot::Sender<()>)>,
+}
+
+impl PreviewServer {
+    /// Get the URLs of the running server, or start one with the given
+    /// function, which stops the server once the receiver is notified.
+    pub fn get_or_start(
+        &mut self,
+        start: impl FnOnce(oneshot::Receiver<()>) -> anyhow::Result<PreviewUrls>,
+    ) -> anyhow::Result<PreviewUrls> {
+        if let Some((urls, _)) = &self.running {
+            return Ok(urls.clone());
+        }
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let urls = start(stop_rx)?;
+        self.running = Some((urls.clone(), stop_tx));
+        Ok(urls)
+    }
+
+    /// Shut down the running server, if any.
+    pub fn shutdown(&mut self) {
+        if let Some((urls, stop)) = self.running.take() {
+            log::info!("Preview: shutting down the server at {}", urls.url);
+            let _ = stop.send(());
+        }
+    }
+}
+
+impl Drop for PreviewServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Reserve a free port on the loopback interface.
+///
+/// The listener is dropped before the port is used, so the port may be taken
+/// by others in between, which is unlikely and fails the start of the server.
+pub fn free_local_addr() -> anyhow::Result<SocketAddr> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?)
+}
+
+#[cfg(feature = "preview")]
+pub use preview_exts::start_preview;
+
 #[cfg(feature = "preview")]
 mod preview_exts {
     use std::path::Path;
+    use std::sync::Arc;
 
+    use clap_builder::{Args, Command, FromArgMatches};
+    use parking_lot::Mutex;
+    use tokio::sync::oneshot;
     use typst::layout::Position;
     use typst::syntax::Span;
     use typst_preview::{
-        CompileHost, DocToSrcJumpInfo, EditorServer, Location, MemoryFiles, MemoryFilesShort,
-        SourceFileServer,
+        CompilationHandleImpl, CompileHost, DocToSrcJumpInfo, EditorServer, Location, MemoryFiles,
+        MemoryFilesShort, PreviewArgs, SourceFileServer,
     };
     use typst_ts_compiler::vfs::notify::FileChangeSet;
     use typst_ts_compiler::vfs::notify::MemoryEvent;
@@ -36,6 +117,53 @@ mod preview_exts {
     use typst_ts_core::Error;
 
     use crate::actor::typ_client::CompileClientActor;
+    use crate::state::normalize_path;
+
+    use super::{free_local_addr, PreviewUrls};
+
+    /// The frontend of typst-preview served by the data plane, which is located
+    /// by the build script.
+    const PREVIEW_HTML: &str = include_str!(env!("TYPST_PREVIEW_FRONTEND"));
+
+    /// Start a previewer of the compiler in the background, whose compilations
+    /// are reported to the handle shared with the compile handler, until the
+    /// `stop` receiver is notified.
+    pub fn start_preview(
+        client: CompileClientActor,
+        handle: Arc<Mutex<Option<CompilationHandleImpl>>>,
+        stop: oneshot::Receiver<()>,
+    ) -> anyhow::Result<PreviewUrls> {
+        let data_plane = free_local_addr()?;
+        let control_plane = free_local_addr()?;
+        let command = PreviewArgs::augment_args(Command::new("preview"));
+        let matches = command.try_get_matches_from([
+            "preview".to_owned(),
+            format!("--data-plane-host={data_plane}"),
+            format!("--control-plane-host={control_plane}"),
+        ])?;
+        let args = PreviewArgs::from_arg_matches(&matches)?;
+
+        tokio::spawn(async move {
+            let compilation_handle = handle.clone();
+            let previewer = typst_preview::preview(
+                args,
+                move |compilation| {
+                    *compilation_handle.lock() = Some(compilation);
+                    Arc::new(client)
+                },
+                PREVIEW_HTML,
+            )
+            .await;
+            // The previewer is dropped, closing its planes, once stopped.
+            tokio::select! {
+                _ = previewer.join() => {}
+                _ = stop => {}
+            }
+            *handle.lock() = None;
+        });
+
+        Ok(PreviewUrls::new(data_plane))
+    }
 
     impl SourceFileServer for CompileClientActor {
         async fn resolve_source_span(
@@ -89,7 +217,7 @@ mod preview_exts {
             files: MemoryFiles,
             reset_shadow: bool,
         ) -> Result<(), Error> {
-            // todo: is it safe to believe that the path is normalized?
+            let roots = &self.config.roots;
             let now = std::time::SystemTime::now();
             let files = FileChangeSet::new_inserts(
                 files
@@ -97,8 +225,7 @@ mod preview_exts {
                     .into_iter()
                     .map(|(path, content)| {
                         let content = content.as_bytes().into();
-                        // todo: cloning PathBuf -> Arc<Path>
-                        (path.into(), Ok((now, content)).into())
+                        (normalize_path(&path, roots), Ok((now, content)).into())
                     })
                     .collect(),
             );
@@ -112,9 +239,9 @@ mod preview_exts {
         }
 
         async fn remove_shadow_files(&mut self, files: MemoryFilesShort) -> Result<(), Error> {
-            // todo: is it safe to believe that the path is normalized?
-            let files =
-                FileChangeSet::new_removes(files.files.into_iter().map(From::from).collect());
+            let roots = &self.config.roots;
+            let files = files.files.iter().map(|path| normalize_path(path, roots));
+            let files = FileChangeSet::new_removes(files.collect());
             self.inner().add_memory_changes(MemoryEvent::Update(files));
 
             Ok(())
@@ -123,3 +250,37 @@ mod preview_exts {
 
     impl CompileHost for CompileClientActor {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_server_reused() {
+        let mut server = PreviewServer::default();
+        let mut started = 0;
+        let mut stops = Vec::new();
+        let mut start = |stop| {
+            started += 1;
+            stops.push(stop);
+            Ok(PreviewUrls::new(free_local_addr()?))
+        };
+
+        let urls = server.get_or_start(&mut start).unwrap();
+        assert!(urls.url.starts_with("http://127.0.0.1:"));
+        assert!(urls.data_plane_url.starts_with("ws://127.0.0.1:"));
+        let port = urls.url.rsplit(':').next().unwrap();
+        assert!(port.parse::<u16>().unwrap() > 0);
+
+        assert_eq!(server.get_or_start(&mut start).unwrap(), urls);
+        assert_eq!(started, 1);
+
+        // A server is started again after the running one is shut down.
+        server.shutdown();
+        server.get_or_start(&mut start).unwrap();
+        assert_eq!(started, 2);
+        drop(start);
+        assert_eq!(stops[0].try_recv(), Ok(()));
+        assert!(stops[1].try_recv().is_err());
+    }
+}
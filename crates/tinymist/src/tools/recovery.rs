@@ -0,0 +1,352 @@
+//! Persist the unsaved buffers of the editor, i.e. the memory overlay, so that
+//! they can be restored after the editor or the server crashes.
+//!
+//! The buffers are written to a recovery directory of the workspace from time
+//! to time, and the directory is removed on a clean shutdown. Finding buffers
+//! in it on startup therefore means that the last session ended unexpectedly.
+//!
+//! The edits made within an interval after a save are saved by a trailing save
+//! at the end of the interval, or when the store is dropped. The buffers are
+//! written by a thread of the store, so that the editing never waits for the
+//! disk.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use typst::syntax::Source;
+use typst_ts_core::ImmutPath;
+
+/// The minimum interval between two saves of the buffers on edits.
+pub const RECOVERY_INTERVAL: Duration = Duration::from_secs(5);
+
+const OVERLAY_FILE: &str = "overlay.json";
+
+/// An unsaved buffer found in the recovery directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveredFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// An unsaved buffer of the editor, which is cheap to clone.
+pub type Buffer = (ImmutPath, Source);
+
+/// The recovery directory of a workspace.
+#[derive(Debug)]
+pub struct RecoveryStore {
+    dir: PathBuf,
+    /// The minimum interval between two saves on edits.
+    interval: Duration,
+    saves: Arc<Mutex<SaveState>>,
+    /// The jobs of the writer thread.
+    jobs: mpsc::Sender<Job>,
+}
+
+/// The state of the saves, shared with the trailing save.
+#[derive(Debug, Default)]
+struct SaveState {
+    last_saved: Option<Instant>,
+    /// The buffers edited after the last save, waiting for the trailing save.
+    pending: Option<Vec<Buffer>>,
+    /// Whether the trailing save is scheduled.
+    scheduled: bool,
+}
+
+/// A job of the writer thread.
+enum Job {
+    /// Write the buffers, replacing the ones written before.
+    Save(Vec<Buffer>),
+    /// Remove the recovery directory.
+    Clear,
+    /// Notify that the jobs sent before are done.
+    Flush(mpsc::Sender<()>),
+}
+
+impl RecoveryStore {
+    /// Create a store in the directory, which is created on the first save.
+    pub fn new(dir: PathBuf) -> Self {
+        let (jobs, rx) = mpsc::channel();
+        let writer_dir = dir.clone();
+        std::thread::spawn(move || run_writer(&writer_dir, rx));
+
+        Self {
+            dir,
+            interval: RECOVERY_INTERVAL,
+            saves: Arc::default(),
+            jobs,
+        }
+    }
+
+    /// Change the minimum interval between two saves on edits.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Create a store in the data directory of the user, namespaced by the
+    /// roots of the workspace.
+    pub fn in_user_data(roots: &[PathBuf]) -> Option<Self> {
+        let workspace = format!("{:032x}", typst::util::hash128(&roots));
+        let dir = dirs::data_local_dir()?.join("tinymist").join("recovery");
+        Some(Self::new(dir.join(workspace)))
+    }
+
+    /// Save the buffers whose content differs from their file on disk, if the
+    /// interval since the last save has passed, and return whether they are
+    /// saved. Otherwise, they are saved by a trailing save at the end of the
+    /// interval.
+    pub fn save_if_due(&self, buffers: Vec<Buffer>, now: Instant) -> bool {
+        let mut saves = self.saves.lock();
+        let due = saves.last_saved.map_or(now, |last| last + self.interval);
+        if now < due {
+            saves.pending = Some(buffers);
+            if !std::mem::replace(&mut saves.scheduled, true) {
+                self.schedule_trailing_save(due - now);
+            }
+            return false;
+        }
+        saves.pending = None;
+        saves.last_saved = Some(now);
+        let _ = self.jobs.send(Job::Save(buffers));
+        true
+    }
+
+    /// Save the buffers whose content differs from their file on disk,
+    /// replacing the ones saved before.
+    pub fn save(&self, buffers: Vec<Buffer>) {
+        let mut saves = self.saves.lock();
+        saves.pending = None;
+        saves.last_saved = Some(Instant::now());
+        let _ = self.jobs.send(Job::Save(buffers));
+    }
+
+    /// Save the pending buffers after the delay, unless they are saved or
+    /// cleared before.
+    fn schedule_trailing_save(&self, delay: Duration) {
+        let saves = self.saves.clone();
+        let jobs = self.jobs.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            let mut saves = saves.lock();
+            saves.scheduled = false;
+            let Some(buffers) = saves.pending.take() else {
+                return;
+            };
+            saves.last_saved = Some(Instant::now());
+            // Send it with the lock held, so that it is never sent after a
+            // clear.
+            let _ = jobs.send(Job::Save(buffers));
+        });
+    }
+
+    /// Wait for the buffers and the clears sent before to be written to disk.
+    pub fn flush(&self) {
+        let (done, rx) = mpsc::channel();
+        if self.jobs.send(Job::Flush(done)).is_ok() {
+            let _ = rx.recv();
+        }
+    }
+
+    /// Load the buffers left by the last session, except the ones saved to
+    /// disk since then.
+    pub fn load(&self) -> Vec<RecoveredFile> {
+        let Ok(overlay) = fs::read(self.dir.join(OVERLAY_FILE)) else {
+            return vec![];
+        };
+        let files: Vec<RecoveredFile> = serde_json::from_slice(&overlay).unwrap_or_else(|err| {
+            log::warn!("RecoveryStore: discard corrupted recovery file: {err}");
+            vec![]
+        });
+        (files.into_iter())
+            .filter(|file| !is_saved(&file.path, &file.content))
+            .collect()
+    }
+
+    /// Remove the recovery directory, e.g. on a clean shutdown, after the
+    /// buffers saved before.
+    pub fn clear(&self) {
+        let mut saves = self.saves.lock();
+        saves.pending = None;
+        let _ = self.jobs.send(Job::Clear);
+    }
+}
+
+impl Drop for RecoveryStore {
+    /// Save the pending buffers and wait for them, e.g. when the server exits
+    /// without a clean shutdown.
+    fn drop(&mut self) {
+        let Some(buffers) = self.saves.lock().pending.take() else {
+            return;
+        };
+        let _ = self.jobs.send(Job::Save(buffers));
+        self.flush();
+    }
+}
+
+/// Run the jobs in order until the store and its trailing saves are dropped.
+fn run_writer(dir: &Path, jobs: mpsc::Receiver<Job>) {
+    for job in jobs {
+        match job {
+            Job::Save(buffers) => {
+                if let Err(err) = write_overlay(dir, &buffers) {
+                    log::warn!("RecoveryStore: failed to save buffers: {err:#}");
+                }
+            }
+            Job::Clear => match fs::remove_dir_all(dir) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    log::warn!("RecoveryStore: failed to clear {}: {err}", dir.display());
+                }
+                _ => {}
+            },
+            Job::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// Write the buffers whose content differs from their file on disk to the
+/// directory, replacing the ones written before.
+fn write_overlay(dir: &Path, buffers: &[Buffer]) -> anyhow::Result<()> {
+    let files: Vec<_> = (buffers.iter())
+        .filter(|(path, source)| !is_saved(path, source.text()))
+        .map(|(path, source)| RecoveredFile {
+            path: path.to_path_buf(),
+            content: source.text().to_owned(),
+        })
+        .collect();
+
+    let overlay = dir.join(OVERLAY_FILE);
+    if files.is_empty() {
+        return match fs::remove_file(&overlay) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).context("failed to remove recovery file")
+            }
+            _ => Ok(()),
+        };
+    }
+
+    // Write to a temporary file first, so that a crash while writing keeps the
+    // buffers saved before.
+    fs::create_dir_all(dir).context("failed to create recovery directory")?;
+    let tmp = dir.join(format!("{OVERLAY_FILE}.tmp"));
+    fs::write(&tmp, serde_json::to_vec(&files)?).context("failed to write recovery file")?;
+    fs::rename(tmp, overlay).context("failed to write recovery file")
+}
+
+/// Whether the file on disk has the content.
+fn is_saved(path: &Path, content: &str) -> bool {
+    fs::read_to_string(path).is_ok_and(|saved| saved == content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(path: &Path, content: &str) -> Buffer {
+        (path.into(), Source::detached(content))
+    }
+
+    fn edit(store: &RecoveryStore, path: &Path, content: &str) -> bool {
+        let buffers = vec![buffer(path, content)];
+        store.save_if_due(buffers, Instant::now())
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tinymist-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_restore_after_crash() {
+        let workspace = temp_dir("recovery-ws");
+        let saved = workspace.join("saved.typ");
+        let edited = workspace.join("edited.typ");
+        fs::write(&saved, "= Saved").unwrap();
+        fs::write(&edited, "= Draft").unwrap();
+        let dir = workspace.join("recovery");
+
+        let store = RecoveryStore::new(dir.clone());
+        let now = Instant::now();
+        let buffers = vec![buffer(&saved, "= Saved"), buffer(&edited, "= Edited")];
+        assert!(store.save_if_due(buffers, now));
+        // The server crashes without clearing the store, after the buffers
+        // are written in the background.
+        store.flush();
+        drop(store);
+
+        let store = RecoveryStore::new(dir.clone());
+        let restored = store.load();
+        assert_eq!(
+            restored,
+            [RecoveredFile {
+                path: edited.clone(),
+                content: "= Edited".to_owned(),
+            }]
+        );
+
+        // The buffers are not offered again once saved.
+        fs::write(&edited, "= Edited").unwrap();
+        assert!(store.load().is_empty());
+
+        store.clear();
+        store.flush();
+        assert!(!dir.exists());
+        fs::remove_dir_all(workspace).unwrap();
+    }
+
+    #[test]
+    fn test_trailing_save() {
+        let workspace = temp_dir("recovery-trailing");
+        let path = workspace.join("main.typ");
+        fs::write(&path, "= Draft").unwrap();
+        let dir = workspace.join("recovery");
+        let load = || RecoveryStore::new(dir.clone()).load();
+
+        let store = RecoveryStore::new(dir.clone()).with_interval(Duration::from_millis(50));
+        assert!(edit(&store, &path, "= A"));
+        // The last edit within the interval is saved at the end of it.
+        assert!(!edit(&store, &path, "= AB"));
+        assert!(!edit(&store, &path, "= ABC"));
+        store.flush();
+        assert_eq!(load()[0].content, "= A");
+        std::thread::sleep(Duration::from_millis(300));
+        store.flush();
+        assert_eq!(load()[0].content, "= ABC");
+
+        // The pending edits are saved when the server is dropped as well, e.g.
+        // on exiting without a shutdown, and restored by the next one.
+        assert!(edit(&store, &path, "= ABCD"));
+        assert!(!edit(&store, &path, "= ABCDE"));
+        drop(store);
+        assert_eq!(load()[0].content, "= ABCDE");
+
+        // A clean shutdown cancels the trailing save.
+        let store = RecoveryStore::new(dir.clone()).with_interval(Duration::from_millis(50));
+        store.save(vec![buffer(&path, "= E")]);
+        assert!(!edit(&store, &path, "= EF"));
+        store.clear();
+        std::thread::sleep(Duration::from_millis(300));
+        store.flush();
+        drop(store);
+        assert!(!dir.exists());
+
+        fs::remove_dir_all(workspace).unwrap();
+    }
+
+    #[test]
+    fn test_namespaced_by_workspace() {
+        let a = RecoveryStore::in_user_data(&[PathBuf::from("/a")]);
+        let b = RecoveryStore::in_user_data(&[PathBuf::from("/b")]);
+        if let (Some(a), Some(b)) = (a, b) {
+            assert_ne!(a.dir, b.dir);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Check whether a document compiles to the same bytes every time, so that it
+//! can be built reproducibly, e.g. in CI.
+//!
+//! The document is compiled twice with the same files and inputs. The second
+//! compilation sees the current date a year later, as a later build would, so
+//! that documents depending on the date are caught as well.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+use tinymist_query::{OverlayWorld, WorldHooks};
+use typst::eval::Tracer;
+use typst::foundations::{Datetime, Smart};
+use typst::model::Document;
+use typst::syntax::Source;
+use typst::visualize::Color;
+use typst::World;
+
+/// The hint given if the document reads the current date.
+const DATE_HINT: &str = "the document reads the current date, which changes between builds; \
+    pin it with an input instead, e.g. `sys.inputs.at(\"date\")`";
+
+/// The format of the exports compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReproducibleFormat {
+    Pdf,
+    Png,
+}
+
+impl ReproducibleFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pdf" => Some(Self::Pdf),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+}
+
+/// The result of comparing two compilations of a document.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReproducibleReport {
+    /// Whether the exports are identical.
+    pub stable: bool,
+    pub pages: usize,
+    /// The one-based numbers of the pages differing between the compilations.
+    pub differing_pages: Vec<usize>,
+    /// Whether the document reads the current date.
+    pub reads_date: bool,
+    pub hint: Option<String>,
+}
+
+/// Compile the source as the entry twice and compare the exports.
+pub fn verify_reproducible(
+    world: &dyn World,
+    main: Source,
+    format: ReproducibleFormat,
+) -> anyhow::Result<ReproducibleReport> {
+    let date_world = |later| {
+        let hooks = DateHooks::new(later);
+        OverlayWorld::new(world)
+            .with_main(main.clone())
+            .with_hooks(hooks)
+    };
+    let first = date_world(false);
+    let first_doc = compile(&first)?;
+    let second_doc = compile(&date_world(true))?;
+    let reads_date = first.hooks.reads_date.load(Ordering::Relaxed);
+
+    let first_pages = export_pages(&first_doc, format)?;
+    let second_pages = export_pages(&second_doc, format)?;
+    let pages = first_pages.len().max(second_pages.len());
+    let differing_pages: Vec<_> = (0..pages)
+        .filter(|&idx| first_pages.get(idx) != second_pages.get(idx))
+        .map(|idx| idx + 1)
+        .collect();
+
+    let stable = match format {
+        ReproducibleFormat::Pdf => {
+            typst_pdf::pdf(&first_doc, Smart::Auto, None)
+                == typst_pdf::pdf(&second_doc, Smart::Auto, None)
+        }
+        ReproducibleFormat::Png => differing_pages.is_empty(),
+    };
+
+    Ok(ReproducibleReport {
+        stable,
+        pages,
+        differing_pages,
+        reads_date,
+        hint: reads_date.then(|| DATE_HINT.to_owned()),
+    })
+}
+
+fn compile(world: &dyn World) -> anyhow::Result<Document> {
+    match typst::compile(world, &mut Tracer::new()) {
+        Ok(doc) => Ok(doc),
+        Err(errors) => {
+            let message = errors.first().map(|e| e.message.as_str()).unwrap_or("");
+            bail!("the document cannot be compiled: {message}");
+        }
+    }
+}
+
+/// Export the pages one by one to compare them, as PNG images or, for PDF,
+/// as SVG images, which are cheaper to make.
+fn export_pages(doc: &Document, format: ReproducibleFormat) -> anyhow::Result<Vec<Vec<u8>>> {
+    doc.pages
+        .iter()
+        .map(|page| match format {
+            ReproducibleFormat::Pdf => Ok(typst_svg::svg(&page.frame).into_bytes()),
+            ReproducibleFormat::Png => typst_render::render(&page.frame, 1., Color::WHITE)
+                .encode_png()
+                .context("failed to encode PNG"),
+        })
+        .collect()
+}
+
+/// The hooks recording whether the current date is read, and optionally moving
+/// it a year later.
+struct DateHooks {
+    later: bool,
+    reads_date: AtomicBool,
+}
+
+impl DateHooks {
+    fn new(later: bool) -> Self {
+        Self {
+            later,
+            reads_date: AtomicBool::new(false),
+        }
+    }
+}
+
+impl WorldHooks for DateHooks {
+    fn today(&self, today: Option<Datetime>) -> Option<Datetime> {
+        self.reads_date.store(true, Ordering::Relaxed);
+        let today = today?;
+        if !self.later {
+            return Some(today);
+        }
+        // The day is capped to stay valid in any month.
+        Datetime::from_ymd(today.year()? + 1, today.month()?, today.day()?.min(28))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    #[test]
+    fn test_fixed_date() {
+        let world = TestWorld::new("#datetime(year: 2024, month: 5, day: 6).display()");
+        let report = verify_reproducible(&world, world.main(), ReproducibleFormat::Pdf).unwrap();
+        assert!(report.stable);
+        assert!(report.differing_pages.is_empty());
+        assert!(!report.reads_date && report.hint.is_none());
+    }
+
+    #[test]
+    fn test_current_date() {
+        let world = TestWorld::new("= Report\n#pagebreak()\n#datetime.today().display()")
+            .with_today(Datetime::from_ymd(2024, 5, 6).unwrap());
+        for format in [ReproducibleFormat::Pdf, ReproducibleFormat::Png] {
+            let report = verify_reproducible(&world, world.main(), format).unwrap();
+            assert!(!report.stable);
+            assert_eq!(report.pages, 2);
+            assert_eq!(report.differing_pages, [2]);
+            assert!(report.reads_date && report.hint.is_some());
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Export a selected range of a source file.
+//!
+//! The selected fragment is wrapped in a minimal scaffold, which inherits the
+//! imports, set rules and show rules of the source file. The scaffold is then
+//! compiled in a world overlaying the original one, so that relative imports
+//! keep working.
+
+use std::ops::Range;
+
+use anyhow::{bail, Context};
+use tinymist_query::OverlayWorld;
+use typst::eval::Tracer;
+use typst::syntax::{ast, FileId, LinkedNode, Source, SyntaxKind, VirtualPath};
+use typst::visualize::Color;
+use typst::World;
+
+/// The format of an exported selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionFormat {
+    Png,
+    Svg,
+}
+
+impl SelectionFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "png" => Some(Self::Png),
+            "svg" => Some(Self::Svg),
+            _ => None,
+        }
+    }
+}
+
+/// Create a scaffold document rendering only the selected range of the
+/// source.
+///
+/// Imports, set rules and show rules at the top level before the selection are
+/// kept. Show-everything rules (`#show: template`) are skipped because they
+/// usually decorate the whole document, e.g. adding a title page.
+pub fn selection_scaffold(source: &Source, range: Range<usize>) -> Option<String> {
+    let selected = source.text().get(range.clone())?;
+    if selected.trim().is_empty() || typst::syntax::parse(selected).erroneous() {
+        return None;
+    }
+
+    let mut scaffold = String::new();
+    let root = LinkedNode::new(source.root());
+    for child in root.children() {
+        if child.range().end > range.start {
+            break;
+        }
+
+        let keep = match child.kind() {
+            SyntaxKind::ModuleImport | SyntaxKind::SetRule => true,
+            SyntaxKind::ShowRule => child
+                .cast::<ast::ShowRule>()
+                .is_some_and(|rule| rule.selector().is_some()),
+            _ => false,
+        };
+        if keep {
+            scaffold.push('#');
+            scaffold.push_str(&child.get().clone().into_text());
+            scaffold.push('\n');
+        }
+    }
+
+    scaffold.push_str("#set page(width: auto, height: auto, margin: 0.5em)\n");
+    scaffold.push_str(selected);
+    Some(scaffold)
+}
+
+/// Compile the selected range of the source and export it to the given
+/// format.
+pub fn export_selection(
+    world: &dyn World,
+    source: &Source,
+    range: Range<usize>,
+    format: SelectionFormat,
+) -> anyhow::Result<Vec<u8>> {
+    let Some(scaffold) = selection_scaffold(source, range) else {
+        bail!("the selection is not a valid fragment");
+    };
+
+    // Place the scaffold next to the source to resolve relative paths.
+    let id = source.id();
+    let vpath = id
+        .vpath()
+        .as_rootless_path()
+        .with_file_name("__selection__.typ");
+    let id = FileId::new(id.package().cloned(), VirtualPath::new(vpath));
+    let world = OverlayWorld::new(world).with_main(Source::new(id, scaffold));
+
+    let doc = match typst::compile(&world, &mut Tracer::new()) {
+        Ok(doc) => doc,
+        Err(errors) => {
+            let message = errors.first().map(|e| e.message.as_str()).unwrap_or("");
+            bail!("the selection cannot be compiled: {message}");
+        }
+    };
+    let Some(page) = doc.pages.first() else {
+        bail!("the selection produces no page");
+    };
+
+    Ok(match format {
+        SelectionFormat::Png => typst_render::render(&page.frame, 3., Color::WHITE)
+            .encode_png()
+            .context("failed to encode PNG")?,
+        SelectionFormat::Svg => typst_svg::svg(&page.frame).into_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    const DOC: &str =
+        "#set text(size: 14pt)\n#show: doc => doc\n= Title\nWe have $a^2 + b^2 = c^2$ here.";
+
+    fn equation_range() -> Range<usize> {
+        DOC.find('$').unwrap()..DOC.rfind('$').unwrap() + 1
+    }
+
+    #[test]
+    fn test_scaffold() {
+        let world = TestWorld::new(DOC);
+        let scaffold = selection_scaffold(&world.main, equation_range()).unwrap();
+        assert_eq!(
+            scaffold,
+            "#set text(size: 14pt)\n#set page(width: auto, height: auto, margin: 0.5em)\n$a^2 + b^2 = c^2$"
+        );
+    }
+
+    #[test]
+    fn test_export_equation() {
+        let world = TestWorld::new(DOC);
+        let png = export_selection(&world, &world.main, equation_range(), SelectionFormat::Png);
+        assert!(png.unwrap().starts_with(b"\x89PNG"));
+
+        let svg = export_selection(&world, &world.main, equation_range(), SelectionFormat::Svg);
+        assert!(String::from_utf8(svg.unwrap()).unwrap().starts_with("<svg"));
+    }
+
+    #[test]
+    fn test_reject_invalid_fragment() {
+        let world = TestWorld::new(DOC);
+        let start = DOC.find('$').unwrap();
+        let res = export_selection(&world, &world.main, start..start + 3, SelectionFormat::Png);
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,313 @@
+//! Export variants of a document compiled with different inputs, e.g. posters
+//! with the titles from a data file.
+//!
+//! The variants are compiled in a fork of the world of the compiler with their
+//! inputs visible through `sys.inputs`, so that the files and fonts are loaded
+//! once and the layouts not depending on the inputs are reused across variants.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+use tinymist_query::OverlayWorld;
+use typst::eval::Tracer;
+use typst::foundations::{Dict, Smart, Value};
+use typst::model::Document;
+use typst::syntax::Source;
+use typst::visualize::Color;
+use typst::World;
+
+use super::animated_svg::inputs_library;
+
+/// The maximum number of variants, as the document is compiled for each one.
+const MAX_VARIANTS: usize = 100;
+
+/// The format of the exported variants. Images are rendered from the first
+/// page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeriesFormat {
+    Pdf,
+    Png,
+    Svg,
+}
+
+impl SeriesFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pdf" => Some(Self::Pdf),
+            "png" => Some(Self::Png),
+            "svg" => Some(Self::Svg),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Png => "png",
+            Self::Svg => "svg",
+        }
+    }
+}
+
+/// The export of a variant, which is either written to `path` or failed with
+/// `error`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SeriesExport {
+    pub inputs: BTreeMap<String, String>,
+    pub path: Option<PathBuf>,
+    pub error: Option<String>,
+}
+
+/// Check the number of variants.
+pub fn validate_variants(count: usize) -> anyhow::Result<()> {
+    if !(1..=MAX_VARIANTS).contains(&count) {
+        bail!("inputsList must have between 1 and {MAX_VARIANTS} items, got {count}");
+    }
+    Ok(())
+}
+
+/// Compile the source as the entry once for each set of inputs, and export
+/// each variant to the path made by [`output_path`]. A variant failing to
+/// compile or export is reported and doesn't stop the others.
+///
+/// The number of variants exported is reported after each one, and the export
+/// stops before the next variant once `cancelled` returns true.
+pub fn export_series(
+    world: &dyn World,
+    main: Source,
+    inputs_list: Vec<BTreeMap<String, String>>,
+    format: SeriesFormat,
+    pattern: &Path,
+    cancelled: impl Fn() -> bool,
+    mut report: impl FnMut(usize, usize),
+) -> anyhow::Result<Vec<SeriesExport>> {
+    validate_variants(inputs_list.len())?;
+
+    let world = OverlayWorld::new(world).with_main(main);
+    let total = inputs_list.len();
+    let mut used = HashSet::new();
+    let mut exports = vec![];
+    for (idx, inputs) in inputs_list.into_iter().enumerate() {
+        if cancelled() {
+            break;
+        }
+        let mut to = output_path(pattern, idx, &inputs, format);
+        // Keep variants apart even if the pattern ignores their inputs.
+        if !used.insert(to.clone()) {
+            let stem = to.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = format.extension();
+            to = to.with_file_name(format!("{stem}-{}.{ext}", idx + 1));
+            used.insert(to.clone());
+        }
+
+        let (path, error) = match export_variant(&world, &inputs, format, &to) {
+            Ok(()) => (Some(to), None),
+            Err(err) => {
+                log::warn!("exportSeries: variant {} failed: {err:#}", idx + 1);
+                (None, Some(format!("{err:#}")))
+            }
+        };
+        exports.push(SeriesExport {
+            inputs,
+            path,
+            error,
+        });
+        report(idx + 1, total);
+    }
+    Ok(exports)
+}
+
+/// Get the path of a variant by replacing the `{key}` placeholders in the
+/// pattern with the values of its inputs and `{index}` with its one-based
+/// index. The values are reduced to characters safe in file names.
+pub fn output_path(
+    pattern: &Path,
+    idx: usize,
+    inputs: &BTreeMap<String, String>,
+    format: SeriesFormat,
+) -> PathBuf {
+    let Some(name) = pattern.file_name() else {
+        return pattern.join(format!("{}.{}", idx + 1, format.extension()));
+    };
+
+    let mut name = name
+        .to_string_lossy()
+        .replace("{index}", &(idx + 1).to_string());
+    for (key, value) in inputs {
+        name = name.replace(&format!("{{{key}}}"), &sanitize(value));
+    }
+    pattern.with_file_name(format!("{name}.{}", format.extension()))
+}
+
+/// Replace the characters other than letters, digits, `-` and `_` by `-`.
+fn sanitize(value: &str) -> String {
+    let sanitized: String = value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' || c == '_' => c,
+            _ => '-',
+        })
+        .collect();
+    if sanitized.is_empty() {
+        "_".to_owned()
+    } else {
+        sanitized
+    }
+}
+
+fn export_variant(
+    world: &dyn World,
+    inputs: &BTreeMap<String, String>,
+    format: SeriesFormat,
+    to: &Path,
+) -> anyhow::Result<()> {
+    let overrides = inputs
+        .iter()
+        .map(|(key, value)| (key.as_str().into(), Value::Str(value.as_str().into())));
+    let library = inputs_library(world, Dict::from_iter(overrides));
+    let world = OverlayWorld::new(world).with_library(library);
+    let doc = match typst::compile(&world, &mut Tracer::new()) {
+        Ok(doc) => doc,
+        Err(errors) => {
+            let message = errors.first().map(|e| e.message.as_str()).unwrap_or("");
+            bail!("the variant cannot be compiled: {message}");
+        }
+    };
+
+    let data = render(&doc, format)?;
+    if let Some(dir) = to.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(to, data).with_context(|| format!("failed to write {to:?}"))
+}
+
+fn render(doc: &Document, format: SeriesFormat) -> anyhow::Result<Vec<u8>> {
+    if format == SeriesFormat::Pdf {
+        return Ok(typst_pdf::pdf(doc, Smart::Auto, None));
+    }
+    let Some(page) = doc.pages.first() else {
+        bail!("the variant produces no page");
+    };
+    Ok(match format {
+        SeriesFormat::Png => typst_render::render(&page.frame, 3., Color::WHITE)
+            .encode_png()
+            .context("failed to encode PNG")?,
+        _ => typst_svg::svg(&page.frame).into_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    fn series_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tinymist-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn inputs(title: &str) -> BTreeMap<String, String> {
+        BTreeMap::from_iter([("title".to_owned(), title.to_owned())])
+    }
+
+    #[test]
+    fn test_export_series() {
+        let world = TestWorld::new(
+            "#set page(width: 80pt, height: 40pt)\n\
+             #sys.inputs.at(\"title\", default: \"Untitled\")",
+        );
+        let dir = series_dir("series");
+        let pattern = dir.join("poster-{title}");
+        let list = vec![inputs("Spring Fair"), inputs("Winter/Ball")];
+        let mut reported = vec![];
+        let report = |done, total| reported.push((done, total));
+        let exports = export_series(
+            &world,
+            world.main(),
+            list,
+            SeriesFormat::Png,
+            &pattern,
+            || false,
+            report,
+        )
+        .unwrap();
+
+        let paths: Vec<_> = exports.iter().map(|e| e.path.clone().unwrap()).collect();
+        assert_eq!(
+            paths,
+            [
+                dir.join("poster-Spring-Fair.png"),
+                dir.join("poster-Winter-Ball.png"),
+            ]
+        );
+        let pngs: Vec<_> = paths.iter().map(|p| std::fs::read(p).unwrap()).collect();
+        assert!(pngs[0].starts_with(b"\x89PNG"));
+        assert_ne!(pngs[0], pngs[1]);
+        assert_eq!(reported, [(1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn test_cancel_series() {
+        let world = TestWorld::new("#sys.inputs.title");
+        let dir = series_dir("series-cancelled");
+        let pattern = dir.join("{title}");
+        let list = vec![inputs("a"), inputs("b"), inputs("c")];
+        let done = std::cell::Cell::new(0);
+        let cancelled = || done.get() == 2;
+        let report = |n, _| done.set(n);
+        let exports = export_series(
+            &world,
+            world.main(),
+            list,
+            SeriesFormat::Svg,
+            &pattern,
+            cancelled,
+            report,
+        )
+        .unwrap();
+
+        // The variants exported before the cancellation are kept.
+        let paths: Vec<_> = exports.iter().map(|e| e.path.clone().unwrap()).collect();
+        assert_eq!(paths, [dir.join("a.svg"), dir.join("b.svg")]);
+        assert!(!dir.join("c.svg").exists());
+    }
+
+    #[test]
+    fn test_failed_variant() {
+        let world = TestWorld::new("#let n = int(sys.inputs.n)\n#n");
+        let dir = series_dir("series-failed");
+        let pattern = dir.join("out");
+        let list = vec![
+            BTreeMap::from_iter([("n".to_owned(), "x".to_owned())]),
+            BTreeMap::from_iter([("n".to_owned(), "2".to_owned())]),
+        ];
+        let exports = export_series(
+            &world,
+            world.main(),
+            list,
+            SeriesFormat::Pdf,
+            &pattern,
+            || false,
+            |_, _| {},
+        )
+        .unwrap();
+
+        assert!(exports[0].path.is_none() && exports[0].error.is_some());
+        // The name of the failed variant is still taken.
+        assert_eq!(exports[1].path, Some(dir.join("out-2.pdf")));
+        let empty = export_series(
+            &world,
+            world.main(),
+            vec![],
+            SeriesFormat::Pdf,
+            &pattern,
+            || false,
+            |_, _| {},
+        );
+        assert!(empty.is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Split a document into several PDFs, each of some of its pages.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use typst::foundations::Smart;
+use typst::model::Document;
+
+use crate::actor::export::PageFilter;
+
+/// How to split the pages of a document.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum PdfSplit {
+    /// `"each"` to export each page as a PDF.
+    Each(String),
+    /// Page ranges, e.g. `["1-2", "3-"]`, exported as PDFs numbered from one.
+    Ranges(Vec<String>),
+    /// Page ranges keyed by the names of the PDFs.
+    Named(BTreeMap<String, String>),
+}
+
+impl PdfSplit {
+    /// Get the parts of a document with the number of pages, as the suffixes
+    /// of the PDFs and the zero-based indices of their pages.
+    pub fn parts(&self, page_count: usize) -> anyhow::Result<Vec<(String, Vec<usize>)>> {
+        let select = |spec: &str| spec.parse::<PageFilter>()?.select(page_count);
+        match self {
+            Self::Each(each) if each == "each" => Ok((0..page_count)
+                .map(|i| ((i + 1).to_string(), vec![i]))
+                .collect()),
+            Self::Each(split) => bail!("unknown split {split:?}, expected \"each\" or ranges"),
+            Self::Ranges(ranges) => (ranges.iter().enumerate())
+                .map(|(i, spec)| Ok(((i + 1).to_string(), select(spec)?)))
+                .collect(),
+            Self::Named(ranges) => (ranges.iter())
+                .map(|(name, spec)| {
+                    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+                        bail!("invalid name of split PDF: {name:?}");
+                    }
+                    Ok((name.clone(), select(spec)?))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The PDFs split from a document.
+#[derive(Debug, Default)]
+pub struct SplitPdfs {
+    /// The suffixes and the contents of the PDFs.
+    pub pdfs: Vec<(String, Vec<u8>)>,
+    /// Whether the split is cancelled before all the PDFs are exported.
+    pub cancelled: bool,
+}
+
+/// Split the document into PDFs. The split stops before the next PDF once it is
+/// cancelled, keeping the PDFs exported so far.
+pub fn split_pdf(
+    doc: &Document,
+    split: &PdfSplit,
+    cancelled: impl Fn() -> bool,
+) -> anyhow::Result<SplitPdfs> {
+    let parts = split.parts(doc.pages.len())?;
+    if parts.is_empty() {
+        bail!("there are no pages to split");
+    }
+
+    let mut pdfs = Vec::with_capacity(parts.len());
+    for (suffix, pages) in parts {
+        if cancelled() {
+            return Ok(SplitPdfs {
+                pdfs,
+                cancelled: true,
+            });
+        }
+        let mut part = doc.clone();
+        part.pages = (pages.into_iter())
+            .map(|i| doc.pages.get(i).cloned())
+            .collect::<Option<_>>()
+            .context("page is out of the document")?;
+        // The outline and the links locate the elements by the pages of the
+        // part.
+        part.introspector.rebuild(&part.pages);
+        pdfs.push((suffix, typst_pdf::pdf(&part, Smart::Auto, None)));
+    }
+    Ok(SplitPdfs {
+        pdfs,
+        cancelled: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    fn document() -> Document {
+        let world = TestWorld::new("One #pagebreak() Two #pagebreak() Three");
+        typst::compile(&world, &mut Tracer::new()).unwrap()
+    }
+
+    fn page_count(pdf: &[u8]) -> usize {
+        assert!(pdf.starts_with(b"%PDF-"));
+        let text = String::from_utf8_lossy(pdf);
+        let count = text.split("/Count ").nth(1).unwrap();
+        let digits = count.chars().take_while(char::is_ascii_digit);
+        digits.collect::<String>().parse().unwrap()
+    }
+
+    #[test]
+    fn test_split_each() {
+        let doc = document();
+        assert_eq!(doc.pages.len(), 3);
+
+        let split = split_pdf(&doc, &PdfSplit::Each("each".to_owned()), || false).unwrap();
+        assert!(!split.cancelled);
+        let pdfs = split.pdfs;
+        let suffixes: Vec<_> = pdfs.iter().map(|(suffix, _)| suffix.as_str()).collect();
+        assert_eq!(suffixes, ["1", "2", "3"]);
+        for (_, pdf) in &pdfs {
+            assert_eq!(page_count(pdf), 1);
+        }
+    }
+
+    #[test]
+    fn test_split_ranges() {
+        let doc = document();
+        let split: PdfSplit = serde_json::from_str(r#"{"intro": "1", "rest": "2-"}"#).unwrap();
+        let pdfs = split_pdf(&doc, &split, || false).unwrap().pdfs;
+        let parts: Vec<_> = (pdfs.iter())
+            .map(|(suffix, pdf)| (suffix.as_str(), page_count(pdf)))
+            .collect();
+        assert_eq!(parts, [("intro", 1), ("rest", 2)]);
+
+        let split: PdfSplit = serde_json::from_str(r#"["1-2", "3"]"#).unwrap();
+        let parts = split.parts(3).unwrap();
+        assert_eq!(
+            parts,
+            [("1".to_owned(), vec![0, 1]), ("2".to_owned(), vec![2])]
+        );
+
+        let split: PdfSplit = serde_json::from_str(r#"{"../up": "1"}"#).unwrap();
+        assert!(split_pdf(&doc, &split, || false).is_err());
+    }
+
+    #[test]
+    fn test_split_outline() {
+        let world = TestWorld::new("= One\n#pagebreak()\n= Two\n#pagebreak()\n= Three");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        let split = PdfSplit::Ranges(vec!["1".to_owned(), "2-".to_owned()]);
+        let pdfs = split_pdf(&doc, &split, || false).unwrap().pdfs;
+
+        // Each outline only has the headings in the pages of its part.
+        let titles = |pdf: &[u8]| {
+            let text = String::from_utf8_lossy(pdf);
+            let titles = ["(One)", "(Two)", "(Three)"].into_iter();
+            titles
+                .filter(|title| text.contains(title))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(&pdfs[0].1), ["(One)"]);
+        assert_eq!(titles(&pdfs[1].1), ["(Two)", "(Three)"]);
+    }
+
+    #[test]
+    fn test_cancel_split() {
+        let doc = document();
+        let split = PdfSplit::Each("each".to_owned());
+
+        // Cancel once the first PDF is exported.
+        let checks = Cell::new(0);
+        let cancelled = || {
+            checks.set(checks.get() + 1);
+            checks.get() > 1
+        };
+        let split = split_pdf(&doc, &split, cancelled).unwrap();
+        assert!(split.cancelled);
+        let suffixes: Vec<_> = (split.pdfs.iter())
+            .map(|(suffix, _)| suffix.as_str())
+            .collect();
+        assert_eq!(suffixes, ["1"]);
+        assert_eq!(page_count(&split.pdfs[0].1), 1);
+    }
+}
@@ -0,0 +1,329 @@
+//! Export SVG with the text written as `<text>` elements, which is selectable
+//! and searchable, rather than as the outlined glyphs of `typst-svg`.
+//!
+//! The other items are still rendered by `typst-svg`, in a single pass where
+//! each text item is replaced by a marker image, which is then replaced by the
+//! `<text>` element. So the text is painted in order with the other items and
+//! clipped by its groups, but it is not filled with gradients or patterns.
+//! The embedded fonts are subsets of the shaped glyphs and of the glyphs of
+//! the characters, since viewers shape the text again without the layout
+//! tables dropped by subsetting.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use base64::Engine;
+use tinymist_query::SvgTextMode;
+use typst::foundations::Bytes;
+use typst::layout::{Abs, Frame, FrameItem, GroupItem, Point, Size};
+use typst::model::Document;
+use typst::syntax::Span;
+use typst::text::{Font, FontStyle, TextItem};
+use typst::visualize::{Color, Image, Paint, VectorFormat};
+
+/// The image standing in for a text item while the other items are rendered.
+const MARKER: &[u8] = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1\" height=\"1\" \
+    id=\"tinymist-text-marker\"/>";
+
+/// Export a frame into an SVG file, with the text written by the mode.
+pub fn svg(frame: &Frame, mode: SvgTextMode) -> String {
+    if mode == SvgTextMode::Outline {
+        return typst_svg::svg(frame);
+    }
+    let marker = match Image::new(Bytes::from_static(MARKER), VectorFormat::Svg.into(), None) {
+        Ok(marker) => marker,
+        Err(err) => {
+            log::warn!("SvgTextExport: failed to create the text marker: {err}");
+            return typst_svg::svg(frame);
+        }
+    };
+
+    let mut texts = vec![];
+    let rendered = typst_svg::svg(&replace_text(frame, &marker, &mut texts));
+
+    let mut fonts: Vec<(Font, BTreeSet<u16>)> = vec![];
+    let mut indices = vec![];
+    for text in &texts {
+        let index = match fonts.iter().position(|(font, _)| *font == text.font) {
+            Some(index) => index,
+            None => {
+                fonts.push((text.font.clone(), BTreeSet::new()));
+                fonts.len() - 1
+            }
+        };
+        let (font, glyphs) = &mut fonts[index];
+        glyphs.extend(text.glyphs.iter().map(|glyph| glyph.id));
+        let ttf = font.ttf();
+        glyphs.extend(
+            text.text
+                .chars()
+                .filter_map(|c| Some(ttf.glyph_index(c)?.0)),
+        );
+        indices.push(index);
+    }
+
+    // The style is the first child of the root element.
+    let head = rendered.find('>').map_or(0, |i| i + 1);
+    let mut svg = String::with_capacity(rendered.len());
+    svg.push_str(&rendered[..head]);
+    if mode == SvgTextMode::Embed && !fonts.is_empty() {
+        write_font_faces(&mut svg, &fonts);
+    }
+
+    let data = base64::engine::general_purpose::STANDARD.encode(MARKER);
+    let marker = format!("<image xlink:href=\"data:image/svg+xml;base64,{data}\"");
+    let mut rest = &rendered[head..];
+    let mut texts = texts.iter().zip(indices);
+    while let Some(start) = rest.find(&marker) {
+        svg.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("/>")
+            .map_or(rest.len(), |i| start + i + 2);
+        if let Some((text, index)) = texts.next() {
+            write_text(&mut svg, text, mode, index);
+        }
+        rest = &rest[end..];
+    }
+    svg.push_str(rest);
+    svg
+}
+
+/// Export the pages of a document stacked into a single SVG file, like
+/// [`typst_svg::svg_merged`] without padding.
+pub fn svg_merged(doc: &Document, mode: SvgTextMode) -> String {
+    if mode == SvgTextMode::Outline {
+        return typst_svg::svg_merged(doc, Abs::zero());
+    }
+
+    let pages = doc.pages.iter().map(|page| &page.frame);
+    let width = pages.clone().map(Frame::width).max().unwrap_or_default();
+    let height: Abs = pages.clone().map(Frame::height).sum();
+    let mut merged = Frame::hard(Size::new(width, height));
+    let mut y = Abs::zero();
+    for frame in pages {
+        merged.push_frame(Point::with_y(y), frame.clone());
+        y += frame.height();
+    }
+    svg(&merged, mode)
+}
+
+/// Replace the text items of a frame by the marker, collecting them in paint
+/// order. The groups without text are kept as they are.
+fn replace_text<'a>(frame: &'a Frame, marker: &Image, texts: &mut Vec<&'a TextItem>) -> Frame {
+    let mut replaced = Frame::new(frame.size(), frame.kind());
+    for (pos, item) in frame.items() {
+        let item = match item {
+            FrameItem::Text(text) => {
+                texts.push(text);
+                FrameItem::Image(marker.clone(), Size::zero(), Span::detached())
+            }
+            FrameItem::Group(group) if has_text(&group.frame) => FrameItem::Group(GroupItem {
+                frame: replace_text(&group.frame, marker, texts),
+                ..group.clone()
+            }),
+            item => item.clone(),
+        };
+        replaced.push(*pos, item);
+    }
+    replaced
+}
+
+fn has_text(frame: &Frame) -> bool {
+    frame.items().any(|(_, item)| match item {
+        FrameItem::Text(_) => true,
+        FrameItem::Group(group) => has_text(&group.frame),
+        _ => false,
+    })
+}
+
+/// Write the fonts as the subsets of the glyphs. A font which cannot be subset
+/// is referenced by its family instead.
+fn write_font_faces(out: &mut String, fonts: &[(Font, BTreeSet<u16>)]) {
+    out.push_str("<style>");
+    for (index, (font, glyphs)) in fonts.iter().enumerate() {
+        let glyphs = glyphs.iter().copied().collect::<Vec<_>>();
+        let profile = subsetter::Profile::pdf(&glyphs);
+        // The face is picked from a collection by its index, and the subset is
+        // a single face.
+        let src = match subsetter::subset(font.data(), font.index(), profile) {
+            Ok(data) => {
+                let (format, mime) = match data.get(..4) {
+                    Some(b"OTTO") => ("opentype", "font/otf"),
+                    _ => ("truetype", "font/ttf"),
+                };
+                let data = base64::engine::general_purpose::STANDARD.encode(data);
+                format!("url(data:{mime};base64,{data}) format(\"{format}\")")
+            }
+            Err(err) => {
+                let family = &font.info().family;
+                log::warn!("SvgTextExport: failed to subset font {family}: {err}");
+                format!("local(\"{}\")", escape(&family.replace(['"', '\\'], "")))
+            }
+        };
+        let _ = write!(
+            out,
+            "@font-face {{ font-family: \"tinymist-font-{index}\"; src: {src}; }}"
+        );
+    }
+    out.push_str("</style>");
+}
+
+/// Write a text item as a `<text>` element at the origin of its marker,
+/// positioning each character at its glyph. The characters of a ligature share
+/// the advance of its glyph.
+fn write_text(out: &mut String, text: &TextItem, mode: SvgTextMode, index: usize) {
+    let mut xs = vec![];
+    let mut content = String::new();
+    let mut x = Abs::zero();
+    let mut last_range = None;
+    for glyph in &text.glyphs {
+        let range = glyph.range();
+        if last_range.as_ref() != Some(&range) {
+            let cluster = &text.text[range.clone()];
+            let count = cluster.chars().count().max(1) as f64;
+            let start = x + glyph.x_offset.at(text.size);
+            let advance = glyph.x_advance.at(text.size);
+            for (i, c) in cluster.chars().enumerate() {
+                xs.push((start + advance * (i as f64 / count)).to_pt().to_string());
+                content.push(c);
+            }
+            last_range = Some(range);
+        }
+        x += glyph.x_advance.at(text.size);
+    }
+    if content.trim().is_empty() {
+        return;
+    }
+
+    let fill = match &text.fill {
+        Paint::Solid(color) => color.to_hex(),
+        Paint::Gradient(_) | Paint::Pattern(_) => Color::BLACK.to_hex(),
+    };
+    let _ = write!(
+        out,
+        r#"<text x="{}" font-size="{}" fill="{fill}""#,
+        xs.join(" "),
+        text.size.to_pt(),
+    );
+    if mode == SvgTextMode::Embed {
+        let _ = write!(out, r#" font-family="tinymist-font-{index}""#);
+    } else {
+        let info = text.font.info();
+        let style = match info.variant.style {
+            FontStyle::Normal => "normal",
+            FontStyle::Italic => "italic",
+            FontStyle::Oblique => "oblique",
+        };
+        let _ = write!(
+            out,
+            r#" font-family="{}" font-weight="{}" font-style="{style}""#,
+            escape(&info.family),
+            info.variant.weight.to_number(),
+        );
+    }
+    let _ = write!(out, ">{}</text>", escape(&content));
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    fn find_text(frame: &Frame) -> Option<&TextItem> {
+        frame.items().find_map(|(_, item)| match item {
+            FrameItem::Text(text) => Some(text),
+            FrameItem::Group(group) => find_text(&group.frame),
+            _ => None,
+        })
+    }
+
+    /// Decode the first font embedded in an SVG file.
+    fn embedded_font(svg: &str) -> Vec<u8> {
+        let (_, data) = svg.split_once(";base64,").unwrap();
+        let data = &data[..data.find(')').unwrap()];
+        base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_svg_text() {
+        let world = TestWorld::new("#set page(width: 100pt, height: 50pt)\nHello & bye");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        let frame = &doc.pages[0].frame;
+        let text = find_text(frame).unwrap();
+
+        let outline = svg(frame, SvgTextMode::Outline);
+        assert!(outline.contains("<path"));
+        assert!(!outline.contains("<text"));
+
+        let embed = svg(frame, SvgTextMode::Embed);
+        assert!(embed.contains("Hello &amp; bye</text>"), "{embed}");
+        assert!(embed.contains("@font-face"));
+        assert!(embed.trim_end().ends_with("</svg>"));
+        // Only the used glyphs of the font are embedded, as a single face.
+        let data = embedded_font(&embed);
+        assert!(data.len() < text.font.data().len());
+        assert_ne!(data.get(..4), Some(&b"ttcf"[..]));
+
+        let reference = svg(frame, SvgTextMode::Reference);
+        assert!(reference.contains("Hello &amp; bye</text>"));
+        assert!(!reference.contains("@font-face"));
+        let family = format!(r#"font-family="{}""#, text.font.info().family);
+        assert!(reference.contains(&family));
+    }
+
+    #[test]
+    fn test_svg_text_ligature() {
+        let world = TestWorld::new("#set page(width: 100pt, height: 50pt)\nfind");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        let frame = &doc.pages[0].frame;
+        let text = find_text(frame).unwrap();
+        // The characters are shaped into a ligature.
+        assert_eq!(text.glyphs.len(), 3);
+
+        let embed = svg(frame, SvgTextMode::Embed);
+        assert!(embed.contains(">find</text>"), "{embed}");
+        // Viewers draw the characters by their own glyphs.
+        let font = Font::new(Bytes::from(embedded_font(&embed)), 0).unwrap();
+        for c in "find".chars() {
+            let glyph = font.ttf().glyph_index(c).unwrap();
+            assert!(font.ttf().glyph_bounding_box(glyph).is_some(), "{c}");
+        }
+    }
+
+    #[test]
+    fn test_svg_text_order() {
+        let world = TestWorld::new(
+            "#set page(width: 100pt, height: 50pt)\n\
+             #rect(fill: red)[Hello]\n\
+             #place(top, rect(fill: blue))",
+        );
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        let embed = svg(&doc.pages[0].frame, SvgTextMode::Embed);
+
+        // The text is painted over the red rectangle but under the blue one.
+        let red = embed.find(Color::RED.to_hex().as_str()).unwrap();
+        let text = embed.find("Hello</text>").unwrap();
+        let blue = embed.find(Color::BLUE.to_hex().as_str()).unwrap();
+        assert!(red < text && text < blue, "{embed}");
+        assert_eq!(embed.matches("<svg").count(), 1);
+        assert!(!embed.contains("<image"), "{embed}");
+    }
+}
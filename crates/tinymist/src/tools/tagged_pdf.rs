@@ -0,0 +1,922 @@
+//! Export documents as tagged PDFs, whose structure trees tell assistive
+//! technologies about the headings, figures and lists of the documents.
+//!
+//! The PDF exporter of Typst does not tag content yet, so the exported PDF is
+//! tagged by an incremental update. The text runs and images in the content
+//! streams of the pages are wrapped into marked content, in the order they
+//! were laid out, and the structure tree refers to the marked content by its
+//! identifiers. The content laid out for no element of the sources, e.g. page
+//! numbers, is marked as artifacts.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::iter::zip;
+
+use anyhow::{anyhow, bail, Context};
+use typst::foundations::{NativeElement, Packed, Selector, Smart, StyleChain};
+use typst::introspection::Meta;
+use typst::layout::{Abs, Frame, FrameItem, Point};
+use typst::model::{Document, FigureElem, HeadingElem};
+use typst::syntax::{ast, FileId, LinkedNode, Source, Span, SyntaxKind};
+use typst::visualize::ImageElem;
+use typst::World;
+
+const HEADINGS: [&str; 6] = ["H1", "H2", "H3", "H4", "H5", "H6"];
+
+/// A tagged PDF, along with the warnings about its accessibility.
+#[derive(Debug)]
+pub struct TaggedPdf {
+    pub pdf: Vec<u8>,
+    /// The figures and images without alternative text.
+    pub warnings: Vec<String>,
+}
+
+/// Export the document as a tagged PDF.
+pub fn tagged_pdf(world: &dyn World, doc: &Document) -> anyhow::Result<TaggedPdf> {
+    let tree = struct_tree(world, doc)?;
+    let pdf = typst_pdf::pdf(doc, Smart::Auto, None);
+    let pdf = tag_pdf(pdf, &tree.root, &tree.marks)?;
+    let warnings = tree.warnings;
+    Ok(TaggedPdf { pdf, warnings })
+}
+
+/// An element of the structure tree.
+#[derive(Debug, Default)]
+struct StructElem {
+    /// The structure type, e.g. `H1`.
+    tag: &'static str,
+    alt: Option<String>,
+    actual_text: Option<String>,
+    /// The syntax node of the list, if the element is one.
+    list: Option<Span>,
+    /// The marked content of the element, as the zero-based numbers of the
+    /// pages and the identifiers of the content on them.
+    mcids: Vec<(usize, usize)>,
+    children: Vec<StructElem>,
+}
+
+impl StructElem {
+    fn new(tag: &'static str) -> Self {
+        Self {
+            tag,
+            ..Default::default()
+        }
+    }
+}
+
+/// How a text run or an image is marked in the content stream of its page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    /// The content of an element with the structure type, identified by the
+    /// number.
+    Tagged(&'static str, usize),
+    /// The content of no element.
+    Artifact,
+}
+
+/// The structure tree of a document.
+struct StructTree {
+    root: StructElem,
+    /// The marks of the text runs and images on each page, in layout order.
+    marks: Vec<Vec<Mark>>,
+    warnings: Vec<String>,
+}
+
+/// Build the structure tree of the document in reading order.
+fn struct_tree(world: &dyn World, doc: &Document) -> anyhow::Result<StructTree> {
+    let mut tagger = Tagger {
+        world,
+        root: StructElem::new("Document"),
+        page: 0,
+        figures: 0,
+        figure_images: HashSet::new(),
+        items: vec![],
+        par: None,
+        line: None,
+        owner: None,
+        marks: vec![],
+        mcids: 0,
+        sources: HashMap::new(),
+        warnings: vec![],
+    };
+    for (i, page) in doc.pages.iter().enumerate() {
+        tagger.page = i + 1;
+        tagger.line = None;
+        tagger.marks.push(vec![]);
+        tagger.mcids = 0;
+        tagger.frame(&page.frame, Point::zero())?;
+    }
+    Ok(StructTree {
+        root: tagger.root,
+        marks: tagger.marks,
+        warnings: tagger.warnings,
+    })
+}
+
+struct Tagger<'a> {
+    world: &'a dyn World,
+    root: StructElem,
+    /// The one-based number of the page being tagged.
+    page: usize,
+    /// The number of the figures tagged so far.
+    figures: usize,
+    /// The images in the tagged figures, which are described by the figures.
+    figure_images: HashSet<Span>,
+    /// The list items containing the last text, from the outermost one, as
+    /// the spans of their lists and of themselves.
+    items: Vec<(Span, Span)>,
+    /// The paragraph of the last text, see [`TextPlace::par`].
+    par: Option<(Span, usize)>,
+    /// The vertical position of the last text on the page.
+    line: Option<Abs>,
+    /// The last heading, figure or image, which owns the content laid out for
+    /// it, as the indices of the children leading to it from the root.
+    owner: Option<Vec<usize>>,
+    /// The marks of the pages tagged so far, see [`StructTree::marks`].
+    marks: Vec<Vec<Mark>>,
+    /// The number of the marked content tagged so far on the page.
+    mcids: usize,
+    sources: HashMap<FileId, Option<Source>>,
+    warnings: Vec<String>,
+}
+
+impl Tagger<'_> {
+    fn frame(&mut self, frame: &Frame, offset: Point) -> anyhow::Result<()> {
+        for (pos, item) in frame.items() {
+            match item {
+                FrameItem::Group(group) => self.frame(&group.frame, offset + *pos)?,
+                FrameItem::Text(text) => {
+                    let owner = match text.glyphs.first() {
+                        Some(glyph) => self.text(glyph.span.0, &text.text, offset.y + pos.y)?,
+                        None => None,
+                    };
+                    self.mark(owner)?;
+                }
+                FrameItem::Image(image, _, span) => {
+                    if !self.figure_images.contains(span) {
+                        let alt = image.alt().map(str::to_owned);
+                        if alt.is_none() {
+                            let page = self.page;
+                            self.warnings
+                                .push(format!("image on page {page} has no alt text"));
+                        }
+                        self.push(StructElem {
+                            alt,
+                            ..StructElem::new("Figure")
+                        })?;
+                    }
+                    self.mark(self.owner.clone())?;
+                }
+                FrameItem::Meta(Meta::Elem(elem), _) => {
+                    if elem.is::<HeadingElem>() || elem.is::<FigureElem>() {
+                        self.enter(elem.span())?;
+                    }
+                    if let Some(heading) = elem.to_packed::<HeadingElem>() {
+                        let level = heading.resolve_level(StyleChain::default()).get();
+                        self.push(StructElem {
+                            actual_text: Some(heading.body().plain_text().into()),
+                            ..StructElem::new(HEADINGS[level.min(6) - 1])
+                        })?;
+                    }
+                    if let Some(figure) = elem.to_packed::<FigureElem>() {
+                        self.figure(figure)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Tag a figure, which is described by the alternative text of its image
+    /// or otherwise by its caption.
+    fn figure(&mut self, figure: &Packed<FigureElem>) -> anyhow::Result<()> {
+        self.figures += 1;
+        let images = figure.body().query(Selector::Elem(ImageElem::elem(), None));
+        let mut alt = None;
+        for image in &images {
+            self.figure_images.insert(image.span());
+            let image = image.to_packed::<ImageElem>();
+            alt = alt.or_else(|| image?.alt(StyleChain::default()));
+        }
+        let caption = figure.caption(StyleChain::default());
+        let alt = alt.or_else(|| Some(caption?.body().plain_text()));
+        if alt.is_none() && !images.is_empty() {
+            let (figure, page) = (self.figures, self.page);
+            self.warnings
+                .push(format!("figure {figure} on page {page} has no alt text"));
+        }
+        self.push(StructElem {
+            alt: alt.map(Into::into),
+            ..StructElem::new("Figure")
+        })
+    }
+
+    /// Add a text to its paragraph, returning the element owning it, if any.
+    fn text(&mut self, span: Span, text: &str, line: Abs) -> anyhow::Result<Option<Vec<usize>>> {
+        let Some(place) = self.enter(span)? else {
+            return Ok(None);
+        };
+        let Some(par) = place.par else {
+            // The text is carried by the heading or the figure laid out for it.
+            return Ok(self.owner.clone());
+        };
+
+        let same_par = self.par == Some(par);
+        let new_line = self.line != Some(line);
+        self.par = Some(par);
+        self.line = Some(line);
+        let mut path = self.container_path()?;
+        let container = self.elem_mut(&path)?;
+        if !same_par || container.children.last().map_or(true, |p| p.tag != "P") {
+            container.children.push(StructElem::new("P"));
+        }
+        let last = container.children.len() - 1;
+        path.push(last);
+        let actual_text = (container.children[last].actual_text).get_or_insert_with(String::new);
+        // The space breaking a line is not laid out.
+        let at_space = actual_text.is_empty()
+            || actual_text.ends_with(char::is_whitespace)
+            || text.starts_with(char::is_whitespace);
+        if new_line && !at_space {
+            actual_text.push(' ');
+        }
+        actual_text.push_str(text);
+        Ok(Some(path))
+    }
+
+    /// Mark a text run or an image as the content of its owner, or as an
+    /// artifact if it has none.
+    fn mark(&mut self, owner: Option<Vec<usize>>) -> anyhow::Result<()> {
+        let mark = match owner {
+            Some(path) => {
+                let (page, mcid) = (self.page - 1, self.mcids);
+                let elem = self.elem_mut(&path)?;
+                elem.mcids.push((page, mcid));
+                let tag = elem.tag;
+                self.mcids += 1;
+                Mark::Tagged(tag, mcid)
+            }
+            None => Mark::Artifact,
+        };
+        let marks = self.marks.last_mut().context("no page is being tagged")?;
+        marks.push(mark);
+        Ok(())
+    }
+
+    /// Open and close the list items around the syntax node of the span,
+    /// returning where the node is.
+    fn enter(&mut self, span: Span) -> anyhow::Result<Option<TextPlace>> {
+        let Some(place) = self.locate(span) else {
+            return Ok(None);
+        };
+        let items = &place.items;
+        let common = zip(&self.items, items).take_while(|(x, y)| x == y).count();
+        self.items.truncate(common);
+        for &(list, item) in &items[common..] {
+            let container = self.container()?;
+            // Items of a list are grouped unless they are interrupted.
+            if container
+                .children
+                .last()
+                .map_or(true, |l| l.list != Some(list))
+            {
+                container.children.push(StructElem {
+                    list: Some(list),
+                    ..StructElem::new("L")
+                });
+            }
+            let last = container.children.len() - 1;
+            container.children[last]
+                .children
+                .push(StructElem::new("LI"));
+            self.items.push((list, item));
+        }
+        Ok(Some(place))
+    }
+
+    /// Locate the syntax node of the span in the lists and paragraphs, or get
+    /// `None` if the node is unknown.
+    fn locate(&mut self, span: Span) -> Option<TextPlace> {
+        let id = span.id()?;
+        let world = self.world;
+        let source = self
+            .sources
+            .entry(id)
+            .or_insert_with(|| world.source(id).ok());
+        let node = source.as_ref()?.find(span)?;
+
+        let mut items = vec![];
+        let mut par = None;
+        let mut carried = false;
+        let mut node = Some(&node);
+        while let Some(current) = node {
+            let kind = current.kind();
+            carried |= kind == SyntaxKind::Heading || is_figure(current);
+            if let (None, Some(markup)) = (par, current.parent()) {
+                // The markup of strong and emphasized text and of content
+                // blocks is inline, e.g. `#text(red)[..]`.
+                let inline = matches!(
+                    markup.parent_kind(),
+                    Some(SyntaxKind::Strong | SyntaxKind::Emph | SyntaxKind::ContentBlock)
+                );
+                if markup.kind() == SyntaxKind::Markup && !inline {
+                    let before = markup.children().take(current.index());
+                    let breaks = before.filter(|c| c.kind() == SyntaxKind::Parbreak);
+                    par = Some((markup.span(), breaks.count()));
+                }
+            }
+            if matches!(
+                kind,
+                SyntaxKind::ListItem | SyntaxKind::EnumItem | SyntaxKind::TermItem
+            ) {
+                let list = current
+                    .parent()
+                    .map_or(Span::detached(), |list| list.span());
+                items.push((list, current.span()));
+            }
+            node = current.parent();
+        }
+        items.reverse();
+        let par = par.filter(|_| !carried);
+        Some(TextPlace { items, par })
+    }
+
+    /// Get the innermost open list item, or the document if there is none.
+    fn container(&mut self) -> anyhow::Result<&mut StructElem> {
+        let path = self.container_path()?;
+        self.elem_mut(&path)
+    }
+
+    /// Get the indices of the children leading to the innermost open list
+    /// item, see [`Self::container`].
+    fn container_path(&self) -> anyhow::Result<Vec<usize>> {
+        let mut path = vec![];
+        let mut elem = &self.root;
+        // Each open item is the last item of the last list in its container.
+        for _ in 0..self.items.len() * 2 {
+            let last = (elem.children.len().checked_sub(1))
+                .context("the structure tree has no open list item")?;
+            path.push(last);
+            elem = &elem.children[last];
+        }
+        Ok(path)
+    }
+
+    fn elem_mut(&mut self, path: &[usize]) -> anyhow::Result<&mut StructElem> {
+        let mut elem = &mut self.root;
+        for &i in path {
+            elem = (elem.children.get_mut(i)).context("the structure tree has no such element")?;
+        }
+        Ok(elem)
+    }
+
+    /// Add an element owning the content laid out next.
+    fn push(&mut self, elem: StructElem) -> anyhow::Result<()> {
+        let mut path = self.container_path()?;
+        let container = self.elem_mut(&path)?;
+        container.children.push(elem);
+        path.push(container.children.len() - 1);
+        self.owner = Some(path);
+        Ok(())
+    }
+}
+
+/// Where a text is in the structure of its source.
+struct TextPlace {
+    /// The list items containing the text, see [`Tagger::items`].
+    items: Vec<(Span, Span)>,
+    /// The paragraph containing the text, as the span of its markup and the
+    /// number of paragraph breaks before it in the markup, or `None` if the
+    /// text is carried by a heading or a figure.
+    par: Option<(Span, usize)>,
+}
+
+/// Whether the node is a call of `figure`.
+fn is_figure(node: &LinkedNode) -> bool {
+    let Some(call) = node.cast::<ast::FuncCall>() else {
+        return false;
+    };
+    matches!(call.callee(), ast::Expr::Ident(callee) if callee.as_str() == "figure")
+}
+
+/// Mark the content of the pages and append the structure tree to the PDF by
+/// an incremental update, which also refers the catalog to it.
+fn tag_pdf(mut pdf: Vec<u8>, root: &StructElem, marks: &[Vec<Mark>]) -> anyhow::Result<Vec<u8>> {
+    let trailer_at = rfind(&pdf, b"trailer").context("the PDF has no trailer")?;
+    let trailer = std::str::from_utf8(&pdf[trailer_at + "trailer".len()..])
+        .context("the trailer of the PDF is malformed")?;
+    let (dict, startxref) =
+        (trailer.split_once("startxref")).context("the PDF has no cross-reference offset")?;
+    let dict = dict.trim();
+    let prev: usize = startxref.trim().trim_end_matches("%%EOF").trim().parse()?;
+    let size: usize = dict_value(dict, "/Size")?.parse()?;
+    let catalog_id: usize = dict_value(dict, "/Root")?.parse()?;
+    let offsets = xref_offsets(&pdf, prev)?;
+    let catalog = object_dict(&pdf, &offsets, catalog_id)?;
+    let page_tree_id: usize = dict_value(catalog, "/Pages")?.parse()?;
+    let page_ids = ref_array(object_dict(&pdf, &offsets, page_tree_id)?, "/Kids")?;
+    if page_ids.len() != marks.len() {
+        bail!(
+            "the PDF has {} pages but the document has {}",
+            page_ids.len(),
+            marks.len()
+        );
+    }
+
+    let mut objects = vec![];
+    for (i, (&page_id, marks)) in zip(&page_ids, marks).enumerate() {
+        let page = object_dict(&pdf, &offsets, page_id)?;
+        let content_id: usize = dict_value(page, "/Contents")?.parse()?;
+        let content = object_stream(&pdf, &offsets, content_id)?;
+        let content = mark_content(&content, marks)
+            .with_context(|| format!("cannot mark the content of page {}", i + 1))?;
+        let content = miniz_oxide::deflate::compress_to_vec_zlib(&content, 6);
+        let object = format!(
+            "<< /Length {} /Filter /FlateDecode >>\nstream\n",
+            content.len()
+        );
+        let mut object = object.into_bytes();
+        object.extend(content);
+        object.extend(b"\nendstream");
+        objects.push((content_id, object));
+
+        let page = page.strip_suffix(">>").context("the page is malformed")?;
+        let page = format!("{page} /StructParents {i} >>");
+        objects.push((page_id, page.into_bytes()));
+    }
+
+    let tree_id = size;
+    let mut writer = ElemWriter {
+        page_ids: &page_ids,
+        next: size + 1,
+        parents: marks.iter().map(|marks| vec![None; marks.len()]).collect(),
+        objects,
+    };
+    let doc_id = writer.write(root, tree_id)?;
+    let parent_tree_id = writer.next;
+    let next = parent_tree_id + 1;
+    let mut objects = writer.objects;
+
+    // The parent tree maps the marked content of each page to its elements.
+    let nums = (writer.parents.iter().enumerate()).map(|(i, parents)| {
+        let parents = parents.iter().flatten().map(|id| format!("{id} 0 R"));
+        format!("{i} [{}]", parents.collect::<Vec<_>>().join(" "))
+    });
+    let nums = nums.collect::<Vec<_>>().join(" ");
+    objects.push((parent_tree_id, format!("<< /Nums [{nums}] >>").into_bytes()));
+    let tree = format!(
+        "<< /Type /StructTreeRoot /K {doc_id} 0 R /ParentTree {parent_tree_id} 0 R \
+        /ParentTreeNextKey {} >>",
+        page_ids.len()
+    );
+    objects.push((tree_id, tree.into_bytes()));
+    let catalog = catalog
+        .strip_suffix(">>")
+        .context("the catalog is malformed")?;
+    let catalog =
+        format!("{catalog} /StructTreeRoot {tree_id} 0 R /MarkInfo << /Marked true >> >>");
+    objects.push((catalog_id, catalog.into_bytes()));
+    objects.sort_by_key(|(id, _)| *id);
+
+    let new_dict = dict.replacen(&format!("/Size {size}"), &format!("/Size {next}"), 1);
+    let Some(new_dict) = new_dict.strip_suffix(">>") else {
+        bail!("the trailer of the PDF is malformed");
+    };
+    if !pdf.ends_with(b"\n") {
+        pdf.push(b'\n');
+    }
+    let mut offsets = vec![];
+    for (id, object) in &objects {
+        offsets.push((*id, pdf.len()));
+        write!(pdf, "{id} 0 obj\n")?;
+        pdf.extend(object);
+        pdf.extend(b"\nendobj\n\n");
+    }
+
+    // Each run of consecutive objects is a subsection of the cross-reference.
+    let xref = pdf.len();
+    pdf.extend_from_slice(b"xref\n");
+    let mut rest = &offsets[..];
+    while let Some(&(first, _)) = rest.first() {
+        let len = (rest.iter().enumerate())
+            .take_while(|(i, (id, _))| *id == first + i)
+            .count();
+        writeln!(pdf, "{first} {len}")?;
+        for (_, offset) in &rest[..len] {
+            write!(pdf, "{offset:010} 00000 n\r\n")?;
+        }
+        rest = &rest[len..];
+    }
+    write!(
+        pdf,
+        "trailer\n{new_dict} /Prev {prev}\n>>\nstartxref\n{xref}\n%%EOF\n"
+    )?;
+    Ok(pdf)
+}
+
+/// Writes the elements of the structure tree as objects.
+struct ElemWriter<'a> {
+    /// The numbers of the page objects.
+    page_ids: &'a [usize],
+    /// The number of the next object.
+    next: usize,
+    /// The elements owning the marked content of each page, by the
+    /// identifiers of the content.
+    parents: Vec<Vec<Option<usize>>>,
+    objects: Vec<(usize, Vec<u8>)>,
+}
+
+impl ElemWriter<'_> {
+    /// Write the element and its children, returning the number of the
+    /// element.
+    fn write(&mut self, elem: &StructElem, parent: usize) -> anyhow::Result<usize> {
+        let id = self.next;
+        self.next += 1;
+
+        let mut kids = vec![];
+        for &(page, mcid) in &elem.mcids {
+            let page_id = self.page_ids.get(page).context("the page is missing")?;
+            let owner = (self.parents.get_mut(page))
+                .and_then(|parents| parents.get_mut(mcid))
+                .context("the marked content is missing")?;
+            *owner = Some(id);
+            kids.push(format!("<< /Type /MCR /Pg {page_id} 0 R /MCID {mcid} >>"));
+        }
+        for child in &elem.children {
+            kids.push(format!("{} 0 R", self.write(child, id)?));
+        }
+
+        let mut object = format!("<< /Type /StructElem /S /{} /P {parent} 0 R", elem.tag);
+        if !kids.is_empty() {
+            object.push_str(&format!(" /K [{}]", kids.join(" ")));
+        }
+        if let Some(alt) = &elem.alt {
+            object.push_str(&format!(" /Alt {}", text_string(alt)));
+        }
+        if let Some(text) = &elem.actual_text {
+            object.push_str(&format!(" /ActualText {}", text_string(text)));
+        }
+        object.push_str(" >>");
+        self.objects.push((id, object.into_bytes()));
+        Ok(id)
+    }
+}
+
+/// Wrap the text runs and images in the content stream into marked content,
+/// in the order they were laid out. Each text run is an object between `BT`
+/// and `ET` and each image is an external object painted by `Do`.
+fn mark_content(content: &[u8], marks: &[Mark]) -> anyhow::Result<Vec<u8>> {
+    let begin = |mark: Option<&Mark>| match mark {
+        Some(Mark::Tagged(tag, mcid)) => Ok(format!("/{tag} <</MCID {mcid}>> BDC\n")),
+        Some(Mark::Artifact) => Ok("/Artifact BMC\n".to_owned()),
+        None => Err(anyhow!(
+            "more text runs and images are painted than laid out"
+        )),
+    };
+    let mut marks = marks.iter();
+    let mut marked = Vec::with_capacity(content.len());
+    let mut copied = 0;
+    // The start of the operands of the next operator.
+    let mut operands = None;
+    let mut at = 0;
+    while let Some((start, end)) = next_token(content, at) {
+        at = end;
+        let token = &content[start..end];
+        let is_operator = token[0].is_ascii_alphabetic() || matches!(token[0], b'\'' | b'"');
+        if !is_operator || matches!(token, b"true" | b"false" | b"null") {
+            operands.get_or_insert(start);
+            continue;
+        }
+
+        let operands = operands.take().unwrap_or(start);
+        let (open, close) = match token {
+            b"BT" => (Some(start), None),
+            b"ET" => (None, Some(end)),
+            b"Do" => (Some(operands), Some(end)),
+            _ => continue,
+        };
+        if let Some(open) = open {
+            marked.extend(&content[copied..open]);
+            marked.extend(begin(marks.next())?.into_bytes());
+            copied = open;
+        }
+        if let Some(close) = close {
+            marked.extend(&content[copied..close]);
+            marked.extend(b"\nEMC");
+            copied = close;
+        }
+    }
+    if marks.next().is_some() {
+        bail!("fewer text runs and images are painted than laid out");
+    }
+    marked.extend(&content[copied..]);
+    Ok(marked)
+}
+
+/// Get the range of the next token in the content stream from the offset,
+/// skipping whitespace and comments.
+fn next_token(content: &[u8], mut at: usize) -> Option<(usize, usize)> {
+    const WHITESPACE: &[u8] = b"\0\t\n\x0c\r ";
+    const DELIMITERS: &[u8] = b"()<>[]{}/%";
+    loop {
+        match content.get(at)? {
+            b if WHITESPACE.contains(b) => at += 1,
+            b'%' => {
+                while content.get(at).is_some_and(|b| !b"\r\n".contains(b)) {
+                    at += 1;
+                }
+            }
+            _ => break,
+        }
+    }
+
+    let start = at;
+    let next = content.get(start + 1);
+    let end = match content[start] {
+        // A literal string may contain balanced parentheses and escapes.
+        b'(' => {
+            let mut depth = 0;
+            loop {
+                match content.get(at) {
+                    None => break at,
+                    Some(b'\\') => at += 2,
+                    Some(b'(') => {
+                        depth += 1;
+                        at += 1;
+                    }
+                    Some(b')') => {
+                        depth -= 1;
+                        at += 1;
+                        if depth == 0 {
+                            break at;
+                        }
+                    }
+                    Some(_) => at += 1,
+                }
+            }
+        }
+        b'<' | b'>' if next == Some(&content[start]) => start + 2,
+        b'<' => find(&content[start..], b">").map_or(content.len(), |len| start + len + 1),
+        b'[' | b']' | b'{' | b'}' => start + 1,
+        _ => {
+            at += 1;
+            while (content.get(at))
+                .is_some_and(|b| !WHITESPACE.contains(b) && !DELIMITERS.contains(b))
+            {
+                at += 1;
+            }
+            at
+        }
+    };
+    Some((start, end.min(content.len())))
+}
+
+/// Encode a text string in UTF-16BE with a byte order mark.
+fn text_string(text: &str) -> String {
+    let mut encoded = "<FEFF".to_owned();
+    for unit in text.encode_utf16() {
+        encoded.push_str(&format!("{unit:04X}"));
+    }
+    encoded.push('>');
+    encoded
+}
+
+/// Get the first token of the value of the key in a dictionary.
+fn dict_value<'a>(dict: &'a str, key: &str) -> anyhow::Result<&'a str> {
+    let value = dict.split_once(key).map(|(_, value)| value);
+    value
+        .and_then(|value| value.split_whitespace().next())
+        .with_context(|| format!("the PDF has no {key}"))
+}
+
+/// Get the numbers of the objects referred by the array of the key in a
+/// dictionary.
+fn ref_array(dict: &str, key: &str) -> anyhow::Result<Vec<usize>> {
+    let array = dict.split_once(key).map(|(_, value)| value.trim_start());
+    let array = array
+        .and_then(|value| value.strip_prefix('['))
+        .and_then(|value| value.split_once(']'))
+        .with_context(|| format!("the PDF has no {key}"))?;
+    // Each reference is written as `id generation R`.
+    let ids = array.0.split_whitespace().step_by(3);
+    ids.map(|id| Ok(id.parse()?)).collect()
+}
+
+/// Get the offsets of the objects listed in the cross-reference table at the
+/// offset.
+fn xref_offsets(pdf: &[u8], at: usize) -> anyhow::Result<HashMap<usize, usize>> {
+    let table = (pdf.get(at..))
+        .and_then(|table| table.strip_prefix(b"xref"))
+        .context("the PDF has no cross-reference table")?;
+    let len = find(table, b"trailer").context("the PDF has no trailer")?;
+    let table = std::str::from_utf8(&table[..len])?;
+
+    let mut offsets = HashMap::new();
+    let mut tokens = table.split_whitespace();
+    while let Some(first) = tokens.next() {
+        let first: usize = first.parse()?;
+        let count: usize = tokens.next().unwrap_or_default().parse()?;
+        for id in first..first + count {
+            let (Some(offset), Some(_), Some(kind)) = (tokens.next(), tokens.next(), tokens.next())
+            else {
+                bail!("the cross-reference table is malformed");
+            };
+            if kind == "n" {
+                offsets.insert(id, offset.parse()?);
+            }
+        }
+    }
+    Ok(offsets)
+}
+
+/// Get the bytes following the header of the object with the number.
+fn object<'a>(
+    pdf: &'a [u8],
+    offsets: &HashMap<usize, usize>,
+    id: usize,
+) -> anyhow::Result<&'a [u8]> {
+    let header = format!("{id} 0 obj");
+    let offset = offsets
+        .get(&id)
+        .with_context(|| format!("the PDF has no object {id}"))?;
+    (pdf.get(*offset..))
+        .and_then(|object| object.strip_prefix(header.as_bytes()))
+        .with_context(|| format!("the object {id} is malformed"))
+}
+
+/// Get the dictionary of the object with the number.
+fn object_dict<'a>(
+    pdf: &'a [u8],
+    offsets: &HashMap<usize, usize>,
+    id: usize,
+) -> anyhow::Result<&'a str> {
+    let object = object(pdf, offsets, id)?;
+    let len = find(object, b"endobj").with_context(|| format!("the object {id} is malformed"))?;
+    let object = std::str::from_utf8(&object[..len])?.trim();
+    if !object.starts_with("<<") || !object.ends_with(">>") {
+        bail!("the object {id} is malformed");
+    }
+    Ok(object)
+}
+
+/// Get the decoded data of the stream object with the number.
+fn object_stream(
+    pdf: &[u8],
+    offsets: &HashMap<usize, usize>,
+    id: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let object = object(pdf, offsets, id)?;
+    let start = find(object, b"stream").with_context(|| format!("the object {id} is no stream"))?;
+    let dict = std::str::from_utf8(&object[..start])?;
+    let len: usize = dict_value(dict, "/Length")?.parse()?;
+    let data = &object[start + "stream".len()..];
+    let data = (data.strip_prefix(b"\r\n"))
+        .or_else(|| data.strip_prefix(b"\n"))
+        .unwrap_or(data);
+    let data = data
+        .get(..len)
+        .with_context(|| format!("the stream {id} is truncated"))?;
+    if !dict.contains("/FlateDecode") {
+        return Ok(data.to_vec());
+    }
+    miniz_oxide::inflate::decompress_to_vec_zlib(data)
+        .map_err(|err| anyhow!("cannot decompress the stream {id}: {err:?}"))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    fn tags(elem: &StructElem) -> String {
+        if elem.children.is_empty() {
+            return elem.tag.to_owned();
+        }
+        let children: Vec<_> = elem.children.iter().map(tags).collect();
+        format!("{}({})", elem.tag, children.join(" "))
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        let world = TestWorld::new(&format!(
+            "#set page(width: 100pt)\n= Title\n{}\n\nSecond *paragraph*.",
+            "word ".repeat(20).trim_end()
+        ));
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        let tree = struct_tree(&world, &doc).unwrap();
+        let root = &tree.root;
+        assert_eq!(tags(root), "Document(H1 P P)");
+
+        // The lines of a paragraph are joined by spaces.
+        let text = |i: usize| root.children[i].actual_text.as_deref().unwrap();
+        assert_eq!(text(1), "word ".repeat(20).trim_end());
+        assert_eq!(text(2), "Second paragraph.");
+
+        // Each text run is marked as the content of its element.
+        let marks = &tree.marks[0];
+        assert_eq!(marks[0], Mark::Tagged("H1", 0));
+        assert!(marks[1..].iter().all(|m| matches!(m, Mark::Tagged("P", _))));
+        let mcids = |i: usize| root.children[i].mcids.len();
+        assert_eq!(mcids(0) + mcids(1) + mcids(2), marks.len());
+    }
+
+    #[test]
+    fn test_mark_content() {
+        let content = b"q\n/Span <</Alt (a)>> BDC\n/Im0 Do\nEMC\nQ\n\
+            BT\n[(BT\\) ET) 5 (x)] TJ\nET\nBT\n(y) Tj\nET\n";
+        let marks = [
+            Mark::Tagged("Figure", 0),
+            Mark::Tagged("P", 1),
+            Mark::Artifact,
+        ];
+        let marked = mark_content(content, &marks).unwrap();
+        assert_eq!(
+            String::from_utf8(marked).unwrap(),
+            "q\n/Span <</Alt (a)>> BDC\n/Figure <</MCID 0>> BDC\n/Im0 Do\nEMC\nEMC\nQ\n\
+            /P <</MCID 1>> BDC\nBT\n[(BT\\) ET) 5 (x)] TJ\nET\nEMC\n\
+            /Artifact BMC\nBT\n(y) Tj\nET\nEMC\n"
+        );
+
+        // The content must match the layout.
+        assert!(mark_content(content, &marks[..2]).is_err());
+        let mut more = marks.to_vec();
+        more.push(Mark::Artifact);
+        assert!(mark_content(content, &more).is_err());
+    }
+
+    #[test]
+    fn test_tagged_pdf() {
+        let square = "image.decode(\"<svg xmlns='http://www.w3.org/2000/svg' width='10' \
+            height='10'/>\", format: \"svg\")";
+        let world = TestWorld::new(&format!(
+            "= Intro\n== Details\n- first\n- second\n  - nested\n\n\
+            #figure({square}, caption: [A square.])\n#figure({square})\n"
+        ));
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+
+        let tree = struct_tree(&world, &doc).unwrap();
+        assert_eq!(
+            tags(&tree.root),
+            "Document(H1 H2 L(LI(P) LI(P L(LI(P)))) Figure Figure)"
+        );
+
+        let tagged = tagged_pdf(&world, &doc).unwrap();
+        assert_eq!(tagged.warnings, ["figure 2 on page 1 has no alt text"]);
+        let pdf = String::from_utf8_lossy(&tagged.pdf);
+        assert!(pdf.contains("/MarkInfo << /Marked true >>"));
+        assert!(pdf.contains("/StructTreeRoot"));
+        assert!(pdf.contains("/ParentTree"));
+        for tag in [
+            "/S /Document",
+            "/S /H1",
+            "/S /H2",
+            "/S /L ",
+            "/S /LI",
+            "/S /P",
+            "/S /Figure",
+        ] {
+            assert!(pdf.contains(tag), "{tag} is missing");
+        }
+        // The figure with a caption is described by it.
+        assert!(pdf.contains(&format!("/Alt {}", text_string("A square."))));
+        assert!(pdf.contains(&format!("/ActualText {}", text_string("nested"))));
+        assert!(pdf.contains("/Type /MCR /Pg "));
+
+        // The update points to its cross-reference section.
+        let startxref = pdf.rsplit("startxref").next().unwrap();
+        let startxref: usize = startxref
+            .trim()
+            .trim_end_matches("%%EOF")
+            .trim()
+            .parse()
+            .unwrap();
+        assert!(tagged.pdf[startxref..].starts_with(b"xref\n"));
+        assert!(pdf.contains("/Prev "));
+
+        // The content of the page is replaced by the marked content.
+        let offsets = xref_offsets(&tagged.pdf, startxref).unwrap();
+        let page = (offsets.keys())
+            .filter_map(|&id| object_dict(&tagged.pdf, &offsets, id).ok())
+            .find(|dict| dict.contains("/StructParents 0"))
+            .unwrap();
+        let content_id = dict_value(page, "/Contents").unwrap().parse().unwrap();
+        let content = object_stream(&tagged.pdf, &offsets, content_id).unwrap();
+        let content = String::from_utf8_lossy(&content);
+        assert!(content.contains("/H1 <</MCID 0>> BDC\nBT"), "{content}");
+        assert!(content.contains("/H2 <</MCID 1>> BDC\nBT"), "{content}");
+        assert!(content.contains("/Figure <</MCID"), "{content}");
+        let opened = content.matches(" BDC").count() + content.matches(" BMC").count();
+        assert_eq!(opened, content.matches("EMC").count());
+    }
+}
@@ -0,0 +1,85 @@
+//! Helpers for testing tools.
+
+use std::collections::HashMap;
+
+use comemo::Prehashed;
+use typst::diag::{FileError, FileResult};
+use typst::foundations::{Bytes, Datetime};
+use typst::syntax::{FileId, Source, VirtualPath};
+use typst::text::{Font, FontBook};
+use typst::{Library, World};
+
+/// A world only containing the given source and embedded fonts.
+pub struct TestWorld {
+    library: Prehashed<Library>,
+    book: Prehashed<FontBook>,
+    fonts: Vec<Font>,
+    files: HashMap<FileId, Bytes>,
+    today: Option<Datetime>,
+    pub main: Source,
+}
+
+impl TestWorld {
+    pub fn new(text: &str) -> Self {
+        let fonts: Vec<_> = typst_assets::fonts()
+            .flat_map(|data| Font::iter(Bytes::from_static(data)))
+            .collect();
+        Self {
+            library: Prehashed::new(Library::default()),
+            book: Prehashed::new(FontBook::from_fonts(&fonts)),
+            fonts,
+            files: HashMap::new(),
+            today: None,
+            main: Source::new(FileId::new(None, VirtualPath::new("main.typ")), text.into()),
+        }
+    }
+
+    /// Add a file at the path relative to the root.
+    pub fn with_file(mut self, path: &str, data: &[u8]) -> Self {
+        let id = FileId::new(None, VirtualPath::new(path));
+        self.files.insert(id, Bytes::from(data.to_vec()));
+        self
+    }
+
+    /// Set the current date, which is unknown otherwise.
+    pub fn with_today(mut self, today: Datetime) -> Self {
+        self.today = Some(today);
+        self
+    }
+}
+
+impl World for TestWorld {
+    fn library(&self) -> &Prehashed<Library> {
+        &self.library
+    }
+
+    fn book(&self) -> &Prehashed<FontBook> {
+        &self.book
+    }
+
+    fn main(&self) -> Source {
+        self.main.clone()
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        if id == self.main.id() {
+            return Ok(self.main.clone());
+        }
+        let data = self.file(id)?;
+        let text = std::str::from_utf8(&data).map_err(|_| FileError::InvalidUtf8)?;
+        Ok(Source::new(id, text.into()))
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        let data = self.files.get(&id).cloned();
+        data.ok_or_else(|| FileError::NotFound(id.vpath().as_rootless_path().into()))
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        self.fonts.get(index).cloned()
+    }
+
+    fn today(&self, _offset: Option<i64>) -> Option<Datetime> {
+        self.today
+    }
+}
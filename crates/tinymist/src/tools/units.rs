@@ -0,0 +1,89 @@
+//! Convert lengths between the units of Typst.
+
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use typst::layout::Abs;
+
+/// A unit of lengths, which is absolute or relative to the font size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthUnit {
+    Pt,
+    Mm,
+    Cm,
+    In,
+    Em,
+}
+
+impl FromStr for LengthUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "pt" => Self::Pt,
+            "mm" => Self::Mm,
+            "cm" => Self::Cm,
+            "in" => Self::In,
+            "em" => Self::Em,
+            _ => bail!("unsupported unit {s:?}, expected pt, mm, cm, in or em"),
+        })
+    }
+}
+
+/// Convert a length between the units, with the font size in points to
+/// resolve `em`.
+pub fn convert_units(
+    value: f64,
+    from: LengthUnit,
+    to: LengthUnit,
+    font_size: Option<f64>,
+) -> anyhow::Result<f64> {
+    if !value.is_finite() {
+        bail!("the value is not finite: {value}");
+    }
+    let font_size = || {
+        let size = font_size.context("converting em requires the font size")?;
+        if !(size.is_finite() && size > 0.) {
+            bail!("the font size must be positive, got {size}");
+        }
+        anyhow::Ok(size)
+    };
+
+    let abs = match from {
+        LengthUnit::Pt => Abs::pt(value),
+        LengthUnit::Mm => Abs::mm(value),
+        LengthUnit::Cm => Abs::cm(value),
+        LengthUnit::In => Abs::inches(value),
+        LengthUnit::Em => Abs::pt(value * font_size()?),
+    };
+    Ok(match to {
+        LengthUnit::Pt => abs.to_pt(),
+        LengthUnit::Mm => abs.to_mm(),
+        LengthUnit::Cm => abs.to_cm(),
+        LengthUnit::In => abs.to_inches(),
+        LengthUnit::Em => abs.to_pt() / font_size()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(value: f64, from: &str, to: &str, font_size: Option<f64>) -> anyhow::Result<f64> {
+        convert_units(value, from.parse()?, to.parse()?, font_size)
+    }
+
+    #[test]
+    fn test_convert_units() {
+        let mm = convert(10., "pt", "mm", None).unwrap();
+        assert!((mm - 3.5278).abs() < 1e-4, "{mm}");
+        let pt = convert(1., "em", "pt", Some(12.)).unwrap();
+        assert!((pt - 12.).abs() < 1e-9, "{pt}");
+        let em = convert(1., "in", "em", Some(12.)).unwrap();
+        assert!((em - 6.).abs() < 1e-9, "{em}");
+
+        // Relative units need more context than a font size.
+        assert!(convert(50., "%", "pt", None).is_err());
+        assert!(convert(1., "em", "pt", None).is_err());
+    }
+}
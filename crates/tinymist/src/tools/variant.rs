@@ -0,0 +1,101 @@
+//! Render a variant of a document, which is a function defined in the source
+//! file rendering the document, e.g. `#let main-print() = ..`.
+//!
+//! The function is imported and called by a scaffold next to the source file,
+//! so that its content is rendered without the rest of the file, but styled by
+//! the `set` and `show` rules at the top level of the file.
+
+use anyhow::bail;
+use tinymist_query::OverlayWorld;
+use typst::eval::Tracer;
+use typst::foundations::{Repr, Smart, Str};
+use typst::syntax::{ast, is_ident, FileId, Source, VirtualPath};
+use typst::World;
+
+/// Create a scaffold calling the function of the source file, after the `set`
+/// and `show` rules at the top level of the file. Everything is imported from
+/// the file so that the names used by the rules are defined.
+pub fn variant_scaffold(source: &Source, function: &str) -> anyhow::Result<String> {
+    if !is_ident(function) {
+        bail!("{function:?} is not a function name");
+    }
+    let vpath = source.id().vpath().as_rootless_path();
+    let Some(name) = vpath.file_name() else {
+        bail!("the source file has no name");
+    };
+    let name = Str::from(name.to_string_lossy().as_ref()).repr();
+
+    let mut scaffold = format!("#import {name}: *\n");
+    let exprs = source.root().cast::<ast::Markup>().into_iter();
+    for expr in exprs.flat_map(|markup| markup.exprs()) {
+        if matches!(expr, ast::Expr::Set(_) | ast::Expr::Show(_)) {
+            scaffold.push('#');
+            scaffold.push_str(&expr.to_untyped().clone().into_text());
+            scaffold.push('\n');
+        }
+    }
+    scaffold.push_str(&format!("#{function}()\n"));
+    Ok(scaffold)
+}
+
+/// Compile the variant of the document rendered by the function and export it
+/// as a PDF.
+pub fn render_variant(
+    world: &dyn World,
+    source: &Source,
+    function: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let scaffold = variant_scaffold(source, function)?;
+
+    // Place the scaffold next to the source to import it by its name.
+    let id = source.id();
+    let vpath = id
+        .vpath()
+        .as_rootless_path()
+        .with_file_name("__variant__.typ");
+    let id = FileId::new(id.package().cloned(), VirtualPath::new(vpath));
+    let world = OverlayWorld::new(world).with_main(Source::new(id, scaffold));
+
+    let doc = match typst::compile(&world, &mut Tracer::new()) {
+        Ok(doc) => doc,
+        Err(errors) => {
+            let message = errors.first().map(|e| e.message.as_str()).unwrap_or("");
+            bail!("the variant {function} cannot be compiled: {message}");
+        }
+    };
+    Ok(typst_pdf::pdf(&doc, Smart::Auto, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    #[test]
+    fn test_render_variant() {
+        let world = TestWorld::new(
+            "#let template(body) = body\n\
+             #set text(size: 12pt)\n\
+             #show heading: set text(blue)\n\
+             #show: template\n\
+             #let main() = [Main]\n\
+             #let variant-print() = [Print]\n\
+             #{ set page(width: 1cm) }\n\
+             Not rendered",
+        );
+        let source = world.main();
+        assert_eq!(
+            variant_scaffold(&source, "variant-print").unwrap(),
+            "#import \"main.typ\": *\n\
+             #set text(size: 12pt)\n\
+             #show heading: set text(blue)\n\
+             #show: template\n\
+             #variant-print()\n"
+        );
+
+        let pdf = render_variant(&world, &source, "main").unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        assert!(render_variant(&world, &source, "missing").is_err());
+        assert!(render_variant(&world, &source, "main()").is_err());
+    }
+}
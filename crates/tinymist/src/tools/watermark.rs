@@ -0,0 +1,182 @@
+//! Overlay a watermark on the pages of documents.
+//!
+//! The watermark is laid out as a page of its own for each page size of the
+//! document, and composited on top of the pages, so backgrounds set by the
+//! document are kept.
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use tinymist_query::OverlayWorld;
+use typst::eval::Tracer;
+use typst::foundations::{Dict, Value};
+use typst::layout::{Abs, Angle, Frame, Point, Size};
+use typst::model::Document;
+use typst::syntax::Source;
+use typst::visualize::Color;
+use typst::{Library, World};
+
+/// The layout of a watermark page, with the options given by `sys.inputs`.
+const WATERMARK_SOURCE: &str = "#let wm = sys.inputs
+#set page(width: wm.width, height: wm.height, margin: 0pt, fill: none)
+#place(center + horizon, rotate(wm.angle, text(size: wm.size, fill: wm.color, wm.text)))";
+
+/// A watermark to stamp on exported pages.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Watermark {
+    /// The text of the watermark, e.g. `DRAFT`.
+    pub text: String,
+    /// The opacity of the watermark, from `0` to `1`.
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    /// The counterclockwise rotation of the watermark in degrees.
+    #[serde(default = "default_angle")]
+    pub angle: f64,
+    /// The color of the watermark as a hex string, e.g. `#808080`.
+    #[serde(default = "default_color")]
+    pub color: String,
+}
+
+fn default_opacity() -> f32 {
+    0.3
+}
+
+fn default_angle() -> f64 {
+    45.
+}
+
+fn default_color() -> String {
+    "#808080".to_owned()
+}
+
+impl Watermark {
+    /// Check the options of the watermark, and get its color with the opacity
+    /// applied.
+    pub fn validate(&self) -> anyhow::Result<Color> {
+        if self.text.trim().is_empty() {
+            bail!("the text of the watermark is empty");
+        }
+        if !(0. ..=1.).contains(&self.opacity) {
+            bail!("opacity must be between 0 and 1, got {}", self.opacity);
+        }
+        if !self.angle.is_finite() {
+            bail!("angle must be finite, got {}", self.angle);
+        }
+        let color: Color = (self.color.parse())
+            .map_err(|err| anyhow::anyhow!("invalid color {:?}: {err}", self.color))?;
+        let alpha = color.alpha().unwrap_or(1.) * self.opacity;
+        Ok(color.with_alpha(alpha))
+    }
+}
+
+/// Overlay the watermark on each page of the document, whose text is laid out
+/// with the fonts of the world.
+pub fn watermark(
+    world: &dyn World,
+    doc: &Document,
+    watermark: &Watermark,
+) -> anyhow::Result<Document> {
+    let color = watermark.validate()?;
+
+    let mut overlays: Vec<(Size, Frame)> = vec![];
+    let mut marked = doc.clone();
+    for page in &mut marked.pages {
+        let size = page.frame.size();
+        let overlay = match overlays.iter().find(|(s, _)| *s == size) {
+            Some((_, overlay)) => overlay.clone(),
+            None => {
+                let overlay = overlay_frame(world, size, color, watermark)?;
+                overlays.push((size, overlay.clone()));
+                overlay
+            }
+        };
+        page.frame.push_frame(Point::zero(), overlay);
+    }
+
+    Ok(marked)
+}
+
+/// Lay out the watermark on a transparent page of the size.
+fn overlay_frame(
+    world: &dyn World,
+    size: Size,
+    color: Color,
+    watermark: &Watermark,
+) -> anyhow::Result<Frame> {
+    let mut inputs = Dict::new();
+    inputs.insert("text".into(), Value::Str(watermark.text.as_str().into()));
+    inputs.insert("color".into(), Value::Color(color));
+    // Typst rotates clockwise.
+    inputs.insert("angle".into(), Value::Angle(Angle::deg(-watermark.angle)));
+    inputs.insert("width".into(), Value::Length(size.x.into()));
+    inputs.insert("height".into(), Value::Length(size.y.into()));
+    let font_size = (size.x.min(size.y) / 6.).max(Abs::pt(1.));
+    inputs.insert("size".into(), Value::Length(font_size.into()));
+
+    // The watermark is laid out with the fonts of the world.
+    let world = OverlayWorld::new(world)
+        .with_main(Source::detached(WATERMARK_SOURCE))
+        .with_library(Library::builder().with_inputs(inputs).build());
+    let doc = match typst::compile(&world, &mut Tracer::new()) {
+        Ok(doc) => doc,
+        Err(errors) => {
+            let message = errors.first().map(|e| e.message.as_str()).unwrap_or("");
+            bail!("cannot lay out the watermark: {message}");
+        }
+    };
+    let page = doc.pages.into_iter().next();
+    Ok(page.context("the watermark produces no page")?.frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    fn draft() -> Watermark {
+        Watermark {
+            text: "DRAFT".to_owned(),
+            opacity: default_opacity(),
+            angle: default_angle(),
+            color: default_color(),
+        }
+    }
+
+    #[test]
+    fn test_watermark() {
+        let world = TestWorld::new(
+            "#set page(width: 100pt, height: 100pt, fill: rgb(\"#ff0000\"))\n\
+             First #pagebreak() Second",
+        );
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        let marked = watermark(&world, &doc, &draft()).unwrap();
+        assert_eq!(marked.pages.len(), doc.pages.len());
+
+        let png = |doc: &Document| {
+            typst_render::render(&doc.pages[0].frame, 1., Color::WHITE)
+                .encode_png()
+                .unwrap()
+        };
+        assert_ne!(png(&marked), png(&doc));
+
+        // The background is composited under the watermark.
+        let pixmap = typst_render::render(&marked.pages[0].frame, 1., Color::WHITE);
+        let corner = pixmap.pixel(0, 0).unwrap();
+        assert_eq!((corner.red(), corner.green(), corner.blue()), (255, 0, 0));
+    }
+
+    #[test]
+    fn test_invalid_watermark() {
+        let mut options = draft();
+        options.opacity = 1.5;
+        assert!(options.validate().is_err());
+
+        let mut options = draft();
+        options.color = "grey".to_owned();
+        assert!(options.validate().is_err());
+
+        let mut options = draft();
+        options.text = " ".to_owned();
+        assert!(options.validate().is_err());
+    }
+}
@@ -23,7 +23,10 @@ pub struct WordsCount {
 }
 
 /// Count words in a document.
-pub fn word_count(doc: &Document) -> WordsCount {
+///
+/// Each CJK character is counted as a word. In the CJK mode, the CJK
+/// punctuation marks, e.g. `，` and `。`, are not counted as words either.
+pub fn word_count(doc: &Document, cjk_mode: bool) -> WordsCount {
     // the mapping is still not use, so we prevent the warning here
     let _ = TextContent::map_back_spans;
 
@@ -54,6 +57,10 @@ pub fn word_count(doc: &Document) -> WordsCount {
         )
     }
 
+    fn is_cjk_punct(c: char) -> bool {
+        matches!(c, '\u{3000}'..='\u{303F}' | '\u{FF00}'..='\u{FFEF}') && !c.is_alphanumeric()
+    }
+
     let mut state = CountState::InSpace;
     for c in content.chars() {
         chars += 1;
@@ -71,6 +78,8 @@ pub fn word_count(doc: &Document) -> WordsCount {
             words += 1;
             cjk_chars += 1;
 
+            state = CountState::InCJK;
+        } else if cjk_mode && is_cjk_punct(c) {
             state = CountState::InCJK;
         } else {
             if state != CountState::InNonCJK {
@@ -300,3 +309,23 @@ impl SpanMapper {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use typst::eval::Tracer;
+
+    use super::*;
+    use crate::tools::tests::TestWorld;
+
+    #[test]
+    fn test_cjk_mode() {
+        let world = TestWorld::new("Hello 世界，你好");
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+
+        let wc = word_count(&doc, true);
+        assert_eq!((wc.words, wc.cjk_chars), (5, 4));
+        // Each CJK character is still a word, but the comma is also one.
+        let wc = word_count(&doc, false);
+        assert_eq!((wc.words, wc.cjk_chars), (6, 4));
+    }
+}
@@ -0,0 +1,155 @@
+//! A minimal writer of zip archives, which stores files without compression
+//! for packages like PPTX decks and EPUB books.
+//!
+//! Zip64 is not supported, so an archive holds at most 65535 files and 4 GiB.
+
+use anyhow::bail;
+
+/// The flag of the names encoded in UTF-8.
+const UTF8_FLAG: u16 = 1 << 11;
+
+/// A writer of zip archives storing files without compression.
+#[derive(Default)]
+pub struct ZipWriter {
+    data: Vec<u8>,
+    central: Vec<u8>,
+    count: u16,
+    /// Whether a file was refused for exceeding the limits of the archive.
+    oversized: bool,
+}
+
+impl ZipWriter {
+    /// Add a file to the archive. The files exceeding the limits of the archive
+    /// are refused, which fails [`Self::finish`].
+    pub fn add(&mut self, name: &str, content: impl Into<Vec<u8>>) {
+        let content = content.into();
+        // Leaves room for the central directory, whose size is checked at last.
+        let end = self.data.len() + 30 + name.len() + content.len();
+        let (Ok(offset), Ok(size), Ok(name_len), Ok(_), Some(count)) = (
+            u32::try_from(self.data.len()),
+            u32::try_from(content.len()),
+            u16::try_from(name.len()),
+            u32::try_from(end),
+            self.count.checked_add(1).filter(|count| *count < u16::MAX),
+        ) else {
+            self.oversized = true;
+            return;
+        };
+        let crc = crc32(&content);
+
+        // Version 2.0, UTF-8 names, stored, at 1980-01-01 00:00.
+        let fields = |buf: &mut Vec<u8>| {
+            for v in [20u16, UTF8_FLAG, 0, 0, 0x21] {
+                buf.extend(v.to_le_bytes());
+            }
+            for v in [crc, size, size] {
+                buf.extend(v.to_le_bytes());
+            }
+            buf.extend(name_len.to_le_bytes());
+            buf.extend(0u16.to_le_bytes());
+        };
+
+        self.data.extend(0x04034b50u32.to_le_bytes());
+        fields(&mut self.data);
+        self.data.extend(name.as_bytes());
+        self.data.extend(content);
+
+        self.central.extend(0x02014b50u32.to_le_bytes());
+        self.central.extend(20u16.to_le_bytes());
+        fields(&mut self.central);
+        // No comment, on the first disk, without attributes.
+        for v in [0u16, 0, 0] {
+            self.central.extend(v.to_le_bytes());
+        }
+        self.central.extend(0u32.to_le_bytes());
+        self.central.extend(offset.to_le_bytes());
+        self.central.extend(name.as_bytes());
+        self.count = count;
+    }
+
+    /// Finish the archive, returning its content.
+    pub fn finish(mut self) -> anyhow::Result<Vec<u8>> {
+        let (Ok(offset), Ok(size), false) = (
+            u32::try_from(self.data.len()),
+            u32::try_from(self.data.len() + self.central.len()),
+            self.oversized,
+        ) else {
+            bail!("the archive exceeds 65535 files or 4 GiB, which needs zip64");
+        };
+        let size = size - offset;
+        self.data.append(&mut self.central);
+
+        self.data.extend(0x06054b50u32.to_le_bytes());
+        for v in [0u16, 0, self.count, self.count] {
+            self.data.extend(v.to_le_bytes());
+        }
+        self.data.extend(size.to_le_bytes());
+        self.data.extend(offset.to_le_bytes());
+        self.data.extend(0u16.to_le_bytes());
+        Ok(self.data)
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Get the names and contents of the files in a stored zip archive.
+#[cfg(test)]
+pub(crate) fn zip_entries(mut data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let u16_at = |data: &[u8], at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let mut entries = vec![];
+    while data.starts_with(&0x04034b50u32.to_le_bytes()) {
+        let size = u32::from_le_bytes(data[18..22].try_into().unwrap()) as usize;
+        let name_len = u16_at(data, 26) as usize;
+        let start = 30 + name_len + u16_at(data, 28) as usize;
+        let name = String::from_utf8(data[30..30 + name_len].to_vec()).unwrap();
+        entries.push((name, data[start..start + size].to_vec()));
+        data = &data[start + size..];
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_utf8_names() {
+        let mut zip = ZipWriter::default();
+        zip.add("章节.typ", "= 章节");
+        let data = zip.finish().unwrap();
+        assert_eq!(u16::from_le_bytes([data[6], data[7]]), UTF8_FLAG);
+        let entry = ("章节.typ".to_owned(), "= 章节".as_bytes().to_vec());
+        assert_eq!(zip_entries(&data), [entry]);
+    }
+
+    #[test]
+    fn test_refuse_too_many_files() {
+        let mut zip = ZipWriter::default();
+        for i in 0..u16::MAX {
+            zip.add(&i.to_string(), "");
+        }
+        assert!(zip.finish().is_err());
+
+        let mut zip = ZipWriter::default();
+        for i in 0..u16::MAX - 1 {
+            zip.add(&i.to_string(), "");
+        }
+        let data = zip.finish().unwrap();
+        assert_eq!(zip_entries(&data).len(), usize::from(u16::MAX - 1));
+    }
+}
@@ -1,7 +1,14 @@
-use std::{borrow::Cow, path::PathBuf, sync::Arc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use std::{borrow::Cow, collections::HashSet, path::PathBuf, sync::Arc};
 
 use comemo::Prehashed;
 use serde::{Deserialize, Serialize};
+use typst::eval::Tracer;
+use typst::syntax::{FileId, VirtualPath};
+use typst::text::{Coverage, FontBook, FontInfo};
+use typst::World;
 use typst_ts_core::{
     config::{compiler::EntryState, CompileFontOpts as FontOptsInner},
     error::prelude::*,
@@ -14,6 +21,7 @@ use typst_ts_compiler::{
     package::http::HttpRegistry,
     vfs::{system::SystemAccessModel, Vfs},
     world::CompilerWorld,
+    ShadowApi,
 };
 
 #[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
@@ -49,16 +57,30 @@ pub struct CompileFontOpts {
 
 #[derive(Debug, Clone)]
 pub struct SharedFontResolver {
-    font_paths: Vec<PathBuf>,
+    /// The options to search the fonts with, with absolute font paths.
+    opts: CompileFontOpts,
     pub inner: Arc<FontResolverImpl>,
+    /// The fallback families, which are tried in order.
+    fallback: Vec<String>,
+    /// The font book preferring the fallback families, if any is configured.
+    fallback_book: Option<Arc<Prehashed<FontBook>>>,
+    /// The budget of the probing compilation using the fonts, if any. The
+    /// worlds of the compilers and the queries are not limited.
+    pub budget: Option<Arc<CompileBudget>>,
 }
 
 impl FontResolver for SharedFontResolver {
     fn font(&self, idx: usize) -> Option<typst_ts_core::TypstFont> {
+        if self.budget.as_ref().is_some_and(|budget| budget.exceeded()) {
+            return None;
+        }
         self.inner.font(idx)
     }
-    fn font_book(&self) -> &Prehashed<typst::text::FontBook> {
-        self.inner.font_book()
+    fn font_book(&self) -> &Prehashed<FontBook> {
+        match &self.fallback_book {
+            Some(book) => book,
+            None => self.inner.font_book(),
+        }
     }
 }
 
@@ -82,16 +104,165 @@ impl SharedFontResolver {
             }
         }
 
-        let font_paths = opts.font_paths.clone();
-        let res = crate::world::LspWorldBuilder::resolve_fonts(opts)?;
+        let res = crate::world::LspWorldBuilder::resolve_fonts(opts.clone())?;
         Ok(Self {
-            font_paths,
+            opts,
             inner: Arc::new(res),
+            fallback: vec![],
+            fallback_book: None,
+            budget: None,
         })
     }
 
     pub fn font_paths(&self) -> &[PathBuf] {
-        &self.font_paths
+        &self.opts.font_paths
+    }
+
+    /// Search the fonts again, e.g. after fonts are installed or removed.
+    pub fn reload(&self) -> ZResult<Self> {
+        let reloaded = Self::new(self.opts.clone())?;
+        Ok(self.with_fonts(&reloaded))
+    }
+
+    /// Create a resolver with the fonts of another one, which keeps the
+    /// fallback families and the budget of this one.
+    pub fn with_fonts(&self, fonts: &SharedFontResolver) -> Self {
+        let resolver = Self {
+            opts: fonts.opts.clone(),
+            inner: fonts.inner.clone(),
+            ..self.clone()
+        };
+        resolver.with_fallback(&self.fallback)
+    }
+
+    /// Create a resolver sharing the fonts, which tries the families in order
+    /// before the default fallback. See [`fallback_book`] for details.
+    pub fn with_fallback(&self, families: &[String]) -> Self {
+        let fallback_book = (!families.is_empty()).then(|| {
+            let book = fallback_book(self.inner.font_book(), families);
+            Arc::new(Prehashed::new(book))
+        });
+        Self {
+            fallback: families.to_vec(),
+            fallback_book,
+            ..self.clone()
+        }
+    }
+
+    /// Create a resolver sharing the fonts, which stops serving them once the
+    /// compilation exceeds the budget.
+    pub fn with_budget(&self, budget: Arc<CompileBudget>) -> Self {
+        Self {
+            budget: Some(budget),
+            ..self.clone()
+        }
+    }
+}
+
+/// Create a font book where the fallback for a character not covered by the
+/// `font` of the text is selected from the families in order, before the other
+/// fonts.
+///
+/// Typst selects the fallback among all fonts covering the character, so the
+/// coverage of a font is reduced by the characters covered by the preferred
+/// families. The fonts themselves are untouched.
+pub fn fallback_book(book: &FontBook, families: &[String]) -> FontBook {
+    let infos: Vec<&FontInfo> = (0..).map_while(|idx| book.info(idx)).collect();
+    let rank_of = |info: &FontInfo| {
+        let family = info.family.to_lowercase();
+        families.iter().position(|f| f.to_lowercase() == family)
+    };
+
+    // The characters covered by the families ranked before each family.
+    let mut covered = vec![HashSet::<u32>::new(); families.len() + 1];
+    for info in &infos {
+        if let Some(rank) = rank_of(info) {
+            for set in &mut covered[rank + 1..] {
+                set.extend(info.coverage.iter());
+            }
+        }
+    }
+
+    FontBook::from_infos(infos.into_iter().map(|info| {
+        let covered = &covered[rank_of(info).unwrap_or(families.len())];
+        if covered.is_empty() {
+            return info.clone();
+        }
+        let codepoints = info.coverage.iter().filter(|c| !covered.contains(c));
+        FontInfo {
+            coverage: Coverage::from_vec(codepoints.collect()),
+            ..info.clone()
+        }
+    }))
+}
+
+/// The error reported for a compilation exceeding its budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompileTimeout(pub Duration);
+
+impl std::fmt::Display for CompileTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = self.0.as_millis();
+        write!(f, "compilation exceeded {ms} ms and was aborted")
+    }
+}
+
+/// The budget of a probing compilation, see [`probe_compile`].
+#[derive(Debug, Default)]
+pub struct CompileBudget {
+    exceeded: AtomicBool,
+}
+
+impl CompileBudget {
+    /// Mark the compilation as exceeding its budget.
+    pub fn exceed(&self) {
+        self.exceeded.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the compilation has exceeded its budget.
+    pub fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+}
+
+/// Compile a fork of the world on another thread, waiting for it at most the
+/// budget. The compiler thread is thus never held by a compilation longer than
+/// the budget, e.g. by a loop in the document.
+///
+/// Typst cannot be interrupted from outside, so a fork exceeding the budget is
+/// left to run to its end with its result discarded. It stops serving fonts
+/// then, which cuts short the layout of text. Once the fork finishes in time,
+/// compiling the world itself mostly hits the cache filled by the fork.
+pub fn probe_compile(world: &LspWorld, budget: Duration) -> Result<(), CompileTimeout> {
+    let exceeded = Arc::new(CompileBudget::default());
+    let fonts = world.font_resolver.with_budget(exceeded.clone());
+    let fork = match LspWorldBuilder::fork(world, fonts) {
+        Ok(fork) => fork,
+        Err(err) => {
+            log::warn!("failed to fork the world to probe the compilation: {err}");
+            return Ok(());
+        }
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let spawned = std::thread::Builder::new()
+        .name("tinymist-compile-probe".to_owned())
+        .spawn(move || {
+            let _ = typst::compile(&fork, &mut Tracer::new());
+            let _ = tx.send(());
+        });
+    if let Err(err) = spawned {
+        log::warn!("failed to spawn the thread probing the compilation: {err}");
+        return Ok(());
+    }
+
+    match rx.recv_timeout(budget) {
+        // A panicking fork is left to the compilation to report.
+        Ok(()) | Err(RecvTimeoutError::Disconnected) => Ok(()),
+        Err(RecvTimeoutError::Timeout) => {
+            exceeded.exceed();
+            Err(CompileTimeout(budget))
+        }
     }
 }
 
@@ -135,6 +306,28 @@ impl LspWorldBuilder {
         Ok(res)
     }
 
+    /// Create a world compiling the same files as the world, with the given
+    /// fonts. The files in memory under the root are copied, which are the only
+    /// ones readable by the document besides the files on disk.
+    pub fn fork(world: &LspWorld, font_resolver: SharedFontResolver) -> ZResult<LspWorld> {
+        let entry = world.entry_state();
+        let fork = Self::build(entry.clone(), font_resolver, world.inputs.clone())?;
+        let Some(root) = entry.root() else {
+            return Ok(fork);
+        };
+        for path in world.shadow_paths() {
+            let Ok(rel) = path.strip_prefix(&root) else {
+                continue;
+            };
+            let id = FileId::new(None, VirtualPath::new(rel));
+            if let Ok(content) = world.file(id) {
+                fork.map_shadow(&path, content)
+                    .map_err(map_string_err("failed to copy a file in memory"))?;
+            }
+        }
+        Ok(fork)
+    }
+
     /// Resolve fonts from given options.
     pub(crate) fn resolve_fonts(opts: CompileFontOpts) -> ZResult<FontResolverImpl> {
         let mut searcher = SystemFontSearcher::new();
@@ -147,3 +340,122 @@ impl LspWorldBuilder {
         Ok(searcher.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use typst::layout::{Frame, FrameItem};
+    use typst::text::{FontFlags, FontVariant};
+
+    use super::*;
+
+    fn info(family: &str, text: &str) -> FontInfo {
+        FontInfo {
+            family: family.to_owned(),
+            variant: FontVariant::default(),
+            flags: FontFlags::empty(),
+            coverage: Coverage::from_vec(text.chars().map(u32::from).collect()),
+        }
+    }
+
+    #[test]
+    fn test_fallback_book() {
+        let book = FontBook::from_infos([
+            info("Linux Libertine", "ab"),
+            info("Noto Sans CJK SC", "a中文"),
+            info("Source Han Serif", "中"),
+            info("LXGW WenKai", "中文"),
+        ]);
+        let variant = FontVariant::default();
+        let select = |book: &FontBook, text: &str| book.select_fallback(None, variant, text);
+        assert_eq!(select(&book, "中"), Some(1));
+
+        let families = ["source han serif".to_owned(), "LXGW WenKai".to_owned()];
+        let book = fallback_book(&book, &families);
+        // The CJK characters are taken from the families in order.
+        assert_eq!(select(&book, "中"), Some(2));
+        assert_eq!(select(&book, "文"), Some(3));
+        assert_eq!(select(&book, "a"), Some(0));
+        assert_eq!(book.select("noto sans cjk sc", variant), Some(1));
+    }
+
+    fn embedded_fonts() -> SharedFontResolver {
+        SharedFontResolver::new(CompileFontOpts {
+            no_system_fonts: true,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn world_of(source: &str) -> LspWorld {
+        world_with_fonts(source, embedded_fonts())
+    }
+
+    fn world_with_fonts(source: &str, font: SharedFontResolver) -> LspWorld {
+        let root = Path::new(if cfg!(windows) { "C:\\doc" } else { "/doc" });
+        let main = FileId::new(None, VirtualPath::new("main.typ"));
+        let entry = EntryState::new_rooted(root.into(), Some(main));
+        let inputs = Arc::new(Prehashed::new(Default::default()));
+        let world = LspWorldBuilder::build(entry, font, inputs).unwrap();
+        world
+            .map_shadow(&root.join("main.typ"), source.as_bytes().into())
+            .unwrap();
+        world
+    }
+
+    /// Collect the families of the fonts used by the text in the frame.
+    fn text_families(frame: &Frame, families: &mut Vec<String>) {
+        for (_, item) in frame.items() {
+            match item {
+                FrameItem::Group(group) => text_families(&group.frame, families),
+                FrameItem::Text(text) => families.push(text.font.info().family.clone()),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_fallback_in_world() {
+        // The text falls back for all characters, as its font is not found.
+        let source = "#set text(font: \"nonexistent\")\nabc";
+        let rendered_with = |fallback: &str| {
+            let fonts = embedded_fonts().with_fallback(&[fallback.to_owned()]);
+            let world = world_with_fonts(source, fonts);
+            let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+            let mut families = vec![];
+            text_families(&doc.pages[0].frame, &mut families);
+            families
+        };
+
+        // Both families cover the characters, so that at least one of them is
+        // not the default fallback.
+        for family in ["DejaVu Sans Mono", "Linux Libertine"] {
+            assert_eq!(rendered_with(family), [family]);
+        }
+    }
+
+    #[test]
+    fn test_probe_compile() {
+        // Typst gives up a `while` loop after 10000 iterations, each of which
+        // loops a lot without accessing the world.
+        let source = "#let n = 0\n#while true {\n  if n < 0 { break }\n  for i in range(100000) { n += 1 }\n}";
+        let limit = Duration::from_millis(50);
+        let timeout = probe_compile(&world_of(source), limit).unwrap_err();
+        assert_eq!(timeout, CompileTimeout(limit));
+        assert_eq!(
+            timeout.to_string(),
+            "compilation exceeded 50 ms and was aborted"
+        );
+
+        // The fork sees the files in memory.
+        let world = world_of("= Fast");
+        assert_eq!(probe_compile(&world, Duration::from_secs(60)), Ok(()));
+        let fork = LspWorldBuilder::fork(&world, world.font_resolver.clone()).unwrap();
+        assert_eq!(fork.main().text(), "= Fast");
+        assert!(typst::compile(&fork, &mut Tracer::new()).is_ok());
+
+        // The fonts of the world itself are not limited.
+        assert!(world.font_resolver.budget.is_none());
+    }
+}
@@ -88,6 +88,19 @@ async function startClient(context: ExtensionContext): Promise<void> {
                     return substVscodeVarsInConfig(items, result);
                 },
             },
+            async executeCommand(command, args, next) {
+                const result = await next(command, args);
+                if (command === "tinymist.toggleCjkMode" && typeof result === "boolean") {
+                    // Persists the toggled mode, which is synchronized back to the server.
+                    const config = workspace.getConfiguration("tinymist");
+                    const inWorkspace = config.inspect("cjkMode")?.workspaceValue !== undefined;
+                    const target = inWorkspace
+                        ? vscode.ConfigurationTarget.Workspace
+                        : vscode.ConfigurationTarget.Global;
+                    await config.update("cjkMode", result, target);
+                }
+                return result;
+            },
         },
     };
 
@@ -608,6 +621,27 @@ async function commandRunCodeLens(...args: string[]): Promise<void> {
 
             break;
         }
+        case "render-variant": {
+            const activeEditor = window.activeTextEditor;
+            if (activeEditor === undefined) {
+                return;
+            }
+
+            const exportPath = await client?.sendRequest<string | null>("workspace/executeCommand", {
+                command: "tinymist.renderVariant",
+                arguments: [{ path: activeEditor.document.uri.fsPath, function: args[1] }],
+            });
+            if (!exportPath) {
+                await window.showErrorMessage(`Failed to render ${args[1]}`);
+                return;
+            }
+
+            await commands.executeCommand("vscode.open", Uri.file(exportPath), {
+                viewColumn: ViewColumn.Beside,
+                preserveFocus: true,
+            } as vscode.TextDocumentShowOptions);
+            break;
+        }
         default: {
             console.error("unknown code lens command", args[0]);
         }
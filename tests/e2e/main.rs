@@ -326,6 +326,71 @@ fn gen_smoke(args: SmokeArgs) {
     });
 }
 
+fn gen_restart(root: &Path) {
+    use lsp_types::notification::*;
+    use lsp_types::request::*;
+    use lsp_types::*;
+
+    gen(root, |srv| {
+        let root_uri = lsp_types::Url::from_directory_path(root).unwrap();
+        srv.request::<Initialize>(fixture("initialization/vscode-1.87.2", |v| {
+            v["rootUri"] = json!(root_uri);
+            v["rootPath"] = json!(root);
+            v["workspaceFolders"] = json!([{
+                "uri": root_uri,
+                "name": "tinymist",
+            }]);
+        }));
+        srv.notify::<Initialized>(json!({}));
+
+        let uri = root_uri.join("main.typ").unwrap();
+        srv.notify::<DidOpenTextDocument>(json!(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "typst".to_owned(),
+                version: 0,
+                text: "#let x = 1;\n= Heading\n#x".to_owned(),
+            },
+        }));
+        // id: 2
+        srv.request::<ExecuteCommand>(json!(ExecuteCommandParams {
+            command: "tinymist.restartCompiler".to_owned(),
+            arguments: vec![json!(true)],
+            work_done_progress_params: Default::default(),
+        }));
+        // id: 3
+        srv.request::<DocumentSymbolRequest>(json!(DocumentSymbolParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default()
+        }));
+        // id: 4
+        srv.request::<HoverRequest>(json!(HoverParams {
+            work_done_progress_params: Default::default(),
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position {
+                    line: 2,
+                    character: 1,
+                },
+            },
+        }));
+    });
+}
+
+fn replay_responses(tinymist_binary: &Path, root: &Path) -> Vec<lsp_server::Response> {
+    let tinymist_binary = tinymist_binary.to_str().unwrap();
+
+    let log_file = root.join("mirror.log").to_str().unwrap().to_owned();
+    let res = messages(exec_output(tinymist_binary, ["lsp", "--replay", &log_file]));
+    res.into_iter()
+        .filter_map(|msg| match msg {
+            lsp_server::Message::Response(res) => Some(res),
+            _ => None,
+        })
+        .collect()
+}
+
 fn replay_log(tinymist_binary: &Path, root: &Path) -> String {
     let tinymist_binary = tinymist_binary.to_str().unwrap();
 
@@ -387,6 +452,18 @@ fn e2e() {
         let hash = replay_log(&tinymist_binary, &root.join("vscode"));
         insta::assert_snapshot!(hash, @"siphash128_13:eb921865a19154411435d3f550f7f16");
     }
+
+    {
+        gen_restart(&root.join("restart"));
+
+        let res = replay_responses(&tinymist_binary, &root.join("restart"));
+        // The restart and the queries after it must all succeed.
+        for id in 2..=4 {
+            let resp = res.iter().find(|r| r.id == RequestId::from(id));
+            let resp = resp.unwrap_or_else(|| panic!("missing response {id}"));
+            assert!(resp.error.is_none(), "request {id} failed: {resp:?}");
+        }
+    }
 }
 
 fn sort_and_redact_value(v: Value) -> Value {